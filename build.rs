@@ -1,17 +1,84 @@
 use hit::repo::Repo;
-use std::path::{Path, PathBuf};
+use sha2::{Digest as _, Sha256};
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Writes `embedded_templates.rs` into `OUT_DIR`, listing every file in the
+/// template packs along with its hash, for `templating::embedded` to include.
+/// Prebuilt binaries never run this script where they're used, so that's how
+/// they get templates.
+fn embed_templates(manifest_dir: &Path) {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    for rel in ["platforms", "apps"]
+        .iter()
+        .map(|prefix| Path::new("templates").join(prefix))
+    {
+        let actions =
+            bicycle::traverse(&manifest_dir.join(&rel), &rel, bicycle::no_transform, None)
+                .expect("failed to traverse src templates dir");
+        for action in actions {
+            match action {
+                bicycle::Action::CreateDirectory { dest } => dirs.push(dest),
+                bicycle::Action::CopyFile { src, dest } => files.push((dest, src)),
+                _ => (),
+            }
+        }
+    }
+    // Paths always use `/`, so the same binary works anywhere.
+    let rel_str = |path: &Path| {
+        path.to_str()
+            .expect("template path wasn't valid UTF-8")
+            .replace('\\', "/")
+    };
+    dirs.sort();
+    files.sort();
+    let mut out =
+        String::from("// Generated by `build.rs`.\n\npub static EMBEDDED_DIRS: &[&str] = &[\n");
+    for dir in &dirs {
+        writeln!(out, "    {:?},", rel_str(dir)).unwrap();
+    }
+    out.push_str("];\n\npub static EMBEDDED_FILES: &[EmbeddedFile] = &[\n");
+    for (dest, src) in &files {
+        let contents = fs::read(src).expect("failed to read template file");
+        writeln!(
+            out,
+            "    EmbeddedFile {{ path: {:?}, contents: include_bytes!({:?}), sha256: {:?} }},",
+            rel_str(dest),
+            src.to_str().expect("template path wasn't valid UTF-8"),
+            format!("{:x}", Sha256::digest(&contents)),
+        )
+        .unwrap();
+    }
+    out.push_str("];\n");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("embedded_templates.rs"), out)
+        .expect("failed to write embedded templates");
+}
 
 fn main() {
     let pkg_name = std::env::var("CARGO_PKG_NAME").unwrap();
     let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-    let install_dir = home::home_dir()
-        .expect("failed to get user's home dir")
-        .join(format!(".{}", pkg_name));
+    // This has to match `util::install_dir`, or the templates we install
+    // won't be where we look for them.
+    println!("cargo:rerun-if-env-changed=CARGO_MOBILE_HOME");
+    let install_dir = std::env::var_os("CARGO_MOBILE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            home::home_dir()
+                .expect("failed to get user's home dir; set `CARGO_MOBILE_HOME` to install somewhere else")
+                .join(format!(".{}", pkg_name))
+        });
     std::fs::create_dir_all(&install_dir).expect("failed to create install dir");
 
     // Copy version info
     match Repo::from_path(&manifest_dir).latest_subject() {
         Ok(msg) => {
+            println!("cargo:rustc-env=CARGO_MOBILE_COMMIT_MSG={}", msg.trim());
             if let Err(err) = std::fs::write(install_dir.join("commit"), msg) {
                 println!(
                     "cargo:warning=failed to write current commit message: {}",
@@ -25,6 +92,18 @@ fn main() {
         ),
     }
 
+    // Projects are stamped with this, so upgrading them can find the
+    // templates they were generated from.
+    match Repo::from_path(&manifest_dir).latest_hash() {
+        Ok(hash) => {
+            println!("cargo:rustc-env=CARGO_MOBILE_COMMIT_HASH={}", hash.trim());
+            if let Err(err) = std::fs::write(install_dir.join("commit-hash"), hash) {
+                println!("cargo:warning=failed to write current commit hash: {}", err)
+            }
+        }
+        Err(err) => println!("cargo:warning=failed to get current commit hash: {}", err),
+    }
+
     // Copy templates
     let bike = bicycle::Bicycle::default();
     for rel in ["platforms", "apps"]
@@ -57,4 +136,6 @@ fn main() {
         )
         .expect("failed to process actions");
     }
+
+    embed_templates(&manifest_dir);
 }
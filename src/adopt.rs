@@ -0,0 +1,466 @@
+//! `cargo mobile init --adopt`, for moving an existing Android Studio or Xcode
+//! project onto a Rust core. Those projects are never generated or touched;
+//! instead, we write out what to paste into them, so they build the Rust lib
+//! the same way a generated project would.
+
+use crate::{
+    config::{self, metadata::Metadata, Config},
+    opts,
+    util::{
+        self,
+        cli::{Report, Reportable},
+    },
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub static DIR_NAME: &str = "cargo-mobile-integration";
+static GRADLE_FILE_NAME: &str = "cargo.gradle";
+static XCODE_SCRIPT_FILE_NAME: &str = "xcode-run-script.sh";
+static README_FILE_NAME: &str = "README.md";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Platform {
+    Android,
+    Apple,
+}
+
+impl Platform {
+    fn section(self) -> &'static str {
+        match self {
+            Self::Android => "android",
+            Self::Apple => "apple",
+        }
+    }
+
+    fn project_kind(self) -> &'static str {
+        match self {
+            Self::Android => "Android Studio",
+            Self::Apple => "Xcode",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    ExistingProjectDirUnset {
+        platform: Platform,
+    },
+    ExistingProjectDirInvalid {
+        platform: Platform,
+        path: PathBuf,
+        cause: io::Error,
+    },
+    DirCreationFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    WriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ExistingProjectDirUnset { platform } => Report::action_request(
+                format!(
+                    "`--adopt` needs to know where your {} project is",
+                    platform.project_kind()
+                ),
+                format!(
+                    "Set `{}.existing-project-dir` in {} to its path (relative to the app root), or mark it as unsupported in your Cargo.toml metadata, and try again.",
+                    platform.section(),
+                    config::file_name(),
+                ),
+            ),
+            Self::ExistingProjectDirInvalid {
+                platform,
+                path,
+                cause,
+            } => Report::action_request(
+                format!(
+                    "`{}.existing-project-dir` is {:?}, which isn't a directory we can get to",
+                    platform.section(),
+                    path
+                ),
+                cause,
+            ),
+            Self::DirCreationFailed { path, cause } => Report::error(
+                format!("Failed to create integration snippet directory {:?}", path),
+                cause,
+            ),
+            Self::WriteFailed { path, cause } => Report::error(
+                format!("Failed to write integration snippet {:?}", path),
+                cause,
+            ),
+        }
+    }
+}
+
+/// The projects being adopted, canonicalized so the relative paths in the
+/// snippets come out right.
+#[derive(Debug, Default)]
+pub struct Adopted {
+    pub android: Option<PathBuf>,
+    pub apple: Option<PathBuf>,
+}
+
+fn resolve(
+    platform: Platform,
+    existing_project_dir: Option<&Path>,
+    adopt: opts::Adopt,
+) -> Result<Option<PathBuf>, Error> {
+    match existing_project_dir {
+        Some(dir) => dir
+            .canonicalize()
+            .and_then(|dir| {
+                if dir.is_dir() {
+                    Ok(Some(dir))
+                } else {
+                    Err(io::Error::new(io::ErrorKind::Other, "it's not a directory"))
+                }
+            })
+            .map_err(|cause| Error::ExistingProjectDirInvalid {
+                platform,
+                path: dir.to_owned(),
+                cause,
+            }),
+        None if adopt.yes() => Err(Error::ExistingProjectDirUnset { platform }),
+        None => Ok(None),
+    }
+}
+
+impl Adopted {
+    /// Which platforms to adopt rather than generate. Having
+    /// `existing-project-dir` set is enough, so re-running `init` without
+    /// `--adopt` doesn't suddenly generate a project; `--adopt` just makes
+    /// sure every supported platform has one.
+    pub fn detect(config: &Config, metadata: &Metadata, adopt: opts::Adopt) -> Result<Self, Error> {
+        let android = if metadata.android().supported() {
+            resolve(
+                Platform::Android,
+                config.android().existing_project_dir(),
+                adopt,
+            )?
+        } else {
+            None
+        };
+        #[cfg(target_os = "macos")]
+        let apple = if metadata.apple().supported() && config.apple().is_configured() {
+            resolve(
+                Platform::Apple,
+                config.apple().existing_project_dir(),
+                adopt,
+            )?
+        } else {
+            None
+        };
+        #[cfg(not(target_os = "macos"))]
+        let apple = None;
+        Ok(Self { android, apple })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.android.is_none() && self.apple.is_none()
+    }
+}
+
+fn rel(path: &Path, relative_to: &Path) -> String {
+    let rel = util::relativize_path(path, relative_to)
+        .display()
+        .to_string();
+    match rel.trim_end_matches('/') {
+        "" => ".".to_owned(),
+        rel => rel.to_owned(),
+    }
+}
+
+/// Meant to be applied from the app module's `build.gradle`, which gradle
+/// resolves `file` against no matter where the script itself lives.
+pub fn gradle_snippet(root_dir: &Path, project_dir: &Path, targets: &[String]) -> String {
+    let app_dir = project_dir.join("app");
+    let targets = targets
+        .iter()
+        .map(|target| format!("{:?}", target))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        r#"// Generated by `cargo mobile init --adopt`; apply it from app/build.gradle:
+//
+//     apply from: "{snippet}"
+//
+// Each variant builds the Rust lib first, and `cargo android build` links it
+// into src/main/jniLibs.
+def cargoMobileRoot = file("{root}")
+def cargoMobileTargets = [{targets}]
+
+["debug", "release"].each {{ profile ->
+    def cargoBuild = tasks.register("cargoBuild${{profile.capitalize()}}", Exec) {{
+        group = "rust"
+        description = "Builds the Rust lib in ${{profile}} mode with cargo-mobile"
+        workingDir cargoMobileRoot
+        def release = profile == "release" ? ["--release"] : []
+        commandLine(["cargo", "android", "build"] + release + cargoMobileTargets)
+    }}
+    tasks.matching {{
+        it.name.startsWith("pre") && it.name.endsWith("${{profile.capitalize()}}Build")
+    }}.configureEach {{
+        dependsOn cargoBuild
+    }}
+}}
+"#,
+        snippet = rel(&root_dir.join(DIR_NAME).join(GRADLE_FILE_NAME), &app_dir),
+        root = rel(root_dir, &app_dir),
+        targets = targets,
+    )
+}
+
+/// The body of a "Run Script" build phase, which is the same `xcode-script`
+/// call the generated project makes. Only `SRCROOT` can be relied on to
+/// point somewhere, and the search paths may well be empty.
+pub fn xcode_script(root_dir: &Path, project_dir: &Path, name_snake: &str) -> String {
+    format!(
+        r#"# Generated by `cargo mobile init --adopt`; paste this into a "Run Script"
+# build phase that runs before "Compile Sources".
+set -e
+cd "${{SRCROOT:?}}/{root}"
+"${{HOME}}/.cargo/bin/cargo-apple" xcode-script -v \
+    --platform "${{PLATFORM_DISPLAY_NAME:?}}" \
+    --sdk-root "${{SDKROOT:?}}" \
+    --framework-search-paths "${{FRAMEWORK_SEARCH_PATHS}}" \
+    --header-search-paths "${{HEADER_SEARCH_PATHS}}" \
+    --gcc-preprocessor-definitions "${{GCC_PREPROCESSOR_DEFINITIONS}}" \
+    --configuration "${{CONFIGURATION:?}}" \
+    --depfile "${{BUILT_PRODUCTS_DIR:?}}/cargo-apple-{name_snake}.d" \
+    ${{ARCHS:?}}
+"#,
+        root = rel(root_dir, project_dir),
+        name_snake = name_snake,
+    )
+}
+
+pub fn readme(root_dir: &Path, target_dir: &Path, name_snake: &str, adopted: &Adopted) -> String {
+    let mut readme = format!(
+        r#"# Hooking your projects up to `{name_snake}`
+
+`cargo mobile init --adopt` leaves your own projects alone, so these are the
+pieces to add to them by hand. They're regenerated every time `init` runs, so
+copy them rather than editing them here.
+"#,
+        name_snake = name_snake,
+    );
+    if let Some(project_dir) = &adopted.android {
+        readme.push_str(&format!(
+            r#"
+## Android Studio ({project_dir})
+
+1. Add `apply from: "{snippet}"` to `{project_dir}/app/build.gradle`. That
+   registers `cargoBuildDebug`/`cargoBuildRelease`, which run before every
+   variant's `preBuild`.
+2. `cargo android build` links the lib into
+   `{project_dir}/app/src/main/jniLibs`, so load it with
+   `System.loadLibrary("{name_snake}")`.
+
+`cargo android build` and `cargo android check` work like they do in a
+generated project. `open`, `run`, and the other commands that drive gradle
+don't, so use Android Studio for those.
+"#,
+            project_dir = project_dir.display(),
+            snippet = rel(
+                &root_dir.join(DIR_NAME).join(GRADLE_FILE_NAME),
+                &project_dir.join("app")
+            ),
+            name_snake = name_snake,
+        ));
+    }
+    if let Some(project_dir) = &adopted.apple {
+        let target = |triple: &str| {
+            format!(
+                "$(SRCROOT)/{}/$(CONFIGURATION)",
+                rel(&target_dir.join(triple), project_dir)
+            )
+        };
+        readme.push_str(&format!(
+            r#"
+## Xcode ({project_dir})
+
+1. Add a "Run Script" build phase to your app target, above "Compile
+   Sources", and paste `{script}` into it. Set its output file to
+   `$(BUILT_PRODUCTS_DIR)/lib{name_snake}.a` so Xcode doesn't skip it.
+2. Add `lib{name_snake}.a` to "Link Binary With Libraries".
+3. Add `{device}` to "Library Search Paths" for `Any iOS SDK`, and
+   `{simulator}` (`{intel_simulator}` on Intel Macs) for
+   `Any iOS Simulator SDK`.
+
+`cargo apple build` builds the lib straight away instead of going through
+Xcode, and `cargo apple check` works like it does in a generated project.
+`open`, `run`, and `archive` assume a generated project, so use Xcode for
+those.
+"#,
+            project_dir = project_dir.display(),
+            script = XCODE_SCRIPT_FILE_NAME,
+            name_snake = name_snake,
+            device = target("aarch64-apple-ios"),
+            simulator = target("aarch64-apple-ios-sim"),
+            intel_simulator = target("x86_64-apple-ios"),
+        ));
+    }
+    readme
+}
+
+fn write_file(path: PathBuf, contents: &str) -> Result<(), Error> {
+    log::info!("writing integration snippet {:?}", path);
+    fs::write(&path, contents).map_err(|cause| Error::WriteFailed { path, cause })
+}
+
+/// Writes the snippets for everything in `adopted`, giving back the directory
+/// they went in.
+pub fn write(config: &Config, adopted: &Adopted) -> Result<PathBuf, Error> {
+    let root_dir = config.app().root_dir();
+    let name_snake = config.app().name_snake();
+    let dir = root_dir.join(DIR_NAME);
+    fs::create_dir_all(&dir).map_err(|cause| Error::DirCreationFailed {
+        path: dir.clone(),
+        cause,
+    })?;
+    if let Some(project_dir) = &adopted.android {
+        write_file(
+            dir.join(GRADLE_FILE_NAME),
+            &gradle_snippet(root_dir, project_dir, config.android().target_names()),
+        )?;
+    }
+    if let Some(project_dir) = &adopted.apple {
+        write_file(
+            dir.join(XCODE_SCRIPT_FILE_NAME),
+            &xcode_script(root_dir, project_dir, &name_snake),
+        )?;
+    }
+    write_file(
+        dir.join(README_FILE_NAME),
+        &readme(
+            root_dir,
+            &util::cargo_target_dir(root_dir),
+            &name_snake,
+            adopted,
+        ),
+    )?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    #[test]
+    fn gradle_snippet_points_back_at_the_root() {
+        let snippet = gradle_snippet(
+            Path::new("/work/app/rust"),
+            Path::new("/work/app/android"),
+            &["aarch64".to_owned(), "armv7".to_owned()],
+        );
+        assert!(
+            snippet.contains(r#"apply from: "../../rust/cargo-mobile-integration/cargo.gradle""#)
+        );
+        assert!(snippet.contains(r#"def cargoMobileRoot = file("../../rust")"#));
+        assert!(snippet.contains(r#"def cargoMobileTargets = ["aarch64", "armv7"]"#));
+        assert!(snippet.contains(r#"tasks.register("cargoBuild${profile.capitalize()}", Exec) {"#));
+    }
+
+    #[test]
+    fn xcode_script_runs_from_the_root() {
+        let script = xcode_script(
+            Path::new("/work/app"),
+            Path::new("/work/app/ios/Game"),
+            "game",
+        );
+        assert!(script.contains(r#"cd "${SRCROOT:?}/../..""#));
+        assert!(script.contains(r#"--framework-search-paths "${FRAMEWORK_SEARCH_PATHS}""#));
+        assert!(script.contains(r#"--depfile "${BUILT_PRODUCTS_DIR:?}/cargo-apple-game.d""#));
+        assert!(script.trim_end().ends_with("${ARCHS:?}"));
+    }
+
+    #[test]
+    fn readme_only_covers_adopted_platforms() {
+        let root_dir = Path::new("/work/app");
+        let target_dir = root_dir.join("target");
+        let android_only = readme(
+            root_dir,
+            &target_dir,
+            "game",
+            &Adopted {
+                android: Some("/work/android".into()),
+                apple: None,
+            },
+        );
+        assert!(android_only.contains("## Android Studio (/work/android)"));
+        assert!(android_only.contains(r#"System.loadLibrary("game")"#));
+        assert!(!android_only.contains("## Xcode"));
+        let apple_only = readme(
+            root_dir,
+            &target_dir,
+            "game",
+            &Adopted {
+                android: None,
+                apple: Some("/work/app/ios".into()),
+            },
+        );
+        assert!(!apple_only.contains("## Android Studio"));
+        assert!(apple_only.contains("`$(SRCROOT)/../target/aarch64-apple-ios/$(CONFIGURATION)`"));
+        assert!(
+            apple_only.contains("`$(SRCROOT)/../target/aarch64-apple-ios-sim/$(CONFIGURATION)`")
+        );
+        let moved = readme(
+            root_dir,
+            Path::new("/build/game"),
+            "game",
+            &Adopted {
+                android: None,
+                apple: Some("/work/app/ios".into()),
+            },
+        );
+        assert!(
+            moved.contains("`$(SRCROOT)/../../../build/game/aarch64-apple-ios/$(CONFIGURATION)`")
+        );
+    }
+
+    #[test]
+    fn requires_a_dir_only_when_adopting() {
+        assert!(matches!(
+            resolve(Platform::Android, None, opts::Adopt::Yes),
+            Err(Error::ExistingProjectDirUnset {
+                platform: Platform::Android
+            })
+        ));
+        assert!(matches!(
+            resolve(Platform::Apple, None, opts::Adopt::No),
+            Ok(None)
+        ));
+        let dir = TempDirGuard::new().unwrap();
+        assert!(matches!(
+            resolve(
+                Platform::Apple,
+                Some(dir.join("missing").as_path()),
+                opts::Adopt::No
+            ),
+            Err(Error::ExistingProjectDirInvalid { .. })
+        ));
+        fs::write(dir.join("file"), "").unwrap();
+        assert!(matches!(
+            resolve(
+                Platform::Android,
+                Some(dir.join("file").as_path()),
+                opts::Adopt::No
+            ),
+            Err(Error::ExistingProjectDirInvalid { .. })
+        ));
+        assert_eq!(
+            resolve(Platform::Android, Some(&*dir), opts::Adopt::No).unwrap(),
+            Some(dir.canonicalize().unwrap())
+        );
+    }
+}
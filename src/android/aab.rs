@@ -0,0 +1,286 @@
+//! Catches broken bundles before they're uploaded, since Play only tells you
+//! after processing them.
+
+use super::{bundletool, config::Config, target::Target};
+use crate::{
+    opts::Profile,
+    util::{
+        cli::{Report, Reportable},
+        TempDirError, TempDirGuard,
+    },
+};
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum SizeParseError {
+    HeaderMissing,
+    ColumnMissing { column: &'static str },
+    RowInvalid { row: String },
+}
+
+impl Display for SizeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HeaderMissing => write!(f, "Output was empty."),
+            Self::ColumnMissing { column } => write!(f, "Output had no {:?} column.", column),
+            Self::RowInvalid { row } => write!(f, "Row {:?} isn't valid.", row),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum VerifyError {
+    BundleMissing { path: PathBuf },
+    BundletoolInstallFailed(bundletool::InstallError),
+    ValidateFailed(bossy::Error),
+    TempDirFailed(TempDirError),
+    BuildApksFailed(bossy::Error),
+    GetSizeFailed(bossy::Error),
+    SizeParseFailed(SizeParseError),
+    AbisMissing { path: PathBuf, abis: Vec<String> },
+}
+
+impl Reportable for VerifyError {
+    fn report(&self) -> Report {
+        match self {
+            Self::BundleMissing { path } => Report::action_request(
+                format!("No AAB at {:?}", path),
+                "Build one with `cargo android run` or Android Studio first, or pass `--bundle` to point at it.",
+            ),
+            Self::BundletoolInstallFailed(err) => err.report(),
+            Self::ValidateFailed(err) => Report::error("AAB is invalid", err),
+            Self::TempDirFailed(err) => Report::error("Failed to create temp dir for APKs", err),
+            Self::BuildApksFailed(err) => Report::error("Failed to build APKs from AAB", err),
+            Self::GetSizeFailed(err) => Report::error("Failed to estimate download sizes", err),
+            Self::SizeParseFailed(err) => {
+                Report::error("Failed to parse `bundletool get-size` output", err)
+            }
+            Self::AbisMissing { path, abis } => Report::error(
+                format!("AAB {:?} is missing ABIs", path),
+                format!(
+                    "There's no native code for {}, even though it's in `android.targets`. Make sure the Rust library was built for every target before bundling.",
+                    abis.join(", ")
+                ),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AbiSize {
+    /// Empty if the bundle doesn't have any native code.
+    pub abi: String,
+    pub min: u64,
+    pub max: u64,
+}
+
+/// Parses `bundletool get-size total --dimensions=ABI`, which is CSV with a
+/// column per dimension followed by `MIN` and `MAX`.
+pub fn parse_sizes(csv: &str) -> Result<Vec<AbiSize>, SizeParseError> {
+    let mut lines = csv.lines().map(str::trim).filter(|line| !line.is_empty());
+    let header = lines
+        .next()
+        .ok_or(SizeParseError::HeaderMissing)?
+        .split(',')
+        .collect::<Vec<_>>();
+    let column = |column: &'static str| {
+        header
+            .iter()
+            .position(|name| *name == column)
+            .ok_or(SizeParseError::ColumnMissing { column })
+    };
+    let (abi, min, max) = (column("ABI")?, column("MIN")?, column("MAX")?);
+    lines
+        .map(|line| {
+            let fields = line
+                .split(',')
+                .map(|field| field.trim_matches('"'))
+                .collect::<Vec<_>>();
+            let invalid = || SizeParseError::RowInvalid {
+                row: line.to_owned(),
+            };
+            let size = |index: usize| {
+                fields
+                    .get(index)
+                    .and_then(|field| field.parse::<u64>().ok())
+                    .ok_or_else(invalid)
+            };
+            Ok(AbiSize {
+                abi: fields.get(abi).ok_or_else(invalid)?.to_string(),
+                min: size(min)?,
+                max: size(max)?,
+            })
+        })
+        .collect()
+}
+
+/// The ABIs in `expected` that none of `sizes` are for.
+pub fn missing_abis(sizes: &[AbiSize], expected: &[&Target<'_>]) -> Vec<String> {
+    expected
+        .iter()
+        .map(|target| target.abi)
+        .filter(|abi| !sizes.iter().any(|size| size.abi == *abi))
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+// Play Console uses decimal units, so we do too.
+pub(super) fn format_size(bytes: u64) -> String {
+    if bytes < 1_000_000 {
+        format!("{:.1} kB", bytes as f64 / 1_000.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+    }
+}
+
+pub fn print_sizes(sizes: &[AbiSize]) {
+    println!("Estimated download sizes:");
+    for size in sizes {
+        let abi = if size.abi.is_empty() {
+            "(no native code)"
+        } else {
+            size.abi.as_str()
+        };
+        println!(
+            "  {:<16}{} - {}",
+            abi,
+            format_size(size.min),
+            format_size(size.max)
+        );
+    }
+}
+
+/// Validates the bundle at `path`, estimates its download sizes, and fails if
+/// it's missing native code for any of `expected`.
+pub fn verify(path: &Path, expected: &[&Target<'_>]) -> Result<Vec<AbiSize>, VerifyError> {
+    if !path.is_file() {
+        return Err(VerifyError::BundleMissing {
+            path: path.to_owned(),
+        });
+    }
+    bundletool::command()
+        .with_arg("validate")
+        .with_arg(format!("--bundle={}", path.display()))
+        .run_and_wait()
+        .map_err(VerifyError::ValidateFailed)?;
+    let stage = TempDirGuard::new().map_err(VerifyError::TempDirFailed)?;
+    let apks_path = stage.join("verify.apks");
+    // Without a device spec, this builds every split, which is what Play
+    // would serve from.
+    bundletool::command()
+        .with_arg("build-apks")
+        .with_arg(format!("--bundle={}", path.display()))
+        .with_arg(format!("--output={}", apks_path.display()))
+        .run_and_wait()
+        .map_err(VerifyError::BuildApksFailed)?;
+    let csv = bundletool::command()
+        .with_args(&["get-size", "total", "--dimensions=ABI"])
+        .with_arg(format!("--apks={}", apks_path.display()))
+        .run_and_wait_for_str(|s| s.to_owned())
+        .map_err(VerifyError::GetSizeFailed)?;
+    let sizes = parse_sizes(&csv).map_err(VerifyError::SizeParseFailed)?;
+    let abis = missing_abis(&sizes, expected);
+    if abis.is_empty() {
+        Ok(sizes)
+    } else {
+        print_sizes(&sizes);
+        Err(VerifyError::AbisMissing {
+            path: path.to_owned(),
+            abis,
+        })
+    }
+}
+
+/// Each flavor's bundle only has its own ABI, so that's all we expect from
+/// it.
+pub fn flavor_bundles(
+    config: &Config,
+    profile: Profile,
+) -> Vec<(PathBuf, Vec<&'static Target<'static>>)> {
+    config
+        .targets()
+        .into_iter()
+        .map(|target| (config.aab_path(profile, target.arch), vec![target]))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::target::TargetTrait as _;
+
+    // Captured from bundletool 1.8.0.
+    static MULTI_ABI: &str = "ABI,MIN,MAX\narm64-v8a,4418318,4418318\narmeabi-v7a,3887095,3887095\nx86,4520080,4520080\nx86_64,4555012,4555012\n";
+
+    #[test]
+    fn parses_sizes() {
+        let sizes = parse_sizes(MULTI_ABI).unwrap();
+        assert_eq!(sizes.len(), 4);
+        assert_eq!(
+            sizes[0],
+            AbiSize {
+                abi: "arm64-v8a".to_owned(),
+                min: 4418318,
+                max: 4418318,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_sizes_with_more_dimensions() {
+        let sizes = parse_sizes("SDK,ABI,MIN,MAX\n21-,arm64-v8a,1200,1500\n").unwrap();
+        assert_eq!(
+            sizes,
+            vec![AbiSize {
+                abi: "arm64-v8a".to_owned(),
+                min: 1200,
+                max: 1500,
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_sizes_without_native_code() {
+        let sizes = parse_sizes("ABI,MIN,MAX\n,2048,2048\n").unwrap();
+        assert_eq!(sizes[0].abi, "");
+        assert_eq!(
+            missing_abis(&sizes, &[Target::for_name("aarch64").unwrap()]),
+            vec!["arm64-v8a"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        assert!(matches!(
+            parse_sizes(""),
+            Err(SizeParseError::HeaderMissing)
+        ));
+        assert!(matches!(
+            parse_sizes("MIN,MAX\n1,2\n"),
+            Err(SizeParseError::ColumnMissing { column: "ABI" })
+        ));
+        assert!(matches!(
+            parse_sizes("ABI,MIN,MAX\narm64-v8a,lots,2\n"),
+            Err(SizeParseError::RowInvalid { .. })
+        ));
+    }
+
+    #[test]
+    fn finds_missing_abis() {
+        let sizes = parse_sizes("ABI,MIN,MAX\narm64-v8a,1,2\nx86_64,1,2\n").unwrap();
+        let expected = ["aarch64", "armv7", "x86_64"]
+            .iter()
+            .map(|name| Target::for_name(name).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(missing_abis(&sizes, &expected), vec!["armeabi-v7a"]);
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(2_048), "2.0 kB");
+        assert_eq!(format_size(4_418_318), "4.4 MB");
+    }
+}
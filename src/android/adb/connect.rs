@@ -0,0 +1,272 @@
+//! Wireless debugging: devices on Android 11+ have to be paired once, and
+//! then connected to each time adb restarts, before they show up in
+//! `adb devices`.
+
+use crate::{
+    android::env::Env,
+    env::ExplicitEnv as _,
+    util::cli::{Report, Reportable},
+};
+use std::{sync::mpsc, thread, time::Duration};
+use thiserror::Error;
+
+/// How long to give each `auto-connect` address before moving on; a device
+/// that's asleep or off the network would otherwise hang `run` for a minute.
+pub const AUTO_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{addr:?} isn't a valid address; it should look like `192.168.1.50:5555`.")]
+    AddrInvalid { addr: String },
+    #[error("Failed to run `adb {subcommand}`: {source}")]
+    CommandFailed {
+        subcommand: &'static str,
+        source: bossy::Error,
+    },
+    #[error("Nothing is listening at {addr}. Make sure wireless debugging is on, and that the port matches the one shown on the device; it changes each time wireless debugging is toggled.")]
+    Refused { addr: String },
+    #[error("{addr} couldn't be reached. Make sure the device is awake and on the same network as this computer.")]
+    Unreachable { addr: String },
+    #[error("{addr} is connected, but offline. Try toggling wireless debugging on the device, then connect again.")]
+    Offline { addr: String },
+    #[error("{addr} refused to authenticate this computer. Pair with it first using `cargo android pair`.")]
+    Unauthorized { addr: String },
+    #[error("The pairing code for {addr} was wrong, or the pairing dialog was closed. Pairing codes are single-use, so get a new one from the device and try again.")]
+    WrongCode { addr: String },
+    #[error("Your adb is too old for wireless pairing; update the Android SDK platform tools to version 30 or later.")]
+    AdbTooOld,
+    #[error("Timed out after {} seconds.", .timeout.as_secs())]
+    TimedOut { timeout: Duration },
+    #[error("adb said: {output}")]
+    Unrecognized { output: String },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::CommandFailed { .. } | Self::Unrecognized { .. } => {
+                Report::error("Failed to connect to device", self)
+            }
+            _ => Report::action_request("Failed to connect to device", self),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Connected {
+    Newly,
+    Already,
+}
+
+/// Whether `addr` looks like `host:port`.
+pub fn addr_valid(addr: &str) -> bool {
+    addr.rsplit_once(':').map_or(false, |(host, port)| {
+        !host.is_empty() && port.parse::<u16>().is_ok()
+    })
+}
+
+fn validate_addr(addr: &str) -> Result<(), Error> {
+    if addr_valid(addr) {
+        Ok(())
+    } else {
+        Err(Error::AddrInvalid {
+            addr: addr.to_owned(),
+        })
+    }
+}
+
+// adb prints most of these to stdout, and older versions exit successfully
+// even when connecting failed, so the exit status tells us very little.
+fn output_text(result: &bossy::Result<bossy::Output>) -> Option<String> {
+    let output = match result {
+        Ok(output) => output,
+        Err(err) => err.output()?,
+    };
+    Some(format!(
+        "{}{}",
+        String::from_utf8_lossy(output.stdout()),
+        String::from_utf8_lossy(output.stderr())
+    ))
+}
+
+pub fn parse_connect(addr: &str, output: &str) -> Result<Connected, Error> {
+    let addr = addr.to_owned();
+    let lower = output.to_ascii_lowercase();
+    if lower.contains("already connected") {
+        Ok(Connected::Already)
+    } else if lower.contains("connection refused") {
+        Err(Error::Refused { addr })
+    } else if lower.contains("failed to authenticate") {
+        Err(Error::Unauthorized { addr })
+    } else if lower.contains("no route to host")
+        || lower.contains("timed out")
+        || lower.contains("network is unreachable")
+        || lower.contains("host is down")
+    {
+        Err(Error::Unreachable { addr })
+    } else if lower.contains("offline") {
+        Err(Error::Offline { addr })
+    } else if lower.trim_start().starts_with("connected to") {
+        Ok(Connected::Newly)
+    } else {
+        Err(Error::Unrecognized {
+            output: output.trim().to_owned(),
+        })
+    }
+}
+
+pub fn parse_pair(addr: &str, output: &str) -> Result<(), Error> {
+    let addr = addr.to_owned();
+    let lower = output.to_ascii_lowercase();
+    if lower.contains("successfully paired") {
+        Ok(())
+    } else if lower.contains("wrong password") {
+        Err(Error::WrongCode { addr })
+    } else if lower.contains("unknown command") {
+        Err(Error::AdbTooOld)
+    } else if lower.contains("unable to start pairing client") {
+        Err(Error::Unreachable { addr })
+    } else {
+        Err(Error::Unrecognized {
+            output: output.trim().to_owned(),
+        })
+    }
+}
+
+fn adb(env: &Env) -> bossy::Command {
+    bossy::Command::pure("adb").with_env_vars(env.explicit_env())
+}
+
+fn run(
+    subcommand: &'static str,
+    command: bossy::Command,
+    parse: impl FnOnce(&str) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut command = command;
+    let result = command.run_and_wait_for_output();
+    match output_text(&result) {
+        Some(text) => parse(&text),
+        None => result
+            .map(|_| ())
+            .map_err(|source| Error::CommandFailed { subcommand, source }),
+    }
+}
+
+fn finish_connect(addr: &str, command: bossy::Command) -> Result<Connected, Error> {
+    let mut connected = Connected::Newly;
+    run("connect", command, |output| {
+        parse_connect(addr, output).map(|status| connected = status)
+    })?;
+    Ok(connected)
+}
+
+pub fn connect(env: &Env, addr: &str) -> Result<Connected, Error> {
+    validate_addr(addr)?;
+    finish_connect(addr, adb(env).with_args(&["connect", addr]))
+}
+
+pub fn pair(env: &Env, addr: &str, code: &str) -> Result<(), Error> {
+    validate_addr(addr)?;
+    run(
+        "pair",
+        adb(env).with_args(&["pair", addr, code]),
+        |output| parse_pair(addr, output),
+    )
+}
+
+/// Like `connect`, but gives up after `timeout`. adb is left to finish on its
+/// own, which is harmless.
+pub fn connect_with_timeout(env: &Env, addr: &str, timeout: Duration) -> Result<Connected, Error> {
+    validate_addr(addr)?;
+    let command = adb(env).with_args(&["connect", addr]);
+    let (tx, rx) = mpsc::channel();
+    let addr = addr.to_owned();
+    thread::spawn(move || {
+        let _ = tx.send(finish_connect(&addr, command));
+    });
+    rx.recv_timeout(timeout)
+        .unwrap_or(Err(Error::TimedOut { timeout }))
+}
+
+/// Tries each address in `android.auto-connect`, so wireless devices are
+/// listed like any other. Failing is fine, since the device might just be
+/// off somewhere.
+pub fn auto_connect(env: &Env, addrs: &[String]) {
+    for addr in addrs {
+        match connect_with_timeout(env, addr, AUTO_CONNECT_TIMEOUT) {
+            Ok(Connected::Newly) => println!("Connected to {}", addr),
+            Ok(Connected::Already) => log::info!("already connected to {}", addr),
+            Err(err) => log::warn!("failed to auto-connect to {}: {}", addr, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        output,
+        expected,
+        case("connected to 192.168.1.50:5555\n", Ok(Connected::Newly)),
+        case("already connected to 192.168.1.50:5555\n", Ok(Connected::Already)),
+        case(
+            "failed to connect to '192.168.1.50:5555': Connection refused\n",
+            Err("refused")
+        ),
+        case(
+            "cannot connect to 192.168.1.50:5555: No route to host (113)\n",
+            Err("unreachable")
+        ),
+        case(
+            "failed to connect to '192.168.1.50:5555': Operation timed out\n",
+            Err("unreachable")
+        ),
+        case("error: device offline\n", Err("offline")),
+        case("failed to authenticate to 192.168.1.50:5555\n", Err("unauthorized")),
+        case("something new\n", Err("unrecognized"))
+    )]
+    fn parses_connect(output: &str, expected: Result<Connected, &str>) {
+        let actual = parse_connect("192.168.1.50:5555", output).map_err(|err| match err {
+            Error::Refused { .. } => "refused",
+            Error::Unreachable { .. } => "unreachable",
+            Error::Offline { .. } => "offline",
+            Error::Unauthorized { .. } => "unauthorized",
+            Error::Unrecognized { .. } => "unrecognized",
+            _ => "other",
+        });
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn parses_pair() {
+        let addr = "192.168.1.50:37123";
+        assert!(parse_pair(
+            addr,
+            "Successfully paired to 192.168.1.50:37123 [guid=adb-28201FDH2004RV-vWxvFz]"
+        )
+        .is_ok());
+        assert!(matches!(
+            parse_pair(addr, "Failed: Wrong password or connection was dropped."),
+            Err(Error::WrongCode { .. })
+        ));
+        assert!(matches!(
+            parse_pair(addr, "adb: unknown command pair"),
+            Err(Error::AdbTooOld)
+        ));
+    }
+
+    #[rstest(
+        addr,
+        valid,
+        case("192.168.1.50:5555", true),
+        case("[fe80::1]:5555", true),
+        case("192.168.1.50", false),
+        case(":5555", false),
+        case("phone:port", false)
+    )]
+    fn validates_addrs(addr: &str, valid: bool) {
+        assert_eq!(validate_addr(addr).is_ok(), valid);
+    }
+}
@@ -1,4 +1,4 @@
-use super::{device_name, get_prop};
+use super::{device_name, get_prop, get_props, server};
 use crate::{
     android::{device::Device, env::Env, target::Target},
     env::ExplicitEnv as _,
@@ -10,33 +10,58 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error(transparent)]
+    ServerFailed(#[from] server::Error),
     #[error("Failed to run `adb devices`: {0}")]
     DevicesFailed(#[from] super::RunCheckedError),
     #[error(transparent)]
     NameFailed(#[from] device_name::Error),
     #[error(transparent)]
-    ModelFailed(get_prop::Error),
-    #[error(transparent)]
-    AbiFailed(get_prop::Error),
+    PropsFailed(#[from] get_prop::Error),
+    #[error("Device didn't report a value for `{0}`.")]
+    PropMissing(&'static str),
     #[error("{0:?} isn't a valid target ABI.")]
     AbiInvalid(String),
+    #[error("{0:?} isn't a valid API level.")]
+    ApiLevelInvalid(String),
 }
 
 impl Reportable for Error {
     fn report(&self) -> Report {
         let msg = "Failed to detect connected Android devices";
         match self {
+            Self::ServerFailed(err) => err.report(),
             Self::DevicesFailed(err) => err.report("Failed to run `adb devices`"),
             Self::NameFailed(err) => err.report(),
-            Self::ModelFailed(err) | Self::AbiFailed(err) => err.report(),
-            Self::AbiInvalid(_) => Report::error(msg, self),
+            Self::PropsFailed(err) => err.report(),
+            Self::PropMissing(_) | Self::AbiInvalid(_) | Self::ApiLevelInvalid(_) => {
+                Report::error(msg, self)
+            }
         }
     }
 }
 
-const ADB_DEVICE_REGEX: &str = r"^([\S]{6,22})	device\b";
+// mDNS service names from wireless debugging are much longer than serials.
+const ADB_DEVICE_REGEX: &str = r"^([\S]{6,22}|\S+\._adb-tls-connect\._tcp\.?)	device\b";
+
+const MODEL_PROP: &str = "ro.product.model";
+const ABI_PROP: &str = "ro.product.cpu.abi";
+const OS_VERSION_PROP: &str = "ro.build.version.release";
+const API_LEVEL_PROP: &str = "ro.build.version.sdk";
 
 pub fn device_list(env: &Env) -> Result<BTreeSet<Device<'static>>, Error> {
+    server::ensure_running(env)?;
+    server::with_restart(
+        env,
+        || list_devices(env),
+        |err| match err {
+            Error::DevicesFailed(err) => err.is_wedged(),
+            _ => false,
+        },
+    )
+}
+
+fn list_devices(env: &Env) -> Result<BTreeSet<Device<'static>>, Error> {
     super::check_authorized(
         bossy::Command::pure("adb")
             .with_env_vars(env.explicit_env())
@@ -48,13 +73,26 @@ pub fn device_list(env: &Env) -> Result<BTreeSet<Device<'static>>, Error> {
                         assert_eq!(caps.len(), 2);
                         let serial_no = caps.get(1).unwrap().as_str().to_owned();
                         let name = device_name(env, &serial_no).map_err(Error::NameFailed)?;
-                        let model = get_prop(env, &serial_no, "ro.product.model")
-                            .map_err(Error::ModelFailed)?;
-                        let abi = get_prop(env, &serial_no, "ro.product.cpu.abi")
-                            .map_err(Error::AbiFailed)?;
+                        let mut props = get_props(
+                            env,
+                            &serial_no,
+                            &[MODEL_PROP, ABI_PROP, OS_VERSION_PROP, API_LEVEL_PROP],
+                        )
+                        .map_err(Error::PropsFailed)?;
+                        let mut take =
+                            |prop: &'static str| props.remove(prop).ok_or(Error::PropMissing(prop));
+                        let model = take(MODEL_PROP)?;
+                        let abi = take(ABI_PROP)?;
+                        let os_version = take(OS_VERSION_PROP)?;
+                        let api_level = take(API_LEVEL_PROP)?;
                         let target =
                             Target::for_abi(&abi).ok_or_else(|| Error::AbiInvalid(abi.clone()))?;
-                        Ok(Device::new(serial_no, name, model, target))
+                        let api_level = api_level
+                            .parse()
+                            .map_err(|_| Error::ApiLevelInvalid(api_level.clone()))?;
+                        Ok(Device::new(
+                            serial_no, name, model, os_version, api_level, target,
+                        ))
                     })
                     .collect()
             }),
@@ -84,6 +122,11 @@ mod test {
             0a388e93	device usb:1-1 product:razor model:Nexus_7 device:flo\n\
             ", vec!["emulator-5556", "emulator-5554", "0a388e93"]
         ),
+        case("List of devices attached\n\
+            adb-28201FDH2004RV-vWxvFz._adb-tls-connect._tcp.\tdevice\n\
+            192.168.1.50:5555\toffline\n\
+            ", vec!["adb-28201FDH2004RV-vWxvFz._adb-tls-connect._tcp."]
+        ),
 
     )]
     fn test_adb_output_regex(input: &str, devices: Vec<&'static str>) {
@@ -3,43 +3,71 @@ use crate::{
     android::env::Env,
     util::cli::{Report, Reportable},
 };
-use std::str;
+use once_cell_regex::regex_multi_line;
+use std::collections::HashMap;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("Failed to run `adb shell getprop {prop}`: {source}")]
-    LookupFailed {
-        prop: String,
-        source: super::RunCheckedError,
-    },
-}
-
-impl Error {
-    fn prop(&self) -> &str {
-        match self {
-            Self::LookupFailed { prop, .. } => prop,
-        }
-    }
+    #[error("Failed to run `adb shell getprop`: {0}")]
+    LookupFailed(#[source] super::RunCheckedError),
 }
 
 impl Reportable for Error {
     fn report(&self) -> Report {
-        let msg = format!("Failed to run `adb shell getprop {}`", self.prop());
         match self {
-            Self::LookupFailed { source, .. } => source.report(&msg),
+            Self::LookupFailed(err) => err.report("Failed to run `adb shell getprop`"),
         }
     }
 }
 
-pub fn get_prop(env: &Env, serial_no: &str, prop: &str) -> Result<String, Error> {
+const GETPROP_LINE_REGEX: &str = r"^\[(?P<key>[^\]]+)\]: \[(?P<value>[^\]]*)\]";
+
+fn parse_props<'a>(raw: &str, props: &[&'a str]) -> HashMap<&'a str, String> {
+    regex_multi_line!(GETPROP_LINE_REGEX)
+        .captures_iter(raw)
+        .filter_map(|caps| {
+            props
+                .iter()
+                .find(|prop| **prop == &caps["key"])
+                .map(|prop| (*prop, caps["value"].trim().to_owned()))
+        })
+        .collect()
+}
+
+/// Looks up several properties using a single `adb shell getprop` call.
+/// Properties that aren't set on the device are absent from the result.
+pub fn get_props<'a>(
+    env: &Env,
+    serial_no: &str,
+    props: &[&'a str],
+) -> Result<HashMap<&'a str, String>, Error> {
     super::check_authorized(
         adb(env, serial_no)
-            .with_args(&["shell", "getprop", prop])
-            .run_and_wait_for_str(|s| s.trim().to_owned()),
+            .with_args(&["shell", "getprop"])
+            .run_and_wait_for_str(|raw| parse_props(raw, props)),
     )
-    .map_err(|source| Error::LookupFailed {
-        prop: prop.to_owned(),
-        source,
-    })
+    .map_err(Error::LookupFailed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(input, props, expected,
+        case("[ro.build.version.release]: [13]\n\
+            [ro.build.version.sdk]: [33]\n\
+            [ro.product.model]: [Pixel 4a]\n\
+            ", &["ro.build.version.sdk", "ro.product.model"], vec![("ro.build.version.sdk", "33"), ("ro.product.model", "Pixel 4a")]
+        ),
+        case("[ro.product.cpu.abi]: [arm64-v8a]\n", &["ro.build.version.sdk"], vec![]),
+    )]
+    fn test_parse_props(input: &str, props: &[&str], expected: Vec<(&str, &str)>) {
+        let parsed = parse_props(input, props);
+        assert_eq!(parsed.len(), expected.len());
+        for (key, value) in expected {
+            assert_eq!(parsed[key], value);
+        }
+    }
 }
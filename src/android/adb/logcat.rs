@@ -0,0 +1,240 @@
+//! Everything that goes into the `adb logcat` command line for `run`, kept
+//! apart from running it so the combinations can be checked without a device.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// `-v color` arrived in Android 7.0, and older devices reject the whole
+/// command if they're given it.
+pub const COLOR_MIN_API_LEVEL: u32 = 24;
+
+/// The line formats `adb logcat -v` takes. The others it takes, like `color`
+/// and `epoch`, only modify one of these.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Brief,
+    Long,
+    Process,
+    Raw,
+    Tag,
+    Thread,
+    /// Date, time, PID, and TID, which is what multi-process apps want.
+    Threadtime,
+    Time,
+}
+
+impl Format {
+    pub const ALL: &'static [Self] = &[
+        Self::Brief,
+        Self::Long,
+        Self::Process,
+        Self::Raw,
+        Self::Tag,
+        Self::Thread,
+        Self::Threadtime,
+        Self::Time,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Brief => "brief",
+            Self::Long => "long",
+            Self::Process => "process",
+            Self::Raw => "raw",
+            Self::Tag => "tag",
+            Self::Thread => "thread",
+            Self::Threadtime => "threadtime",
+            Self::Time => "time",
+        }
+    }
+
+    pub fn names() -> Vec<&'static str> {
+        Self::ALL.iter().map(|format| format.as_str()).collect()
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "{:?} isn't a logcat format; expected one of {}",
+                    s,
+                    Self::names().join(", ")
+                )
+            })
+    }
+}
+
+/// Whether `tag` can go in a `<tag>:<priority>` filterspec.
+pub fn tag_valid(tag: &str) -> bool {
+    !tag.is_empty() && !tag.contains(|c: char| c == ':' || c.is_whitespace())
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Args {
+    format: Option<Format>,
+    color: bool,
+    tags: Vec<String>,
+    priority: &'static str,
+}
+
+impl Args {
+    /// Only `priority` and up gets through for each of `tags`, and nothing
+    /// else gets through at all.
+    pub fn new(tags: impl IntoIterator<Item = String>, priority: &'static str) -> Self {
+        let mut deduped = Vec::new();
+        for tag in tags {
+            if !deduped.contains(&tag) {
+                deduped.push(tag);
+            }
+        }
+        Self {
+            format: None,
+            color: true,
+            tags: deduped,
+            priority,
+        }
+    }
+
+    pub fn with_format(mut self, format: Option<Format>) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Leaves out `-v color` for devices that don't know it.
+    pub fn for_api_level(mut self, api_level: u32) -> Self {
+        self.color = api_level >= COLOR_MIN_API_LEVEL;
+        self
+    }
+
+    /// Everything after `adb -s <serial>`. Once we've `reattached` after a
+    /// disconnect, only new lines are asked for, since otherwise the whole
+    /// buffer gets printed again.
+    pub fn args(&self, reattached: bool) -> Vec<String> {
+        let mut args = vec!["logcat".to_owned()];
+        if let Some(format) = self.format {
+            args.push("-v".to_owned());
+            args.push(format.to_string());
+        }
+        if self.color {
+            args.push("-v".to_owned());
+            args.push("color".to_owned());
+        }
+        if reattached {
+            args.push("-T".to_owned());
+            args.push("1".to_owned());
+        }
+        args.push("-s".to_owned());
+        args.extend(
+            self.tags
+                .iter()
+                .map(|tag| format!("{}:{}", tag, self.priority)),
+        );
+        args
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn args(format: Option<Format>, api_level: u32, tags: &[&str], reattached: bool) -> String {
+        Args::new(tags.iter().map(|tag| (*tag).to_owned()), "I")
+            .with_format(format)
+            .for_api_level(api_level)
+            .args(reattached)
+            .join(" ")
+    }
+
+    #[rstest(
+        format,
+        api_level,
+        tags,
+        reattached,
+        expected,
+        case(None, 30, &["app"], false, "logcat -v color -s app:I"),
+        case(
+            Some(Format::Threadtime),
+            30,
+            &["app"],
+            false,
+            "logcat -v threadtime -v color -s app:I"
+        ),
+        case(Some(Format::Time), 23, &["app"], false, "logcat -v time -s app:I"),
+        case(None, 23, &["app"], false, "logcat -s app:I"),
+        case(
+            None,
+            30,
+            &["app", "app-sandbox", "app"],
+            false,
+            "logcat -v color -s app:I app-sandbox:I"
+        ),
+        case(
+            Some(Format::Brief),
+            30,
+            &["app"],
+            true,
+            "logcat -v brief -v color -T 1 -s app:I"
+        )
+    )]
+    fn builds_args(
+        format: Option<Format>,
+        api_level: u32,
+        tags: &[&str],
+        reattached: bool,
+        expected: &str,
+    ) {
+        assert_eq!(args(format, api_level, tags, reattached), expected);
+    }
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!("threadtime".parse(), Ok(Format::Threadtime));
+        assert_eq!("TIME".parse(), Ok(Format::Time));
+        assert!("color".parse::<Format>().is_err());
+        for format in Format::ALL {
+            assert_eq!(format.as_str().parse(), Ok(*format));
+        }
+    }
+
+    #[test]
+    fn deserializes_formats() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            format: Format,
+        }
+        let wrapper = toml::from_str::<Wrapper>(r#"format = "threadtime""#).unwrap();
+        assert_eq!(wrapper.format, Format::Threadtime);
+        assert!(toml::from_str::<Wrapper>(r#"format = "epoch""#).is_err());
+    }
+
+    #[rstest(
+        tag,
+        valid,
+        case("app", true),
+        case("app-sandbox", true),
+        case("", false),
+        case("app:I", false),
+        case("my app", false)
+    )]
+    fn validates_tags(tag: &str, valid: bool) {
+        assert_eq!(tag_valid(tag), valid);
+    }
+}
@@ -1,8 +1,13 @@
+pub mod connect;
 pub mod device_list;
 pub mod device_name;
 pub mod get_prop;
+pub mod logcat;
+pub mod pidof;
+pub mod reconnect;
+pub mod server;
 
-pub use self::{device_list::device_list, device_name::device_name, get_prop::get_prop};
+pub use self::{device_list::device_list, device_name::device_name, get_prop::get_props};
 
 use super::env::Env;
 use crate::{env::ExplicitEnv as _, util::cli::Report};
@@ -15,6 +20,17 @@ pub fn adb(env: &Env, serial_no: &str) -> bossy::Command {
         .with_args(&["-s", serial_no])
 }
 
+/// The same as `adb`, but for running under the watchdog, which needs to own
+/// the child.
+pub fn watched_adb(env: &Env, serial_no: &str) -> std::process::Command {
+    let mut command = std::process::Command::new("adb");
+    command
+        .env_clear()
+        .envs(env.explicit_env())
+        .args(&["-s", serial_no]);
+    command
+}
+
 #[derive(Debug, Error)]
 pub enum RunCheckedError {
     #[error(transparent)]
@@ -26,6 +42,13 @@ pub enum RunCheckedError {
 }
 
 impl RunCheckedError {
+    pub fn is_wedged(&self) -> bool {
+        match self {
+            Self::CommandFailed(err) => server::is_wedged_error(err),
+            _ => false,
+        }
+    }
+
     pub fn report(&self, msg: &str) -> Report {
         match self {
             Self::InvalidUtf8(err) => Report::error(msg, err),
@@ -35,6 +58,13 @@ impl RunCheckedError {
     }
 }
 
+/// `adb shell` joins its arguments into a single command line that the
+/// device's shell splits again, so anything we want passed through verbatim
+/// needs to be quoted first.
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
 fn check_authorized<T>(result: bossy::Result<T>) -> Result<T, RunCheckedError> {
     if let Err(err) = &result {
         if let Some(stderr) = err
@@ -49,3 +79,21 @@ fn check_authorized<T>(result: bossy::Result<T>) -> Result<T, RunCheckedError> {
     }
     result.map_err(RunCheckedError::CommandFailed)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        arg,
+        quoted,
+        case("-e", "'-e'"),
+        case("two words", "'two words'"),
+        case("key=value", "'key=value'"),
+        case("it's", r"'it'\''s'")
+    )]
+    fn test_shell_quote(arg: &str, quoted: &str) {
+        assert_eq!(shell_quote(arg), quoted);
+    }
+}
@@ -0,0 +1,87 @@
+use super::adb;
+use crate::{
+    android::env::Env,
+    util::cli::{Report, Reportable},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to look up the app's process: {0}")]
+    LookupFailed(#[source] super::RunCheckedError),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::LookupFailed(err) => err.report("Failed to look up the app's process"),
+        }
+    }
+}
+
+/// The pids in the output of `pidof` or `ps`. `pidof` gives a line of pids,
+/// while `ps` gives a table with the pid second and the name last.
+pub fn parse_pids(output: &str, app_id: &str) -> Vec<u32> {
+    let mut pids = Vec::new();
+    for line in output.lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.is_empty() {
+            continue;
+        }
+        if fields.iter().all(|field| field.parse::<u32>().is_ok()) {
+            pids.extend(fields.iter().filter_map(|field| field.parse::<u32>().ok()));
+        } else if fields.last() == Some(&app_id) {
+            pids.extend(fields.get(1).and_then(|field| field.parse::<u32>().ok()));
+        }
+    }
+    pids
+}
+
+/// The pids `app_id` is running as, which is empty if it isn't. `pidof` only
+/// showed up in Android 7, so older devices get `ps` instead, which lists
+/// everything there; newer ones only fall through to it when nothing was
+/// found, and their `ps` then only lists the shell.
+pub fn pids(env: &Env, serial_no: &str, app_id: &str) -> Result<Vec<u32>, Error> {
+    let quoted = super::shell_quote(app_id);
+    super::check_authorized(
+        adb(env, serial_no)
+            .with_args(&["shell", &format!("pidof {} 2>/dev/null || ps", quoted)])
+            .run_and_wait_for_str(|output| parse_pids(output, app_id)),
+    )
+    .map_err(Error::LookupFailed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    static APP_ID: &str = "com.example.tictac";
+
+    #[rstest(
+        output,
+        expected,
+        case("12345\n", vec![12345]),
+        case("12345 12377\n", vec![12345, 12377]),
+        case("", vec![]),
+        // Android 6's `ps`, after its shell said `pidof` wasn't there.
+        case(
+            "/system/bin/sh: pidof: not found\n\
+            USER      PID   PPID  VSIZE  RSS   WCHAN              PC  NAME\n\
+            root      1     0     8896   780   SyS_epoll_ 00000000 S /init\n\
+            u0_a87    4821  213   1523600 61420 SyS_epoll_ 00000000 S com.example.tictac\n\
+            u0_a87    4870  213   1412048 40112 SyS_epoll_ 00000000 S com.example.tictac:remote\n",
+            vec![4821]
+        ),
+        // Android 13's `ps`, which only lists the shell.
+        case(
+            "USER           PID  PPID     VSZ    RSS WCHAN            ADDR S NAME\n\
+            shell        20212 20210 10904336  3376 __do_sys_+          0 S sh\n\
+            shell        20215 20212 10929096  3892 0                   0 R ps\n",
+            vec![]
+        )
+    )]
+    fn parses_pids(output: &str, expected: Vec<u32>) {
+        assert_eq!(parse_pids(output, APP_ID), expected);
+    }
+}
@@ -0,0 +1,236 @@
+//! A USB hiccup kills `adb logcat` even though the app keeps running, so
+//! instead of ending the session there, we wait for the device to come back
+//! and pick the stream back up. We never install a Ctrl-C handler, so
+//! cancelling still exits right away, even while we're waiting.
+
+use super::{adb, logcat::Args};
+use crate::android::env::Env;
+use colored::Colorize as _;
+use std::{thread, time::Duration};
+
+/// How often we check whether the device is back.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// What adb says when the device went away out from under it.
+static DISCONNECT_SIGNATURES: &[&str] = &[
+    "device offline",
+    "no devices/emulators found",
+    "error: closed",
+    "device not found",
+    "' not found",
+];
+
+/// How a logcat process ended.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    Exited(i32),
+    /// Which is what Ctrl-C does, since it goes to the whole process group.
+    Signaled,
+    /// It never got going, i.e. `adb` isn't where we think it is.
+    NotRun,
+}
+
+impl Status {
+    fn from_error(err: &bossy::Error) -> Self {
+        match (err.output(), err.code()) {
+            (None, _) => Self::NotRun,
+            (Some(_), Some(code)) => Self::Exited(code),
+            (Some(_), None) => Self::Signaled,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Exit {
+    Ended,
+    Cancelled,
+    Disconnected,
+    Failed,
+}
+
+pub fn is_disconnect(stderr: &str) -> bool {
+    let lower = stderr.to_ascii_lowercase();
+    DISCONNECT_SIGNATURES
+        .iter()
+        .any(|signature| lower.contains(signature))
+}
+
+/// What to make of logcat ending. adb doesn't always say anything when the
+/// cable goes, so `online` is asked as a fallback.
+pub fn classify(status: Status, stderr: &str, online: impl FnOnce() -> bool) -> Exit {
+    match status {
+        // 130 is the shell's way of saying SIGINT.
+        Status::Signaled | Status::Exited(130) => Exit::Cancelled,
+        Status::NotRun => Exit::Failed,
+        Status::Exited(code) => {
+            if is_disconnect(stderr) || !online() {
+                Exit::Disconnected
+            } else if code == 0 {
+                Exit::Ended
+            } else {
+                Exit::Failed
+            }
+        }
+    }
+}
+
+/// Polls `online` every `interval` until it's true or `window` has passed,
+/// giving back whether the device came back.
+pub fn wait_for_device(
+    window: Duration,
+    interval: Duration,
+    mut online: impl FnMut() -> bool,
+    mut sleep: impl FnMut(Duration),
+) -> bool {
+    let mut waited = Duration::ZERO;
+    while waited < window {
+        let nap = interval.min(window - waited);
+        sleep(nap);
+        waited += nap;
+        if online() {
+            return true;
+        }
+    }
+    false
+}
+
+fn online(env: &Env, serial_no: &str) -> bool {
+    adb(env, serial_no)
+        .with_arg("get-state")
+        .run_and_wait_for_str(|state| state.trim() == "device")
+        .unwrap_or(false)
+}
+
+/// Streams logcat with `args` until it ends on its own, reattaching (without
+/// reinstalling or relaunching anything) whenever the device drops for less
+/// than `window`. Past that, the original error is what's returned.
+pub fn logcat(
+    env: &Env,
+    serial_no: &str,
+    args: &Args,
+    window: Duration,
+) -> Result<(), bossy::Error> {
+    let mut reattached = false;
+    loop {
+        let err = match adb(env, serial_no)
+            .with_args(args.args(reattached))
+            .with_stderr_piped()
+            .run()
+            .and_then(|handle| handle.wait_for_output())
+        {
+            Ok(_) => return Ok(()),
+            Err(err) => err,
+        };
+        let stderr = err
+            .output()
+            .map(|output| String::from_utf8_lossy(output.stderr()).into_owned())
+            .unwrap_or_default();
+        match classify(Status::from_error(&err), &stderr, || online(env, serial_no)) {
+            Exit::Ended | Exit::Cancelled => return Ok(()),
+            Exit::Failed => return Err(err),
+            Exit::Disconnected if window.is_zero() => return Err(err),
+            Exit::Disconnected => {
+                println!(
+                    "{}",
+                    format!(
+                        "--- {} disconnected; waiting up to {}s for it to come back ---",
+                        serial_no,
+                        window.as_secs()
+                    )
+                    .yellow()
+                    .bold()
+                );
+                if !wait_for_device(
+                    window,
+                    POLL_INTERVAL,
+                    || online(env, serial_no),
+                    thread::sleep,
+                ) {
+                    return Err(err);
+                }
+                println!(
+                    "{}",
+                    format!("--- {} reconnected; resuming logs ---", serial_no)
+                        .green()
+                        .bold()
+                );
+                reattached = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+    use std::cell::Cell;
+
+    #[rstest(
+        status,
+        stderr,
+        online,
+        expected,
+        case(Status::Signaled, "", false, Exit::Cancelled),
+        case(Status::Exited(130), "", true, Exit::Cancelled),
+        case(Status::NotRun, "", true, Exit::Failed),
+        case(Status::Exited(1), "error: device offline\n", true, Exit::Disconnected),
+        case(
+            Status::Exited(1),
+            "error: device 'R58M123' not found\n",
+            true,
+            Exit::Disconnected
+        ),
+        case(Status::Exited(255), "error: closed\n", true, Exit::Disconnected),
+        case(Status::Exited(1), "", false, Exit::Disconnected),
+        case(Status::Exited(0), "", false, Exit::Disconnected),
+        case(Status::Exited(0), "", true, Exit::Ended),
+        case(
+            Status::Exited(1),
+            "logcat: Unknown -v option 'colour'\n",
+            true,
+            Exit::Failed
+        )
+    )]
+    fn classifies_exits(status: Status, stderr: &str, online: bool, expected: Exit) {
+        assert_eq!(classify(status, stderr, || online), expected);
+    }
+
+    #[test]
+    fn cancelling_never_asks_about_the_device() {
+        assert_eq!(
+            classify(Status::Signaled, "", || panic!("asked about the device")),
+            Exit::Cancelled
+        );
+    }
+
+    /// How many polls it took, how long was slept, and whether it came back.
+    fn wait(window_secs: u64, online_after: Option<usize>) -> (usize, Duration, bool) {
+        let polls = Cell::new(0);
+        let slept = Cell::new(Duration::ZERO);
+        let back = wait_for_device(
+            Duration::from_secs(window_secs),
+            Duration::from_secs(2),
+            || {
+                polls.set(polls.get() + 1);
+                online_after.map_or(false, |after| polls.get() >= after)
+            },
+            |nap| slept.set(slept.get() + nap),
+        );
+        (polls.get(), slept.get(), back)
+    }
+
+    #[test]
+    fn waits_until_the_device_is_back() {
+        assert_eq!(wait(30, Some(3)), (3, Duration::from_secs(6), true));
+        assert_eq!(wait(30, Some(1)), (1, Duration::from_secs(2), true));
+    }
+
+    #[test]
+    fn gives_up_once_the_window_passes() {
+        // The last nap is cut short so we don't wait past the window.
+        assert_eq!(wait(5, None), (3, Duration::from_secs(5), false));
+        assert_eq!(wait(5, Some(4)), (3, Duration::from_secs(5), false));
+        assert_eq!(wait(0, Some(1)), (0, Duration::ZERO, false));
+    }
+}
@@ -0,0 +1,249 @@
+//! The adb server sometimes wedges, usually after another SDK's adb talked to
+//! it, and then every command fails until it's restarted. Restarting kills
+//! every other tool's connection, so we only do it when adb tells us it's
+//! stuck, and only once.
+
+use crate::{
+    android::env::Env,
+    env::ExplicitEnv as _,
+    util::cli::{Report, Reportable},
+};
+use std::{sync::mpsc, thread, time::Duration};
+use thiserror::Error;
+
+/// How long `adb devices` gets to answer before we call the server wedged.
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// What adb says when the server is alive but not talking sense.
+static WEDGED_SIGNATURES: &[&str] = &["protocol fault", "didn't ack"];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to run `adb start-server`: {0}")]
+    StartFailed(bossy::Error),
+    #[error("Failed to run `adb devices`: {0}")]
+    DevicesFailed(bossy::Error),
+    #[error("`adb devices` didn't respond within {} seconds.", .timeout.as_secs())]
+    TimedOut { timeout: Duration },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        Report::error("The adb server isn't responding", self)
+    }
+}
+
+impl Error {
+    /// Whether restarting the server could help, which a server that doesn't
+    /// answer at all needs as much as one that answers nonsense.
+    pub fn is_wedged(&self) -> bool {
+        match self {
+            Self::StartFailed(err) | Self::DevicesFailed(err) => is_wedged_error(err),
+            Self::TimedOut { .. } => true,
+        }
+    }
+}
+
+fn adb(env: &Env) -> bossy::Command {
+    bossy::Command::pure("adb").with_env_vars(env.explicit_env())
+}
+
+/// Whether adb's output means the server needs restarting.
+pub fn is_wedged(output: &str) -> bool {
+    let lower = output.to_ascii_lowercase();
+    WEDGED_SIGNATURES
+        .iter()
+        .any(|signature| lower.contains(signature))
+}
+
+/// Everything a failed command printed, since adb isn't consistent about
+/// which stream errors go to.
+pub fn error_output(err: &bossy::Error) -> Option<String> {
+    err.output().map(|output| {
+        format!(
+            "{}{}",
+            String::from_utf8_lossy(output.stdout()),
+            String::from_utf8_lossy(output.stderr())
+        )
+    })
+}
+
+pub fn is_wedged_error(err: &bossy::Error) -> bool {
+    error_output(err).map_or(false, |output| is_wedged(&output))
+}
+
+/// Runs `op`, and if it fails in a way `wedged` recognizes, calls `restart`
+/// and runs `op` once more. If restarting fails, the original error is what's
+/// surfaced, since that's the one the user can act on.
+pub fn retry_if_wedged<T, E>(
+    mut op: impl FnMut() -> Result<T, E>,
+    wedged: impl Fn(&E) -> bool,
+    restart: impl FnOnce() -> Result<(), Error>,
+) -> Result<T, E> {
+    match op() {
+        Err(err) if wedged(&err) => match restart() {
+            Ok(()) => op(),
+            Err(restart_err) => {
+                log::error!("failed to restart adb server: {}", restart_err);
+                Err(err)
+            }
+        },
+        result => result,
+    }
+}
+
+/// `retry_if_wedged` with an actual restart.
+pub fn with_restart<T, E>(
+    env: &Env,
+    op: impl FnMut() -> Result<T, E>,
+    wedged: impl Fn(&E) -> bool,
+) -> Result<T, E> {
+    retry_if_wedged(op, wedged, || restart(env))
+}
+
+fn devices_with_timeout(env: &Env, timeout: Duration) -> Result<(), Error> {
+    let mut command = adb(env).with_arg("devices");
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(
+            command
+                .run_and_wait_for_output()
+                .map(|_| ())
+                .map_err(Error::DevicesFailed),
+        );
+    });
+    // adb is left to finish on its own, like in `connect_with_timeout`.
+    rx.recv_timeout(timeout)
+        .unwrap_or(Err(Error::TimedOut { timeout }))
+}
+
+fn start(env: &Env) -> Result<(), Error> {
+    adb(env)
+        .with_arg("start-server")
+        .run_and_wait_for_output()
+        .map_err(Error::StartFailed)?;
+    devices_with_timeout(env, RESPONSE_TIMEOUT)
+}
+
+/// Starts the server if it isn't running, and checks that it answers,
+/// restarting it once if it doesn't.
+pub fn ensure_running(env: &Env) -> Result<(), Error> {
+    with_restart(env, || start(env), Error::is_wedged)
+}
+
+/// Kills and restarts the server, which disconnects anything else using it.
+pub fn restart(env: &Env) -> Result<(), Error> {
+    println!(
+        "Restarting the adb server, since it stopped responding; Android Studio and anything else using adb will be disconnected from devices until they reconnect."
+    );
+    // This fails if the server is already dead, which is fine.
+    if let Err(err) = adb(env).with_arg("kill-server").run_and_wait_for_output() {
+        log::warn!("`adb kill-server` failed: {}", err);
+    }
+    start(env)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+    use std::cell::Cell;
+
+    #[rstest(
+        output,
+        wedged,
+        case("adb: protocol fault (couldn't read status): Connection reset by peer\n", true),
+        case("error: protocol fault (no status)\n", true),
+        case("adb server version (41) doesn't match this client (39); killing...\n* daemon started successfully\nADB server didn't ACK\n* failed to start daemon\n", true),
+        case("* daemon not running; starting now at tcp:5037\n* daemon started successfully\nList of devices attached\n", false),
+        case("error: device unauthorized.\n", false),
+        case("adb: failed to install app.apk: Failure [INSTALL_FAILED_INSUFFICIENT_STORAGE]\n", false)
+    )]
+    fn detects_wedged_server(output: &str, wedged: bool) {
+        assert_eq!(is_wedged(output), wedged);
+    }
+
+    fn retry(
+        outputs: &[Result<(), &'static str>],
+        restart_result: Result<(), Error>,
+    ) -> (Result<(), &'static str>, usize, bool) {
+        let attempts = Cell::new(0);
+        let restarted = Cell::new(false);
+        let result = retry_if_wedged(
+            || {
+                let output = outputs[attempts.get()];
+                attempts.set(attempts.get() + 1);
+                output
+            },
+            |output| is_wedged(output),
+            || {
+                restarted.set(true);
+                restart_result
+            },
+        );
+        (result, attempts.get(), restarted.get())
+    }
+
+    #[test]
+    fn retries_once_after_restarting() {
+        assert_eq!(
+            retry(&[Err("error: protocol fault (no status)"), Ok(())], Ok(())),
+            (Ok(()), 2, true)
+        );
+        // Still wedged after restarting means something else is wrong.
+        assert_eq!(
+            retry(
+                &[Err("error: protocol fault"), Err("error: protocol fault")],
+                Ok(())
+            ),
+            (Err("error: protocol fault"), 2, true)
+        );
+    }
+
+    #[test]
+    fn doesnt_retry_other_failures() {
+        assert_eq!(retry(&[Ok(())], Ok(())), (Ok(()), 1, false));
+        assert_eq!(
+            retry(&[Err("error: device unauthorized.")], Ok(())),
+            (Err("error: device unauthorized."), 1, false)
+        );
+    }
+
+    #[test]
+    fn timeouts_count_as_wedged() {
+        assert!(Error::TimedOut {
+            timeout: RESPONSE_TIMEOUT
+        }
+        .is_wedged());
+        let attempts = Cell::new(0);
+        let result = retry_if_wedged(
+            || {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() == 1 {
+                    Err(Error::TimedOut {
+                        timeout: RESPONSE_TIMEOUT,
+                    })
+                } else {
+                    Ok(())
+                }
+            },
+            Error::is_wedged,
+            || Ok(()),
+        );
+        assert!(result.is_ok());
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[test]
+    fn failed_restarts_surface_the_original_error() {
+        assert_eq!(
+            retry(
+                &[Err("ADB server didn't ACK")],
+                Err(Error::TimedOut {
+                    timeout: RESPONSE_TIMEOUT
+                })
+            ),
+            (Err("ADB server didn't ACK"), 1, true)
+        );
+    }
+}
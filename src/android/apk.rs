@@ -0,0 +1,126 @@
+use super::{
+    config::{Config, Signing},
+    env::{BuildToolsError, Env},
+};
+use crate::{
+    env::ExplicitEnv as _,
+    util::cli::{Report, Reportable},
+};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum SignError {
+    SigningConfigMissing,
+    PasswordEnvNotSet { var: String },
+    ApksignerNotFound(BuildToolsError),
+    SignFailed(bossy::Error),
+    VerifyFailed(bossy::Error),
+}
+
+impl Reportable for SignError {
+    fn report(&self) -> Report {
+        match self {
+            Self::SigningConfigMissing => Report::action_request(
+                "No keystore was specified",
+                format!(
+                    "Pass `--keystore`, `--key-alias`, and `--ks-pass-env`, or add an `{}.signing` section to your {}.",
+                    super::NAME,
+                    crate::config::file_name(),
+                ),
+            ),
+            Self::PasswordEnvNotSet { var } => Report::action_request(
+                "Keystore password isn't available",
+                format!(
+                    "The `{}` environment variable isn't set. Passwords are only ever read from the environment, so set it and try again.",
+                    var
+                ),
+            ),
+            Self::ApksignerNotFound(err) => err.report(),
+            Self::SignFailed(err) => Report::error("Failed to sign artifact", err),
+            Self::VerifyFailed(err) => Report::error("Failed to verify signed artifact", err),
+        }
+    }
+}
+
+/// Overrides for the `android.signing` config; anything left unset falls
+/// back to the config.
+#[derive(Debug, Default)]
+pub struct SigningOverrides {
+    pub keystore: Option<PathBuf>,
+    pub key_alias: Option<String>,
+    pub keystore_password_env: Option<String>,
+}
+
+impl SigningOverrides {
+    fn resolve(self, config: Option<&Signing>) -> Option<Signing> {
+        let keystore = self
+            .keystore
+            .or_else(|| config.map(|config| config.keystore.clone()))?;
+        let key_alias = self
+            .key_alias
+            .or_else(|| config.map(|config| config.key_alias.clone()))?;
+        let keystore_password_env = self
+            .keystore_password_env
+            .or_else(|| config.map(|config| config.keystore_password_env.clone()))?;
+        Some(Signing {
+            keystore,
+            key_alias,
+            keystore_password_env,
+        })
+    }
+}
+
+pub fn sign(
+    config: &Config,
+    env: &Env,
+    artifact: &Path,
+    out: Option<&Path>,
+    overrides: SigningOverrides,
+) -> Result<(), SignError> {
+    let signing = overrides
+        .resolve(config.signing())
+        .ok_or(SignError::SigningConfigMissing)?;
+    if std::env::var_os(&signing.keystore_password_env).is_none() {
+        return Err(SignError::PasswordEnvNotSet {
+            var: signing.keystore_password_env,
+        });
+    }
+    let apksigner = env
+        .build_tool_path("apksigner")
+        .map_err(SignError::ApksignerNotFound)?;
+    let mut command = bossy::Command::impure(&apksigner)
+        .with_env_vars(env.explicit_env())
+        .with_arg("sign")
+        .with_arg("--ks")
+        .with_arg(&signing.keystore)
+        // `env:` makes apksigner read the password itself, so it never shows
+        // up in our args or logs.
+        .with_args(&[
+            "--ks-pass",
+            &format!("env:{}", signing.keystore_password_env),
+        ])
+        .with_args(&["--ks-key-alias", &signing.key_alias]);
+    if artifact.extension().and_then(|ext| ext.to_str()) == Some("aab") {
+        // There's no manifest for apksigner to read this from.
+        command.add_args(&["--min-sdk-version", &config.min_sdk_version().to_string()]);
+    }
+    if let Some(out) = out {
+        command.add_arg("--out").add_arg(out);
+    }
+    command
+        .with_arg(artifact)
+        .run_and_wait()
+        .map_err(SignError::SignFailed)?;
+    let signed = out.unwrap_or(artifact);
+    let certs = bossy::Command::impure(&apksigner)
+        .with_env_vars(env.explicit_env())
+        .with_args(&["verify", "--print-certs"])
+        .with_arg(signed)
+        .run_and_wait_for_string()
+        .map_err(SignError::VerifyFailed)?;
+    println!("Signed {:?}", signed);
+    for line in certs.lines().filter(|line| line.contains("digest")) {
+        println!("  {}", line.trim());
+    }
+    Ok(())
+}
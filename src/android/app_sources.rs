@@ -0,0 +1,315 @@
+use super::config::AppSource;
+use crate::templating;
+use once_cell_regex::regex_multi_line;
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+// Lists everything we copied last time, relative to the `app` dir. The
+// template's manifest says what it wrote, and we leave those files alone
+// unless we were the ones who wrote them.
+static MANIFEST_NAME: &str = ".app-sources";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    TemplateManifestLoadFailed(templating::ProcessError),
+    #[error("App source {0:?} doesn't exist")]
+    SourceMissing(PathBuf),
+    #[error("Failed to read app source {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("App source {0:?} has no file name")]
+    NoFileName(PathBuf),
+    #[error("Can't tell where app source dir {0:?} belongs; name it `res` or `assets`, or use the `{{ path = \"...\", dest = \"...\" }}` form")]
+    DestUnknown(PathBuf),
+    #[error("Failed to walk app source dir {path:?}: {source}")]
+    WalkFailed {
+        path: PathBuf,
+        source: walkdir::Error,
+    },
+    #[error("Failed to copy app source {src:?} to {dest:?}: {source}")]
+    CopyFailed {
+        src: PathBuf,
+        dest: PathBuf,
+        source: io::Error,
+    },
+    #[error("Failed to write app source manifest {path:?}: {source}")]
+    ManifestWriteFailed { path: PathBuf, source: io::Error },
+}
+
+fn package_of(contents: &str) -> Option<&str> {
+    regex_multi_line!(r"^\s*package\s+([A-Za-z_][\w.]*)\s*;?\s*$")
+        .captures(contents)
+        .and_then(|caps| caps.get(1))
+        .map(|package| package.as_str())
+}
+
+fn is_code(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("kt") | Some("java")
+    )
+}
+
+// Where a dir's contents go (relative to the `app` dir) if it doesn't say.
+fn inferred_dir_dest(path: &Path) -> Option<&'static str> {
+    match path.file_name()?.to_str()? {
+        "res" => Some("src/main/res"),
+        "assets" => Some("src/main/assets"),
+        _ => None,
+    }
+}
+
+pub struct Installer {
+    app_dir: PathBuf,
+    generated: templating::Manifest,
+    previous: BTreeSet<PathBuf>,
+    copied: BTreeSet<PathBuf>,
+}
+
+impl Installer {
+    /// For the Android Studio project in `project_dir`, which is where the
+    /// template's manifest is.
+    pub fn new(project_dir: &Path) -> Result<Self, Error> {
+        let generated =
+            templating::Manifest::load(project_dir).map_err(Error::TemplateManifestLoadFailed)?;
+        let app_dir = project_dir.join("app");
+        let previous = fs::read_to_string(app_dir.join(MANIFEST_NAME))
+            .map(|manifest| manifest.lines().map(PathBuf::from).collect())
+            .unwrap_or_default();
+        Ok(Self {
+            app_dir,
+            generated,
+            previous,
+            copied: Default::default(),
+        })
+    }
+
+    // Files left over from an earlier gen, like ones we copied for a source
+    // that's since been removed, aren't the template's, so they're fair game.
+    fn is_template_file(&self, rel_dest: &Path) -> bool {
+        self.generated
+            .recorded(&Path::new("app").join(rel_dest))
+            .is_some()
+            && !self.previous.contains(rel_dest)
+    }
+
+    fn copy_file(&mut self, src: &Path, rel_dest: PathBuf) -> Result<(), Error> {
+        let dest = self.app_dir.join(&rel_dest);
+        if self.is_template_file(&rel_dest) {
+            log::warn!(
+                "not copying app source {:?} to {:?}, since the template generated that file",
+                src,
+                dest
+            );
+            return Ok(());
+        }
+        let map_err = |source| Error::CopyFailed {
+            src: src.to_owned(),
+            dest: dest.clone(),
+            source,
+        };
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(map_err)?;
+        }
+        fs::copy(src, &dest).map_err(map_err)?;
+        self.copied.insert(rel_dest);
+        Ok(())
+    }
+
+    fn copy_dir(&mut self, src: &Path, rel_dest: &Path) -> Result<(), Error> {
+        for entry in walkdir::WalkDir::new(src).follow_links(true) {
+            let entry = entry.map_err(|source| Error::WalkFailed {
+                path: src.to_owned(),
+                source,
+            })?;
+            if entry.file_type().is_file() {
+                let rel = entry
+                    .path()
+                    .strip_prefix(src)
+                    .expect("developer error: walked path wasn't prefixed by its root");
+                self.copy_file(entry.path(), rel_dest.join(rel))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `source` (relative to `root_dir`) to wherever it belongs in the
+    /// `app` dir:
+    /// - Kotlin and Java files go in the dir for their `package`
+    /// - Dirs are merged into `src/main/res` or `src/main/assets`, based on
+    ///   their name
+    /// - Anything else is copied into the top of the `app` dir
+    ///
+    /// An explicit `dest` overrides all of that.
+    pub fn install(&mut self, root_dir: &Path, source: &AppSource) -> Result<(), Error> {
+        let src = root_dir.join(source.path());
+        if src.is_dir() {
+            let rel_dest = source
+                .dest()
+                .or_else(|| inferred_dir_dest(&src))
+                .ok_or_else(|| Error::DestUnknown(src.clone()))?;
+            return self.copy_dir(&src, Path::new(rel_dest));
+        }
+        if !src.is_file() {
+            return Err(Error::SourceMissing(src));
+        }
+        let file_name = src
+            .file_name()
+            .ok_or_else(|| Error::NoFileName(src.clone()))?;
+        let rel_dir = if let Some(dest) = source.dest() {
+            PathBuf::from(dest)
+        } else if is_code(&src) {
+            let contents = fs::read_to_string(&src).map_err(|source| Error::ReadFailed {
+                path: src.clone(),
+                source,
+            })?;
+            let mut dir = PathBuf::from("src/main/java");
+            if let Some(package) = package_of(&contents) {
+                dir.extend(package.split('.'));
+            } else {
+                log::warn!("app source {:?} has no `package` line", src);
+            }
+            dir
+        } else {
+            PathBuf::new()
+        };
+        self.copy_file(&src, rel_dir.join(file_name))
+    }
+
+    /// Records what was copied, so it can be overwritten next time.
+    pub fn finish(self) -> Result<(), Error> {
+        let path = self.app_dir.join(MANIFEST_NAME);
+        let manifest = self
+            .copied
+            .iter()
+            .map(|rel| format!("{}\n", rel.display()))
+            .collect::<String>();
+        fs::write(&path, manifest).map_err(|source| Error::ManifestWriteFailed { path, source })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    #[test]
+    fn parses_package() {
+        assert_eq!(
+            package_of("// hi\npackage com.example.game;\n\nclass Foo {}\n"),
+            Some("com.example.game")
+        );
+        assert_eq!(
+            package_of("package com.example.game\n\nclass Foo\n"),
+            Some("com.example.game")
+        );
+        assert_eq!(package_of("class Foo {}\n"), None);
+    }
+
+    #[test]
+    fn places_each_kind() {
+        let root = TempDirGuard::new().unwrap();
+        let app = root.join("app");
+        fs::create_dir_all(&app).unwrap();
+        fs::write(
+            root.join("Foo.java"),
+            "package com.example.game;\nclass Foo {}\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("Bar.kt"),
+            "package com.example.game.ui\nclass Bar\n",
+        )
+        .unwrap();
+        fs::write(root.join("google-services.json"), "{}").unwrap();
+        fs::create_dir_all(root.join("res/layout")).unwrap();
+        fs::write(root.join("res/layout/main.xml"), "<x/>").unwrap();
+        fs::create_dir_all(root.join("extra")).unwrap();
+        fs::write(root.join("extra/values.xml"), "<y/>").unwrap();
+
+        let mut installer = Installer::new(&root).unwrap();
+        for source in &[
+            AppSource::Path("Foo.java".to_owned()),
+            AppSource::Path("Bar.kt".to_owned()),
+            AppSource::Path("google-services.json".to_owned()),
+            AppSource::Path("res".to_owned()),
+            AppSource::Explicit {
+                path: "extra".to_owned(),
+                dest: "src/main/res/values".to_owned(),
+            },
+        ] {
+            installer.install(&root, source).unwrap();
+        }
+        installer.finish().unwrap();
+
+        for rel in &[
+            "src/main/java/com/example/game/Foo.java",
+            "src/main/java/com/example/game/ui/Bar.kt",
+            "google-services.json",
+            "src/main/res/layout/main.xml",
+            "src/main/res/values/values.xml",
+        ] {
+            assert!(app.join(rel).is_file(), "{} wasn't copied", rel);
+        }
+        assert!(matches!(
+            Installer::new(&root)
+                .unwrap()
+                .install(&root, &AppSource::Path("extra".to_owned())),
+            Err(Error::DestUnknown(_))
+        ));
+    }
+
+    #[test]
+    fn skips_template_files_but_updates_own_copies() {
+        let root = TempDirGuard::new().unwrap();
+        let app = root.join("app");
+        fs::create_dir_all(&app).unwrap();
+        fs::write(app.join("build.gradle.kts"), "generated").unwrap();
+        let mut generated = templating::Manifest::default();
+        generated.record_contents(Path::new("app/build.gradle.kts"), b"generated");
+        generated.save(&root).unwrap();
+        // Copied by an earlier gen for a source that's no longer listed.
+        fs::write(app.join("google-services.json"), "stale").unwrap();
+        fs::write(root.join("build.gradle.kts"), "mine").unwrap();
+        fs::write(root.join("proguard-rules.pro"), "v1").unwrap();
+        fs::write(root.join("google-services.json"), "{}").unwrap();
+
+        let sources = [
+            AppSource::Path("build.gradle.kts".to_owned()),
+            AppSource::Path("proguard-rules.pro".to_owned()),
+            AppSource::Path("google-services.json".to_owned()),
+        ];
+        let mut installer = Installer::new(&root).unwrap();
+        for source in &sources {
+            installer.install(&root, source).unwrap();
+        }
+        installer.finish().unwrap();
+        assert_eq!(
+            fs::read_to_string(app.join("build.gradle.kts")).unwrap(),
+            "generated"
+        );
+        assert_eq!(
+            fs::read_to_string(app.join("google-services.json")).unwrap(),
+            "{}"
+        );
+
+        fs::write(root.join("proguard-rules.pro"), "v2").unwrap();
+        let mut installer = Installer::new(&root).unwrap();
+        for source in &sources {
+            installer.install(&root, source).unwrap();
+        }
+        installer.finish().unwrap();
+        assert_eq!(
+            fs::read_to_string(app.join("proguard-rules.pro")).unwrap(),
+            "v2"
+        );
+        assert_eq!(
+            fs::read_to_string(app.join("build.gradle.kts")).unwrap(),
+            "generated"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Everything that happens to an APK or AAB after gradle builds it, so
+//! there's one place that knows what goes alongside a final artifact.
+
+use super::{
+    config::Config,
+    mapping::{self, Outcome, RetainError},
+};
+use crate::opts::Profile;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Apk,
+    Aab,
+}
+
+pub fn path(config: &Config, profile: Profile, flavor: &str, kind: Kind) -> PathBuf {
+    match kind {
+        Kind::Apk => config.apk_path(profile, flavor),
+        Kind::Aab => config.aab_path(profile, flavor),
+    }
+}
+
+/// Keeps the R8 mapping next to the artifact.
+pub fn finish(
+    config: &Config,
+    profile: Profile,
+    flavor: &str,
+    kind: Kind,
+) -> Result<Outcome, RetainError> {
+    let artifact = path(config, profile, flavor, kind);
+    let dest_dir = artifact
+        .parent()
+        .expect("developer error: artifact path had no parent");
+    let outcome = mapping::retain(config, profile, flavor, dest_dir)?;
+    match &outcome {
+        Outcome::NotMinified => (),
+        Outcome::Retained(path) => log::info!("retained R8 mapping at {:?}", path),
+        Outcome::Missing(path) => log::warn!(
+            "`{}.minify` is on, but gradle didn't write a mapping to {:?}; Play Console won't be able to deobfuscate stack traces from {:?}, so don't ship it",
+            super::NAME,
+            path,
+            artifact
+        ),
+    }
+    Ok(outcome)
+}
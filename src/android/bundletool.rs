@@ -1,5 +1,5 @@
 #[cfg(not(target_os = "macos"))]
-use crate::util;
+use crate::util::tools::{self, Resolution, Tools};
 use crate::{
     opts,
     util::cli::{Report, Reportable},
@@ -21,10 +21,14 @@ impl BundletoolJarInfo {
         format!("bundletool-all-{}.jar", self.version)
     }
 
-    fn installation_path(&self) -> PathBuf {
-        util::tools_dir()
-            .map(|tools_dir| tools_dir.join(self.file_name()))
-            .unwrap()
+    /// Where the jar is for `tools`' scope, or where it'd be installed.
+    pub fn resolve_with(&self, tools: &Tools) -> Resolution {
+        tools.resolve(&self.file_name()).unwrap()
+    }
+
+    /// `resolve_with` for the loaded config's `[tools] scope`.
+    pub fn resolve(&self) -> Resolution {
+        self.resolve_with(tools::get())
     }
 
     fn download_url(&self) -> String {
@@ -36,8 +40,7 @@ impl BundletoolJarInfo {
     }
 
     fn run_command(&self) -> bossy::Command {
-        let installation_path = self.installation_path();
-        bossy::Command::impure_parse("java -jar").with_arg(installation_path)
+        bossy::Command::impure_parse("java -jar").with_arg(self.resolve().path())
     }
 }
 
@@ -97,12 +100,31 @@ impl Reportable for InstallError {
 pub fn install(reinstall_deps: opts::ReinstallDeps) -> Result<(), InstallError> {
     #[cfg(not(target_os = "macos"))]
     {
-        let jar_path = BUNDLE_TOOL_JAR_INFO.installation_path();
-        if !jar_path.exists() || reinstall_deps.yes() {
+        let resolution = BUNDLE_TOOL_JAR_INFO.resolve();
+        if let Resolution::Found {
+            path,
+            fallback: true,
+            ..
+        } = &resolution
+        {
+            if reinstall_deps.no() {
+                log::warn!(
+                    "`[tools] scope` is \"project\", but bundletool is only in {:?}; using that for now (pass `--reinstall-deps` to download it into the project)",
+                    path
+                );
+            }
+        }
+        if matches!(resolution, Resolution::Missing { .. }) || reinstall_deps.yes() {
+            let jar_path = tools::get()
+                .install_path(&BUNDLE_TOOL_JAR_INFO.file_name())
+                .unwrap();
             let response = ureq::get(&BUNDLE_TOOL_JAR_INFO.download_url())
                 .call()
                 .map_err(InstallError::DownloadFailed)?;
-            let tools_dir = util::tools_dir().unwrap();
+            let tools_dir = jar_path
+                .parent()
+                .expect("developer error: tool path had no parent")
+                .to_owned();
             std::fs::create_dir_all(&tools_dir).map_err(|cause| {
                 InstallError::JarFileCreationFailed {
                     path: tools_dir,
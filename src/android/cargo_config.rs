@@ -0,0 +1,616 @@
+//! Cargo merges every config file from the current dir on up, and then the
+//! one in `CARGO_HOME`. Older guides said to set NDK linkers in the global
+//! one, so those end up alongside the ones we write into the project's
+//! `.cargo/config.toml`, and it's hard to tell which linker actually ran.
+//! On top of that, `compile_lib` sets the linker and `ar` for the target
+//! through the environment, which wins over all of them.
+
+use crate::util::{self, toolchain};
+use once_cell_regex::regex;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Cargo reads `config` over `config.toml` when a dir has both.
+static FILE_NAMES: &[&str] = &["config", "config.toml"];
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Field {
+    Linker,
+    Ar,
+    Rustflags,
+}
+
+impl Field {
+    pub const ALL: &'static [Self] = &[Self::Linker, Self::Ar, Self::Rustflags];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Linker => "linker",
+            Self::Ar => "ar",
+            Self::Rustflags => "rustflags",
+        }
+    }
+
+    fn from_name(s: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|field| field.as_str() == s)
+    }
+
+    /// Lists are joined across configs instead of one winning outright.
+    fn merges(self) -> bool {
+        matches!(self, Self::Rustflags)
+    }
+
+    pub fn env_var(self, triple: &str) -> String {
+        format!(
+            "CARGO_TARGET_{}_{}",
+            triple.to_ascii_uppercase().replace('-', "_"),
+            self.as_str().to_ascii_uppercase()
+        )
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Origin {
+    File(PathBuf),
+    Env(String),
+    /// Set by us for the cargo we run, with this var.
+    Injected(String),
+}
+
+impl Origin {
+    /// Whether this is what `cargo config get --show-origin` means by `s`.
+    fn is(&self, s: &str) -> bool {
+        match self {
+            Self::File(path) => path == Path::new(s),
+            Self::Env(var) | Self::Injected(var) => s.contains(var.as_str()),
+        }
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{:?}", path),
+            Self::Env(var) => write!(f, "`{}`", var),
+            Self::Injected(var) => write!(f, "`{}` (set by cargo-mobile when building)", var),
+        }
+    }
+}
+
+/// One `target.<triple>.<field>` definition, from wherever it came from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub triple: String,
+    pub field: Field,
+    pub value: String,
+    pub origin: Origin,
+}
+
+fn toml_value(field: Field, value: &toml::Value) -> Option<String> {
+    match (field, value) {
+        (_, toml::Value::String(s)) => Some(s.clone()),
+        (Field::Rustflags, toml::Value::Array(flags)) => Some(
+            flags
+                .iter()
+                .filter_map(toml::Value::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => None,
+    }
+}
+
+/// The entries one config file sets for `triples`.
+pub fn file_entries(
+    path: &Path,
+    contents: &str,
+    triples: &[&str],
+) -> Result<Vec<Entry>, toml::de::Error> {
+    let value = toml::from_str::<toml::Value>(contents)?;
+    let targets = match value.get("target").and_then(toml::Value::as_table) {
+        Some(targets) => targets,
+        None => return Ok(Vec::new()),
+    };
+    Ok(triples
+        .iter()
+        .filter_map(|triple| targets.get(*triple).map(|target| (*triple, target)))
+        .flat_map(|(triple, target)| {
+            Field::ALL.iter().filter_map(move |field| {
+                target
+                    .get(field.as_str())
+                    .and_then(|value| toml_value(*field, value))
+                    .map(|value| Entry {
+                        triple: triple.to_owned(),
+                        field: *field,
+                        value,
+                        origin: Origin::File(path.to_owned()),
+                    })
+            })
+        })
+        .collect())
+}
+
+/// The `CARGO_TARGET_<TRIPLE>_<FIELD>` vars set for `triples`, which win over
+/// every file.
+pub fn env_entries(triples: &[&str], var: impl Fn(&str) -> Option<String>) -> Vec<Entry> {
+    triples
+        .iter()
+        .flat_map(|triple| Field::ALL.iter().map(move |field| (*triple, *field)))
+        .filter_map(|(triple, field)| {
+            let name = field.env_var(triple);
+            var(&name)
+                .filter(|value| !value.is_empty())
+                .map(|value| Entry {
+                    triple: triple.to_owned(),
+                    field,
+                    value,
+                    origin: Origin::Env(name),
+                })
+        })
+        .collect()
+}
+
+/// An entry for what we set `field` to for `triple` when running cargo.
+pub fn injected_entry(triple: &str, field: Field, value: String) -> Entry {
+    Entry {
+        triple: triple.to_owned(),
+        field,
+        value,
+        origin: Origin::Injected(field.env_var(triple)),
+    }
+}
+
+/// Every entry cargo would see in `cwd` for `triples`, from the one that wins
+/// to the one that loses, starting with `injected`. Files that `read` can't
+/// read are taken to not be there, and ones that don't parse are skipped,
+/// since cargo will complain about those on its own.
+pub fn chain(
+    cwd: &Path,
+    cargo_home: Option<&Path>,
+    triples: &[&str],
+    injected: Vec<Entry>,
+    var: impl Fn(&str) -> Option<String>,
+    read: impl Fn(&Path) -> Option<String>,
+) -> Vec<Entry> {
+    let mut entries = injected;
+    entries.extend(env_entries(triples, var));
+    let mut paths = Vec::new();
+    let dirs = cwd
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home.map(Path::to_owned));
+    for dir in dirs {
+        let found = FILE_NAMES
+            .iter()
+            .map(|name| dir.join(name))
+            .filter(|path| !paths.contains(path))
+            .find_map(|path| read(&path).map(|contents| (path, contents)));
+        if let Some((path, contents)) = found {
+            match file_entries(&path, &contents, triples) {
+                Ok(found) => entries.extend(found),
+                Err(err) => log::warn!("failed to parse cargo config at {:?}: {}", path, err),
+            }
+            paths.push(path);
+        }
+    }
+    entries
+}
+
+/// A `target` entry that more than one config sets differently.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Conflict {
+    pub triple: String,
+    pub field: Field,
+    /// From the one that wins to the one that loses.
+    pub definitions: Vec<Entry>,
+    /// `None` if they're all used, like `rustflags` are.
+    pub winner: Option<Origin>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`target.{}.{}` is set by {}",
+            self.triple,
+            self.field.as_str(),
+            self.definitions
+                .iter()
+                .map(|entry| format!("{} (`{}`)", entry.origin, entry.value))
+                .collect::<Vec<_>>()
+                .join(" and ")
+        )?;
+        match &self.winner {
+            Some(winner) => write!(f, "; the one from {} wins", winner),
+            None => write!(
+                f,
+                "; cargo uses all of them, with the ones from {} last",
+                self.definitions[0].origin
+            ),
+        }
+    }
+}
+
+/// The entries in `chain` that something else also sets, leaving out ones
+/// that are merely set to the same thing twice.
+pub fn conflicts(chain: &[Entry]) -> Vec<Conflict> {
+    let mut grouped = BTreeMap::<(&str, Field), Vec<&Entry>>::new();
+    for entry in chain {
+        grouped
+            .entry((entry.triple.as_str(), entry.field))
+            .or_default()
+            .push(entry);
+    }
+    grouped
+        .into_iter()
+        .filter(|((_, field), definitions)| {
+            if field.merges() {
+                definitions.len() > 1
+            } else {
+                definitions
+                    .iter()
+                    .any(|entry| entry.value != definitions[0].value)
+            }
+        })
+        .map(|((triple, field), definitions)| Conflict {
+            triple: triple.to_owned(),
+            field,
+            winner: if field.merges() {
+                None
+            } else {
+                Some(definitions[0].origin.clone())
+            },
+            definitions: definitions.into_iter().cloned().collect(),
+        })
+        .collect()
+}
+
+/// Where `cargo config get --format toml --show-origin target` says each of
+/// the entries that don't merge came from, i.e.
+/// `target.aarch64-linux-android.linker = "..." # /home/me/.cargo/config.toml`.
+pub fn parse_show_origin(output: &str) -> BTreeMap<(String, Field), String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let caps = regex!(r#"^target\."?([^".]+)"?\.(\w+) = ".*" # (.+)$"#).captures(line)?;
+            let field = Field::from_name(&caps[2]).filter(|field| !field.merges())?;
+            Some(((caps[1].to_owned(), field), caps[3].trim().to_owned()))
+        })
+        .collect()
+}
+
+/// Goes with what cargo said won, wherever it did, since that's the final
+/// word on precedence.
+pub fn apply_reported(conflicts: &mut [Conflict], reported: &BTreeMap<(String, Field), String>) {
+    for conflict in conflicts {
+        if let Some(origin) = reported.get(&(conflict.triple.clone(), conflict.field)) {
+            if let Some(entry) = conflict
+                .definitions
+                .iter()
+                .find(|entry| entry.origin.is(origin))
+            {
+                conflict.winner = Some(entry.origin.clone());
+            }
+        }
+    }
+}
+
+// This is still unstable, so it only works on nightly toolchains.
+fn reported() -> Option<BTreeMap<(String, Field), String>> {
+    toolchain::cargo_impure()
+        .with_args(&[
+            "-Zunstable-options",
+            "config",
+            "get",
+            "--format",
+            "toml",
+            "--show-origin",
+            "target",
+        ])
+        .with_stderr_piped()
+        .run_and_wait_for_str(parse_show_origin)
+        .map_err(|err| log::info!("`cargo config get` isn't available: {}", err))
+        .ok()
+}
+
+/// The conflicts between the configs cargo would read from `root_dir` for
+/// `triples`, including the `injected` ones. That's where we run cargo from,
+/// wherever we were run from.
+pub fn check(root_dir: &Path, triples: &[&str], injected: Vec<Entry>) -> Vec<Conflict> {
+    let cargo_home = util::cargo_home()
+        .map_err(|err| log::warn!("failed to find `CARGO_HOME`: {}", err))
+        .ok();
+    let chain = chain(
+        root_dir,
+        cargo_home.as_deref(),
+        triples,
+        injected,
+        |var| std::env::var(var).ok(),
+        |path| fs::read_to_string(path).ok(),
+    );
+    let mut conflicts = conflicts(&chain);
+    if !conflicts.is_empty() {
+        if let Some(reported) = reported() {
+            apply_reported(&mut conflicts, &reported);
+        }
+    }
+    conflicts
+}
+
+/// Runs before builds at pedantic noise level.
+pub fn warn_conflicts(root_dir: &Path, triples: &[&str], injected: Vec<Entry>) {
+    for conflict in check(root_dir, triples, injected) {
+        log::warn!("{}", conflict);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    static TRIPLES: &[&str] = &["aarch64-linux-android", "x86_64-linux-android"];
+
+    static PROJECT: &str = r#"
+[target.aarch64-linux-android]
+linker = "/ndk/25/bin/aarch64-linux-android21-clang"
+ar = "/ndk/25/bin/llvm-ar"
+rustflags = ["-Clink-arg=-landroid"]
+
+[target.x86_64-linux-android]
+linker = "/ndk/25/bin/x86_64-linux-android21-clang"
+"#;
+
+    static GLOBAL: &str = r#"
+[target.aarch64-linux-android]
+linker = "/old-ndk/bin/aarch64-linux-android-clang"
+ar = "/ndk/25/bin/llvm-ar"
+rustflags = "-Clink-arg=-llog"
+
+[target.'cfg(target_os = "android")']
+linker = "nope"
+
+[target.armv7-linux-androideabi]
+linker = "/old-ndk/bin/armv7a-linux-androideabi-clang"
+"#;
+
+    fn tree(files: &[(&str, &str)]) -> impl Fn(&Path) -> Option<String> {
+        let files = files
+            .iter()
+            .map(|(path, contents)| (PathBuf::from(path), (*contents).to_owned()))
+            .collect::<HashMap<_, _>>();
+        move |path| files.get(path).cloned()
+    }
+
+    fn file(path: &str) -> Origin {
+        Origin::File(path.into())
+    }
+
+    fn chain_for(files: &[(&str, &str)], vars: &[(&str, &str)]) -> Vec<Entry> {
+        chain_with(files, vars, Vec::new())
+    }
+
+    fn chain_with(
+        files: &[(&str, &str)],
+        vars: &[(&str, &str)],
+        injected: Vec<Entry>,
+    ) -> Vec<Entry> {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+            .collect::<HashMap<_, _>>();
+        chain(
+            Path::new("/work/app"),
+            Some(Path::new("/home/me/.cargo")),
+            TRIPLES,
+            injected,
+            |var| vars.get(var).cloned(),
+            tree(files),
+        )
+    }
+
+    #[test]
+    fn reads_the_chain_in_precedence_order() {
+        let chain = chain_for(
+            &[
+                ("/work/app/.cargo/config.toml", PROJECT),
+                (
+                    "/work/.cargo/config",
+                    "[target.x86_64-linux-android]\nar = \"ar\"\n",
+                ),
+                // Cargo would read `config` instead.
+                (
+                    "/work/.cargo/config.toml",
+                    "[target.x86_64-linux-android]\nar = \"x\"\n",
+                ),
+                ("/home/me/.cargo/config.toml", GLOBAL),
+            ],
+            &[("CARGO_TARGET_X86_64_LINUX_ANDROID_LINKER", "/env/clang")],
+        );
+        let origins = chain
+            .iter()
+            .map(|entry| (entry.triple.as_str(), entry.field, entry.origin.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            origins,
+            vec![
+                (
+                    "x86_64-linux-android",
+                    Field::Linker,
+                    Origin::Env("CARGO_TARGET_X86_64_LINUX_ANDROID_LINKER".to_owned())
+                ),
+                (
+                    "aarch64-linux-android",
+                    Field::Linker,
+                    file("/work/app/.cargo/config.toml")
+                ),
+                (
+                    "aarch64-linux-android",
+                    Field::Ar,
+                    file("/work/app/.cargo/config.toml")
+                ),
+                (
+                    "aarch64-linux-android",
+                    Field::Rustflags,
+                    file("/work/app/.cargo/config.toml")
+                ),
+                (
+                    "x86_64-linux-android",
+                    Field::Linker,
+                    file("/work/app/.cargo/config.toml")
+                ),
+                (
+                    "x86_64-linux-android",
+                    Field::Ar,
+                    file("/work/.cargo/config")
+                ),
+                (
+                    "aarch64-linux-android",
+                    Field::Linker,
+                    file("/home/me/.cargo/config.toml")
+                ),
+                (
+                    "aarch64-linux-android",
+                    Field::Ar,
+                    file("/home/me/.cargo/config.toml")
+                ),
+                (
+                    "aarch64-linux-android",
+                    Field::Rustflags,
+                    file("/home/me/.cargo/config.toml")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn cargo_home_under_cwd_is_only_read_once() {
+        let chain = chain(
+            Path::new("/home/me/app"),
+            Some(Path::new("/home/me/.cargo")),
+            TRIPLES,
+            Vec::new(),
+            |_| None,
+            tree(&[("/home/me/.cargo/config.toml", GLOBAL)]),
+        );
+        assert_eq!(chain.len(), 3);
+    }
+
+    #[test]
+    fn skips_configs_that_dont_parse() {
+        let chain = chain_for(
+            &[
+                ("/work/app/.cargo/config.toml", "[target"),
+                ("/home/me/.cargo/config.toml", GLOBAL),
+            ],
+            &[],
+        );
+        assert!(chain
+            .iter()
+            .all(|entry| entry.origin == file("/home/me/.cargo/config.toml")));
+    }
+
+    #[test]
+    fn finds_conflicts() {
+        let conflicts = conflicts(&chain_for(
+            &[
+                ("/work/app/.cargo/config.toml", PROJECT),
+                ("/home/me/.cargo/config.toml", GLOBAL),
+            ],
+            &[],
+        ));
+        // `ar` is set twice, but to the same thing.
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(|conflict| (conflict.field, conflict.winner.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Field::Linker, Some(file("/work/app/.cargo/config.toml"))),
+                (Field::Rustflags, None),
+            ]
+        );
+        assert_eq!(
+            conflicts[0].to_string(),
+            "`target.aarch64-linux-android.linker` is set by \"/work/app/.cargo/config.toml\" (`/ndk/25/bin/aarch64-linux-android21-clang`) and \"/home/me/.cargo/config.toml\" (`/old-ndk/bin/aarch64-linux-android-clang`); the one from \"/work/app/.cargo/config.toml\" wins"
+        );
+        assert_eq!(
+            conflicts[1].to_string(),
+            "`target.aarch64-linux-android.rustflags` is set by \"/work/app/.cargo/config.toml\" (`-Clink-arg=-landroid`) and \"/home/me/.cargo/config.toml\" (`-Clink-arg=-llog`); cargo uses all of them, with the ones from \"/work/app/.cargo/config.toml\" last"
+        );
+    }
+
+    #[test]
+    fn no_conflicts_without_a_global_config() {
+        assert!(conflicts(&chain_for(
+            &[("/work/app/.cargo/config.toml", PROJECT)],
+            &[]
+        ))
+        .is_empty());
+    }
+
+    #[test]
+    fn env_vars_win() {
+        let conflicts = conflicts(&chain_for(
+            &[("/work/app/.cargo/config.toml", PROJECT)],
+            &[("CARGO_TARGET_X86_64_LINUX_ANDROID_LINKER", "/env/clang")],
+        ));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].winner,
+            Some(Origin::Env(
+                "CARGO_TARGET_X86_64_LINUX_ANDROID_LINKER".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn injected_vars_win_over_everything() {
+        let conflicts = conflicts(&chain_with(
+            &[("/work/app/.cargo/config.toml", PROJECT)],
+            &[("CARGO_TARGET_X86_64_LINUX_ANDROID_LINKER", "/env/clang")],
+            vec![injected_entry(
+                "x86_64-linux-android",
+                Field::Linker,
+                "/ndk/26/bin/x86_64-linux-android21-clang".to_owned(),
+            )],
+        ));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].winner,
+            Some(Origin::Injected(
+                "CARGO_TARGET_X86_64_LINUX_ANDROID_LINKER".to_owned()
+            ))
+        );
+        assert_eq!(conflicts[0].definitions.len(), 3);
+    }
+
+    #[test]
+    fn goes_with_what_cargo_reports() {
+        let output = r#"target.aarch64-linux-android.linker = "/old-ndk/bin/aarch64-linux-android-clang" # /home/me/.cargo/config.toml
+target.aarch64-linux-android.rustflags = [
+    "-Clink-arg=-llog", # /home/me/.cargo/config.toml
+]
+target.x86_64-linux-android.linker = "/env/clang" # environment variable `CARGO_TARGET_X86_64_LINUX_ANDROID_LINKER`
+"#;
+        let reported = parse_show_origin(output);
+        assert_eq!(reported.len(), 2);
+        let mut conflicts = conflicts(&chain_for(
+            &[
+                ("/work/app/.cargo/config.toml", PROJECT),
+                ("/home/me/.cargo/config.toml", GLOBAL),
+            ],
+            &[],
+        ));
+        apply_reported(&mut conflicts, &reported);
+        assert_eq!(
+            conflicts[0].winner,
+            Some(file("/home/me/.cargo/config.toml"))
+        );
+        assert_eq!(conflicts[1].winner, None);
+    }
+}
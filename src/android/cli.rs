@@ -1,28 +1,45 @@
 use crate::{
     android::{
-        adb,
+        aab, adb,
+        apk::{self, SignError, SigningOverrides},
+        artifacts::{self, Kind},
+        bundletool, cargo_config,
         config::{Config, Metadata},
-        device::{Device, RunError, StacktraceError},
+        debug::DebugError,
+        device::{Device, RestartPolicy, RunError, StacktraceError},
         env::{Env, Error as EnvError},
+        gradle_daemons,
+        inspect::{self, InspectError},
+        keystore,
+        mapping::{self, Outcome},
+        project, reproducible, rust_version_check,
+        snapshot::{self, SnapshotError},
+        state,
         target::{BuildError, CompileLibError, Target},
         NAME,
     },
+    assets,
     config::{
+        app::AssetPlatform,
         metadata::{self, Metadata as OmniMetadata},
         Config as OmniConfig, LoadOrGenError,
     },
     define_device_prompt,
     device::PromptError,
-    opts, os,
+    dot_cargo,
+    hooks::{self, Event},
+    icons, opts, os,
     target::{call_for_targets_with_fallback, TargetInvalid, TargetTrait as _},
     util::{
+        self,
         cli::{
             self, Exec, GlobalFlags, Report, Reportable, TextWrapper, VERSION_LONG, VERSION_SHORT,
         },
+        prefixed::{self, Prefix},
         prompt,
     },
 };
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -52,15 +69,20 @@ pub enum Command {
     Open,
     #[structopt(name = "check", about = "Checks if code compiles for target(s)")]
     Check {
-        #[structopt(name = "targets", default_value = Target::DEFAULT_KEY, possible_values = Target::name_list())]
+        #[structopt(name = "targets", possible_values = Target::name_list_with_opt_in())]
         targets: Vec<String>,
     },
     #[structopt(name = "build", about = "Builds dynamic libraries for target(s)")]
     Build {
-        #[structopt(name = "targets", default_value = Target::DEFAULT_KEY, possible_values = Target::name_list())]
+        #[structopt(name = "targets", possible_values = Target::name_list_with_opt_in())]
         targets: Vec<String>,
         #[structopt(flatten)]
         profile: cli::Profile,
+        #[structopt(
+            long = "check-reproducible",
+            help = "Also assemble each target's APK twice in separate build dirs, and fail if they differ"
+        )]
+        check_reproducible: bool,
     },
     #[structopt(name = "run", about = "Deploys APK to connected device")]
     Run {
@@ -70,11 +92,299 @@ pub enum Command {
         filter: cli::Filter,
         #[structopt(flatten)]
         reinstall_deps: cli::ReinstallDeps,
+        #[structopt(flatten)]
+        launch_args: cli::LaunchArgs,
+        #[structopt(
+            long = "force",
+            help = "Deploy even if the device's API level is below `min-sdk-version`",
+            parse(from_flag = opts::Force::from_bool),
+        )]
+        force: opts::Force,
+        #[structopt(
+            long = "trace",
+            value_name = "seconds",
+            help = "Capture a Perfetto trace for this many seconds after launching, then exit"
+        )]
+        trace: Option<u32>,
+        #[structopt(
+            long = "logcat-format",
+            value_name = "format",
+            help = "Show logs in this logcat format instead of `android.logcat-format`, i.e. `threadtime` for PIDs and TIDs",
+            possible_values = &adb::logcat::Format::names(),
+            case_insensitive = true,
+        )]
+        logcat_format: Option<adb::logcat::Format>,
+        #[structopt(
+            long = "no-launch",
+            help = "Install the app without starting it or showing its logs",
+            conflicts_with = "trace"
+        )]
+        no_launch: bool,
+        #[structopt(
+            long = "restart-policy",
+            value_name = "policy",
+            help = "When to start the app once it's installed; `if-not-running` leaves a running app alone",
+            possible_values = &RestartPolicy::names(),
+            case_insensitive = true,
+            default_value = "always",
+        )]
+        restart_policy: RestartPolicy,
+    },
+    #[structopt(
+        name = "debug",
+        about = "Deploys a debug APK and sets up a native debugging session"
+    )]
+    Debug {
+        #[structopt(flatten)]
+        launch_args: cli::LaunchArgs,
+        #[structopt(
+            long = "force",
+            help = "Deploy even if the device's API level is below `min-sdk-version`",
+            parse(from_flag = opts::Force::from_bool),
+        )]
+        force: opts::Force,
     },
     #[structopt(name = "st", about = "Displays a detailed stacktrace for a device")]
     Stacktrace,
+    #[structopt(
+        name = "snapshot",
+        about = "Captures a screenshot and/or screen recording from a device"
+    )]
+    Snapshot {
+        #[structopt(
+            long = "screenshot",
+            value_name = "path",
+            help = "Save a screenshot, by default to `./screenshot-<timestamp>.png` (this is the default if `--record` isn't given)"
+        )]
+        screenshot: Option<Option<PathBuf>>,
+        #[structopt(
+            long = "record",
+            value_name = "seconds",
+            help = "Record the screen for this many seconds (at most 180, and without audio)"
+        )]
+        record: Option<u32>,
+        #[structopt(
+            long = "record-out",
+            value_name = "path",
+            help = "Save the recording here instead of `./screenrecord-<timestamp>.mp4`"
+        )]
+        record_out: Option<PathBuf>,
+        #[structopt(
+            long = "device",
+            value_name = "serial",
+            help = "Serial number of the device to use, instead of prompting"
+        )]
+        device: Option<String>,
+    },
+    #[structopt(name = "sign", about = "Signs an existing APK or AAB using apksigner")]
+    Sign {
+        #[structopt(name = "artifact", help = "Path to the APK or AAB to sign")]
+        artifact: PathBuf,
+        #[structopt(
+            long = "out",
+            help = "Write the signed artifact here instead of in place"
+        )]
+        out: Option<PathBuf>,
+        #[structopt(
+            long = "keystore",
+            help = "Keystore to sign with (defaults to `android.signing.keystore`)"
+        )]
+        keystore: Option<PathBuf>,
+        #[structopt(
+            long = "ks-pass-env",
+            help = "Environment variable containing the keystore password (defaults to `android.signing.keystore-password-env`)"
+        )]
+        ks_pass_env: Option<String>,
+        #[structopt(
+            long = "key-alias",
+            help = "Alias of the key to sign with (defaults to `android.signing.key-alias`)"
+        )]
+        key_alias: Option<String>,
+    },
+    #[structopt(
+        name = "icons",
+        about = "Regenerates launcher icons without re-running init"
+    )]
+    Icons {
+        #[structopt(
+            long = "check",
+            help = "Don't write anything; just fail if any icons are out of date"
+        )]
+        check: bool,
+    },
+    #[structopt(name = "keystore", about = "Manages signing keystores")]
+    Keystore {
+        #[structopt(subcommand)]
+        command: KeystoreCommand,
+    },
+    #[structopt(
+        name = "aab",
+        about = "Checks Android App Bundles before uploading them"
+    )]
+    Aab {
+        #[structopt(subcommand)]
+        command: AabCommand,
+    },
+    #[structopt(
+        name = "apk",
+        about = "Works with APKs built outside of `cargo android run`"
+    )]
+    Apk {
+        #[structopt(subcommand)]
+        command: ApkCommand,
+    },
+    #[structopt(
+        name = "inspect",
+        about = "Checks built APKs' manifest and native libs against your config"
+    )]
+    Inspect {
+        #[structopt(name = "targets", possible_values = Target::name_list_with_opt_in())]
+        targets: Vec<String>,
+        #[structopt(
+            long = "apk",
+            value_name = "path",
+            help = "Inspect this APK, which should have every given target's ABI, instead of each flavor's APK"
+        )]
+        apk: Option<PathBuf>,
+        #[structopt(flatten)]
+        profile: cli::Profile,
+    },
+    #[structopt(name = "mappings", about = "Manages retained R8 mappings")]
+    Mappings {
+        #[structopt(subcommand)]
+        command: MappingsCommand,
+    },
+    #[structopt(
+        name = "gradle-daemons",
+        about = "Lists the gradle daemons the generated project uses, or stops them"
+    )]
+    GradleDaemons {
+        #[structopt(
+            long = "stop",
+            help = "Stop them, i.e. when a build timed out waiting to lock gradle's caches"
+        )]
+        stop: bool,
+    },
+    #[structopt(
+        name = "update-ndk",
+        about = "Points the cargo config at the current NDK, without touching the rest of the project"
+    )]
+    UpdateNdk,
+    #[structopt(
+        name = "relocalize",
+        about = "Points the cargo config at this machine's NDK, i.e. after cloning the project"
+    )]
+    Relocalize,
     #[structopt(name = "list", about = "Lists connected devices")]
     List,
+    #[structopt(
+        name = "connect",
+        about = "Connects to a device over Wi-Fi, using `adb connect`"
+    )]
+    Connect {
+        #[structopt(
+            name = "addr",
+            help = "Address shown under \"Wireless debugging\" on the device, i.e. `192.168.1.50:5555`"
+        )]
+        addr: String,
+    },
+    #[structopt(
+        name = "pair",
+        about = "Pairs with a device for wireless debugging, using `adb pair`"
+    )]
+    Pair {
+        #[structopt(
+            name = "addr",
+            help = "Address shown in the device's \"Pair device with pairing code\" dialog"
+        )]
+        addr: String,
+        #[structopt(name = "code", help = "Pairing code shown in the same dialog")]
+        code: String,
+    },
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum KeystoreCommand {
+    #[structopt(
+        name = "generate",
+        about = "Generates a keystore with keytool, prompting for anything not given"
+    )]
+    Generate {
+        #[structopt(
+            long = "out",
+            value_name = "path",
+            help = "Write the keystore here (defaults to `<app-name>.keystore` in the app root)"
+        )]
+        out: Option<PathBuf>,
+        #[structopt(long = "alias", help = "Alias of the generated key")]
+        alias: Option<String>,
+        #[structopt(
+            long = "validity",
+            value_name = "days",
+            help = "How long the key is valid for (defaults to 10000 days)"
+        )]
+        validity: Option<u32>,
+        #[structopt(
+            long = "dname",
+            help = "Distinguished name for the certificate, i.e. `CN=Jane Doe,O=Example,C=US`"
+        )]
+        dname: Option<String>,
+        #[structopt(
+            long = "ks-pass-env",
+            help = "Environment variable containing the keystore password (defaults to `<APP_NAME>_KEYSTORE_PASSWORD`)"
+        )]
+        ks_pass_env: Option<String>,
+        #[structopt(
+            long = "debug",
+            help = "Generate a debug keystore, using the same alias and name as Android Studio"
+        )]
+        debug: bool,
+        #[structopt(long = "force", help = "Overwrite the keystore if it already exists")]
+        force: bool,
+    },
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum AabCommand {
+    #[structopt(
+        name = "verify",
+        about = "Validates AABs with bundletool and estimates their download sizes"
+    )]
+    Verify {
+        #[structopt(
+            long = "bundle",
+            value_name = "path",
+            help = "Verify this AAB, which should have every target's ABI, instead of each flavor's AAB"
+        )]
+        bundle: Option<PathBuf>,
+        #[structopt(flatten)]
+        profile: cli::Profile,
+        #[structopt(flatten)]
+        reinstall_deps: cli::ReinstallDeps,
+    },
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum ApkCommand {
+    #[structopt(
+        name = "pull-mapping",
+        about = "Retains the R8 mapping from the last build of each target's APK, i.e. one built in Android Studio"
+    )]
+    PullMapping {
+        #[structopt(name = "targets", possible_values = Target::name_list_with_opt_in())]
+        targets: Vec<String>,
+        #[structopt(flatten)]
+        profile: cli::Profile,
+    },
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum MappingsCommand {
+    #[structopt(
+        name = "list",
+        about = "Lists retained R8 mappings and their version codes"
+    )]
+    List,
 }
 
 #[derive(Debug)]
@@ -82,16 +392,39 @@ pub enum Error {
     EnvInitFailed(EnvError),
     DevicePromptFailed(PromptError<adb::device_list::Error>),
     TargetInvalid(TargetInvalid),
+    TargetNotConfigured { triple: &'static str },
     ConfigFailed(LoadOrGenError),
     MetadataFailed(metadata::Error),
     Unsupported,
     ProjectDirAbsent { project_dir: PathBuf },
+    Adopted { command: &'static str },
     OpenFailed(bossy::Error),
     CheckFailed(CompileLibError),
+    AssetRefreshFailed(assets::Error),
     BuildFailed(BuildError),
     RunFailed(RunError),
+    DebugFailed(DebugError),
     StacktraceFailed(StacktraceError),
+    DeviceNotFound { serial_no: String },
+    SnapshotFailed(SnapshotError),
+    SignFailed(SignError),
+    IconsFailed(project::Error),
+    IconsStale { count: usize },
     ListFailed(adb::device_list::Error),
+    RustVersionCheckFailed(util::RustVersionError),
+    ConnectFailed(adb::connect::Error),
+    KeystoreFailed(keystore::Error),
+    AabVerifyFailed(aab::VerifyError),
+    InspectFailed(InspectError),
+    MappingRetainFailed(mapping::RetainError),
+    MappingMissing { path: PathBuf },
+    HookFailed(hooks::HookFailed),
+    ReproducibleCheckFailed(reproducible::CheckError),
+    DotCargoLoadFailed(dot_cargo::LoadError),
+    DotCargoGenFailed(project::Error),
+    DotCargoWriteFailed(dot_cargo::WriteError),
+    NdkStampFailed(state::Error),
+    GradleDaemonsFailed(gradle_daemons::Error),
 }
 
 impl Reportable for Error {
@@ -100,6 +433,10 @@ impl Reportable for Error {
             Self::EnvInitFailed(err) => err.report(),
             Self::DevicePromptFailed(err) => err.report(),
             Self::TargetInvalid(err) => Report::error("Specified target was invalid", err),
+            Self::TargetNotConfigured { triple } => Report::action_request(
+                format!("Target {:?} isn't in `android.targets`", triple),
+                "Add it to `android.targets` and run `cargo mobile init`, so the Android project gets a matching flavor.",
+            ),
             Self::ConfigFailed(err) => err.report(),
             Self::MetadataFailed(err) => err.report(),
             Self::Unsupported => Report::error("Android is marked as unsupported in your Cargo.toml metadata", "If your project should support Android, modify your Cargo.toml, then run `cargo mobile init` and try again."),
@@ -110,12 +447,54 @@ impl Reportable for Error {
                     project_dir
                 ),
             ),
+            Self::Adopted { command } => Report::action_request(
+                format!("`cargo {} {}` only works with a generated Android Studio project", NAME, command),
+                format!(
+                    "`{}.existing-project-dir` points at your own project, so use Android Studio for this instead; `cargo {} build` and `cargo {} check` still work.",
+                    NAME, NAME, NAME
+                ),
+            ),
             Self::OpenFailed(err) => Report::error("Failed to open project in Android Studio", err),
             Self::CheckFailed(err) => err.report(),
+            Self::AssetRefreshFailed(err) => Report::error("Failed to refresh assets", err),
             Self::BuildFailed(err) => err.report(),
             Self::RunFailed(err) => err.report(),
+            Self::DebugFailed(err) => err.report(),
             Self::StacktraceFailed(err) => err.report(),
+            Self::DeviceNotFound { serial_no } => Report::error(
+                format!("No connected device has serial number {:?}", serial_no),
+                "Run `cargo android list` to see connected devices.",
+            ),
+            Self::SnapshotFailed(err) => err.report(),
+            Self::SignFailed(err) => err.report(),
+            Self::IconsFailed(err) => err.report(),
+            Self::IconsStale { count } => Report::action_request(
+                format!("{} icon(s) are out of date", count),
+                "Run `cargo android icons` to regenerate them.",
+            ),
             Self::ListFailed(err) => err.report(),
+            Self::RustVersionCheckFailed(err) => err.report(),
+            Self::ConnectFailed(err) => err.report(),
+            Self::KeystoreFailed(err) => err.report(),
+            Self::AabVerifyFailed(err) => err.report(),
+            Self::InspectFailed(err) => err.report(),
+            Self::MappingRetainFailed(err) => Report::error("Failed to retain R8 mapping", err),
+            Self::MappingMissing { path } => Report::action_request(
+                format!("No R8 mapping at {:?}", path),
+                format!(
+                    "Build a release APK with `{}.minify` on first; if you just did, check the release build type in the generated `build.gradle.kts` still has `isMinifyEnabled = true`.",
+                    NAME
+                ),
+            ),
+            Self::HookFailed(err) => err.report(),
+            Self::ReproducibleCheckFailed(err) => err.report(),
+            Self::DotCargoLoadFailed(err) => err.report(),
+            Self::DotCargoGenFailed(err) => err.report(),
+            Self::DotCargoWriteFailed(err) => err.report(),
+            Self::NdkStampFailed(err) => {
+                Report::error("Failed to record which NDK the project uses", err)
+            }
+            Self::GradleDaemonsFailed(err) => err.report(),
         }
     }
 }
@@ -124,16 +503,33 @@ impl Exec for Input {
     type Report = Error;
 
     fn global_flags(&self) -> GlobalFlags {
-        self.flags
+        self.flags.clone()
     }
 
     fn exec(self, wrapper: &TextWrapper) -> Result<(), Self::Report> {
         define_device_prompt!(adb::device_list, adb::device_list::Error, Android);
-        fn detect_target_ok<'a>(env: &Env) -> Option<&'a Target<'a>> {
-            device_prompt(env).map(|device| device.target()).ok()
+        fn detect_target_ok<'a>(
+            (env, preferred_triple): (&Env, Option<&str>),
+        ) -> Option<&'a Target<'a>> {
+            device_prompt(env, preferred_triple)
+                .map(|device| device.target())
+                .ok()
         }
 
         fn with_config(
+            non_interactive: opts::NonInteractive,
+            wrapper: &TextWrapper,
+            env: &Env,
+            f: impl FnOnce(&Config, &Metadata) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            with_config_unchecked(non_interactive, wrapper, |config, metadata| {
+                state::warn_if_ndk_changed(config, &env.ndk);
+                project::warn_if_tool_paths_missing(config);
+                f(config, metadata)
+            })
+        }
+
+        fn with_config_unchecked(
             non_interactive: opts::NonInteractive,
             wrapper: &TextWrapper,
             f: impl FnOnce(&Config, &Metadata) -> Result<(), Error>,
@@ -159,6 +555,54 @@ impl Exec for Input {
             }
         }
 
+        // Building for a target that isn't in `android.targets` would leave
+        // libs the Android project has no flavor for.
+        fn ensure_configured(config: &Config, target: &Target) -> Result<(), Error> {
+            if config
+                .targets()
+                .iter()
+                .any(|configured| configured.triple == target.triple)
+            {
+                Ok(())
+            } else {
+                Err(Error::TargetNotConfigured {
+                    triple: target.triple,
+                })
+            }
+        }
+
+        fn ensure_generated(config: &Config, command: &'static str) -> Result<(), Error> {
+            if config.existing_project_dir().is_some() {
+                Err(Error::Adopted { command })
+            } else {
+                Ok(())
+            }
+        }
+
+        fn run_hook(
+            config: &Config,
+            env: &Env,
+            event: Event,
+            context: hooks::Context<'_>,
+        ) -> Result<(), Error> {
+            config
+                .app()
+                .hooks()
+                .run(event, config.app().root_dir(), env, context)
+                .map_err(Error::HookFailed)
+        }
+
+        // Copied asset dirs go stale whenever their source changes, and
+        // gradle packages whatever's there.
+        fn refresh_assets(config: &Config) -> Result<(), Error> {
+            assets::refresh(
+                config.app(),
+                AssetPlatform::Android,
+                &config.project_dir().join("app/src/main/assets/"),
+            )
+            .map_err(Error::AssetRefreshFailed)
+        }
+
         fn open_in_android_studio(config: &Config) -> Result<(), Error> {
             os::open_file_with("Android Studio", config.project_dir()).map_err(Error::OpenFailed)
         }
@@ -168,23 +612,53 @@ impl Exec for Input {
                 GlobalFlags {
                     noise_level,
                     non_interactive,
+                    ..
                 },
             command,
         } = self;
         let env = Env::new().map_err(Error::EnvInitFailed)?;
+        let version_check =
+            || rust_version_check(wrapper, &env.ndk).map_err(Error::RustVersionCheckFailed);
+        // Only at pedantic noise level, since it reads every cargo config
+        // from here on up, and maybe runs cargo.
+        let warn_cargo_config_conflicts = |config: &Config| {
+            if noise_level.pedantic() {
+                let targets = config.targets();
+                let triples = targets
+                    .iter()
+                    .map(|target| target.triple)
+                    .collect::<Vec<_>>();
+                let injected = targets
+                    .iter()
+                    .filter_map(|target| {
+                        target
+                            .injected_cargo_config(config, &env)
+                            .map_err(|err| log::warn!("{}", err))
+                            .ok()
+                    })
+                    .flatten()
+                    .collect();
+                cargo_config::warn_conflicts(config.app().root_dir(), &triples, injected);
+            }
+        };
         match command {
-            Command::Open => with_config(non_interactive, wrapper, |config, _| {
+            Command::Open => with_config(non_interactive, wrapper, &env, |config, _| {
+                ensure_generated(config, "open")?;
                 ensure_init(config)?;
                 open_in_android_studio(config)
             }),
             Command::Check { targets } => {
-                with_config(non_interactive, wrapper, |config, metadata| {
+                version_check()?;
+                with_config(non_interactive, wrapper, &env, |config, metadata| {
+                    warn_cargo_config_conflicts(config);
                     let force_color = opts::ForceColor::Yes;
                     call_for_targets_with_fallback(
                         targets.iter(),
                         &detect_target_ok,
-                        &env,
+                        (&env, config.default_target().map(|target| target.triple)),
+                        config.default_target(),
                         |target: &Target| {
+                            ensure_configured(config, target)?;
                             target
                                 .check(config, metadata, &env, noise_level, force_color)
                                 .map_err(Error::CheckFailed)
@@ -196,30 +670,100 @@ impl Exec for Input {
             Command::Build {
                 targets,
                 profile: cli::Profile { profile },
-            } => with_config(non_interactive, wrapper, |config, metadata| {
+                check_reproducible,
+            } => with_config(non_interactive, wrapper, &env, |config, metadata| {
+                if check_reproducible {
+                    // That means building APKs, which is gradle's job.
+                    ensure_generated(config, "build --check-reproducible")?;
+                }
+                version_check()?;
                 ensure_init(config)?;
+                warn_cargo_config_conflicts(config);
+                refresh_assets(config)?;
                 let force_color = opts::ForceColor::Yes;
-                call_for_targets_with_fallback(
+                // A single target's output is passed through untouched, so
+                // it looks exactly like running cargo by hand.
+                let prefixes = if targets.len() > 1 {
+                    targets
+                        .iter()
+                        .filter_map(|name| Target::for_name(name))
+                        .enumerate()
+                        .map(|(index, target)| (target.triple, Prefix::new(target.triple, index)))
+                        .collect::<BTreeMap<_, _>>()
+                } else {
+                    Default::default()
+                };
+                let result = call_for_targets_with_fallback(
                     targets.iter(),
                     &detect_target_ok,
-                    &env,
+                    (&env, config.default_target().map(|target| target.triple)),
+                    config.default_target(),
                     |target: &Target| {
+                        ensure_configured(config, target)?;
+                        // Hooks run per target, around just the Rust build.
+                        let context = hooks::Context::new(profile).with_target(target.triple);
+                        run_hook(config, &env, Event::AndroidPreBuild, context)?;
                         target
-                            .build(config, metadata, &env, noise_level, force_color, profile)
-                            .map_err(Error::BuildFailed)
+                            .build(
+                                config,
+                                metadata,
+                                &env,
+                                noise_level,
+                                force_color,
+                                profile,
+                                prefixes.get(target.triple),
+                            )
+                            .map_err(Error::BuildFailed)?;
+                        run_hook(config, &env, Event::AndroidPostBuild, context)?;
+                        if check_reproducible {
+                            reproducible::check(config, &env, noise_level, profile, target)
+                                .map_err(Error::ReproducibleCheckFailed)?;
+                            println!(
+                                "The {} APK for {} is reproducible.",
+                                profile.as_str(),
+                                target.triple
+                            );
+                        }
+                        Ok(())
                     },
                 )
-                .map_err(Error::TargetInvalid)?
+                .map_err(Error::TargetInvalid)?;
+                if !prefixes.is_empty() {
+                    prefixed::print_summary(&prefixes.values().collect::<Vec<_>>());
+                }
+                result
             }),
             Command::Run {
                 profile: cli::Profile { profile },
                 filter: cli::Filter { filter },
                 reinstall_deps: cli::ReinstallDeps { reinstall_deps },
-            } => with_config(non_interactive, wrapper, |config, metadata| {
+                launch_args: cli::LaunchArgs { launch_args },
+                force,
+                trace,
+                logcat_format,
+                no_launch,
+                restart_policy,
+            } => with_config(non_interactive, wrapper, &env, |config, metadata| {
                 let build_app_bundle = metadata.asset_packs().is_some();
+                ensure_generated(config, "run")?;
+                version_check()?;
                 ensure_init(config)?;
-                device_prompt(&env)
-                    .map_err(Error::DevicePromptFailed)?
+                warn_cargo_config_conflicts(config);
+                refresh_assets(config)?;
+                adb::connect::auto_connect(&env, config.auto_connect());
+                let device =
+                    device_prompt(&env, config.default_target().map(|target| target.triple))
+                        .map_err(Error::DevicePromptFailed)?;
+                ensure_configured(config, device.target())?;
+                // Runs once we know which device, but before building
+                // anything for it.
+                run_hook(
+                    config,
+                    &env,
+                    Event::AndroidPreRun,
+                    hooks::Context::new(profile).with_target(device.target().triple),
+                )?;
+                device
                     .run(
                         config,
                         &env,
@@ -228,21 +772,316 @@ impl Exec for Input {
                         filter,
                         build_app_bundle,
                         reinstall_deps,
+                        force,
+                        &launch_args,
+                        trace,
+                        logcat_format,
+                        no_launch,
+                        restart_policy,
                     )
                     .map_err(Error::RunFailed)
             }),
-            Command::Stacktrace => with_config(non_interactive, wrapper, |config, _| {
+            Command::Debug {
+                launch_args: cli::LaunchArgs { launch_args },
+                force,
+            } => with_config(non_interactive, wrapper, &env, |config, _| {
+                ensure_generated(config, "debug")?;
+                version_check()?;
+                ensure_init(config)?;
+                device_prompt(&env, config.default_target().map(|target| target.triple))
+                    .map_err(Error::DevicePromptFailed)?
+                    .debug(config, &env, noise_level, force, &launch_args)
+                    .map_err(Error::DebugFailed)
+            }),
+            Command::Icons { check } => with_config(non_interactive, wrapper, &env, |config, _| {
+                ensure_generated(config, "icons")?;
                 ensure_init(config)?;
-                device_prompt(&env)
+                let mode = if check {
+                    icons::Mode::Check
+                } else {
+                    icons::Mode::Overwrite
+                };
+                let changes = project::sync_icons(config, mode).map_err(Error::IconsFailed)?;
+                changes.print(mode);
+                if check && !changes.is_empty() {
+                    Err(Error::IconsStale {
+                        count: changes.len(),
+                    })
+                } else {
+                    Ok(())
+                }
+            }),
+            Command::Stacktrace => with_config(non_interactive, wrapper, &env, |config, _| {
+                ensure_init(config)?;
+                device_prompt(&env, config.default_target().map(|target| target.triple))
                     .map_err(Error::DevicePromptFailed)?
                     .stacktrace(config, &env)
                     .map_err(Error::StacktraceFailed)
             }),
-            Command::List => adb::device_list(&env)
-                .map_err(Error::ListFailed)
-                .map(|device_list| {
-                    prompt::list_display_only(device_list.iter(), device_list.len());
-                }),
+            Command::Snapshot {
+                screenshot,
+                record,
+                record_out,
+                device,
+            } => {
+                // Validating first means a typo doesn't cost a device prompt.
+                if let Some(seconds) = record {
+                    snapshot::validate_record_seconds(seconds).map_err(Error::SnapshotFailed)?;
+                }
+                let device = if let Some(serial_no) = device {
+                    adb::device_list(&env)
+                        .map_err(Error::ListFailed)?
+                        .into_iter()
+                        .find(|device| device.serial_no() == serial_no)
+                        .ok_or(Error::DeviceNotFound { serial_no })?
+                } else {
+                    device_prompt(&env, None).map_err(Error::DevicePromptFailed)?
+                };
+                if screenshot.is_some() || record.is_none() {
+                    let path = screenshot
+                        .flatten()
+                        .unwrap_or_else(snapshot::default_screenshot_path);
+                    let size = device
+                        .screenshot(&env, &path)
+                        .map_err(Error::SnapshotFailed)?;
+                    println!("Screenshot saved to {:?} ({} bytes)", path, size);
+                }
+                if let Some(seconds) = record {
+                    let path = record_out.unwrap_or_else(snapshot::default_record_path);
+                    let size = device
+                        .screenrecord(&env, seconds, &path)
+                        .map_err(Error::SnapshotFailed)?;
+                    println!("Recording saved to {:?} ({} bytes)", path, size);
+                }
+                Ok(())
+            }
+            Command::Sign {
+                artifact,
+                out,
+                keystore,
+                ks_pass_env,
+                key_alias,
+            } => with_config(non_interactive, wrapper, &env, |config, _| {
+                apk::sign(
+                    config,
+                    &env,
+                    &artifact,
+                    out.as_deref(),
+                    SigningOverrides {
+                        keystore,
+                        key_alias,
+                        keystore_password_env: ks_pass_env,
+                    },
+                )
+                .map_err(Error::SignFailed)
+            }),
+            Command::Keystore {
+                command:
+                    KeystoreCommand::Generate {
+                        out,
+                        alias,
+                        validity,
+                        dname,
+                        ks_pass_env,
+                        debug,
+                        force,
+                    },
+            } => with_config(non_interactive, wrapper, &env, |config, _| {
+                keystore::generate(
+                    config.app().root_dir(),
+                    config.app().name(),
+                    keystore::Options {
+                        out,
+                        alias,
+                        validity_days: validity,
+                        dname,
+                        password_env: ks_pass_env,
+                        debug,
+                        force,
+                        non_interactive: non_interactive.yes(),
+                    },
+                    // Without anyone to ask, we print the config instead.
+                    if non_interactive.yes() {
+                        Some(false)
+                    } else {
+                        None
+                    },
+                )
+                .map_err(Error::KeystoreFailed)
+            }),
+            Command::Aab {
+                command:
+                    AabCommand::Verify {
+                        bundle,
+                        profile: cli::Profile { profile },
+                        reinstall_deps: cli::ReinstallDeps { reinstall_deps },
+                    },
+            } => with_config(non_interactive, wrapper, &env, |config, _| {
+                bundletool::install(reinstall_deps).map_err(|err| {
+                    Error::AabVerifyFailed(aab::VerifyError::BundletoolInstallFailed(err))
+                })?;
+                let bundles = match bundle {
+                    Some(bundle) => vec![(bundle, config.targets())],
+                    None => aab::flavor_bundles(config, profile),
+                };
+                for (path, expected) in bundles {
+                    println!("Verifying {:?}...", path);
+                    let sizes = aab::verify(&path, &expected).map_err(Error::AabVerifyFailed)?;
+                    aab::print_sizes(&sizes);
+                }
+                Ok(())
+            }),
+            Command::Apk {
+                command:
+                    ApkCommand::PullMapping {
+                        targets,
+                        profile: cli::Profile { profile },
+                    },
+            } => with_config(non_interactive, wrapper, &env, |config, _| {
+                let targets = if targets.is_empty() {
+                    config.targets()
+                } else {
+                    targets
+                        .iter()
+                        .filter_map(|name| Target::for_name(name))
+                        .collect()
+                };
+                for target in targets {
+                    match artifacts::finish(config, profile, target.arch, Kind::Apk)
+                        .map_err(Error::MappingRetainFailed)?
+                    {
+                        Outcome::NotMinified => {
+                            println!(
+                                "{} builds aren't minified, so there's no mapping to retain",
+                                profile.as_str()
+                            );
+                            break;
+                        }
+                        Outcome::Retained(path) => println!("Retained {:?}", path),
+                        Outcome::Missing(path) => return Err(Error::MappingMissing { path }),
+                    }
+                }
+                Ok(())
+            }),
+            Command::Inspect {
+                targets,
+                apk,
+                profile: cli::Profile { profile },
+            } => with_config(non_interactive, wrapper, &env, |config, _| {
+                let targets = if targets.is_empty() {
+                    config.targets()
+                } else {
+                    targets
+                        .iter()
+                        .filter_map(|name| Target::for_name(name))
+                        .collect()
+                };
+                let apks = match apk {
+                    Some(apk) => vec![(apk, targets)],
+                    None => targets
+                        .into_iter()
+                        .map(|target| (config.apk_path(profile, target.arch), vec![target]))
+                        .collect(),
+                };
+                for (path, targets) in apks {
+                    println!("Inspecting {:?}...", path);
+                    inspect::check(
+                        &env,
+                        &path,
+                        &inspect::Expected::new(config, profile, &targets),
+                    )
+                    .map_err(Error::InspectFailed)?;
+                }
+                Ok(())
+            }),
+            Command::Mappings {
+                command: MappingsCommand::List,
+            } => with_config(non_interactive, wrapper, &env, |config, _| {
+                let retained = mapping::list(&config.build_dir().join("outputs"));
+                if retained.is_empty() {
+                    println!("No retained mappings");
+                }
+                for retained in retained {
+                    println!(
+                        "  {:<10}version code {:<8}{}",
+                        retained.flavor,
+                        retained.version_code,
+                        retained.path.display()
+                    );
+                }
+                Ok(())
+            }),
+            Command::GradleDaemons { stop } => {
+                with_config(non_interactive, wrapper, &env, |config, _| {
+                    ensure_init(config)?;
+                    if stop {
+                        match gradle_daemons::stop(config, &env)
+                            .map_err(Error::GradleDaemonsFailed)?
+                        {
+                            Some(0) => println!("No gradle daemons were running"),
+                            Some(1) => println!("Stopped 1 gradle daemon"),
+                            Some(count) => println!("Stopped {} gradle daemons", count),
+                            None => println!("Stopped gradle daemons"),
+                        }
+                    } else {
+                        let daemons = gradle_daemons::status(config, &env)
+                            .map_err(Error::GradleDaemonsFailed)?;
+                        println!("{}", gradle_daemons::Summary::new(&daemons));
+                        for daemon in daemons {
+                            println!("  {:<8}{:<10}{}", daemon.pid, daemon.state, daemon.info);
+                        }
+                    }
+                    Ok(())
+                })
+            }
+            // The cargo config (and the state recording what it was
+            // generated against) is the only machine-specific part of the
+            // project, so these come down to the same thing.
+            Command::UpdateNdk | Command::Relocalize => {
+                with_config_unchecked(non_interactive, wrapper, |config, _| {
+                    ensure_init(config)?;
+                    let mut dot_cargo = dot_cargo::DotCargo::load(config.app())
+                        .map_err(Error::DotCargoLoadFailed)?;
+                    project::update_dot_cargo(config, &env, &mut dot_cargo)
+                        .map_err(Error::DotCargoGenFailed)?;
+                    dot_cargo
+                        .write(config.app())
+                        .map_err(Error::DotCargoWriteFailed)?;
+                    state::stamp_ndk(config, &env.ndk).map_err(Error::NdkStampFailed)?;
+                    println!("Updated cargo config for NDK at {:?}", env.ndk.home());
+                    Ok(())
+                })
+            }
+            Command::List => {
+                // Listing works outside of projects too, so there might not
+                // be anything to auto-connect to.
+                match OmniConfig::load(".") {
+                    Ok(config) => adb::connect::auto_connect(&env, config.android().auto_connect()),
+                    Err(err) => {
+                        log::info!("not auto-connecting, since no config was loaded: {:?}", err)
+                    }
+                }
+                adb::device_list(&env)
+                    .map_err(Error::ListFailed)
+                    .map(|device_list| {
+                        prompt::list_display_only(device_list.iter(), device_list.len());
+                    })
+            }
+            Command::Connect { addr } => {
+                match adb::connect::connect(&env, &addr).map_err(Error::ConnectFailed)? {
+                    adb::connect::Connected::Newly => println!("Connected to {}", addr),
+                    adb::connect::Connected::Already => println!("Already connected to {}", addr),
+                }
+                Ok(())
+            }
+            Command::Pair { addr, code } => {
+                adb::connect::pair(&env, &addr, &code).map_err(Error::ConnectFailed)?;
+                println!(
+                    "Paired with {}! Now connect using the address shown under \"Wireless debugging\", which has a different port: `cargo android connect <addr>`",
+                    addr
+                );
+                Ok(())
+            }
         }
     }
 }
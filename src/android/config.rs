@@ -1,21 +1,59 @@
+use super::{adb::logcat, jnilibs, target::Target};
 use crate::{
-    config::app::App,
-    util::{self, cli::Report},
+    config::app::{self, App},
+    opts::Profile,
+    target::TargetTrait as _,
+    util::{self, cli::Report, prompt, VersionTriple},
 };
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::{self, Display},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
 const DEFAULT_MIN_SDK_VERSION: u32 = 24;
+static DEFAULT_TARGETS: &[&str] = &["aarch64", "armv7", "i686", "x86_64"];
 const DEFAULT_VULKAN_VALIDATION: bool = true;
+const DEFAULT_VERSION_CODE: u32 = 1;
+static DEFAULT_VERSION_NAME: &str = "1.0";
+const DEFAULT_RECONNECT_WINDOW_SECS: u64 = 30;
+const DEFAULT_LIB_SIZE_WARNING_MB: u64 = 500;
+/// What `NativeActivity` looks up, per our manifest template.
+static NATIVE_ACTIVITY_ENTRY_POINT: &str = "ANativeActivity_onCreate";
+/// What a project of the user's own is more likely to have, since it loads
+/// the lib from its own activity.
+static JNI_ENTRY_POINT: &str = "JNI_OnLoad";
 static DEFAULT_PROJECT_DIR: &str = "gen/android";
 
 const fn default_true() -> bool {
     true
 }
 
+/// An entry in `app-sources`, which is either just a path or a table with an
+/// explicit destination (relative to the `app` dir).
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AppSource {
+    Path(String),
+    Explicit { path: String, dest: String },
+}
+
+impl AppSource {
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Path(path) | Self::Explicit { path, .. } => path,
+        }
+    }
+
+    pub fn dest(&self) -> Option<&str> {
+        match self {
+            Self::Path(_) => None,
+            Self::Explicit { dest, .. } => Some(dest),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AssetPackInfo {
     pub name: String,
@@ -28,7 +66,7 @@ pub struct Metadata {
     #[serde(default = "default_true")]
     supported: bool,
     features: Option<Vec<String>>,
-    app_sources: Option<Vec<String>>,
+    app_sources: Option<Vec<AppSource>>,
     app_plugins: Option<Vec<String>>,
     project_dependencies: Option<Vec<String>>,
     app_dependencies: Option<Vec<String>>,
@@ -64,7 +102,7 @@ impl Metadata {
         self.features.as_deref()
     }
 
-    pub fn app_sources(&self) -> &[String] {
+    pub fn app_sources(&self) -> &[AppSource] {
         self.app_sources.as_deref().unwrap_or_else(|| &[])
     }
 
@@ -130,6 +168,18 @@ impl Display for ProjectDirInvalid {
 #[derive(Debug)]
 pub enum Error {
     ProjectDirInvalid(ProjectDirInvalid),
+    TargetsEmpty,
+    TargetInvalid { name: String },
+    DefaultTargetInvalid { name: String },
+    CleartextDomainsEmpty,
+    CleartextWildcard,
+    CleartextDomainInvalid { domain: String },
+    AutoConnectAddrInvalid { addr: String },
+    DebugApplicationIdSuffixInvalid { suffix: String },
+    VersionCodeZero,
+    VersionNameEmpty,
+    ExistingProjectDirConflict,
+    ExtraLogTagInvalid { tag: String },
 }
 
 impl Error {
@@ -139,10 +189,165 @@ impl Error {
                 msg,
                 format!("`{}.project-dir` invalid: {}", super::NAME, err),
             ),
+            Self::TargetsEmpty => Report::error(
+                msg,
+                format!("`{}.targets` can't be empty", super::NAME),
+            ),
+            Self::TargetInvalid { name } => Report::error(
+                msg,
+                format!(
+                    "`{}.targets` contains {:?}, which isn't a known target; the known targets are {:?}",
+                    super::NAME,
+                    name,
+                    Target::name_list_with_opt_in(),
+                ),
+            ),
+            Self::DefaultTargetInvalid { name } => Report::error(
+                msg,
+                format!(
+                    "`{}.default-target` is {:?}, which isn't a known target; the known targets are {:?}",
+                    super::NAME,
+                    name,
+                    Target::name_list_with_opt_in(),
+                ),
+            ),
+            Self::CleartextDomainsEmpty => Report::error(
+                msg,
+                format!("`{}.allow-cleartext` can't be an empty list; remove it to disallow cleartext traffic", super::NAME),
+            ),
+            Self::CleartextWildcard => Report::error(
+                msg,
+                format!("`{}.allow-cleartext` can't allow every domain, since release builds would get it too; set it to `true` to allow cleartext traffic in debug builds only", super::NAME),
+            ),
+            Self::CleartextDomainInvalid { domain } => Report::error(
+                msg,
+                format!("`{}.allow-cleartext` contains {:?}, which isn't a bare domain or IP address (leave out any scheme, port, or path)", super::NAME, domain),
+            ),
+            Self::AutoConnectAddrInvalid { addr } => Report::error(
+                msg,
+                format!("`{}.auto-connect` contains {:?}, which isn't a `host:port` address", super::NAME, addr),
+            ),
+            Self::DebugApplicationIdSuffixInvalid { suffix } => Report::error(
+                msg,
+                format!("`{}.debug-application-id-suffix` is {:?}, but it needs to start with `.` and only have letters, numbers, and underscores between dots (i.e. `.debug`)", super::NAME, suffix),
+            ),
+            Self::VersionCodeZero => Report::error(
+                msg,
+                format!("`{}.version-code` can't be 0, since Play only accepts positive version codes", super::NAME),
+            ),
+            Self::VersionNameEmpty => Report::error(
+                msg,
+                format!("`{}.version-name` can't be empty", super::NAME),
+            ),
+            Self::ExistingProjectDirConflict => Report::error(
+                msg,
+                format!("`{0}.project-dir` and `{0}.existing-project-dir` can't both be set, since we either generate a project or adopt yours; remove one of them", super::NAME),
+            ),
+            Self::ExtraLogTagInvalid { tag } => Report::error(
+                msg,
+                format!("`{}.extra-log-tags` contains {:?}, but logcat tags can't be empty or have colons or whitespace in them", super::NAME, tag),
+            ),
         }
     }
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RawAllowCleartext {
+    Everywhere(bool),
+    Domains(Vec<String>),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RawEntryPoint {
+    Check(bool),
+    Symbol(String),
+}
+
+/// The symbol the lib has to export for the app to start.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntryPoint {
+    pub symbol: String,
+    /// Whether a lib without it fails the build, rather than just warning.
+    /// That's only not the case when we're guessing what a project of the
+    /// user's own needs.
+    pub required: bool,
+}
+
+impl EntryPoint {
+    fn from_raw(raw: Option<RawEntryPoint>, existing_project: bool) -> Option<Self> {
+        match raw {
+            Some(RawEntryPoint::Check(false)) => None,
+            Some(RawEntryPoint::Symbol(symbol)) => Some(Self {
+                symbol,
+                required: true,
+            }),
+            Some(RawEntryPoint::Check(true)) | None => Some(if existing_project {
+                Self {
+                    symbol: JNI_ENTRY_POINT.to_owned(),
+                    required: false,
+                }
+            } else {
+                Self {
+                    symbol: NATIVE_ACTIVITY_ENTRY_POINT.to_owned(),
+                    required: true,
+                }
+            }),
+        }
+    }
+}
+
+/// Which cleartext (plain HTTP) traffic the app permits.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AllowCleartext {
+    /// Any domain, but only in debug builds.
+    Debug,
+    /// Only these domains, in every build.
+    Domains(Vec<String>),
+}
+
+impl AllowCleartext {
+    fn from_raw(raw: RawAllowCleartext) -> Result<Option<Self>, Error> {
+        match raw {
+            RawAllowCleartext::Everywhere(allow) => Ok(Some(Self::Debug).filter(|_| allow)),
+            RawAllowCleartext::Domains(domains) => {
+                if domains.is_empty() {
+                    return Err(Error::CleartextDomainsEmpty);
+                }
+                for domain in &domains {
+                    if domain.contains('*') {
+                        return Err(Error::CleartextWildcard);
+                    }
+                    let valid = !domain.is_empty()
+                        && domain
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || ".-:".contains(c))
+                        // A single colon is a port, which IPv6 addresses never
+                        // have.
+                        && domain.matches(':').count() != 1;
+                    if !valid {
+                        return Err(Error::CleartextDomainInvalid {
+                            domain: domain.clone(),
+                        });
+                    }
+                }
+                Ok(Some(Self::Domains(domains)))
+            }
+        }
+    }
+}
+
+/// Passwords are never stored in the config; only the name of the
+/// environment variable to read them from.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Signing {
+    pub keystore: PathBuf,
+    pub key_alias: String,
+    pub keystore_password_env: String,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Raw {
@@ -151,6 +356,41 @@ pub struct Raw {
     project_dir: Option<String>,
     no_default_features: Option<bool>,
     features: Option<Vec<String>>,
+    debug_application_id_suffix: Option<String>,
+    signing: Option<Signing>,
+    targets: Option<Vec<String>>,
+    default_target: Option<String>,
+    allow_cleartext: Option<RawAllowCleartext>,
+    auto_connect: Option<Vec<String>>,
+    strip: Option<bool>,
+    debug_symbols_dir: Option<String>,
+    minify: Option<bool>,
+    version_code: Option<u32>,
+    version_name: Option<String>,
+    jnilibs_strategy: Option<jnilibs::Strategy>,
+    reconnect_window_secs: Option<u64>,
+    existing_project_dir: Option<String>,
+    logcat_format: Option<logcat::Format>,
+    extra_log_tags: Option<Vec<String>>,
+    lib_size_warning_mb: Option<u64>,
+    entry_point: Option<RawEntryPoint>,
+}
+
+impl Raw {
+    pub fn clear_project_dir(&mut self) {
+        self.project_dir = None;
+    }
+
+    pub fn prompt_project_dir(&mut self) -> Result<(), prompt::Error> {
+        self.project_dir = Some(prompt::default(
+            "config.android.project-dir",
+            "android.project-dir",
+            "Android Studio project dir",
+            Some(DEFAULT_PROJECT_DIR),
+            None,
+        )?);
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -161,6 +401,50 @@ pub struct Config {
     min_sdk_version: u32,
     vulkan_validation: bool,
     project_dir: PathBuf,
+    signing: Option<Signing>,
+    targets: Vec<String>,
+    #[serde(skip_serializing)]
+    default_target: Option<String>,
+    #[serde(skip_serializing)]
+    allow_cleartext: Option<AllowCleartext>,
+    #[serde(skip_serializing)]
+    auto_connect: Vec<String>,
+    debug_application_id_suffix: Option<String>,
+    #[serde(skip_serializing)]
+    strip: bool,
+    #[serde(skip_serializing)]
+    debug_symbols_dir: Option<PathBuf>,
+    minify: bool,
+    version_code: u32,
+    version_name: String,
+    #[serde(skip_serializing)]
+    jnilibs_strategy: jnilibs::Strategy,
+    #[serde(skip_serializing)]
+    reconnect_window: Duration,
+    #[serde(skip_serializing)]
+    existing_project_dir: Option<PathBuf>,
+    #[serde(skip_serializing)]
+    logcat_format: Option<logcat::Format>,
+    #[serde(skip_serializing)]
+    extra_log_tags: Vec<String>,
+    #[serde(skip_serializing)]
+    lib_size_warning: u64,
+    #[serde(skip_serializing)]
+    entry_point: Option<EntryPoint>,
+}
+
+/// `version-name` wins over `app.version`, which wins over the default.
+fn version_name(
+    explicit: Option<String>,
+    inherited: Option<VersionTriple>,
+) -> Result<String, Error> {
+    match explicit {
+        Some(name) if name.trim().is_empty() => Err(Error::VersionNameEmpty),
+        Some(name) => Ok(name),
+        None => Ok(inherited
+            .map(|version| version.to_string())
+            .unwrap_or_else(|| DEFAULT_VERSION_NAME.to_owned())),
+    }
 }
 
 impl Config {
@@ -185,6 +469,13 @@ impl Config {
             DEFAULT_VULKAN_VALIDATION
         });
 
+        if raw.existing_project_dir.is_some() && raw.project_dir.is_some() {
+            return Err(Error::ExistingProjectDirConflict);
+        }
+        let existing_project_dir = raw
+            .existing_project_dir
+            .map(|existing_project_dir| app.prefix_path(existing_project_dir));
+
         let project_dir = if let Some(project_dir) = raw.project_dir {
             if project_dir == DEFAULT_PROJECT_DIR {
                 log::warn!(
@@ -222,11 +513,122 @@ impl Config {
             Ok(DEFAULT_PROJECT_DIR.into())
         }?;
 
+        let signing = raw.signing.map(|signing| Signing {
+            keystore: app.prefix_path(&signing.keystore),
+            ..signing
+        });
+
+        let targets = raw.targets.unwrap_or_else(|| {
+            log::info!(
+                "`{}.targets` not set; defaulting to {:?}",
+                super::NAME,
+                DEFAULT_TARGETS
+            );
+            DEFAULT_TARGETS
+                .iter()
+                .map(|name| name.to_string())
+                .collect()
+        });
+        if targets.is_empty() {
+            return Err(Error::TargetsEmpty);
+        }
+        if let Some(name) = targets.iter().find(|name| Target::for_name(name).is_none()) {
+            return Err(Error::TargetInvalid {
+                name: name.to_owned(),
+            });
+        }
+
+        let default_target = raw.default_target;
+        if let Some(name) = default_target
+            .as_deref()
+            .filter(|name| Target::for_name(name).is_none())
+        {
+            return Err(Error::DefaultTargetInvalid {
+                name: name.to_owned(),
+            });
+        }
+
+        let allow_cleartext = raw
+            .allow_cleartext
+            .map(AllowCleartext::from_raw)
+            .transpose()?
+            .flatten();
+
+        let auto_connect = raw.auto_connect.unwrap_or_default();
+        if let Some(addr) = auto_connect
+            .iter()
+            .find(|addr| !super::adb::connect::addr_valid(addr))
+        {
+            return Err(Error::AutoConnectAddrInvalid {
+                addr: addr.to_owned(),
+            });
+        }
+
+        let debug_application_id_suffix = raw.debug_application_id_suffix;
+        if let Some(suffix) = debug_application_id_suffix
+            .as_deref()
+            .filter(|suffix| !app::id_suffix_valid(suffix, '_'))
+        {
+            return Err(Error::DebugApplicationIdSuffixInvalid {
+                suffix: suffix.to_owned(),
+            });
+        }
+
+        let strip = raw.strip.unwrap_or(true);
+        let debug_symbols_dir = raw.debug_symbols_dir.map(|dir| app.prefix_path(dir));
+
+        let minify = raw.minify.unwrap_or(false);
+        let version_code = raw.version_code.unwrap_or(DEFAULT_VERSION_CODE);
+        if version_code == 0 {
+            return Err(Error::VersionCodeZero);
+        }
+        let version_name = version_name(raw.version_name, app.version())?;
+
+        let jnilibs_strategy = raw
+            .jnilibs_strategy
+            .unwrap_or_else(jnilibs::Strategy::default_for_host);
+
+        let reconnect_window = Duration::from_secs(
+            raw.reconnect_window_secs
+                .unwrap_or(DEFAULT_RECONNECT_WINDOW_SECS),
+        );
+
+        let extra_log_tags = raw.extra_log_tags.unwrap_or_default();
+        if let Some(tag) = extra_log_tags.iter().find(|tag| !logcat::tag_valid(tag)) {
+            return Err(Error::ExtraLogTagInvalid {
+                tag: tag.to_owned(),
+            });
+        }
+
+        let lib_size_warning = raw
+            .lib_size_warning_mb
+            .unwrap_or(DEFAULT_LIB_SIZE_WARNING_MB)
+            .saturating_mul(1_000_000);
+        let entry_point = EntryPoint::from_raw(raw.entry_point, existing_project_dir.is_some());
+
         Ok(Self {
             app,
             min_sdk_version,
             vulkan_validation,
             project_dir,
+            signing,
+            targets,
+            default_target,
+            allow_cleartext,
+            auto_connect,
+            debug_application_id_suffix,
+            strip,
+            debug_symbols_dir,
+            minify,
+            version_code,
+            version_name,
+            jnilibs_strategy,
+            reconnect_window,
+            existing_project_dir,
+            logcat_format: raw.logcat_format,
+            extra_log_tags,
+            lib_size_warning,
+            entry_point,
         })
     }
 
@@ -234,6 +636,86 @@ impl Config {
         &self.app
     }
 
+    /// Wireless devices to `adb connect` to before looking for devices.
+    pub fn auto_connect(&self) -> &[String] {
+        &self.auto_connect
+    }
+
+    /// The application id builds for `profile` are installed under.
+    pub fn app_id(&self, profile: Profile) -> String {
+        app::for_profile(
+            &format!("{}.{}", self.app.reverse_domain(), self.app.name_snake()),
+            self.debug_application_id_suffix.as_deref(),
+            profile,
+        )
+    }
+
+    pub fn debug_application_id_suffix(&self) -> Option<&str> {
+        self.debug_application_id_suffix.as_deref()
+    }
+
+    /// Whether release libs get stripped before they're packaged. Debug libs
+    /// never are.
+    pub fn strip(&self) -> bool {
+        self.strip
+    }
+
+    /// Where unstripped copies of stripped libs get copied to, in a subdir
+    /// per ABI, on top of staying in cargo's target dir.
+    pub fn debug_symbols_dir(&self) -> Option<&Path> {
+        self.debug_symbols_dir.as_deref()
+    }
+
+    /// Whether release builds are minified with R8, in which case their
+    /// mappings are kept alongside them.
+    pub fn minify(&self) -> bool {
+        self.minify
+    }
+
+    pub fn version_code(&self) -> u32 {
+        self.version_code
+    }
+
+    pub fn version_name(&self) -> &str {
+        &self.version_name
+    }
+
+    pub fn jnilibs_strategy(&self) -> jnilibs::Strategy {
+        self.jnilibs_strategy
+    }
+
+    /// How long `run` waits for a device that dropped off to come back before
+    /// giving up on its logs. Zero means not waiting at all.
+    pub fn reconnect_window(&self) -> Duration {
+        self.reconnect_window
+    }
+
+    /// The `-v` format for `run`'s logs, if not logcat's default.
+    pub fn logcat_format(&self) -> Option<logcat::Format> {
+        self.logcat_format
+    }
+
+    /// Tags `run` shows logs for besides the app's own, i.e. ones that a
+    /// separate service process logs under.
+    pub fn extra_log_tags(&self) -> &[String] {
+        &self.extra_log_tags
+    }
+
+    /// How big a packaged lib can get, in bytes, before builds warn about it.
+    pub fn lib_size_warning(&self) -> u64 {
+        self.lib_size_warning
+    }
+
+    /// What the lib is checked for after building, unless that's been
+    /// turned off with `entry-point = false`.
+    pub fn entry_point(&self) -> Option<&EntryPoint> {
+        self.entry_point.as_ref()
+    }
+
+    pub fn cargo_target_dir(&self) -> PathBuf {
+        util::cargo_target_dir(self.app.root_dir())
+    }
+
     pub fn so_name(&self) -> String {
         format!("lib{}.so", self.app().name_snake())
     }
@@ -242,7 +724,22 @@ impl Config {
         self.min_sdk_version
     }
 
+    /// The Android Studio project we were pointed at instead of generating
+    /// one, if `existing-project-dir` is set.
+    pub fn existing_project_dir(&self) -> Option<&Path> {
+        self.existing_project_dir.as_deref()
+    }
+
+    /// Whether the Android Studio project is the user's own, in which case
+    /// none of the commands that assume a generated project are available.
+    pub fn adopted(&self) -> bool {
+        self.existing_project_dir.is_some()
+    }
+
     pub fn project_dir(&self) -> PathBuf {
+        if let Some(existing_project_dir) = &self.existing_project_dir {
+            return existing_project_dir.clone();
+        }
         self.app
             .prefix_path(&self.project_dir)
             .join(self.app().name())
@@ -251,4 +748,152 @@ impl Config {
     pub fn project_dir_exists(&self) -> bool {
         self.project_dir().is_dir()
     }
+
+    pub fn allow_cleartext(&self) -> Option<&AllowCleartext> {
+        self.allow_cleartext.as_ref()
+    }
+
+    pub fn signing(&self) -> Option<&Signing> {
+        self.signing.as_ref()
+    }
+
+    /// The targets named in `android.targets`, which everything that'd
+    /// otherwise act on all targets is limited to.
+    pub fn targets(&self) -> Vec<&'static Target<'static>> {
+        self.targets
+            .iter()
+            .map(|name| {
+                Target::for_name(name).expect("developer error: `targets` wasn't validated")
+            })
+            .collect()
+    }
+
+    pub fn target_names(&self) -> &[String] {
+        &self.targets
+    }
+
+    /// The target to use when none are given and no device was detected.
+    pub fn default_target(&self) -> Option<&'static Target<'static>> {
+        self.default_target.as_deref().map(|name| {
+            Target::for_name(name).expect("developer error: `default-target` wasn't validated")
+        })
+    }
+
+    /// Where gradle builds the app module to, unless told otherwise.
+    pub fn build_dir(&self) -> PathBuf {
+        self.project_dir().join("app/build")
+    }
+
+    fn output_resource_path(
+        &self,
+        build_dir: &Path,
+        output_dir: String,
+        file_extension: &str,
+        profile: Profile,
+        flavor: &str,
+    ) -> PathBuf {
+        let suffix = match profile {
+            Profile::Debug => profile.as_str(),
+            // TODO: how to handle signed APKs?
+            Profile::Release => "release-unsigned",
+        };
+        build_dir.join(format!(
+            "outputs/{}/app-{}-{}.{}",
+            output_dir, flavor, suffix, file_extension
+        ))
+    }
+
+    pub fn apk_path(&self, profile: Profile, flavor: &str) -> PathBuf {
+        self.apk_path_in(&self.build_dir(), profile, flavor)
+    }
+
+    /// `apk_path`, for a build into `build_dir` instead of the usual one.
+    pub fn apk_path_in(&self, build_dir: &Path, profile: Profile, flavor: &str) -> PathBuf {
+        self.output_resource_path(
+            build_dir,
+            format!("apk/{}/{}", flavor, profile.as_str()),
+            "apk",
+            profile,
+            flavor,
+        )
+    }
+
+    pub fn apks_path(&self, profile: Profile, flavor: &str) -> PathBuf {
+        self.output_resource_path(
+            &self.build_dir(),
+            format!("apk/{}/{}", flavor, profile.as_str()),
+            "apks",
+            profile,
+            flavor,
+        )
+    }
+
+    pub fn aab_path(&self, profile: Profile, flavor: &str) -> PathBuf {
+        self.output_resource_path(
+            &self.build_dir(),
+            format!("bundle/{}{}", flavor, profile.as_str()),
+            "aab",
+            profile,
+            flavor,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        explicit,
+        inherited,
+        expected,
+        case(None, None, "1.0"),
+        case(None, Some(VersionTriple::new(1, 4, 0)), "1.4.0"),
+        case(Some("1.4.0-beta"), Some(VersionTriple::new(1, 4, 0)), "1.4.0-beta"),
+        case(Some("2"), None, "2")
+    )]
+    fn version_name_falls_back_on_app_version(
+        explicit: Option<&str>,
+        inherited: Option<VersionTriple>,
+        expected: &str,
+    ) {
+        assert_eq!(
+            version_name(explicit.map(ToOwned::to_owned), inherited).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn rejects_empty_version_name() {
+        assert!(matches!(
+            version_name(Some(" ".to_owned()), Some(VersionTriple::new(1, 4, 0))),
+            Err(Error::VersionNameEmpty)
+        ));
+    }
+
+    #[rstest(
+        raw,
+        existing_project,
+        expected,
+        case(None, false, Some((NATIVE_ACTIVITY_ENTRY_POINT, true))),
+        case(None, true, Some((JNI_ENTRY_POINT, false))),
+        case(Some("entry-point = true"), true, Some((JNI_ENTRY_POINT, false))),
+        case(Some("entry-point = \"android_main\""), true, Some(("android_main", true))),
+        case(Some("entry-point = false"), false, None)
+    )]
+    fn only_guessed_entry_points_are_optional(
+        raw: Option<&str>,
+        existing_project: bool,
+        expected: Option<(&str, bool)>,
+    ) {
+        let raw = raw.map(|raw| toml::from_str::<Raw>(raw).unwrap().entry_point.unwrap());
+        assert_eq!(
+            EntryPoint::from_raw(raw, existing_project),
+            expected.map(|(symbol, required)| EntryPoint {
+                symbol: symbol.to_owned(),
+                required,
+            })
+        );
+    }
 }
@@ -0,0 +1,304 @@
+use super::{adb, config::Config, device::RunError, env::Env, ndk, target::Target};
+use crate::{
+    opts::Profile,
+    util::cli::{Report, Reportable},
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+static STAGING_PATH: &str = "/data/local/tmp/cargo-mobile-lldb-server";
+static SERVER_NAME: &str = "lldb-server";
+
+#[derive(Debug)]
+pub enum DebugError {
+    RunFailed(RunError),
+    LldbServerMissing(ndk::MissingToolError),
+    PushFailed(bossy::Error),
+    CopyFailed(bossy::Error),
+    ServerStartFailed(bossy::Error),
+    PidNotFound { app_id: String },
+    ForwardFailed(bossy::Error),
+    ForwardPortInvalid(String),
+    InitWriteFailed { path: PathBuf, cause: io::Error },
+    CtrlCHandlerFailed(ctrlc::Error),
+    Cancelled,
+}
+
+impl Reportable for DebugError {
+    fn report(&self) -> Report {
+        match self {
+            Self::RunFailed(err) => err.report(),
+            Self::LldbServerMissing(err) => Report::error(
+                "Failed to find `lldb-server` in your NDK",
+                format!("{} NDK r23 or later ships it for every ABI.", err),
+            ),
+            Self::PushFailed(err) => Report::error("Failed to push `lldb-server` to device", err),
+            Self::CopyFailed(err) => Report::error(
+                "Failed to copy `lldb-server` into the app's data dir",
+                format!("{} `run-as` only works for debuggable builds, so make sure the debug APK was installed.", err),
+            ),
+            Self::ServerStartFailed(err) => Report::error("Failed to start `lldb-server`", err),
+            Self::PidNotFound { app_id } => Report::error(
+                "App didn't start",
+                format!("No process for {:?} showed up on the device.", app_id),
+            ),
+            Self::ForwardFailed(err) => Report::error("Failed to forward JDWP port", err),
+            Self::ForwardPortInvalid(output) => Report::error(
+                "Failed to forward JDWP port",
+                format!("`adb forward` printed {:?} instead of a port number", output),
+            ),
+            Self::InitWriteFailed { path, cause } => Report::error(
+                format!("Failed to write lldb init file to {:?}", path),
+                cause,
+            ),
+            Self::CtrlCHandlerFailed(err) => {
+                Report::error("Failed to set up cleanup on Ctrl-C", err)
+            }
+            Self::Cancelled => Report::error(
+                "Debug session cancelled",
+                "Ctrl-C was pressed before the app started.",
+            ),
+        }
+    }
+}
+
+// `lldb-server` is shipped per clang arch, which doesn't always match the
+// Android arch.
+fn lldb_arch(target: Target<'_>) -> &str {
+    match target.arch {
+        "arm64" => "aarch64",
+        "x86" => "i386",
+        arch => arch,
+    }
+}
+
+fn socket_name(app_id: &str) -> String {
+    format!("cargo-mobile-debug-{}", app_id)
+}
+
+fn lldb_init(serial_no: &str, app_id: &str, pid: u32, search_path: &Path) -> String {
+    format!(
+        "platform select remote-android\n\
+         platform connect unix-abstract-connect://{}/{}\n\
+         settings set target.exec-search-paths \"{}\"\n\
+         process attach --pid {}\n",
+        serial_no,
+        socket_name(app_id),
+        search_path.display(),
+        pid,
+    )
+}
+
+/// Everything staged on the device for a debug session. Dropping this tears
+/// it all down again, so nothing is left running no matter how we exit.
+pub struct Session<'a> {
+    env: &'a Env,
+    serial_no: &'a str,
+    app_id: String,
+    server: Option<bossy::Handle>,
+    jdwp_port: Option<u16>,
+    // Fed by Enter and by Ctrl-C, which would otherwise kill us before `Drop`
+    // got to clean up.
+    end_tx: mpsc::Sender<()>,
+    end: mpsc::Receiver<()>,
+}
+
+impl<'a> Session<'a> {
+    fn run_as(&self) -> bossy::Command {
+        adb::adb(self.env, self.serial_no).with_args(&["shell", "run-as", self.app_id.as_str()])
+    }
+
+    /// Pushes the NDK's `lldb-server` into the app's data dir and starts it.
+    pub fn start(
+        env: &'a Env,
+        serial_no: &'a str,
+        target: Target<'_>,
+        app_id: String,
+    ) -> Result<Self, DebugError> {
+        let (end_tx, end) = mpsc::channel();
+        let ctrl_c_tx = end_tx.clone();
+        ctrlc::set_handler(move || {
+            let _ = ctrl_c_tx.send(());
+        })
+        .map_err(DebugError::CtrlCHandlerFailed)?;
+        let server_path = env
+            .ndk
+            .lldb_server_path(lldb_arch(target))
+            .map_err(DebugError::LldbServerMissing)?;
+        adb::adb(env, serial_no)
+            .with_arg("push")
+            .with_arg(&server_path)
+            .with_arg(STAGING_PATH)
+            .run_and_wait()
+            .map_err(DebugError::PushFailed)?;
+        let mut session = Self {
+            env,
+            serial_no,
+            app_id,
+            server: None,
+            jdwp_port: None,
+            end_tx,
+            end,
+        };
+        // `run-as` puts us in the app's data dir, which is the only place the
+        // app's user is allowed to run things from.
+        session
+            .run_as()
+            .with_args(&["cp", STAGING_PATH, SERVER_NAME])
+            .run_and_wait()
+            .map_err(DebugError::CopyFailed)?;
+        session
+            .run_as()
+            .with_args(&["chmod", "700", SERVER_NAME])
+            .run_and_wait()
+            .map_err(DebugError::CopyFailed)?;
+        let listen = format!("unix-abstract:///{}", socket_name(&session.app_id));
+        session.server = Some(
+            session
+                .run_as()
+                .with_arg(format!("./{}", SERVER_NAME))
+                .with_args(&["platform", "--server", "--listen", &listen])
+                .with_stdout(bossy::Stdio::null())
+                .run()
+                .map_err(DebugError::ServerStartFailed)?,
+        );
+        Ok(session)
+    }
+
+    /// Waits for the app to show up after `am start -D`, since it takes a
+    /// moment to spawn.
+    pub fn wait_for_pid(&self) -> Result<u32, DebugError> {
+        for _ in 0..20 {
+            let pid = adb::adb(self.env, self.serial_no)
+                .with_args(&["shell", "pidof", self.app_id.as_str()])
+                .run_and_wait_for_string()
+                .ok()
+                .and_then(|output| output.split_whitespace().next()?.parse().ok());
+            if let Some(pid) = pid {
+                return Ok(pid);
+            }
+            if self.end.recv_timeout(Duration::from_millis(250)).is_ok() {
+                return Err(DebugError::Cancelled);
+            }
+        }
+        Err(DebugError::PidNotFound {
+            app_id: self.app_id.clone(),
+        })
+    }
+
+    /// Forwards a local port to the app's JDWP socket, which is needed to tell
+    /// the app to stop waiting for a debugger.
+    pub fn forward_jdwp(&mut self, pid: u32) -> Result<u16, DebugError> {
+        let output = adb::adb(self.env, self.serial_no)
+            .with_args(&["forward", "tcp:0"])
+            .with_arg(format!("jdwp:{}", pid))
+            .run_and_wait_for_string()
+            .map_err(DebugError::ForwardFailed)?;
+        let port = output
+            .trim()
+            .parse()
+            .map_err(|_| DebugError::ForwardPortInvalid(output.clone()))?;
+        self.jdwp_port = Some(port);
+        Ok(port)
+    }
+
+    /// Writes an lldb init file into the project's `debug` dir that attaches
+    /// to `pid` with symbols from the (unstripped) libs in the cargo target
+    /// dir.
+    pub fn write_init(
+        &self,
+        config: &Config,
+        target: Target<'_>,
+        pid: u32,
+    ) -> Result<PathBuf, DebugError> {
+        let dir = config.project_dir().join("debug");
+        let path = dir.join("lldb-init");
+        let search_path = config
+            .cargo_target_dir()
+            .join(target.triple)
+            .join(Profile::Debug.as_str());
+        fs::create_dir_all(&dir)
+            .and_then(|()| {
+                fs::write(
+                    &path,
+                    lldb_init(self.serial_no, &self.app_id, pid, &search_path),
+                )
+            })
+            .map_err(|cause| DebugError::InitWriteFailed {
+                path: path.clone(),
+                cause,
+            })?;
+        Ok(path)
+    }
+
+    /// Returns once Enter or Ctrl-C is pressed.
+    pub fn wait_for_exit(&self) {
+        println!("Press Enter or Ctrl-C here to end the debug session and clean up.");
+        let enter_tx = self.end_tx.clone();
+        // Stdin can't be read with a timeout, so this thread just gets left
+        // behind if Ctrl-C wins.
+        thread::spawn(move || {
+            if let Err(err) = io::stdin().read_line(&mut String::new()) {
+                log::warn!("failed to read from stdin: {}", err);
+            }
+            let _ = enter_tx.send(());
+        });
+        // We're holding a sender, so this can't fail.
+        let _ = self.end.recv();
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        // Cleanup is best-effort; the worst case is a stray forward or server,
+        // which go away on their own when the device reboots.
+        if let Some(port) = self.jdwp_port {
+            if let Err(err) = adb::adb(self.env, self.serial_no)
+                .with_args(&["forward", "--remove"])
+                .with_arg(format!("tcp:{}", port))
+                .run_and_wait()
+            {
+                log::warn!("failed to remove JDWP forward: {}", err);
+            }
+        }
+        if let Some(mut server) = self.server.take() {
+            if let Err(err) = self
+                .run_as()
+                .with_args(&["pkill", "-f", SERVER_NAME])
+                .run_and_wait()
+            {
+                log::warn!("failed to stop `lldb-server`: {}", err);
+            }
+            if let Err(err) = server.wait_for_output() {
+                log::info!("`lldb-server` exited uncleanly: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lldb_init_attaches_with_search_path() {
+        let init = lldb_init(
+            "emulator-5554",
+            "com.example.game",
+            1234,
+            Path::new("/work/game/target/aarch64-linux-android/debug"),
+        );
+        assert_eq!(
+            init,
+            "platform select remote-android\n\
+             platform connect unix-abstract-connect://emulator-5554/cargo-mobile-debug-com.example.game\n\
+             settings set target.exec-search-paths \"/work/game/target/aarch64-linux-android/debug\"\n\
+             process attach --pid 1234\n"
+        );
+    }
+}
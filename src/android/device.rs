@@ -1,9 +1,19 @@
 use super::{
-    adb, bundletool,
+    adb,
+    artifacts::{self, Kind},
+    bundletool,
     config::Config,
-    env::Env,
+    debug::{self, DebugError},
+    env::{Env, LicensesError},
+    gradle::{self, GradleInvocation, TaskKind},
+    gradle_daemons,
+    inspect::{self, InspectError},
     jnilibs::{self, JniLibs},
+    mapping,
+    snapshot::{self, SnapshotError},
+    strip,
     target::{BuildError, Target},
+    trace::{self, TraceError},
 };
 use crate::{
     env::ExplicitEnv as _,
@@ -11,47 +21,52 @@ use crate::{
     util::{
         self,
         cli::{Report, Reportable},
+        watchdog,
     },
 };
 use std::{
     fmt::{self, Display},
-    path::PathBuf,
+    path::Path,
+    str::FromStr,
 };
 
-fn gradlew(config: &Config, env: &Env) -> bossy::Command {
-    let gradlew_path = config.project_dir().join("gradlew");
-    bossy::Command::pure(&gradlew_path)
-        .with_env_vars(env.explicit_env())
-        .with_arg("--project-dir")
-        .with_arg(config.project_dir())
-}
+// `pm install --dont-kill` is only understood from Android 8 on.
+static DONT_KILL_MIN_API_LEVEL: u32 = 26;
 
 #[derive(Debug)]
 pub enum ApkBuildError {
+    LicensesNotAccepted(LicensesError),
     LibSymlinkCleaningFailed(jnilibs::RemoveBrokenLinksError),
     LibBuildFailed(BuildError),
-    AssembleFailed(bossy::Error),
+    AssembleFailed(gradle::BuildError),
 }
 
 impl Reportable for ApkBuildError {
     fn report(&self) -> Report {
         match self {
+            Self::LicensesNotAccepted(err) => err.report(),
             Self::LibSymlinkCleaningFailed(err) => err.report(),
             Self::LibBuildFailed(err) => err.report(),
-            Self::AssembleFailed(err) => Report::error("Failed to assemble APK", err),
+            Self::AssembleFailed(err) => {
+                Report::error("Failed to assemble APK", gradle_daemons::BuildFailure(err))
+            }
         }
     }
 }
 
 #[derive(Debug)]
 pub enum AabBuildError {
-    BuildFailed(bossy::Error),
+    LicensesNotAccepted(LicensesError),
+    BuildFailed(gradle::BuildError),
 }
 
 impl Reportable for AabBuildError {
     fn report(&self) -> Report {
         match self {
-            Self::BuildFailed(err) => Report::error("Failed to build AAB", err),
+            Self::LicensesNotAccepted(err) => err.report(),
+            Self::BuildFailed(err) => {
+                Report::error("Failed to build AAB", gradle_daemons::BuildFailure(err))
+            }
         }
     }
 }
@@ -73,13 +88,14 @@ impl Reportable for ApksBuildError {
 
 #[derive(Debug)]
 pub enum ApkInstallError {
-    InstallFailed(bossy::Error),
+    InstallFailed(watchdog::Error),
     InstallFromAabFailed(bossy::Error),
 }
 
 impl Reportable for ApkInstallError {
     fn report(&self) -> Report {
         match self {
+            Self::InstallFailed(err) if err.is_hung() => err.report(),
             Self::InstallFailed(err) => Report::error("Failed to install APK", err),
             Self::InstallFromAabFailed(err) => Report::error("Failed to install APK from AAB", err),
         }
@@ -88,6 +104,10 @@ impl Reportable for ApkInstallError {
 
 #[derive(Debug)]
 pub enum RunError {
+    ApiLevelTooLow {
+        api_level: u32,
+        min_sdk_version: u32,
+    },
     ApkBuildFailed(ApkBuildError),
     ApkInstallFailed(ApkInstallError),
     StartFailed(bossy::Error),
@@ -96,11 +116,26 @@ pub enum RunError {
     BundletoolInstallFailed(bundletool::InstallError),
     AabBuildFailed(AabBuildError),
     ApksFromAabBuildFailed(ApksBuildError),
+    MappingRetainFailed(mapping::RetainError),
+    TraceFailed(TraceError),
+    InspectFailed(InspectError),
 }
 
 impl Reportable for RunError {
     fn report(&self) -> Report {
         match self {
+            Self::ApiLevelTooLow {
+                api_level,
+                min_sdk_version,
+            } => Report::error(
+                "Device API level is below `min-sdk-version`",
+                format!(
+                    "The selected device runs API level {}, but this app's `min-sdk-version` is {}. Use a newer device, lower `android.min-sdk-version` in your {}, or pass `--force` to try anyway.",
+                    api_level,
+                    min_sdk_version,
+                    crate::config::file_name(),
+                ),
+            ),
             Self::ApkBuildFailed(err) => err.report(),
             Self::ApkInstallFailed(err) => err.report(),
             Self::StartFailed(err) => Report::error("Failed to start app on device", err),
@@ -109,6 +144,9 @@ impl Reportable for RunError {
             Self::BundletoolInstallFailed(err) => err.report(),
             Self::AabBuildFailed(err) => err.report(),
             Self::ApksFromAabBuildFailed(err) => err.report(),
+            Self::MappingRetainFailed(err) => Report::error("Failed to retain R8 mapping", err),
+            Self::TraceFailed(err) => err.report(),
+            Self::InspectFailed(err) => err.report(),
         }
     }
 }
@@ -126,11 +164,119 @@ impl Reportable for StacktraceError {
     }
 }
 
+/// How adb reaches a device.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Transport {
+    Usb,
+    Tcp,
+    Emulator,
+}
+
+impl Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Usb => "USB",
+                Self::Tcp => "Wi-Fi",
+                Self::Emulator => "emulator",
+            }
+        )
+    }
+}
+
+impl Transport {
+    /// adb names TCP devices after their address, or after their mDNS
+    /// service when they were discovered via wireless debugging.
+    pub fn from_serial_no(serial_no: &str) -> Self {
+        if serial_no.starts_with("emulator-") {
+            Self::Emulator
+        } else if serial_no.contains(':') || serial_no.contains("._adb-tls-connect.") {
+            Self::Tcp
+        } else {
+            Self::Usb
+        }
+    }
+}
+
+/// Whether `run` starts the app once it's installed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RestartPolicy {
+    /// Starts it even if it's running, which is what `run` always did.
+    Always,
+    /// Leaves it alone if it's still running after the install. A full
+    /// reinstall stops the app on most devices, even with `--dont-kill`, in
+    /// which case it's started again.
+    IfNotRunning,
+    /// Never starts it, though its logs are still shown.
+    Never,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self::Always
+    }
+}
+
+impl RestartPolicy {
+    pub const ALL: &'static [Self] = &[Self::Always, Self::IfNotRunning, Self::Never];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::IfNotRunning => "if-not-running",
+            Self::Never => "never",
+        }
+    }
+
+    pub fn names() -> Vec<&'static str> {
+        Self::ALL.iter().map(|policy| policy.as_str()).collect()
+    }
+
+    /// `pids` is what `pidof` found, or `None` if we couldn't tell (or
+    /// didn't ask), in which case `IfNotRunning` starts the app to be safe.
+    pub fn should_start(self, pids: Option<&[u32]>) -> bool {
+        match self {
+            Self::Always => true,
+            Self::IfNotRunning => pids.map_or(true, |pids| pids.is_empty()),
+            Self::Never => false,
+        }
+    }
+}
+
+impl Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|policy| policy.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "{:?} isn't a restart policy; expected one of {}",
+                    s,
+                    Self::names().join(", ")
+                )
+            })
+    }
+}
+
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Device<'a> {
     serial_no: String,
+    transport: Transport,
     name: String,
     model: String,
+    os_version: String,
+    api_level: u32,
     target: &'a Target<'a>,
 }
 
@@ -140,6 +286,10 @@ impl<'a> Display for Device<'a> {
         if self.model != self.name {
             write!(f, " ({})", self.model)?;
         }
+        write!(f, " (Android {}, API {})", self.os_version, self.api_level)?;
+        if self.transport != Transport::Usb {
+            write!(f, " [{}]", self.transport)?;
+        }
         Ok(())
     }
 }
@@ -149,74 +299,35 @@ impl<'a> Device<'a> {
         serial_no: String,
         name: String,
         model: String,
+        os_version: String,
+        api_level: u32,
         target: &'a Target<'a>,
     ) -> Self {
         Self {
+            transport: Transport::from_serial_no(&serial_no),
             serial_no,
             name,
             model,
+            os_version,
+            api_level,
             target,
         }
     }
 
-    pub fn target(&self) -> &'a Target<'a> {
-        self.target
+    pub fn serial_no(&self) -> &str {
+        &self.serial_no
     }
 
-    fn adb(&self, env: &Env) -> bossy::Command {
-        adb::adb(env, &self.serial_no)
+    pub fn transport(&self) -> Transport {
+        self.transport
     }
 
-    fn suffix(profile: Profile) -> &'static str {
-        match profile {
-            Profile::Debug => profile.as_str(),
-            // TODO: how to handle signed APKs?
-            Profile::Release => "release-unsigned",
-        }
-    }
-
-    fn output_resource_path(
-        output_dir: String,
-        file_extension: &str,
-        config: &Config,
-        profile: Profile,
-        flavor: &str,
-    ) -> PathBuf {
-        let suffix = Self::suffix(profile);
-        config.project_dir().join(format!(
-            "app/build/outputs/{}/app-{}-{}.{}",
-            output_dir, flavor, suffix, file_extension
-        ))
-    }
-
-    fn apk_path(config: &Config, profile: Profile, flavor: &str) -> PathBuf {
-        Self::output_resource_path(
-            format!("apk/{}/{}", flavor, profile.as_str()),
-            "apk",
-            config,
-            profile,
-            flavor,
-        )
-    }
-
-    fn apks_path(config: &Config, profile: Profile, flavor: &str) -> PathBuf {
-        Self::output_resource_path(
-            format!("apk/{}/{}", flavor, profile.as_str()),
-            "apks",
-            config,
-            profile,
-            flavor,
-        )
+    pub fn target(&self) -> &'a Target<'a> {
+        self.target
     }
 
-    fn aab_path(config: &Config, profile: Profile, flavor: &str) -> PathBuf {
-        Self::output_resource_path(
-            format!("bundle/{}{}", flavor, profile.as_str()),
-            "aab",
-            config,
-            profile,
-            flavor,
-        )
+    fn adb(&self, env: &Env) -> bossy::Command {
+        adb::adb(env, &self.serial_no)
     }
 
     fn build_apk(
@@ -226,41 +337,60 @@ impl<'a> Device<'a> {
         noise_level: NoiseLevel,
         profile: Profile,
     ) -> Result<(), ApkBuildError> {
-        use heck::ToUpperCamelCase as _;
+        env.check_licenses()
+            .map_err(ApkBuildError::LicensesNotAccepted)?;
         JniLibs::remove_broken_links(config).map_err(ApkBuildError::LibSymlinkCleaningFailed)?;
-        let flavor = self.target.arch.to_upper_camel_case();
-        let build_ty = profile.as_str().to_upper_camel_case();
-        gradlew(config, env)
-            .with_arg(format!("assemble{}{}", flavor, build_ty))
-            .with_arg(match noise_level {
-                NoiseLevel::Polite => "--warn",
-                NoiseLevel::LoudAndProud => "--info",
-                NoiseLevel::FranklyQuitePedantic => "--debug",
-            })
-            .run_and_wait()
+        let task = gradle::task(TaskKind::Assemble, self.target.arch, profile);
+        let invocation = GradleInvocation::new()
+            .with_task(&task)
+            .with_config(config)
+            .with_noise_level(noise_level);
+        log::info!("running {}", invocation);
+        gradle::run_build(format!("gradle {}", task), invocation.command(config, env))
             .map_err(ApkBuildError::AssembleFailed)?;
         Ok(())
     }
 
+    /// `dont_kill` asks the package manager to leave the app running, though
+    /// most devices only honor that for split installs and still stop it
+    /// for a full one.
     fn install_apk(
         &self,
         config: &Config,
         env: &Env,
         profile: Profile,
+        dont_kill: bool,
     ) -> Result<(), ApkInstallError> {
         let flavor = self.target.arch;
-        let apk_path = Self::apk_path(config, profile, flavor);
-        self.adb(env)
-            .with_arg("install")
-            .with_arg(apk_path)
-            .run_and_wait()
-            .map_err(ApkInstallError::InstallFailed)?;
+        let apk_path = config.apk_path(profile, flavor);
+        let dont_kill = dont_kill && self.api_level >= DONT_KILL_MIN_API_LEVEL;
+        adb::server::with_restart(
+            env,
+            || {
+                let mut command = adb::watched_adb(env, &self.serial_no);
+                command.arg("install");
+                if dont_kill {
+                    command.arg("--dont-kill");
+                }
+                command.arg(&apk_path);
+                watchdog::run(watchdog::Class::Adb, command)
+            },
+            // An install that just sits there is how a wedged server usually
+            // shows itself.
+            |err: &watchdog::Error| {
+                err.is_hung()
+                    || err
+                        .output()
+                        .map_or(false, |output| adb::server::is_wedged(&output.text()))
+            },
+        )
+        .map_err(ApkInstallError::InstallFailed)?;
         Ok(())
     }
 
     fn clean_apks(&self, config: &Config, profile: Profile) -> Result<(), ApksBuildError> {
         let flavor = self.target.arch;
-        let apks_path = Self::apks_path(config, profile, flavor);
+        let apks_path = config.apks_path(profile, flavor);
         if apks_path.exists() {
             std::fs::remove_file(&apks_path).map_err(ApksBuildError::CleanFailed)?;
         }
@@ -268,20 +398,20 @@ impl<'a> Device<'a> {
     }
 
     fn build_aab(&self, config: &Config, env: &Env, profile: Profile) -> Result<(), AabBuildError> {
-        use heck::ToUpperCamelCase as _;
-        let flavor = self.target.arch.to_upper_camel_case();
-        let build_ty = profile.as_str().to_upper_camel_case();
-        gradlew(config, env)
-            .with_arg(format!(":app:bundle{}{}", flavor, build_ty))
-            .run_and_wait()
+        env.check_licenses()
+            .map_err(AabBuildError::LicensesNotAccepted)?;
+        let task = gradle::task(TaskKind::Bundle, self.target.arch, profile);
+        let invocation = GradleInvocation::new().with_task(&task).with_config(config);
+        log::info!("running {}", invocation);
+        gradle::run_build(format!("gradle {}", task), invocation.command(config, env))
             .map_err(AabBuildError::BuildFailed)?;
         Ok(())
     }
 
     fn build_apks_from_aab(&self, config: &Config, profile: Profile) -> Result<(), ApksBuildError> {
         let flavor = self.target.arch;
-        let apks_path = Self::apks_path(config, profile, flavor);
-        let aab_path = Self::aab_path(config, profile, flavor);
+        let apks_path = config.apks_path(profile, flavor);
+        let aab_path = config.aab_path(profile, flavor);
         bundletool::command()
             .with_arg("build-apks")
             .with_arg(format!("--bundle={}", aab_path.to_str().unwrap()))
@@ -298,7 +428,7 @@ impl<'a> Device<'a> {
         profile: Profile,
     ) -> Result<(), ApkInstallError> {
         let flavor = self.target.arch;
-        let apks_path = Self::apks_path(config, profile, flavor);
+        let apks_path = config.apks_path(profile, flavor);
         bundletool::command()
             .with_arg("install-apks")
             .with_arg(format!("--apks={}", apks_path.to_str().unwrap()))
@@ -314,6 +444,43 @@ impl<'a> Device<'a> {
         Ok(())
     }
 
+    fn check_api_level(&self, config: &Config, force: opts::Force) -> Result<(), RunError> {
+        if force.no() && self.api_level < config.min_sdk_version() {
+            Err(RunError::ApiLevelTooLow {
+                api_level: self.api_level,
+                min_sdk_version: config.min_sdk_version(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// What `pidof` found for `app_id`, or `None` if we couldn't tell.
+    fn pids(&self, env: &Env, app_id: &str) -> Option<Vec<u32>> {
+        adb::pidof::pids(env, &self.serial_no, app_id)
+            .map_err(|err| log::warn!("{}; starting the app anyway", err))
+            .ok()
+    }
+
+    fn start_app(
+        &self,
+        env: &Env,
+        app_id: &str,
+        launch_args: &[String],
+        wait_for_debugger: bool,
+    ) -> bossy::Result<()> {
+        let activity = format!("{}/android.app.NativeActivity", app_id);
+        let mut command = self.adb(env).with_args(&["shell", "am", "start"]);
+        if wait_for_debugger {
+            command.add_arg("-D");
+        }
+        command
+            .with_args(&["-n", &activity])
+            .with_args(launch_args.iter().map(|arg| adb::shell_quote(arg)))
+            .run_and_wait()?;
+        Ok(())
+    }
+
     pub fn run(
         &self,
         config: &Config,
@@ -323,13 +490,30 @@ impl<'a> Device<'a> {
         filter_level: Option<FilterLevel>,
         build_app_bundle: bool,
         reinstall_deps: opts::ReinstallDeps,
+        force: opts::Force,
+        launch_args: &[String],
+        trace_seconds: Option<u32>,
+        logcat_format: Option<adb::logcat::Format>,
+        no_launch: bool,
+        restart_policy: RestartPolicy,
     ) -> Result<(), RunError> {
+        self.check_api_level(config, force)?;
+        let app_id = config.app_id(profile);
+        // Installing can stop the app, so whether it was running has to be
+        // checked first.
+        let was_running = if restart_policy == RestartPolicy::IfNotRunning && !no_launch {
+            self.pids(env, &app_id).map(|pids| !pids.is_empty())
+        } else {
+            None
+        };
         if build_app_bundle {
             bundletool::install(reinstall_deps).map_err(RunError::BundletoolInstallFailed)?;
             self.clean_apks(config, profile)
                 .map_err(RunError::ApksFromAabBuildFailed)?;
             self.build_aab(config, env, profile)
                 .map_err(RunError::AabBuildFailed)?;
+            artifacts::finish(config, profile, self.target.arch, Kind::Aab)
+                .map_err(RunError::MappingRetainFailed)?;
             self.build_apks_from_aab(config, profile)
                 .map_err(RunError::ApksFromAabBuildFailed)?;
             self.install_apk_from_aab(config, profile)
@@ -337,37 +521,120 @@ impl<'a> Device<'a> {
         } else {
             self.build_apk(config, env, noise_level, profile)
                 .map_err(RunError::ApkBuildFailed)?;
-            self.install_apk(config, env, profile)
+            artifacts::finish(config, profile, self.target.arch, Kind::Apk)
+                .map_err(RunError::MappingRetainFailed)?;
+            inspect::check(
+                env,
+                &config.apk_path(profile, self.target.arch),
+                &inspect::Expected::new(config, profile, &[self.target]),
+            )
+            .map_err(RunError::InspectFailed)?;
+            self.install_apk(config, env, profile, was_running == Some(true))
                 .map_err(RunError::ApkInstallFailed)?;
         }
-        let activity = format!(
-            "{}.{}/android.app.NativeActivity",
-            config.app().reverse_domain(),
-            config.app().name_snake(),
-        );
-        self.adb(env)
-            .with_args(&["shell", "am", "start", "-n", &activity])
-            .run_and_wait()
-            .map_err(RunError::StartFailed)?;
+        if no_launch {
+            println!(
+                "Installed {} on {}; it wasn't started, since `--no-launch` was passed.",
+                app_id, self
+            );
+            return Ok(());
+        }
+        let pids = if was_running == Some(true) {
+            self.pids(env, &app_id)
+        } else {
+            was_running.map(|_| Vec::new())
+        };
+        if restart_policy.should_start(pids.as_deref()) {
+            if was_running == Some(true) {
+                println!(
+                    "Installing stopped {}, so it's being started again.",
+                    app_id
+                );
+            }
+            self.start_app(env, &app_id, launch_args, false)
+                .map_err(RunError::StartFailed)?;
+        } else if restart_policy == RestartPolicy::Never {
+            println!(
+                "Not starting {}, since `--restart-policy` is \"never\".",
+                app_id
+            );
+        } else {
+            println!("{} is already running, so it wasn't restarted.", app_id);
+        }
         self.wake_screen(env).map_err(RunError::WakeScreenFailed)?;
-        let filter = format!(
-            "{}:{}",
-            config.app().name(),
-            filter_level
-                .unwrap_or(match noise_level {
-                    NoiseLevel::Polite => FilterLevel::Warn,
-                    NoiseLevel::LoudAndProud => FilterLevel::Info,
-                    NoiseLevel::FranklyQuitePedantic => FilterLevel::Verbose,
-                })
-                .logcat()
-        );
-        adb::adb(env, &self.serial_no)
-            .with_args(&["logcat", "-v", "color", "-s", &filter])
-            .run_and_wait()
+        if let Some(seconds) = trace_seconds {
+            let path = trace::capture(config, env, &self.serial_no, &app_id, seconds)
+                .map_err(RunError::TraceFailed)?;
+            println!(
+                "Trace saved to {:?}; open it at https://ui.perfetto.dev",
+                path
+            );
+            return Ok(());
+        }
+        let priority = filter_level
+            .unwrap_or(match noise_level {
+                NoiseLevel::Polite => FilterLevel::Warn,
+                NoiseLevel::LoudAndProud => FilterLevel::Info,
+                NoiseLevel::FranklyQuitePedantic => FilterLevel::Verbose,
+            })
+            .logcat();
+        let args = adb::logcat::Args::new(
+            std::iter::once(config.app().name().to_owned())
+                .chain(config.extra_log_tags().iter().cloned()),
+            priority,
+        )
+        .with_format(logcat_format.or_else(|| config.logcat_format()))
+        .for_api_level(self.api_level);
+        adb::reconnect::logcat(env, &self.serial_no, &args, config.reconnect_window())
             .map_err(RunError::LogcatFailed)?;
         Ok(())
     }
 
+    /// Installs a debug build, then launches it waiting for a debugger with
+    /// `lldb-server` running alongside it, and prints how to attach.
+    pub fn debug(
+        &self,
+        config: &Config,
+        env: &Env,
+        noise_level: NoiseLevel,
+        force: opts::Force,
+        launch_args: &[String],
+    ) -> Result<(), DebugError> {
+        let profile = Profile::Debug;
+        self.check_api_level(config, force)
+            .map_err(DebugError::RunFailed)?;
+        self.build_apk(config, env, noise_level, profile)
+            .map_err(|err| DebugError::RunFailed(RunError::ApkBuildFailed(err)))?;
+        self.install_apk(config, env, profile, false)
+            .map_err(|err| DebugError::RunFailed(RunError::ApkInstallFailed(err)))?;
+        let app_id = config.app_id(profile);
+        let mut session =
+            debug::Session::start(env, &self.serial_no, *self.target, app_id.clone())?;
+        self.start_app(env, &app_id, launch_args, true)
+            .map_err(|err| DebugError::RunFailed(RunError::StartFailed(err)))?;
+        self.wake_screen(env)
+            .map_err(|err| DebugError::RunFailed(RunError::WakeScreenFailed(err)))?;
+        let pid = session.wait_for_pid()?;
+        let jdwp_port = session.forward_jdwp(pid)?;
+        let init = session.write_init(config, *self.target, pid)?;
+        println!(
+            "The app (PID {}) is waiting for a debugger. To attach, run:\n    lldb -s {:?}\nOnce attached, let the app continue with:\n    jdb -connect com.sun.jdi.SocketAttach:hostname=localhost,port={}",
+            pid, init, jdwp_port
+        );
+        session.wait_for_exit();
+        Ok(())
+    }
+
+    /// Returns the screenshot's size in bytes.
+    pub fn screenshot(&self, env: &Env, path: &Path) -> Result<u64, SnapshotError> {
+        snapshot::screenshot(env, &self.serial_no, path)
+    }
+
+    /// Returns the recording's size in bytes.
+    pub fn screenrecord(&self, env: &Env, seconds: u32, path: &Path) -> Result<u64, SnapshotError> {
+        snapshot::record(env, &self.serial_no, seconds, path)
+    }
+
     pub fn stacktrace(&self, config: &Config, env: &Env) -> Result<(), StacktraceError> {
         // -d = print and exit
         let logcat_command = adb::adb(env, &self.serial_no).with_args(&["logcat", "-d"]);
@@ -378,19 +645,66 @@ impl<'a> Device<'a> {
                 util::prepend_to_path(env.ndk.home().display(), env.path()),
             )
             .with_arg("-sym")
-            .with_arg(
+            .with_arg({
+                let symbols_dir = strip::symbols_dir(config, *self.target);
                 config
                     .app()
                     // ndk-stack can't seem to handle spaces in args, no matter
                     // how I try to quote or escape them... so, instead of
                     // mandating that the entire path not contain spaces, we'll
                     // just use a relative path!
-                    .unprefix_path(jnilibs::path(config, *self.target))
-                    .expect("developer error: jnilibs subdir not prefixed"),
-            );
+                    .unprefix_path(&symbols_dir)
+                    .unwrap_or(symbols_dir)
+            });
         if !util::pipe(logcat_command, stack_command).map_err(StacktraceError::PipeFailed)? {
             println!("  -- no stacktrace --");
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        serial_no,
+        transport,
+        case("0a388e93", Transport::Usb),
+        case("emulator-5554", Transport::Emulator),
+        case("192.168.1.50:5555", Transport::Tcp),
+        case("adb-28201FDH2004RV-vWxvFz._adb-tls-connect._tcp.", Transport::Tcp)
+    )]
+    fn detects_transport(serial_no: &str, transport: Transport) {
+        assert_eq!(Transport::from_serial_no(serial_no), transport);
+    }
+
+    #[rstest(
+        policy,
+        pidof,
+        expected,
+        case(RestartPolicy::Always, Some("4821\n"), true),
+        case(RestartPolicy::Always, None, true),
+        case(RestartPolicy::IfNotRunning, Some("4821\n"), false),
+        case(RestartPolicy::IfNotRunning, Some(""), true),
+        // The lookup failed, so we can't say it's running.
+        case(RestartPolicy::IfNotRunning, None, true),
+        case(RestartPolicy::Never, Some(""), false),
+        case(RestartPolicy::Never, None, false)
+    )]
+    fn decides_whether_to_start(policy: RestartPolicy, pidof: Option<&str>, expected: bool) {
+        let pids = pidof.map(|output| adb::pidof::parse_pids(output, "com.example.tictac"));
+        assert_eq!(policy.should_start(pids.as_deref()), expected);
+    }
+
+    #[test]
+    fn parses_restart_policy() {
+        assert_eq!(
+            "if-not-running".parse::<RestartPolicy>(),
+            Ok(RestartPolicy::IfNotRunning)
+        );
+        assert_eq!("NEVER".parse::<RestartPolicy>(), Ok(RestartPolicy::Never));
+        assert!("sometimes".parse::<RestartPolicy>().is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! Just enough ELF to list the symbols a shared lib exports, so checking a
+//! lib doesn't depend on the NDK's `readelf` or on the format of its output.
+
+use crate::util::cli::{Report, Reportable};
+use std::{
+    collections::BTreeSet,
+    convert::{TryFrom as _, TryInto as _},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+const MAGIC: &[u8] = b"\x7fELF";
+const CLASS_32: u8 = 1;
+const CLASS_64: u8 = 2;
+const DATA_LE: u8 = 1;
+const DATA_BE: u8 = 2;
+const SHT_DYNSYM: u32 = 11;
+const SHN_UNDEF: u16 = 0;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STV_DEFAULT: u8 = 0;
+const STV_PROTECTED: u8 = 3;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to read {path:?}: {cause}")]
+    ReadFailed { path: PathBuf, cause: io::Error },
+    #[error("{path:?} isn't an ELF file")]
+    NotElf { path: PathBuf },
+    #[error("{path:?} is truncated or malformed")]
+    Malformed { path: PathBuf },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        Report::error("Failed to get list of exported symbols", self)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn slice(&self, offset: u64, len: u64) -> Option<&'a [u8]> {
+        let start = usize::try_from(offset).ok()?;
+        let end = start.checked_add(usize::try_from(len).ok()?)?;
+        self.bytes.get(start..end)
+    }
+
+    fn u16(&self, offset: u64) -> Option<u16> {
+        let bytes = self.slice(offset, 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: u64) -> Option<u32> {
+        let bytes = self.slice(offset, 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u64(&self, offset: u64) -> Option<u64> {
+        let bytes = self.slice(offset, 8)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    /// An address or offset, which is as wide as the class.
+    fn word(&self, offset: u64) -> Option<u64> {
+        if self.is_64 {
+            self.u64(offset)
+        } else {
+            self.u32(offset).map(u64::from)
+        }
+    }
+
+    fn str(&self, offset: u64) -> Option<&'a str> {
+        let bytes = self.bytes.get(usize::try_from(offset).ok()?..)?;
+        let len = bytes.iter().position(|b| *b == 0)?;
+        std::str::from_utf8(&bytes[..len]).ok()
+    }
+}
+
+struct Section {
+    kind: u32,
+    link: u32,
+    offset: u64,
+    size: u64,
+    entry_size: u64,
+}
+
+impl<'a> Reader<'a> {
+    fn section(&self, table: u64, entry_size: u64, index: u64) -> Option<Section> {
+        let base = table.checked_add(index.checked_mul(entry_size)?)?;
+        let (offset, size, entry_size_at) = if self.is_64 {
+            (base + 0x18, base + 0x20, base + 0x38)
+        } else {
+            (base + 0x10, base + 0x14, base + 0x24)
+        };
+        Some(Section {
+            kind: self.u32(base + 0x04)?,
+            link: self.u32(base + if self.is_64 { 0x28 } else { 0x18 })?,
+            offset: self.word(offset)?,
+            size: self.word(size)?,
+            entry_size: self.word(entry_size_at)?,
+        })
+    }
+
+    fn sections(&self) -> Option<Vec<Section>> {
+        let (table, entry_size, count) = if self.is_64 {
+            (self.u64(0x28)?, self.u16(0x3a)?, self.u16(0x3c)?)
+        } else {
+            (u64::from(self.u32(0x20)?), self.u16(0x2e)?, self.u16(0x30)?)
+        };
+        (0..u64::from(count))
+            .map(|index| self.section(table, u64::from(entry_size), index))
+            .collect()
+    }
+
+    /// The names of the defined, visible, global or weak symbols in
+    /// `.dynsym`. A lib without one exports nothing.
+    fn exported(&self) -> Option<BTreeSet<String>> {
+        let sections = self.sections()?;
+        let dynsym = match sections.iter().find(|section| section.kind == SHT_DYNSYM) {
+            Some(dynsym) => dynsym,
+            None => return Some(BTreeSet::new()),
+        };
+        let strtab = sections.get(usize::try_from(dynsym.link).ok()?)?;
+        if dynsym.entry_size == 0 {
+            return None;
+        }
+        let mut exported = BTreeSet::new();
+        for index in 0..dynsym.size / dynsym.entry_size {
+            let sym = dynsym.offset + index * dynsym.entry_size;
+            // The fields are in a different order for each class.
+            let (info, other, shndx) = if self.is_64 {
+                (sym + 0x04, sym + 0x05, sym + 0x06)
+            } else {
+                (sym + 0x0c, sym + 0x0d, sym + 0x0e)
+            };
+            let info = *self.slice(info, 1)?.first()?;
+            let visibility = *self.slice(other, 1)?.first()? & 0x3;
+            if self.u16(shndx)? == SHN_UNDEF
+                || !matches!(info >> 4, STB_GLOBAL | STB_WEAK)
+                || !matches!(visibility, STV_DEFAULT | STV_PROTECTED)
+            {
+                continue;
+            }
+            let name = self.str(strtab.offset + u64::from(self.u32(sym)?))?;
+            if !name.is_empty() {
+                exported.insert(name.to_owned());
+            }
+        }
+        Some(exported)
+    }
+}
+
+/// The symbols the ELF file in `bytes` defines and other libs can see.
+pub fn parse_exported_symbols(path: &Path, bytes: &[u8]) -> Result<BTreeSet<String>, Error> {
+    if !bytes.starts_with(MAGIC) || bytes.len() < 6 {
+        return Err(Error::NotElf {
+            path: path.to_owned(),
+        });
+    }
+    let reader = Reader {
+        bytes,
+        is_64: match bytes[4] {
+            CLASS_32 => false,
+            CLASS_64 => true,
+            _ => {
+                return Err(Error::Malformed {
+                    path: path.to_owned(),
+                })
+            }
+        },
+        big_endian: match bytes[5] {
+            DATA_LE => false,
+            DATA_BE => true,
+            _ => {
+                return Err(Error::Malformed {
+                    path: path.to_owned(),
+                })
+            }
+        },
+    };
+    reader.exported().ok_or_else(|| Error::Malformed {
+        path: path.to_owned(),
+    })
+}
+
+pub fn exported_symbols(path: &Path) -> Result<BTreeSet<String>, Error> {
+    let bytes = fs::read(path).map_err(|cause| Error::ReadFailed {
+        path: path.to_owned(),
+        cause,
+    })?;
+    parse_exported_symbols(path, &bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/android/fixtures")
+            .join(name)
+    }
+
+    // The fixtures are built from the sources next to them with
+    // `gcc -shared -fPIC -nostdlib -s`, and `-m32` for the 32-bit one.
+    #[rstest(
+        name,
+        expected,
+        case(
+            "libnative_activity.so",
+            &["ANativeActivity_onCreate", "keep", "rust_eh_personality"]
+        ),
+        case("libjni.so", &["JNI_OnLoad"]),
+        case("libjni32.so", &["JNI_OnLoad"])
+    )]
+    fn finds_exported_symbols(name: &str, expected: &[&str]) {
+        assert_eq!(
+            exported_symbols(&fixture(name)).unwrap(),
+            expected.iter().map(|symbol| symbol.to_string()).collect()
+        );
+    }
+
+    #[test]
+    fn rejects_non_elf_files() {
+        let path = fixture("native_activity.c");
+        assert!(matches!(
+            exported_symbols(&path),
+            Err(Error::NotElf { path: not_elf }) if not_elf == path
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = fs::read(fixture("libjni.so")).unwrap();
+        assert!(matches!(
+            parse_exported_symbols(Path::new("libjni.so"), &bytes[..64]),
+            Err(Error::Malformed { .. })
+        ));
+    }
+}
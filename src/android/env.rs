@@ -4,9 +4,15 @@ use super::{
 };
 use crate::{
     env::{Env as CoreEnv, Error as CoreError, ExplicitEnv},
-    util::cli::{Report, Reportable},
+    util::{
+        self,
+        cli::{Report, Reportable},
+    },
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
 };
-use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -16,8 +22,8 @@ pub enum Error {
     // TODO: we should be nice and provide a platform-specific suggestion
     #[error("Have you installed the Android SDK? The `ANDROID_SDK_ROOT` environment variable isn't set, and is required: {0}")]
     AndroidSdkRootNotSet(#[from] std::env::VarError),
-    #[error("Have you installed the Android SDK? The `ANDROID_SDK_ROOT` environment variable is set, but doesn't point to an existing directory.")]
-    AndroidSdkRootNotADir,
+    #[error("Have you installed the Android SDK? The `{var}` environment variable is set to {raw:?}, but that doesn't point to an existing directory.")]
+    AndroidSdkRootNotADir { var: &'static str, raw: String },
     #[error(transparent)]
     NdkEnvError(#[from] ndk::Error),
 }
@@ -38,6 +44,100 @@ impl Error {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum BuildToolsError {
+    #[error("Failed to read build-tools directory {dir:?}: {source}")]
+    ReadFailed { dir: PathBuf, source: io::Error },
+    #[error("No Android SDK build-tools are installed in {dir:?}{}. You can install them using the SDK Manager in Android Studio, or by running `sdkmanager \"build-tools;<version>\"`.", if .entries.is_empty() { String::new() } else { format!(" (found only {})", .entries.join(", ")) })]
+    NoneInstalled { dir: PathBuf, entries: Vec<String> },
+    #[error("`{tool}` wasn't found in build-tools directory {dir:?}.")]
+    ToolMissing { dir: PathBuf, tool: String },
+}
+
+#[derive(Debug, Error)]
+pub enum LicensesError {
+    #[error("{path:?} doesn't exist, so the Android SDK licenses haven't been accepted.")]
+    Missing { path: PathBuf },
+    #[error("Failed to read {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("{path:?} doesn't contain any license hashes, so the Android SDK licenses haven't been accepted.")]
+    NoHashes { path: PathBuf },
+}
+
+impl Reportable for LicensesError {
+    fn report(&self) -> Report {
+        match self {
+            Self::ReadFailed { .. } => Report::error("Failed to check Android SDK licenses", self),
+            _ => Report::action_request(
+                "Please run `sdkmanager --licenses` to accept the Android SDK licenses",
+                format!("{} Gradle would otherwise fail partway through the build. `sdkmanager` lives in `cmdline-tools/latest/bin` inside your SDK.", self),
+            ),
+        }
+    }
+}
+
+static LICENSE_FILE_NAME: &str = "android-sdk-license";
+
+/// Gradle refuses to build until the SDK license has been accepted, which
+/// leaves its hash in `licenses/android-sdk-license`. This only checks that
+/// some hash is there, since new license versions get new hashes.
+fn check_licenses(sdk_root: &Path) -> Result<(), LicensesError> {
+    let path = sdk_root.join("licenses").join(LICENSE_FILE_NAME);
+    if !path.is_file() {
+        return Err(LicensesError::Missing { path });
+    }
+    let contents = fs::read_to_string(&path).map_err(|source| LicensesError::ReadFailed {
+        path: path.clone(),
+        source,
+    })?;
+    let has_hash = contents.lines().any(|line| {
+        let line = line.trim();
+        line.len() == 40 && line.chars().all(|c| c.is_ascii_hexdigit())
+    });
+    if has_hash {
+        Ok(())
+    } else {
+        Err(LicensesError::NoHashes { path })
+    }
+}
+
+// Env files that don't go through a shell leave `~` unexpanded, and trailing
+// slashes trip up some of the tools we hand this to.
+fn normalize_sdk_root(var: &'static str, raw: String) -> Result<PathBuf, Error> {
+    let normalized = util::expand_home(&raw)
+        .ok()
+        .and_then(|path| path.canonicalize().ok())
+        .filter(|path| path.is_dir());
+    // `canonicalize` gives verbatim paths on Windows, which plenty of tools
+    // can't handle.
+    #[cfg(windows)]
+    let normalized = normalized.map(|path| {
+        path.to_str()
+            .and_then(|path| path.strip_prefix(r"\\?\"))
+            .map(PathBuf::from)
+            .unwrap_or(path)
+    });
+    normalized.ok_or(Error::AndroidSdkRootNotADir { var, raw })
+}
+
+impl Reportable for BuildToolsError {
+    fn report(&self) -> Report {
+        Report::error("Failed to find Android SDK build-tools", self)
+    }
+}
+
+// Build-tools directories are named after their version, i.e. `33.0.2` or
+// `31.0.0-rc1`; release versions sort after their release candidates.
+fn build_tools_version(name: &str) -> Option<(Vec<u32>, bool)> {
+    let mut parts = name.splitn(2, '-');
+    let version = parts
+        .next()?
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    Some((version, parts.next().is_none()))
+}
+
 #[derive(Debug)]
 pub struct Env {
     base: CoreEnv,
@@ -53,31 +153,11 @@ impl Env {
     pub fn from_env(base: CoreEnv) -> Result<Self, Error> {
         let sdk_root = std::env::var("ANDROID_SDK_ROOT")
             .map_err(Error::AndroidSdkRootNotSet)
-            .map(PathBuf::from)
-            .and_then(|sdk_root| {
-                if sdk_root.is_dir() {
-                    Ok(sdk_root)
-                } else {
-                    Err(Error::AndroidSdkRootNotADir)
-                }
-            })
+            .and_then(|raw| normalize_sdk_root("ANDROID_SDK_ROOT", raw))
             .or_else(|err| {
                 if let Some(android_home) = std::env::var("ANDROID_HOME")
                     .ok()
-                    .map(PathBuf::from)
-                    .filter(|android_home| android_home.is_dir())
-                {
-                    log::warn!("`ANDROID_SDK_ROOT` isn't set; falling back to `ANDROID_HOME`, which is deprecated");
-                    Ok(android_home)
-                } else {
-                    Err(err)
-                }
-            })
-            .or_else(|err| {
-                if let Some(android_home) = std::env::var("ANDROID_HOME")
-                    .ok()
-                    .map(PathBuf::from)
-                    .filter(|android_home| android_home.is_dir())
+                    .and_then(|raw| normalize_sdk_root("ANDROID_HOME", raw).ok())
                 {
                     log::warn!("`ANDROID_SDK_ROOT` isn't set; falling back to `ANDROID_HOME`, which is deprecated");
                     Ok(android_home)
@@ -104,6 +184,62 @@ impl Env {
         SourceProps::from_path(Path::new(self.sdk_root()).join("tools/source.properties"))
             .map(|props| props.pkg.revision)
     }
+
+    pub fn check_licenses(&self) -> Result<(), LicensesError> {
+        check_licenses(&self.sdk_root)
+    }
+
+    /// The newest installed `build-tools/<version>` directory.
+    pub fn build_tools_dir(&self) -> Result<PathBuf, BuildToolsError> {
+        let dir = self.sdk_root.join("build-tools");
+        let mut unrecognized = Vec::new();
+        let newest = fs::read_dir(&dir)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .map_err(|source| BuildToolsError::ReadFailed {
+                dir: dir.clone(),
+                source,
+            })?
+            .into_iter()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let version = build_tools_version(&name);
+                if version.is_none() {
+                    unrecognized.push(name);
+                }
+                version.map(|version| (version, entry.path()))
+            })
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, path)| path);
+        newest.ok_or(BuildToolsError::NoneInstalled {
+            dir,
+            entries: unrecognized,
+        })
+    }
+
+    /// The path to a tool within the newest installed build-tools, i.e.
+    /// `apksigner` or `zipalign`.
+    pub fn build_tool_path(&self, tool: &str) -> Result<PathBuf, BuildToolsError> {
+        let dir = self.build_tools_dir()?;
+        find_tool(&dir, tool).ok_or_else(|| BuildToolsError::ToolMissing {
+            dir,
+            tool: tool.to_owned(),
+        })
+    }
+}
+
+// On Windows, the binaries are `.exe`s and the scripts (i.e. `apksigner`)
+// are `.bat`s.
+#[cfg(windows)]
+const TOOL_EXTENSIONS: &[&str] = &["exe", "bat"];
+#[cfg(not(windows))]
+const TOOL_EXTENSIONS: &[&str] = &[""];
+
+fn find_tool(dir: &Path, tool: &str) -> Option<PathBuf> {
+    TOOL_EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(tool).with_extension(extension))
+        .find(|path| path.is_file())
 }
 
 impl ExplicitEnv for Env {
@@ -116,3 +252,81 @@ impl ExplicitEnv for Env {
         envs
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    #[test]
+    fn normalizes_trailing_slash() {
+        let sdk = TempDirGuard::new().unwrap();
+        let raw = format!("{}/", sdk.display());
+        assert_eq!(
+            normalize_sdk_root("ANDROID_SDK_ROOT", raw).unwrap(),
+            sdk.canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn normalizes_dot_segments() {
+        let sdk = TempDirGuard::new().unwrap();
+        fs::create_dir_all(sdk.join("platforms")).unwrap();
+        let raw = format!("{}/platforms/..", sdk.display());
+        assert_eq!(
+            normalize_sdk_root("ANDROID_SDK_ROOT", raw).unwrap(),
+            sdk.canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn keeps_raw_value_for_errors() {
+        let raw = "~/cargo-mobile-definitely-not-an-sdk".to_owned();
+        assert!(matches!(
+            normalize_sdk_root("ANDROID_SDK_ROOT", raw.clone()),
+            Err(Error::AndroidSdkRootNotADir { var: "ANDROID_SDK_ROOT", raw: err_raw }) if err_raw == raw
+        ));
+    }
+
+    #[test]
+    fn finds_build_tools() {
+        let dir = TempDirGuard::new().unwrap();
+        #[cfg(windows)]
+        let names = ["apksigner.bat", "zipalign.exe"];
+        #[cfg(not(windows))]
+        let names = ["apksigner", "zipalign"];
+        for name in &names {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(find_tool(&dir, "apksigner"), Some(dir.join(names[0])));
+        assert_eq!(find_tool(&dir, "zipalign"), Some(dir.join(names[1])));
+        assert_eq!(find_tool(&dir, "aapt2"), None);
+    }
+
+    #[test]
+    fn accepted_licenses() {
+        let sdk = TempDirGuard::new().unwrap();
+        fs::create_dir_all(sdk.join("licenses")).unwrap();
+        fs::write(
+            sdk.join("licenses").join(LICENSE_FILE_NAME),
+            "\n24333f8a63b6825ea9c5514f83c2829b004d1fee",
+        )
+        .unwrap();
+        assert!(check_licenses(&sdk).is_ok());
+    }
+
+    #[test]
+    fn missing_licenses() {
+        let sdk = TempDirGuard::new().unwrap();
+        assert!(matches!(
+            check_licenses(&sdk),
+            Err(LicensesError::Missing { .. })
+        ));
+        fs::create_dir_all(sdk.join("licenses")).unwrap();
+        fs::write(sdk.join("licenses").join(LICENSE_FILE_NAME), "\n").unwrap();
+        assert!(matches!(
+            check_licenses(&sdk),
+            Err(LicensesError::NoHashes { .. })
+        ));
+    }
+}
@@ -0,0 +1 @@
+int JNI_OnLoad(void *vm, void *reserved) { return 0x00010006; }
@@ -0,0 +1,6 @@
+void ALooper_prepare(void);
+__attribute__((visibility("default"))) void ANativeActivity_onCreate(void) { ALooper_prepare(); }
+__attribute__((weak)) void rust_eh_personality(void) {}
+__attribute__((visibility("hidden"))) void __rust_alloc(void) {}
+static void internal_helper(void) {}
+void (*keep)(void) = internal_helper;
@@ -0,0 +1,335 @@
+//! Builds gradle invocations. Anything the generated `build.gradle.kts` needs
+//! from us at build time is passed as a `-P` project property named in
+//! `property`, which is the only place those names live on our side.
+
+use super::{config::Config, env::Env, gradlew, jnilibs::Strategy};
+use crate::{
+    env::ExplicitEnv as _,
+    opts::{NoiseLevel, Profile},
+    util::{self, events, timings},
+};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt::{self, Display},
+    io::{self, Write as _},
+};
+
+/// The project properties the generated `build.gradle.kts` reads. Each one
+/// is optional there, with a default for builds from Android Studio.
+pub mod property {
+    /// Overrides `buildDir`, so builds can be compared side by side.
+    pub static BUILD_DIR: &str = "cargoMobileBuildDir";
+    /// Drops file timestamps and the dependency metadata block, for
+    /// reproducible builds. Passed whenever `SOURCE_DATE_EPOCH` is set, and
+    /// always by the reproducibility check.
+    pub static SOURCE_DATE_EPOCH: &str = "sourceDateEpoch";
+    /// Overrides `versionCode`, which otherwise stays whatever
+    /// `android.version-code` was when the project was generated.
+    pub static VERSION_CODE: &str = "cargoMobileVersionCode";
+    /// Where cargo's target dir is, for `jnilibs-strategy = "direct"`.
+    pub static TARGET_DIR: &str = "cargoMobileTargetDir";
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TaskKind {
+    Assemble,
+    Bundle,
+}
+
+// Gradle only capitalizes the first letter of each part of a variant name, so
+// `x86_64` stays `X86_64` instead of becoming `X8664`.
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// The task that builds `flavor`'s APK or AAB, i.e. `assembleAarch64Debug`.
+pub fn task(kind: TaskKind, flavor: &str, profile: Profile) -> String {
+    let prefix = match kind {
+        TaskKind::Assemble => "assemble",
+        // Unqualified, this would also bundle any asset packs.
+        TaskKind::Bundle => ":app:bundle",
+    };
+    format!(
+        "{}{}{}",
+        prefix,
+        capitalize(flavor),
+        capitalize(profile.as_str())
+    )
+}
+
+fn property_name_valid(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Only for showing the command, since it's never run through a shell.
+fn quote(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_=./:,+@%".contains(c))
+    {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GradleInvocation {
+    tasks: Vec<String>,
+    properties: BTreeMap<&'static str, String>,
+    noise_level: Option<NoiseLevel>,
+}
+
+impl GradleInvocation {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_task(mut self, task: impl Into<String>) -> Self {
+        self.tasks.push(task.into());
+        self
+    }
+
+    /// An option for the task added last, i.e. `--configuration` for
+    /// `dependencies`.
+    pub fn with_task_option(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.tasks.push(format!("--{}", name));
+        self.tasks.push(value.into());
+        self
+    }
+
+    /// `name` should be one of `property`. Setting a property twice keeps the
+    /// last value.
+    pub fn with_property(mut self, name: &'static str, value: impl Display) -> Self {
+        assert!(
+            property_name_valid(name),
+            "developer error: gradle property name {:?} is invalid",
+            name
+        );
+        let value = value.to_string();
+        // Gradle has no way to escape these, and they'd break the template's
+        // Kotlin anyway.
+        assert!(
+            !value.contains(&['\n', '\r', '\0'][..]),
+            "developer error: gradle property {:?} has a value with line breaks or NULs",
+            name
+        );
+        self.properties.insert(name, value);
+        self
+    }
+
+    pub fn with_noise_level(mut self, noise_level: NoiseLevel) -> Self {
+        self.noise_level = Some(noise_level);
+        self
+    }
+
+    /// Adds what every build we start needs.
+    pub fn with_config(self, config: &Config) -> Self {
+        let mut invocation = self.with_property(property::VERSION_CODE, config.version_code());
+        if config.jnilibs_strategy() == Strategy::Direct {
+            invocation =
+                invocation.with_property(property::TARGET_DIR, config.cargo_target_dir().display());
+        }
+        match util::source_date_epoch() {
+            Some(epoch) => invocation.with_property(property::SOURCE_DATE_EPOCH, epoch),
+            None => invocation,
+        }
+    }
+
+    /// Everything after `gradlew --project-dir <dir>`.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = self.tasks.clone();
+        args.extend(
+            self.properties
+                .iter()
+                .map(|(name, value)| format!("-P{}={}", name, value)),
+        );
+        if let Some(noise_level) = self.noise_level {
+            args.push(
+                match noise_level {
+                    NoiseLevel::Polite => "--warn",
+                    NoiseLevel::LoudAndProud => "--info",
+                    NoiseLevel::FranklyQuitePedantic => "--debug",
+                }
+                .to_owned(),
+            );
+        }
+        args
+    }
+
+    pub fn command(&self, config: &Config, env: &Env) -> bossy::Command {
+        let gradlew_path = gradlew::prepare(&config.project_dir());
+        bossy::Command::pure(&gradlew_path)
+            .with_env_vars(env.explicit_env())
+            .with_arg("--project-dir")
+            .with_arg(config.project_dir())
+            .with_args(self.args())
+    }
+}
+
+/// A failed build, along with everything it printed to stderr, which is
+/// where gradle explains itself.
+#[derive(Debug)]
+pub struct BuildError {
+    pub cause: bossy::Error,
+    pub stderr: String,
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.cause)
+    }
+}
+
+/// Passes everything through to our stderr, keeping a copy.
+struct Tee<'a>(&'a mut Vec<u8>);
+
+impl Write for Tee<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stderr().write_all(buf)?;
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// Runs a build as a step named `name`. Its stderr is passed through as it
+/// comes, and also kept so failures can be explained.
+pub fn run_build(name: impl Into<String>, mut command: bossy::Command) -> Result<(), BuildError> {
+    timings::step(name, || {
+        if events::enabled() {
+            return events::run_and_wait(command).map_err(|cause| {
+                let stderr = cause
+                    .output()
+                    .map(|output| String::from_utf8_lossy(output.stderr()).into_owned())
+                    .unwrap_or_default();
+                BuildError { cause, stderr }
+            });
+        }
+        let mut handle = command
+            .with_stderr_piped()
+            .run()
+            .map_err(|cause| BuildError {
+                cause,
+                stderr: Default::default(),
+            })?;
+        let mut stderr = Vec::new();
+        // Only stderr is piped, so reading it to the end can't stall anything.
+        if let Some(pipe) = handle.stderr() {
+            if let Err(err) = io::copy(pipe, &mut Tee(&mut stderr)) {
+                log::error!("failed to pass gradle's stderr through: {}", err);
+            }
+        }
+        handle
+            .wait_for_output()
+            .map(|_| ())
+            .map_err(|cause| BuildError {
+                cause,
+                stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            })
+    })
+}
+
+impl Display for GradleInvocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gradle")?;
+        for arg in self.args() {
+            write!(f, " {}", quote(&arg))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        kind,
+        flavor,
+        profile,
+        expected,
+        case(TaskKind::Assemble, "aarch64", Profile::Debug, "assembleAarch64Debug"),
+        case(
+            TaskKind::Assemble,
+            "x86_64",
+            Profile::Release,
+            "assembleX86_64Release"
+        ),
+        case(TaskKind::Bundle, "armv7", Profile::Release, ":app:bundleArmv7Release")
+    )]
+    fn names_tasks(kind: TaskKind, flavor: &str, profile: Profile, expected: &str) {
+        assert_eq!(task(kind, flavor, profile), expected);
+    }
+
+    #[test]
+    fn builds_args() {
+        let invocation = GradleInvocation::new()
+            .with_task(task(TaskKind::Assemble, "aarch64", Profile::Release))
+            .with_property(property::VERSION_CODE, 42)
+            .with_property(property::BUILD_DIR, "/tmp/my build")
+            .with_noise_level(NoiseLevel::LoudAndProud);
+        assert_eq!(
+            invocation.args(),
+            vec![
+                "assembleAarch64Release",
+                "-PcargoMobileBuildDir=/tmp/my build",
+                "-PcargoMobileVersionCode=42",
+                "--info",
+            ]
+        );
+        assert_eq!(
+            invocation.to_string(),
+            "gradle assembleAarch64Release '-PcargoMobileBuildDir=/tmp/my build' -PcargoMobileVersionCode=42 --info"
+        );
+    }
+
+    #[test]
+    fn passes_task_options() {
+        let invocation = GradleInvocation::new()
+            .with_task(":app:dependencies")
+            .with_task_option("configuration", "releaseRuntimeClasspath");
+        assert_eq!(
+            invocation.args(),
+            vec![
+                ":app:dependencies",
+                "--configuration",
+                "releaseRuntimeClasspath"
+            ]
+        );
+    }
+
+    #[test]
+    fn later_properties_win() {
+        let invocation = GradleInvocation::new()
+            .with_property(property::VERSION_CODE, 1)
+            .with_property(property::VERSION_CODE, 2);
+        assert_eq!(invocation.args(), vec!["-PcargoMobileVersionCode=2"]);
+    }
+
+    #[test]
+    fn quotes_for_display() {
+        assert_eq!(
+            quote("-PsourceDateEpoch=1700000000"),
+            "-PsourceDateEpoch=1700000000"
+        );
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote(""), "''");
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_line_breaks() {
+        GradleInvocation::new().with_property(property::BUILD_DIR, "a\nb");
+    }
+}
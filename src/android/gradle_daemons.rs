@@ -0,0 +1,326 @@
+//! Gradle daemons outlive the builds that started them, and a new one is
+//! spawned whenever the JDK or gradle's arguments change, so they pile up.
+//! Besides the memory, a stray one can hold a cache lock long enough that
+//! the next build gives up waiting for it.
+
+use super::{
+    config::Config,
+    env::Env,
+    gradle::{BuildError, GradleInvocation},
+};
+use crate::util::cli::{Report, Reportable};
+use once_cell_regex::regex;
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+};
+use thiserror::Error;
+
+/// What to run to get rid of them.
+pub static STOP_COMMAND: &str = "cargo android gradle-daemons --stop";
+
+// How gradle says it gave up on a lock another daemon is sitting on, i.e.
+// "Timeout waiting to lock journal cache (~/.gradle/caches/journal-1)".
+static LOCK_TIMEOUT_SIGNATURE: &str = "Timeout waiting to lock";
+
+pub fn is_lock_timeout(output: &str) -> bool {
+    output.contains(LOCK_TIMEOUT_SIGNATURE)
+}
+
+/// The hint that goes with a failed build, if gradle's stderr says it failed
+/// on a lock.
+pub fn lock_timeout_hint(stderr: &str) -> Option<String> {
+    if is_lock_timeout(stderr) {
+        Some(format!(
+            "Another gradle daemon is holding a lock this build needed. Run `{}` and try again.",
+            STOP_COMMAND
+        ))
+    } else {
+        None
+    }
+}
+
+/// A failed gradle build, along with the hint for it if we have one.
+#[derive(Debug)]
+pub struct BuildFailure<'a>(pub &'a BuildError);
+
+impl Display for BuildFailure<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+        if let Some(hint) = lock_timeout_hint(&self.0.stderr) {
+            write!(f, "\n{}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum State {
+    Busy,
+    Idle,
+    /// Stopped daemons are listed for a while after they're gone.
+    Stopped,
+    Other(String),
+}
+
+impl State {
+    fn parse(s: &str) -> Self {
+        match s {
+            "BUSY" => Self::Busy,
+            "IDLE" => Self::Idle,
+            "STOPPED" | "STOPPING" => Self::Stopped,
+            _ => Self::Other(s.to_ascii_lowercase()),
+        }
+    }
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Busy => write!(f, "busy"),
+            Self::Idle => write!(f, "idle"),
+            Self::Stopped => write!(f, "stopped"),
+            Self::Other(state) => write!(f, "{}", state),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Daemon {
+    pub pid: u32,
+    pub state: State,
+    /// The gradle version for running daemons, or why it stopped otherwise.
+    pub info: String,
+}
+
+impl Daemon {
+    pub fn running(&self) -> bool {
+        self.state != State::Stopped
+    }
+}
+
+/// Reads the table `gradlew --status` prints, which looks like this:
+///
+/// ```text
+///    PID STATUS   INFO
+///  81067 BUSY     7.5
+///  79323 STOPPED  (stop command received)
+/// ```
+pub fn parse_status(output: &str) -> Vec<Daemon> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let caps = regex!(r"^\s*(\d+)\s+([A-Z_]+)\s+(.*?)\s*$").captures(line)?;
+            Some(Daemon {
+                pid: caps[1].parse().ok()?,
+                state: State::parse(&caps[2]),
+                info: caps[3].to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// How many `gradlew --stop` says it stopped, i.e. "2 Daemons stopped".
+pub fn parse_stopped(output: &str) -> Option<usize> {
+    if output.contains("No Gradle daemons are running") {
+        return Some(0);
+    }
+    regex!(r"(\d+) Daemons? stopped")
+        .captures(output)
+        .and_then(|caps| caps[1].parse().ok())
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Summary {
+    pub busy: usize,
+    pub idle: usize,
+    pub other: usize,
+    pub versions: BTreeSet<String>,
+}
+
+impl Summary {
+    /// Past this many, the doctor suggests stopping them.
+    pub const TOO_MANY: usize = 3;
+
+    pub fn new(daemons: &[Daemon]) -> Self {
+        let mut summary = Self::default();
+        for daemon in daemons.iter().filter(|daemon| daemon.running()) {
+            match daemon.state {
+                State::Busy => summary.busy += 1,
+                State::Idle => summary.idle += 1,
+                _ => summary.other += 1,
+            }
+            summary.versions.insert(daemon.info.clone());
+        }
+        summary
+    }
+
+    pub fn running(&self) -> usize {
+        self.busy + self.idle + self.other
+    }
+
+    pub fn too_many(&self) -> bool {
+        self.running() > Self::TOO_MANY
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.running() {
+            0 => return write!(f, "No gradle daemons are running"),
+            1 => write!(f, "1 gradle daemon is running")?,
+            running => write!(f, "{} gradle daemons are running", running)?,
+        }
+        write!(
+            f,
+            " (v{}): {} busy, {} idle",
+            self.versions
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", v"),
+            self.busy,
+            self.idle,
+        )?;
+        if self.other > 0 {
+            write!(f, ", {} in some other state", self.other)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to run `gradlew --status`: {0}")]
+    StatusFailed(bossy::Error),
+    #[error("Failed to run `gradlew --stop`: {0}")]
+    StopFailed(bossy::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::StatusFailed(_) => Report::error("Failed to get gradle daemon status", self),
+            Self::StopFailed(_) => Report::error("Failed to stop gradle daemons", self),
+        }
+    }
+}
+
+fn gradlew(config: &Config, env: &Env, flag: &str) -> bossy::Command {
+    GradleInvocation::new().command(config, env).with_arg(flag)
+}
+
+/// The daemons the generated project's gradle version would use. Gradle only
+/// lists daemons for its own version, so others won't show up here.
+pub fn status(config: &Config, env: &Env) -> Result<Vec<Daemon>, Error> {
+    gradlew(config, env, "--status")
+        .run_and_wait_for_str(parse_status)
+        .map_err(Error::StatusFailed)
+}
+
+/// Stops the daemons `status` would list, giving back how many there were if
+/// gradle said.
+pub fn stop(config: &Config, env: &Env) -> Result<Option<usize>, Error> {
+    gradlew(config, env, "--stop")
+        .run_and_wait_for_str(parse_stopped)
+        .map_err(Error::StopFailed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static STATUS_4_10: &str = "   PID STATUS   INFO
+ 58409 IDLE     4.10.3
+
+Only Daemons for the current Gradle version are displayed. See https://docs.gradle.org/4.10.3/userguide/gradle_daemon.html#sec:status
+";
+
+    static STATUS_7_5: &str = "Downloading https://services.gradle.org/distributions/gradle-7.5-bin.zip
+...........10%...........20%...........30%...........40%...........50%...........60%...........70%...........80%...........90%...........100%
+
+   PID STATUS   INFO
+ 81067 BUSY     7.5
+ 80709 IDLE     7.5
+ 80112 IDLE     7.5
+ 79323 STOPPED  (stop command received)
+ 78001 STOPPED  (by user or operating system)
+
+Only Daemons for the current Gradle version are displayed. See https://docs.gradle.org/7.5/userguide/gradle_daemon.html#sec:status
+";
+
+    static STATUS_8_NONE: &str = "No Gradle daemons are running.
+";
+
+    fn daemon(pid: u32, state: State, info: &str) -> Daemon {
+        Daemon {
+            pid,
+            state,
+            info: info.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parses_old_status() {
+        assert_eq!(
+            parse_status(STATUS_4_10),
+            vec![daemon(58409, State::Idle, "4.10.3")]
+        );
+    }
+
+    #[test]
+    fn parses_status_with_stopped_daemons() {
+        let daemons = parse_status(STATUS_7_5);
+        assert_eq!(
+            daemons,
+            vec![
+                daemon(81067, State::Busy, "7.5"),
+                daemon(80709, State::Idle, "7.5"),
+                daemon(80112, State::Idle, "7.5"),
+                daemon(79323, State::Stopped, "(stop command received)"),
+                daemon(78001, State::Stopped, "(by user or operating system)"),
+            ]
+        );
+        let summary = Summary::new(&daemons);
+        assert_eq!(summary.running(), 3);
+        assert!(!summary.too_many());
+        assert_eq!(
+            summary.to_string(),
+            "3 gradle daemons are running (v7.5): 1 busy, 2 idle"
+        );
+    }
+
+    #[test]
+    fn parses_status_with_nothing_running() {
+        let daemons = parse_status(STATUS_8_NONE);
+        assert!(daemons.is_empty());
+        assert_eq!(
+            Summary::new(&daemons).to_string(),
+            "No gradle daemons are running"
+        );
+    }
+
+    #[test]
+    fn counts_stopped_daemons() {
+        assert_eq!(
+            parse_stopped("Stopping Daemon(s)\n2 Daemons stopped\n"),
+            Some(2)
+        );
+        assert_eq!(
+            parse_stopped("Stopping Daemon(s)\n1 Daemon stopped\n"),
+            Some(1)
+        );
+        assert_eq!(parse_stopped(STATUS_8_NONE), Some(0));
+        assert_eq!(parse_stopped("Stopping Daemon(s)\n"), None);
+    }
+
+    #[test]
+    fn recognizes_lock_timeouts() {
+        assert!(is_lock_timeout(
+            "* What went wrong:\nTimeout waiting to lock journal cache (/home/me/.gradle/caches/journal-1). It is currently in use by another Gradle instance.\n"
+        ));
+        assert!(!is_lock_timeout(
+            "* What went wrong:\nExecution failed for task ':app:mergeDebugNativeLibs'.\n"
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+//! Repairs the gradle wrapper script before we run it, since a checkout
+//! that went through Windows can leave it without its executable bit or
+//! with CRLF line endings, and either way the shell refuses to run it.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+static BACKUP_EXTENSION: &str = "crlf-backup";
+
+/// Whether the shebang line ends with CRLF, which makes the kernel look for
+/// an interpreter called `sh\r`.
+pub fn has_crlf_shebang(contents: &[u8]) -> bool {
+    contents.starts_with(b"#!")
+        && contents
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(false, |end| end > 0 && contents[end - 1] == b'\r')
+}
+
+pub fn to_lf(contents: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(contents.len());
+    for (index, byte) in contents.iter().enumerate() {
+        if *byte != b'\r' || contents.get(index + 1) != Some(&b'\n') {
+            out.push(*byte);
+        }
+    }
+    out
+}
+
+pub fn backup_path(script: &Path) -> PathBuf {
+    script.with_extension(BACKUP_EXTENSION)
+}
+
+/// Rewrites `script` with LF endings, after copying the original next to it.
+/// Returns whether anything needed fixing.
+pub fn fix_line_endings(script: &Path) -> io::Result<bool> {
+    let contents = fs::read(script)?;
+    if !has_crlf_shebang(&contents) {
+        return Ok(false);
+    }
+    let backup = backup_path(script);
+    fs::copy(script, &backup)?;
+    fs::write(script, to_lf(&contents))?;
+    log::warn!(
+        "{:?} had CRLF line endings, so it was rewritten with LF endings; the original is at {:?}",
+        script,
+        backup
+    );
+    Ok(true)
+}
+
+/// Sets the executable bits on `script` if none are set. Returns whether
+/// anything needed fixing.
+#[cfg(unix)]
+pub fn fix_executable_bit(script: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt as _;
+    let mut permissions = fs::metadata(script)?.permissions();
+    let mode = permissions.mode();
+    if mode & 0o111 != 0 {
+        return Ok(false);
+    }
+    // Anyone who can read it gets to run it.
+    permissions.set_mode(mode | ((mode & 0o444) >> 2));
+    fs::set_permissions(script, permissions)?;
+    log::warn!("{:?} wasn't executable, so it was made executable", script);
+    Ok(true)
+}
+
+/// The wrapper script to run from `project_dir`, repaired if it needed it.
+/// Failing to repair it isn't fatal, since running it will fail with a
+/// clearer error anyway.
+pub fn prepare(project_dir: &Path) -> PathBuf {
+    let bat = project_dir.join("gradlew.bat");
+    if cfg!(windows) && bat.is_file() {
+        return bat;
+    }
+    let script = project_dir.join("gradlew");
+    if script.is_file() {
+        if let Err(err) = fix_line_endings(&script) {
+            log::warn!("failed to check line endings of {:?}: {}", script, err);
+        }
+        #[cfg(unix)]
+        {
+            if let Err(err) = fix_executable_bit(&script) {
+                log::warn!("failed to check permissions of {:?}: {}", script, err);
+            }
+        }
+    }
+    script
+}
+
+/// The distribution `gradle-wrapper.properties` points at, i.e.
+/// "gradle-7.5-bin", which is also what the wrapper unpacks it as.
+pub fn distribution_name(properties: &str) -> Option<&str> {
+    properties
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("distributionUrl"))
+        .filter_map(|rest| rest.trim_start().strip_prefix('='))
+        .filter_map(|url| url.trim().rsplit('/').next())
+        .find_map(|file| file.strip_suffix(".zip"))
+}
+
+/// `GRADLE_USER_HOME`, or where gradle puts it by default.
+pub fn gradle_user_home() -> Option<PathBuf> {
+    std::env::var_os("GRADLE_USER_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            crate::util::home_dir()
+                .ok()
+                .map(|home| home.join(".gradle"))
+        })
+}
+
+/// Whether the wrapper in `project_dir` can run without downloading its
+/// distribution first. The wrapper unpacks it into a dir named for a hash
+/// of the URL, and marks it done with a `.ok` file next to it.
+pub fn distribution_cached(project_dir: &Path, gradle_user_home: &Path) -> bool {
+    let properties = project_dir.join("gradle/wrapper/gradle-wrapper.properties");
+    let properties = match fs::read_to_string(&properties) {
+        Ok(properties) => properties,
+        Err(err) => {
+            log::info!("failed to read {:?}: {}", properties, err);
+            return false;
+        }
+    };
+    let name = match distribution_name(&properties) {
+        Some(name) => name,
+        None => return false,
+    };
+    let marker = format!("{}.zip.ok", name);
+    fs::read_dir(gradle_user_home.join("wrapper/dists").join(name))
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .any(|entry| entry.path().join(&marker).is_file())
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+
+    static LF: &str = "#!/usr/bin/env sh\n\n# Gradle start up script\nexec \"$JAVACMD\" \"$@\"\n";
+    static CRLF: &str =
+        "#!/usr/bin/env sh\r\n\r\n# Gradle start up script\r\nexec \"$JAVACMD\" \"$@\"\r\n";
+
+    #[rstest(
+        contents,
+        crlf,
+        case(LF, false),
+        case(CRLF, true),
+        case("#!/usr/bin/env sh\n# a stray\r\n", false),
+        case("no shebang\r\n", false),
+        case("", false)
+    )]
+    fn detects_crlf_shebang(contents: &str, crlf: bool) {
+        assert_eq!(has_crlf_shebang(contents.as_bytes()), crlf);
+    }
+
+    #[test]
+    fn converts_to_lf() {
+        assert_eq!(to_lf(CRLF.as_bytes()), LF.as_bytes());
+        assert_eq!(to_lf(b"lone\rcarriage\r"), b"lone\rcarriage\r");
+    }
+
+    #[test]
+    fn fixes_line_endings_with_backup() {
+        let dir = TempDirGuard::new().unwrap();
+        let script = dir.join("gradlew");
+        fs::write(&script, CRLF).unwrap();
+        assert!(fix_line_endings(&script).unwrap());
+        assert_eq!(fs::read_to_string(&script).unwrap(), LF);
+        assert_eq!(fs::read_to_string(backup_path(&script)).unwrap(), CRLF);
+        assert!(!fix_line_endings(&script).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fixes_executable_bit() {
+        use std::os::unix::fs::PermissionsExt as _;
+        let dir = TempDirGuard::new().unwrap();
+        let script = dir.join("gradlew");
+        fs::write(&script, LF).unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(fix_executable_bit(&script).unwrap());
+        assert_eq!(
+            fs::metadata(&script).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+        assert!(!fix_executable_bit(&script).unwrap());
+    }
+
+    static PROPERTIES: &str = "distributionBase=GRADLE_USER_HOME
+distributionPath=wrapper/dists
+distributionUrl=https\\://services.gradle.org/distributions/gradle-7.5-bin.zip
+zipStoreBase=GRADLE_USER_HOME
+";
+
+    #[test]
+    fn finds_distribution_name() {
+        assert_eq!(distribution_name(PROPERTIES), Some("gradle-7.5-bin"));
+        assert_eq!(
+            distribution_name("distributionBase=GRADLE_USER_HOME\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn checks_distribution_is_unpacked() {
+        let project = TempDirGuard::new().unwrap();
+        let home = TempDirGuard::new().unwrap();
+        fs::create_dir_all(project.join("gradle/wrapper")).unwrap();
+        fs::write(
+            project.join("gradle/wrapper/gradle-wrapper.properties"),
+            PROPERTIES,
+        )
+        .unwrap();
+        let dist = home.join("wrapper/dists/gradle-7.5-bin/9uukhhbclvbegdvsww0sn0xgo");
+        fs::create_dir_all(&dist).unwrap();
+        // Half-downloaded, so running it would still download it.
+        assert!(!distribution_cached(&project, &home));
+        fs::write(dist.join("gradle-7.5-bin.zip.ok"), "").unwrap();
+        assert!(distribution_cached(&project, &home));
+    }
+}
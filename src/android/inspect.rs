@@ -0,0 +1,558 @@
+//! Checks a built APK against the config it was supposedly built from, since
+//! a wrong application id, a missing lib for the device's ABI, or a
+//! debuggable release otherwise only shows up once it's on a device.
+
+use super::{config::Config, env::Env, reproducible, target::Target};
+use crate::{
+    env::ExplicitEnv as _,
+    opts::Profile,
+    util::cli::{Report, Reportable},
+};
+use colored::Colorize as _;
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum BadgingParseError {
+    PackageMissing,
+    FieldMissing { field: &'static str },
+    FieldInvalid { field: &'static str, value: String },
+}
+
+impl Display for BadgingParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PackageMissing => write!(f, "Output had no `package:` line."),
+            Self::FieldMissing { field } => write!(f, "Output had no {:?}.", field),
+            Self::FieldInvalid { field, value } => {
+                write!(f, "{:?} was {:?}, which isn't a number.", field, value)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum InspectError {
+    ApkMissing { path: PathBuf },
+    ReadFailed { path: PathBuf, cause: io::Error },
+    ZipInvalid(reproducible::ZipError),
+    BadgingFailed(bossy::Error),
+    BadgingParseFailed(BadgingParseError),
+    XmlTreeFailed(bossy::Error),
+    Mismatched { path: PathBuf, count: usize },
+}
+
+impl Reportable for InspectError {
+    fn report(&self) -> Report {
+        match self {
+            Self::ApkMissing { path } => Report::action_request(
+                format!("No APK at {:?}", path),
+                "Build one with `cargo android run` or Android Studio first, or pass `--apk` to point at it.",
+            ),
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to read APK at {:?}", path), cause)
+            }
+            Self::ZipInvalid(err) => Report::error("APK isn't a valid zip archive", err),
+            Self::BadgingFailed(err) => Report::error("Failed to run `aapt2 dump badging`", err),
+            Self::BadgingParseFailed(err) => {
+                Report::error("Failed to parse `aapt2 dump badging` output", err)
+            }
+            Self::XmlTreeFailed(err) => Report::error("Failed to run `aapt2 dump xmltree`", err),
+            Self::Mismatched { path, count } => Report::error(
+                format!("APK {:?} doesn't match your config", path),
+                format!(
+                    "Found {} problem(s), listed above. If you changed `{}` without re-running `cargo mobile init`, do that first; otherwise, check the generated `build.gradle.kts` for local changes.",
+                    count,
+                    crate::config::file_name(),
+                ),
+            ),
+        }
+    }
+}
+
+/// What `aapt2 dump badging` says about an APK.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Badging {
+    pub application_id: String,
+    pub version_code: u32,
+    pub version_name: String,
+    pub min_sdk: u32,
+    pub debuggable: bool,
+    /// Empty if there's no native code.
+    pub native_code: Vec<String>,
+}
+
+/// The `'`-quoted strings in `s`, in order.
+fn quoted(s: &str) -> Vec<&str> {
+    s.split('\'').skip(1).step_by(2).collect()
+}
+
+/// The value of `key='...'` in a badging line.
+fn attr<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let start = line.find(&format!(" {}='", key))? + key.len() + 3;
+    line[start..].split('\'').next()
+}
+
+fn number(field: &'static str, value: &str) -> Result<u32, BadgingParseError> {
+    value.parse().map_err(|_| BadgingParseError::FieldInvalid {
+        field,
+        value: value.to_owned(),
+    })
+}
+
+pub fn parse_badging(output: &str) -> Result<Badging, BadgingParseError> {
+    let package = output
+        .lines()
+        .find(|line| line.starts_with("package:"))
+        .ok_or(BadgingParseError::PackageMissing)?;
+    let field = |key: &'static str| {
+        attr(package, key).ok_or(BadgingParseError::FieldMissing { field: key })
+    };
+    let line_value = |prefixes: &[&str]| {
+        output.lines().find_map(|line| {
+            prefixes
+                .iter()
+                .find_map(|prefix| line.strip_prefix(prefix))
+                .and_then(|rest| quoted(rest).first().copied())
+        })
+    };
+    // Older build-tools call it `sdkVersion`.
+    let min_sdk =
+        line_value(&["minSdkVersion:", "sdkVersion:"]).ok_or(BadgingParseError::FieldMissing {
+            field: "minSdkVersion",
+        })?;
+    Ok(Badging {
+        application_id: field("name")?.to_owned(),
+        version_code: number("versionCode", field("versionCode")?)?,
+        version_name: field("versionName")?.to_owned(),
+        min_sdk: number("minSdkVersion", min_sdk)?,
+        debuggable: output
+            .lines()
+            .any(|line| line.trim() == "application-debuggable"),
+        native_code: output
+            .lines()
+            .find_map(|line| line.strip_prefix("native-code:"))
+            .map(|rest| quoted(rest).into_iter().map(ToOwned::to_owned).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// Whether the manifest sets `android:debuggable`, according to
+/// `aapt2 dump xmltree --file AndroidManifest.xml`. Badging leaves it out for
+/// some values, so this is what we go by when it's available.
+pub fn parse_xmltree_debuggable(output: &str) -> Option<bool> {
+    output.lines().find_map(|line| {
+        let value = line
+            .trim()
+            .strip_prefix("A: http://schemas.android.com/apk/res/android:debuggable(")?
+            .split_once(")=")?
+            .1;
+        // Booleans are `true`/`false`, or `-1`/`0` when written out as raw
+        // resource values.
+        Some(matches!(
+            value.trim().trim_matches('"'),
+            "true" | "-1" | "0xffffffff"
+        ))
+    })
+}
+
+/// The ABIs with libs under `lib/` in an APK, read straight from the zip so
+/// it works without `aapt2`.
+pub fn zip_abis(entries: &[reproducible::Entry]) -> Vec<String> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let mut parts = entry.name.split('/');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some("lib"), Some(abi), Some(file)) if file.ends_with(".so") => {
+                    Some(abi.to_owned())
+                }
+                _ => None,
+            }
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// What the config says the APK should be.
+#[derive(Clone, Debug)]
+pub struct Expected {
+    pub application_id: String,
+    pub version_code: u32,
+    pub min_sdk: u32,
+    pub debuggable: bool,
+    pub abis: Vec<String>,
+}
+
+impl Expected {
+    pub fn new(config: &Config, profile: Profile, targets: &[&Target<'_>]) -> Self {
+        Self {
+            application_id: config.app_id(profile),
+            version_code: config.version_code(),
+            min_sdk: config.min_sdk_version(),
+            debuggable: profile.debug(),
+            abis: targets.iter().map(|target| target.abi.to_owned()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub msg: String,
+}
+
+impl Finding {
+    fn warning(msg: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            msg,
+        }
+    }
+
+    fn error(msg: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            msg,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "{} {}", "warning:".yellow().bold(), self.msg),
+            Severity::Error => write!(f, "{} {}", "error:".bright_red().bold(), self.msg),
+        }
+    }
+}
+
+/// Compares what's in the APK with `expected`. `badging` is `None` when
+/// `aapt2` is unavailable, in which case only the ABIs get checked.
+pub fn compare(expected: &Expected, badging: Option<&Badging>, abis: &[String]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for abi in &expected.abis {
+        if !abis.iter().any(|found| found == abi) {
+            findings.push(Finding::error(format!(
+                "There's no native lib for {}, even though it's in `android.targets`.",
+                abi
+            )));
+        }
+    }
+    for abi in abis {
+        if !expected.abis.contains(abi) {
+            findings.push(Finding::warning(format!(
+                "There's a native lib for {}, which isn't what this was built for.",
+                abi
+            )));
+        }
+    }
+    if let Some(badging) = badging {
+        if badging.application_id != expected.application_id {
+            findings.push(Finding::error(format!(
+                "The application id is {:?}, but the config says it should be {:?}.",
+                badging.application_id, expected.application_id
+            )));
+        }
+        if badging.min_sdk != expected.min_sdk {
+            findings.push(Finding::error(format!(
+                "The min SDK version is {}, but `android.min-sdk-version` is {}.",
+                badging.min_sdk, expected.min_sdk
+            )));
+        }
+        // CI often overrides this with `cargoMobileVersionCode`.
+        if badging.version_code != expected.version_code {
+            findings.push(Finding::warning(format!(
+                "The version code is {}, but `android.version-code` is {}.",
+                badging.version_code, expected.version_code
+            )));
+        }
+        match (badging.debuggable, expected.debuggable) {
+            (true, false) => findings.push(Finding::error(
+                "This release build is debuggable, so anyone can attach a debugger to it."
+                    .to_owned(),
+            )),
+            (false, true) => findings.push(Finding::warning(
+                "This debug build isn't debuggable, so you won't be able to attach a debugger."
+                    .to_owned(),
+            )),
+            _ => (),
+        }
+        let mut native_code = badging.native_code.clone();
+        native_code.sort();
+        if native_code != abis {
+            findings.push(Finding::warning(format!(
+                "The manifest's native code ({}) doesn't match the libs that are actually in the APK ({}).",
+                native_code.join(", "),
+                abis.join(", ")
+            )));
+        }
+    }
+    findings
+}
+
+fn dump(aapt2: &Path, env: &Env, args: &[&str], apk: &Path) -> bossy::Result<String> {
+    bossy::Command::impure(aapt2)
+        .with_env_vars(env.explicit_env())
+        .with_args(args)
+        .with_arg(apk)
+        .run_and_wait_for_string()
+}
+
+/// Reads what's in the APK at `path`, going without `aapt2` if it's missing.
+pub fn inspect(
+    env: &Env,
+    path: &Path,
+    expected: &Expected,
+) -> Result<(Option<Badging>, Vec<Finding>), InspectError> {
+    if !path.is_file() {
+        return Err(InspectError::ApkMissing {
+            path: path.to_owned(),
+        });
+    }
+    let bytes = fs::read(path).map_err(|cause| InspectError::ReadFailed {
+        path: path.to_owned(),
+        cause,
+    })?;
+    let abis = zip_abis(&reproducible::entries(&bytes).map_err(InspectError::ZipInvalid)?);
+    let badging = match env.build_tool_path("aapt2") {
+        Ok(aapt2) => {
+            let mut badging = parse_badging(
+                &dump(&aapt2, env, &["dump", "badging"], path)
+                    .map_err(InspectError::BadgingFailed)?,
+            )
+            .map_err(InspectError::BadgingParseFailed)?;
+            let xmltree = dump(
+                &aapt2,
+                env,
+                &["dump", "xmltree", "--file", "AndroidManifest.xml"],
+                path,
+            )
+            .map_err(InspectError::XmlTreeFailed)?;
+            if let Some(debuggable) = parse_xmltree_debuggable(&xmltree) {
+                badging.debuggable = debuggable;
+            }
+            Some(badging)
+        }
+        Err(err) => {
+            log::warn!(
+                "only checking which ABIs {:?} has libs for, since `aapt2` wasn't found: {}",
+                path,
+                err
+            );
+            None
+        }
+    };
+    let findings = compare(expected, badging.as_ref(), &abis);
+    Ok((badging, findings))
+}
+
+/// Prints `findings`, failing if any are errors.
+pub fn check(env: &Env, path: &Path, expected: &Expected) -> Result<(), InspectError> {
+    let (badging, findings) = inspect(env, path, expected)?;
+    if let Some(badging) = badging {
+        println!(
+            "{} {} ({}), min SDK {}",
+            badging.application_id, badging.version_name, badging.version_code, badging.min_sdk
+        );
+    }
+    for finding in &findings {
+        println!("  {}", finding);
+    }
+    let count = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if count == 0 {
+        Ok(())
+    } else {
+        Err(InspectError::Mismatched {
+            path: path.to_owned(),
+            count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::target::TargetTrait as _;
+
+    // Captured from build-tools 33.0.1.
+    static BADGING: &str = "package: name='com.example.my_game.debug' versionCode='3' versionName='0.1.0' platformBuildVersionName='13' platformBuildVersionCode='33' compileSdkVersion='33' compileSdkVersionCodename='13'
+minSdkVersion:'24'
+targetSdkVersion:'33'
+application-label:'My Game'
+application-icon-160:'res/mipmap-mdpi-v4/ic_launcher.png'
+application: label='My Game' icon='res/mipmap-mdpi-v4/ic_launcher.png'
+application-debuggable
+launchable-activity: name='android.app.NativeActivity'  label='My Game' icon=''
+feature-group: label=''
+  uses-feature: name='android.hardware.faketouch'
+  uses-implied-feature: name='android.hardware.faketouch' reason='default feature for all apps'
+main
+supports-screens: 'small' 'normal' 'large' 'xlarge'
+supports-any-density: 'true'
+locales: '--_--'
+densities: '160' '240' '320' '480' '640' '65534'
+native-code: 'arm64-v8a'
+";
+
+    // Captured from build-tools 33.0.1, trimmed.
+    static XMLTREE: &str = "N: android=http://schemas.android.com/apk/res/android (line=2)
+  E: manifest (line=2)
+    A: http://schemas.android.com/apk/res/android:versionCode(0x0101021b)=3
+    A: package=\"com.example.my_game.debug\" (Raw: \"com.example.my_game.debug\")
+      E: application (line=5)
+        A: http://schemas.android.com/apk/res/android:label(0x01010001)=@0x7f0c001b
+        A: http://schemas.android.com/apk/res/android:debuggable(0x0101000f)=true
+        A: http://schemas.android.com/apk/res/android:hasCode(0x0101000c)=false
+";
+
+    fn expected(debuggable: bool, abis: &[&str]) -> Expected {
+        Expected {
+            application_id: "com.example.my_game.debug".to_owned(),
+            version_code: 3,
+            min_sdk: 24,
+            debuggable,
+            abis: abis.iter().map(|abi| (*abi).to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_badging() {
+        assert_eq!(
+            parse_badging(BADGING).unwrap(),
+            Badging {
+                application_id: "com.example.my_game.debug".to_owned(),
+                version_code: 3,
+                version_name: "0.1.0".to_owned(),
+                min_sdk: 24,
+                debuggable: true,
+                native_code: vec!["arm64-v8a".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn parses_old_badging() {
+        let badging = parse_badging(
+            "package: name='com.example.game' versionCode='1' versionName='1.0'\nsdkVersion:'21'\nnative-code: 'armeabi-v7a' 'x86'\n",
+        )
+        .unwrap();
+        assert_eq!(badging.min_sdk, 21);
+        assert!(!badging.debuggable);
+        assert_eq!(badging.native_code, vec!["armeabi-v7a", "x86"]);
+    }
+
+    #[test]
+    fn rejects_invalid_badging() {
+        assert!(matches!(
+            parse_badging("application-debuggable\n"),
+            Err(BadgingParseError::PackageMissing)
+        ));
+        assert!(matches!(
+            parse_badging(
+                "package: name='a.b' versionCode='lots' versionName='1'\nminSdkVersion:'24'\n"
+            ),
+            Err(BadgingParseError::FieldInvalid {
+                field: "versionCode",
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_badging("package: name='a.b' versionCode='1' versionName='1'\n"),
+            Err(BadgingParseError::FieldMissing {
+                field: "minSdkVersion"
+            })
+        ));
+    }
+
+    #[test]
+    fn parses_xmltree_debuggable() {
+        assert_eq!(parse_xmltree_debuggable(XMLTREE), Some(true));
+        assert_eq!(
+            parse_xmltree_debuggable(&XMLTREE.replace(")=true", ")=false")),
+            Some(false)
+        );
+        assert_eq!(
+            parse_xmltree_debuggable(&XMLTREE.replace(")=true", ")=-1")),
+            Some(true)
+        );
+        assert_eq!(parse_xmltree_debuggable("E: manifest (line=2)\n"), None);
+    }
+
+    #[test]
+    fn lists_zip_abis() {
+        let entries = [
+            "AndroidManifest.xml",
+            "lib/x86_64/libmy_game.so",
+            "lib/arm64-v8a/libc++_shared.so",
+            "lib/arm64-v8a/libmy_game.so",
+            "lib/README",
+        ]
+        .iter()
+        .map(|name| reproducible::Entry {
+            name: (*name).to_owned(),
+            crc32: 0,
+            size: 0,
+            modified: (0, 0),
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(zip_abis(&entries), vec!["arm64-v8a", "x86_64"]);
+    }
+
+    #[test]
+    fn matching_apk_has_no_findings() {
+        let badging = parse_badging(BADGING).unwrap();
+        let target = Target::for_name("aarch64").unwrap();
+        assert_eq!(
+            compare(
+                &expected(true, &[target.abi]),
+                Some(&badging),
+                &["arm64-v8a".to_owned()]
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn finds_mismatches() {
+        let mut badging = parse_badging(BADGING).unwrap();
+        badging.application_id = "com.example.other".to_owned();
+        badging.min_sdk = 21;
+        let findings = compare(
+            &expected(false, &["arm64-v8a", "x86_64"]),
+            Some(&badging),
+            &["arm64-v8a".to_owned()],
+        );
+        let errors = findings
+            .iter()
+            .filter(|finding| finding.severity == Severity::Error)
+            .count();
+        // The missing ABI, the application id, the min SDK, and debuggable.
+        assert_eq!(errors, 4, "{:#?}", findings);
+    }
+
+    #[test]
+    fn checks_abis_without_aapt2() {
+        let findings = compare(
+            &expected(false, &["arm64-v8a"]),
+            None,
+            &["arm64-v8a".to_owned(), "x86".to_owned()],
+        );
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+    }
+}
@@ -1,13 +1,61 @@
 use super::{config::Config, target::Target};
 use crate::{
+    opts::Profile,
     target::TargetTrait as _,
     util::{
         cli::{Report, Reportable},
         ln,
     },
 };
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
+/// How built libs get to where gradle packages them from.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Symlinked into `app/src/main/jniLibs`.
+    Symlink,
+    /// Copied into a dir per ABI in cargo's target dir, which the generated
+    /// `build.gradle.kts` adds to `jniLibs.srcDirs`, so nothing needs to be
+    /// able to make symlinks.
+    Direct,
+}
+
+impl Strategy {
+    /// Symlinks usually need developer mode on Windows.
+    pub fn default_for_host() -> Self {
+        if cfg!(windows) {
+            log::info!(
+                "`{}.jnilibs-strategy` not set; defaulting to \"direct\", since Windows often can't make symlinks",
+                super::NAME
+            );
+            Self::Direct
+        } else {
+            Self::Symlink
+        }
+    }
+
+    /// The dir `target`'s libs get packaged from for `profile`.
+    pub fn abi_dir(self, config: &Config, target: Target<'_>, profile: Profile) -> PathBuf {
+        match self {
+            Self::Symlink => path(config, target),
+            Self::Direct => {
+                direct_src_dir(&config.cargo_target_dir(), target, profile).join(target.abi)
+            }
+        }
+    }
+}
+
+/// What the generated `build.gradle.kts` adds to `jniLibs.srcDirs` for
+/// `target` in direct mode.
+pub fn direct_src_dir(cargo_target_dir: &Path, target: Target<'_>, profile: Profile) -> PathBuf {
+    cargo_target_dir
+        .join(target.triple)
+        .join(profile.as_str())
+        .join("jniLibs")
+}
+
 #[derive(Debug)]
 pub enum RemoveBrokenLinksError {
     ReadDirFailed {
@@ -43,21 +91,46 @@ impl Reportable for RemoveBrokenLinksError {
     }
 }
 
+#[derive(Debug)]
+pub struct RemoveUnconfiguredError {
+    path: PathBuf,
+    source: std::io::Error,
+}
+
+impl Reportable for RemoveUnconfiguredError {
+    fn report(&self) -> Report {
+        Report::error(
+            format!(
+                "Failed to remove unconfigured jniLibs directory {:?}",
+                self.path
+            ),
+            &self.source,
+        )
+    }
+}
+
 #[derive(Debug)]
 pub enum SymlinkLibError {
     SourceMissing(PathBuf),
     SymlinkFailed(ln::Error),
+    CopyFailed {
+        dest: PathBuf,
+        source: std::io::Error,
+    },
 }
 
 impl Reportable for SymlinkLibError {
     fn report(&self) -> Report {
-        let msg = "Failed to symlink lib";
+        let msg = "Failed to install lib";
         match self {
             Self::SourceMissing(src) => Report::error(
                 msg,
-                format!("The symlink source is {:?}, but nothing exists there", src),
+                format!("The lib should be at {:?}, but nothing exists there", src),
             ),
             Self::SymlinkFailed(err) => Report::error(msg, err),
+            Self::CopyFailed { dest, source } => {
+                Report::error(format!("Failed to copy lib to {:?}", dest), source)
+            }
         }
     }
 }
@@ -68,20 +141,47 @@ pub fn path(config: &Config, target: Target<'_>) -> PathBuf {
         .join(format!("app/src/main/jniLibs/{}", &target.abi))
 }
 
+fn remove_links(dir: &Path) -> std::io::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_symlink() {
+            log::info!(
+                "deleting symlink {:?} left over from symlink mode",
+                entry.path()
+            );
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct JniLibs {
     path: PathBuf,
+    strategy: Strategy,
 }
 
 impl JniLibs {
-    pub fn create(config: &Config, target: Target<'_>) -> std::io::Result<Self> {
-        let path = path(config, target);
-        std::fs::create_dir_all(&path).map(|()| Self { path })
+    /// In direct mode, this also removes any links left in the symlink dir
+    /// from before switching, since gradle would package those too.
+    pub fn create(config: &Config, target: Target<'_>, profile: Profile) -> std::io::Result<Self> {
+        let strategy = config.jnilibs_strategy();
+        if strategy == Strategy::Direct {
+            remove_links(&path(config, target))?;
+        }
+        let path = strategy.abi_dir(config, target, profile);
+        std::fs::create_dir_all(&path).map(|()| Self { path, strategy })
     }
 
+    /// Does nothing in direct mode, since there aren't any links.
     pub fn remove_broken_links(config: &Config) -> Result<(), RemoveBrokenLinksError> {
-        for abi_dir in Target::all()
-            .values()
+        if config.jnilibs_strategy() == Strategy::Direct {
+            return Ok(());
+        }
+        for abi_dir in Target::all_with_opt_in()
             .map(|target| path(config, *target))
             .filter(|path| path.is_dir())
         {
@@ -115,20 +215,128 @@ impl JniLibs {
         Ok(())
     }
 
-    pub fn symlink_lib(&self, src: &Path) -> Result<(), SymlinkLibError> {
-        log::info!("symlinking lib {:?} in jniLibs dir {:?}", src, self.path);
-        if src.is_file() {
-            ln::force_symlink(
-                src,
-                self.path.join(
-                    src.file_name()
-                        .expect("developer error: file had no file name"),
-                ),
-                ln::TargetStyle::File,
-            )
-            .map_err(SymlinkLibError::SymlinkFailed)
-        } else {
-            Err(SymlinkLibError::SourceMissing(src.to_owned()))
+    /// Removes the jniLibs dirs for any targets not in `android.targets`, so
+    /// stale libs don't get packaged.
+    pub fn remove_unconfigured(config: &Config) -> Result<(), RemoveUnconfiguredError> {
+        let configured = config.targets();
+        for path in Target::all_with_opt_in()
+            .filter(|target| !configured.iter().any(|other| other.abi == target.abi))
+            .map(|target| path(config, *target))
+            .filter(|path| path.is_dir())
+        {
+            log::info!("deleting jniLibs dir for unconfigured target {:?}", path);
+            std::fs::remove_dir_all(&path)
+                .map_err(|source| RemoveUnconfiguredError { path, source })?;
+        }
+        Ok(())
+    }
+
+    /// Symlinks or copies `src` into the dir it gets packaged from.
+    pub fn install_lib(&self, src: &Path) -> Result<(), SymlinkLibError> {
+        if !src.is_file() {
+            return Err(SymlinkLibError::SourceMissing(src.to_owned()));
         }
+        let dest = self.path.join(
+            src.file_name()
+                .expect("developer error: file had no file name"),
+        );
+        match self.strategy {
+            Strategy::Symlink => {
+                log::info!("symlinking lib {:?} in jniLibs dir {:?}", src, self.path);
+                ln::force_symlink(src, dest, ln::TargetStyle::File)
+                    .map_err(SymlinkLibError::SymlinkFailed)
+            }
+            Strategy::Direct => {
+                log::info!("copying lib {:?} to {:?}", src, self.path);
+                std::fs::copy(src, &dest)
+                    .map(|_| ())
+                    .map_err(|source| SymlinkLibError::CopyFailed { dest, source })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::fixture::{self, Fixture};
+    use rstest::rstest;
+
+    fn load(strategy: &str) -> Fixture {
+        fixture::app(
+            "jnilibs",
+            &format!("[android]\njnilibs-strategy = \"{}\"\n", strategy),
+            "",
+        )
+    }
+
+    #[rstest(
+        strategy,
+        profile,
+        expected,
+        case(
+            "symlink",
+            Profile::Debug,
+            "gen/android/jnilibs/app/src/main/jniLibs/arm64-v8a"
+        ),
+        case(
+            "symlink",
+            Profile::Release,
+            "gen/android/jnilibs/app/src/main/jniLibs/arm64-v8a"
+        ),
+        case(
+            "direct",
+            Profile::Debug,
+            "target/aarch64-linux-android/debug/jniLibs/arm64-v8a"
+        ),
+        case(
+            "direct",
+            Profile::Release,
+            "target/aarch64-linux-android/release/jniLibs/arm64-v8a"
+        )
+    )]
+    fn resolves_abi_dirs(strategy: &str, profile: Profile, expected: &str) {
+        let fixture = load(strategy);
+        let config = fixture.config.android();
+        let target = Target::for_name("aarch64").unwrap();
+        assert_eq!(
+            config.jnilibs_strategy().abi_dir(config, *target, profile),
+            fixture.root.join(expected)
+        );
+    }
+
+    #[rstest(
+        profile,
+        expected,
+        case(Profile::Debug, "target/aarch64-linux-android/debug/jniLibs/arm64-v8a"),
+        case(
+            Profile::Release,
+            "target/aarch64-linux-android/release/jniLibs/arm64-v8a"
+        )
+    )]
+    fn resolves_direct_dirs(profile: Profile, expected: &str) {
+        let target = Target::for_name("aarch64").unwrap();
+        assert_eq!(
+            direct_src_dir(Path::new("target"), *target, profile).join(target.abi),
+            Path::new(expected)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn direct_mode_removes_leftover_links() {
+        let fixture = load("direct");
+        let config = fixture.config.android();
+        let target = Target::for_name("aarch64").unwrap();
+        let lib = fixture.root.join("libjnilibs.so");
+        std::fs::write(&lib, "").unwrap();
+        let links = path(config, *target);
+        std::fs::create_dir_all(&links).unwrap();
+        std::os::unix::fs::symlink(&lib, links.join("libjnilibs.so")).unwrap();
+        std::fs::write(links.join("libc++_shared.so"), "").unwrap();
+        JniLibs::create(config, *target, Profile::Debug).unwrap();
+        assert!(std::fs::symlink_metadata(links.join("libjnilibs.so")).is_err());
+        assert!(links.join("libc++_shared.so").is_file());
+        assert!(lib.is_file());
     }
 }
@@ -0,0 +1,519 @@
+use super::config::Signing;
+use crate::util::{
+    self,
+    cli::{Report, Reportable},
+    prompt,
+};
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+
+pub static DEFAULT_VALIDITY_DAYS: u32 = 10000;
+pub static DEFAULT_KEY_SIZE: u32 = 2048;
+pub static DEFAULT_RELEASE_ALIAS: &str = "upload";
+// These match what Android Studio generates, so existing debug builds keep
+// installing over each other.
+pub static DEFAULT_DEBUG_ALIAS: &str = "androiddebugkey";
+pub static DEFAULT_DEBUG_DNAME: &str = "CN=Android Debug,O=Android,C=US";
+
+/// What went wrong, going by what keytool wrote to stderr.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Problem {
+    PasswordIncorrect,
+    PasswordTooShort,
+    DnameInvalid,
+    AliasExists,
+}
+
+/// keytool only reports problems in prose, so this is the best we can do.
+pub fn classify(stderr: &str) -> Option<Problem> {
+    let stderr = stderr.to_ascii_lowercase();
+    if stderr.contains("password was incorrect") || stderr.contains("password verification failed")
+    {
+        Some(Problem::PasswordIncorrect)
+    } else if stderr.contains("must be at least 6 characters") {
+        Some(Problem::PasswordTooShort)
+    } else if stderr.contains("ava format")
+        || stderr.contains("invalid keyword")
+        || stderr.contains("no equals sign")
+        || stderr.contains("dname")
+    {
+        Some(Problem::DnameInvalid)
+    } else if stderr.contains("already exists") {
+        Some(Problem::AliasExists)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    AlreadyExists {
+        path: PathBuf,
+    },
+    DnameRequired,
+    RemoveFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    RenameFailed {
+        from: PathBuf,
+        to: PathBuf,
+        cause: io::Error,
+    },
+    PasswordEnvNotSet {
+        var: String,
+    },
+    KeytoolMissing(bossy::Error),
+    PromptFailed(prompt::Error),
+    ValidityInvalid {
+        input: String,
+    },
+    GenerateFailed(Option<Problem>, bossy::Error),
+    VerifyFailed(Option<Problem>, bossy::Error),
+    SetSigningFailed(crate::config::SetSigningError),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        fn keytool_report(msg: &str, problem: &Option<Problem>, err: &bossy::Error) -> Report {
+            match problem {
+                Some(Problem::PasswordIncorrect) => Report::action_request(
+                    msg,
+                    "The keystore password was incorrect. Check the environment variable it's read from.",
+                ),
+                Some(Problem::PasswordTooShort) => Report::action_request(
+                    msg,
+                    "keytool requires passwords to be at least 6 characters long.",
+                ),
+                Some(Problem::DnameInvalid) => Report::action_request(
+                    msg,
+                    "The distinguished name was invalid; it should look like `CN=Jane Doe,O=Example,C=US`, with any commas inside values escaped as `\\,`.",
+                ),
+                Some(Problem::AliasExists) => Report::action_request(
+                    msg,
+                    "The keystore already has a key with that alias; pick another with `--alias`.",
+                ),
+                None => Report::error(msg, err),
+            }
+        }
+
+        match self {
+            Self::AlreadyExists { path } => Report::action_request(
+                format!("Keystore {:?} already exists", path),
+                "Pass `--force` to overwrite it, or `--out` to write somewhere else. Overwriting a release keystore means you'll never be able to update apps signed with it!",
+            ),
+            Self::DnameRequired => Report::action_request(
+                "Distinguished name is required",
+                "Release keystores need one, and there's no one to ask in non-interactive mode; pass it with `--dname`, i.e. `--dname \"CN=Jane Doe,O=Example,C=US\"`.",
+            ),
+            Self::RemoveFailed { path, cause } => Report::error(
+                format!("Failed to remove leftover keystore {:?}", path),
+                cause,
+            ),
+            Self::RenameFailed { from, to, cause } => Report::error(
+                format!("Failed to move generated keystore {:?} to {:?}", from, to),
+                cause,
+            ),
+            Self::PasswordEnvNotSet { var } => Report::action_request(
+                "Keystore password isn't available",
+                format!(
+                    "The `{}` environment variable isn't set. Passwords are only ever read from the environment, so set it to the password you want and try again.",
+                    var
+                ),
+            ),
+            Self::KeytoolMissing(err) => Report::action_request(
+                "`keytool` wasn't found",
+                format!(
+                    "keytool comes with the JDK; install one, then put its `bin` dir on your `PATH` or set `JAVA_HOME`: {}",
+                    err
+                ),
+            ),
+            Self::PromptFailed(err) => err.report(),
+            Self::ValidityInvalid { input } => Report::error(
+                "Validity invalid",
+                format!("{:?} isn't a whole number of days", input),
+            ),
+            Self::GenerateFailed(problem, err) => {
+                keytool_report("Failed to generate keystore", problem, err)
+            }
+            Self::VerifyFailed(problem, err) => {
+                keytool_report("Failed to verify generated keystore", problem, err)
+            }
+            Self::SetSigningFailed(err) => err.report(),
+        }
+    }
+}
+
+fn problem(err: &bossy::Error) -> Option<Problem> {
+    err.output()
+        .and_then(|output| std::str::from_utf8(output.stderr()).ok())
+        .and_then(classify)
+}
+
+/// keytool from `JAVA_HOME` if it's set there, and otherwise from the `PATH`.
+fn keytool() -> Result<OsString, Error> {
+    if let Some(java_home) = std::env::var_os("JAVA_HOME") {
+        let path = Path::new(&java_home).join("bin").join(if cfg!(windows) {
+            "keytool.exe"
+        } else {
+            "keytool"
+        });
+        if path.is_file() {
+            return Ok(path.into_os_string());
+        }
+        log::warn!("`JAVA_HOME` is set, but has no keytool at {:?}", path);
+    }
+    crate::os::command_path("keytool")
+        .map(|_| "keytool".into())
+        .map_err(Error::KeytoolMissing)
+}
+
+/// Everything keytool needs. Anything left `None` is prompted for, unless
+/// `non_interactive` is set, in which case the defaults are used.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub out: Option<PathBuf>,
+    pub alias: Option<String>,
+    pub validity_days: Option<u32>,
+    pub dname: Option<String>,
+    pub password_env: Option<String>,
+    pub debug: bool,
+    pub force: bool,
+    pub non_interactive: bool,
+}
+
+struct Resolved {
+    out: PathBuf,
+    alias: String,
+    validity_days: u32,
+    dname: String,
+    password_env: String,
+}
+
+fn prompt_dname() -> Result<String, prompt::Error> {
+    println!("These identify you in the certificate; only the name is required.");
+    let fields = [
+        ("CN", "android.keystore.dname.cn", "Your name"),
+        ("OU", "android.keystore.dname.ou", "Organizational unit"),
+        ("O", "android.keystore.dname.o", "Organization"),
+        ("L", "android.keystore.dname.l", "City or locality"),
+        ("ST", "android.keystore.dname.st", "State or province"),
+        ("C", "android.keystore.dname.c", "Two-letter country code"),
+    ];
+    let mut parts = Vec::new();
+    for (key, id, msg) in &fields {
+        let value = loop {
+            let value = prompt::minimal(id, "--dname", msg)?;
+            if !value.is_empty() || *key != "CN" {
+                break value;
+            }
+            println!("Sorry, a name is required.");
+        };
+        if !value.is_empty() {
+            parts.push(format!("{}={}", key, escape_dname_value(&value)));
+        }
+    }
+    Ok(parts.join(","))
+}
+
+/// Values can contain anything, but some characters have to be escaped.
+pub fn escape_dname_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl Options {
+    fn resolve(self, root_dir: &Path, app_name: &str) -> Result<Resolved, Error> {
+        let (default_alias, default_file) = if self.debug {
+            (DEFAULT_DEBUG_ALIAS, "debug.keystore".to_owned())
+        } else {
+            (DEFAULT_RELEASE_ALIAS, format!("{}.keystore", app_name))
+        };
+        let non_interactive = self.non_interactive;
+        let default_out = root_dir.join(default_file);
+        let out = match self.out {
+            Some(out) => out,
+            None if non_interactive => default_out,
+            None => prompt::default(
+                "android.keystore.path",
+                "--out",
+                "Keystore path",
+                Some(&default_out.display().to_string()),
+                None,
+            )
+            .map(PathBuf::from)
+            .map_err(Error::PromptFailed)?,
+        };
+        let alias = match self.alias {
+            Some(alias) => alias,
+            None if non_interactive => default_alias.to_owned(),
+            None => prompt::default(
+                "android.keystore.alias",
+                "--alias",
+                "Key alias",
+                Some(default_alias),
+                None,
+            )
+            .map_err(Error::PromptFailed)?,
+        };
+        let validity_days = match self.validity_days {
+            Some(days) => days,
+            None if non_interactive => DEFAULT_VALIDITY_DAYS,
+            None => {
+                let input = prompt::default(
+                    "android.keystore.validity-days",
+                    "--validity",
+                    "Validity in days",
+                    Some(&DEFAULT_VALIDITY_DAYS.to_string()),
+                    None,
+                )
+                .map_err(Error::PromptFailed)?;
+                input
+                    .parse()
+                    .map_err(|_| Error::ValidityInvalid { input })?
+            }
+        };
+        let dname = match self.dname {
+            Some(dname) => dname,
+            None if self.debug => DEFAULT_DEBUG_DNAME.to_owned(),
+            None if non_interactive => return Err(Error::DnameRequired),
+            None => prompt_dname().map_err(Error::PromptFailed)?,
+        };
+        let password_env = self
+            .password_env
+            .unwrap_or_else(|| default_password_env(app_name));
+        Ok(Resolved {
+            out,
+            alias,
+            validity_days,
+            dname,
+            password_env,
+        })
+    }
+}
+
+pub fn default_password_env(app_name: &str) -> String {
+    format!(
+        "{}_KEYSTORE_PASSWORD",
+        app_name.to_ascii_uppercase().replace('-', "_")
+    )
+}
+
+/// Where a keystore for `out` is generated before it replaces `out`.
+fn tmp_path(out: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(out.file_name().unwrap_or_else(|| "keystore".as_ref()));
+    file_name.push(".tmp");
+    out.with_file_name(file_name)
+}
+
+fn generate_at(
+    keytool: &OsString,
+    path: &Path,
+    alias: &str,
+    validity_days: u32,
+    dname: &str,
+    password_env: &str,
+) -> Result<(), Error> {
+    // A leftover from an earlier failure would otherwise get added to.
+    if path.exists() {
+        std::fs::remove_file(path).map_err(|cause| Error::RemoveFailed {
+            path: path.to_owned(),
+            cause,
+        })?;
+    }
+    // The `:env` suffix makes keytool read the password itself, so it never
+    // shows up in our args or logs. PKCS12 keystores need the key password
+    // to match the store's.
+    let storepass = ["-storepass:env", password_env];
+    let keypass = ["-keypass:env", password_env];
+    bossy::Command::impure(keytool)
+        .with_args(&["-genkeypair", "-noprompt", "-keystore"])
+        .with_arg(path)
+        .with_args(&["-alias", alias])
+        .with_args(&["-keyalg", "RSA"])
+        .with_args(&["-keysize", &DEFAULT_KEY_SIZE.to_string()])
+        .with_args(&["-validity", &validity_days.to_string()])
+        .with_args(&["-dname", dname])
+        .with_args(&storepass)
+        .with_args(&keypass)
+        .run_and_wait_for_output()
+        .map_err(|err| Error::GenerateFailed(problem(&err), err))?;
+    bossy::Command::impure(keytool)
+        .with_args(&["-list", "-keystore"])
+        .with_arg(path)
+        .with_args(&["-alias", alias])
+        .with_args(&storepass)
+        .run_and_wait_for_output()
+        .map_err(|err| Error::VerifyFailed(problem(&err), err))?;
+    Ok(())
+}
+
+pub fn generate(
+    root_dir: &Path,
+    app_name: &str,
+    options: Options,
+    write_config: Option<bool>,
+) -> Result<(), Error> {
+    let force = options.force;
+    let Resolved {
+        out,
+        alias,
+        validity_days,
+        dname,
+        password_env,
+    } = options.resolve(root_dir, app_name)?;
+    if out.exists() && !force {
+        return Err(Error::AlreadyExists { path: out });
+    }
+    if std::env::var_os(&password_env).is_none() {
+        return Err(Error::PasswordEnvNotSet { var: password_env });
+    }
+    let keytool = keytool()?;
+    // keytool adds to existing keystores instead of replacing them, so we
+    // generate a fresh one next to `out` and only replace `out` with it once
+    // it's verified. That way a failure never costs you the old keystore.
+    let tmp = tmp_path(&out);
+    let result = generate_at(&keytool, &tmp, &alias, validity_days, &dname, &password_env)
+        .and_then(|()| {
+            if out.exists() {
+                log::info!("overwriting keystore at {:?}", out);
+            }
+            std::fs::rename(&tmp, &out).map_err(|cause| Error::RenameFailed {
+                from: tmp.clone(),
+                to: out.clone(),
+                cause,
+            })
+        });
+    if result.is_err() && tmp.exists() {
+        if let Err(err) = std::fs::remove_file(&tmp) {
+            log::warn!("failed to remove {:?}: {}", tmp, err);
+        }
+    }
+    result?;
+    println!("Generated keystore at {:?} with key {:?}", out, alias);
+
+    // The config stores paths relative to the app root when it can.
+    let keystore = util::unprefix_path(root_dir, &out).unwrap_or_else(|_| out.clone());
+    let signing = Signing {
+        keystore,
+        key_alias: alias,
+        keystore_password_env: password_env.clone(),
+    };
+    let write_config = match write_config {
+        Some(write_config) => write_config,
+        None => prompt::yes_no(
+            "android.keystore.write-config",
+            "--non-interactive",
+            format!(
+                "Add this keystore to the `android.signing` section of your {}?",
+                crate::config::file_name()
+            ),
+            Some(prompt::YesOrNo::Yes),
+        )
+        .map_err(Error::PromptFailed)?
+        .map_or(false, |answer| answer.yes()),
+    };
+    if write_config {
+        let path = crate::config::set_android_signing(root_dir, signing)
+            .map_err(Error::SetSigningFailed)?;
+        println!("Updated {:?}", path);
+    } else {
+        println!(
+            "To sign with this keystore, add this to your {}:\n\n[android.signing]\nkeystore = {:?}\nkey-alias = {:?}\nkeystore-password-env = {:?}\n",
+            crate::config::file_name(),
+            signing.keystore.display().to_string(),
+            signing.key_alias,
+            signing.keystore_password_env,
+        );
+    }
+    println!(
+        "The password is only ever read from the environment, so add this to your shell profile (i.e. `~/.bashrc` or `~/.zshrc`), with the password you just used:\n\n    export {}='<password>'\n",
+        password_env
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        stderr,
+        problem,
+        case(
+            "keytool error: java.io.IOException: Keystore was tampered with, or password was incorrect",
+            Some(Problem::PasswordIncorrect)
+        ),
+        case(
+            "keytool error: java.lang.Exception: Key password must be at least 6 characters",
+            Some(Problem::PasswordTooShort)
+        ),
+        case(
+            "keytool error: java.io.IOException: Incorrect AVA format",
+            Some(Problem::DnameInvalid)
+        ),
+        case(
+            "keytool error: java.lang.Exception: Key pair not generated, alias <upload> already exists",
+            Some(Problem::AliasExists)
+        ),
+        case("keytool error: java.lang.OutOfMemoryError", None)
+    )]
+    fn classifies_keytool_errors(stderr: &str, problem: Option<Problem>) {
+        assert_eq!(classify(stderr), problem);
+    }
+
+    #[test]
+    fn escapes_dname_values() {
+        assert_eq!(escape_dname_value("Doe, Jane"), "Doe\\, Jane");
+        assert_eq!(escape_dname_value("Example Inc"), "Example Inc");
+    }
+
+    #[test]
+    fn generates_next_to_out() {
+        assert_eq!(
+            tmp_path(Path::new("keys/game.keystore")),
+            Path::new("keys/.game.keystore.tmp")
+        );
+    }
+
+    #[test]
+    fn non_interactive_uses_defaults() {
+        let resolved = Options {
+            debug: true,
+            non_interactive: true,
+            ..Default::default()
+        }
+        .resolve(Path::new("/app"), "game")
+        .unwrap();
+        assert_eq!(resolved.out, Path::new("/app/debug.keystore"));
+        assert_eq!(resolved.alias, DEFAULT_DEBUG_ALIAS);
+        assert_eq!(resolved.validity_days, DEFAULT_VALIDITY_DAYS);
+        assert_eq!(resolved.dname, DEFAULT_DEBUG_DNAME);
+    }
+
+    #[test]
+    fn non_interactive_release_needs_a_dname() {
+        let result = Options {
+            non_interactive: true,
+            ..Default::default()
+        }
+        .resolve(Path::new("/app"), "game");
+        assert!(matches!(result, Err(Error::DnameRequired)));
+    }
+
+    #[test]
+    fn password_env_is_shouty() {
+        assert_eq!(default_password_env("my-game"), "MY_GAME_KEYSTORE_PASSWORD");
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{
+    config::app::{self, App},
+    opts::Profile,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("Failed to write localized display name to {path:?}: {source}")]
+pub struct Error {
+    path: PathBuf,
+    source: io::Error,
+}
+
+// Android only understands `ll` and `ll-rRR` in the old qualifier format;
+// anything with a script, numeric region, or variant needs the `b+` form.
+fn qualifier(tag: &str) -> String {
+    let parts = tag.split('-').collect::<Vec<_>>();
+    match parts.as_slice() {
+        [lang] => lang.to_ascii_lowercase(),
+        [lang, region] if region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic()) => {
+            format!(
+                "{}-r{}",
+                lang.to_ascii_lowercase(),
+                region.to_ascii_uppercase()
+            )
+        }
+        _ => format!("b+{}", parts.join("+")),
+    }
+}
+
+// `aapt2` treats quotes and backslashes specially, and a leading `@` or `?`
+// would make the string a resource reference.
+pub(crate) fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    if s.starts_with('@') || s.starts_with('?') {
+        escaped.push('\\');
+    }
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\'' => escaped.push_str("\\'"),
+            '"' => escaped.push_str("\\\""),
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn write(dir: PathBuf, display_name: &str) -> Result<(), Error> {
+    let path = dir.join("strings.xml");
+    fs::create_dir_all(&dir)
+        .and_then(|()| {
+            fs::write(
+                &path,
+                format!(
+                    "<resources>\n    <string name=\"app_name\">{}</string>\n</resources>\n",
+                    escape(display_name)
+                ),
+            )
+        })
+        .map_err(|source| Error {
+            path: path.clone(),
+            source,
+        })
+}
+
+/// Writes a `values-<qualifier>/strings.xml` for each entry in
+/// `app.display-name-localized`.
+pub fn gen(app: &App, res_dir: PathBuf) -> Result<(), Error> {
+    for (tag, display_name) in app.display_name_localized() {
+        write(
+            res_dir.join(format!("values-{}", qualifier(tag))),
+            display_name,
+        )?;
+    }
+    Ok(())
+}
+
+// Clears out what an earlier `gen_debug` wrote, so neither removing the suffix
+// nor dropping a locale leaves debug builds with a stale name. Other debug
+// resources are left alone, along with any dir they're in.
+fn remove_debug(res_dir: &Path) -> Result<(), Error> {
+    let entries = match fs::read_dir(res_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(source) => {
+            return Err(Error {
+                path: res_dir.to_owned(),
+                source,
+            })
+        }
+    };
+    for entry in entries {
+        let dir = entry
+            .map_err(|source| Error {
+                path: res_dir.to_owned(),
+                source,
+            })?
+            .path();
+        let is_values = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| {
+                name == "values" || name.starts_with("values-")
+            });
+        let path = dir.join("strings.xml");
+        if is_values && path.is_file() {
+            fs::remove_file(&path).map_err(|source| Error { path, source })?;
+            // Fails if something else is in there, which is fine.
+            let _ = fs::remove_dir(&dir);
+        }
+    }
+    Ok(())
+}
+
+/// With `app.debug-display-name-suffix`, writes the debug source set's
+/// `strings.xml`s, which Gradle prefers over the main ones for debug builds.
+/// Every localized name gets the suffix too, since otherwise the main
+/// localized names would win.
+pub fn gen_debug(app: &App, res_dir: PathBuf) -> Result<(), Error> {
+    remove_debug(&res_dir)?;
+    let suffix = match app.debug_display_name_suffix() {
+        Some(suffix) => suffix,
+        None => return Ok(()),
+    };
+    write(
+        res_dir.join("values"),
+        &app.display_name_for(Profile::Debug),
+    )?;
+    for (tag, display_name) in app.display_name_localized() {
+        write(
+            res_dir.join(format!("values-{}", qualifier(tag))),
+            &app::for_profile(display_name, Some(suffix), Profile::Debug),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::fixture, util::TempDirGuard};
+    use rstest::rstest;
+
+    #[rstest(
+        tag,
+        expected,
+        case("fr", "fr"),
+        case("pt-BR", "pt-rBR"),
+        case("en-gb", "en-rGB"),
+        case("zh-Hans", "b+zh+Hans"),
+        case("es-419", "b+es+419"),
+        case("sr-Latn-RS", "b+sr+Latn+RS")
+    )]
+    fn maps_tags_to_qualifiers(tag: &str, expected: &str) {
+        assert_eq!(qualifier(tag), expected);
+    }
+
+    #[rstest(
+        s,
+        expected,
+        case("Jeu d'échecs", "Jeu d\\'échecs"),
+        case("\"A\" & <B>", "\\\"A\\\" &amp; &lt;B&gt;"),
+        case("@home", "\\@home"),
+        case("لعبة", "لعبة")
+    )]
+    fn escapes_strings(s: &str, expected: &str) {
+        assert_eq!(escape(s), expected);
+    }
+
+    #[test]
+    fn removing_the_suffix_clears_debug_names() {
+        let app = |suffix: &str| {
+            fixture::app(
+                "chess",
+                &format!("{}[app.display-name-localized]\nfr = \"Échecs\"\n", suffix),
+                "",
+            )
+        };
+        let res_dir = TempDirGuard::new().unwrap();
+        let network_security = res_dir.join("xml/network_security_config.xml");
+        fs::create_dir_all(network_security.parent().unwrap()).unwrap();
+        fs::write(&network_security, "").unwrap();
+
+        let suffixed = app("debug-display-name-suffix = \" Dev\"\n");
+        gen_debug(suffixed.config.app(), res_dir.to_path_buf()).unwrap();
+        let fr = fs::read_to_string(res_dir.join("values-fr/strings.xml")).unwrap();
+        assert!(fr.contains("Échecs Dev"));
+
+        let plain = app("");
+        gen_debug(plain.config.app(), res_dir.to_path_buf()).unwrap();
+        assert!(!res_dir.join("values").exists());
+        assert!(!res_dir.join("values-fr").exists());
+        assert!(network_security.is_file());
+    }
+}
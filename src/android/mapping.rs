@@ -0,0 +1,176 @@
+//! Keeps the R8 mapping from every minified release, since Play Console can't
+//! deobfuscate stack traces without the exact mapping the build produced.
+
+use super::config::Config;
+use crate::opts::Profile;
+use once_cell_regex::regex;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RetainError {
+    #[error("Failed to copy mapping {src:?} to {dest:?}: {source}")]
+    CopyFailed {
+        src: PathBuf,
+        dest: PathBuf,
+        source: io::Error,
+    },
+}
+
+/// Where gradle writes the mapping for `flavor`'s build, i.e.
+/// `app/build/outputs/mapping/aarch64Release/mapping.txt`.
+pub fn generated_path(config: &Config, profile: Profile, flavor: &str) -> PathBuf {
+    use heck::ToUpperCamelCase as _;
+    config.build_dir().join(format!(
+        "outputs/mapping/{}{}/mapping.txt",
+        flavor,
+        profile.as_str().to_upper_camel_case()
+    ))
+}
+
+/// The name a retained mapping is copied to, i.e. `mapping-aarch64-42.txt`.
+/// `list` relies on this, so the two have to agree.
+pub fn retained_file_name(flavor: &str, version_code: u32) -> String {
+    format!("mapping-{}-{}.txt", flavor, version_code)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Retained {
+    pub path: PathBuf,
+    pub flavor: String,
+    pub version_code: u32,
+}
+
+impl Retained {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let caps = regex!(r"^mapping-([A-Za-z0-9_]+)-(\d+)\.txt$")
+            .captures(path.file_name()?.to_str()?)?;
+        Some(Self {
+            path: path.to_owned(),
+            flavor: caps[1].to_owned(),
+            version_code: caps[2].parse().ok()?,
+        })
+    }
+}
+
+/// What happened to a build's mapping.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The build wasn't minified, so there's no mapping to keep.
+    NotMinified,
+    Retained(PathBuf),
+    /// The build was minified, but gradle didn't write a mapping here.
+    Missing(PathBuf),
+}
+
+/// Copies the mapping for `flavor`'s build into `dest_dir`, named after the
+/// configured version code.
+pub fn retain(
+    config: &Config,
+    profile: Profile,
+    flavor: &str,
+    dest_dir: &Path,
+) -> Result<Outcome, RetainError> {
+    // Only release builds are minified.
+    if !config.minify() || profile.debug() {
+        return Ok(Outcome::NotMinified);
+    }
+    let src = generated_path(config, profile, flavor);
+    if !src.is_file() {
+        return Ok(Outcome::Missing(src));
+    }
+    let dest = dest_dir.join(retained_file_name(flavor, config.version_code()));
+    fs::copy(&src, &dest).map_err(|source| RetainError::CopyFailed {
+        src,
+        dest: dest.clone(),
+        source,
+    })?;
+    Ok(Outcome::Retained(dest))
+}
+
+/// Every retained mapping under `dir`, newest version code first.
+pub fn list(dir: &Path) -> Vec<Retained> {
+    let mut retained = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if entry.file_type().is_file() {
+                Retained::from_path(entry.path())
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    retained.sort_by(|a, b| {
+        b.version_code
+            .cmp(&a.version_code)
+            .then_with(|| a.flavor.cmp(&b.flavor))
+    });
+    retained
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+
+    #[rstest(
+        file_name,
+        expected,
+        case("mapping-aarch64-42.txt", Some(("aarch64", 42))),
+        case("mapping-x86_64-1.txt", Some(("x86_64", 1))),
+        case("mapping.txt", None),
+        case("mapping-aarch64.txt", None),
+        case("mapping-aarch64-42.txt.bak", None)
+    )]
+    fn parses_retained_names(file_name: &str, expected: Option<(&str, u32)>) {
+        let retained = Retained::from_path(Path::new(file_name));
+        assert_eq!(
+            retained
+                .as_ref()
+                .map(|retained| (retained.flavor.as_str(), retained.version_code)),
+            expected
+        );
+    }
+
+    #[test]
+    fn names_round_trip() {
+        let name = retained_file_name("armv7", 1234);
+        let retained = Retained::from_path(Path::new(&name)).unwrap();
+        assert_eq!(
+            (retained.flavor.as_str(), retained.version_code),
+            ("armv7", 1234)
+        );
+    }
+
+    #[test]
+    fn lists_newest_first() {
+        let dir = TempDirGuard::new().unwrap();
+        let apk_dir = dir.join("apk/aarch64/release");
+        fs::create_dir_all(&apk_dir).unwrap();
+        for name in &[
+            retained_file_name("aarch64", 3),
+            retained_file_name("aarch64", 7),
+            "output-metadata.json".to_owned(),
+        ] {
+            fs::write(apk_dir.join(name), "").unwrap();
+        }
+        fs::write(dir.join(retained_file_name("armv7", 7)), "").unwrap();
+        let summary = list(&dir)
+            .into_iter()
+            .map(|retained| (retained.flavor, retained.version_code))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("aarch64".to_owned(), 7),
+                ("armv7".to_owned(), 7),
+                ("aarch64".to_owned(), 3),
+            ]
+        );
+    }
+}
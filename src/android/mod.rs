@@ -1,13 +1,51 @@
+mod aab;
 pub(crate) mod adb;
-mod bundletool;
+mod apk;
+mod app_sources;
+mod artifacts;
+pub(crate) mod bundletool;
+pub(crate) mod cargo_config;
 pub mod cli;
 pub(crate) mod config;
+mod debug;
 mod device;
+mod elf;
 pub(crate) mod env;
+pub(crate) mod gradle;
+pub(crate) mod gradle_daemons;
+pub(crate) mod gradlew;
+mod inspect;
 mod jnilibs;
+mod keystore;
+pub(crate) mod localization;
+mod mapping;
 mod ndk;
+mod network_security;
 pub(crate) mod project;
+mod reproducible;
+mod snapshot;
 mod source_props;
-mod target;
+mod state;
+mod strip;
+pub(crate) mod target;
+mod trace;
+
+use crate::util::{self, cli::TextWrapper, RustVersionRequirement};
 
 pub static NAME: &str = "android";
+
+pub fn rust_version_requirements(ndk: &ndk::Env) -> Vec<RustVersionRequirement> {
+    ndk.version()
+        .map_err(|err| log::warn!("failed to get NDK version: {}", err))
+        .ok()
+        .and_then(|revision| ndk::rust_version_requirement(revision.into()))
+        .into_iter()
+        .collect()
+}
+
+pub fn rust_version_check(
+    wrapper: &TextWrapper,
+    ndk: &ndk::Env,
+) -> Result<(), util::RustVersionError> {
+    util::rust_version_check(wrapper, &rust_version_requirements(ndk)).map(|_| ())
+}
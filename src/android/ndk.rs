@@ -4,11 +4,11 @@ use super::{
 };
 use crate::util::{
     cli::{Report, Reportable},
-    VersionDouble,
+    RustVersionRequirement, VersionDouble, VersionTriple,
 };
 use once_cell_regex::regex_multi_line;
 use std::{
-    collections::HashSet,
+    collections::BTreeSet,
     fmt::{self, Display},
     path::{Path, PathBuf},
 };
@@ -16,6 +16,27 @@ use thiserror::Error;
 
 const MIN_NDK_VERSION: NdkVersion = NdkVersion(VersionDouble::new(19, 0));
 
+/// The oldest rustc that works with each NDK, keyed by the first NDK major
+/// version that needs it. Keep this sorted!
+static MIN_RUSTC_FOR_NDK: &[(u32, VersionTriple, &str)] = &[(
+    23,
+    VersionTriple::new(1, 68, 0),
+    "NDK r23 and later don't ship `libgcc` anymore, which Rust versions before 1.68.0 link against.",
+)];
+
+/// What rustc the NDK needs, if anything in particular.
+pub fn rust_version_requirement(ndk: NdkVersion) -> Option<RustVersionRequirement> {
+    MIN_RUSTC_FOR_NDK
+        .iter()
+        .rev()
+        .find(|(major, _, _)| ndk.0.major >= *major)
+        .map(|(_, min, reason)| RustVersionRequirement {
+            platform: "Android",
+            min: *min,
+            reason: format!("You're using NDK {}. {}", ndk, reason),
+        })
+}
+
 #[cfg(target_os = "macos")]
 pub fn host_tag() -> &'static str {
     "darwin-x86_64"
@@ -36,6 +57,54 @@ pub fn host_tag() -> &'static str {
     "windows-x86_64"
 }
 
+// The tags worth trying, best first. On Windows, we can't trust how we were
+// compiled, since an x86_64 build of cargo-mobile runs just fine under
+// emulation on ARM64; `PROCESSOR_ARCHITEW6432` is only set for WOW64
+// processes, and holds the real arch when it is.
+fn host_tag_candidates(os: &str, arch: &str, processor_arch: Option<&str>) -> Vec<&'static str> {
+    let processor_is = |name: &str| {
+        processor_arch
+            .map(|processor_arch| processor_arch.eq_ignore_ascii_case(name))
+            .unwrap_or(false)
+    };
+    match os {
+        "windows" if arch == "aarch64" || processor_is("ARM64") => {
+            vec!["windows-arm64", "windows-x86_64"]
+        }
+        "windows" if arch == "x86" && !processor_is("AMD64") => vec!["windows"],
+        "windows" => vec!["windows-x86_64", "windows"],
+        _ => vec![host_tag()],
+    }
+}
+
+fn detect_host_tag(ndk_home: &Path) -> &'static str {
+    let processor_arch = std::env::var("PROCESSOR_ARCHITEW6432")
+        .or_else(|_| std::env::var("PROCESSOR_ARCHITECTURE"))
+        .ok();
+    let candidates = host_tag_candidates(
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        processor_arch.as_deref(),
+    );
+    let prebuilt = ndk_home.join("toolchains/llvm/prebuilt");
+    if let Some(tag) = candidates
+        .iter()
+        .find(|tag| prebuilt.join(tag).is_dir())
+        .copied()
+    {
+        log::info!("using NDK host tag `{}`", tag);
+        tag
+    } else {
+        log::warn!(
+            "none of the NDK host tags {:?} exist in {:?}; guessing `{}`",
+            candidates,
+            prebuilt,
+            host_tag()
+        );
+        host_tag()
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Compiler {
     Clang,
@@ -56,6 +125,7 @@ pub enum Binutil {
     Ar,
     #[allow(dead_code)]
     Ld,
+    Strip,
 }
 
 impl Binutil {
@@ -63,6 +133,7 @@ impl Binutil {
         match self {
             Binutil::Ar => "ar",
             Binutil::Ld => "ld",
+            Binutil::Strip => "strip",
         }
     }
 }
@@ -168,6 +239,7 @@ impl Reportable for RequiredLibsError {
 #[derive(Debug)]
 pub struct Env {
     ndk_home: PathBuf,
+    host_tag: &'static str,
 }
 
 impl Env {
@@ -182,7 +254,8 @@ impl Env {
                     Err(Error::NdkHomeNotADir)
                 }
             })?;
-        let env = Self { ndk_home };
+        let host_tag = detect_host_tag(&ndk_home);
+        let env = Self { ndk_home, host_tag };
         let version = env
             .version()
             .map(NdkVersion::from)
@@ -201,6 +274,10 @@ impl Env {
         &self.ndk_home
     }
 
+    pub fn host_tag(&self) -> &'static str {
+        self.host_tag
+    }
+
     pub fn version(&self) -> Result<source_props::Revision, source_props::Error> {
         SourceProps::from_path(self.ndk_home.join("source.properties"))
             .map(|props| props.pkg.revision)
@@ -209,7 +286,7 @@ impl Env {
     pub fn prebuilt_dir(&self) -> Result<PathBuf, MissingToolError> {
         MissingToolError::check_dir(
             self.ndk_home
-                .join(format!("toolchains/llvm/prebuilt/{}", host_tag())),
+                .join(format!("toolchains/llvm/prebuilt/{}", self.host_tag)),
             // TODO: shove this square peg into a squarer hole
             "prebuilt toolchain",
         )
@@ -232,15 +309,42 @@ impl Env {
         )
     }
 
+    /// NDK r23 dropped the binutils prefixed with the triple, leaving only
+    /// the `llvm-` ones.
     pub fn binutil_path(
         &self,
         binutil: Binutil,
         triple: &str,
     ) -> Result<PathBuf, MissingToolError> {
+        let tool_dir = self.tool_dir()?;
+        let prefixed = tool_dir.join(format!("{}-{}", triple, binutil.as_str()));
+        if prefixed.is_file() {
+            Ok(prefixed)
+        } else {
+            MissingToolError::check_file(
+                tool_dir.join(format!("llvm-{}", binutil.as_str())),
+                binutil.as_str(),
+            )
+        }
+    }
+
+    /// Where this lives has moved between NDK versions, and the clang version
+    /// is part of the path, so we look for it instead of assuming a layout.
+    pub fn lldb_server_path(&self, arch: &str) -> Result<PathBuf, MissingToolError> {
+        static NAME: &str = "lldb-server";
+        let prebuilt = self.prebuilt_dir()?;
+        let suffix = Path::new("linux").join(arch).join(NAME);
+        let found = walkdir::WalkDir::new(&prebuilt)
+            .max_depth(8)
+            .into_iter()
+            // Thousands of headers and libs, none of which are it.
+            .filter_entry(|entry| entry.file_name() != "sysroot")
+            .filter_map(Result::ok)
+            .map(walkdir::DirEntry::into_path)
+            .find(|path| path.ends_with(&suffix) && path.is_file());
         MissingToolError::check_file(
-            self.tool_dir()?
-                .join(format!("{}-{}", triple, binutil.as_str())),
-            binutil.as_str(),
+            found.unwrap_or_else(|| prebuilt.join("lib/clang/*/lib").join(&suffix)),
+            NAME,
         )
     }
 
@@ -266,7 +370,7 @@ impl Env {
         &self,
         elf: &Path,
         triple: &str,
-    ) -> Result<HashSet<String>, RequiredLibsError> {
+    ) -> Result<BTreeSet<String>, RequiredLibsError> {
         Ok(regex_multi_line!(r"\(NEEDED\)\s+Shared library: \[(.+)\]")
             .captures_iter(
                 bossy::Command::impure(self.readelf_path(triple)?)
@@ -286,3 +390,80 @@ impl Env {
             .collect())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        os,
+        arch,
+        processor_arch,
+        expected,
+        case("windows", "x86_64", Some("AMD64"), &["windows-x86_64", "windows"]),
+        case("windows", "x86_64", Some("ARM64"), &["windows-arm64", "windows-x86_64"]),
+        case("windows", "aarch64", None, &["windows-arm64", "windows-x86_64"]),
+        case("windows", "x86", Some("AMD64"), &["windows-x86_64", "windows"]),
+        case("windows", "x86", Some("x86"), &["windows"])
+    )]
+    fn windows_host_tag_candidates(
+        os: &str,
+        arch: &str,
+        processor_arch: Option<&str>,
+        expected: &[&str],
+    ) {
+        assert_eq!(host_tag_candidates(os, arch, processor_arch), expected);
+    }
+
+    #[rstest(
+        ndk,
+        rustc,
+        required,
+        satisfied,
+        case((19, 0), (1, 50, 0), false, true),
+        case((22, 1), (1, 60, 0), false, true),
+        case((23, 0), (1, 67, 1), true, false),
+        case((23, 2), (1, 68, 0), true, true),
+        case((26, 1), (1, 65, 0), true, false),
+        case((26, 1), (1, 75, 0), true, true)
+    )]
+    fn rustc_matches_ndk(ndk: (u32, u32), rustc: (u32, u32, u32), required: bool, satisfied: bool) {
+        let version = crate::util::RustVersion {
+            triple: VersionTriple::new(rustc.0, rustc.1, rustc.2),
+            flavor: None,
+            details: None,
+        };
+        let requirement = rust_version_requirement(NdkVersion(VersionDouble::new(ndk.0, ndk.1)));
+        assert_eq!(requirement.is_some(), required);
+        assert_eq!(
+            requirement.map_or(true, |requirement| requirement.satisfied_by(&version)),
+            satisfied
+        );
+    }
+
+    #[rstest(
+        layout,
+        case("lib64/clang/12.0.8/lib/linux"),
+        case("lib/clang/14.0.6/lib/linux"),
+        case("lib/clang/17/lib/linux")
+    )]
+    fn finds_lldb_server(layout: &str) {
+        let ndk_home = crate::util::TempDirGuard::new().unwrap();
+        let prebuilt = ndk_home.join("toolchains/llvm/prebuilt/linux-x86_64");
+        let arch_dir = prebuilt.join(layout).join("aarch64");
+        std::fs::create_dir_all(&arch_dir).unwrap();
+        std::fs::create_dir_all(prebuilt.join(layout).join("arm")).unwrap();
+        std::fs::write(arch_dir.join("lldb-server"), "").unwrap();
+        std::fs::write(prebuilt.join(layout).join("arm/lldb-server"), "").unwrap();
+        let env = Env {
+            ndk_home: ndk_home.to_path_buf(),
+            host_tag: "linux-x86_64",
+        };
+        assert_eq!(
+            env.lldb_server_path("aarch64").unwrap(),
+            arch_dir.join("lldb-server")
+        );
+        assert!(env.lldb_server_path("x86_64").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use super::config::AllowCleartext;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+pub static FILE_NAME: &str = "network_security_config.xml";
+
+#[derive(Debug, Error)]
+#[error("Failed to write network security config to {path:?}: {source}")]
+pub struct Error {
+    path: PathBuf,
+    source: io::Error,
+}
+
+fn base_config(cleartext: bool) -> String {
+    format!(
+        "    <base-config cleartextTrafficPermitted=\"{}\" />\n",
+        cleartext
+    )
+}
+
+fn document(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<network-security-config>\n{}</network-security-config>\n",
+        body
+    )
+}
+
+/// The config every build gets. Domains were already validated, so they
+/// can't contain anything that needs escaping.
+pub fn main_xml(allow: &AllowCleartext) -> String {
+    match allow {
+        // Debug builds get their own copy that overrides this one.
+        AllowCleartext::Debug => document(&base_config(false)),
+        AllowCleartext::Domains(domains) => {
+            let mut body = String::from("    <domain-config cleartextTrafficPermitted=\"true\">\n");
+            for domain in domains {
+                body.push_str(&format!(
+                    "        <domain includeSubdomains=\"false\">{}</domain>\n",
+                    domain
+                ));
+            }
+            body.push_str("    </domain-config>\n");
+            document(&body)
+        }
+    }
+}
+
+/// The config only debug builds get, if any.
+pub fn debug_xml(allow: &AllowCleartext) -> Option<String> {
+    match allow {
+        AllowCleartext::Debug => Some(document(&base_config(true))),
+        AllowCleartext::Domains(_) => None,
+    }
+}
+
+fn write(res_dir: &Path, xml: &str) -> Result<(), Error> {
+    let dir = res_dir.join("xml");
+    let path = dir.join(FILE_NAME);
+    fs::create_dir_all(&dir)
+        .and_then(|()| fs::write(&path, xml))
+        .map_err(|source| Error {
+            path: path.clone(),
+            source,
+        })
+}
+
+/// Writes the config into the `main` source set, and into the `debug` one
+/// when cleartext is allowed everywhere, since resources there win for debug
+/// builds only. Any stale debug copy is removed, so switching from `true` to
+/// a list can't leave debug builds wide open.
+pub fn gen(allow: Option<&AllowCleartext>, app_src_dir: &Path) -> Result<(), Error> {
+    let debug_path = app_src_dir.join("debug/res/xml").join(FILE_NAME);
+    if let Some(debug) = allow.and_then(debug_xml) {
+        write(&app_src_dir.join("debug/res"), &debug)?;
+    } else if debug_path.exists() {
+        fs::remove_file(&debug_path).map_err(|source| Error {
+            path: debug_path.clone(),
+            source,
+        })?;
+    }
+    if let Some(allow) = allow {
+        write(&app_src_dir.join("main/res"), &main_xml(allow))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn debug_only_allows_everything_in_debug() {
+        let main = main_xml(&AllowCleartext::Debug);
+        assert!(main.contains("<base-config cleartextTrafficPermitted=\"false\" />"));
+        let debug = debug_xml(&AllowCleartext::Debug).unwrap();
+        assert!(debug.contains("<base-config cleartextTrafficPermitted=\"true\" />"));
+    }
+
+    #[test]
+    fn domains_are_listed() {
+        let allow = AllowCleartext::Domains(vec!["10.0.2.2".to_owned(), "192.168.1.10".to_owned()]);
+        assert_eq!(
+            main_xml(&allow),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<network-security-config>
+    <domain-config cleartextTrafficPermitted="true">
+        <domain includeSubdomains="false">10.0.2.2</domain>
+        <domain includeSubdomains="false">192.168.1.10</domain>
+    </domain-config>
+</network-security-config>
+"#
+        );
+        assert!(debug_xml(&allow).is_none());
+    }
+}
@@ -1,42 +1,54 @@
 use super::{
+    app_sources,
     config::{Config, Metadata},
     env::Env,
-    ndk,
+    jnilibs, localization, ndk, network_security, state,
     target::Target,
 };
 use crate::{
-    dot_cargo,
+    assets,
+    config::app::AssetPlatform,
+    dot_cargo, icons, opts,
     target::TargetTrait as _,
     templating::{self, Pack},
     util::{
         self,
         cli::{Report, Reportable, TextWrapper},
-        ln,
+        events,
     },
 };
 use path_abs::PathOps;
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 pub static TEMPLATE_PACK: &str = "android-studio";
+pub const RUSTUP_STEP: events::Step =
+    events::Step::new("android.rustup", "Installing Android toolchains");
 pub static ASSET_PACK_TEMPLATE_PACK: &str = "android-studio-asset-pack";
 
 #[derive(Debug)]
 pub enum Error {
-    RustupFailed(bossy::Error),
+    RustupFailed(util::toolchain::RustupTargetAddFailed),
     MissingPack(templating::LookupError),
-    TemplateProcessingFailed(bicycle::ProcessingError),
+    TemplateProcessingFailed(templating::ProcessError),
     DirectoryCreationFailed {
         path: PathBuf,
         cause: std::io::Error,
     },
-    AssetDirSymlinkFailed(ln::Error),
-    DotCargoGenFailed(ndk::MissingToolError),
-    FileCopyFailed {
-        src: PathBuf,
-        dest: PathBuf,
-        cause: std::io::Error,
+    TargetUnsupportedByNdk {
+        target: &'static str,
+        cause: ndk::MissingToolError,
     },
-    AssetSourceInvalid(PathBuf),
+    AssetDirInstallFailed(assets::Error),
+    DotCargoGenFailed(ndk::MissingToolError),
+    NdkStampFailed(state::Error),
+    StaleJniLibsRemovalFailed(jnilibs::RemoveUnconfiguredError),
+    AppSourceInstallFailed(app_sources::Error),
+    LocalizationGenFailed(localization::Error),
+    NetworkSecurityGenFailed(network_security::Error),
+    IconSyncFailed(icons::Error),
 }
 
 impl Reportable for Error {
@@ -51,59 +63,99 @@ impl Reportable for Error {
                 format!("Failed to create Android assets directory at {:?}", path),
                 cause,
             ),
-            Self::AssetDirSymlinkFailed(err) => {
-                Report::error("Asset dir couldn't be symlinked into Android project", err)
+            Self::TargetUnsupportedByNdk { target, cause } => Report::error(
+                format!(
+                    "`{}.targets` contains {:?}, but your NDK doesn't support it",
+                    super::NAME,
+                    target
+                ),
+                format!(
+                    "{} Either remove it from `{}.targets` or install a newer NDK.",
+                    cause,
+                    super::NAME
+                ),
+            ),
+            Self::AssetDirInstallFailed(err) => {
+                Report::error("Asset dir couldn't be installed into Android project", err)
             }
             Self::DotCargoGenFailed(err) => {
                 Report::error("Failed to generate Android cargo config", err)
             }
-            Self::FileCopyFailed { src, dest, cause } => Report::error(
-                format!("Failed to copy file at {:?} to {:?}", src, dest),
-                cause,
-            ),
-            Self::AssetSourceInvalid(src) => Report::error(
-                format!("Asset source at {:?} invalid", src),
-                "Asset sources must be either a directory or a file",
-            ),
+            Self::NdkStampFailed(err) => {
+                Report::error("Failed to record which NDK the project uses", err)
+            }
+            Self::StaleJniLibsRemovalFailed(err) => err.report(),
+            Self::AppSourceInstallFailed(err) => {
+                Report::error("Failed to install app source into Android project", err)
+            }
+            Self::LocalizationGenFailed(err) => {
+                Report::error("Failed to generate Android localizations", err)
+            }
+            Self::NetworkSecurityGenFailed(err) => {
+                Report::error("Failed to generate network security config", err)
+            }
+            Self::IconSyncFailed(err) => Report::error("Failed to generate Android icons", err),
         }
     }
 }
 
-pub fn gen(
+// Relative to the root of the template pack (and the project dir).
+fn is_icon(rel: &Path) -> bool {
+    rel.starts_with("app/src/main/res")
+        && (rel
+            .parent()
+            .and_then(Path::file_name)
+            .and_then(|dir| dir.to_str())
+            .map_or(false, |dir| dir.starts_with("mipmap-"))
+            || rel
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with("ic_launcher")))
+}
+
+/// Syncs the launcher icons (mipmaps and adaptive icon drawables) from the
+/// template pack into the project.
+pub fn sync_icons(config: &Config, mode: icons::Mode) -> Result<icons::Changes, Error> {
+    let src = Pack::lookup_platform(TEMPLATE_PACK)
+        .map_err(Error::MissingPack)?
+        .expect_local();
+    // None of the icon paths in the pack are templated.
+    let render = |rel: &Path| Ok(rel.to_owned());
+    icons::sync(&src, &config.project_dir(), is_icon, render, mode).map_err(Error::IconSyncFailed)
+}
+
+/// Everything in `gen` that comes out of template packs, which needs the
+/// same inputs to always produce the same project.
+pub(crate) fn process_templates(
     config: &Config,
     metadata: &Metadata,
-    env: &Env,
     bike: &bicycle::Bicycle,
-    wrapper: &TextWrapper,
     filter: &templating::Filter,
-    dot_cargo: &mut dot_cargo::DotCargo,
+    conflicts: opts::Conflicts,
+    src: &Path,
+    asset_pack_src: &Path,
+    dest: &Path,
 ) -> Result<(), Error> {
-    println!("Installing Android toolchains...");
-    Target::install_all().map_err(Error::RustupFailed)?;
-    println!("Generating Android Studio project...");
-    let src = Pack::lookup_platform(TEMPLATE_PACK)
-        .map_err(Error::MissingPack)?
-        .expect_local();
-    let dest = config.project_dir();
-
+    let targets = config.targets();
     let asset_packs = metadata.asset_packs().unwrap_or_default();
-    bike.filter_and_process(
+    templating::process(
+        bike,
         src,
-        &dest,
+        dest,
         |map| {
+            // There's deliberately no absolute `root-dir`, since the project
+            // has to keep working after being cloned elsewhere.
             map.insert(
                 "root-dir-rel",
                 util::relativize_path(config.app().root_dir(), config.project_dir()),
             );
-            map.insert("root-dir", config.app().root_dir());
-            map.insert("targets", Target::all().values().collect::<Vec<_>>());
-            map.insert("target-names", Target::all().keys().collect::<Vec<_>>());
+            // Debug builds add `applicationIdSuffix` on top of this.
+            map.insert("app-id", config.app_id(opts::Profile::Release));
+            map.insert("targets", &targets);
+            map.insert("target-names", config.target_names());
             map.insert(
                 "arches",
-                Target::all()
-                    .values()
-                    .map(|target| target.arch)
-                    .collect::<Vec<_>>(),
+                targets.iter().map(|target| target.arch).collect::<Vec<_>>(),
             );
             map.insert("android-app-plugins", metadata.app_plugins());
             map.insert(
@@ -121,6 +173,14 @@ pub fn gen(
                     || metadata.app_dependencies().is_some()
                     || metadata.app_dependencies_platform().is_some(),
             );
+            map.insert(
+                "jnilibs-direct",
+                config.jnilibs_strategy() == jnilibs::Strategy::Direct,
+            );
+            map.insert(
+                "has-network-security-config",
+                config.allow_cleartext().is_some(),
+            );
             map.insert(
                 "asset-packs",
                 asset_packs
@@ -129,65 +189,285 @@ pub fn gen(
                     .collect::<Vec<_>>(),
             );
         },
-        filter.fun(),
+        filter,
+        conflicts,
     )
     .map_err(Error::TemplateProcessingFailed)?;
-    if !asset_packs.is_empty() {
-        Report::action_request(
-            "When running from Android Studio, you must first set your deployment option to \"APK from app bundle\".", 
-            "Android Studio will not be able to find your asset packs otherwise. The option can be found under \"Run > Edit Configurations > Deploy\"."
-        ).print(wrapper);
-    }
-
-    let asset_pack_src = Pack::lookup_platform(ASSET_PACK_TEMPLATE_PACK)
-        .map_err(Error::MissingPack)?
-        .expect_local();
     for asset_pack in asset_packs {
-        bike.filter_and_process(
-            &asset_pack_src,
+        templating::process(
+            bike,
+            asset_pack_src,
             dest.join(&asset_pack.name),
             |map| {
                 map.insert("pack-name", &asset_pack.name);
                 map.insert("delivery-type", &asset_pack.delivery_type);
             },
-            filter.fun(),
+            filter,
+            conflicts,
         )
         .map_err(Error::TemplateProcessingFailed)?;
     }
 
-    let source_dest = dest.join("app");
+    Ok(())
+}
+
+pub fn gen(
+    config: &Config,
+    metadata: &Metadata,
+    env: &Env,
+    bike: &bicycle::Bicycle,
+    wrapper: &TextWrapper,
+    filter: &templating::Filter,
+    conflicts: opts::Conflicts,
+    dot_cargo: &mut dot_cargo::DotCargo,
+) -> Result<(), Error> {
+    install_toolchains(config, env)?;
+    events::say("Generating Android Studio project...");
+    let src = Pack::lookup_platform(TEMPLATE_PACK)
+        .map_err(Error::MissingPack)?
+        .expect_local();
+    let asset_pack_src = Pack::lookup_platform(ASSET_PACK_TEMPLATE_PACK)
+        .map_err(Error::MissingPack)?
+        .expect_local();
+    let dest = config.project_dir();
+    process_templates(
+        config,
+        metadata,
+        bike,
+        filter,
+        conflicts,
+        &src,
+        &asset_pack_src,
+        &dest,
+    )?;
+    if !metadata.asset_packs().unwrap_or_default().is_empty() {
+        Report::action_request(
+            "When running from Android Studio, you must first set your deployment option to \"APK from app bundle\".", 
+            "Android Studio will not be able to find your asset packs otherwise. The option can be found under \"Run > Edit Configurations > Deploy\"."
+        ).print(wrapper);
+    }
+
+    localization::gen_debug(config.app(), dest.join("app/src/debug/res"))
+        .map_err(Error::LocalizationGenFailed)?;
+    localization::gen(config.app(), dest.join("app/src/main/res"))
+        .map_err(Error::LocalizationGenFailed)?;
+
+    network_security::gen(config.allow_cleartext(), &dest.join("app/src"))
+        .map_err(Error::NetworkSecurityGenFailed)?;
+
+    let mut app_sources =
+        app_sources::Installer::new(&dest).map_err(Error::AppSourceInstallFailed)?;
     for source in metadata.app_sources() {
-        let source_src = config.app().root_dir().join(&source);
-        let source_file = source_src
-            .file_name()
-            .ok_or_else(|| Error::AssetSourceInvalid(source_src.clone()))?;
-        fs::copy(&source_src, source_dest.join(source_file)).map_err(|cause| {
-            Error::FileCopyFailed {
-                src: source_src,
-                dest: source_dest.clone(),
-                cause,
-            }
-        })?;
+        app_sources
+            .install(config.app().root_dir(), source)
+            .map_err(Error::AppSourceInstallFailed)?;
     }
+    app_sources
+        .finish()
+        .map_err(Error::AppSourceInstallFailed)?;
 
     let dest = dest.join("app/src/main/assets/");
     fs::create_dir_all(&dest).map_err(|cause| Error::DirectoryCreationFailed {
         path: dest.clone(),
         cause,
     })?;
-    ln::force_symlink_relative(config.app().asset_dir(), dest, ln::TargetStyle::Directory)
-        .map_err(Error::AssetDirSymlinkFailed)?;
-
-    {
-        for target in Target::all().values() {
-            dot_cargo.insert_target(
-                target.triple.to_owned(),
-                target
-                    .generate_cargo_config(config, &env)
-                    .map_err(Error::DotCargoGenFailed)?,
+    assets::install(config.app(), AssetPlatform::Android, &dest)
+        .map_err(Error::AssetDirInstallFailed)?;
+
+    jnilibs::JniLibs::remove_unconfigured(config).map_err(Error::StaleJniLibsRemovalFailed)?;
+
+    update_dot_cargo(config, env, dot_cargo)?;
+    state::stamp_ndk(config, &env.ndk).map_err(Error::NdkStampFailed)
+}
+
+fn install_toolchains(config: &Config, env: &Env) -> Result<(), Error> {
+    let targets = config.targets();
+    for target in &targets {
+        target
+            .probe_ndk(&env.ndk, config.min_sdk_version())
+            .map_err(|cause| Error::TargetUnsupportedByNdk {
+                target: target.triple,
+                cause,
+            })?;
+    }
+    events::say("Installing Android toolchains...");
+    events::step(&RUSTUP_STEP, || {
+        util::toolchain::install_targets(targets.iter().map(|target| target.triple))
+    })
+    .map_err(Error::RustupFailed)
+}
+
+/// Everything `gen` does except generating the project, for when the Android
+/// Studio project is the user's own.
+pub fn adopt(config: &Config, env: &Env, dot_cargo: &mut dot_cargo::DotCargo) -> Result<(), Error> {
+    install_toolchains(config, env)?;
+    update_dot_cargo(config, env, dot_cargo)?;
+    state::stamp_ndk(config, &env.ndk).map_err(Error::NdkStampFailed)
+}
+
+/// Regenerates the cargo config entries for `android.targets`, which have
+/// the NDK's tool paths baked in.
+pub fn update_dot_cargo(
+    config: &Config,
+    env: &Env,
+    dot_cargo: &mut dot_cargo::DotCargo,
+) -> Result<(), Error> {
+    // Entries for targets that were dropped from `android.targets` would
+    // otherwise linger forever.
+    for target in Target::all_with_opt_in() {
+        dot_cargo.remove_target(target.triple);
+    }
+    for target in &config.targets() {
+        dot_cargo.insert_target(
+            target.triple.to_owned(),
+            target
+                .generate_cargo_config(config, env)
+                .map_err(Error::DotCargoGenFailed)?,
+        );
+    }
+    Ok(())
+}
+
+/// The NDK tools `dot_cargo` points `android.targets` at that don't exist,
+/// which means the project was generated on another machine (or against an
+/// NDK that's since been removed). Cargo doesn't expand env vars in `linker`
+/// or `ar`, so these paths have to be absolute.
+pub fn missing_tool_paths(config: &Config, dot_cargo: &dot_cargo::DotCargo) -> Vec<PathBuf> {
+    config
+        .targets()
+        .iter()
+        .filter_map(|target| dot_cargo.target(target.triple))
+        .flat_map(|target| target.linker.iter().chain(target.ar.iter()))
+        .map(PathBuf::from)
+        .filter(|path| !path.exists())
+        .collect()
+}
+
+/// Runs before every Android command. Our own builds override these paths
+/// anyway, but plain `cargo build --target` and rust-analyzer don't.
+pub fn warn_if_tool_paths_missing(config: &Config) {
+    let dot_cargo = match dot_cargo::DotCargo::load(config.app()) {
+        Ok(dot_cargo) => dot_cargo,
+        Err(err) => {
+            log::warn!(
+                "failed to check cargo config for stale NDK paths: {:?}",
+                err
             );
+            return;
         }
+    };
+    if let Some(path) = missing_tool_paths(config, &dot_cargo).first() {
+        log::warn!(
+            "`.cargo/config.toml` points at {:?}, which doesn't exist on this machine; run `cargo {} relocalize` to fix it",
+            path,
+            super::NAME,
+        );
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::{
+            self,
+            fixture::{self, Fixture},
+            metadata,
+        },
+        util::TempDirGuard,
+    };
+
+    fn fixture() -> Fixture {
+        fixture::app(
+            "repro",
+            "[android]\ntargets = [\"x86_64\", \"aarch64\", \"armv7\"]\n",
+            r#"
+            [package.metadata.cargo-android]
+            app-plugins = ["kotlin-android", "com.google.gms.google-services"]
+            app-dependencies = ["androidx.core:core-ktx:1.7.0", "androidx.appcompat:appcompat:1.4.1"]
+            asset-packs = [
+                { name = "levels", delivery-type = "fast-follow" },
+                { name = "music", delivery-type = "on-demand" },
+            ]
+            "#,
+        )
+    }
+
+    fn gen_into(config: &config::Config, metadata: &metadata::Metadata, dest: &Path) {
+        let packs = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates/platforms");
+        process_templates(
+            config.android(),
+            metadata.android(),
+            &config.build_a_bike(),
+            &templating::Filter::WildWest,
+            opts::Conflicts::Keep,
+            &packs.join(TEMPLATE_PACK),
+            &packs.join(ASSET_PACK_TEMPLATE_PACK),
+            dest,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn generation_is_reproducible() {
+        let fixture = fixture();
+        let gen = || {
+            let dest = TempDirGuard::new().unwrap();
+            gen_into(&fixture.config, &fixture.metadata, &dest);
+            templating::read_tree(&dest)
+        };
+        let first = gen();
+        assert!(first.len() > 1);
+        assert_eq!(first, gen());
+    }
+
+    #[test]
+    fn generated_project_is_portable() {
+        let fixture = fixture();
+        let root = fixture.root.to_str().unwrap().to_owned();
+        let dest = fixture.config.android().project_dir();
+        gen_into(&fixture.config, &fixture.metadata, &dest);
+        let files = templating::read_tree(&dest)
+            .into_iter()
+            .filter_map(|(rel, contents)| contents.map(|contents| (rel, contents)))
+            .collect::<Vec<_>>();
+        assert!(files.len() > 1);
+        for (rel, contents) in files {
+            assert!(
+                !String::from_utf8_lossy(&contents).contains(&root),
+                "{:?} has the app root baked in",
+                rel
+            );
+        }
+    }
+
+    #[test]
+    fn finds_missing_tool_paths() {
+        let fixture = fixture();
+        let project = &fixture.root;
+        let linker = project.join("clang");
+        fs::write(&linker, "").unwrap();
+        let missing = project.join("elsewhere/llvm-ar");
+        let mut dot_cargo = dot_cargo::DotCargo::default();
+        dot_cargo.insert_target(
+            "aarch64-linux-android",
+            dot_cargo::DotCargoTarget {
+                ar: Some(missing.display().to_string()),
+                linker: Some(linker.display().to_string()),
+                rustflags: Vec::new(),
+            },
+        );
+        // Not in `android.targets`, so it doesn't matter.
+        dot_cargo.insert_target(
+            "i686-linux-android",
+            dot_cargo::DotCargoTarget {
+                ar: Some("/nope/llvm-ar".to_owned()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            missing_tool_paths(fixture.config.android(), &dot_cargo),
+            vec![missing]
+        );
+    }
 }
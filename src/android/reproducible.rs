@@ -0,0 +1,414 @@
+//! Checks that building the same APK twice gives the same bytes, so
+//! nondeterminism is caught when it's introduced instead of when a release
+//! can't be verified.
+//!
+//! Both builds get the same `sourceDateEpoch`, which is `SOURCE_DATE_EPOCH`
+//! if it's set, so what's left usually comes from the tools we don't
+//! control: Rust artifacts embed absolute paths unless
+//! `--remap-path-prefix` is in `RUSTFLAGS`, signing with ECDSA keys or with
+//! timestamping produces different signatures every time, and on iOS,
+//! `codesign` and `xcodebuild -exportArchive` always stamp the current time.
+
+use super::{
+    config::Config,
+    env::{Env, LicensesError},
+    gradle::{self, property, GradleInvocation, TaskKind},
+    target::Target,
+};
+use crate::{
+    opts::{NoiseLevel, Profile},
+    util::{
+        self,
+        cli::{Report, Reportable},
+        timings,
+    },
+};
+use std::{
+    convert::TryInto as _,
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// What both builds are pinned to when `SOURCE_DATE_EPOCH` isn't set, since
+/// otherwise neither would drop anything that depends on the clock. It's the
+/// earliest time a zip entry can have.
+const FALLBACK_EPOCH: u64 = 315_532_800;
+
+#[derive(Debug)]
+pub enum ZipError {
+    EndMissing,
+    Truncated,
+}
+
+impl Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EndMissing => write!(f, "No end of central directory record was found."),
+            Self::Truncated => write!(f, "The central directory is truncated."),
+        }
+    }
+}
+
+/// What the central directory says about an entry, which is enough to tell
+/// where two archives differ without decompressing anything.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub crc32: u32,
+    pub size: u32,
+    /// The DOS time and date fields.
+    pub modified: (u16, u16),
+}
+
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const ENTRY_SIGNATURE: u32 = 0x0201_4b50;
+const END_LEN: usize = 22;
+const ENTRY_LEN: usize = 46;
+
+fn u16_at(bytes: &[u8], at: usize) -> Result<u16, ZipError> {
+    bytes
+        .get(at..at + 2)
+        .map(|field| u16::from_le_bytes(field.try_into().unwrap()))
+        .ok_or(ZipError::Truncated)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<u32, ZipError> {
+    bytes
+        .get(at..at + 4)
+        .map(|field| u32::from_le_bytes(field.try_into().unwrap()))
+        .ok_or(ZipError::Truncated)
+}
+
+/// Lists the entries of a zip archive (which APKs and AABs are), in the order
+/// they're stored.
+pub fn entries(bytes: &[u8]) -> Result<Vec<Entry>, ZipError> {
+    // The end record is followed by a comment of up to 64 KiB.
+    let end = (0..=bytes.len().saturating_sub(END_LEN))
+        .rev()
+        .take(END_LEN + usize::from(u16::MAX))
+        .find(|at| u32_at(bytes, *at).ok() == Some(END_SIGNATURE))
+        .ok_or(ZipError::EndMissing)?;
+    let count = u16_at(bytes, end + 10)?;
+    let mut at = u32_at(bytes, end + 16)? as usize;
+    let mut entries = Vec::with_capacity(count.into());
+    for _ in 0..count {
+        if u32_at(bytes, at)? != ENTRY_SIGNATURE {
+            return Err(ZipError::Truncated);
+        }
+        let name_len = usize::from(u16_at(bytes, at + 28)?);
+        let extra_len = usize::from(u16_at(bytes, at + 30)?);
+        let comment_len = usize::from(u16_at(bytes, at + 32)?);
+        let name = bytes
+            .get(at + ENTRY_LEN..at + ENTRY_LEN + name_len)
+            .ok_or(ZipError::Truncated)?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            crc32: u32_at(bytes, at + 16)?,
+            size: u32_at(bytes, at + 24)?,
+            modified: (u16_at(bytes, at + 12)?, u16_at(bytes, at + 14)?),
+        });
+        at += ENTRY_LEN + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Difference {
+    OnlyInFirst {
+        name: String,
+    },
+    OnlyInSecond {
+        name: String,
+    },
+    Order {
+        name: String,
+    },
+    Contents {
+        name: String,
+    },
+    Timestamp {
+        name: String,
+    },
+    /// Every entry matches, so it's down to how they're packed, or to the APK
+    /// signing block.
+    Structure,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OnlyInFirst { name } => write!(f, "{:?} is only in the first build.", name),
+            Self::OnlyInSecond { name } => write!(f, "{:?} is only in the second build.", name),
+            Self::Order { name } => write!(f, "{:?} is stored in a different order.", name),
+            Self::Contents { name } => write!(f, "{:?} has different contents.", name),
+            Self::Timestamp { name } => write!(f, "{:?} has a different timestamp.", name),
+            Self::Structure => write!(
+                f,
+                "Every entry matches, so the difference is in the zip structure or the APK signing block."
+            ),
+        }
+    }
+}
+
+/// The first way `second` differs from `first`, if it does at all.
+pub fn first_difference(first: &[u8], second: &[u8]) -> Result<Option<Difference>, ZipError> {
+    if first == second {
+        return Ok(None);
+    }
+    let (first, second) = (entries(first)?, entries(second)?);
+    for (index, entry) in first.iter().enumerate() {
+        let difference = match second.get(index) {
+            Some(other) if other == entry => continue,
+            Some(other) if other.name == entry.name => {
+                if other.crc32 != entry.crc32 || other.size != entry.size {
+                    Difference::Contents {
+                        name: entry.name.clone(),
+                    }
+                } else {
+                    Difference::Timestamp {
+                        name: entry.name.clone(),
+                    }
+                }
+            }
+            _ if second.iter().any(|other| other.name == entry.name) => Difference::Order {
+                name: entry.name.clone(),
+            },
+            _ => Difference::OnlyInFirst {
+                name: entry.name.clone(),
+            },
+        };
+        return Ok(Some(difference));
+    }
+    Ok(Some(match second.get(first.len()) {
+        Some(extra) => Difference::OnlyInSecond {
+            name: extra.name.clone(),
+        },
+        None => Difference::Structure,
+    }))
+}
+
+#[derive(Debug)]
+pub enum CheckError {
+    LicensesNotAccepted(LicensesError),
+    CleanFailed {
+        path: PathBuf,
+        source: io::Error,
+    },
+    AssembleFailed(bossy::Error),
+    ReadFailed {
+        path: PathBuf,
+        source: io::Error,
+    },
+    ZipInvalid {
+        path: PathBuf,
+        source: ZipError,
+    },
+    NotReproducible {
+        first: PathBuf,
+        second: PathBuf,
+        difference: Difference,
+    },
+}
+
+impl Reportable for CheckError {
+    fn report(&self) -> Report {
+        match self {
+            Self::LicensesNotAccepted(err) => err.report(),
+            Self::CleanFailed { path, source } => Report::error(
+                format!("Failed to clean reproducibility check build dir {:?}", path),
+                source,
+            ),
+            Self::AssembleFailed(err) => Report::error("Failed to assemble APK", err),
+            Self::ReadFailed { path, source } => {
+                Report::error(format!("Failed to read APK {:?}", path), source)
+            }
+            Self::ZipInvalid { path, source } => {
+                Report::error(format!("APK {:?} isn't a valid zip", path), source)
+            }
+            Self::NotReproducible {
+                first,
+                second,
+                difference,
+            } => Report::error(
+                format!("APKs {:?} and {:?} differ", first, second),
+                difference,
+            ),
+        }
+    }
+}
+
+fn assemble(
+    config: &Config,
+    env: &Env,
+    noise_level: NoiseLevel,
+    task: &str,
+    build_dir: &Path,
+    epoch: u64,
+) -> Result<(), CheckError> {
+    // Stale outputs would make gradle skip the work we want to compare.
+    if build_dir.is_dir() {
+        fs::remove_dir_all(build_dir).map_err(|source| CheckError::CleanFailed {
+            path: build_dir.to_owned(),
+            source,
+        })?;
+    }
+    let invocation = GradleInvocation::new()
+        .with_task(task)
+        .with_config(config)
+        .with_property(property::BUILD_DIR, build_dir.display())
+        .with_property(property::SOURCE_DATE_EPOCH, epoch)
+        .with_noise_level(noise_level);
+    log::info!("running {}", invocation);
+    timings::run_and_wait(format!("gradle {}", task), invocation.command(config, env))
+        .map_err(CheckError::AssembleFailed)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, CheckError> {
+    fs::read(path).map_err(|source| CheckError::ReadFailed {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Assembles the APK for `target` twice, each into a fresh build dir and with
+/// the same `sourceDateEpoch`, and fails with the first difference between
+/// them. Both builds are left in
+/// `app/build/reproducible` for inspection.
+pub fn check(
+    config: &Config,
+    env: &Env,
+    noise_level: NoiseLevel,
+    profile: Profile,
+    target: &Target<'_>,
+) -> Result<(), CheckError> {
+    env.check_licenses()
+        .map_err(CheckError::LicensesNotAccepted)?;
+    let task = gradle::task(TaskKind::Assemble, target.arch, profile);
+    let base = config.build_dir().join("reproducible");
+    let epoch = util::source_date_epoch().unwrap_or(FALLBACK_EPOCH);
+    let mut apks = Vec::new();
+    for name in &["first", "second"] {
+        let build_dir = base.join(name);
+        assemble(config, env, noise_level, &task, &build_dir, epoch)?;
+        apks.push(config.apk_path_in(&build_dir, profile, target.arch));
+    }
+    let (first, second) = (&apks[0], &apks[1]);
+    let difference = first_difference(&read(first)?, &read(second)?).map_err(|source| {
+        CheckError::ZipInvalid {
+            path: first.clone(),
+            source,
+        }
+    })?;
+    match difference {
+        Some(difference) => Err(CheckError::NotReproducible {
+            first: first.clone(),
+            second: second.clone(),
+            difference,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Just the central directory and end record, since that's all we read.
+    fn zip(entries: &[(&str, u32, u16)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (name, crc32, time) in entries {
+            bytes.extend_from_slice(&ENTRY_SIGNATURE.to_le_bytes());
+            bytes.extend_from_slice(&[0; 8]);
+            bytes.extend_from_slice(&time.to_le_bytes());
+            bytes.extend_from_slice(&0x5021u16.to_le_bytes());
+            bytes.extend_from_slice(&crc32.to_le_bytes());
+            bytes.extend_from_slice(&4u32.to_le_bytes());
+            bytes.extend_from_slice(&4u32.to_le_bytes());
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(&[0; 16]);
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        let size = bytes.len() as u32;
+        bytes.extend_from_slice(&END_SIGNATURE.to_le_bytes());
+        bytes.extend_from_slice(&[0; 6]);
+        bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&size.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn lists_entries() {
+        assert_eq!(
+            entries(&zip(&[
+                ("AndroidManifest.xml", 7, 0),
+                ("classes.dex", 9, 1)
+            ]))
+            .unwrap(),
+            vec![
+                Entry {
+                    name: "AndroidManifest.xml".to_owned(),
+                    crc32: 7,
+                    size: 4,
+                    modified: (0, 0x5021),
+                },
+                Entry {
+                    name: "classes.dex".to_owned(),
+                    crc32: 9,
+                    size: 4,
+                    modified: (1, 0x5021),
+                },
+            ]
+        );
+        assert!(matches!(entries(b"not a zip"), Err(ZipError::EndMissing)));
+    }
+
+    #[test]
+    fn finds_first_difference() {
+        let base = zip(&[("a", 1, 0), ("b", 2, 0), ("c", 3, 0)]);
+        let diff = |other: &[(&str, u32, u16)]| first_difference(&base, &zip(other)).unwrap();
+        assert_eq!(first_difference(&base, &base).unwrap(), None);
+        assert_eq!(
+            diff(&[("a", 1, 0), ("b", 2, 5), ("c", 4, 0)]),
+            Some(Difference::Timestamp {
+                name: "b".to_owned()
+            })
+        );
+        assert_eq!(
+            diff(&[("a", 1, 0), ("b", 2, 0), ("c", 4, 0)]),
+            Some(Difference::Contents {
+                name: "c".to_owned()
+            })
+        );
+        assert_eq!(
+            diff(&[("b", 2, 0), ("a", 1, 0), ("c", 3, 0)]),
+            Some(Difference::Order {
+                name: "a".to_owned()
+            })
+        );
+        assert_eq!(
+            diff(&[("a", 1, 0), ("c", 3, 0)]),
+            Some(Difference::OnlyInFirst {
+                name: "b".to_owned()
+            })
+        );
+        assert_eq!(
+            diff(&[("a", 1, 0), ("b", 2, 0), ("c", 3, 0), ("d", 4, 0)]),
+            Some(Difference::OnlyInSecond {
+                name: "d".to_owned()
+            })
+        );
+        // Same entries, but something else changed, like the signing block.
+        let mut signed = base.clone();
+        signed.insert(0, 0);
+        let offset = signed.len() - 6;
+        let cd_offset = u32_at(&signed, offset).unwrap() + 1;
+        signed[offset..offset + 4].copy_from_slice(&cd_offset.to_le_bytes());
+        assert_eq!(
+            first_difference(&base, &signed).unwrap(),
+            Some(Difference::Structure)
+        );
+    }
+}
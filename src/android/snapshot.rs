@@ -0,0 +1,194 @@
+use super::{adb, env::Env};
+use crate::util::cli::{Report, Reportable};
+use std::{
+    fs::{self, File},
+    io::{self, Read as _},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static RECORD_REMOTE_PATH: &str = "/data/local/tmp/cargo-mobile-screenrecord.mp4";
+// `screenrecord` stops by itself after this long, no matter what we ask for.
+pub const MAX_RECORD_SECONDS: u32 = 180;
+
+static PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    RecordDurationInvalid(u32),
+    CreateFailed { path: PathBuf, cause: io::Error },
+    ScreencapFailed(bossy::Error),
+    ScreenrecordFailed(bossy::Error),
+    PullFailed(bossy::Error),
+    ReadFailed { path: PathBuf, cause: io::Error },
+    Empty { path: PathBuf },
+    NotPng { path: PathBuf },
+}
+
+impl Reportable for SnapshotError {
+    fn report(&self) -> Report {
+        match self {
+            Self::RecordDurationInvalid(seconds) => Report::error(
+                format!("Can't record for {}s", seconds),
+                format!(
+                    "`screenrecord` only supports recordings between 1s and {}s long.",
+                    MAX_RECORD_SECONDS
+                ),
+            ),
+            Self::CreateFailed { path, cause } => {
+                Report::error(format!("Failed to create {:?}", path), cause)
+            }
+            Self::ScreencapFailed(err) => Report::error("Failed to capture screenshot", err),
+            Self::ScreenrecordFailed(err) => Report::error("Failed to record screen", err),
+            Self::PullFailed(err) => Report::error("Failed to pull recording from device", err),
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to check {:?}", path), cause)
+            }
+            Self::Empty { path } => Report::error(
+                "Device didn't send anything",
+                format!("{:?} is empty; make sure the device is unlocked and try again.", path),
+            ),
+            Self::NotPng { path } => Report::error(
+                "Screenshot is corrupt",
+                format!("{:?} isn't a valid PNG. If you're on Windows, make sure your `adb` is up to date, since older versions mangle line endings even with `exec-out`.", path),
+            ),
+        }
+    }
+}
+
+/// Checked up front, since otherwise we'd only find out once `screenrecord`
+/// has been running for a while.
+pub fn validate_record_seconds(seconds: u32) -> Result<(), SnapshotError> {
+    if seconds == 0 || seconds > MAX_RECORD_SECONDS {
+        Err(SnapshotError::RecordDurationInvalid(seconds))
+    } else {
+        Ok(())
+    }
+}
+
+fn stamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn default_screenshot_path() -> PathBuf {
+    PathBuf::from(format!("screenshot-{}.png", stamp()))
+}
+
+pub fn default_record_path() -> PathBuf {
+    PathBuf::from(format!("screenrecord-{}.mp4", stamp()))
+}
+
+// Returns the file's size. A failed capture tends to leave an empty file
+// behind rather than an error, and a mangled one won't start with the PNG
+// signature.
+fn check_output(path: &Path, png: bool) -> Result<u64, SnapshotError> {
+    let map_err = |cause| SnapshotError::ReadFailed {
+        path: path.to_owned(),
+        cause,
+    };
+    let len = fs::metadata(path).map_err(map_err)?.len();
+    if len == 0 {
+        return Err(SnapshotError::Empty {
+            path: path.to_owned(),
+        });
+    }
+    if png {
+        let mut signature = [0; 8];
+        let read = File::open(path)
+            .and_then(|mut file| file.read(&mut signature))
+            .map_err(map_err)?;
+        if signature[..read] != *PNG_SIGNATURE {
+            return Err(SnapshotError::NotPng {
+                path: path.to_owned(),
+            });
+        }
+    }
+    Ok(len)
+}
+
+/// Streams a PNG screenshot straight into `path`. This uses `exec-out`
+/// rather than `shell`, since `shell` goes through a pty that translates
+/// line endings.
+pub fn screenshot(env: &Env, serial_no: &str, path: &Path) -> Result<u64, SnapshotError> {
+    let file = File::create(path).map_err(|cause| SnapshotError::CreateFailed {
+        path: path.to_owned(),
+        cause,
+    })?;
+    adb::adb(env, serial_no)
+        .with_args(&["exec-out", "screencap", "-p"])
+        .with_stdout(bossy::Stdio::from(file))
+        .run_and_wait()
+        .map_err(SnapshotError::ScreencapFailed)?;
+    check_output(path, true)
+}
+
+/// Records the screen for `seconds` (without audio, which `screenrecord`
+/// doesn't support), then pulls the recording into `path`.
+pub fn record(env: &Env, serial_no: &str, seconds: u32, path: &Path) -> Result<u64, SnapshotError> {
+    validate_record_seconds(seconds)?;
+    println!("Recording for {}s...", seconds);
+    let result = adb::adb(env, serial_no)
+        .with_args(&["shell", "screenrecord", "--time-limit"])
+        .with_arg(seconds.to_string())
+        .with_arg(RECORD_REMOTE_PATH)
+        .run_and_wait()
+        .map_err(SnapshotError::ScreenrecordFailed)
+        .and_then(|_| {
+            adb::adb(env, serial_no)
+                .with_args(&["pull", RECORD_REMOTE_PATH])
+                .with_arg(path)
+                .run_and_wait()
+                .map_err(SnapshotError::PullFailed)
+        });
+    // Cleanup is best-effort, since a leftover recording is harmless and gets
+    // overwritten next time anyway.
+    if let Err(err) = adb::adb(env, serial_no)
+        .with_args(&["shell", "rm", "-f", RECORD_REMOTE_PATH])
+        .run_and_wait()
+    {
+        log::warn!("failed to remove recording from device: {}", err);
+    }
+    result?;
+    check_output(path, false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    #[test]
+    fn record_duration_is_bounded() {
+        assert!(validate_record_seconds(1).is_ok());
+        assert!(validate_record_seconds(MAX_RECORD_SECONDS).is_ok());
+        assert!(validate_record_seconds(0).is_err());
+        assert!(validate_record_seconds(MAX_RECORD_SECONDS + 1).is_err());
+    }
+
+    #[test]
+    fn checks_output() {
+        let dir = TempDirGuard::new().unwrap();
+        let empty = dir.join("empty.png");
+        fs::write(&empty, "").unwrap();
+        assert!(matches!(
+            check_output(&empty, true),
+            Err(SnapshotError::Empty { .. })
+        ));
+
+        let png = dir.join("ok.png");
+        fs::write(&png, b"\x89PNG\r\n\x1a\nrest").unwrap();
+        assert_eq!(check_output(&png, true).unwrap(), 12);
+
+        // This is what a pty's line ending translation does to a PNG.
+        let mangled = dir.join("mangled.png");
+        fs::write(&mangled, b"\x89PNG\r\r\n\x1a\r\nrest").unwrap();
+        assert!(matches!(
+            check_output(&mangled, true),
+            Err(SnapshotError::NotPng { .. })
+        ));
+        assert!(check_output(&mangled, false).is_ok());
+    }
+}
@@ -0,0 +1,200 @@
+//! What the Android project was last generated against. `.cargo/config.toml`
+//! bakes in absolute NDK tool paths, so upgrading the NDK afterwards either
+//! breaks builds outright or quietly keeps using the old toolchain.
+
+use super::{config::Config, ndk};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+static FILE_NAME: &str = ".cargo-mobile-state.toml";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to read Android project state from {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to deserialize Android project state at {path:?}: {source}")]
+    DeserializeFailed {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Failed to serialize Android project state: {0}")]
+    SerializeFailed(#[from] toml::ser::Error),
+    #[error("Failed to write Android project state to {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: io::Error },
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NdkStamp {
+    pub home: PathBuf,
+    pub version: String,
+}
+
+impl NdkStamp {
+    /// `Env::new` already checked the version, so this is only a file read.
+    pub fn current(ndk: &ndk::Env) -> Self {
+        Self {
+            home: ndk.home().to_owned(),
+            version: ndk
+                .version()
+                .map(|revision| revision.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct State {
+    ndk: Option<NdkStamp>,
+}
+
+impl State {
+    pub fn path(config: &Config) -> PathBuf {
+        // An adopted project isn't ours to leave files in.
+        if config.adopted() {
+            config.app().root_dir().join(FILE_NAME)
+        } else {
+            config.project_dir().join(FILE_NAME)
+        }
+    }
+
+    /// `None` if there's no state yet, i.e. for projects generated before
+    /// there was any.
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let bytes = fs::read(path).map_err(|source| Error::ReadFailed {
+            path: path.to_owned(),
+            source,
+        })?;
+        toml::from_slice(&bytes)
+            .map(Some)
+            .map_err(|source| Error::DeserializeFailed {
+                path: path.to_owned(),
+                source,
+            })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let ser = toml::to_string_pretty(self)?;
+        fs::write(path, ser).map_err(|source| Error::WriteFailed {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    pub fn ndk(&self) -> Option<&NdkStamp> {
+        self.ndk.as_ref()
+    }
+}
+
+/// Records `ndk` as what the project's cargo config was generated against,
+/// keeping anything else already in the state.
+pub fn stamp_ndk(config: &Config, ndk: &ndk::Env) -> Result<(), Error> {
+    let path = State::path(config);
+    let mut state = State::load(&path)?.unwrap_or_default();
+    state.ndk = Some(NdkStamp::current(ndk));
+    state.write(&path)
+}
+
+/// `Some((generated, current))` if the project was generated against a
+/// different NDK than `current`. `generated` is `None` if the project doesn't
+/// say, i.e. if it was generated before there was any state, since then
+/// there's no telling whether it matches.
+pub fn ndk_mismatch(state: &State, current: NdkStamp) -> Option<(Option<NdkStamp>, NdkStamp)> {
+    match state.ndk() {
+        Some(generated) if *generated == current => None,
+        generated => Some((generated.cloned(), current)),
+    }
+}
+
+/// Runs before every Android command, so it only reads one small file.
+pub fn warn_if_ndk_changed(config: &Config, ndk: &ndk::Env) {
+    // Nothing's been generated yet, so there's nothing to be out of date.
+    if !config.project_dir_exists() {
+        return;
+    }
+    let state = match State::load(&State::path(config)) {
+        Ok(state) => state.unwrap_or_default(),
+        Err(err) => {
+            log::warn!("{}", err);
+            return;
+        }
+    };
+    match ndk_mismatch(&state, NdkStamp::current(ndk)) {
+        Some((Some(generated), current)) => log::warn!(
+            "the Android project was generated against NDK {} at {:?}, but `NDK_HOME` is now NDK {} at {:?}; `.cargo/config.toml` still points at the old toolchain, so run `cargo {} update-ndk` to fix it",
+            generated.version,
+            generated.home,
+            current.version,
+            current.home,
+            super::NAME,
+        ),
+        Some((None, current)) => log::warn!(
+            "the Android project doesn't record which NDK it was generated against, so `.cargo/config.toml` might point at a different toolchain than NDK {} at {:?}; run `cargo {} update-ndk` to make sure it doesn't",
+            current.version,
+            current.home,
+            super::NAME,
+        ),
+        None => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    fn stamp(home: &str, version: &str) -> NdkStamp {
+        NdkStamp {
+            home: home.into(),
+            version: version.to_owned(),
+        }
+    }
+
+    #[test]
+    fn detects_mismatch() {
+        let state = State {
+            ndk: Some(stamp("/sdk/ndk/25.2.9519653", "25.2.9519653")),
+        };
+        assert_eq!(
+            ndk_mismatch(&state, stamp("/sdk/ndk/25.2.9519653", "25.2.9519653")),
+            None
+        );
+        assert_eq!(
+            ndk_mismatch(&state, stamp("/sdk/ndk/26.1.10909125", "26.1.10909125")),
+            Some((
+                Some(stamp("/sdk/ndk/25.2.9519653", "25.2.9519653")),
+                stamp("/sdk/ndk/26.1.10909125", "26.1.10909125")
+            ))
+        );
+        // Upgrading in place keeps the path, but not the version.
+        assert!(ndk_mismatch(&state, stamp("/sdk/ndk-bundle", "26.1.10909125")).is_some());
+        // Without a stamp, there's no telling.
+        assert_eq!(
+            ndk_mismatch(&State::default(), stamp("/sdk/ndk-bundle", "26.1.10909125")),
+            Some((None, stamp("/sdk/ndk-bundle", "26.1.10909125")))
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let dir = TempDirGuard::new().unwrap();
+        let path = dir.join(FILE_NAME);
+        assert!(State::load(&path).unwrap().is_none());
+        let state = State {
+            ndk: Some(stamp("/sdk/ndk/25.2.9519653", "25.2.9519653")),
+        };
+        state.write(&path).unwrap();
+        assert_eq!(
+            State::load(&path).unwrap().unwrap().ndk(),
+            Some(&stamp("/sdk/ndk/25.2.9519653", "25.2.9519653"))
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Strips release libs before they're packaged, while keeping the unstripped
+//! originals around for `ndk-stack`.
+
+use super::{
+    aab,
+    config::Config,
+    jnilibs::{self, Strategy},
+    ndk,
+    target::Target,
+};
+use crate::opts::Profile;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+static STRIPPED_DIR: &str = "stripped";
+
+#[derive(Debug, Error)]
+pub enum StripError {
+    #[error(transparent)]
+    MissingTool(ndk::MissingToolError),
+    #[error("Failed to create directory {path:?}: {source}")]
+    DirCreationFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to strip {path:?}: {source}")]
+    StripFailed { path: PathBuf, source: bossy::Error },
+    #[error("Failed to copy unstripped lib to {dest:?}: {source}")]
+    CopyFailed { dest: PathBuf, source: io::Error },
+}
+
+/// The stripped lib that gets packaged in place of `unstripped`, which is
+/// kept in a `stripped` dir next to it so the two are never confused.
+pub fn stripped_path(unstripped: &Path) -> PathBuf {
+    let file_name = unstripped
+        .file_name()
+        .expect("developer error: lib had no file name");
+    unstripped
+        .parent()
+        .map_or_else(|| PathBuf::from(STRIPPED_DIR), |dir| dir.join(STRIPPED_DIR))
+        .join(file_name)
+}
+
+/// The inverse of `stripped_path`, or `None` if `path` isn't a stripped lib.
+pub fn unstripped_path(path: &Path) -> Option<PathBuf> {
+    let dir = path.parent()?;
+    if dir.file_name()? == STRIPPED_DIR {
+        Some(dir.parent()?.join(path.file_name()?))
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Plan {
+    pub unstripped: PathBuf,
+    pub stripped: PathBuf,
+    pub symbols_copy: Option<PathBuf>,
+}
+
+impl Plan {
+    /// `None` if `unstripped` should be packaged as-is, which is always the
+    /// case for debug builds.
+    pub fn new(
+        unstripped: &Path,
+        profile: Profile,
+        strip: bool,
+        symbols_dir: Option<&Path>,
+    ) -> Option<Self> {
+        if !strip || profile.debug() {
+            return None;
+        }
+        Some(Self {
+            unstripped: unstripped.to_owned(),
+            stripped: stripped_path(unstripped),
+            symbols_copy: symbols_dir
+                .and_then(|dir| unstripped.file_name().map(|file_name| dir.join(file_name))),
+        })
+    }
+
+    pub fn run(&self, ndk: &ndk::Env, triple: &str) -> Result<(), StripError> {
+        let strip = ndk
+            .binutil_path(ndk::Binutil::Strip, triple)
+            .map_err(StripError::MissingTool)?;
+        let create_parent = |path: &Path| {
+            let dir = path.parent().expect("developer error: lib had no parent");
+            fs::create_dir_all(dir).map_err(|source| StripError::DirCreationFailed {
+                path: dir.to_owned(),
+                source,
+            })
+        };
+        create_parent(&self.stripped)?;
+        bossy::Command::impure(strip)
+            .with_arg("--strip-unneeded")
+            .with_arg("-o")
+            .with_arg(&self.stripped)
+            .with_arg(&self.unstripped)
+            .run_and_wait_for_output()
+            .map_err(|source| StripError::StripFailed {
+                path: self.unstripped.clone(),
+                source,
+            })?;
+        if let (Ok(before), Ok(after)) =
+            (fs::metadata(&self.unstripped), fs::metadata(&self.stripped))
+        {
+            log::info!(
+                "stripped {:?} from {} to {}",
+                self.unstripped,
+                aab::format_size(before.len()),
+                aab::format_size(after.len())
+            );
+        }
+        if let Some(dest) = &self.symbols_copy {
+            create_parent(dest)?;
+            fs::copy(&self.unstripped, dest).map_err(|source| StripError::CopyFailed {
+                dest: dest.clone(),
+                source,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Where `ndk-stack` should look for symbols for `target`, which is wherever
+/// the unstripped copy of the packaged lib is.
+pub fn symbols_dir(config: &Config, target: Target<'_>) -> PathBuf {
+    match config.jnilibs_strategy() {
+        Strategy::Symlink => {
+            let jnilibs = jnilibs::path(config, target);
+            fs::read_link(jnilibs.join(config.so_name()))
+                .ok()
+                .and_then(|linked| unstripped_path(&linked))
+                .and_then(|unstripped| unstripped.parent().map(Path::to_owned))
+                .unwrap_or(jnilibs)
+        }
+        // Copies don't point back at what they were copied from, so this
+        // goes with whichever profile was built last.
+        Strategy::Direct => {
+            let out_dir = |profile: Profile| {
+                config
+                    .cargo_target_dir()
+                    .join(target.triple)
+                    .join(profile.as_str())
+            };
+            [Profile::Debug, Profile::Release]
+                .iter()
+                .filter_map(|profile| {
+                    let dir = out_dir(*profile);
+                    let modified = fs::metadata(dir.join(config.so_name()))
+                        .and_then(|metadata| metadata.modified())
+                        .ok()?;
+                    Some((modified, dir))
+                })
+                .max_by_key(|(modified, _)| *modified)
+                .map(|(_, dir)| dir)
+                .unwrap_or_else(|| out_dir(Profile::Debug))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn pairs_stripped_with_unstripped() {
+        let unstripped = Path::new("target/aarch64-linux-android/release/libgame.so");
+        let stripped = stripped_path(unstripped);
+        assert_eq!(
+            stripped,
+            Path::new("target/aarch64-linux-android/release/stripped/libgame.so")
+        );
+        assert_eq!(unstripped_path(&stripped).as_deref(), Some(unstripped));
+        assert_eq!(unstripped_path(unstripped), None);
+    }
+
+    #[rstest(
+        profile,
+        strip,
+        stripped,
+        case(Profile::Debug, true, false),
+        case(Profile::Debug, false, false),
+        case(Profile::Release, true, true),
+        case(Profile::Release, false, false)
+    )]
+    fn only_strips_release_builds(profile: Profile, strip: bool, stripped: bool) {
+        let plan = Plan::new(Path::new("release/libgame.so"), profile, strip, None);
+        assert_eq!(plan.is_some(), stripped);
+    }
+
+    #[test]
+    fn copies_symbols_to_configured_dir() {
+        let plan = Plan::new(
+            Path::new("release/libgame.so"),
+            Profile::Release,
+            true,
+            Some(Path::new("symbols/arm64-v8a")),
+        )
+        .unwrap();
+        assert_eq!(
+            plan,
+            Plan {
+                unstripped: "release/libgame.so".into(),
+                stripped: "release/stripped/libgame.so".into(),
+                symbols_copy: Some("symbols/arm64-v8a/libgame.so".into()),
+            }
+        );
+    }
+}
@@ -1,8 +1,12 @@
 use super::{
-    config::{Config, Metadata},
+    aab,
+    cargo_config::{self, Field},
+    config::{Config, EntryPoint, Metadata},
+    elf,
     env::Env,
     jnilibs::{self, JniLibs},
     ndk,
+    strip::{self, Plan},
 };
 use crate::{
     dot_cargo::DotCargoTarget,
@@ -10,12 +14,22 @@ use crate::{
     target::TargetTrait,
     util::{
         cli::{Report, Reportable},
-        CargoCommand,
+        diagnostics::{self, Hint, Inspector, Outcome},
+        prefixed::Prefix,
+        timings, CargoCommand,
     },
 };
 use once_cell_regex::exports::once_cell::sync::OnceCell;
 use serde::Serialize;
-use std::{collections::BTreeMap, fmt, io, str};
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str,
+};
+
+/// APK entries this big need zip64, which the package manager can't read.
+const ZIP_ENTRY_LIMIT: u64 = u32::MAX as u64;
 
 #[derive(Clone, Copy, Debug)]
 pub enum CargoMode {
@@ -47,6 +61,7 @@ pub enum CompileLibError {
     CargoFailed {
         mode: CargoMode,
         cause: bossy::Error,
+        hints: Vec<Hint>,
     },
 }
 
@@ -54,9 +69,10 @@ impl Reportable for CompileLibError {
     fn report(&self) -> Report {
         match self {
             Self::MissingTool(err) => Report::error("Failed to locate required build tool", err),
-            Self::CargoFailed { mode, cause } => {
-                Report::error(format!("`Failed to run `cargo {}`", mode), cause)
-            }
+            Self::CargoFailed { mode, cause, hints } => Report::error(
+                format!("`Failed to run `cargo {}`", mode),
+                diagnostics::with_hints(cause, hints),
+            ),
         }
     }
 }
@@ -67,6 +83,9 @@ pub enum SymlinkLibsError {
     SymlinkFailed(jnilibs::SymlinkLibError),
     RequiredLibsFailed(ndk::RequiredLibsError),
     LibcxxSharedPathFailed(ndk::MissingToolError),
+    StripFailed(strip::StripError),
+    ExportedSymbolsFailed(elf::Error),
+    EntryPointMissing { lib: PathBuf, symbol: String },
 }
 
 impl Reportable for SymlinkLibsError {
@@ -80,6 +99,15 @@ impl Reportable for SymlinkLibsError {
             Self::LibcxxSharedPathFailed(err) => {
                 Report::error("Failed to locate \"libc++_shared.so\"", err)
             }
+            Self::StripFailed(err) => Report::error("Failed to strip lib", err),
+            Self::ExportedSymbolsFailed(err) => err.report(),
+            Self::EntryPointMissing { lib, symbol } => Report::action_request(
+                format!("{:?} doesn't export `{}`, so the app would crash as soon as it starts", lib, symbol),
+                format!(
+                    "Make sure `crate-type` in the `[lib]` section of your `Cargo.toml` includes \"cdylib\", and that `{}` is defined with `#[no_mangle] pub extern \"C\"` (which `#[ndk_glue::main]` does for you). If it's defined in a dependency, that dependency has to be used from your lib, or the linker drops it. If your activity isn't `NativeActivity`, set `entry-point` in the `[android]` section of `mobile.toml` to the symbol it needs, or to `false` to skip this check.",
+                    symbol
+                ),
+            ),
         }
     }
 }
@@ -90,6 +118,53 @@ pub enum BuildError {
     SymlinkLibsFailed(SymlinkLibsError),
 }
 
+/// What to say about a packaged lib of `size` bytes, if anything.
+fn lib_size_warning(size: u64, threshold: u64) -> Option<String> {
+    if size >= ZIP_ENTRY_LIMIT {
+        Some(format!(
+            "it's {}, which is too big to fit in an APK at all; installing will fail with `INSTALL_FAILED_INVALID_APK`",
+            aab::format_size(size)
+        ))
+    } else if size >= threshold {
+        Some(format!(
+            "it's {}, which can make packaging or installing fail; reducing `debug` in your Cargo profile or stripping it would help",
+            aab::format_size(size)
+        ))
+    } else {
+        None
+    }
+}
+
+fn check_lib_size(lib: &Path, threshold: u64) {
+    match fs::metadata(lib) {
+        Ok(metadata) => {
+            if let Some(warning) = lib_size_warning(metadata.len(), threshold) {
+                log::warn!("lib {:?} is very large; {}", lib, warning);
+            }
+        }
+        Err(err) => log::warn!("failed to get size of lib {:?}: {}", lib, err),
+    }
+}
+
+fn check_entry_point(lib: &Path, entry_point: &EntryPoint) -> Result<(), SymlinkLibsError> {
+    let exported = elf::exported_symbols(lib).map_err(SymlinkLibsError::ExportedSymbolsFailed)?;
+    if exported.contains(&entry_point.symbol) {
+        Ok(())
+    } else if entry_point.required {
+        Err(SymlinkLibsError::EntryPointMissing {
+            lib: lib.to_owned(),
+            symbol: entry_point.symbol.clone(),
+        })
+    } else {
+        log::warn!(
+            "lib {:?} doesn't export `{}`, which your activity is likely to need; if it needs something else, set `entry-point` in the `[android]` section of `mobile.toml` to that symbol, or to `false` to skip this check",
+            lib,
+            entry_point.symbol
+        );
+        Ok(())
+    }
+}
+
 impl Reportable for BuildError {
     fn report(&self) -> Report {
         match self {
@@ -166,6 +241,23 @@ impl<'a> TargetTrait<'a> for Target<'a> {
     fn arch(&'a self) -> &'a str {
         self.arch
     }
+
+    // The opt-in targets aren't in `all`, but they can still be asked for by
+    // name.
+    fn for_name(name: &str) -> Option<&'a Self> {
+        Self::all()
+            .get(name)
+            .or_else(|| Self::opt_in_all().get(name))
+    }
+}
+
+fn injected(triple: &str, tools: DotCargoTarget) -> Vec<cargo_config::Entry> {
+    vec![(Field::Linker, tools.linker), (Field::Ar, tools.ar)]
+        .into_iter()
+        .filter_map(|(field, value)| {
+            value.map(|value| cargo_config::injected_entry(triple, field, value))
+        })
+        .collect()
 }
 
 impl<'a> Target<'a> {
@@ -177,8 +269,72 @@ impl<'a> Target<'a> {
         self.binutils_triple_override.unwrap_or_else(|| self.triple)
     }
 
+    /// The targets that only get built when they're in `android.targets`,
+    /// which are kept out of `all` so nothing installs or builds them
+    /// otherwise.
+    pub fn opt_in_all() -> &'a BTreeMap<&'a str, Self> {
+        static TARGETS: OnceCell<BTreeMap<&'static str, Target<'static>>> = OnceCell::new();
+        TARGETS.get_or_init(|| {
+            let mut targets = BTreeMap::new();
+            // Needs NDK r27 or later.
+            targets.insert(
+                "riscv64",
+                Target {
+                    triple: "riscv64-linux-android",
+                    clang_triple_override: None,
+                    binutils_triple_override: None,
+                    abi: "riscv64",
+                    arch: "riscv64",
+                },
+            );
+            targets
+        })
+    }
+
+    /// Every target, including the opt-in ones.
+    pub fn all_with_opt_in() -> impl Iterator<Item = &'a Self> {
+        Self::all().values().chain(Self::opt_in_all().values())
+    }
+
+    /// Every name `android.targets` and the target args take, which includes
+    /// the opt-in targets.
+    pub fn name_list_with_opt_in() -> &'static [&'a str]
+    where
+        Self: 'static,
+    {
+        static INSTANCE: OnceCell<Vec<&str>> = OnceCell::new();
+        INSTANCE.get_or_init(|| {
+            Self::all()
+                .keys()
+                .chain(Self::opt_in_all().keys())
+                .copied()
+                .collect()
+        })
+    }
+
     pub fn for_abi(abi: &str) -> Option<&'a Self> {
-        Self::all().values().find(|target| target.abi == abi)
+        Self::all_with_opt_in().find(|target| target.abi == abi)
+    }
+
+    /// Checks that the NDK ships a toolchain for this target.
+    pub fn probe_ndk(
+        &self,
+        ndk: &ndk::Env,
+        min_sdk_version: u32,
+    ) -> Result<(), ndk::MissingToolError> {
+        ndk.compiler_path(ndk::Compiler::Clang, self.clang_triple(), min_sdk_version)
+            .map(|_| ())
+    }
+
+    /// What `compile_lib` sets the linker and `ar` to through the
+    /// environment, which wins over every cargo config.
+    pub fn injected_cargo_config(
+        &self,
+        config: &Config,
+        env: &Env,
+    ) -> Result<Vec<cargo_config::Entry>, ndk::MissingToolError> {
+        self.generate_cargo_config(config, env)
+            .map(|tools| injected(self.triple, tools))
     }
 
     pub fn generate_cargo_config(
@@ -221,12 +377,18 @@ impl<'a> Target<'a> {
         force_color: ForceColor,
         profile: Profile,
         mode: CargoMode,
-    ) -> Result<(), CompileLibError> {
+        prefix: Option<&Prefix>,
+    ) -> Result<Outcome, CompileLibError> {
         let min_sdk_version = config.min_sdk_version();
         // Force color, since gradle would otherwise give us uncolored output
         // (which Android Studio makes red, which is extra gross!)
         let color = if force_color.yes() { "always" } else { "auto" };
-        CargoCommand::new(mode.as_str())
+        // `.cargo/config.toml` has the NDK paths of whichever machine
+        // generated it, so we override them with this machine's.
+        let tools = self
+            .generate_cargo_config(config, env)
+            .map_err(CompileLibError::MissingTool)?;
+        let command = CargoCommand::new(mode.as_str())
             .with_verbose(noise_level.pedantic())
             .with_package(Some(config.app().name()))
             .with_manifest_path(Some(config.app().manifest_path()))
@@ -234,6 +396,7 @@ impl<'a> Target<'a> {
             .with_no_default_features(metadata.no_default_features())
             .with_features(metadata.features())
             .with_release(profile.release())
+            .with_message_format(Some(diagnostics::message_format(force_color.yes())))
             .into_command_pure(env)
             .with_env_var("ANDROID_NATIVE_API_LEVEL", min_sdk_version.to_string())
             .with_env_var(
@@ -254,10 +417,37 @@ impl<'a> Target<'a> {
                     .compiler_path(ndk::Compiler::Clangxx, self.clang_triple(), min_sdk_version)
                     .map_err(CompileLibError::MissingTool)?,
             )
-            .with_args(&["--color", color])
-            .run_and_wait()
-            .map_err(|cause| CompileLibError::CargoFailed { mode, cause })?;
-        Ok(())
+            .with_args(&["--color", color]);
+        let command = injected(self.triple, tools)
+            .into_iter()
+            .fold(command, |command, entry| {
+                command.with_env_var(entry.field.env_var(&entry.triple), entry.value)
+            });
+        let command = if prefix.is_some() {
+            // Progress bars redraw in place, which turns into a mess once
+            // every line is prefixed.
+            command.with_env_var("CARGO_TERM_PROGRESS_WHEN", "never")
+        } else {
+            command
+        };
+        timings::step(format!("cargo {} {}", mode.as_str(), self.triple), || {
+            let (result, outcome) = diagnostics::run_and_wait(
+                command,
+                Inspector::new(config.app().name(), "cdylib"),
+                prefix,
+            );
+            match result {
+                Ok(()) => {
+                    outcome.warn();
+                    Ok(outcome)
+                }
+                Err(cause) => Err(CompileLibError::CargoFailed {
+                    mode,
+                    cause,
+                    hints: outcome.hints,
+                }),
+            }
+        })
     }
 
     pub fn check(
@@ -276,26 +466,47 @@ impl<'a> Target<'a> {
             force_color,
             Profile::Debug,
             CargoMode::Check,
+            None,
         )
+        .map(|_| ())
     }
 
-    pub fn symlink_libs(
+    /// `built` is where cargo said it put the lib, if it said. Otherwise, the
+    /// lib's looked for where cargo usually puts it.
+    pub fn install_libs(
         &self,
         config: &Config,
         ndk: &ndk::Env,
         profile: Profile,
+        built: Option<&Path>,
     ) -> Result<(), SymlinkLibsError> {
-        let jnilibs =
-            JniLibs::create(config, *self).map_err(SymlinkLibsError::JniLibsCreationFailed)?;
-
-        let src = config.app().prefix_path(format!(
-            "target/{}/{}/{}",
-            &self.triple,
-            profile.as_str(),
-            config.so_name(),
-        ));
+        let jnilibs = JniLibs::create(config, *self, profile)
+            .map_err(SymlinkLibsError::JniLibsCreationFailed)?;
+
+        let src = built.map(ToOwned::to_owned).unwrap_or_else(|| {
+            config
+                .cargo_target_dir()
+                .join(self.triple)
+                .join(profile.as_str())
+                .join(config.so_name())
+        });
+        // The unstripped lib stays where cargo put it, so `ndk-stack` can
+        // still find its symbols.
+        let symbols_dir = config.debug_symbols_dir().map(|dir| dir.join(self.abi));
+        let packaged = match Plan::new(&src, profile, config.strip(), symbols_dir.as_deref()) {
+            Some(plan) => {
+                plan.run(ndk, self.binutils_triple())
+                    .map_err(SymlinkLibsError::StripFailed)?;
+                plan.stripped
+            }
+            None => src.clone(),
+        };
+        check_lib_size(&packaged, config.lib_size_warning());
+        if let Some(entry_point) = config.entry_point() {
+            check_entry_point(&packaged, entry_point)?;
+        }
         jnilibs
-            .symlink_lib(&src)
+            .install_lib(&packaged)
             .map_err(SymlinkLibsError::SymlinkFailed)?;
 
         let needs_cxx_shared = ndk
@@ -308,7 +519,7 @@ impl<'a> Target<'a> {
                 .libcxx_shared_path(*self)
                 .map_err(SymlinkLibsError::LibcxxSharedPathFailed)?;
             jnilibs
-                .symlink_lib(&cxx_shared)
+                .install_lib(&cxx_shared)
                 .map_err(SymlinkLibsError::SymlinkFailed)?;
         }
 
@@ -323,18 +534,63 @@ impl<'a> Target<'a> {
         noise_level: NoiseLevel,
         force_color: ForceColor,
         profile: Profile,
+        prefix: Option<&Prefix>,
     ) -> Result<(), BuildError> {
-        self.compile_lib(
+        let outcome = self
+            .compile_lib(
+                config,
+                metadata,
+                env,
+                noise_level,
+                force_color,
+                profile,
+                CargoMode::Build,
+                prefix,
+            )
+            .map_err(BuildError::BuildFailed)?;
+        self.install_libs(
             config,
-            metadata,
-            env,
-            noise_level,
-            force_color,
+            &env.ndk,
             profile,
-            CargoMode::Build,
+            outcome.lib_path(config.app().name(), "so"),
+        )
+        .map_err(BuildError::SymlinkLibsFailed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        size,
+        expected,
+        case(120_000_000, None),
+        case(
+            500_000_000,
+            Some("it's 500.0 MB, which can make packaging or installing fail; reducing `debug` in your Cargo profile or stripping it would help")
+        ),
+        case(
+            4_294_967_295,
+            Some("it's 4295.0 MB, which is too big to fit in an APK at all; installing will fail with `INSTALL_FAILED_INVALID_APK`")
         )
-        .map_err(BuildError::BuildFailed)?;
-        self.symlink_libs(config, &env.ndk, profile)
-            .map_err(BuildError::SymlinkLibsFailed)
+    )]
+    fn warns_about_lib_size(size: u64, expected: Option<&str>) {
+        assert_eq!(lib_size_warning(size, 500_000_000).as_deref(), expected);
+    }
+
+    #[test]
+    fn opt_in_targets_are_only_found_by_name() {
+        assert!(!Target::all().contains_key("riscv64"));
+        assert!(!Target::name_list().contains(&"riscv64"));
+        assert_eq!(
+            Target::for_name("riscv64").map(|target| target.triple),
+            Some("riscv64-linux-android")
+        );
+        assert_eq!(
+            Target::for_abi("riscv64").map(|target| target.triple),
+            Some("riscv64-linux-android")
+        );
     }
 }
@@ -0,0 +1,187 @@
+use super::{adb, config::Config, env::Env};
+use crate::util::cli::{Report, Reportable};
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static PERFETTO_REMOTE_PATH: &str = "/data/misc/perfetto-traces/cargo-mobile.trace";
+static ATRACE_REMOTE_PATH: &str = "/data/local/tmp/cargo-mobile.atrace";
+
+#[derive(Debug)]
+pub enum TraceError {
+    DirCreationFailed { path: PathBuf, cause: io::Error },
+    PerfettoFailed(bossy::Error),
+    ConfigWriteFailed(io::Error),
+    AtraceFailed(bossy::Error),
+    PullFailed(bossy::Error),
+}
+
+impl Reportable for TraceError {
+    fn report(&self) -> Report {
+        match self {
+            Self::DirCreationFailed { path, cause } => Report::error(
+                format!("Failed to create trace directory at {:?}", path),
+                cause,
+            ),
+            Self::PerfettoFailed(err) => Report::error("Failed to capture Perfetto trace", err),
+            Self::ConfigWriteFailed(err) => {
+                Report::error("Failed to send trace config to `perfetto`", err)
+            }
+            Self::AtraceFailed(err) => Report::error("Failed to capture atrace trace", err),
+            Self::PullFailed(err) => Report::error("Failed to pull trace from device", err),
+        }
+    }
+}
+
+fn perfetto_config(app_id: &str, seconds: u32) -> String {
+    format!(
+        r#"buffers: {{ size_kb: 63488 fill_policy: RING_BUFFER }}
+buffers: {{ size_kb: 2048 fill_policy: RING_BUFFER }}
+data_sources: {{
+  config {{
+    name: "linux.ftrace"
+    target_buffer: 0
+    ftrace_config {{
+      ftrace_events: "sched/sched_switch"
+      ftrace_events: "sched/sched_waking"
+      ftrace_events: "power/cpu_frequency"
+      ftrace_events: "power/cpu_idle"
+      ftrace_events: "power/gpu_frequency"
+      atrace_categories: "gfx"
+      atrace_categories: "view"
+      atrace_apps: "{}"
+    }}
+  }}
+}}
+data_sources: {{
+  config {{
+    name: "linux.process_stats"
+    target_buffer: 1
+  }}
+}}
+duration_ms: {}
+"#,
+        app_id,
+        u64::from(seconds) * 1000
+    )
+}
+
+fn has_perfetto(env: &Env, serial_no: &str) -> bool {
+    // Exit codes aren't propagated by `adb shell` on older devices, so we go
+    // by the output instead.
+    adb::adb(env, serial_no)
+        .with_args(&["shell", "command", "-v", "perfetto"])
+        .run_and_wait_for_string()
+        .map(|path| !path.trim().is_empty())
+        .unwrap_or(false)
+}
+
+fn capture_perfetto(env: &Env, serial_no: &str, config: String) -> Result<(), TraceError> {
+    let mut perfetto = adb::adb(env, serial_no)
+        .with_args(&[
+            "shell",
+            "perfetto",
+            "-c",
+            "-",
+            "--txt",
+            "-o",
+            PERFETTO_REMOTE_PATH,
+        ])
+        .with_stdin_piped()
+        .run()
+        .map_err(TraceError::PerfettoFailed)?;
+    let write_result = perfetto
+        .stdin()
+        .expect("developer error: `perfetto` stdin not captured")
+        .write_all(config.as_bytes())
+        .map_err(TraceError::ConfigWriteFailed);
+    // Waiting closes stdin, which is what tells `perfetto` its config is
+    // complete. It stops by itself once `duration_ms` is up.
+    let wait_result = perfetto
+        .wait_for_output()
+        .map_err(TraceError::PerfettoFailed);
+    write_result?;
+    wait_result?;
+    Ok(())
+}
+
+fn capture_atrace(
+    env: &Env,
+    serial_no: &str,
+    app_id: &str,
+    seconds: u32,
+) -> Result<(), TraceError> {
+    adb::adb(env, serial_no)
+        .with_args(&["shell", "atrace", "-t"])
+        .with_arg(seconds.to_string())
+        .with_args(&["-a", app_id, "-o", ATRACE_REMOTE_PATH])
+        .with_args(&["sched", "freq", "gfx", "view"])
+        .run_and_wait()
+        .map_err(TraceError::AtraceFailed)?;
+    Ok(())
+}
+
+/// Captures a trace of the running app for `seconds`, then pulls it into the
+/// project's `traces` dir and returns where it ended up.
+pub fn capture(
+    config: &Config,
+    env: &Env,
+    serial_no: &str,
+    app_id: &str,
+    seconds: u32,
+) -> Result<PathBuf, TraceError> {
+    let dir = config.project_dir().join("traces");
+    fs::create_dir_all(&dir).map_err(|cause| TraceError::DirCreationFailed {
+        path: dir.clone(),
+        cause,
+    })?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    println!("Capturing a {}s trace...", seconds);
+    let (remote, local) = if has_perfetto(env, serial_no) {
+        capture_perfetto(env, serial_no, perfetto_config(app_id, seconds))?;
+        (
+            PERFETTO_REMOTE_PATH,
+            dir.join(format!("{}-{}.perfetto-trace", app_id, stamp)),
+        )
+    } else {
+        log::warn!("`perfetto` isn't available on this device; falling back to `atrace`");
+        capture_atrace(env, serial_no, app_id, seconds)?;
+        (
+            ATRACE_REMOTE_PATH,
+            dir.join(format!("{}-{}.atrace", app_id, stamp)),
+        )
+    };
+    let pull_result = adb::adb(env, serial_no)
+        .with_args(&["pull", remote])
+        .with_arg(&local)
+        .run_and_wait()
+        .map_err(TraceError::PullFailed);
+    // Cleanup is best-effort, since a leftover trace is harmless and gets
+    // overwritten next time anyway.
+    if let Err(err) = adb::adb(env, serial_no)
+        .with_args(&["shell", "rm", "-f", remote])
+        .run_and_wait()
+    {
+        log::warn!("failed to remove trace from device: {}", err);
+    }
+    pull_result?;
+    Ok(local)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn perfetto_config_has_app_and_duration() {
+        let config = perfetto_config("com.example.game", 5);
+        assert!(config.contains(r#"atrace_apps: "com.example.game""#));
+        assert!(config.contains("duration_ms: 5000"));
+    }
+}
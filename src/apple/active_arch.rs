@@ -0,0 +1,82 @@
+//! Picks the one target worth building while iterating, like Xcode's
+//! `ONLY_ACTIVE_ARCH`: whatever the connected device uses, or the simulator
+//! arch for this Mac otherwise.
+
+use super::target::Target;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Host {
+    AppleSilicon,
+    Intel,
+}
+
+impl Host {
+    /// Asks the kernel instead of going by what we were compiled for, since
+    /// we might be an x86_64 build running under Rosetta.
+    pub fn detect() -> Self {
+        let arm64 = bossy::Command::impure_parse("sysctl -n hw.optional.arm64")
+            .run_and_wait_for_str(|s| s.trim() == "1")
+            // Intel Macs don't have this at all.
+            .unwrap_or(cfg!(target_arch = "aarch64"));
+        if arm64 {
+            Self::AppleSilicon
+        } else {
+            Self::Intel
+        }
+    }
+
+    pub fn simulator_arch(self) -> &'static str {
+        match self {
+            Self::AppleSilicon => "arm64",
+            Self::Intel => "x86_64",
+        }
+    }
+}
+
+/// The target for `device` if there is one, and otherwise the one for
+/// `host`'s simulator arch.
+pub fn resolve<'a>(device: Option<&'a Target<'a>>, host: Host) -> &'a Target<'a> {
+    match device {
+        Some(target) => {
+            log::info!(
+                "only building for {}, since that's what the connected device uses",
+                target.triple()
+            );
+            target
+        }
+        None => {
+            let arch = host.simulator_arch();
+            let target = Target::for_simulator_arch(arch)
+                .expect("developer error: no target for the host's simulator arch");
+            log::info!(
+                "no device is connected, so only building for {}, since the simulator on this Mac is {}",
+                target.triple,
+                arch
+            );
+            target
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::target::TargetTrait as _;
+    use rstest::rstest;
+
+    #[rstest(
+        device,
+        host,
+        expected,
+        case(Some("aarch64"), Host::AppleSilicon, "aarch64-apple-ios"),
+        case(Some("aarch64"), Host::Intel, "aarch64-apple-ios"),
+        case(Some("x86_64"), Host::AppleSilicon, "x86_64-apple-ios"),
+        case(Some("x86_64"), Host::Intel, "x86_64-apple-ios"),
+        case(None, Host::AppleSilicon, "aarch64-apple-ios-sim"),
+        case(None, Host::Intel, "x86_64-apple-ios")
+    )]
+    fn resolves_active_target(device: Option<&str>, host: Host, expected: &str) {
+        let device = device.map(|name| &Target::all()[name]);
+        assert_eq!(resolve(device, host).triple, expected);
+    }
+}
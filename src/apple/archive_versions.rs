@@ -0,0 +1,106 @@
+//! The last bundle version archived for each scheme, so we can catch builds
+//! that App Store Connect would reject for not increasing it.
+
+use super::version_number::{VersionNumber, VersionNumberError};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+pub static FILE_NAME: &str = ".archive-versions.json";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to read archive versions from {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to parse archive versions in {path:?}: {source}")]
+    ParseFailed {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Archive version {version:?} for scheme {scheme:?} in {path:?} is invalid: {source}")]
+    VersionInvalid {
+        path: PathBuf,
+        scheme: String,
+        version: String,
+        source: VersionNumberError,
+    },
+    #[error("Failed to write archive versions to {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: io::Error },
+}
+
+#[derive(Debug)]
+pub struct ArchiveVersions {
+    path: PathBuf,
+    versions: BTreeMap<String, String>,
+}
+
+impl ArchiveVersions {
+    pub fn load(export_dir: &Path) -> Result<Self, Error> {
+        let path = export_dir.join(FILE_NAME);
+        let versions = if path.is_file() {
+            let contents = fs::read(&path).map_err(|source| Error::ReadFailed {
+                path: path.clone(),
+                source,
+            })?;
+            serde_json::from_slice(&contents).map_err(|source| Error::ParseFailed {
+                path: path.clone(),
+                source,
+            })?
+        } else {
+            Default::default()
+        };
+        Ok(Self { path, versions })
+    }
+
+    pub fn last(&self, scheme: &str) -> Result<Option<VersionNumber>, Error> {
+        self.versions
+            .get(scheme)
+            .map(|version| {
+                VersionNumber::from_str(version).map_err(|source| Error::VersionInvalid {
+                    path: self.path.clone(),
+                    scheme: scheme.to_owned(),
+                    version: version.clone(),
+                    source,
+                })
+            })
+            .transpose()
+    }
+
+    pub fn record(&mut self, scheme: &str, version: &VersionNumber) -> Result<(), Error> {
+        self.versions.insert(scheme.to_owned(), version.to_string());
+        let json = serde_json::to_vec_pretty(&self.versions)
+            .expect("developer error: archive versions didn't serialize");
+        self.path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&self.path, json))
+            .map_err(|source| Error::WriteFailed {
+                path: self.path.clone(),
+                source,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_per_scheme() {
+        let dir = crate::util::TempDirGuard::new().unwrap();
+        let mut versions = ArchiveVersions::load(&dir).unwrap();
+        assert!(versions.last("game_iOS").unwrap().is_none());
+        versions
+            .record("game_iOS", &VersionNumber::from_str("1.0.0.3").unwrap())
+            .unwrap();
+        let versions = ArchiveVersions::load(&dir).unwrap();
+        assert_eq!(
+            versions.last("game_iOS").unwrap().unwrap().to_string(),
+            "1.0.0.3"
+        );
+        assert!(versions.last("game_macOS").unwrap().is_none());
+    }
+}
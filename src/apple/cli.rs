@@ -1,19 +1,29 @@
 use crate::{
     apple::{
-        config::{Config, Metadata},
+        archive_versions::{self, ArchiveVersions},
+        config::{Config, Metadata, SchemeNotGenerated},
+        depfile,
         device::{Device, RunError},
-        ios_deploy, rust_version_check,
-        target::{ArchiveError, BuildError, CheckError, CompileLibError, ExportError, Target},
+        entitlements, ios_deploy,
+        lib_env::{self, HostEnv, XcodeVars},
+        pod, project, relocalize, rust_version_check, schemes, sdk,
+        target::{ArchiveError, BuildError, CheckError, CompileLibError, ExportError, Os, Target},
+        validate,
+        version_number::VersionNumber,
         NAME,
     },
+    assets,
     config::{
+        app::AssetPlatform,
         metadata::{self, Metadata as OmniMetadata},
         Config as OmniConfig, LoadOrGenError,
     },
     define_device_prompt,
     device::PromptError,
+    doctor::Status,
     env::{Env, Error as EnvError},
-    opts, os,
+    hooks::{self, Event},
+    icons, opts,
     target::{call_for_targets_with_fallback, TargetInvalid, TargetTrait as _},
     util::{
         self,
@@ -23,7 +33,7 @@ use crate::{
         prompt,
     },
 };
-use std::{collections::HashMap, ffi::OsStr, path::PathBuf};
+use std::path::{Path, PathBuf};
 use structopt::{clap::AppSettings, StructOpt};
 
 #[derive(Debug, StructOpt)]
@@ -47,8 +57,44 @@ impl Input {
     }
 }
 
-fn macos_from_platform(platform: &str) -> bool {
-    platform == "macOS"
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct Interfaces {
+    #[structopt(
+        long = "usb-only",
+        help = "Only look for devices that are plugged in",
+        conflicts_with = "wifi-only"
+    )]
+    pub usb_only: bool,
+    #[structopt(
+        long = "wifi-only",
+        help = "Only look for devices paired over the network"
+    )]
+    pub wifi_only: bool,
+}
+
+impl Interfaces {
+    fn filter(self) -> ios_deploy::InterfaceFilter {
+        ios_deploy::InterfaceFilter::from_flags(self.usb_only, self.wifi_only)
+    }
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct Scheme {
+    #[structopt(
+        long = "scheme",
+        value_name = "name",
+        help = "Build this scheme instead of the generated one, i.e. for a target you added in Xcode"
+    )]
+    pub scheme: Option<String>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum SchemeCommand {
+    #[structopt(
+        name = "list",
+        about = "Lists the schemes in the Xcode project, which `--scheme` can be any of"
+    )]
+    List,
 }
 
 fn profile_from_configuration(configuration: &str) -> opts::Profile {
@@ -62,28 +108,57 @@ fn profile_from_configuration(configuration: &str) -> opts::Profile {
 #[derive(Clone, Debug, StructOpt)]
 pub enum Command {
     #[structopt(name = "open", about = "Open project in Xcode")]
-    Open,
+    Open {
+        #[structopt(long = "file", help = "Source file to open the project at")]
+        file: Option<PathBuf>,
+        #[structopt(long = "line", help = "Line to jump to in `--file`", requires = "file")]
+        line: Option<u32>,
+    },
     #[structopt(name = "check", about = "Checks if code compiles for target(s)")]
     Check {
-        #[structopt(name = "targets", default_value = Target::DEFAULT_KEY, possible_values = Target::name_list())]
+        #[structopt(name = "targets", possible_values = Target::name_list_with_watchos())]
         targets: Vec<String>,
         #[structopt(long = "features")]
         features: Option<String>,
+        #[structopt(
+            long = "only-active-arch",
+            help = "Only build for the connected device's arch, or this Mac's simulator arch without one",
+            conflicts_with = "targets"
+        )]
+        only_active_arch: bool,
     },
     #[structopt(name = "build", about = "Builds static libraries for target(s)")]
     Build {
-        #[structopt(name = "targets", default_value = Target::DEFAULT_KEY, possible_values = Target::name_list())]
+        #[structopt(name = "targets", possible_values = Target::name_list_with_watchos())]
         targets: Vec<String>,
         #[structopt(long = "features")]
         features: Option<String>,
         #[structopt(flatten)]
         profile: cli::Profile,
+        #[structopt(
+            long = "only-active-arch",
+            help = "Only build for the connected device's arch, or this Mac's simulator arch without one",
+            conflicts_with = "targets"
+        )]
+        only_active_arch: bool,
+        #[structopt(flatten)]
+        scheme: Scheme,
     },
     #[structopt(name = "archive", about = "Builds and archives for targets(s)")]
     Archive {
         #[structopt(long = "build-number")]
         build_number: Option<u32>,
-        #[structopt(name = "targets", default_value = Target::DEFAULT_KEY, possible_values = Target::name_list())]
+        #[structopt(
+            long = "auto-increment",
+            help = "Bump the bundle version last archived for this scheme, unless it was already bumped by hand"
+        )]
+        auto_increment: bool,
+        #[structopt(
+            long = "allow-version-rollback",
+            help = "Only warn when the bundle version didn't increase since the last archive"
+        )]
+        allow_version_rollback: bool,
+        #[structopt(name = "targets", possible_values = Target::name_list())]
         targets: Vec<String>,
         #[structopt(long = "features")]
         features: Option<String>,
@@ -94,6 +169,18 @@ pub enum Command {
             about = "Appended to archive name to differentiate builds in same project"
         )]
         suffix: Option<String>,
+        #[structopt(flatten)]
+        scheme: Scheme,
+    },
+    #[structopt(
+        name = "validate",
+        about = "Checks that the project is ready to archive, without building anything"
+    )]
+    Validate {
+        #[structopt(long = "build-number")]
+        build_number: Option<u32>,
+        #[structopt(flatten)]
+        profile: cli::Profile,
     },
     #[structopt(name = "run", about = "Deploys IPA to connected device")]
     Run {
@@ -101,9 +188,48 @@ pub enum Command {
         features: Option<String>,
         #[structopt(flatten)]
         profile: cli::Profile,
+        #[structopt(flatten)]
+        launch_args: cli::LaunchArgs,
+        #[structopt(
+            long = "attach-debugger",
+            help = "Hand over an lldb session that knows where Rust's sources are, with the app installed but not yet launched"
+        )]
+        attach_debugger: bool,
+        #[structopt(
+            long = "keep-running",
+            requires = "attach-debugger",
+            help = "Make `q` in lldb detach instead of stopping the app"
+        )]
+        keep_running: bool,
+        #[structopt(flatten)]
+        interfaces: Interfaces,
+    },
+    #[structopt(
+        name = "icons",
+        about = "Regenerates app icons without re-running init"
+    )]
+    Icons {
+        #[structopt(
+            long = "check",
+            help = "Don't write anything; just fail if any icons are out of date"
+        )]
+        check: bool,
     },
     #[structopt(name = "list", about = "Lists connected devices")]
-    List,
+    List {
+        #[structopt(flatten)]
+        interfaces: Interfaces,
+    },
+    #[structopt(
+        name = "relocalize",
+        about = "Points the Xcode project at this machine's paths, i.e. after cloning it"
+    )]
+    Relocalize,
+    #[structopt(name = "scheme", about = "Manages Xcode schemes")]
+    Scheme {
+        #[structopt(subcommand)]
+        command: SchemeCommand,
+    },
     #[structopt(name = "pod", about = "Runs `pod <args>`")]
     Pod {
         #[structopt(
@@ -114,6 +240,37 @@ pub enum Command {
         )]
         arguments: Vec<String>,
     },
+    #[structopt(
+        name = "compile-lib",
+        about = "Compiles the static lib for one arch, just like Xcode would, and prints its path"
+    )]
+    CompileLib {
+        #[structopt(long = "arch", help = "Arch to build for, i.e. `arm64` or `x86_64`")]
+        arch: String,
+        #[structopt(
+            long = "platform",
+            help = "Platform to build for",
+            possible_values = Os::DISPLAY_NAMES,
+            default_value = "iOS",
+            parse(from_str = Os::from_display_name),
+        )]
+        os: Os,
+        #[structopt(
+            long = "profile",
+            help = "Profile to build with",
+            possible_values = &["debug", "release"],
+            default_value = "debug",
+            parse(from_str = profile_from_configuration),
+        )]
+        profile: opts::Profile,
+        #[structopt(
+            long = "sdk-root",
+            help = "SDK to build against (defaults to `xcrun --sdk <sdk> --show-sdk-path` for the platform's SDK)"
+        )]
+        sdk_root: Option<PathBuf>,
+        #[structopt(long = "features")]
+        features: Option<String>,
+    },
     #[structopt(
         name = "xcode-script",
         about = "Compiles static lib (should only be called by Xcode!)",
@@ -123,9 +280,9 @@ pub enum Command {
         #[structopt(
             long = "platform",
             help = "Value of `PLATFORM_DISPLAY_NAME` env var",
-            parse(from_str = macos_from_platform),
+            parse(from_str = Os::from_display_name),
         )]
-        macos: bool,
+        os: Os,
         #[structopt(long = "sdk-root", help = "Value of `SDKROOT` env var")]
         sdk_root: PathBuf,
         #[structopt(
@@ -164,6 +321,12 @@ pub enum Command {
         arches: Vec<String>,
         #[structopt(long = "features")]
         features: Option<String>,
+        #[structopt(
+            long = "depfile",
+            value_name = "path",
+            help = "Write a depfile here listing everything the build read, for Xcode's `discoveredDependencyFile`"
+        )]
+        depfile: Option<PathBuf>,
     },
 }
 
@@ -176,22 +339,65 @@ pub enum Error {
     ConfigFailed(LoadOrGenError),
     MetadataFailed(metadata::Error),
     Unsupported,
-    ProjectDirAbsent { project_dir: PathBuf },
-    OpenFailed(bossy::Error),
+    Unconfigured,
+    SchemeNotGenerated(SchemeNotGenerated),
+    SchemeListFailed(schemes::Error),
+    SchemeMissing(schemes::Missing),
+    XcodeIncompatible(sdk::Incompatible),
+    ProjectDirAbsent {
+        project_dir: PathBuf,
+    },
+    Adopted {
+        command: &'static str,
+        project_dir: PathBuf,
+    },
+    OpenFailed(util::OpenInXcodeError),
     CheckFailed(CheckError),
     BuildFailed(BuildError),
     ArchiveFailed(ArchiveError),
+    EntitlementsCheckFailed(entitlements::CheckError),
+    ArchiveVersionsFailed(archive_versions::Error),
+    VersionNotIncreased {
+        scheme: String,
+        version: VersionNumber,
+        previous: VersionNumber,
+    },
     ExportFailed(ExportError),
+    ValidationFailed,
     RunFailed(RunError),
     ListFailed(ios_deploy::DeviceListError),
+    IconsFailed(project::Error),
+    IconsStale {
+        count: usize,
+    },
     NoHomeDir(util::NoHomeDir),
     CargoEnvFailed(bossy::Error),
-    SdkRootInvalid { sdk_root: PathBuf },
-    IncludeDirInvalid { include_dir: PathBuf },
-    MacosSdkRootInvalid { macos_sdk_root: PathBuf },
-    ArchInvalid { arch: String },
+    SdkRootInvalid {
+        sdk_root: PathBuf,
+    },
+    IncludeDirInvalid {
+        include_dir: PathBuf,
+    },
+    MacosSdkRootInvalid {
+        macos_sdk_root: PathBuf,
+    },
+    ArchInvalid {
+        arch: String,
+    },
+    WatchosDisabled {
+        triple: String,
+    },
+    WatchosXcodeDriven {
+        triple: String,
+    },
+    SdkPathLookupFailed(bossy::Error),
+    AssetRefreshFailed(assets::Error),
     CompileLibFailed(CompileLibError),
+    DepfileFailed(depfile::Error),
     PodCommandFailed(bossy::Error),
+    PodInstallFailed(pod::Error),
+    HookFailed(hooks::HookFailed),
+    RelocalizeFailed(relocalize::Error),
 }
 
 impl Reportable for Error {
@@ -204,17 +410,56 @@ impl Reportable for Error {
             Self::ConfigFailed(err) => err.report(),
             Self::MetadataFailed(err) => err.report(),
             Self::Unsupported => Report::error("iOS is marked as unsupported in your Cargo.toml metadata", "If your project should support Android, modify your Cargo.toml, then run `cargo mobile init` and try again."),
+            Self::SchemeNotGenerated(err) => err.report(),
+            Self::SchemeListFailed(err) => err.report(),
+            Self::SchemeMissing(err) => err.report(),
+            Self::Unconfigured => Report::action_request("Apple setup was skipped", format!("Add an `[{}]` section with your `development-team` to {}, then run `cargo mobile init` and try again.", NAME, crate::config::file_name())),
             Self::ProjectDirAbsent { project_dir } => Report::action_request(
                 "Please run `cargo mobile init` and try again!",
                 format!("Xcode project directory {:?} doesn't exist.", project_dir),
             ),
+            Self::Adopted { command, project_dir } => Report::action_request(
+                format!("`cargo {} {}` only works with a generated Xcode project", NAME, command),
+                format!(
+                    "`{}.existing-project-dir` points at your own project in {:?}, so use Xcode for this instead; `cargo {} build` and `cargo {} check` still work.",
+                    NAME, project_dir, NAME, NAME
+                ),
+            ),
+            Self::XcodeIncompatible(err) => err.report(),
+            Self::OpenFailed(err @ util::OpenInXcodeError::XedMissing { .. }) => {
+                Report::action_request("Couldn't jump to file in Xcode", err)
+            }
             Self::OpenFailed(err) => Report::error("Failed to open project in Xcode", err),
             Self::CheckFailed(err) => err.report(),
             Self::BuildFailed(err) => err.report(),
             Self::ArchiveFailed(err) => err.report(),
+            Self::EntitlementsCheckFailed(err) => err.report(),
+            Self::ArchiveVersionsFailed(err) => {
+                Report::error("Failed to check previously archived versions", err)
+            }
+            Self::VersionNotIncreased {
+                scheme,
+                version,
+                previous,
+            } => Report::error(
+                "Bundle version didn't increase since the last archive",
+                format!(
+                    "{} isn't greater than {}, which was last archived for scheme {:?}, so App Store Connect would reject it. Pass `--auto-increment` to bump it automatically, or `--allow-version-rollback` to archive anyway.",
+                    version, previous, scheme
+                ),
+            ),
             Self::ExportFailed(err) => err.report(),
+            Self::ValidationFailed => Report::action_request(
+                "Project isn't ready to archive",
+                "Fix the failures above, then run `cargo apple validate` again.",
+            ),
             Self::RunFailed(err) => err.report(),
             Self::ListFailed(err) => err.report(),
+            Self::IconsFailed(err) => err.report(),
+            Self::IconsStale { count } => Report::action_request(
+                format!("{} icon(s) are out of date", count),
+                "Run `cargo apple icons` to regenerate them.",
+            ),
             Self::NoHomeDir(err) => Report::error("Failed to load cargo env profile", err),
             Self::CargoEnvFailed(err) => Report::error("Failed to load cargo env profile", err),
             Self::SdkRootInvalid { sdk_root } => Report::error(
@@ -233,8 +478,42 @@ impl Reportable for Error {
                 "Arch specified by Xcode was invalid",
                 format!("{:?} isn't a known arch", arch),
             ),
+            Self::WatchosDisabled { triple } => Report::action_request(
+                format!("Can't build for {}, since watchOS isn't enabled", triple),
+                "Add a `[package.metadata.cargo-apple.watchos]` section to your Cargo.toml, then run `cargo mobile init` and try again.",
+            ),
+            Self::WatchosXcodeDriven { triple } => Report::action_request(
+                format!("Can't archive for {}", triple),
+                "The watch app is embedded in the iOS app, so archive an iOS target instead, or use Xcode.",
+            ),
+            Self::SdkPathLookupFailed(err) => Report::error(
+                "Failed to find the iOS SDK",
+                format!("{} Pass `--sdk-root` to choose one yourself.", err),
+            ),
+            Self::AssetRefreshFailed(err) => Report::error("Failed to refresh assets", err),
             Self::CompileLibFailed(err) => err.report(),
+            Self::DepfileFailed(err) => Report::error("Failed to write depfile", err),
             Self::PodCommandFailed(err) => Report::error("pod command failed", err),
+            Self::PodInstallFailed(err) => err.report(),
+            Self::HookFailed(err) => err.report(),
+            Self::RelocalizeFailed(err) => {
+                Report::error("Failed to point the Xcode project at this machine", err)
+            }
+        }
+    }
+}
+
+impl From<lib_env::Error> for Error {
+    fn from(err: lib_env::Error) -> Self {
+        match err {
+            lib_env::Error::SdkRootInvalid { sdk_root } => Self::SdkRootInvalid { sdk_root },
+            lib_env::Error::IncludeDirInvalid { include_dir } => {
+                Self::IncludeDirInvalid { include_dir }
+            }
+            lib_env::Error::MacosSdkRootInvalid { macos_sdk_root } => {
+                Self::MacosSdkRootInvalid { macos_sdk_root }
+            }
+            lib_env::Error::ArchInvalid { arch } => Self::ArchInvalid { arch },
         }
     }
 }
@@ -243,13 +522,56 @@ impl Exec for Input {
     type Report = Error;
 
     fn global_flags(&self) -> GlobalFlags {
-        self.flags
+        self.flags.clone()
     }
 
     fn exec(self, wrapper: &TextWrapper) -> Result<(), Self::Report> {
-        define_device_prompt!(ios_deploy::device_list, ios_deploy::DeviceListError, iOS);
-        fn detect_target_ok<'a>(env: &Env) -> Option<&'a Target<'a>> {
-            device_prompt(env).map(|device| device.target()).ok()
+        define_device_prompt!(
+            ios_deploy::device_list,
+            ios_deploy::DeviceListError,
+            iOS,
+            filter: ios_deploy::InterfaceFilter
+        );
+        fn detect_target_ok<'a>(
+            (env, preferred_triple): (&Env, Option<&str>),
+        ) -> Option<&'a Target<'a>> {
+            device_prompt(env, preferred_triple, Default::default())
+                .map(|device| device.target())
+                .map_err(|err| {
+                    let report = err.report();
+                    log::warn!(
+                        "couldn't detect a device: {}: {}",
+                        report.msg(),
+                        report.details()
+                    )
+                })
+                .ok()
+        }
+
+        /// Runs `f` for `targets`, or for just the active one with
+        /// `--only-active-arch`.
+        fn call_for_targets(
+            env: &Env,
+            config: &Config,
+            targets: &[String],
+            only_active_arch: bool,
+            f: impl Fn(&Target) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            if only_active_arch {
+                f(active_arch::resolve(
+                    detect_target_ok((env, None)),
+                    active_arch::Host::detect(),
+                ))
+            } else {
+                call_for_targets_with_fallback(
+                    targets.iter(),
+                    &detect_target_ok,
+                    (env, config.default_target().map(|target| target.triple)),
+                    config.default_target(),
+                    f,
+                )
+                .map_err(Error::TargetInvalid)?
+            }
         }
 
         fn with_config(
@@ -262,16 +584,32 @@ impl Exec for Input {
                 .map_err(Error::ConfigFailed)?;
             let mut metadata =
                 OmniMetadata::load(&config.app().root_dir()).map_err(Error::MetadataFailed)?;
-            if metadata.apple().supported() {
+            if !config.apple().is_configured() {
+                Err(Error::Unconfigured)
+            } else if metadata.apple().supported() {
                 if let Some(features) = features {
                     metadata.add_features(features);
                 }
+                relocalize::warn_if_stale(config.apple());
                 f(config.apple(), metadata.apple())
             } else {
                 Err(Error::Unsupported)
             }
         }
 
+        fn run_hook(
+            config: &Config,
+            env: &Env,
+            event: Event,
+            context: hooks::Context<'_>,
+        ) -> Result<(), Error> {
+            config
+                .app()
+                .hooks()
+                .run(event, config.app().root_dir(), env, context)
+                .map_err(Error::HookFailed)
+        }
+
         fn ensure_init(config: &Config) -> Result<(), Error> {
             if !config.project_dir_exists() {
                 Err(Error::ProjectDirAbsent {
@@ -282,8 +620,130 @@ impl Exec for Input {
             }
         }
 
-        fn open_in_xcode(config: &Config) -> Result<(), Error> {
-            os::open_in_xcode(config.project_dir()).map_err(Error::OpenFailed)
+        fn ensure_generated(config: &Config, command: &'static str) -> Result<(), Error> {
+            if let Some(project_dir) = config.existing_project_dir() {
+                Err(Error::Adopted {
+                    command,
+                    project_dir: project_dir.to_owned(),
+                })
+            } else {
+                Ok(())
+            }
+        }
+
+        fn ensure_watchos(metadata: &Metadata, target: &Target) -> Result<(), Error> {
+            if target.is_watchos() && metadata.watchos().is_none() {
+                Err(Error::WatchosDisabled {
+                    triple: target.triple.to_owned(),
+                })
+            } else {
+                Ok(())
+            }
+        }
+
+        fn ensure_scheme(config: &Config, metadata: &Metadata) -> Result<(), Error> {
+            if config.scheme_overridden() {
+                let scheme = config.scheme();
+                let available =
+                    schemes::list(config, Some(&scheme)).map_err(Error::SchemeListFailed)?;
+                schemes::check(&scheme, &config.workspace_path(), &available)
+                    .map_err(Error::SchemeMissing)
+            } else {
+                config
+                    .generated_scheme(metadata)
+                    .map(|_| ())
+                    .map_err(Error::SchemeNotGenerated)
+            }
+        }
+
+        fn ensure_compat(
+            config: &Config,
+            metadata: &Metadata,
+            device: Option<&Device<'_>>,
+        ) -> Result<(), Error> {
+            // An invalid deployment target gets reported by project gen.
+            let deployment_target = metadata
+                .ios()
+                .deployment_target()
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| config.ios_version());
+            sdk::preflight(
+                deployment_target,
+                device
+                    .and_then(|device| device.os_version().map(|version| (device.name(), version))),
+            )
+            .map_err(Error::XcodeIncompatible)
+        }
+
+        fn open_in_xcode(
+            config: &Config,
+            file: Option<&Path>,
+            line: Option<u32>,
+        ) -> Result<(), Error> {
+            util::open_in_xcode_at(
+                &config.workspace_path(),
+                config.app().root_dir(),
+                file,
+                line,
+            )
+            .map_err(Error::OpenFailed)
+        }
+
+        // An adopted project is the user's own, and so is getting assets
+        // into it.
+        fn refresh_assets(config: &Config) -> Result<(), Error> {
+            if config.adopted() {
+                return Ok(());
+            }
+            assets::refresh(config.app(), AssetPlatform::Ios, &config.project_dir())
+                .map_err(Error::AssetRefreshFailed)
+        }
+
+        // What `compile-lib` does, which is also how adopted projects get
+        // built, since we don't know anything about their schemes.
+        fn compile_lib(
+            config: &Config,
+            metadata: &Metadata,
+            env: &Env,
+            noise_level: opts::NoiseLevel,
+            force_color: opts::ForceColor,
+            profile: opts::Profile,
+            arch: &str,
+            os: Os,
+            sdk_root: Option<PathBuf>,
+        ) -> Result<PathBuf, Error> {
+            let target = lib_env::target(arch, os)?;
+            ensure_watchos(metadata, &target)?;
+            let env = env
+                .clone()
+                .prepend_to_path(util::cargo_bin_dir().map_err(Error::NoHomeDir)?);
+            let sdk_root = match sdk_root {
+                Some(sdk_root) => sdk_root,
+                None => bossy::Command::impure("xcrun")
+                    .with_args(&["--sdk", target.sdk(), "--show-sdk-path"])
+                    .run_and_wait_for_str(|s| PathBuf::from(s.trim()))
+                    .map_err(Error::SdkPathLookupFailed)?,
+            };
+            let target_env = HostEnv::new(&sdk_root, &XcodeVars::default())?.for_target(&target);
+            refresh_assets(config)?;
+            let outcome = target
+                .compile_lib(
+                    config,
+                    metadata,
+                    noise_level,
+                    force_color,
+                    profile,
+                    &env,
+                    &target_env,
+                )
+                .map_err(Error::CompileLibFailed)?;
+            // Cargo says where it put the lib, which is more reliable than
+            // working it out ourselves if anything's been customized.
+            Ok(outcome
+                .lib_path(config.app().name(), "a")
+                .map(Path::to_owned)
+                .unwrap_or_else(|| lib_env::lib_paths(config, &target, profile).0))
         }
 
         let version_check = || rust_version_check(wrapper).map_err(Error::RustVersionCheckFailed);
@@ -293,115 +753,327 @@ impl Exec for Input {
                 GlobalFlags {
                     noise_level,
                     non_interactive,
+                    ..
                 },
             command,
         } = self;
         let env = Env::new().map_err(Error::EnvInitFailed)?;
         match command {
-            Command::Open => {
+            Command::Open { file, line } => {
                 version_check()?;
                 with_config(non_interactive, wrapper, None, |config, _| {
+                    ensure_generated(config, "open")?;
                     ensure_init(config)?;
-                    open_in_xcode(config)
+                    open_in_xcode(config, file.as_deref(), line)
                 })
             }
-            Command::Check { targets, features } => {
+            Command::Check {
+                targets,
+                features,
+                only_active_arch,
+            } => {
                 version_check()?;
                 with_config(non_interactive, wrapper, features, |config, metadata| {
-                    call_for_targets_with_fallback(
-                        targets.iter(),
-                        &detect_target_ok,
+                    call_for_targets(
                         &env,
+                        config,
+                        &targets,
+                        only_active_arch,
                         |target: &Target| {
+                            ensure_watchos(metadata, target)?;
                             target
                                 .check(config, metadata, &env, noise_level)
                                 .map_err(Error::CheckFailed)
                         },
                     )
-                    .map_err(Error::TargetInvalid)?
                 })
             }
             Command::Build {
                 targets,
                 features,
                 profile: cli::Profile { profile },
-            } => with_config(non_interactive, wrapper, features.clone(), |config, _| {
-                version_check()?;
-                ensure_init(config)?;
-                call_for_targets_with_fallback(
-                    targets.iter(),
-                    &detect_target_ok,
-                    &env,
-                    |target: &Target| {
-                        target
-                            .build(config, &env, noise_level, profile, features.clone())
-                            .map_err(Error::BuildFailed)
-                    },
-                )
-                .map_err(Error::TargetInvalid)?
-            }),
+                only_active_arch,
+                scheme: Scheme { scheme },
+            } => with_config(
+                non_interactive,
+                wrapper,
+                features.clone(),
+                |config, metadata| {
+                    let config = &config.with_scheme(scheme);
+                    version_check()?;
+                    ensure_init(config)?;
+                    // The watch targets don't have a scheme of their own to
+                    // build, so they're built like adopted projects are.
+                    let compile_only = |target: &Target| {
+                        let context = hooks::Context::new(profile).with_target(target.triple);
+                        run_hook(config, &env, Event::ApplePreBuild, context)?;
+                        let lib = compile_lib(
+                            config,
+                            metadata,
+                            &env,
+                            noise_level,
+                            opts::ForceColor::Yes,
+                            profile,
+                            target.arch,
+                            target.os,
+                            None,
+                        )?;
+                        println!("{}", lib.display());
+                        run_hook(config, &env, Event::ApplePostBuild, context)
+                    };
+                    if config.adopted() {
+                        return call_for_targets(
+                            &env,
+                            config,
+                            &targets,
+                            only_active_arch,
+                            compile_only,
+                        );
+                    }
+                    ensure_scheme(config, metadata)?;
+                    ensure_compat(config, metadata, None)?;
+                    call_for_targets(
+                        &env,
+                        config,
+                        &targets,
+                        only_active_arch,
+                        |target: &Target| {
+                            if target.is_watchos() {
+                                return compile_only(target);
+                            }
+                            // The build hooks run from `xcode-script`, which
+                            // this goes through too.
+                            target
+                                .build(config, &env, noise_level, profile, features.clone())
+                                .map_err(Error::BuildFailed)
+                        },
+                    )
+                },
+            ),
             Command::Archive {
                 features,
                 targets,
                 build_number,
+                auto_increment,
+                allow_version_rollback,
                 profile: cli::Profile { profile },
                 suffix,
-            } => with_config(non_interactive, wrapper, features.clone(), |config, _| {
-                version_check()?;
-                ensure_init(config)?;
-                call_for_targets_with_fallback(
-                    targets.iter(),
-                    &detect_target_ok,
-                    &env,
-                    |target: &Target| {
-                        let mut app_version = config.bundle_version().clone();
-                        if let Some(build_number) = build_number {
-                            app_version.push_extra(build_number);
+                scheme: Scheme { scheme },
+            } => with_config(
+                non_interactive,
+                wrapper,
+                features.clone(),
+                |config, metadata| {
+                    let config = &config.with_scheme(scheme);
+                    ensure_generated(config, "archive")?;
+                    version_check()?;
+                    ensure_init(config)?;
+                    ensure_scheme(config, metadata)?;
+                    ensure_compat(config, metadata, None)?;
+                    // Signing would fail anyway, but only after building
+                    // everything, and with a much less helpful message.
+                    if let Some(requested) = metadata.ios().entitlements() {
+                        entitlements::check_profiles(config, profile, requested)
+                            .map_err(Error::EntitlementsCheckFailed)?;
+                    }
+                    let mut app_version = config.bundle_version().clone();
+                    if let Some(build_number) = build_number {
+                        app_version.push_extra(build_number);
+                    }
+                    let scheme = config.scheme();
+                    let mut archive_versions = ArchiveVersions::load(&config.export_dir())
+                        .map_err(Error::ArchiveVersionsFailed)?;
+                    if let Some(previous) = archive_versions
+                        .last(&scheme)
+                        .map_err(Error::ArchiveVersionsFailed)?
+                    {
+                        if auto_increment {
+                            app_version = app_version.next_after(&previous);
+                            println!("Archiving with bundle version {}", app_version);
+                        } else if !app_version.follows(&previous) {
+                            if allow_version_rollback {
+                                Report::action_request(
+                                    "Bundle version didn't increase since the last archive",
+                                    format!(
+                                        "{} isn't greater than {}, which was last archived for scheme {:?}; archiving anyway, since `--allow-version-rollback` was passed.",
+                                        app_version, previous, scheme
+                                    ),
+                                )
+                                .print(wrapper);
+                            } else {
+                                return Err(Error::VersionNotIncreased {
+                                    scheme,
+                                    version: app_version,
+                                    previous,
+                                });
+                            }
                         }
-
-                        target
-                            .build(config, &env, noise_level, profile, features.clone())
-                            .map_err(Error::BuildFailed)?;
-                        target
-                            .archive(
+                    }
+                    call_for_targets_with_fallback(
+                        targets.iter(),
+                        &detect_target_ok,
+                        (&env, config.default_target().map(|target| target.triple)),
+                        config.default_target(),
+                        |target: &Target| {
+                            if target.is_watchos() {
+                                return Err(Error::WatchosXcodeDriven {
+                                    triple: target.triple.to_owned(),
+                                });
+                            }
+                            let context = hooks::Context::new(profile).with_target(target.triple);
+                            run_hook(config, &env, Event::ApplePreArchive, context)?;
+                            target
+                                .build(config, &env, noise_level, profile, features.clone())
+                                .map_err(Error::BuildFailed)?;
+                            target
+                                .archive(
+                                    config,
+                                    &env,
+                                    noise_level,
+                                    profile,
+                                    features.clone(),
+                                    suffix.clone(),
+                                    Some(app_version.clone()),
+                                )
+                                .map_err(Error::ArchiveFailed)?;
+                            let archive_path =
+                                config.xcarchive_path(suffix.as_deref().unwrap_or_default());
+                            run_hook(
                                 config,
                                 &env,
-                                noise_level,
-                                profile,
-                                features.clone(),
-                                suffix.clone(),
-                                Some(app_version),
+                                Event::ApplePostArchive,
+                                context.with_artifact_path(&archive_path),
                             )
-                            .map_err(Error::ArchiveFailed)
-                    },
-                )
-                .map_err(Error::TargetInvalid)?
+                        },
+                    )
+                    .map_err(Error::TargetInvalid)??;
+                    // Only recorded once every target archived, since they
+                    // all share the same version.
+                    archive_versions
+                        .record(&scheme, &app_version)
+                        .map_err(Error::ArchiveVersionsFailed)
+                },
+            ),
+            Command::Validate {
+                build_number,
+                profile: cli::Profile { profile },
+            } => with_config(non_interactive, wrapper, None, |config, metadata| {
+                let section = validate::run(config, metadata, profile, build_number);
+                section.print(wrapper, false);
+                if section.status() == Status::Failures {
+                    Err(Error::ValidationFailed)
+                } else {
+                    Ok(())
+                }
             }),
             Command::Run {
                 features,
                 profile: cli::Profile { profile },
-            } => with_config(non_interactive, wrapper, features.clone(), |config, _| {
-                version_check()?;
-                ensure_init(config)?;
-                device_prompt(&env)
-                    .map_err(Error::DevicePromptFailed)?
-                    .run(
-                        config,
+                launch_args: cli::LaunchArgs { launch_args },
+                attach_debugger,
+                keep_running,
+                interfaces,
+            } => with_config(
+                non_interactive,
+                wrapper,
+                features.clone(),
+                |config, metadata| {
+                    ensure_generated(config, "run")?;
+                    version_check()?;
+                    ensure_init(config)?;
+                    ensure_scheme(config, metadata)?;
+                    let device = device_prompt(
                         &env,
-                        noise_level,
-                        non_interactive,
-                        profile,
-                        features,
+                        config.default_target().map(|target| target.triple),
+                        interfaces.filter(),
                     )
-                    .map_err(Error::RunFailed)
+                    .map_err(Error::DevicePromptFailed)?;
+                    ensure_compat(config, metadata, Some(&device))?;
+                    // Runs once we know which device, but before building
+                    // anything for it.
+                    run_hook(
+                        config,
+                        &env,
+                        Event::ApplePreRun,
+                        hooks::Context::new(profile).with_target(device.target().triple),
+                    )?;
+                    device
+                        .run(
+                            config,
+                            &env,
+                            noise_level,
+                            non_interactive,
+                            profile,
+                            features,
+                            &launch_args,
+                            if attach_debugger {
+                                Some(ios_deploy::lldb::Attach { keep_running })
+                            } else {
+                                None
+                            },
+                        )
+                        .map_err(Error::RunFailed)
+                },
+            ),
+            Command::Icons { check } => with_config(non_interactive, wrapper, None, |config, _| {
+                ensure_generated(config, "icons")?;
+                ensure_init(config)?;
+                let mode = if check {
+                    icons::Mode::Check
+                } else {
+                    icons::Mode::Overwrite
+                };
+                let changes = project::sync_icons(config, mode).map_err(Error::IconsFailed)?;
+                changes.print(mode);
+                if check && !changes.is_empty() {
+                    Err(Error::IconsStale {
+                        count: changes.len(),
+                    })
+                } else {
+                    Ok(())
+                }
             }),
-            Command::List => ios_deploy::device_list(&env)
+            Command::List { interfaces } => ios_deploy::device_list(&env, interfaces.filter())
                 .map_err(Error::ListFailed)
                 .map(|device_list| {
                     prompt::list_display_only(device_list.iter(), device_list.len());
                 }),
+            Command::Relocalize => {
+                with_config(non_interactive, wrapper, None, |config, metadata| {
+                    ensure_generated(config, "relocalize")?;
+                    ensure_init(config)?;
+                    match relocalize::relocalize(config, metadata).map_err(Error::RelocalizeFailed)? {
+                        Some(old_root) => println!(
+                            "Pointed the Xcode project at {:?} instead of {:?}; run `cargo mobile init` to stop it depending on where it's cloned",
+                            config.app().root_dir(),
+                            old_root
+                        ),
+                        None => println!("The Xcode project already points at this machine"),
+                    }
+                    Ok(())
+                })
+            }
+            Command::Scheme {
+                command: SchemeCommand::List,
+            } => with_config(non_interactive, wrapper, None, |config, _| {
+                ensure_generated(config, "scheme list")?;
+                ensure_init(config)?;
+                let default = config.default_scheme();
+                for scheme in schemes::list(config, None).map_err(Error::SchemeListFailed)? {
+                    if scheme == default {
+                        println!("{} (default)", scheme);
+                    } else {
+                        println!("{}", scheme);
+                    }
+                }
+                Ok(())
+            }),
             Command::Pod { arguments } => {
                 with_config(non_interactive, wrapper, None, |config, _| {
+                    if arguments.first().map(String::as_str) == Some("install") {
+                        return pod::install(&config.project_dir(), &arguments)
+                            .map_err(Error::PodInstallFailed);
+                    }
                     bossy::Command::impure_parse("pod")
                         .with_args(arguments)
                         .with_arg(format!(
@@ -414,7 +1086,7 @@ impl Exec for Input {
                 })
             }
             Command::XcodeScript {
-                macos,
+                os,
                 sdk_root,
                 framework_search_paths,
                 gcc_preprocessor_definitions,
@@ -423,6 +1095,7 @@ impl Exec for Input {
                 force_color,
                 arches,
                 features,
+                depfile,
             } => with_config(
                 non_interactive,
                 wrapper,
@@ -431,81 +1104,30 @@ impl Exec for Input {
                     // The `PATH` env var Xcode gives us is missing any additions
                     // made by the user's profile, so we'll manually add cargo's
                     // `PATH`.
-                    let env = env.prepend_to_path(
-                        util::home_dir()
-                            .map_err(Error::NoHomeDir)?
-                            .join(".cargo/bin"),
-                    );
-
-                    if !sdk_root.is_dir() {
-                        return Err(Error::SdkRootInvalid { sdk_root });
-                    }
-                    let include_dir = sdk_root.join("usr/include");
-                    if !include_dir.is_dir() {
-                        return Err(Error::IncludeDirInvalid { include_dir });
-                    }
-
-                    let mut host_env = HashMap::<&str, &OsStr>::new();
-
-                    // Host flags that are used by build scripts
-                    let (macos_isysroot, library_path) = {
-                        let macos_sdk_root =
-                            sdk_root.join("../../../../MacOSX.platform/Developer/SDKs/MacOSX.sdk");
-                        if !macos_sdk_root.is_dir() {
-                            return Err(Error::MacosSdkRootInvalid { macos_sdk_root });
-                        }
-                        (
-                            format!("-isysroot {}", macos_sdk_root.display()),
-                            format!("{}/usr/lib", macos_sdk_root.display()),
-                        )
-                    };
-                    host_env.insert("MAC_FLAGS", macos_isysroot.as_ref());
-                    host_env.insert("CFLAGS_x86_64_apple_darwin", macos_isysroot.as_ref());
-                    host_env.insert("CXXFLAGS_x86_64_apple_darwin", macos_isysroot.as_ref());
-
-                    host_env.insert(
-                        "OBJC_INCLUDE_PATH_x86_64_apple_darwin",
-                        include_dir.as_os_str(),
-                    );
+                    let env = env.prepend_to_path(util::cargo_bin_dir().map_err(Error::NoHomeDir)?);
 
-                    host_env.insert("RUST_BACKTRACE", "1".as_ref());
-
-                    host_env.insert("FRAMEWORK_SEARCH_PATHS", framework_search_paths.as_ref());
-                    host_env.insert(
-                        "GCC_PREPROCESSOR_DEFINITIONS",
-                        gcc_preprocessor_definitions.as_ref(),
-                    );
-                    host_env.insert("HEADER_SEARCH_PATHS", header_search_paths.as_ref());
-
-                    let macos_target = Target::macos();
+                    let host_env = HostEnv::new(
+                        &sdk_root,
+                        &XcodeVars {
+                            framework_search_paths: Some(framework_search_paths),
+                            gcc_preprocessor_definitions: Some(gcc_preprocessor_definitions),
+                            header_search_paths: Some(header_search_paths),
+                        },
+                    )?;
 
-                    let isysroot = format!("-isysroot {}", sdk_root.display());
+                    refresh_assets(config)?;
 
+                    // Each target's artifact, along with the dep-info cargo
+                    // wrote for it.
+                    let mut artifacts = Vec::new();
                     for arch in arches {
-                        // Set target-specific flags
-                        let triple = match arch.as_str() {
-                            "arm64" => "aarch64_apple_ios",
-                            "x86_64" => "x86_64_apple_ios",
-                            _ => return Err(Error::ArchInvalid { arch }),
-                        };
-                        let cflags = format!("CFLAGS_{}", triple);
-                        let cxxflags = format!("CFLAGS_{}", triple);
-                        let objc_include_path = format!("OBJC_INCLUDE_PATH_{}", triple);
-                        let mut target_env = host_env.clone();
-                        target_env.insert(cflags.as_ref(), isysroot.as_ref());
-                        target_env.insert(cxxflags.as_ref(), isysroot.as_ref());
-                        target_env.insert(objc_include_path.as_ref(), include_dir.as_ref());
-                        // Prevents linker errors in build scripts and proc macros:
-                        // https://github.com/signalapp/libsignal-client/commit/02899cac643a14b2ced7c058cc15a836a2165b6d
-                        target_env.insert("LIBRARY_PATH", library_path.as_ref());
-
-                        let target = if macos {
-                            &macos_target
-                        } else {
-                            Target::for_arch(&arch).ok_or_else(|| Error::ArchInvalid {
-                                arch: arch.to_owned(),
-                            })?
-                        };
+                        let target = lib_env::target(&arch, os)?;
+                        let target_env = host_env.for_target(&target);
+                        // Hooks run per target, around just the Rust build.
+                        // Every Xcode build gets here, including the ones
+                        // `build`, `run`, and `archive` start.
+                        let context = hooks::Context::new(profile).with_target(target.triple);
+                        run_hook(config, &env, Event::ApplePreBuild, context)?;
                         target
                             .compile_lib(
                                 config,
@@ -514,13 +1136,40 @@ impl Exec for Input {
                                 force_color,
                                 profile,
                                 &env,
-                                target_env,
+                                &target_env,
                             )
                             .map_err(Error::CompileLibFailed)?;
+                        run_hook(config, &env, Event::ApplePostBuild, context)?;
+                        artifacts.push(lib_env::lib_paths(config, &target, profile));
+                    }
+                    if let Some(depfile) = depfile {
+                        depfile::write(&depfile, &artifacts).map_err(Error::DepfileFailed)?;
+                        println!("Wrote depfile to {:?}", depfile);
                     }
                     Ok(())
                 },
             ),
+            Command::CompileLib {
+                arch,
+                os,
+                profile,
+                sdk_root,
+                features,
+            } => with_config(non_interactive, wrapper, features, |config, metadata| {
+                let lib = compile_lib(
+                    config,
+                    metadata,
+                    &env,
+                    noise_level,
+                    opts::ForceColor::Yes,
+                    profile,
+                    &arch,
+                    os,
+                    sdk_root,
+                )?;
+                println!("{}", lib.display());
+                Ok(())
+            }),
         }
     }
 }
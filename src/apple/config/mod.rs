@@ -2,18 +2,27 @@ mod raw;
 
 pub use self::raw::*;
 
-use super::version_number::{VersionNumber, VersionNumberError};
+use super::{
+    deps,
+    entitlements::Entitlements,
+    target::Target,
+    version_number::{VersionNumber, VersionNumberError},
+};
 use crate::{
-    config::app::App,
+    config::app::{self, App},
+    opts::Profile,
+    target::TargetTrait as _,
     util::{
-        self, cli::Report, Pod, VersionDouble, VersionDoubleError, VersionTriple,
-        VersionTripleError,
+        self,
+        cli::{Report, Reportable},
+        Pod, VersionDouble, VersionDoubleError, VersionTriple, VersionTripleError,
     },
 };
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use std::{
+    collections::HashSet,
     fmt::{self, Display},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 static DEFAULT_PROJECT_DIR: &str = "gen/apple";
@@ -50,6 +59,106 @@ pub struct BuildScript {
     discovered_dependency_file: Option<String>,
 }
 
+#[derive(Debug, Eq, PartialEq)]
+pub enum BuildScriptProblem {
+    NameEmpty,
+    NameDuplicated,
+    NeitherPathNorScript,
+    BothPathAndScript,
+    PathMissing(PathBuf),
+    EntryEmpty { field: &'static str },
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct BuildScriptInvalid {
+    pub platform: &'static str,
+    pub phase: &'static str,
+    /// The script's name, or its 1-based position in `phase` if it doesn't
+    /// have one.
+    pub script: String,
+    pub problem: BuildScriptProblem,
+}
+
+impl Display for BuildScriptInvalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Script {} in `package.metadata.cargo-apple.{}.{}` is invalid: ",
+            self.script, self.platform, self.phase
+        )?;
+        match &self.problem {
+            BuildScriptProblem::NameEmpty => write!(f, "`name` is empty."),
+            BuildScriptProblem::NameDuplicated => write!(
+                f,
+                "another {} script has the same `name`, which XcodeGen can't tell apart.",
+                self.platform
+            ),
+            BuildScriptProblem::NeitherPathNorScript => {
+                write!(f, "it needs either a `path` or a `script`.")
+            }
+            BuildScriptProblem::BothPathAndScript => {
+                write!(
+                    f,
+                    "it has both a `path` and a `script`, but can only have one."
+                )
+            }
+            BuildScriptProblem::PathMissing(path) => {
+                write!(f, "`path` {:?} doesn't exist.", path)
+            }
+            BuildScriptProblem::EntryEmpty { field } => {
+                write!(f, "`{}` has an empty entry.", field)
+            }
+        }
+    }
+}
+
+impl BuildScript {
+    fn problem(&self, root_dir: &Path) -> Option<BuildScriptProblem> {
+        if self
+            .name
+            .as_deref()
+            .map_or(false, |name| name.trim().is_empty())
+        {
+            return Some(BuildScriptProblem::NameEmpty);
+        }
+        match (&self.path, &self.script) {
+            (None, None) => return Some(BuildScriptProblem::NeitherPathNorScript),
+            (Some(_), Some(_)) => return Some(BuildScriptProblem::BothPathAndScript),
+            (Some(path), None) => {
+                let path = root_dir.join(path);
+                if !path.is_file() {
+                    return Some(BuildScriptProblem::PathMissing(path));
+                }
+            }
+            (None, Some(_)) => (),
+        }
+        [
+            ("input-files", &self.input_files),
+            ("output-files", &self.output_files),
+            ("input-file-lists", &self.input_file_lists),
+            ("output-file-lists", &self.output_file_lists),
+        ]
+        .iter()
+        .find(|(_, entries)| {
+            entries
+                .iter()
+                .flatten()
+                .any(|entry| entry.trim().is_empty())
+        })
+        .map(|(field, _)| BuildScriptProblem::EntryEmpty { field })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Dsym {
+    pub copy_to: PathBuf,
+    /// Run through `sh -c`, with `{dsym_path}` replaced by the archive's
+    /// dSYMs dir.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_command: Option<String>,
+}
+
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Platform {
@@ -57,6 +166,7 @@ pub struct Platform {
     libraries: Option<Vec<String>>,
     frameworks: Option<Vec<String>>,
     valid_archs: Option<Vec<String>>,
+    excluded_simulator_archs: Option<Vec<String>>,
     vendor_frameworks: Option<Vec<String>>,
     vendor_sdks: Option<Vec<String>>,
     asset_catalogs: Option<Vec<PathBuf>>,
@@ -67,9 +177,29 @@ pub struct Platform {
     post_compile_scripts: Option<Vec<BuildScript>>,
     post_build_scripts: Option<Vec<BuildScript>>,
     command_line_arguments: Option<Vec<String>>,
+    deployment_target: Option<String>,
+    generate_scheme: Option<bool>,
+    entitlements: Option<Entitlements>,
+    background_modes: Option<Vec<String>>,
+    info_plist_patch: Option<PathBuf>,
 }
 
 impl Platform {
+    /// Overrides `ios-version`/`macos-version` for just this platform's
+    /// target.
+    pub fn deployment_target(&self) -> Result<Option<VersionDouble>, VersionDoubleError> {
+        self.deployment_target
+            .as_deref()
+            .map(VersionDouble::from_str)
+            .transpose()
+    }
+
+    /// When this is off, the target still exists but gets no scheme, so it
+    /// stays out of the way in Xcode.
+    pub fn generate_scheme(&self) -> bool {
+        self.generate_scheme.unwrap_or(true)
+    }
+
     pub fn no_default_features(&self) -> bool {
         self.features.is_some()
     }
@@ -90,6 +220,14 @@ impl Platform {
         self.valid_archs.as_deref()
     }
 
+    /// Archs left out of simulator builds, for vendor frameworks that don't
+    /// have a simulator slice for every arch.
+    pub fn excluded_simulator_archs(&self) -> &[String] {
+        self.excluded_simulator_archs
+            .as_deref()
+            .unwrap_or_else(|| &[])
+    }
+
     pub fn vendor_frameworks(&self) -> &[String] {
         self.vendor_frameworks.as_deref().unwrap_or_else(|| &[])
     }
@@ -130,6 +268,62 @@ impl Platform {
         self.command_line_arguments.as_deref().unwrap_or_default()
     }
 
+    /// `None` if there aren't any, in which case the target doesn't get an
+    /// entitlements file at all.
+    pub fn entitlements(&self) -> Option<&Entitlements> {
+        self.entitlements
+            .as_ref()
+            .filter(|entitlements| !entitlements.is_empty())
+    }
+
+    /// Goes into `UIBackgroundModes` in the `Info.plist`.
+    pub fn background_modes(&self) -> &[String] {
+        self.background_modes.as_deref().unwrap_or_default()
+    }
+
+    /// Merged into the generated `Info.plist`; relative to the app root.
+    pub fn info_plist_patch(&self) -> Option<&Path> {
+        self.info_plist_patch.as_deref()
+    }
+
+    /// Checks every phase's scripts, with paths relative to `root_dir`.
+    /// Names have to be unique across all of this target's phases.
+    pub fn validate_build_scripts(
+        &self,
+        platform: &'static str,
+        root_dir: &Path,
+    ) -> Result<(), BuildScriptInvalid> {
+        let mut names = HashSet::new();
+        for (phase, scripts) in &[
+            ("pre-build-scripts", &self.pre_build_scripts),
+            ("post-compile-scripts", &self.post_compile_scripts),
+            ("post-build-scripts", &self.post_build_scripts),
+        ] {
+            for (index, script) in scripts.iter().flatten().enumerate() {
+                let invalid = |problem| BuildScriptInvalid {
+                    platform,
+                    phase,
+                    script: script
+                        .name
+                        .as_ref()
+                        .filter(|name| !name.trim().is_empty())
+                        .map(|name| format!("{:?}", name))
+                        .unwrap_or_else(|| format!("#{}", index + 1)),
+                    problem,
+                };
+                if let Some(problem) = script.problem(root_dir) {
+                    return Err(invalid(problem));
+                }
+                if let Some(name) = &script.name {
+                    if !names.insert(name.as_str()) {
+                        return Err(invalid(BuildScriptProblem::NameDuplicated));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_features(&mut self, features: String) {
         if let Some(f) = &mut self.features {
             f.push(features);
@@ -139,18 +333,59 @@ impl Platform {
     }
 }
 
+/// The watch app and its extension, which are generated alongside the iOS
+/// app and link the same Rust lib.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Watchos {
+    #[serde(default = "default_true")]
+    supported: bool,
+    deployment_target: Option<String>,
+    frameworks: Option<Vec<String>>,
+}
+
+impl Watchos {
+    /// watchOS 9 dropped armv7k, which leaves `arm64_32` as the only device
+    /// arch Xcode builds by default.
+    pub const DEFAULT_DEPLOYMENT_TARGET: VersionDouble = VersionDouble::new(9, 0);
+
+    pub fn deployment_target(&self) -> Result<VersionDouble, VersionDoubleError> {
+        self.deployment_target
+            .as_deref()
+            .map(VersionDouble::from_str)
+            .unwrap_or(Ok(Self::DEFAULT_DEPLOYMENT_TARGET))
+    }
+
+    pub fn frameworks(&self) -> &[String] {
+        self.frameworks.as_deref().unwrap_or_default()
+    }
+}
+
 const fn default_true() -> bool {
     true
 }
 
+/// macOS apps don't run in a simulator, so `excluded-simulator-archs` there
+/// would only look like it did something.
+fn deserialize_macos<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Platform, D::Error> {
+    let platform = Platform::deserialize(deserializer)?;
+    if platform.excluded_simulator_archs.is_some() {
+        return Err(de::Error::custom(
+            "`excluded-simulator-archs` only applies to iOS, since there's no macOS simulator",
+        ));
+    }
+    Ok(platform)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Metadata {
     #[serde(default = "default_true")]
     supported: bool,
     #[serde(default)]
     ios: Platform,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_macos")]
     macos: Platform,
+    watchos: Option<Watchos>,
 }
 
 impl Default for Metadata {
@@ -159,6 +394,7 @@ impl Default for Metadata {
             supported: true,
             ios: Default::default(),
             macos: Default::default(),
+            watchos: None,
         }
     }
 }
@@ -176,6 +412,17 @@ impl Metadata {
         &self.macos
     }
 
+    /// `None` unless there's a `watchos` section that isn't marked as
+    /// unsupported, since the watch targets are opt-in.
+    pub fn watchos(&self) -> Option<&Watchos> {
+        self.watchos.as_ref().filter(|watchos| watchos.supported)
+    }
+
+    pub fn validate_build_scripts(&self, root_dir: &Path) -> Result<(), BuildScriptInvalid> {
+        self.ios.validate_build_scripts("ios", root_dir)?;
+        self.macos.validate_build_scripts("macos", root_dir)
+    }
+
     pub fn add_features(&mut self, features: String) {
         self.ios.add_features(features.clone());
         self.macos.add_features(features);
@@ -214,9 +461,55 @@ impl Display for ProjectDirInvalid {
     }
 }
 
+/// The first of `expected` that exists, or else whatever's in `dir` with
+/// `extension` as long as there's only one, since with `--scheme` it's named
+/// after a product we don't know the name of.
+fn find_artifact(dir: &Path, expected: &[&Path], extension: &str) -> Option<PathBuf> {
+    expected
+        .iter()
+        .find(|path| {
+            let found = path.exists();
+            log::info!(
+                "{} {}found at {:?}",
+                extension,
+                if found { "" } else { "not " },
+                path
+            );
+            found
+        })
+        .map(|path| path.to_path_buf())
+        .or_else(|| {
+            let mut found = std::fs::read_dir(dir)
+                .ok()?
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.extension().map_or(false, |ext| ext == extension));
+            let only = found.next()?;
+            if found.next().is_none() {
+                log::info!("{} found at {:?}", extension, only);
+                Some(only)
+            } else {
+                log::warn!("found more than one {} in {:?}", extension, dir);
+                None
+            }
+        })
+}
+
+#[derive(Debug)]
+pub struct SchemeNotGenerated {
+    scheme: String,
+}
+
+impl Reportable for SchemeNotGenerated {
+    fn report(&self) -> Report {
+        Report::action_request(
+            format!("The {:?} scheme isn't generated", self.scheme),
+            "`generate-scheme` is set to `false` in `[package.metadata.cargo-apple.ios]` in your Cargo.toml; remove it, run `cargo mobile init`, and try again.",
+        )
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
-    DevelopmentTeamMissing,
     DevelopmentTeamEmpty,
     ProjectDirInvalid(ProjectDirInvalid),
     BundleVersionInvalid(VersionTripleError),
@@ -225,15 +518,15 @@ pub enum Error {
     IosVersionNumberInvalid(VersionNumberError),
     IosVersionNumberMismatch,
     InvalidVersionConfiguration,
+    DebugBundleIdSuffixInvalid { suffix: String },
+    DefaultTargetInvalid { name: String },
+    DependencyPinInvalid(deps::PinError),
+    ExistingProjectDirConflict,
 }
 
 impl Error {
     pub fn report(&self, msg: &str) -> Report {
         match self {
-            Self::DevelopmentTeamMissing => Report::error(
-                msg,
-                format!("`{}.development-team` must be specified", super::NAME),
-            ),
             Self::DevelopmentTeamEmpty => {
                 Report::error(msg, format!("`{}.development-team` is empty", super::NAME))
             }
@@ -271,6 +564,31 @@ impl Error {
                     super::NAME
                 ),
             ),
+            Self::DebugBundleIdSuffixInvalid { suffix } => Report::error(
+                msg,
+                format!(
+                    "`{}.debug-bundle-id-suffix` is {:?}, but it needs to start with `.` and only have letters, numbers, and hyphens between dots (i.e. `.debug`)",
+                    super::NAME,
+                    suffix
+                ),
+            ),
+            Self::DefaultTargetInvalid { name } => Report::error(
+                msg,
+                format!(
+                    "`{}.default-target` is {:?}, which isn't a known target; the known targets are {:?}",
+                    super::NAME,
+                    name,
+                    Target::name_list(),
+                ),
+            ),
+            Self::DependencyPinInvalid(err) => Report::error(
+                msg,
+                format!("`{}.dependencies` invalid: {}", super::NAME, err),
+            ),
+            Self::ExistingProjectDirConflict => Report::error(
+                msg,
+                format!("`{0}.project-dir` and `{0}.existing-project-dir` can't both be set, since we either generate a project or adopt yours; remove one of them", super::NAME),
+            ),
         }
     }
 }
@@ -282,9 +600,12 @@ pub(crate) struct VersionInfo {
 }
 
 impl VersionInfo {
+    /// `inherited` is `app.version`, which stands in for both when
+    /// `bundle-version` isn't set.
     pub(crate) fn from_raw(
         version_string: &Option<String>,
         short_version_string: &Option<String>,
+        inherited: Option<VersionTriple>,
     ) -> Result<Self, Error> {
         let version_number = version_string
             .as_deref()
@@ -306,6 +627,12 @@ impl VersionInfo {
                 return Err(Error::IosVersionNumberMismatch);
             }
         }
+        if let (None, Some(inherited)) = (&version_number, inherited) {
+            return Ok(Self {
+                version_number: Some(VersionNumber::new_from_triple(inherited)),
+                short_version_number: Some(inherited),
+            });
+        }
         Ok(Self {
             version_number,
             short_version_number,
@@ -318,7 +645,9 @@ impl VersionInfo {
 pub struct Config {
     #[serde(skip_serializing)]
     app: App,
-    development_team: String,
+    // `None` when Apple setup was skipped, i.e. because no teams were found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    development_team: Option<String>,
     project_dir: String,
     bundle_version: VersionNumber,
     bundle_version_short: VersionTriple,
@@ -327,15 +656,42 @@ pub struct Config {
     use_legacy_build_system: bool,
     plist_pairs: Vec<PListPair>,
     enable_bitcode: bool,
+    dsym: Option<Dsym>,
+    #[serde(skip_serializing)]
+    debug_bundle_id_suffix: Option<String>,
+    #[serde(skip_serializing)]
+    default_target: Option<String>,
+    #[serde(skip_serializing)]
+    dependencies: deps::Dependencies,
+    #[serde(skip_serializing)]
+    existing_project_dir: Option<PathBuf>,
+    #[serde(skip_serializing)]
+    scheme_override: Option<String>,
 }
 
 impl Config {
     pub fn from_raw(app: App, raw: Option<Raw>) -> Result<Self, Error> {
-        let raw = raw.ok_or_else(|| Error::DevelopmentTeamMissing)?;
+        let (raw, development_team) = if let Some(raw) = raw {
+            if raw.development_team.is_empty() {
+                return Err(Error::DevelopmentTeamEmpty);
+            }
+            let development_team = raw.development_team.clone();
+            (raw, Some(development_team))
+        } else {
+            log::info!(
+                "`{}` isn't configured; Apple targets won't be available",
+                super::NAME
+            );
+            (Raw::default(), None)
+        };
 
-        if raw.development_team.is_empty() {
-            return Err(Error::DevelopmentTeamEmpty);
+        if raw.existing_project_dir.is_some() && raw.project_dir.is_some() {
+            return Err(Error::ExistingProjectDirConflict);
         }
+        let existing_project_dir = raw
+            .existing_project_dir
+            .as_ref()
+            .map(|existing_project_dir| app.prefix_path(existing_project_dir));
 
         let project_dir = raw
             .project_dir
@@ -364,22 +720,55 @@ impl Config {
                 Ok(DEFAULT_PROJECT_DIR.to_owned())
             })?;
 
-        let (bundle_version, bundle_version_short) =
-            VersionInfo::from_raw(&raw.bundle_version, &raw.bundle_version_short).map(|info| {
-                let bundle_version = info
-                    .version_number
-                    .clone()
-                    .unwrap_or(DEFAULT_BUNDLE_VERSION);
+        let (bundle_version, bundle_version_short) = VersionInfo::from_raw(
+            &raw.bundle_version,
+            &raw.bundle_version_short,
+            app.version(),
+        )
+        .map(|info| {
+            let bundle_version = info
+                .version_number
+                .clone()
+                .unwrap_or(DEFAULT_BUNDLE_VERSION);
 
-                let bundle_version_short =
-                    info.short_version_number.unwrap_or(bundle_version.triple);
+            let bundle_version_short = info.short_version_number.unwrap_or(bundle_version.triple);
 
-                (bundle_version, bundle_version_short)
-            })?;
+            (bundle_version, bundle_version_short)
+        })?;
+
+        let dsym = raw.dsym.map(|dsym| Dsym {
+            copy_to: app.prefix_path(&dsym.copy_to),
+            ..dsym
+        });
+
+        let debug_bundle_id_suffix = raw.debug_bundle_id_suffix;
+        if let Some(suffix) = debug_bundle_id_suffix
+            .as_deref()
+            .filter(|suffix| !app::id_suffix_valid(suffix, '-'))
+        {
+            return Err(Error::DebugBundleIdSuffixInvalid {
+                suffix: suffix.to_owned(),
+            });
+        }
+
+        let default_target = raw.default_target;
+        if let Some(name) = default_target
+            .as_deref()
+            .filter(|name| Target::for_name(name).is_none())
+        {
+            return Err(Error::DefaultTargetInvalid {
+                name: name.to_owned(),
+            });
+        }
+
+        let dependencies = raw.dependencies.unwrap_or_default();
+        dependencies
+            .validate()
+            .map_err(Error::DependencyPinInvalid)?;
 
         Ok(Self {
             app,
-            development_team: raw.development_team,
+            development_team,
             project_dir,
             bundle_version,
             bundle_version_short,
@@ -393,11 +782,17 @@ impl Config {
                 .macos_version
                 .map(|str| VersionDouble::from_str(&str))
                 .transpose()
-                .map_err(Error::IosVersionInvalid)?
+                .map_err(Error::MacOsVersionInvalid)?
                 .unwrap_or(DEFAULT_MACOS_VERSION),
             use_legacy_build_system: raw.use_legacy_build_system.unwrap_or(true),
             plist_pairs: raw.plist_pairs.unwrap_or_default(),
             enable_bitcode: raw.enable_bitcode.unwrap_or(false),
+            dsym,
+            debug_bundle_id_suffix,
+            default_target,
+            dependencies,
+            existing_project_dir,
+            scheme_override: None,
         })
     }
 
@@ -405,7 +800,28 @@ impl Config {
         &self.app
     }
 
+    /// Whether there's an `apple` section at all; without one, there's no
+    /// development team to sign with.
+    pub fn is_configured(&self) -> bool {
+        self.development_team.is_some()
+    }
+
+    /// The Xcode project we were pointed at instead of generating one, if
+    /// `existing-project-dir` is set.
+    pub fn existing_project_dir(&self) -> Option<&Path> {
+        self.existing_project_dir.as_deref()
+    }
+
+    /// Whether the Xcode project is the user's own, in which case we can't
+    /// assume anything about its schemes or layout.
+    pub fn adopted(&self) -> bool {
+        self.existing_project_dir.is_some()
+    }
+
     pub fn project_dir(&self) -> PathBuf {
+        if let Some(existing_project_dir) = &self.existing_project_dir {
+            return existing_project_dir.clone();
+        }
         self.app.prefix_path(&self.project_dir)
     }
 
@@ -413,6 +829,11 @@ impl Config {
         self.project_dir().is_dir()
     }
 
+    pub fn xcodeproj_path(&self) -> PathBuf {
+        self.project_dir()
+            .join(format!("{}.xcodeproj", self.app.name()))
+    }
+
     pub fn workspace_path(&self) -> PathBuf {
         let root_workspace = self
             .project_dir()
@@ -420,10 +841,7 @@ impl Config {
         if root_workspace.exists() {
             root_workspace
         } else {
-            self.project_dir().join(format!(
-                "{}.xcodeproj/project.xcworkspace/",
-                self.app.name()
-            ))
+            self.xcodeproj_path().join("project.xcworkspace/")
         }
     }
 
@@ -431,6 +849,11 @@ impl Config {
         self.project_dir().join(suffix).join("build")
     }
 
+    pub fn xcarchive_path(&self, suffix: &str) -> PathBuf {
+        self.archive_dir(suffix)
+            .join(format!("{}.xcarchive", self.scheme()))
+    }
+
     pub fn export_dir(&self) -> PathBuf {
         self.project_dir().join("build")
     }
@@ -444,28 +867,313 @@ impl Config {
         let old = path(&self.scheme());
         // It seems like the format changed recently?
         let new = path(self.app.name());
-        std::iter::once(&old)
-            .chain(std::iter::once(&new))
-            .filter(|path| {
-                let found = path.is_file();
-                log::info!("IPA {}found at {:?}", if found { "" } else { "not " }, path);
-                found
-            })
-            .next()
-            .cloned()
-            .ok_or_else(|| (old, new))
+        find_artifact(&self.export_dir(), &[&old, &new], "ipa").ok_or_else(|| (old, new))
     }
 
     pub fn app_path(&self) -> PathBuf {
-        self.export_dir()
-            .join(format!("Payload/{}.app", self.app.name()))
+        let payload = self.export_dir().join("Payload");
+        let expected = payload.join(format!("{}.app", self.app.name()));
+        find_artifact(&payload, &[&expected], "app").unwrap_or(expected)
     }
 
-    pub fn scheme(&self) -> String {
+    pub fn development_team(&self) -> Option<&str> {
+        self.development_team.as_deref()
+    }
+
+    /// The bundle identifier builds for `profile` are signed with.
+    pub fn bundle_identifier(&self, profile: Profile) -> String {
+        app::for_profile(
+            &format!("{}.{}", self.app.reverse_domain(), self.app.name()),
+            self.debug_bundle_id_suffix.as_deref(),
+            profile,
+        )
+    }
+
+    /// This config, but building `scheme` instead of the one we generate, if
+    /// it's given; i.e. for `--scheme`.
+    pub fn with_scheme(&self, scheme: Option<String>) -> Self {
+        Self {
+            scheme_override: scheme,
+            ..self.clone()
+        }
+    }
+
+    pub fn default_scheme(&self) -> String {
         format!("{}_iOS", self.app.name())
     }
 
+    /// The scheme that gets built and archived, which everything named after
+    /// it (like the archive and IPA) follows.
+    pub fn scheme(&self) -> String {
+        self.scheme_override
+            .clone()
+            .unwrap_or_else(|| self.default_scheme())
+    }
+
+    pub fn scheme_overridden(&self) -> bool {
+        self.scheme_override.is_some()
+    }
+
+    /// Like `scheme`, but fails if `metadata` turned off generating it, since
+    /// then there's nothing for `xcodebuild` to find. Schemes from `--scheme`
+    /// aren't ours, so they're checked for separately.
+    pub fn generated_scheme(&self, metadata: &Metadata) -> Result<String, SchemeNotGenerated> {
+        if self.scheme_overridden() || metadata.ios().generate_scheme() {
+            Ok(self.scheme())
+        } else {
+            Err(SchemeNotGenerated {
+                scheme: self.scheme(),
+            })
+        }
+    }
+
+    pub fn ios_version(&self) -> VersionDouble {
+        self.ios_version
+    }
+
+    pub fn macos_version(&self) -> VersionDouble {
+        self.macos_version
+    }
+
     pub fn bundle_version(&self) -> &VersionNumber {
         &self.bundle_version
     }
+
+    pub fn dsym(&self) -> Option<&Dsym> {
+        self.dsym.as_ref()
+    }
+
+    /// The target to use when none are given and no device was detected.
+    pub fn default_target(&self) -> Option<&'static Target<'static>> {
+        self.default_target.as_deref().map(|name| {
+            Target::for_name(name).expect("developer error: `default-target` wasn't validated")
+        })
+    }
+
+    pub fn dependencies(&self) -> &deps::Dependencies {
+        &self.dependencies
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+    use std::fs;
+
+    fn validate(ios: &str) -> Result<(), BuildScriptInvalid> {
+        let dir = TempDirGuard::new().unwrap();
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        fs::write(dir.join("scripts/gen.sh"), "").unwrap();
+        let metadata = toml::from_str::<Metadata>(&format!("[ios]\n{}", ios)).unwrap();
+        metadata.validate_build_scripts(&dir)
+    }
+
+    #[test]
+    fn accepts_valid_scripts() {
+        assert_eq!(
+            validate(
+                r#"
+                pre-build-scripts = [{ name = "Generate", path = "scripts/gen.sh", input-files = ["a.txt"] }]
+                post-compile-scripts = [{ script = "echo hi" }, { script = "echo bye" }]
+                post-build-scripts = [{ name = "Done", script = "echo done" }]
+                "#
+            ),
+            Ok(())
+        );
+    }
+
+    #[rstest(
+        ios,
+        phase,
+        script,
+        problem,
+        case(
+            r#"pre-build-scripts = [{ name = "", script = "true" }]"#,
+            "pre-build-scripts",
+            "#1",
+            BuildScriptProblem::NameEmpty
+        ),
+        case(
+            r#"pre-build-scripts = [{ name = "Lint", script = "true" }]
+            post-build-scripts = [{ name = "Lint", script = "false" }]"#,
+            "post-build-scripts",
+            "\"Lint\"",
+            BuildScriptProblem::NameDuplicated
+        ),
+        case(
+            r#"post-compile-scripts = [{ script = "true" }, { name = "Nothing" }]"#,
+            "post-compile-scripts",
+            "\"Nothing\"",
+            BuildScriptProblem::NeitherPathNorScript
+        ),
+        case(
+            r#"post-compile-scripts = [{ path = "scripts/gen.sh", script = "true" }]"#,
+            "post-compile-scripts",
+            "#1",
+            BuildScriptProblem::BothPathAndScript
+        ),
+        case(
+            r#"post-build-scripts = [{ script = "true", output-file-lists = ["lists/out.xcfilelist", " "] }]"#,
+            "post-build-scripts",
+            "#1",
+            BuildScriptProblem::EntryEmpty { field: "output-file-lists" }
+        )
+    )]
+    fn rejects_invalid_scripts(ios: &str, phase: &str, script: &str, problem: BuildScriptProblem) {
+        let err = validate(ios).unwrap_err();
+        assert_eq!(
+            (err.platform, err.phase, err.script.as_str(), err.problem),
+            ("ios", phase, script, problem)
+        );
+    }
+
+    #[test]
+    fn rejects_missing_paths() {
+        let err = validate(r#"pre-build-scripts = [{ path = "scripts/nope.sh" }]"#).unwrap_err();
+        assert!(matches!(
+            err.problem,
+            BuildScriptProblem::PathMissing(path) if path.ends_with("scripts/nope.sh")
+        ));
+    }
+
+    #[test]
+    fn names_are_per_target() {
+        let dir = TempDirGuard::new().unwrap();
+        let metadata = toml::from_str::<Metadata>(
+            r#"
+            [ios]
+            post-build-scripts = [{ name = "Lint", script = "true" }]
+            [macos]
+            post-build-scripts = [{ name = "Lint", script = "true" }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(metadata.validate_build_scripts(&dir), Ok(()));
+    }
+
+    #[test]
+    fn excluded_simulator_archs_are_ios_only() {
+        let parse = |section: &str| {
+            toml::from_str::<Metadata>(&format!(
+                "[{}]\nexcluded-simulator-archs = [\"arm64\"]",
+                section
+            ))
+        };
+        assert_eq!(
+            parse("ios").unwrap().ios().excluded_simulator_archs(),
+            ["arm64"]
+        );
+        assert!(parse("macos")
+            .unwrap_err()
+            .to_string()
+            .contains("only applies to iOS"));
+    }
+
+    fn versions(
+        bundle_version: Option<&str>,
+        bundle_version_short: Option<&str>,
+        inherited: Option<&str>,
+    ) -> Result<(String, String), Error> {
+        VersionInfo::from_raw(
+            &bundle_version.map(ToOwned::to_owned),
+            &bundle_version_short.map(ToOwned::to_owned),
+            inherited.map(|version| VersionTriple::from_str(version).unwrap()),
+        )
+        .map(|info| {
+            (
+                info.version_number
+                    .map(|version| version.to_string())
+                    .unwrap_or_default(),
+                info.short_version_number
+                    .map(|version| version.to_string())
+                    .unwrap_or_default(),
+            )
+        })
+    }
+
+    #[rstest(
+        bundle_version,
+        bundle_version_short,
+        inherited,
+        expected,
+        case(None, None, None, ("", "")),
+        case(None, None, Some("1.4.0"), ("1.4.0", "1.4.0")),
+        case(Some("2.0.0.7"), None, Some("1.4.0"), ("2.0.0.7", "")),
+        case(Some("2.0.0.7"), Some("2.0.0"), Some("1.4.0"), ("2.0.0.7", "2.0.0")),
+        case(Some("1.4"), None, None, ("1.4.0", ""))
+    )]
+    fn explicit_versions_override_app_version(
+        bundle_version: Option<&str>,
+        bundle_version_short: Option<&str>,
+        inherited: Option<&str>,
+        expected: (&str, &str),
+    ) {
+        let (version, short) = versions(bundle_version, bundle_version_short, inherited).unwrap();
+        assert_eq!((version.as_str(), short.as_str()), expected);
+    }
+
+    #[test]
+    fn app_version_doesnt_excuse_a_lone_short_version() {
+        assert!(matches!(
+            versions(None, Some("1.4.0"), Some("1.4.0")),
+            Err(Error::InvalidVersionConfiguration)
+        ));
+    }
+
+    #[rstest(
+        toml,
+        enabled,
+        case("", false),
+        case("[watchos]", true),
+        case("[watchos]\nsupported = false", false)
+    )]
+    fn watchos_is_opt_in(toml: &str, enabled: bool) {
+        let metadata = toml::from_str::<Metadata>(toml).unwrap();
+        assert_eq!(metadata.watchos().is_some(), enabled);
+    }
+
+    #[test]
+    fn watchos_defaults_and_overrides() {
+        let metadata = toml::from_str::<Metadata>("[watchos]").unwrap();
+        let watchos = metadata.watchos().unwrap();
+        assert_eq!(
+            watchos.deployment_target().unwrap(),
+            Watchos::DEFAULT_DEPLOYMENT_TARGET
+        );
+        assert!(watchos.frameworks().is_empty());
+        let metadata = toml::from_str::<Metadata>(
+            r#"
+            [watchos]
+            deployment-target = "10.2"
+            frameworks = ["HealthKit"]
+            "#,
+        )
+        .unwrap();
+        let watchos = metadata.watchos().unwrap();
+        assert_eq!(watchos.deployment_target().unwrap().to_string(), "10.2");
+        assert_eq!(watchos.frameworks(), ["HealthKit"]);
+    }
+
+    #[test]
+    fn finds_artifacts() {
+        let dir = TempDirGuard::new().unwrap();
+        let expected = dir.join("tic-tac_iOS.ipa");
+        assert_eq!(find_artifact(&dir, &[&expected], "ipa"), None);
+        // With `--scheme`, it's named after whatever that scheme builds.
+        fs::write(dir.join("TicTacClip.ipa"), "").unwrap();
+        fs::write(dir.join("ExportOptions.plist"), "").unwrap();
+        assert_eq!(
+            find_artifact(&dir, &[&expected], "ipa"),
+            Some(dir.join("TicTacClip.ipa"))
+        );
+        fs::write(&expected, "").unwrap();
+        assert_eq!(find_artifact(&dir, &[&expected], "ipa"), Some(expected));
+        // It's ambiguous without the expected one.
+        assert_eq!(
+            find_artifact(&dir, &[&dir.join("tic-tac.ipa")], "ipa"),
+            None
+        );
+    }
 }
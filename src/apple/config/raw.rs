@@ -1,6 +1,11 @@
 use crate::{
     apple::teams,
-    util::{cli::TextWrapper, prompt},
+    config::guide,
+    opts::Guided,
+    util::{
+        cli::TextWrapper,
+        prompt::{self, YesOrNo},
+    },
 };
 use colored::{Color, Colorize as _};
 use serde::{Deserialize, Serialize};
@@ -29,7 +34,10 @@ impl Display for DetectError {
 #[derive(Debug)]
 pub enum PromptError {
     DeveloperTeamLookupFailed(teams::Error),
-    DeveloperTeamPromptFailed(std::io::Error),
+    DeveloperTeamPromptFailed(prompt::Error),
+    SkipPromptFailed(prompt::Error),
+    ProjectDirPromptFailed(prompt::Error),
+    VersionPromptFailed(prompt::Error),
 }
 
 impl Display for PromptError {
@@ -41,6 +49,15 @@ impl Display for PromptError {
             Self::DeveloperTeamPromptFailed(err) => {
                 write!(f, "Failed to prompt for Apple developer team: {}", err)
             }
+            Self::SkipPromptFailed(err) => {
+                write!(f, "Failed to prompt for skipping Apple setup: {}", err)
+            }
+            Self::ProjectDirPromptFailed(err) => {
+                write!(f, "Failed to prompt for Xcode project dir: {}", err)
+            }
+            Self::VersionPromptFailed(err) => {
+                write!(f, "Failed to prompt for deployment target: {}", err)
+            }
         }
     }
 }
@@ -117,9 +134,10 @@ pub struct PListPair {
     value: PlistValue,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Raw {
+    #[serde(default)]
     pub development_team: String,
     pub project_dir: Option<String>,
     pub ios_no_default_features: Option<bool>,
@@ -128,11 +146,16 @@ pub struct Raw {
     pub macos_features: Option<Vec<String>>,
     pub bundle_version: Option<String>,
     pub bundle_version_short: Option<String>,
+    pub debug_bundle_id_suffix: Option<String>,
     pub ios_version: Option<String>,
     pub macos_version: Option<String>,
     pub use_legacy_build_system: Option<bool>,
     pub plist_pairs: Option<Vec<PListPair>>,
     pub enable_bitcode: Option<bool>,
+    pub dsym: Option<super::Dsym>,
+    pub default_target: Option<String>,
+    pub dependencies: Option<crate::apple::deps::Dependencies>,
+    pub existing_project_dir: Option<String>,
 }
 
 impl Raw {
@@ -151,80 +174,50 @@ impl Raw {
             macos_features: None,
             bundle_version: None,
             bundle_version_short: None,
+            debug_bundle_id_suffix: None,
             ios_version: None,
             macos_version: None,
             use_legacy_build_system: None,
             plist_pairs: None,
             enable_bitcode: None,
+            dsym: None,
+            default_target: None,
+            dependencies: None,
+            existing_project_dir: None,
         })
     }
 
-    pub fn prompt(wrapper: &TextWrapper) -> Result<Self, PromptError> {
-        let development_team = {
-            let development_teams =
-                teams::find_development_teams().map_err(PromptError::DeveloperTeamLookupFailed)?;
-            let default_team = if !development_teams.is_empty() {
-                Some("0")
-            } else {
-                None
-            };
-            println!("Detected development teams:");
-            for (index, team) in development_teams.iter().enumerate() {
-                if index == 0 {
-                    println!(
-                        "{}",
-                        format!(
-                            "  [{}] {} ({})",
-                            index.to_string().bright_green(),
-                            team.name,
-                            team.id.bright_cyan(),
-                        )
-                        .bright_white()
-                        .bold()
-                    );
-                } else {
-                    println!(
-                        "  [{}] {} ({})",
-                        index.to_string().green(),
-                        team.name,
-                        team.id.cyan(),
-                    );
-                }
-            }
-            if development_teams.is_empty() {
-                println!("  -- none --");
-            }
-            loop {
-                println!(
-                    "  Enter an {} for a team above, or enter a {} manually.",
-                    "index".green(),
-                    "team ID".cyan(),
-                );
-                let team_input = prompt::default(
-                    "Apple development team",
-                    default_team,
-                    Some(Color::BrightGreen),
+    /// Returns `None` if the user chose to skip Apple setup, which is only
+    /// offered when no teams were found.
+    pub fn prompt(wrapper: &TextWrapper, guided: Guided) -> Result<Option<Self>, PromptError> {
+        let development_teams =
+            teams::find_development_teams().map_err(PromptError::DeveloperTeamLookupFailed)?;
+        if development_teams.is_empty() {
+            println!(
+                "{}",
+                wrapper.fill(
+                    "No Apple development teams were found. If you only want to build for Android for now, you can skip Apple setup and add an `apple` section with your `development-team` later."
+                )
+            );
+            let skip = loop {
+                if let Some(answer) = prompt::yes_no(
+                    "config.apple.skip",
+                    "apple.development-team",
+                    "Skip Apple setup for now?",
+                    Some(YesOrNo::Yes),
                 )
-                .map_err(PromptError::DeveloperTeamPromptFailed)?;
-                let team_id = team_input
-                    .parse::<usize>()
-                    .ok()
-                    .and_then(|index| development_teams.get(index))
-                    .map(|team| team.id.clone())
-                    .unwrap_or_else(|| team_input);
-                if !team_id.is_empty() {
-                    break team_id;
-                } else {
-                    println!(
-                        "{}",
-                        wrapper
-                            .fill("Uh-oh, you need to specify a development team ID.")
-                            .bright_magenta()
-                    );
+                .map_err(PromptError::SkipPromptFailed)?
+                {
+                    break answer.yes();
                 }
+            };
+            if skip {
+                return Ok(None);
             }
-        };
-        Ok(Self {
+        }
+        guide::DEVELOPMENT_TEAM.print(guided, wrapper);
+        let development_team = Self::prompt_team_from(&development_teams, wrapper)?;
+        Ok(Some(Self {
             development_team,
             project_dir: None,
             ios_no_default_features: None,
@@ -233,11 +226,123 @@ impl Raw {
             macos_features: None,
             bundle_version: None,
             bundle_version_short: None,
+            debug_bundle_id_suffix: None,
             ios_version: None,
             macos_version: None,
             use_legacy_build_system: None,
             plist_pairs: None,
             enable_bitcode: None,
+            dsym: None,
+            default_target: None,
+            dependencies: None,
+            existing_project_dir: None,
+        }))
+    }
+
+    pub fn prompt_development_team(wrapper: &TextWrapper) -> Result<String, PromptError> {
+        let development_teams =
+            teams::find_development_teams().map_err(PromptError::DeveloperTeamLookupFailed)?;
+        Self::prompt_team_from(&development_teams, wrapper)
+    }
+
+    fn prompt_team_from(
+        development_teams: &[teams::Team],
+        wrapper: &TextWrapper,
+    ) -> Result<String, PromptError> {
+        let default_team = if !development_teams.is_empty() {
+            Some("0")
+        } else {
+            None
+        };
+        println!("Detected development teams:");
+        for (index, team) in development_teams.iter().enumerate() {
+            if index == 0 {
+                println!(
+                    "{}",
+                    format!(
+                        "  [{}] {} ({})",
+                        index.to_string().bright_green(),
+                        team.name,
+                        team.id.bright_cyan(),
+                    )
+                    .bright_white()
+                    .bold()
+                );
+            } else {
+                println!(
+                    "  [{}] {} ({})",
+                    index.to_string().green(),
+                    team.name,
+                    team.id.cyan(),
+                );
+            }
+        }
+        if development_teams.is_empty() {
+            println!("  -- none --");
+        }
+        Ok(loop {
+            println!(
+                "  Enter an {} for a team above, or enter a {} manually.",
+                "index".green(),
+                "team ID".cyan(),
+            );
+            let team_input = prompt::default(
+                "config.apple.development-team",
+                "apple.development-team",
+                "Apple development team",
+                default_team,
+                Some(Color::BrightGreen),
+            )
+            .map_err(PromptError::DeveloperTeamPromptFailed)?;
+            let team_id = team_input
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| development_teams.get(index))
+                .map(|team| team.id.clone())
+                .unwrap_or_else(|| team_input);
+            if !team_id.is_empty() {
+                break team_id;
+            } else {
+                println!(
+                    "{}",
+                    wrapper
+                        .fill("Uh-oh, you need to specify a development team ID.")
+                        .bright_magenta()
+                );
+            }
         })
     }
+
+    pub fn prompt_project_dir() -> Result<String, PromptError> {
+        prompt::default(
+            "config.apple.project-dir",
+            "apple.project-dir",
+            "Xcode project dir",
+            Some(super::DEFAULT_PROJECT_DIR),
+            None,
+        )
+        .map_err(PromptError::ProjectDirPromptFailed)
+    }
+
+    pub fn prompt_ios_version() -> Result<String, PromptError> {
+        prompt::default(
+            "config.apple.ios-version",
+            "apple.ios-version",
+            "iOS deployment target",
+            Some(&super::DEFAULT_IOS_VERSION.to_string()),
+            None,
+        )
+        .map_err(PromptError::VersionPromptFailed)
+    }
+
+    pub fn prompt_macos_version() -> Result<String, PromptError> {
+        prompt::default(
+            "config.apple.macos-version",
+            "apple.macos-version",
+            "macOS deployment target",
+            Some(&super::DEFAULT_MACOS_VERSION.to_string()),
+            None,
+        )
+        .map_err(PromptError::VersionPromptFailed)
+    }
 }
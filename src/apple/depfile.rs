@@ -0,0 +1,179 @@
+//! Xcode only reruns the script phase when something it knows about changed,
+//! so we merge the dep-info cargo emits for each target into a single depfile
+//! and point the phase's `discoveredDependencyFile` at it.
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to read cargo dep-info at {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to create depfile directory {path:?}: {source}")]
+    DirCreationFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to write depfile to {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: io::Error },
+}
+
+// Splits on whitespace that isn't escaped, and unescapes what's left; this
+// is all of Makefile syntax that cargo uses.
+fn split_paths(paths: &str) -> impl Iterator<Item = PathBuf> + '_ {
+    let mut current = String::new();
+    let mut chars = paths.chars().peekable();
+    std::iter::from_fn(move || {
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if matches!(chars.peek(), Some(' ') | Some('\\') | Some('#')) => {
+                    current.push(chars.next().unwrap());
+                }
+                c if c.is_whitespace() => {
+                    if !current.is_empty() {
+                        return Some(PathBuf::from(std::mem::take(&mut current)));
+                    }
+                }
+                c => current.push(c),
+            }
+        }
+        if current.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(std::mem::take(&mut current)))
+        }
+    })
+}
+
+/// The prerequisites listed in a cargo dep-info file.
+pub fn parse(contents: &str) -> BTreeSet<PathBuf> {
+    contents
+        // Line continuations aren't something cargo emits, but they're legal.
+        .replace("\\\n", " ")
+        .lines()
+        // Take care of `# env-dep:` lines and anything else like them.
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            // The target comes first, and a path containing `: ` would've
+            // had its space escaped.
+            line.find(": ")
+                .map(|index| &line[index + 2..])
+                .or_else(|| line.strip_suffix(':').map(|_| ""))
+        })
+        .flat_map(|deps| split_paths(deps).collect::<Vec<_>>())
+        .collect()
+}
+
+/// The targets of a cargo dep-info file, i.e. the lib it was written for,
+/// as they were named when it was built.
+pub fn targets(contents: &str) -> BTreeSet<PathBuf> {
+    contents
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.find(": ").map(|index| &line[..index]))
+        .flat_map(|targets| split_paths(targets).collect::<Vec<_>>())
+        .collect()
+}
+
+fn escape(path: &Path) -> String {
+    path.display()
+        .to_string()
+        .replace('\\', "\\\\")
+        .replace(' ', "\\ ")
+        .replace('#', "\\#")
+}
+
+/// A single rule making every output depend on every input.
+pub fn render(outputs: &[PathBuf], deps: &BTreeSet<PathBuf>) -> String {
+    let join = |paths: &mut dyn Iterator<Item = &PathBuf>| {
+        paths.map(|path| escape(path)).collect::<Vec<_>>().join(" ")
+    };
+    format!(
+        "{}: {}\n",
+        join(&mut outputs.iter()),
+        join(&mut deps.iter())
+    )
+}
+
+/// Merges the dep-info for each `(artifact, dep_info)` pair into a depfile at
+/// `path`.
+pub fn write(path: &Path, artifacts: &[(PathBuf, PathBuf)]) -> Result<(), Error> {
+    let mut deps = BTreeSet::new();
+    for (_, dep_info) in artifacts {
+        let contents = fs::read_to_string(dep_info).map_err(|source| Error::ReadFailed {
+            path: dep_info.clone(),
+            source,
+        })?;
+        deps.extend(parse(&contents));
+    }
+    let outputs = artifacts
+        .iter()
+        .map(|(artifact, _)| artifact.clone())
+        .collect::<Vec<_>>();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|source| Error::DirCreationFailed {
+            path: parent.to_owned(),
+            source,
+        })?;
+    }
+    fs::write(path, render(&outputs, &deps)).map_err(|source| Error::WriteFailed {
+        path: path.to_owned(),
+        source,
+    })?;
+    log::info!(
+        "wrote depfile with {} dependencies to {:?}",
+        deps.len(),
+        path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_dep_info_targets() {
+        assert_eq!(
+            targets(
+                "/Users/me/My\\ Game/target/aarch64-apple-ios/debug/libmy_game.a: /Users/me/My\\ Game/src/lib.rs\n\
+                 # env-dep:CARGO_PKG_NAME=my-game\n",
+            )
+            .into_iter()
+            .collect::<Vec<_>>(),
+            vec![PathBuf::from(
+                "/Users/me/My Game/target/aarch64-apple-ios/debug/libmy_game.a"
+            )]
+        );
+    }
+
+    #[test]
+    fn parses_cargo_dep_info() {
+        let deps = parse(
+            "/Users/me/My Game/target/aarch64-apple-ios/debug/libmy_game.a: /Users/me/My\\ Game/src/lib.rs /Users/me/My\\ Game/src/ui.rs\n\
+             \n\
+             # env-dep:CARGO_PKG_NAME=my-game\n",
+        );
+        assert_eq!(
+            deps.into_iter().collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("/Users/me/My Game/src/lib.rs"),
+                PathBuf::from("/Users/me/My Game/src/ui.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_and_renders() {
+        let mut deps = parse("a.a: src/lib.rs src/shared.rs\n");
+        deps.extend(parse("b.a: src/lib.rs src/other\\ file.rs\n"));
+        let rendered = render(&[PathBuf::from("a.a"), PathBuf::from("b.a")], &deps);
+        assert_eq!(
+            rendered,
+            "a.a b.a: src/lib.rs src/other\\ file.rs src/shared.rs\n"
+        );
+        // Our output has to be readable by the same rules.
+        assert_eq!(parse(&rendered), deps);
+    }
+}
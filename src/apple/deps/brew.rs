@@ -0,0 +1,251 @@
+//! Macs migrated from Intel to Apple Silicon often end up with two Homebrew
+//! installations, each owning a different subset of packages, so we can't
+//! just trust whichever `brew` happens to be first on the `PATH`.
+
+use once_cell_regex::exports::once_cell::sync::Lazy;
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Arch {
+    Arm64,
+    X86_64,
+}
+
+impl Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Arch {
+    pub fn host() -> Self {
+        // If we're running under Rosetta, we look like x86_64 to ourselves,
+        // but `sysctl` still knows the truth. Intel Macs don't have this key
+        // at all.
+        let arm64 = bossy::Command::impure_parse("sysctl -n hw.optional.arm64")
+            .run_and_wait_for_str(|flag| flag.trim() == "1")
+            .unwrap_or(cfg!(target_arch = "aarch64"));
+        if arm64 {
+            Self::Arm64
+        } else {
+            Self::X86_64
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Arm64 => "arm64",
+            Self::X86_64 => "x86_64",
+        }
+    }
+
+    /// What to pass `arch` to run something as this architecture.
+    pub fn arch_flag(&self) -> &'static str {
+        match self {
+            Self::Arm64 => "-arm64",
+            Self::X86_64 => "-x86_64",
+        }
+    }
+
+    /// Where Homebrew installs itself by default on this architecture.
+    pub fn standard_prefix(&self) -> &'static Path {
+        Path::new(match self {
+            Self::Arm64 => "/opt/homebrew",
+            Self::X86_64 => "/usr/local",
+        })
+    }
+
+    fn from_prefix(prefix: &Path) -> Option<Self> {
+        [Self::Arm64, Self::X86_64]
+            .iter()
+            .copied()
+            .find(|arch| arch.standard_prefix() == prefix)
+    }
+}
+
+#[derive(Debug)]
+pub struct Brew {
+    pub prefix: PathBuf,
+    /// `None` for installations in non-standard prefixes, which we can't
+    /// tell the architecture of.
+    pub arch: Option<Arch>,
+}
+
+impl Brew {
+    fn new(prefix: PathBuf) -> Self {
+        let arch = Arch::from_prefix(&prefix);
+        Self { prefix, arch }
+    }
+
+    pub fn bin(&self) -> PathBuf {
+        self.prefix.join("bin/brew")
+    }
+
+    /// Runs under `arch` when we know the installation's architecture, since
+    /// a Homebrew run as the wrong one (i.e. from a shell under Rosetta)
+    /// refuses to do anything, or worse, builds packages for it.
+    pub fn command(&self) -> bossy::Command {
+        match self.arch {
+            Some(arch) => bossy::Command::impure("arch")
+                .with_arg(arch.arch_flag())
+                .with_arg(self.bin()),
+            None => bossy::Command::impure(self.bin()),
+        }
+    }
+
+    pub fn has(&self, package: &str) -> bool {
+        self.command()
+            .with_args(&["list", "--versions"])
+            .with_arg(package)
+            .run_and_wait_for_output()
+            .is_ok()
+    }
+
+    pub fn matches(&self, host: Arch) -> bool {
+        self.arch.map_or(true, |arch| arch == host)
+    }
+
+    pub fn owns(&self, path: &Path) -> bool {
+        path.starts_with(&self.prefix)
+    }
+}
+
+impl Display for Brew {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(arch) = self.arch {
+            write!(f, "{} Homebrew at {:?}", arch, self.prefix)
+        } else {
+            write!(f, "Homebrew at {:?}", self.prefix)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Brews {
+    pub host: Arch,
+    // Sorted so that the ones matching the host come first.
+    brews: Vec<Brew>,
+}
+
+impl Brews {
+    fn from_prefixes(host: Arch, prefixes: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut brews = Vec::<Brew>::new();
+        for prefix in prefixes {
+            if !brews.iter().any(|brew| brew.prefix == prefix) {
+                brews.push(Brew::new(prefix));
+            }
+        }
+        // `sort_by_key` is stable, so otherwise the order is kept.
+        brews.sort_by_key(|brew| !brew.matches(host));
+        Self { host, brews }
+    }
+
+    pub fn detect() -> Self {
+        let path_prefix = bossy::Command::impure_parse("brew --prefix")
+            .run_and_wait_for_str(|prefix| PathBuf::from(prefix.trim()))
+            .map_err(|err| log::info!("no `brew` on `PATH`: {}", err))
+            .ok();
+        let standard_prefixes = [Arch::Arm64, Arch::X86_64]
+            .iter()
+            .map(|arch| arch.standard_prefix())
+            .filter(|prefix| prefix.join("bin/brew").is_file())
+            .map(Path::to_owned);
+        let brews = Self::from_prefixes(
+            Arch::host(),
+            path_prefix
+                .into_iter()
+                .chain(standard_prefixes)
+                // Symlinked prefixes would otherwise show up twice.
+                .map(|prefix| prefix.canonicalize().unwrap_or(prefix)),
+        );
+        log::info!("detected Homebrew installations: {:#?}", brews);
+        brews
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Brew> {
+        self.brews.iter()
+    }
+
+    /// The one we should install new packages with.
+    pub fn preferred(&self) -> Option<&Brew> {
+        self.brews.first()
+    }
+
+    /// The installation that has `package`, preferring ones matching the host.
+    pub fn owner(&self, package: &str) -> Option<&Brew> {
+        self.brews.iter().find(|brew| brew.has(package))
+    }
+
+    /// The installation a binary at `path` belongs to, if any.
+    pub fn owner_of_path(&self, path: &Path) -> Option<&Brew> {
+        self.brews.iter().find(|brew| brew.owns(path))
+    }
+}
+
+static BREWS: Lazy<Brews> = Lazy::new(Brews::detect);
+
+pub fn brews() -> &'static Brews {
+    &BREWS
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn host_arch_comes_first() {
+        let brews = Brews::from_prefixes(
+            Arch::Arm64,
+            vec![
+                PathBuf::from("/usr/local"),
+                PathBuf::from("/opt/homebrew"),
+                PathBuf::from("/usr/local"),
+            ],
+        );
+        let prefixes = brews
+            .iter()
+            .map(|brew| brew.prefix.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(prefixes, vec!["/opt/homebrew", "/usr/local"]);
+        assert_eq!(brews.preferred().unwrap().arch, Some(Arch::Arm64));
+        assert_eq!(
+            brews
+                .owner_of_path(Path::new("/usr/local/bin/xcodegen"))
+                .and_then(|brew| brew.arch),
+            Some(Arch::X86_64)
+        );
+    }
+
+    #[test]
+    fn commands_run_as_the_installation_arch() {
+        let brew = Brew::new(PathBuf::from("/usr/local"));
+        assert_eq!(
+            brew.command().with_arg("list").display(),
+            "arch -x86_64 /usr/local/bin/brew list"
+        );
+        let brew = Brew::new(PathBuf::from("/Users/me/brew"));
+        assert_eq!(
+            brew.command().with_arg("list").display(),
+            "/Users/me/brew/bin/brew list"
+        );
+    }
+
+    #[test]
+    fn custom_prefixes_match_any_arch() {
+        let brews = Brews::from_prefixes(
+            Arch::X86_64,
+            vec![
+                PathBuf::from("/opt/homebrew"),
+                PathBuf::from("/Users/me/brew"),
+            ],
+        );
+        let prefixes = brews
+            .iter()
+            .map(|brew| brew.prefix.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(prefixes, vec!["/Users/me/brew", "/opt/homebrew"]);
+    }
+}
@@ -1,7 +1,14 @@
+pub mod brew;
+mod policy;
 mod update;
 pub(crate) mod xcode_plugin;
 
-use self::update::{Outdated, OutdatedError};
+pub use self::policy::{AutoUpdate, Dependencies, PinError};
+
+use self::{
+    brew::{brews, Brew},
+    update::{Outdated, OutdatedError},
+};
 use super::system_profile::{self, DeveloperTools};
 use crate::{
     opts,
@@ -12,10 +19,10 @@ use crate::{
     },
 };
 use once_cell_regex::regex;
-use std::collections::hash_set::HashSet;
+use std::{collections::hash_set::HashSet, path::PathBuf};
 use thiserror::Error;
 
-static PACKAGES: &[PackageSpec] = &[
+pub(crate) static PACKAGES: &[PackageSpec] = &[
     PackageSpec::brew("xcodegen"),
     PackageSpec::brew("ios-deploy"),
     PackageSpec::brew_or_gem("cocoapods").with_bin_name("pod"),
@@ -30,13 +37,32 @@ pub enum Error {
         package: &'static str,
         source: bossy::Error,
     },
+    #[error("Homebrew isn't installed, so `{package}` can't be installed")]
+    BrewMissing { package: &'static str },
     #[error("Failed to install `{package}`: {source}")]
     InstallFailed {
         package: &'static str,
         source: bossy::Error,
     },
+    #[error(
+        "Failed to set up the `{}` tap for pinned packages: {source}",
+        policy::PIN_TAP
+    )]
+    PinTapFailed { source: bossy::Error },
+    #[error("Failed to extract `{package}` {version} from Homebrew's history (this needs `homebrew/core` tapped, which `brew tap homebrew/core --force` does): {source}")]
+    PinExtractFailed {
+        package: &'static str,
+        version: String,
+        source: bossy::Error,
+    },
+    #[error("`{package}` is pinned to {pinned} in `apple.dependencies`, but {} is installed; run `cargo mobile init --reinstall-deps` to install the pinned version", .installed.as_deref().unwrap_or("an unknown version"))]
+    PinMismatch {
+        package: &'static str,
+        pinned: String,
+        installed: Option<String>,
+    },
     #[error("Failed to prompt to install updates: {0}")]
-    PromptFailed(#[from] std::io::Error),
+    PromptFailed(#[from] prompt::Error),
     #[error(transparent)]
     VersionLookupFailed(#[from] system_profile::Error),
     #[error("Failed to update package `{package}`")]
@@ -85,43 +111,110 @@ impl GemCache {
         self.set.contains(package)
     }
 
-    pub fn reinstall(&mut self, package: &'static str) -> Result<(), Error> {
+    pub fn reinstall(&mut self, package: &'static str, pin: Option<&str>) -> Result<(), Error> {
         let command = if self.contains(package)? {
-            "gem update"
+            if pin.is_some() {
+                "gem install"
+            } else {
+                "gem update"
+            }
         } else {
             println!("`sudo` is required to install {} using gem", package);
             "sudo gem install"
         };
         bossy::Command::impure_parse(command)
-            .with_arg(package)
+            .with_args(policy::gem_install_args(package, pin))
             .run_and_wait()
             .map_err(|source| Error::InstallFailed { package, source })?;
         Ok(())
     }
 }
 
-fn installed_with_brew(package: &str) -> bool {
-    bossy::Command::impure_parse("brew list")
+fn warn_if_mismatched(package: &str, brew: &Brew) {
+    let host = brews().host;
+    if !brew.matches(host) {
+        println!(
+            "`{}` is only installed with the {}, which doesn't match this {} Mac; reinstalling it with the {} Homebrew is recommended",
+            package, brew, host, host,
+        );
+    }
+}
+
+// `brew extract` needs a tap to put the formula in, and `tap-new` fails if
+// the tap's already there.
+fn ensure_pin_tap(brew: &Brew) -> Result<(), Error> {
+    let tapped = brew
+        .command()
+        .with_arg("tap")
+        .run_and_wait_for_str(|taps| taps.lines().any(|tap| tap.trim() == policy::PIN_TAP))
+        .map_err(|source| Error::PinTapFailed { source })?;
+    if !tapped {
+        brew.command()
+            .with_args(&["tap-new", "--no-git", policy::PIN_TAP])
+            .run_and_wait()
+            .map_err(|source| Error::PinTapFailed { source })?;
+    }
+    Ok(())
+}
+
+fn brew_extract(package: &'static str, version: &str, brew: &Brew) -> Result<(), Error> {
+    ensure_pin_tap(brew)?;
+    brew.command()
+        .with_args(&["extract", "--force"])
+        .with_arg(format!("--version={}", version))
+        .with_args(&[package, policy::PIN_TAP])
+        .run_and_wait()
+        .map_err(|source| Error::PinExtractFailed {
+            package,
+            version: version.to_owned(),
+            source,
+        })?;
+    // The unversioned formula would keep the binary's name linked, so the
+    // pinned one couldn't be. It's fine if it isn't installed.
+    if let Err(err) = brew
+        .command()
+        .with_arg("unlink")
         .with_arg(package)
-        .run_and_wait_for_output()
-        .is_ok()
+        .run_and_wait()
+    {
+        log::info!("didn't unlink `{}`: {}", package, err);
+    }
+    Ok(())
 }
 
-fn brew_reinstall(package: &'static str) -> Result<(), Error> {
+// `brew` is the installation that already has `package`, if any; otherwise,
+// the one matching the host is used.
+fn brew_reinstall(
+    package: &'static str,
+    pin: Option<&str>,
+    brew: Option<&Brew>,
+) -> Result<(), Error> {
+    let brew = brew
+        .or_else(|| brews().preferred())
+        .ok_or(Error::BrewMissing { package })?;
+    if let Some(version) = pin {
+        brew_extract(package, version, brew)?;
+    }
     // reinstall works even if it's not installed yet, and will upgrade
     // if it's already installed!
-    bossy::Command::impure_parse("brew reinstall")
-        .with_arg(package)
+    brew.command()
+        .with_arg("reinstall")
+        .with_arg(policy::brew_formula(package, pin).as_ref())
         .run_and_wait()
         .map_err(|source| Error::InstallFailed { package, source })?;
     Ok(())
 }
 
-fn update_package(package: &'static str, gem_cache: &mut GemCache) -> Result<(), Error> {
-    if installed_with_brew(package) {
-        brew_reinstall(package)?;
+fn update_package(
+    package: &'static str,
+    pin: Option<&str>,
+    gem_cache: &mut GemCache,
+) -> Result<(), Error> {
+    if let Some(brew) = brews().owner(package) {
+        warn_if_mismatched(package, brew);
+        brew_reinstall(package, pin, Some(brew))?;
     } else {
-        gem_cache.reinstall(package)?;
+        gem_cache.reinstall(package, pin)?;
     }
     Ok(())
 }
@@ -167,54 +260,140 @@ impl PackageSpec {
                 package: self.pkg_name,
                 source,
             })?;
-        log::info!("package `{}` present: {}", self.pkg_name, found);
+        if found {
+            log::info!(
+                "package `{}` present, resolving into Homebrew prefix {:?}",
+                self.pkg_name,
+                self.brew().map(|brew| &brew.prefix)
+            );
+        } else {
+            log::info!("package `{}` absent", self.pkg_name);
+        }
         Ok(found)
     }
 
+    /// The Homebrew installation that the binary on the `PATH` belongs to.
+    pub fn brew(&self) -> Option<&'static Brew> {
+        let path = crate::os::command_path(self.bin_name)
+            .ok()?
+            .stdout_str()
+            .ok()
+            .map(|path| PathBuf::from(path.trim()))?;
+        // Homebrew symlinks binaries into `bin`, pointing into the Cellar,
+        // which is inside the prefix either way.
+        let path = path.canonicalize().unwrap_or(path);
+        brews().owner_of_path(&path)
+    }
+
     pub fn install(
         &self,
         reinstall_deps: opts::ReinstallDeps,
         gem_cache: &mut GemCache,
     ) -> Result<bool, Error> {
-        if !self.found()? || reinstall_deps.yes() {
-            println!("Installing `{}`...", self.pkg_name);
+        self.install_pinned(None, reinstall_deps, gem_cache)
+    }
+
+    /// The version the binary on the `PATH` says it is.
+    pub fn installed_version(&self) -> Option<String> {
+        bossy::Command::impure(self.bin_name)
+            .with_arg("--version")
+            .run_and_wait_for_str(|output| policy::parse_version(output).map(ToOwned::to_owned))
+            .map_err(|err| log::info!("failed to get version of `{}`: {}", self.pkg_name, err))
+            .ok()
+            .flatten()
+    }
+
+    // What's on the `PATH` is what we'll run, so that's what has to match,
+    // no matter what Homebrew or gem think they installed.
+    fn check_pin(&self, pin: &str) -> Result<(), Error> {
+        let installed = self.installed_version();
+        if installed.as_deref() == Some(pin) {
+            Ok(())
+        } else {
+            Err(Error::PinMismatch {
+                package: self.pkg_name,
+                pinned: pin.to_owned(),
+                installed,
+            })
+        }
+    }
+
+    /// Like `install`, but installs `pin` instead of the latest version.
+    /// An installed package is left alone either way, but if it's pinned,
+    /// it has to be at the pinned version.
+    pub fn install_pinned(
+        &self,
+        pin: Option<&str>,
+        reinstall_deps: opts::ReinstallDeps,
+        gem_cache: &mut GemCache,
+    ) -> Result<bool, Error> {
+        let installed = if !self.found()? || reinstall_deps.yes() {
+            match pin {
+                Some(version) => println!("Installing `{}` {}...", self.pkg_name, version),
+                None => println!("Installing `{}`...", self.pkg_name),
+            }
             match self.package_source {
-                PackageSource::Brew => brew_reinstall(self.pkg_name)?,
-                PackageSource::BrewOrGem => update_package(self.pkg_name, gem_cache)?,
+                // Fresh installs always go to the Homebrew matching the host.
+                PackageSource::Brew => brew_reinstall(self.pkg_name, pin, None)?,
+                PackageSource::BrewOrGem => update_package(self.pkg_name, pin, gem_cache)?,
             }
-            Ok(true)
+            true
         } else {
-            Ok(false)
+            if let Some(brew) = self.brew() {
+                warn_if_mismatched(self.pkg_name, brew);
+            }
+            false
+        };
+        if let Some(version) = pin {
+            self.check_pin(version)?;
         }
+        Ok(installed)
     }
 }
 
 pub fn install_all(
     wrapper: &TextWrapper,
+    dependencies: &Dependencies,
     non_interactive: opts::NonInteractive,
     skip_dev_tools: opts::SkipDevTools,
     reinstall_deps: opts::ReinstallDeps,
 ) -> Result<(), Error> {
     let mut gem_cache = GemCache::new();
     for package in PACKAGES {
-        package.install(reinstall_deps, &mut gem_cache)?;
+        package.install_pinned(
+            dependencies.pin(package.pkg_name),
+            reinstall_deps,
+            &mut gem_cache,
+        )?;
     }
     gem_cache.initialize()?;
-    let outdated = Outdated::load(&mut gem_cache)?;
+    let (outdated, pinned) = Outdated::load(&mut gem_cache)?.split_pinned(dependencies);
     outdated.print_notice();
-    if !outdated.is_empty() && non_interactive.no() {
-        let answer = loop {
-            if let Some(answer) = prompt::yes_no(
-                "Would you like these outdated dependencies to be updated for you?",
-                Some(prompt::YesOrNo::Yes),
-            )? {
-                break answer;
+    pinned.print_pinned_notice(dependencies);
+    let update = !outdated.is_empty()
+        && match dependencies.auto_update() {
+            AutoUpdate::Never => {
+                println!("Not updating, since `apple.dependencies.auto-update` is \"never\"");
+                false
             }
-        };
-        if answer.yes() {
-            for package in outdated.iter() {
-                update_package(package, &mut gem_cache)?;
+            AutoUpdate::Prompt => {
+                non_interactive.no()
+                    && loop {
+                        if let Some(answer) = prompt::yes_no(
+                            "apple.deps.update",
+                            "apple.dependencies.auto-update",
+                            "Would you like these outdated dependencies to be updated for you?",
+                            Some(prompt::YesOrNo::Yes),
+                        )? {
+                            break answer.yes();
+                        }
+                    }
             }
+            AutoUpdate::Always => true,
+        };
+    if update {
+        for package in outdated.iter() {
+            update_package(package, None, &mut gem_cache)?;
         }
     }
     // we definitely don't want to install this on CI...
@@ -0,0 +1,186 @@
+//! `[apple.dependencies]`, i.e. what `cargo mobile init` is allowed to do
+//! about the packages in `PACKAGES`.
+
+use super::PACKAGES;
+use once_cell_regex::regex;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, collections::BTreeMap};
+use thiserror::Error;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoUpdate {
+    /// Report outdated packages, but leave them alone.
+    Never,
+    /// Ask before updating, unless we're non-interactive.
+    Prompt,
+    /// Update without asking.
+    Always,
+}
+
+impl Default for AutoUpdate {
+    fn default() -> Self {
+        Self::Prompt
+    }
+}
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum PinError {
+    #[error("`{package}` is pinned, but isn't one of the packages we install ({known:?})")]
+    UnknownPackage {
+        package: String,
+        known: Vec<&'static str>,
+    },
+    #[error("`{package}` is pinned to an empty version")]
+    VersionEmpty { package: String },
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Dependencies {
+    #[serde(default)]
+    auto_update: AutoUpdate,
+    /// Everything else is a version pin, i.e. `xcodegen = "2.38.0"`.
+    #[serde(flatten)]
+    pins: BTreeMap<String, String>,
+}
+
+impl Dependencies {
+    pub fn validate(&self) -> Result<(), PinError> {
+        for (package, version) in &self.pins {
+            if !PACKAGES.iter().any(|spec| spec.pkg_name == package) {
+                return Err(PinError::UnknownPackage {
+                    package: package.clone(),
+                    known: PACKAGES.iter().map(|spec| spec.pkg_name).collect(),
+                });
+            }
+            if version.trim().is_empty() {
+                return Err(PinError::VersionEmpty {
+                    package: package.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn auto_update(&self) -> AutoUpdate {
+        self.auto_update
+    }
+
+    pub fn pin(&self, package: &str) -> Option<&str> {
+        self.pins.get(package).map(|version| version.trim())
+    }
+}
+
+/// Where pinned formulae go. Homebrew only keeps versioned formulae for a
+/// few packages, so we `brew extract` the version we want into our own tap.
+pub static PIN_TAP: &str = "cargo-mobile/pins";
+
+/// The formula to install, which for a pin is the one extracted into
+/// `PIN_TAP` (`cargo-mobile/pins/xcodegen@2.38.0`).
+pub fn brew_formula<'a>(package: &'a str, pin: Option<&str>) -> Cow<'a, str> {
+    match pin {
+        Some(version) => Cow::Owned(format!("{}/{}@{}", PIN_TAP, package, version)),
+        None => Cow::Borrowed(package),
+    }
+}
+
+/// The version in what a package's `--version` printed, i.e. `2.38.0` out of
+/// `Version: 2.38.0`.
+pub fn parse_version(output: &str) -> Option<&str> {
+    regex!(r"\d+(?:\.\d+)+")
+        .find(output)
+        .map(|version| version.as_str())
+}
+
+/// The args for `gem install`, which is what we use for pins since
+/// `gem update` can only go to the latest version.
+pub fn gem_install_args(package: &str, pin: Option<&str>) -> Vec<String> {
+    let mut args = vec![package.to_owned()];
+    if let Some(version) = pin {
+        args.push("-v".to_owned());
+        args.push(version.to_owned());
+    }
+    args
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn parse(toml: &str) -> Dependencies {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn defaults_to_prompting_without_pins() {
+        let deps = parse("");
+        assert_eq!(deps.auto_update(), AutoUpdate::Prompt);
+        assert_eq!(deps.pin("xcodegen"), None);
+        assert_eq!(deps.validate(), Ok(()));
+    }
+
+    #[test]
+    fn reads_pins_next_to_auto_update() {
+        let deps = parse(
+            r#"
+            auto-update = "never"
+            xcodegen = "2.38.0"
+            cocoapods = " 1.11.3 "
+            "#,
+        );
+        assert_eq!(deps.auto_update(), AutoUpdate::Never);
+        assert_eq!(deps.pin("xcodegen"), Some("2.38.0"));
+        assert_eq!(deps.pin("cocoapods"), Some("1.11.3"));
+        assert_eq!(deps.pin("ios-deploy"), None);
+        assert_eq!(deps.validate(), Ok(()));
+    }
+
+    #[rstest(
+        toml,
+        expected,
+        case(
+            r#"xcodgen = "2.38.0""#,
+            PinError::UnknownPackage {
+                package: "xcodgen".to_owned(),
+                known: vec!["xcodegen", "ios-deploy", "cocoapods"],
+            }
+        ),
+        case(
+            r#"ios-deploy = "  ""#,
+            PinError::VersionEmpty {
+                package: "ios-deploy".to_owned(),
+            }
+        )
+    )]
+    fn rejects_bad_pins(toml: &str, expected: PinError) {
+        assert_eq!(parse(toml).validate(), Err(expected));
+    }
+
+    #[test]
+    fn resolves_install_args() {
+        assert_eq!(brew_formula("xcodegen", None), "xcodegen");
+        assert_eq!(
+            brew_formula("xcodegen", Some("2.38.0")),
+            "cargo-mobile/pins/xcodegen@2.38.0"
+        );
+        assert_eq!(gem_install_args("cocoapods", None), vec!["cocoapods"]);
+        assert_eq!(
+            gem_install_args("cocoapods", Some("1.11.3")),
+            vec!["cocoapods", "-v", "1.11.3"]
+        );
+    }
+
+    #[rstest(
+        output,
+        expected,
+        case("Version: 2.38.0\n", Some("2.38.0")),
+        case("1.12.2\n", Some("1.12.2")),
+        case("1.11.3\n", Some("1.11.3")),
+        case("command not found\n", None)
+    )]
+    fn parses_versions(output: &str, expected: Option<&str>) {
+        assert_eq!(parse_version(output), expected);
+    }
+}
@@ -1,6 +1,7 @@
 use super::{
+    brew::brews,
     util::{self, CaptureGroupError},
-    GemCache, PACKAGES,
+    Dependencies, GemCache, PACKAGES,
 };
 use once_cell_regex::regex;
 use serde::Deserialize;
@@ -97,21 +98,24 @@ impl Outdated {
             formulae: Vec<Formula>,
         }
 
-        bossy::Command::impure_parse("brew outdated --json=v2")
-            .run_and_wait_for_output()
-            .map_err(OutdatedError::CommandFailed)
-            .and_then(|output| serde_json::from_slice(output.stdout()).map_err(Into::into))
-            .map(|Raw { formulae }| {
-                formulae
-                    .into_iter()
-                    .filter(|formula| {
-                        PACKAGES
-                            .iter()
-                            .find(|spec| formula.name == spec.pkg_name)
-                            .is_some()
-                    })
-                    .map(Ok)
-            })
+        let mut outdated = Vec::<Formula>::new();
+        // Each installation only knows about its own packages. They're
+        // ordered host-first, so if both have a package, the host's wins.
+        for brew in brews().iter() {
+            let Raw { formulae } = brew
+                .command()
+                .with_args(&["outdated", "--json=v2"])
+                .run_and_wait_for_output()
+                .map_err(OutdatedError::CommandFailed)
+                .and_then(|output| serde_json::from_slice(output.stdout()).map_err(Into::into))?;
+            for formula in formulae {
+                let relevant = PACKAGES.iter().any(|spec| formula.name == spec.pkg_name);
+                if relevant && !outdated.iter().any(|other| other.name == formula.name) {
+                    outdated.push(formula);
+                }
+            }
+        }
+        Ok(outdated.into_iter().map(Ok))
     }
 
     pub fn load(gem_cache: &mut GemCache) -> Result<Self, OutdatedError> {
@@ -137,6 +141,16 @@ impl Outdated {
         })
     }
 
+    /// Splits off the packages that are pinned, which are never updated,
+    /// giving back `(unpinned, pinned)`.
+    pub fn split_pinned(self, dependencies: &Dependencies) -> (Self, Self) {
+        let (pinned, unpinned) = self
+            .packages
+            .into_iter()
+            .partition(|formula| dependencies.pin(&formula.name).is_some());
+        (Self { packages: unpinned }, Self { packages: pinned })
+    }
+
     pub fn is_empty(&self) -> bool {
         self.packages.is_empty()
     }
@@ -151,4 +165,65 @@ impl Outdated {
             println!("Apple dependencies are up to date");
         }
     }
+
+    pub fn print_pinned_notice(&self, dependencies: &Dependencies) {
+        if !self.is_empty() {
+            println!("Pinned dependencies, which won't be updated:");
+            for package in self.packages.iter() {
+                package.print_notice();
+                if let Some(version) = dependencies.pin(&package.name) {
+                    println!("    (pinned to {} in `apple.dependencies`)", version);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn formula(name: &str) -> Formula {
+        Formula {
+            name: name.to_owned(),
+            installed_versions: vec!["1.0.0".to_owned()],
+            current_version: "2.0.0".to_owned(),
+        }
+    }
+
+    fn names(outdated: &Outdated) -> Vec<&str> {
+        outdated.iter().collect()
+    }
+
+    #[test]
+    fn pinned_packages_are_split_off() {
+        let outdated = Outdated {
+            packages: vec![
+                formula("xcodegen"),
+                formula("ios-deploy"),
+                formula("cocoapods"),
+            ],
+        };
+        let dependencies = toml::from_str(
+            r#"
+            auto-update = "always"
+            xcodegen = "2.38.0"
+            cocoapods = "1.11.3"
+            "#,
+        )
+        .unwrap();
+        let (unpinned, pinned) = outdated.split_pinned(&dependencies);
+        assert_eq!(names(&unpinned), vec!["ios-deploy"]);
+        assert_eq!(names(&pinned), vec!["xcodegen", "cocoapods"]);
+    }
+
+    #[test]
+    fn nothing_is_split_off_without_pins() {
+        let outdated = Outdated {
+            packages: vec![formula("xcodegen")],
+        };
+        let (unpinned, pinned) = outdated.split_pinned(&Dependencies::default());
+        assert_eq!(names(&unpinned), vec!["xcodegen"]);
+        assert!(pinned.is_empty());
+    }
 }
@@ -1,15 +1,21 @@
 use super::{
     config::Config,
-    ios_deploy,
+    depfile,
+    ios_deploy::{self, lldb},
+    lib_env,
     target::{ArchiveError, BuildError, ExportError, Target},
 };
 use crate::{
     env::{Env, ExplicitEnv as _},
     opts,
-    util::cli::{Report, Reportable},
+    util::{
+        cli::{Report, Reportable},
+        VersionDouble,
+    },
 };
 use std::{
     fmt::{self, Display},
+    fs,
     path::PathBuf,
 };
 
@@ -20,6 +26,7 @@ pub enum RunError {
     ExportFailed(ExportError),
     IpaMissing { old: PathBuf, new: PathBuf },
     UnzipFailed(bossy::Error),
+    LldbInitFailed(lldb::Error),
     DeployFailed(ios_deploy::RunAndDebugError),
 }
 
@@ -34,6 +41,7 @@ impl Reportable for RunError {
                 format!("Not found at either {:?} or {:?}", old, new),
             ),
             Self::UnzipFailed(err) => Report::error("Failed to unzip archive", err),
+            Self::LldbInitFailed(err) => err.report(),
             Self::DeployFailed(err) => err.report(),
         }
     }
@@ -44,29 +52,62 @@ pub struct Device<'a> {
     id: String,
     name: String,
     model: String,
+    os_version: Option<VersionDouble>,
     target: &'a Target<'a>,
+    interface: ios_deploy::Interface,
 }
 
 impl<'a> Display for Device<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} ({})", self.name, self.model)
+        match self.interface {
+            ios_deploy::Interface::Usb => write!(f, "{} ({})", self.name, self.model),
+            ios_deploy::Interface::Network => {
+                write!(f, "{} ({}, network)", self.name, self.model)
+            }
+        }
     }
 }
 
 impl<'a> Device<'a> {
-    pub(super) fn new(id: String, name: String, model: String, target: &'a Target<'a>) -> Self {
+    pub(super) fn new(
+        id: String,
+        name: String,
+        model: String,
+        os_version: Option<VersionDouble>,
+        target: &'a Target<'a>,
+        interface: ios_deploy::Interface,
+    ) -> Self {
         Self {
             id,
             name,
             model,
+            os_version,
             target,
+            interface,
         }
     }
 
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The iOS version, if `ios-deploy` reported it.
+    pub fn os_version(&self) -> Option<VersionDouble> {
+        self.os_version
+    }
+
     pub fn target(&self) -> &'a Target<'a> {
         self.target
     }
 
+    pub fn interface(&self) -> ios_deploy::Interface {
+        self.interface
+    }
+
     pub fn run(
         &self,
         config: &Config,
@@ -75,6 +116,8 @@ impl<'a> Device<'a> {
         non_interactive: opts::NonInteractive,
         profile: opts::Profile,
         features: Option<String>,
+        launch_args: &[String],
+        attach: Option<lldb::Attach>,
     ) -> Result<(), RunError> {
         // TODO: These steps are run unconditionally, which is slooooooow
         println!("Building app...");
@@ -107,8 +150,59 @@ impl<'a> Device<'a> {
             .with_arg(&config.export_dir())
             .run_and_wait()
             .map_err(RunError::UnzipFailed)?;
-        ios_deploy::run_and_debug(config, env, non_interactive, &self.id)
-            .map_err(RunError::DeployFailed)?;
+        let lldb_init = attach
+            .map(|attach| {
+                let path = self.write_lldb_init(config, profile, attach)?;
+                attach.print_handoff();
+                Ok(path)
+            })
+            .transpose()?;
+        ios_deploy::run_and_debug(
+            config,
+            env,
+            non_interactive,
+            &self.id,
+            self.interface,
+            launch_args,
+            lldb_init.as_deref(),
+        )
+        .map_err(RunError::DeployFailed)?;
         Ok(())
     }
+
+    fn write_lldb_init(
+        &self,
+        config: &Config,
+        profile: opts::Profile,
+        attach: lldb::Attach,
+    ) -> Result<PathBuf, RunError> {
+        let toolchain = lldb::Toolchain::detect().map_err(RunError::LldbInitFailed)?;
+        if !toolchain.rust_src().is_dir() {
+            println!(
+                "Warning: `rust-src` isn't installed, so lldb won't be able to show std's sources; `rustup component add rust-src` fixes that."
+            );
+        }
+        let root_dir = config.app().root_dir();
+        let (lib_path, dep_info_path) = lib_env::lib_paths(config, self.target, profile);
+        // The dep-info names the lib as it was when it was built, which tells
+        // us if the checkout's moved since.
+        let build_dir = fs::read_to_string(&dep_info_path)
+            .ok()
+            .and_then(|contents| depfile::targets(&contents).into_iter().next())
+            .map(|built| lldb::build_dir(&built, &lib_path, root_dir))
+            .unwrap_or_else(|| root_dir.to_owned());
+        let path = lib_path.with_file_name("cargo-mobile.lldbinit");
+        lldb::Init {
+            toolchain: &toolchain,
+            triple: self.target.triple,
+            lib_path: &lib_path,
+            build_dir: &build_dir,
+            root_dir,
+            keep_running: attach.keep_running,
+        }
+        .write(&path)
+        .map_err(RunError::LldbInitFailed)?;
+        log::info!("wrote lldb init file to {:?}", path);
+        Ok(path)
+    }
 }
@@ -0,0 +1,144 @@
+use super::config::Config;
+use crate::util::{
+    self,
+    cli::{Report, Reportable},
+};
+use once_cell_regex::regex;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum DsymError {
+    DsymMissing(PathBuf),
+    UuidLookupFailed(bossy::Error),
+    UuidMismatch {
+        binary: PathBuf,
+        binary_uuids: BTreeSet<(String, String)>,
+        dsym_uuids: BTreeSet<(String, String)>,
+    },
+    CopyFailed(util::CopyDirError),
+    UploadFailed(bossy::Error),
+}
+
+fn format_uuids(uuids: &BTreeSet<(String, String)>) -> String {
+    uuids
+        .iter()
+        .map(|(uuid, arch)| format!("{} ({})", uuid, arch))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Reportable for DsymError {
+    fn report(&self) -> Report {
+        match self {
+            Self::DsymMissing(path) => Report::error(
+                "Archive doesn't contain a dSYM for the app",
+                format!(
+                    "Expected one at {:?}; make sure `DEBUG_INFORMATION_FORMAT` is `dwarf-with-dsym` for release builds.",
+                    path
+                ),
+            ),
+            Self::UuidLookupFailed(err) => Report::error("Failed to read UUIDs via `dwarfdump`", err),
+            Self::UuidMismatch {
+                binary,
+                binary_uuids,
+                dsym_uuids,
+            } => Report::error(
+                "dSYM doesn't match the archived binary",
+                format!(
+                    "{:?} has UUIDs [{}], but the dSYM has [{}]. Uploading these symbols would silently produce unsymbolicated crash reports, so nothing was copied or uploaded.",
+                    binary,
+                    format_uuids(binary_uuids),
+                    format_uuids(dsym_uuids),
+                ),
+            ),
+            Self::CopyFailed(err) => Report::error("Failed to copy dSYMs", err),
+            Self::UploadFailed(err) => Report::error("Failed to run `apple.dsym.upload-command`", err),
+        }
+    }
+}
+
+fn parse_uuids(output: &str) -> BTreeSet<(String, String)> {
+    regex!(r"UUID: ([0-9A-Fa-f-]+) \(([^)]+)\)")
+        .captures_iter(output)
+        .map(|caps| (caps[1].to_uppercase(), caps[2].to_owned()))
+        .collect()
+}
+
+fn uuids(path: &Path) -> Result<BTreeSet<(String, String)>, DsymError> {
+    bossy::Command::impure_parse("xcrun dwarfdump --uuid")
+        .with_arg(path)
+        .run_and_wait_for_str(parse_uuids)
+        .map_err(DsymError::UuidLookupFailed)
+}
+
+/// Verifies, copies, and uploads the dSYMs from a freshly made archive, as
+/// configured by `apple.dsym`.
+pub fn process(config: &Config, archive_path: &Path) -> Result<(), DsymError> {
+    let dsym = if let Some(dsym) = config.dsym() {
+        dsym
+    } else {
+        log::info!("`{}.dsym` not set; skipping dSYM handling", super::NAME);
+        return Ok(());
+    };
+    let name = config.app().name();
+    let dsyms_dir = archive_path.join("dSYMs");
+    let app_dsym = dsyms_dir.join(format!("{}.app.dSYM", name));
+    if !app_dsym.is_dir() {
+        return Err(DsymError::DsymMissing(app_dsym));
+    }
+    let binary = archive_path.join(format!("Products/Applications/{0}.app/{0}", name));
+    let binary_uuids = uuids(&binary)?;
+    let dsym_uuids = uuids(&app_dsym)?;
+    if binary_uuids.is_empty() || !binary_uuids.is_subset(&dsym_uuids) {
+        return Err(DsymError::UuidMismatch {
+            binary,
+            binary_uuids,
+            dsym_uuids,
+        });
+    }
+    println!("dSYM UUIDs match: {}", format_uuids(&binary_uuids));
+
+    util::copy_dir_if_changed(&dsyms_dir, &dsym.copy_to).map_err(DsymError::CopyFailed)?;
+    println!("Copied dSYMs to {:?}", dsym.copy_to);
+
+    if let Some(upload_command) = &dsym.upload_command {
+        // Going through an env var spares us from having to quote the path.
+        let command = upload_command.replace("{dsym_path}", "\"$DSYM_PATH\"");
+        println!("Uploading dSYMs...");
+        bossy::Command::impure("sh")
+            .with_env_var("DSYM_PATH", &dsyms_dir)
+            .with_arg("-c")
+            .with_arg(&command)
+            .run_and_wait()
+            .map_err(DsymError::UploadFailed)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_dwarfdump_uuids() {
+        let output = "UUID: 0f4b6a2e-1d2c-3b4a-9e8f-7a6b5c4d3e2f (arm64) /tmp/Foo.app/Foo\n\
+                      UUID: 1A2B3C4D-5E6F-7081-92A3-B4C5D6E7F809 (x86_64) /tmp/Foo.app/Foo\n";
+        let uuids = parse_uuids(output);
+        assert_eq!(
+            uuids.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "0F4B6A2E-1D2C-3B4A-9E8F-7A6B5C4D3E2F".to_owned(),
+                    "arm64".to_owned()
+                ),
+                (
+                    "1A2B3C4D-5E6F-7081-92A3-B4C5D6E7F809".to_owned(),
+                    "x86_64".to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,470 @@
+//! Capabilities like push notifications need entitlements, which Xcode reads
+//! from the plist named by `CODE_SIGN_ENTITLEMENTS`. We generate that plist
+//! from the metadata, so it doesn't get lost when the project is regenerated.
+
+use super::config::Config;
+use crate::{
+    opts::Profile,
+    util::{
+        self,
+        cli::{Report, Reportable},
+        TempDirError, TempDirGuard,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// The subset of plist types that entitlements actually use. Keeping these
+/// typed means `true` stays a `<true/>` instead of turning into a string.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    String(String),
+    Array(Vec<Value>),
+}
+
+/// Sorted, so the generated file only changes when the entitlements do.
+pub type Entitlements = BTreeMap<String, Value>;
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn render_value(out: &mut String, value: &Value, depth: usize) {
+    let indent = "\t".repeat(depth);
+    match value {
+        Value::Bool(true) => writeln!(out, "{}<true/>", indent),
+        Value::Bool(false) => writeln!(out, "{}<false/>", indent),
+        Value::String(s) => writeln!(out, "{}<string>{}</string>", indent, escape(s)),
+        Value::Array(values) if values.is_empty() => writeln!(out, "{}<array/>", indent),
+        Value::Array(values) => {
+            writeln!(out, "{}<array>", indent).unwrap();
+            for value in values {
+                render_value(out, value, depth + 1);
+            }
+            writeln!(out, "{}</array>", indent)
+        }
+    }
+    .unwrap();
+}
+
+/// Renders `entitlements` the same way Xcode writes plists.
+pub fn render(entitlements: &Entitlements) -> String {
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n",
+        "<dict>\n",
+    ));
+    for (key, value) in entitlements {
+        writeln!(out, "\t<key>{}</key>", escape(key)).unwrap();
+        render_value(&mut out, value, 1);
+    }
+    out.push_str("</dict>\n</plist>\n");
+    out
+}
+
+/// Relative to the project dir, next to the target's `Info.plist`.
+pub fn rel_path(app_name: &str, platform: &str) -> PathBuf {
+    Path::new(&format!("{}_{}", app_name, platform))
+        .join(format!("{}_{}.entitlements", app_name, platform))
+}
+
+#[derive(Debug, Error)]
+pub enum WriteError {
+    #[error("Failed to create directory {path:?}: {source}")]
+    DirCreationFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to write entitlements to {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: io::Error },
+}
+
+/// Writes the entitlements file, leaving it alone if nothing changed so Xcode
+/// doesn't rebuild for no reason.
+pub fn write(path: &Path, entitlements: &Entitlements) -> Result<(), WriteError> {
+    let contents = render(entitlements);
+    if fs::read_to_string(path).map_or(false, |current| current == contents) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|source| WriteError::DirCreationFailed {
+            path: parent.to_owned(),
+            source,
+        })?;
+    }
+    log::info!("writing entitlements to {:?}", path);
+    fs::write(path, contents).map_err(|source| WriteError::WriteFailed {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum CheckError {
+    #[error(transparent)]
+    TempDirFailed(TempDirError),
+    #[error("Failed to decode provisioning profile {path:?}: {source}")]
+    DecodeFailed { path: PathBuf, source: bossy::Error },
+    #[error("Failed to extract entitlements from provisioning profile {path:?}: {source}")]
+    ExtractFailed { path: PathBuf, source: bossy::Error },
+    #[error("Provisioning profile {path:?} has invalid entitlements: {source}")]
+    ParseFailed {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Provisioning profile {profile:?} doesn't grant {}, which this app's entitlements ask for. Enable the matching capabilities for {bundle_id:?} in the Apple Developer portal, then download the updated profile (i.e. via Xcode's account settings) and archive again.", .missing.join(", "))]
+    CapabilitiesMissing {
+        profile: PathBuf,
+        bundle_id: String,
+        missing: Vec<String>,
+    },
+}
+
+impl Reportable for CheckError {
+    fn report(&self) -> Report {
+        match self {
+            Self::CapabilitiesMissing { .. } => {
+                Report::action_request("Provisioning profile is missing capabilities", self)
+            }
+            _ => Report::error("Failed to check provisioning profile", self),
+        }
+    }
+}
+
+/// Whether a profile's `application-identifier`, which can end in a `*`
+/// wildcard, covers `app_id`. Both start with a team ID, which has to match
+/// exactly, since the wildcard only ever stands in for part of the bundle ID.
+pub fn app_id_matches(pattern: &str, app_id: &str) -> bool {
+    match (pattern.split_once('.'), app_id.split_once('.')) {
+        (Some((pattern_team, pattern)), Some((team, bundle_id))) if pattern_team == team => {
+            match pattern.strip_suffix('*') {
+                Some(prefix) => bundle_id.starts_with(prefix),
+                None => pattern == bundle_id,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Out of `(path, application-identifier)` pairs, the profile Xcode would
+/// sign `app_id` with, and whether it's an exact match. Exact matches win,
+/// and then the most specific wildcard.
+pub fn best_profile<'a>(
+    profiles: &'a [(PathBuf, String)],
+    app_id: &str,
+) -> Option<(&'a Path, bool)> {
+    profiles
+        .iter()
+        .filter(|(_, pattern)| app_id_matches(pattern, app_id))
+        .map(|(path, pattern)| (path, pattern == app_id, pattern.len()))
+        .max_by_key(|(_, exact, len)| (*exact, *len))
+        .map(|(path, exact, _)| (path.as_path(), exact))
+}
+
+/// The entitlement keys in `requested` that `granted` doesn't have. Only keys
+/// are compared, since profiles often grant `*` or a list of values.
+pub fn missing(
+    requested: &Entitlements,
+    granted: &serde_json::Map<String, serde_json::Value>,
+) -> Vec<String> {
+    requested
+        .keys()
+        .filter(|key| !granted.contains_key(key.as_str()))
+        .cloned()
+        .collect()
+}
+
+fn profiles_dir() -> Option<PathBuf> {
+    util::home_dir()
+        .map(|home| home.join("Library/MobileDevice/Provisioning Profiles"))
+        .ok()
+}
+
+/// The installed provisioning profiles, sorted by path.
+fn installed_profiles() -> Vec<PathBuf> {
+    let mut profiles = profiles_dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .map_or(false, |ext| ext == "mobileprovision")
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    profiles.sort();
+    profiles
+}
+
+fn profile_entitlements(
+    path: &Path,
+    stage: &Path,
+) -> Result<serde_json::Map<String, serde_json::Value>, CheckError> {
+    let decoded = stage.join("profile.plist");
+    bossy::Command::impure_parse("security cms -D")
+        .with_arg("-i")
+        .with_arg(path)
+        .with_arg("-o")
+        .with_arg(&decoded)
+        .run_and_wait_for_output()
+        .map_err(|source| CheckError::DecodeFailed {
+            path: path.to_owned(),
+            source,
+        })?;
+    let json = bossy::Command::impure_parse("plutil -extract Entitlements json -o -")
+        .with_arg(&decoded)
+        .run_and_wait_for_str(|s| s.to_owned())
+        .map_err(|source| CheckError::ExtractFailed {
+            path: path.to_owned(),
+            source,
+        })?;
+    serde_json::from_str(&json).map_err(|source| CheckError::ParseFailed {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// The `application-identifier` of each installed provisioning profile,
+/// skipping any that can't be read.
+pub fn installed_app_ids() -> Result<Vec<(PathBuf, String)>, CheckError> {
+    let profiles = installed_profiles();
+    if profiles.is_empty() {
+        return Ok(Vec::new());
+    }
+    let stage = TempDirGuard::new().map_err(CheckError::TempDirFailed)?;
+    Ok(profiles
+        .into_iter()
+        .filter_map(|path| {
+            let granted = profile_entitlements(&path, &stage)
+                .map_err(|err| log::warn!("skipping provisioning profile: {}", err))
+                .ok()?;
+            let app_id = granted.get("application-identifier")?.as_str()?.to_owned();
+            Some((path, app_id))
+        })
+        .collect())
+}
+
+/// Fails if there are installed provisioning profiles for this app, but none
+/// of them grant everything in `requested`. Having no profile at all is fine,
+/// since Xcode can make one while archiving.
+pub fn check_profiles(
+    config: &Config,
+    profile: Profile,
+    requested: &Entitlements,
+) -> Result<(), CheckError> {
+    let team = match config.development_team() {
+        Some(team) => team,
+        None => return Ok(()),
+    };
+    let bundle_id = config.bundle_identifier(profile);
+    let app_id = format!("{}.{}", team, bundle_id);
+    let profiles = installed_profiles();
+    if profiles.is_empty() {
+        return Ok(());
+    }
+    let stage = TempDirGuard::new().map_err(CheckError::TempDirFailed)?;
+    // An exact match is what Xcode would pick, so that's what we complain
+    // about if nothing fits.
+    let mut best: Option<(bool, PathBuf, Vec<String>)> = None;
+    for path in profiles {
+        let granted = match profile_entitlements(&path, &stage) {
+            Ok(granted) => granted,
+            Err(err) => {
+                log::warn!("skipping provisioning profile: {}", err);
+                continue;
+            }
+        };
+        let pattern = match granted
+            .get("application-identifier")
+            .and_then(|id| id.as_str())
+        {
+            Some(pattern) if app_id_matches(pattern, &app_id) => pattern,
+            _ => continue,
+        };
+        let missing = missing(requested, &granted);
+        if missing.is_empty() {
+            log::info!("provisioning profile {:?} grants all entitlements", path);
+            return Ok(());
+        }
+        let exact = pattern == app_id;
+        if best
+            .as_ref()
+            .map_or(true, |(best_exact, ..)| exact && !best_exact)
+        {
+            best = Some((exact, path, missing));
+        }
+    }
+    match best {
+        Some((_, profile, missing)) => Err(CheckError::CapabilitiesMissing {
+            profile,
+            bundle_id,
+            missing,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn parse(toml: &str) -> Entitlements {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn values_keep_their_types() {
+        let entitlements = parse(
+            r#"
+            "aps-environment" = "development"
+            "com.apple.developer.icloud-container-environment" = ["Development"]
+            "com.apple.security.app-sandbox" = true
+            "#,
+        );
+        assert_eq!(
+            entitlements["aps-environment"],
+            Value::String("development".to_owned())
+        );
+        assert_eq!(
+            entitlements["com.apple.developer.icloud-container-environment"],
+            Value::Array(vec![Value::String("Development".to_owned())])
+        );
+        assert_eq!(
+            entitlements["com.apple.security.app-sandbox"],
+            Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn renders_sorted_plist() {
+        let entitlements = parse(
+            r#"
+            "com.apple.security.app-sandbox" = false
+            "aps-environment" = "development"
+            "com.apple.security.application-groups" = ["group.a&b", "group.c"]
+            "keychain-access-groups" = []
+            "#,
+        );
+        assert_eq!(
+            render(&entitlements),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>aps-environment</key>
+	<string>development</string>
+	<key>com.apple.security.app-sandbox</key>
+	<false/>
+	<key>com.apple.security.application-groups</key>
+	<array>
+		<string>group.a&amp;b</string>
+		<string>group.c</string>
+	</array>
+	<key>keychain-access-groups</key>
+	<array/>
+</dict>
+</plist>
+"#
+        );
+    }
+
+    #[test]
+    fn writing_is_idempotent() {
+        let dir = TempDirGuard::new().unwrap();
+        let path = dir.join(rel_path("app", "iOS"));
+        let entitlements = parse(r#""aps-environment" = "development""#);
+        write(&path, &entitlements).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), render(&entitlements));
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        write(&path, &entitlements).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    }
+
+    #[rstest(
+        pattern,
+        matches,
+        case("ABCDE12345.com.example.app", true),
+        case("ABCDE12345.com.example.*", true),
+        case("ABCDE12345.*", true),
+        case("ABCDE12345.com.example.other", false),
+        case("ABCDE12345.com.example.app.*", false),
+        case("ABCDE12345.com.example", false),
+        case("FGHIJ67890.*", false),
+        case("FGHIJ67890.com.example.app", false),
+        case("ABCDE*", false),
+        case("abcde12345.*", false),
+        case("*", false)
+    )]
+    fn matches_app_ids(pattern: &str, matches: bool) {
+        assert_eq!(
+            app_id_matches(pattern, "ABCDE12345.com.example.app"),
+            matches
+        );
+    }
+
+    fn profiles(patterns: &[&str]) -> Vec<(PathBuf, String)> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                (
+                    PathBuf::from(format!("{}.mobileprovision", pattern)),
+                    pattern.to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[rstest(
+        patterns,
+        best,
+        case(&["ABCDE12345.*", "ABCDE12345.com.example.app"], Some(("ABCDE12345.com.example.app", true))),
+        case(&["ABCDE12345.com.example.app", "ABCDE12345.*"], Some(("ABCDE12345.com.example.app", true))),
+        case(&["ABCDE12345.*", "ABCDE12345.com.example.*"], Some(("ABCDE12345.com.example.*", false))),
+        case(&["FGHIJ67890.com.example.app", "ABCDE12345.*"], Some(("ABCDE12345.*", false))),
+        case(&["FGHIJ67890.*", "ABCDE12345.com.example.other"], None),
+        case(&[], None)
+    )]
+    fn picks_best_profile(patterns: &[&str], best: Option<(&str, bool)>) {
+        let profiles = profiles(patterns);
+        assert_eq!(
+            best_profile(&profiles, "ABCDE12345.com.example.app"),
+            best.map(|(pattern, exact)| (
+                PathBuf::from(format!("{}.mobileprovision", pattern)),
+                exact
+            ))
+            .as_ref()
+            .map(|(path, exact)| (path.as_path(), *exact))
+        );
+    }
+
+    #[test]
+    fn finds_missing_capabilities() {
+        let requested = parse(
+            r#"
+            "aps-environment" = "development"
+            "com.apple.developer.associated-domains" = ["applinks:example.com"]
+            "#,
+        );
+        let granted = json!({
+            "application-identifier": "ABCDE12345.com.example.app",
+            "aps-environment": "development",
+            "get-task-allow": true,
+        });
+        assert_eq!(
+            missing(&requested, granted.as_object().unwrap()),
+            vec!["com.apple.developer.associated-domains".to_owned()]
+        );
+    }
+}
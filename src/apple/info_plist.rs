@@ -0,0 +1,364 @@
+//! `info-plist-patch`, which names a plist (or TOML file) whose keys get
+//! merged into the `Info.plist` XcodeGen generates, so things like URL schemes
+//! and usage descriptions survive regeneration.
+//!
+//! Dictionaries are merged recursively, while anything else in the patch,
+//! arrays included, replaces what was there.
+
+use plist::{Dictionary, Value};
+use std::{
+    fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Keys we fill in from the config, which a patch can override, though it
+/// probably shouldn't.
+pub static MANAGED_KEYS: &[&str] = &[
+    "CFBundleDisplayName",
+    "CFBundleExecutable",
+    "CFBundleIdentifier",
+    "CFBundleName",
+    "CFBundleShortVersionString",
+    "CFBundleVersion",
+    "UIBackgroundModes",
+];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to read Info.plist patch {path:?}: {source}")]
+    PatchReadFailed { path: PathBuf, source: io::Error },
+    #[error("Info.plist patch {path:?} isn't a valid plist: {source}")]
+    PatchPlistInvalid { path: PathBuf, source: plist::Error },
+    #[error("Info.plist patch {path:?} isn't valid TOML: {source}")]
+    PatchTomlInvalid {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Info.plist patch {path:?} needs a dictionary at the top level")]
+    PatchNotDictionary { path: PathBuf },
+    #[error("Failed to read generated Info.plist {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: plist::Error },
+    #[error("Generated Info.plist {path:?} doesn't have a dictionary at the top level")]
+    NotDictionary { path: PathBuf },
+    #[error("Failed to write Info.plist {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: plist::Error },
+}
+
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::Integer(i.into()),
+        toml::Value::Float(f) => Value::Real(f),
+        toml::Value::Boolean(b) => Value::Boolean(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(values) => Value::Array(values.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Value::Dictionary(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, from_toml(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// `.toml` files are read as TOML, and anything else as a plist, either XML
+/// or binary.
+pub fn parse_patch(path: &Path, contents: &[u8]) -> Result<Dictionary, Error> {
+    let value = if path.extension().map_or(false, |ext| ext == "toml") {
+        let contents = String::from_utf8_lossy(contents);
+        toml::from_str::<toml::Value>(&contents)
+            .map(from_toml)
+            .map_err(|source| Error::PatchTomlInvalid {
+                path: path.to_owned(),
+                source,
+            })?
+    } else {
+        Value::from_reader(Cursor::new(contents)).map_err(|source| Error::PatchPlistInvalid {
+            path: path.to_owned(),
+            source,
+        })?
+    };
+    value
+        .into_dictionary()
+        .ok_or_else(|| Error::PatchNotDictionary {
+            path: path.to_owned(),
+        })
+}
+
+fn merge_dictionary(base: &mut Dictionary, patch: Dictionary) {
+    for (key, value) in patch {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Dictionary(base)), Value::Dictionary(patch)) => {
+                merge_dictionary(base, patch)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Merges `patch` into `base`, giving back the managed keys it changed.
+pub fn merge(base: &mut Dictionary, patch: Dictionary) -> Vec<String> {
+    let overridden = patch
+        .iter()
+        .filter(|(key, value)| {
+            MANAGED_KEYS.contains(&key.as_str())
+                && base.get(key).map_or(false, |current| current != *value)
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    merge_dictionary(base, patch);
+    overridden
+}
+
+/// Sorts every dictionary by key, so the output only changes when the
+/// contents do.
+fn sort(value: &mut Value) {
+    match value {
+        Value::Dictionary(dictionary) => {
+            dictionary.sort_keys();
+            dictionary.values_mut().for_each(sort);
+        }
+        Value::Array(values) => values.iter_mut().for_each(sort),
+        _ => (),
+    }
+}
+
+/// Merges the patch at `patch_path` into the `Info.plist` at `info_plist`.
+pub fn apply(info_plist: &Path, patch_path: &Path) -> Result<(), Error> {
+    let contents = fs::read(patch_path).map_err(|source| Error::PatchReadFailed {
+        path: patch_path.to_owned(),
+        source,
+    })?;
+    let patch = parse_patch(patch_path, &contents)?;
+    let mut base = Value::from_file(info_plist)
+        .map_err(|source| Error::ReadFailed {
+            path: info_plist.to_owned(),
+            source,
+        })?
+        .into_dictionary()
+        .ok_or_else(|| Error::NotDictionary {
+            path: info_plist.to_owned(),
+        })?;
+    for key in merge(&mut base, patch) {
+        log::warn!(
+            "Info.plist patch {:?} overrides `{}`, which cargo-mobile sets from your config",
+            patch_path,
+            key
+        );
+    }
+    let mut merged = Value::Dictionary(base);
+    sort(&mut merged);
+    log::info!(
+        "merging Info.plist patch {:?} into {:?}",
+        patch_path,
+        info_plist
+    );
+    merged
+        .to_file_xml(info_plist)
+        .map_err(|source| Error::WriteFailed {
+            path: info_plist.to_owned(),
+            source,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    static GENERATED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>CFBundleIdentifier</key>
+	<string>$(PRODUCT_BUNDLE_IDENTIFIER)</string>
+	<key>CFBundleVersion</key>
+	<string>1.0.0</string>
+	<key>NSAppTransportSecurity</key>
+	<dict>
+		<key>NSAllowsArbitraryLoads</key>
+		<false/>
+		<key>NSExceptionDomains</key>
+		<dict>
+			<key>example.com</key>
+			<dict>
+				<key>NSIncludesSubdomains</key>
+				<true/>
+			</dict>
+		</dict>
+	</dict>
+	<key>UIRequiredDeviceCapabilities</key>
+	<array>
+		<string>arm64</string>
+		<string>metal</string>
+	</array>
+</dict>
+</plist>
+"#;
+
+    static PATCH: &str = r#"
+CFBundleVersion = "1.0.0"
+NSCameraUsageDescription = "Scanning QR codes"
+UIRequiredDeviceCapabilities = ["arm64"]
+
+[NSAppTransportSecurity.NSExceptionDomains."localhost"]
+NSExceptionAllowsInsecureHTTPLoads = true
+"#;
+
+    fn generated() -> Dictionary {
+        Value::from_reader(Cursor::new(GENERATED.as_bytes()))
+            .unwrap()
+            .into_dictionary()
+            .unwrap()
+    }
+
+    fn patch(toml: &str) -> Dictionary {
+        parse_patch(Path::new("Info.plist.toml"), toml.as_bytes()).unwrap()
+    }
+
+    fn at<'a>(dictionary: &'a Dictionary, path: &[&str]) -> Option<&'a Value> {
+        let (last, parents) = path.split_last()?;
+        let mut current = dictionary;
+        for key in parents {
+            current = current.get(key)?.as_dictionary()?;
+        }
+        current.get(last)
+    }
+
+    #[test]
+    fn merges_nested_dictionaries() {
+        let mut base = generated();
+        assert!(merge(&mut base, patch(PATCH)).is_empty());
+        let domains = ["NSAppTransportSecurity", "NSExceptionDomains"];
+        assert_eq!(
+            at(
+                &base,
+                &[
+                    domains[0],
+                    domains[1],
+                    "localhost",
+                    "NSExceptionAllowsInsecureHTTPLoads"
+                ]
+            ),
+            Some(&Value::Boolean(true))
+        );
+        // What was already there is kept.
+        assert_eq!(
+            at(
+                &base,
+                &[
+                    domains[0],
+                    domains[1],
+                    "example.com",
+                    "NSIncludesSubdomains"
+                ]
+            ),
+            Some(&Value::Boolean(true))
+        );
+        assert_eq!(
+            at(&base, &[domains[0], "NSAllowsArbitraryLoads"]),
+            Some(&Value::Boolean(false))
+        );
+        assert_eq!(
+            at(&base, &["NSCameraUsageDescription"]),
+            Some(&Value::String("Scanning QR codes".to_owned()))
+        );
+    }
+
+    #[test]
+    fn replaces_arrays() {
+        let mut base = generated();
+        merge(&mut base, patch(PATCH));
+        assert_eq!(
+            at(&base, &["UIRequiredDeviceCapabilities"]),
+            Some(&Value::Array(vec![Value::String("arm64".to_owned())]))
+        );
+    }
+
+    #[test]
+    fn reports_managed_overrides() {
+        let mut base = generated();
+        let overridden = merge(
+            &mut base,
+            patch("CFBundleIdentifier = \"com.example.other\"\nCFBundleVersion = \"1.0.0\"\nUIBackgroundModes = [\"audio\"]\nCFBundleURLTypes = []\n"),
+        );
+        // `CFBundleVersion` didn't change, and `UIBackgroundModes` wasn't
+        // there to override.
+        assert_eq!(overridden, vec!["CFBundleIdentifier"]);
+        assert_eq!(
+            at(&base, &["CFBundleIdentifier"]),
+            Some(&Value::String("com.example.other".to_owned()))
+        );
+    }
+
+    #[test]
+    fn rejects_non_dictionary_patch() {
+        assert!(matches!(
+            parse_patch(
+                Path::new("Info.plist.patch"),
+                b"<?xml version=\"1.0\"?><plist version=\"1.0\"><array/></plist>"
+            ),
+            Err(Error::PatchNotDictionary { .. })
+        ));
+        assert!(matches!(
+            parse_patch(Path::new("Info.plist.toml"), b"NSCameraUsageDescription ="),
+            Err(Error::PatchTomlInvalid { .. })
+        ));
+    }
+
+    #[test]
+    fn applies_deterministically() {
+        let dir = TempDirGuard::new().unwrap();
+        let info_plist = dir.join("Info.plist");
+        let patch_path = dir.join("Info.plist.patch");
+        fs::write(
+            &patch_path,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>NSCameraUsageDescription</key>
+	<string>Scanning QR codes</string>
+	<key>CFBundleURLTypes</key>
+	<array>
+		<dict>
+			<key>CFBundleURLSchemes</key>
+			<array>
+				<string>tictac</string>
+			</array>
+		</dict>
+	</array>
+</dict>
+</plist>
+"#,
+        )
+        .unwrap();
+        fs::write(&info_plist, GENERATED).unwrap();
+        apply(&info_plist, &patch_path).unwrap();
+        let first = fs::read_to_string(&info_plist).unwrap();
+        fs::write(&info_plist, GENERATED).unwrap();
+        apply(&info_plist, &patch_path).unwrap();
+        assert_eq!(fs::read_to_string(&info_plist).unwrap(), first);
+        let keys = Value::from_file(&info_plist)
+            .unwrap()
+            .into_dictionary()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                "CFBundleIdentifier",
+                "CFBundleURLTypes",
+                "CFBundleVersion",
+                "NSAppTransportSecurity",
+                "NSCameraUsageDescription",
+                "UIRequiredDeviceCapabilities",
+            ]
+        );
+    }
+}
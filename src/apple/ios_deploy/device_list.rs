@@ -1,12 +1,19 @@
-use super::{DeviceInfo, Event};
+use super::{DeviceInfo, Event, Interface, InterfaceFilter};
 use crate::{
     apple::{device::Device, target::Target},
     env::{Env, ExplicitEnv as _},
-    util::cli::{Report, Reportable},
+    util::{
+        cli::{Report, Reportable},
+        VersionDouble, VersionTriple,
+    },
 };
-use std::collections::BTreeSet;
+use once_cell_regex::regex;
+use std::collections::{BTreeMap, BTreeSet};
 use thiserror::Error;
 
+/// The first version with `--json`.
+const JSON_MIN_VERSION: VersionTriple = VersionTriple::new(1, 9, 0);
+
 #[derive(Debug, Error)]
 pub enum DeviceListError {
     #[error("Failed to request device list from `ios-deploy`: {0}")]
@@ -21,31 +28,142 @@ impl Reportable for DeviceListError {
     }
 }
 
-fn parse_device_list<'a>(output: &bossy::Output) -> Result<BTreeSet<Device<'a>>, DeviceListError> {
-    Event::parse_list(output.stdout_str()?)
+// A device shows up once per interface it's connected through, and USB is
+// what we'd rather use.
+fn dedupe(devices: Vec<(DeviceInfo, Interface)>) -> Vec<(DeviceInfo, Interface)> {
+    let mut by_id = BTreeMap::<String, (DeviceInfo, Interface)>::new();
+    for (info, interface) in devices {
+        match by_id.get(&info.device_identifier) {
+            Some((_, existing)) if *existing <= interface => (),
+            _ => {
+                by_id.insert(info.device_identifier.clone(), (info, interface));
+            }
+        }
+    }
+    by_id.into_values().collect()
+}
+
+// Filtering comes first, so that with `--wifi-only` a device that's also
+// plugged in is still found over the network.
+fn into_devices<'a>(
+    devices: Vec<(DeviceInfo, Interface)>,
+    filter: InterfaceFilter,
+) -> Result<BTreeSet<Device<'a>>, DeviceListError> {
+    let devices = devices
+        .into_iter()
+        .filter(|(_, interface)| filter.allows(*interface))
+        .collect();
+    dedupe(devices)
         .into_iter()
-        .flat_map(|event| event.device_info().cloned())
         .map(
-            |DeviceInfo {
-                 device_identifier,
-                 device_name,
-                 model_arch,
-                 model_name,
-             }| {
+            |(
+                DeviceInfo {
+                    device_identifier,
+                    device_name,
+                    model_arch,
+                    model_name,
+                    product_version,
+                },
+                interface,
+            )| {
+                let os_version = product_version.and_then(|version| {
+                    VersionDouble::from_str(&version)
+                        .map_err(|err| log::warn!("device has invalid iOS version: {}", err))
+                        .ok()
+                });
                 Target::for_arch(&model_arch)
-                    .map(|target| Device::new(device_identifier, device_name, model_name, target))
+                    .map(|target| {
+                        Device::new(
+                            device_identifier,
+                            device_name,
+                            model_name,
+                            os_version,
+                            target,
+                            interface,
+                        )
+                    })
                     .ok_or_else(|| DeviceListError::ArchInvalid(model_arch))
             },
         )
-        .collect::<Result<_, _>>()
+        .collect()
 }
 
-pub fn device_list<'a>(env: &Env) -> Result<BTreeSet<Device<'a>>, DeviceListError> {
-    let result = bossy::Command::pure_parse("ios-deploy --detect --timeout 1 --json --no-wifi")
+fn parse_json_device_list<'a>(
+    output: &str,
+    filter: InterfaceFilter,
+) -> Result<BTreeSet<Device<'a>>, DeviceListError> {
+    into_devices(
+        Event::parse_list(output)
+            .iter()
+            .filter_map(Event::device_info)
+            .map(|(info, interface)| (info.clone(), interface))
+            .collect(),
+        filter,
+    )
+}
+
+/// Parses the lines versions without `--json` print, like
+/// `[....] Found <udid> (<hardware model>, <model name>, <sdk>, <arch>, <iOS version>, <build>) a.k.a. '<name>' connected through USB.`,
+/// where older versions stop after the arch.
+fn parse_text_device_list<'a>(
+    output: &str,
+    filter: InterfaceFilter,
+) -> Result<BTreeSet<Device<'a>>, DeviceListError> {
+    let devices = output
+        .lines()
+        .filter_map(|line| {
+            let caps = regex!(r"Found (\S+) \((.*)\) a\.k\.a\. '(.*)' connected through (\w+)\.")
+                .captures(line)?;
+            let fields = caps[2].split(", ").collect::<Vec<_>>();
+            let info = DeviceInfo {
+                device_identifier: caps[1].to_owned(),
+                device_name: caps[3].to_owned(),
+                model_arch: fields.get(3)?.to_string(),
+                model_name: fields.get(1)?.to_string(),
+                product_version: fields.get(4).map(|version| version.to_string()),
+            };
+            let interface = if &caps[4] == "USB" {
+                Interface::Usb
+            } else {
+                Interface::Network
+            };
+            Some((info, interface))
+        })
+        .collect();
+    into_devices(devices, filter)
+}
+
+fn supports_json(env: &Env) -> bool {
+    bossy::Command::pure_parse("ios-deploy --version")
+        .with_env_vars(env.explicit_env())
+        .run_and_wait_for_str(|version| VersionTriple::from_str(version.trim()).ok())
+        .map_err(|err| log::warn!("failed to check `ios-deploy` version: {}", err))
+        .ok()
+        .flatten()
+        // If we can't tell, it's probably new enough.
+        .map_or(true, |version| version >= JSON_MIN_VERSION)
+}
+
+pub fn device_list<'a>(
+    env: &Env,
+    filter: InterfaceFilter,
+) -> Result<BTreeSet<Device<'a>>, DeviceListError> {
+    let json = supports_json(env);
+    let result = bossy::Command::pure_parse("ios-deploy --detect --timeout")
         .with_env_vars(env.explicit_env())
+        .with_arg(filter.detect_timeout().to_string())
+        .with_args(if json { Some("--json") } else { None })
         .run_and_wait_for_output();
+    let parse = |output: &bossy::Output| {
+        let stdout = output.stdout_str()?;
+        if json {
+            parse_json_device_list(stdout, filter)
+        } else {
+            parse_text_device_list(stdout, filter)
+        }
+    };
     match result {
-        Ok(output) => parse_device_list(&output),
+        Ok(output) => parse(&output),
         Err(err) => {
             let output = err
                 .output()
@@ -59,3 +177,186 @@ pub fn device_list<'a>(env: &Env) -> Result<BTreeSet<Device<'a>>, DeviceListErro
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Captured from ios-deploy 1.11.4, with identifiers changed.
+    static JSON_NONE: &str = "";
+    static JSON_ONE: &str = r#"{
+  "Event" : "DeviceDetected",
+  "Interface" : "USB",
+  "Device" : {
+    "DeviceIdentifier" : "00008030-001A2D3C0E38802E",
+    "DeviceName" : "Jane's iPhone",
+    "modelArch" : "arm64e",
+    "modelName" : "iPhone 11 Pro",
+    "modelSDK" : "iphoneos",
+    "HardwareModel" : "D421AP",
+    "ProductVersion" : "15.1",
+    "BuildVersion" : "19B74"
+  }
+}"#;
+    // Two devices, with a second USB device and a network-paired one
+    // interleaved, and the first device showing up again over the network.
+    static JSON_TWO_AND_NETWORK: &str = r#"[....] Waiting up to 1 seconds for iOS device to be connected
+{
+  "Event" : "DeviceDetected",
+  "Interface" : "USB",
+  "Device" : {
+    "DeviceIdentifier" : "00008030-001A2D3C0E38802E",
+    "DeviceName" : "Jane's iPhone",
+    "modelArch" : "arm64e",
+    "modelName" : "iPhone 11 Pro",
+    "ProductVersion" : "15.1"
+  }
+}{
+  "Event" : "DeviceDetected",
+  "Interface" : "WIFI",
+  "Device" : {
+    "DeviceIdentifier" : "00008101-000C64D00E80001E",
+    "DeviceName" : "Test iPad",
+    "modelArch" : "arm64e",
+    "modelName" : "iPad Pro (11-inch) (3rd generation)",
+    "ProductVersion" : "16.0.3"
+  }
+}
+{"Event":"DeviceDetected","Interface":"WIFI","Device":{"DeviceIdentifier":"00008030-001A2D3C0E38802E","DeviceName":"Jane's iPhone","modelArch":"arm64e","modelName":"iPhone 11 Pro","ProductVersion":"15.1"}}
+{"Event":"DeviceDetected","Interface":"USB","Device":{"DeviceIdentifier":"3d4f1b2a9e8c7d6b5a4f3e2d1c0b9a8f7e6d5c4b","DeviceName":"Old iPhone","modelArch":"arm64","modelName":"iPhone 8"}}
+"#;
+    static TEXT_TWO: &str = "[....] Waiting up to 1 seconds for iOS device to be connected
+[....] Found 00008030-001A2D3C0E38802E (D421AP, iPhone 11 Pro, iphoneos, arm64e, 15.1, 19B74) a.k.a. 'Jane's iPhone' connected through USB.
+[....] Found 3d4f1b2a9e8c7d6b5a4f3e2d1c0b9a8f7e6d5c4b (D20AP, iPhone 8, iphoneos, arm64) a.k.a. 'Old iPhone' connected through WIFI.
+";
+
+    fn summary(devices: &BTreeSet<Device<'_>>) -> Vec<(String, Interface)> {
+        devices
+            .iter()
+            .map(|device| (device.to_string(), device.interface()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_no_devices() {
+        assert!(parse_json_device_list(JSON_NONE, InterfaceFilter::Any)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn parses_one_device() {
+        let devices = parse_json_device_list(JSON_ONE, InterfaceFilter::Any).unwrap();
+        assert_eq!(
+            summary(&devices),
+            vec![("Jane's iPhone (iPhone 11 Pro)".to_owned(), Interface::Usb)]
+        );
+        let device = devices.iter().next().unwrap();
+        assert_eq!(device.id(), "00008030-001A2D3C0E38802E");
+        assert_eq!(device.os_version(), Some(VersionDouble::new(15, 1)));
+    }
+
+    #[test]
+    fn parses_interleaved_devices() {
+        let mut devices =
+            summary(&parse_json_device_list(JSON_TWO_AND_NETWORK, InterfaceFilter::Any).unwrap());
+        devices.sort();
+        assert_eq!(
+            devices,
+            vec![
+                ("Jane's iPhone (iPhone 11 Pro)".to_owned(), Interface::Usb),
+                ("Old iPhone (iPhone 8)".to_owned(), Interface::Usb),
+                (
+                    "Test iPad (iPad Pro (11-inch) (3rd generation), network)".to_owned(),
+                    Interface::Network
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_text_devices() {
+        let mut devices = summary(&parse_text_device_list(TEXT_TWO, InterfaceFilter::Any).unwrap());
+        devices.sort();
+        assert_eq!(
+            devices,
+            vec![
+                ("Jane's iPhone (iPhone 11 Pro)".to_owned(), Interface::Usb),
+                (
+                    "Old iPhone (iPhone 8, network)".to_owned(),
+                    Interface::Network
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_to_usb() {
+        let mut devices = summary(
+            &parse_json_device_list(JSON_TWO_AND_NETWORK, InterfaceFilter::UsbOnly).unwrap(),
+        );
+        devices.sort();
+        assert_eq!(
+            devices,
+            vec![
+                ("Jane's iPhone (iPhone 11 Pro)".to_owned(), Interface::Usb),
+                ("Old iPhone (iPhone 8)".to_owned(), Interface::Usb),
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_to_network() {
+        let mut devices = summary(
+            &parse_json_device_list(JSON_TWO_AND_NETWORK, InterfaceFilter::NetworkOnly).unwrap(),
+        );
+        devices.sort();
+        // Jane's iPhone is plugged in too, but it's still found over the
+        // network.
+        assert_eq!(
+            devices,
+            vec![
+                (
+                    "Jane's iPhone (iPhone 11 Pro, network)".to_owned(),
+                    Interface::Network
+                ),
+                (
+                    "Test iPad (iPad Pro (11-inch) (3rd generation), network)".to_owned(),
+                    Interface::Network
+                ),
+            ]
+        );
+        let devices =
+            summary(&parse_text_device_list(TEXT_TWO, InterfaceFilter::NetworkOnly).unwrap());
+        assert_eq!(
+            devices,
+            vec![(
+                "Old iPhone (iPhone 8, network)".to_owned(),
+                Interface::Network
+            )]
+        );
+    }
+
+    #[test]
+    fn picks_filter_from_flags() {
+        assert_eq!(
+            InterfaceFilter::from_flags(false, false),
+            InterfaceFilter::Any
+        );
+        assert_eq!(
+            InterfaceFilter::from_flags(true, false),
+            InterfaceFilter::UsbOnly
+        );
+        assert_eq!(
+            InterfaceFilter::from_flags(false, true),
+            InterfaceFilter::NetworkOnly
+        );
+    }
+
+    #[test]
+    fn waits_longer_for_network_devices() {
+        assert_eq!(InterfaceFilter::UsbOnly.detect_timeout(), 1);
+        assert!(InterfaceFilter::Any.detect_timeout() > 1);
+        assert!(InterfaceFilter::NetworkOnly.detect_timeout() > 1);
+    }
+}
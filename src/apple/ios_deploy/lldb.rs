@@ -0,0 +1,300 @@
+//! `cargo apple run --attach-debugger`, which hands over the lldb session
+//! `ios-deploy` starts, with an init file that knows where Rust's sources and
+//! our symbols are. Without it, lldb can't step into std (which is built with
+//! its paths remapped to `/rustc/<commit-hash>`), and Rust values show up as
+//! raw structs.
+
+use crate::util::{
+    cli::{Report, Reportable},
+    toolchain,
+};
+use once_cell_regex::regex;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Where std's sources are within the sysroot, once `rust-src` is installed.
+static RUST_SRC: &str = "lib/rustlib/src/rust";
+
+#[derive(Debug)]
+pub enum Error {
+    SysrootFailed(bossy::Error),
+    VersionFailed(bossy::Error),
+    WriteFailed { path: PathBuf, cause: io::Error },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::SysrootFailed(err) => Report::error("Failed to get sysroot from `rustc`", err),
+            Self::VersionFailed(err) => {
+                Report::error("Failed to get version info from `rustc`", err)
+            }
+            Self::WriteFailed { path, cause } => Report::error(
+                format!("Failed to write lldb init file to {:?}", path),
+                cause,
+            ),
+        }
+    }
+}
+
+/// `--attach-debugger`, and what to do once it's done with.
+#[derive(Clone, Copy, Debug)]
+pub struct Attach {
+    pub keep_running: bool,
+}
+
+impl Attach {
+    pub fn print_handoff(&self) {
+        println!(
+            "lldb is connected; set any breakpoints you need, and then `run` to launch the app."
+        );
+        if self.keep_running {
+            println!("`q` detaches and leaves the app running, while `quit` stops it.");
+        } else {
+            println!("Quitting lldb stops the app.");
+        }
+    }
+}
+
+/// What we need to know about the toolchain the lib was built with.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Toolchain {
+    pub sysroot: PathBuf,
+    /// Locally built toolchains don't have one, and don't remap std's paths.
+    pub commit_hash: Option<String>,
+}
+
+impl Toolchain {
+    pub fn detect() -> Result<Self, Error> {
+        let sysroot = toolchain::rustc()
+            .with_args(&["--print", "sysroot"])
+            .run_and_wait_for_str(|sysroot| PathBuf::from(sysroot.trim()))
+            .map_err(Error::SysrootFailed)?;
+        let commit_hash = toolchain::rustc()
+            .with_arg("-vV")
+            .run_and_wait_for_str(parse_commit_hash)
+            .map_err(Error::VersionFailed)?;
+        Ok(Self {
+            sysroot,
+            commit_hash,
+        })
+    }
+
+    pub fn rust_src(&self) -> PathBuf {
+        self.sysroot.join(RUST_SRC)
+    }
+}
+
+/// The `commit-hash` line of `rustc -vV`, which says `unknown` when there
+/// isn't one.
+pub fn parse_commit_hash(version: &str) -> Option<String> {
+    regex!(r"(?m)^commit-hash: ([0-9a-f]+)\s*$")
+        .captures(version)
+        .map(|caps| caps[1].to_owned())
+}
+
+/// Where the checkout was when `built` (the lib's path back then, from its
+/// dep-info) was built, given where that lib is now. That's `root_dir` unless
+/// the checkout's moved, or the lib came from somewhere else entirely.
+pub fn build_dir(built: &Path, lib_path: &Path, root_dir: &Path) -> PathBuf {
+    lib_path
+        .strip_prefix(root_dir)
+        .ok()
+        .and_then(|relative| {
+            let depth = relative.components().count();
+            built
+                .ends_with(relative)
+                .then(|| built.ancestors().nth(depth))
+                .flatten()
+        })
+        .map(Path::to_owned)
+        .unwrap_or_else(|| root_dir.to_owned())
+}
+
+/// lldb splits commands shell-style, so paths with spaces need quoting.
+fn quote(path: &Path) -> String {
+    format!(
+        "\"{}\"",
+        path.display()
+            .to_string()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+    )
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Init<'a> {
+    pub toolchain: &'a Toolchain,
+    pub triple: &'a str,
+    /// The static lib the app links, whose object files hold our debug info.
+    pub lib_path: &'a Path,
+    /// Where the lib was built from, so sources still resolve when the
+    /// checkout's moved since then.
+    pub build_dir: &'a Path,
+    pub root_dir: &'a Path,
+    /// Whether quitting should detach instead of killing the app.
+    pub keep_running: bool,
+}
+
+impl<'a> Init<'a> {
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!(
+            "# Written by `cargo apple run --attach-debugger` for {}; it's rewritten on every run.",
+            self.triple
+        )];
+        let mut source_map = Vec::new();
+        if let Some(commit_hash) = &self.toolchain.commit_hash {
+            source_map.push((
+                Path::new("/rustc").join(commit_hash),
+                self.toolchain.rust_src(),
+            ));
+        }
+        if self.build_dir != self.root_dir {
+            source_map.push((self.build_dir.to_owned(), self.root_dir.to_owned()));
+        }
+        if !source_map.is_empty() {
+            // Only the last `settings set` counts, so the pairs all go in one.
+            lines.push(format!(
+                "settings set target.source-map {}",
+                source_map
+                    .iter()
+                    .map(|(from, to)| format!("{} {}", quote(from), quote(to)))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+        }
+        if let Some(lib_dir) = self.lib_path.parent() {
+            lines.push(format!(
+                "settings append target.exec-search-paths {}",
+                quote(lib_dir)
+            ));
+            lines.push(format!(
+                "settings append target.debug-file-search-paths {}",
+                quote(lib_dir)
+            ));
+        }
+        // The same formatters `rust-lldb` loads.
+        let etc = self.toolchain.sysroot.join("lib/rustlib/etc");
+        lines.push(format!(
+            "command script import {}",
+            quote(&etc.join("lldb_lookup.py"))
+        ));
+        lines.push(format!(
+            "command source -s 0 {}",
+            quote(&etc.join("lldb_commands"))
+        ));
+        if self.keep_running {
+            // `safequit` comes from `ios-deploy`, and detaches before quitting.
+            lines.push("command alias q safequit".to_owned());
+        } else {
+            // Quitting kills the app anyway, so there's nothing to confirm.
+            lines.push("settings set interpreter.prompt-on-quit false".to_owned());
+        }
+        let mut rendered = lines.join("\n");
+        rendered.push('\n');
+        rendered
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.render()).map_err(|cause| Error::WriteFailed {
+            path: path.to_owned(),
+            cause,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn toolchain(commit_hash: Option<&str>) -> Toolchain {
+        Toolchain {
+            sysroot: "/Users/jane/.rustup/toolchains/stable-aarch64-apple-darwin".into(),
+            commit_hash: commit_hash.map(Into::into),
+        }
+    }
+
+    #[rstest(
+        version,
+        expected,
+        case(
+            "rustc 1.56.0 (09c42c458 2021-10-18)\nbinary: rustc\ncommit-hash: 09c42c45858d5f3aedfa670698275303a3d19afa\ncommit-date: 2021-10-18\nhost: aarch64-apple-darwin\n",
+            Some("09c42c45858d5f3aedfa670698275303a3d19afa")
+        ),
+        case(
+            "rustc 1.58.0-dev\nbinary: rustc\ncommit-hash: unknown\ncommit-date: unknown\n",
+            None
+        ),
+        case("rustc 1.56.0\n", None)
+    )]
+    fn parses_commit_hash(version: &str, expected: Option<&str>) {
+        assert_eq!(parse_commit_hash(version).as_deref(), expected);
+    }
+
+    #[rstest(
+        built,
+        expected,
+        case(
+            "/Users/jane/game/target/aarch64-apple-ios/debug/libgame.a",
+            "/Users/jane/game"
+        ),
+        case("/ci/build/target/aarch64-apple-ios/debug/libgame.a", "/ci/build"),
+        case("/ci/build/out/aarch64-apple-ios/debug/libgame.a", "/Users/jane/game")
+    )]
+    fn finds_build_dir(built: &str, expected: &str) {
+        assert_eq!(
+            build_dir(
+                Path::new(built),
+                Path::new("/Users/jane/game/target/aarch64-apple-ios/debug/libgame.a"),
+                Path::new("/Users/jane/game"),
+            ),
+            Path::new(expected)
+        );
+    }
+
+    #[test]
+    fn renders_init() {
+        let toolchain = toolchain(Some("09c42c45858d5f3aedfa670698275303a3d19afa"));
+        let init = Init {
+            toolchain: &toolchain,
+            triple: "aarch64-apple-ios",
+            lib_path: Path::new("/Users/jane/My Game/target/aarch64-apple-ios/debug/libmy_game.a"),
+            build_dir: Path::new("/Users/jane/My Game"),
+            root_dir: Path::new("/Users/jane/My Game"),
+            keep_running: false,
+        };
+        assert_eq!(
+            init.render(),
+            r#"# Written by `cargo apple run --attach-debugger` for aarch64-apple-ios; it's rewritten on every run.
+settings set target.source-map "/rustc/09c42c45858d5f3aedfa670698275303a3d19afa" "/Users/jane/.rustup/toolchains/stable-aarch64-apple-darwin/lib/rustlib/src/rust"
+settings append target.exec-search-paths "/Users/jane/My Game/target/aarch64-apple-ios/debug"
+settings append target.debug-file-search-paths "/Users/jane/My Game/target/aarch64-apple-ios/debug"
+command script import "/Users/jane/.rustup/toolchains/stable-aarch64-apple-darwin/lib/rustlib/etc/lldb_lookup.py"
+command source -s 0 "/Users/jane/.rustup/toolchains/stable-aarch64-apple-darwin/lib/rustlib/etc/lldb_commands"
+settings set interpreter.prompt-on-quit false
+"#
+        );
+    }
+
+    #[test]
+    fn renders_moved_checkout_and_keep_running() {
+        let toolchain = toolchain(None);
+        let init = Init {
+            toolchain: &toolchain,
+            triple: "x86_64-apple-ios",
+            lib_path: Path::new("/ci/build/target/x86_64-apple-ios/release/libgame.a"),
+            build_dir: Path::new("/ci/build"),
+            root_dir: Path::new("/Users/jane/game"),
+            keep_running: true,
+        };
+        let rendered = init.render();
+        assert!(rendered
+            .contains("settings set target.source-map \"/ci/build\" \"/Users/jane/game\"\n"));
+        assert!(!rendered.contains("/rustc/"));
+        assert!(rendered.ends_with("command alias q safequit\n"));
+        assert!(!rendered.contains("prompt-on-quit"));
+    }
+}
@@ -1,4 +1,5 @@
 mod device_list;
+pub mod lldb;
 mod run;
 
 pub use self::{device_list::*, run::*};
@@ -16,6 +17,65 @@ struct DeviceInfo {
     model_arch: String,
     #[serde(rename = "modelName")]
     model_name: String,
+    #[serde(rename = "ProductVersion", default)]
+    product_version: Option<String>,
+}
+
+/// How a device is connected, which `ios-deploy` reports as `USB` or `WIFI`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Interface {
+    #[serde(rename = "USB")]
+    Usb,
+    #[serde(other)]
+    Network,
+}
+
+impl Default for Interface {
+    fn default() -> Self {
+        Self::Usb
+    }
+}
+
+/// `--usb-only` and `--wifi-only`, which narrow down what `device_list` finds.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InterfaceFilter {
+    Any,
+    UsbOnly,
+    NetworkOnly,
+}
+
+impl Default for InterfaceFilter {
+    fn default() -> Self {
+        Self::Any
+    }
+}
+
+impl InterfaceFilter {
+    pub fn from_flags(usb_only: bool, wifi_only: bool) -> Self {
+        match (usb_only, wifi_only) {
+            (true, _) => Self::UsbOnly,
+            (_, true) => Self::NetworkOnly,
+            _ => Self::Any,
+        }
+    }
+
+    pub fn allows(self, interface: Interface) -> bool {
+        match self {
+            Self::Any => true,
+            Self::UsbOnly => interface == Interface::Usb,
+            Self::NetworkOnly => interface == Interface::Network,
+        }
+    }
+
+    /// How many seconds `ios-deploy --detect` waits for devices. Plugged in
+    /// devices show up right away, but ones on the network can take a few
+    /// seconds to answer.
+    pub fn detect_timeout(self) -> u32 {
+        match self {
+            Self::UsbOnly => 1,
+            Self::Any | Self::NetworkOnly => 5,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -34,7 +94,12 @@ enum Event {
         status: String,
     },
     #[serde(rename_all = "PascalCase")]
-    DeviceDetected { device: DeviceInfo },
+    DeviceDetected {
+        device: DeviceInfo,
+        // Versions before this was added only ever saw USB devices.
+        #[serde(default)]
+        interface: Interface,
+    },
     #[serde(rename_all = "PascalCase")]
     Error { code: u32, status: String },
     #[serde(other)]
@@ -42,39 +107,50 @@ enum Event {
 }
 
 impl Event {
-    fn parse_list<'a>(s: &str) -> Vec<Self> {
-        fn parse_and_push(s: &str, docs: &mut Vec<Event>) {
-            if !s.is_empty() {
-                match serde_json::from_str(s) {
-                    Ok(event) => {
-                        log::debug!("parsed `ios-deploy` event: {:#?}", event);
-                        docs.push(event)
-                    }
-                    Err(err) => {
-                        log::error!(
-                            "failed to parse `ios-deploy` event: {}\nraw event text:\n{}",
+    /// `ios-deploy --json` prints one object per event, one after another
+    /// rather than in an array, and sometimes with plain text mixed in. Each
+    /// object is read with a streaming deserializer, and anything that isn't
+    /// one is skipped.
+    fn parse_list(s: &str) -> Vec<Self> {
+        let mut events = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            rest = &rest[start..];
+            let mut stream =
+                serde_json::Deserializer::from_str(rest).into_iter::<serde_json::Value>();
+            match stream.next() {
+                Some(Ok(value)) => {
+                    rest = &rest[stream.byte_offset()..];
+                    match serde_json::from_value(value.clone()) {
+                        Ok(event) => {
+                            log::debug!("parsed `ios-deploy` event: {:#?}", event);
+                            events.push(event)
+                        }
+                        Err(err) => log::error!(
+                            "failed to parse `ios-deploy` event: {}\nraw event:\n{:#}",
                             err,
-                            s
-                        );
+                            value
+                        ),
                     }
                 }
+                Some(Err(err)) => {
+                    log::error!(
+                        "failed to parse `ios-deploy` output as JSON: {}\nraw output:\n{}",
+                        err,
+                        rest
+                    );
+                    // Whatever this was, the next object starts after it.
+                    rest = &rest[1..];
+                }
+                None => break,
             }
         }
-
-        let (mut docs, prev_index) =
-            s.match_indices("}{")
-                .fold((Vec::new(), 0), |(mut docs, prev_index), (index, _)| {
-                    let end = index + 1;
-                    parse_and_push(&s[prev_index..end], &mut docs);
-                    (docs, end)
-                });
-        parse_and_push(&s[prev_index..], &mut docs);
-        docs
+        events
     }
 
-    fn device_info(&self) -> Option<&DeviceInfo> {
-        if let Self::DeviceDetected { device } = self {
-            Some(device)
+    fn device_info(&self) -> Option<(&DeviceInfo, Interface)> {
+        if let Self::DeviceDetected { device, interface } = self {
+            Some((device, *interface))
         } else {
             None
         }
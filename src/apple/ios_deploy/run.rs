@@ -4,40 +4,137 @@ use crate::{
     opts,
     util::cli::{Report, Reportable},
 };
+use std::path::Path;
+
+/// What `ios-deploy` exits with when the device never answered.
+const EXIT_TIMEOUT: i32 = 252;
+
+/// How long to wait for a device over the network, where it can take a while
+/// to wake up; `ios-deploy`'s own default is meant for USB.
+const NETWORK_TIMEOUT_SECS: &str = "30";
 
 #[derive(Debug)]
 pub enum RunAndDebugError {
     DeployFailed(bossy::Error),
+    NetworkTimedOut(bossy::Error),
 }
 
 impl Reportable for RunAndDebugError {
     fn report(&self) -> Report {
         match self {
             Self::DeployFailed(err) => Report::error("Failed to deploy app to device", err),
+            Self::NetworkTimedOut(err) => Report::action_request(
+                "Timed out deploying to the device over the network; re-pair it in Xcode's Devices and Simulators window (with \"Connect via network\" checked), or plug it in and pass `--usb-only`",
+                err,
+            ),
         }
     }
 }
 
+/// The flags that pick how `ios-deploy` reaches the device.
+pub fn transport_args(interface: super::Interface) -> &'static [&'static str] {
+    match interface {
+        // Without this, a device that's also paired over the network could
+        // get deployed to that way, which is much slower.
+        super::Interface::Usb => &["--no-wifi"],
+        super::Interface::Network => &["--timeout", NETWORK_TIMEOUT_SECS],
+    }
+}
+
+/// Whether a failed deploy was the network timing out, which has a fix of its
+/// own.
+pub fn timed_out(interface: super::Interface, code: Option<i32>) -> bool {
+    interface == super::Interface::Network && code == Some(EXIT_TIMEOUT)
+}
+
+// ios-deploy hands `--args` to LLDB as a single string, which LLDB splits
+// shell-style; quoting every argument keeps them intact.
+fn join_launch_args(launch_args: &[String]) -> String {
+    launch_args
+        .iter()
+        .map(|arg| format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 pub fn run_and_debug(
     config: &Config,
     env: &Env,
     non_interactive: opts::NonInteractive,
     id: &str,
+    interface: super::Interface,
+    launch_args: &[String],
+    lldb_init: Option<&Path>,
 ) -> Result<(), RunAndDebugError> {
     println!("Deploying app to device...");
-    bossy::Command::pure("ios-deploy")
+    let mut command = bossy::Command::pure("ios-deploy")
         .with_env_vars(env.explicit_env())
         .with_arg("--debug")
         .with_args(&["--id", id])
         .with_arg("--bundle")
         .with_arg(&config.app_path())
-        .with_args(if non_interactive.yes() {
+        // The debugger session is the whole point of `lldb_init`, so it stays
+        // interactive.
+        .with_args(if non_interactive.yes() && lldb_init.is_none() {
             Some("--noninteractive")
         } else {
             None
         })
-        .with_arg("--no-wifi")
-        .run_and_wait()
-        .map(|_| ())
-        .map_err(RunAndDebugError::DeployFailed)
+        .with_args(transport_args(interface));
+    if !launch_args.is_empty() {
+        command
+            .add_arg("--args")
+            .add_arg(join_launch_args(launch_args));
+    }
+    if let Some(lldb_init) = lldb_init {
+        // The app's installed and lldb's connected, but nothing's launched
+        // until `run`, so breakpoints can be set first.
+        command
+            .add_arg("--nostart")
+            .add_arg("--custom-command")
+            .add_arg(format!("command source -s 0 \"{}\"", lldb_init.display()));
+    }
+    command.run_and_wait().map(|_| ()).map_err(|err| {
+        if timed_out(interface, err.code()) {
+            RunAndDebugError::NetworkTimedOut(err)
+        } else {
+            RunAndDebugError::DeployFailed(err)
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{super::Interface, *};
+    use rstest::rstest;
+
+    #[rstest(args, joined,
+        case(&["--benchmark-mode"], r#""--benchmark-mode""#),
+        case(&["--level", "two words"], r#""--level" "two words""#),
+        case(&["--seed=42", "key=a b"], r#""--seed=42" "key=a b""#),
+        case(&[r#"say "hi""#, r"C:\path"], r#""say \"hi\"" "C:\\path""#),
+    )]
+    fn test_join_launch_args(args: &[&str], joined: &str) {
+        let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(join_launch_args(&args), joined);
+    }
+
+    #[rstest(
+        interface,
+        code,
+        expected,
+        case(Interface::Network, Some(252), true),
+        case(Interface::Network, Some(253), false),
+        case(Interface::Network, None, false),
+        case(Interface::Usb, Some(252), false)
+    )]
+    fn test_timed_out(interface: Interface, code: Option<i32>, expected: bool) {
+        assert_eq!(timed_out(interface, code), expected);
+    }
+
+    #[test]
+    fn test_transport_args() {
+        assert_eq!(transport_args(Interface::Usb), &["--no-wifi"]);
+        assert_eq!(transport_args(Interface::Network), &["--timeout", "30"]);
+    }
 }
@@ -0,0 +1,269 @@
+//! The env the Rust lib gets compiled with. `xcode-script` and `compile-lib`
+//! both go through here, so a lib built outside of Xcode is built exactly
+//! like one built inside it.
+
+use super::{
+    config::Config,
+    target::{Os, Target},
+};
+use crate::{opts::Profile, util};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    SdkRootInvalid { sdk_root: PathBuf },
+    IncludeDirInvalid { include_dir: PathBuf },
+    MacosSdkRootInvalid { macos_sdk_root: PathBuf },
+    ArchInvalid { arch: String },
+}
+
+/// The search paths Xcode gives its build phases, which build scripts
+/// sometimes read. Outside of Xcode, there usually aren't any.
+#[derive(Clone, Debug, Default)]
+pub struct XcodeVars {
+    pub framework_search_paths: Option<String>,
+    pub gcc_preprocessor_definitions: Option<String>,
+    pub header_search_paths: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HostEnv {
+    vars: BTreeMap<String, OsString>,
+    include_dir: PathBuf,
+    isysroot: String,
+    library_path: String,
+}
+
+impl HostEnv {
+    /// Flags for build scripts, which run on the host, for building against
+    /// the SDK at `sdk_root`.
+    pub fn new(sdk_root: &Path, xcode: &XcodeVars) -> Result<Self, Error> {
+        if !sdk_root.is_dir() {
+            return Err(Error::SdkRootInvalid {
+                sdk_root: sdk_root.to_owned(),
+            });
+        }
+        let include_dir = sdk_root.join("usr/include");
+        if !include_dir.is_dir() {
+            return Err(Error::IncludeDirInvalid { include_dir });
+        }
+        let macos_sdk_root = sdk_root.join("../../../../MacOSX.platform/Developer/SDKs/MacOSX.sdk");
+        if !macos_sdk_root.is_dir() {
+            return Err(Error::MacosSdkRootInvalid { macos_sdk_root });
+        }
+        let macos_isysroot = format!("-isysroot {}", macos_sdk_root.display());
+        let library_path = format!("{}/usr/lib", macos_sdk_root.display());
+
+        let mut vars = BTreeMap::<String, OsString>::new();
+        let mut insert = |key: &str, value: &dyn AsRef<std::ffi::OsStr>| {
+            vars.insert(key.to_owned(), value.as_ref().to_owned());
+        };
+        insert("MAC_FLAGS", &macos_isysroot);
+        insert("CFLAGS_x86_64_apple_darwin", &macos_isysroot);
+        insert("CXXFLAGS_x86_64_apple_darwin", &macos_isysroot);
+        insert("OBJC_INCLUDE_PATH_x86_64_apple_darwin", &include_dir);
+        insert("RUST_BACKTRACE", &"1");
+        for (key, value) in &[
+            ("FRAMEWORK_SEARCH_PATHS", &xcode.framework_search_paths),
+            (
+                "GCC_PREPROCESSOR_DEFINITIONS",
+                &xcode.gcc_preprocessor_definitions,
+            ),
+            ("HEADER_SEARCH_PATHS", &xcode.header_search_paths),
+        ] {
+            if let Some(value) = value {
+                insert(key, value);
+            }
+        }
+        Ok(Self {
+            vars,
+            include_dir,
+            isysroot: format!("-isysroot {}", sdk_root.display()),
+            library_path,
+        })
+    }
+
+    /// Adds the flags for compiling C code for `target`. The macOS target's
+    /// flags are always there, since build scripts need them too.
+    pub fn for_target(&self, target: &Target<'_>) -> BTreeMap<String, OsString> {
+        let mut vars = self.vars.clone();
+        if !target.is_macos() {
+            let triple = target.triple.replace('-', "_");
+            vars.insert(format!("CFLAGS_{}", triple), self.isysroot.clone().into());
+            vars.insert(format!("CXXFLAGS_{}", triple), self.isysroot.clone().into());
+            vars.insert(
+                format!("OBJC_INCLUDE_PATH_{}", triple),
+                self.include_dir.clone().into(),
+            );
+        }
+        // Prevents linker errors in build scripts and proc macros:
+        // https://github.com/signalapp/libsignal-client/commit/02899cac643a14b2ced7c058cc15a836a2165b6d
+        vars.insert("LIBRARY_PATH".to_owned(), self.library_path.clone().into());
+        vars
+    }
+}
+
+/// The target to build for `arch` on `os`, which is always the macOS target
+/// when building for macOS.
+pub fn target(arch: &str, os: Os) -> Result<Target<'static>, Error> {
+    if os == Os::Macos {
+        Ok(Target::macos())
+    } else {
+        Target::for_os_arch(os, arch)
+            .copied()
+            .ok_or_else(|| Error::ArchInvalid {
+                arch: arch.to_owned(),
+            })
+    }
+}
+
+/// Where cargo puts the static lib, and the dep-info it wrote for it.
+pub fn lib_paths(config: &Config, target: &Target<'_>, profile: Profile) -> (PathBuf, PathBuf) {
+    let out_dir = util::cargo_target_dir(config.app().root_dir())
+        .join(target.triple)
+        .join(profile.as_str());
+    let lib_name = format!("lib{}", config.app().name_snake());
+    (
+        out_dir.join(format!("{}.a", lib_name)),
+        out_dir.join(format!("{}.d", lib_name)),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+    use std::fs;
+
+    // Laid out like Xcode's, since the macOS SDK is found relative to the
+    // iOS one.
+    fn fake_sdks() -> (TempDirGuard, PathBuf) {
+        let dir = TempDirGuard::new().unwrap();
+        let platforms = dir.join("Platforms");
+        let sdk_root = platforms.join("iPhoneOS.platform/Developer/SDKs/iPhoneOS.sdk");
+        fs::create_dir_all(sdk_root.join("usr/include")).unwrap();
+        fs::create_dir_all(platforms.join("MacOSX.platform/Developer/SDKs/MacOSX.sdk")).unwrap();
+        (dir, sdk_root)
+    }
+
+    #[rstest(
+        arch,
+        os,
+        triple,
+        case("arm64", Os::Ios, "aarch64-apple-ios"),
+        case("arm64e", Os::Ios, "aarch64-apple-ios"),
+        case("x86_64", Os::Ios, "x86_64-apple-ios"),
+        case("arm64", Os::IosSimulator, "aarch64-apple-ios-sim"),
+        case("x86_64", Os::IosSimulator, "x86_64-apple-ios"),
+        case("arm64_32", Os::Watchos, "arm64_32-apple-watchos"),
+        case("arm64", Os::Watchos, "aarch64-apple-watchos"),
+        case("arm64", Os::WatchosSimulator, "aarch64-apple-watchos-sim"),
+        case("x86_64", Os::WatchosSimulator, "x86_64-apple-watchos-sim")
+    )]
+    fn builds_env_for_each_arch(arch: &str, os: Os, triple: &str) {
+        let (_dir, sdk_root) = fake_sdks();
+        let xcode = XcodeVars {
+            header_search_paths: Some("/headers".to_owned()),
+            ..Default::default()
+        };
+        let target = target(arch, os).unwrap();
+        assert_eq!(target.triple, triple);
+        let vars = HostEnv::new(&sdk_root, &xcode).unwrap().for_target(&target);
+        let triple = triple.replace('-', "_");
+        let isysroot = OsString::from(format!("-isysroot {}", sdk_root.display()));
+        assert_eq!(vars.get(&format!("CFLAGS_{}", triple)), Some(&isysroot));
+        assert_eq!(vars.get(&format!("CXXFLAGS_{}", triple)), Some(&isysroot));
+        assert_eq!(
+            vars.get(&format!("OBJC_INCLUDE_PATH_{}", triple)),
+            Some(&sdk_root.join("usr/include").into_os_string())
+        );
+        let library_path = vars["LIBRARY_PATH"].to_str().unwrap();
+        assert!(library_path.ends_with("MacOSX.sdk/usr/lib"));
+        assert!(vars["MAC_FLAGS"].to_str().unwrap().contains("MacOSX.sdk"));
+        assert_eq!(vars["HEADER_SEARCH_PATHS"], OsString::from("/headers"));
+        assert!(!vars.contains_key("FRAMEWORK_SEARCH_PATHS"));
+    }
+
+    // `compile-lib` is run with `--os iOS` for the x86_64 simulator, which is
+    // still a simulator.
+    #[rstest(
+        arch,
+        os,
+        sdk,
+        case("arm64", Os::Ios, "iphoneos"),
+        case("x86_64", Os::Ios, "iphonesimulator"),
+        case("arm64", Os::IosSimulator, "iphonesimulator"),
+        case("arm64_32", Os::Watchos, "watchos"),
+        case("x86_64", Os::WatchosSimulator, "watchsimulator")
+    )]
+    fn finds_the_sdk_for_each_target(arch: &str, os: Os, sdk: &str) {
+        assert_eq!(target(arch, os).unwrap().sdk(), sdk);
+    }
+
+    #[rstest(arch, case("arm64"), case("x86_64"), case("armv7"))]
+    fn macos_always_builds_for_darwin(arch: &str) {
+        let (_dir, sdk_root) = fake_sdks();
+        let target = target(arch, Os::Macos).unwrap();
+        assert_eq!(target.triple, "x86_64-apple-darwin");
+        let vars = HostEnv::new(&sdk_root, &XcodeVars::default())
+            .unwrap()
+            .for_target(&target);
+        assert!(vars["CFLAGS_x86_64_apple_darwin"]
+            .to_str()
+            .unwrap()
+            .contains("MacOSX.sdk"));
+        assert!(!vars.keys().any(|key| key.ends_with("_apple_ios")));
+    }
+
+    #[rstest(
+        arch,
+        os,
+        case("armv7", Os::Ios),
+        case("arm64_32", Os::Ios),
+        case("armv7k", Os::Watchos),
+        case("arm64_32", Os::WatchosSimulator)
+    )]
+    fn rejects_unknown_arch(arch: &str, os: Os) {
+        assert!(matches!(
+            target(arch, os),
+            Err(Error::ArchInvalid { arch: invalid }) if invalid == arch
+        ));
+    }
+
+    #[test]
+    fn parses_platform_display_names() {
+        for (name, os) in Os::DISPLAY_NAMES.iter().zip(&[
+            Os::Ios,
+            Os::IosSimulator,
+            Os::Macos,
+            Os::Watchos,
+            Os::WatchosSimulator,
+        ]) {
+            assert_eq!(Os::from_display_name(name), *os);
+        }
+        assert_eq!(Os::from_display_name("tvOS"), Os::Ios);
+    }
+
+    #[test]
+    fn rejects_missing_sdks() {
+        let dir = TempDirGuard::new().unwrap();
+        assert!(matches!(
+            HostEnv::new(&dir.join("nope"), &XcodeVars::default()),
+            Err(Error::SdkRootInvalid { .. })
+        ));
+        assert!(matches!(
+            HostEnv::new(&dir, &XcodeVars::default()),
+            Err(Error::IncludeDirInvalid { .. })
+        ));
+        fs::create_dir_all(dir.join("usr/include")).unwrap();
+        assert!(matches!(
+            HostEnv::new(&dir, &XcodeVars::default()),
+            Err(Error::MacosSdkRootInvalid { .. })
+        ));
+    }
+}
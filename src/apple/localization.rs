@@ -0,0 +1,133 @@
+use crate::{
+    config::app::{self, App},
+    opts::Profile,
+};
+use std::{fs, io, path::PathBuf};
+use thiserror::Error;
+
+pub static DIR_NAME: &str = "Localization";
+
+#[derive(Debug, Error)]
+#[error("Failed to write localized display name to {path:?}: {source}")]
+pub struct Error {
+    path: PathBuf,
+    source: io::Error,
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn info_plist_strings(display_name: &str) -> String {
+    let display_name = escape(display_name);
+    format!(
+        "\"CFBundleDisplayName\" = \"{}\";\n\"CFBundleName\" = \"{}\";\n",
+        display_name, display_name
+    )
+}
+
+/// Where the debug configuration's localized names go, since they need the
+/// display name suffix and `InfoPlist.strings` can't use build settings.
+pub static DEBUG_DIR_NAME: &str = "LocalizationDebug";
+
+fn write(dir: PathBuf, display_name: &str) -> Result<(), Error> {
+    let path = dir.join("InfoPlist.strings");
+    fs::create_dir_all(&dir)
+        .and_then(|()| fs::write(&path, info_plist_strings(display_name)))
+        .map_err(|source| Error {
+            path: path.clone(),
+            source,
+        })
+}
+
+/// Whether debug builds get their own localized names.
+pub fn has_debug(app: &App) -> bool {
+    app.debug_display_name_suffix().is_some() && !app.display_name_localized().is_empty()
+}
+
+/// Writes a `<tag>.lproj/InfoPlist.strings` for each entry in
+/// `app.display-name-localized`. Xcode's locale dirs are named with the same
+/// BCP-47 tags, so there's no translating to do. With
+/// `app.debug-display-name-suffix`, the debug configuration gets a suffixed
+/// copy of each.
+pub fn gen(app: &App, project_dir: PathBuf) -> Result<(), Error> {
+    for (tag, display_name) in app.display_name_localized() {
+        write(
+            project_dir.join(DIR_NAME).join(format!("{}.lproj", tag)),
+            display_name,
+        )?;
+    }
+    let debug_dir = project_dir.join(DEBUG_DIR_NAME);
+    if !has_debug(app) {
+        // Left over from before the suffix was removed.
+        return match fs::remove_dir_all(&debug_dir) {
+            Err(source) if source.kind() != io::ErrorKind::NotFound => Err(Error {
+                path: debug_dir,
+                source,
+            }),
+            _ => Ok(()),
+        };
+    }
+    for (tag, display_name) in app.display_name_localized() {
+        write(
+            debug_dir.join(format!("{}.lproj", tag)),
+            &app::for_profile(
+                display_name,
+                app.debug_display_name_suffix(),
+                Profile::Debug,
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::fixture, util::TempDirGuard};
+
+    #[test]
+    fn escapes_quotes_and_keeps_unicode() {
+        assert_eq!(
+            info_plist_strings("\"Échecs\" \\ שחמט"),
+            "\"CFBundleDisplayName\" = \"\\\"Échecs\\\" \\\\ שחמט\";\n\"CFBundleName\" = \"\\\"Échecs\\\" \\\\ שחמט\";\n"
+        );
+    }
+
+    #[test]
+    fn debug_names_follow_the_suffix() {
+        let app = |suffix: &str| {
+            fixture::app(
+                "chess",
+                &format!("{}[app.display-name-localized]\nfr = \"Échecs\"\n", suffix),
+                "",
+            )
+        };
+        let project_dir = TempDirGuard::new().unwrap();
+        let debug_strings = project_dir.join("LocalizationDebug/fr.lproj/InfoPlist.strings");
+
+        let suffixed = app("debug-display-name-suffix = \" Dev\"\n");
+        gen(suffixed.config.app(), project_dir.to_path_buf()).unwrap();
+        assert!(fs::read_to_string(&debug_strings)
+            .unwrap()
+            .contains("\"Échecs Dev\""));
+        assert!(
+            !fs::read_to_string(project_dir.join("Localization/fr.lproj/InfoPlist.strings"))
+                .unwrap()
+                .contains("Dev")
+        );
+
+        let plain = app("");
+        gen(plain.config.app(), project_dir.to_path_buf()).unwrap();
+        assert!(!project_dir.join(DEBUG_DIR_NAME).exists());
+    }
+}
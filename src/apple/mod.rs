@@ -1,26 +1,40 @@
+pub(crate) mod active_arch;
+mod archive_versions;
 pub mod cli;
 pub(crate) mod config;
+mod depfile;
 pub(crate) mod deps;
 mod device;
+mod dsym;
+mod entitlements;
+mod info_plist;
 pub(crate) mod ios_deploy;
+mod lib_env;
+mod localization;
+mod pod;
 pub(crate) mod project;
+mod relocalize;
+mod schemes;
+mod sdk;
+mod sim_archs;
 pub(crate) mod system_profile;
-mod target;
+pub(crate) mod target;
 pub(crate) mod teams;
+mod validate;
 mod version_number;
 
-use crate::util::{
-    self,
-    cli::{Report, TextWrapper},
-};
+use crate::util::{self, cli::TextWrapper, RustVersionRequirement, VersionTriple};
 
 pub static NAME: &str = "apple";
 
+pub fn rust_version_requirements() -> Vec<RustVersionRequirement> {
+    vec![RustVersionRequirement {
+        platform: "iOS",
+        min: VersionTriple::new(1, 49, 0),
+        reason: "iOS linking is broken on Rust versions later than 1.45.2 (d3fb005a3 2020-07-31) and earlier than 1.49.0-nightly (ffa2e7ae8 2020-10-24), and older versions can't build for current iOS SDKs.".to_owned(),
+    }]
+}
+
 pub fn rust_version_check(wrapper: &TextWrapper) -> Result<(), util::RustVersionError> {
-    util::RustVersion::check().map(|version| if !version.valid() {
-        Report::action_request(
-            format!("iOS linking is broken on Rust versions later than 1.45.2 (d3fb005a3 2020-07-31) and earlier than 1.49.0-nightly (ffa2e7ae8 2020-10-24), but you're on {}!", version),
-            "This is fixed in Rust 1.49.0 and later:\n`rustup update stable && rustup default stable`",
-        ).print(wrapper);
-    })
+    util::rust_version_check(wrapper, &rust_version_requirements()).map(|_| ())
 }
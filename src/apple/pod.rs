@@ -0,0 +1,133 @@
+use crate::util::{
+    cli::{Report, Reportable},
+    watchdog,
+};
+use once_cell_regex::regex;
+use std::{path::Path, process, thread, time::Duration};
+
+const CDN_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum Error {
+    SpecMissing {
+        pod: Option<String>,
+        cause: watchdog::Error,
+    },
+    InstallFailed(watchdog::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::SpecMissing { pod, cause } => Report::error(
+                match pod {
+                    Some(pod) => format!("CocoaPods couldn't find a specification for `{}`", pod),
+                    None => "CocoaPods couldn't find a pod specification".to_owned(),
+                },
+                format!(
+                    "This persisted after updating the spec repos, so check the pod's name and version in your `Cargo.toml` metadata. Private pods also need their spec repo added via `pod repo add`. {}",
+                    cause
+                ),
+            ),
+            Self::InstallFailed(err) if err.is_hung() => err.report(),
+            Self::InstallFailed(err) => Report::error("Failed to run `pod install`", err),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Failure {
+    SpecMissing(Option<String>),
+    CdnFlake,
+    Other,
+}
+
+impl Failure {
+    fn classify(output: &str) -> Self {
+        if output.contains("Unable to find a specification for") {
+            Self::SpecMissing(
+                regex!(r"Unable to find a specification for `([^`]+)`")
+                    .captures(output)
+                    .map(|caps| caps[1].to_owned()),
+            )
+        } else if regex!(r"CDN: .+ URL couldn't be downloaded|(?:Operation|Connection) timed out")
+            .is_match(output)
+        {
+            Self::CdnFlake
+        } else {
+            Self::Other
+        }
+    }
+}
+
+// The watchdog passes the output along as it comes, while still keeping it
+// around for us to classify failures with.
+fn attempt(project_dir: &Path, args: &[String], repo_update: bool) -> Result<(), watchdog::Error> {
+    let mut command = process::Command::new("pod");
+    command.args(args);
+    if repo_update {
+        command.arg("--repo-update");
+    }
+    command.arg(format!("--project-directory={}", project_dir.display()));
+    watchdog::run(watchdog::Class::Pod, command).map(|_| ())
+}
+
+/// Runs `pod <args>` (where `args` starts with `install`), retrying once with
+/// `--repo-update` if a spec is missing, and once after a pause if the
+/// CocoaPods CDN flakes out.
+pub fn install(project_dir: &Path, args: &[String]) -> Result<(), Error> {
+    let mut repo_update = args.iter().any(|arg| arg == "--repo-update");
+    let mut retried_cdn = false;
+    loop {
+        let err = match attempt(project_dir, args, repo_update) {
+            Ok(()) => return Ok(()),
+            // Retrying something that hung would just hang again.
+            Err(err) if err.is_hung() => return Err(Error::InstallFailed(err)),
+            Err(err) => err,
+        };
+        let output = err.output().map(watchdog::Output::text).unwrap_or_default();
+        match Failure::classify(&output) {
+            Failure::SpecMissing(_) if !repo_update => {
+                println!("A pod specification was missing; retrying with `--repo-update`...");
+                repo_update = true;
+            }
+            Failure::SpecMissing(pod) => return Err(Error::SpecMissing { pod, cause: err }),
+            Failure::CdnFlake if !retried_cdn => {
+                println!(
+                    "The CocoaPods CDN timed out; retrying in {}s...",
+                    CDN_RETRY_DELAY.as_secs()
+                );
+                thread::sleep(CDN_RETRY_DELAY);
+                retried_cdn = true;
+            }
+            _ => return Err(Error::InstallFailed(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        output,
+        expected,
+        case(
+            "[!] Unable to find a specification for `Firebase/Core`\n",
+            Failure::SpecMissing(Some("Firebase/Core".to_owned()))
+        ),
+        case(
+            "[!] Unable to find a specification for a dependency\n",
+            Failure::SpecMissing(None)
+        ),
+        case(
+            "[!] CDN: trunk URL couldn't be downloaded: https://cdn.cocoapods.org/all_pods_versions_a_b_c.txt Response: Operation timed out\n",
+            Failure::CdnFlake
+        ),
+        case("[!] No `Podfile' found in the project directory.\n", Failure::Other)
+    )]
+    fn classifies_failures(output: &str, expected: Failure) {
+        assert_eq!(Failure::classify(output), expected);
+    }
+}
@@ -1,36 +1,56 @@
 use super::{
-    config::{Config, Metadata},
-    deps, rust_version_check,
+    config::{Config, Metadata, Platform},
+    deps, entitlements, info_plist, localization, pod, rust_version_check,
     target::Target,
 };
 use crate::{
-    opts,
-    target::TargetTrait as _,
+    assets,
+    config::app::AssetPlatform,
+    icons, opts,
     templating::{self, Pack},
     util::{
         self,
         cli::{Report, Reportable, TextWrapper},
-        ln,
+        events, watchdog,
     },
 };
 use std::path::{Path, PathBuf};
 
+pub const RUSTUP_STEP: events::Step =
+    events::Step::new("apple.rustup", "Installing iOS toolchains");
+pub const DEPS_INSTALL_STEP: events::Step =
+    events::Step::new("apple.deps.install", "Installing iOS dependencies");
+pub const XCODEGEN_STEP: events::Step = events::Step::new("apple.xcodegen", "Running XcodeGen");
+pub const POD_INSTALL_STEP: events::Step =
+    events::Step::new("apple.pods.install", "Installing pods");
+
 pub static TEMPLATE_PACK: &str = "xcode";
+/// The watch app and its extension, which only get generated when the
+/// watchOS metadata is there.
+pub static WATCHOS_TEMPLATE_PACK: &str = "xcode-watchos";
 
 #[derive(Debug)]
 pub enum Error {
-    RustupFailed(bossy::Error),
+    RustupFailed(util::toolchain::RustupTargetAddFailed),
     RustVersionCheckFailed(util::RustVersionError),
     DepsInstallFailed(deps::Error),
     MissingPack(templating::LookupError),
-    TemplateProcessingFailed(bicycle::ProcessingError),
-    AssetDirSymlinkFailed(ln::Error),
+    TemplateProcessingFailed(templating::ProcessError),
+    EntitlementsWriteFailed(entitlements::WriteError),
+    InfoPlistPatchFailed(info_plist::Error),
+    AssetDirInstallFailed(assets::Error),
     DirectoryCreationFailed {
         path: PathBuf,
         cause: std::io::Error,
     },
-    XcodegenFailed(bossy::Error),
-    PodInstallFailed(bossy::Error),
+    XcodegenFailed(watchdog::Error),
+    PodInstallFailed(pod::Error),
+    LocalizationGenFailed(localization::Error),
+    IconSyncFailed(icons::Error),
+    DeploymentTargetInvalid {
+        platform: &'static str,
+        cause: util::VersionDoubleError,
+    },
 }
 
 impl Reportable for Error {
@@ -45,129 +65,376 @@ impl Reportable for Error {
             Self::TemplateProcessingFailed(err) => {
                 Report::error("Xcode template processing failed", err)
             }
-            Self::AssetDirSymlinkFailed(err) => {
-                Report::error("Asset dir couldn't be symlinked into Xcode project", err)
+            Self::EntitlementsWriteFailed(err) => {
+                Report::error("Failed to generate entitlements", err)
+            }
+            Self::InfoPlistPatchFailed(err) => {
+                Report::error("Failed to apply Info.plist patch", err)
+            }
+            Self::AssetDirInstallFailed(err) => {
+                Report::error("Asset dir couldn't be installed into Xcode project", err)
             }
             Self::DirectoryCreationFailed { path, cause } => Report::error(
                 format!("Failed to create iOS assets directory at {:?}", path),
                 cause,
             ),
-            Self::XcodegenFailed(err) => Report::error("Failed to run `xcodegen`", err),
-            Self::PodInstallFailed(err) => Report::error("Failed to run `pod install`", err),
+            Self::XcodegenFailed(err) => err.report(),
+            Self::PodInstallFailed(err) => err.report(),
+            Self::LocalizationGenFailed(err) => {
+                Report::error("Failed to generate Xcode localizations", err)
+            }
+            Self::IconSyncFailed(err) => Report::error("Failed to generate Xcode icons", err),
+            Self::DeploymentTargetInvalid { platform, cause } => Report::error(
+                format!(
+                    "`package.metadata.cargo-apple.{}.deployment-target` invalid",
+                    platform
+                ),
+                cause,
+            ),
         }
     }
 }
 
-// unprefixed app_root seems pretty dangerous!!
-// TODO: figure out what I meant by that
-pub fn gen(
+/// Template variables for a single platform, with every key prefixed by the
+/// platform name (i.e. `ios-libraries` and `macos-libraries`).
+fn platform_template_vars(
+    prefix: &str,
+    platform: &Platform,
+    default_archs: &[String],
+    deployment_target: util::VersionDouble,
+) -> Vec<(String, serde_json::Value)> {
+    use serde_json::json;
+    vec![
+        ("deployment-target", json!(deployment_target.to_string())),
+        ("generate-scheme", json!(platform.generate_scheme())),
+        ("libraries", json!(platform.libraries())),
+        ("frameworks", json!(platform.frameworks())),
+        (
+            "valid-archs",
+            json!(platform.valid_archs().unwrap_or(default_archs)),
+        ),
+        (
+            "excluded-simulator-archs",
+            json!(platform.excluded_simulator_archs()),
+        ),
+        ("vendor-frameworks", json!(platform.vendor_frameworks())),
+        ("vendor-sdks", json!(platform.vendor_sdks())),
+        ("pods", json!(platform.pods().unwrap_or_default())),
+        (
+            "pod-options",
+            json!(platform.pod_options().unwrap_or_default()),
+        ),
+        ("additional-targets", json!(platform.additional_targets())),
+        ("pre-build-scripts", json!(platform.pre_build_scripts())),
+        (
+            "post-compile-scripts",
+            json!(platform.post_compile_scripts()),
+        ),
+        ("post-build-scripts", json!(platform.post_build_scripts())),
+        (
+            "command-line-arguments",
+            json!(platform.command_line_arguments()),
+        ),
+        ("has-entitlements", json!(platform.entitlements().is_some())),
+        ("background-modes", json!(platform.background_modes())),
+    ]
+    .into_iter()
+    .map(|(name, value)| (format!("{}-{}", prefix, name), value))
+    .collect()
+}
+
+// The stock template doesn't ship any, but packs that do will have them in
+// an app icon set inside an asset catalog.
+fn is_icon(rel: &Path) -> bool {
+    rel.components().any(|component| {
+        Path::new(component.as_os_str())
+            .extension()
+            .map_or(false, |ext| ext == "appiconset")
+    })
+}
+
+/// Syncs the app icon sets from the template pack into the project.
+pub fn sync_icons(config: &Config, mode: icons::Mode) -> Result<icons::Changes, Error> {
+    let src = Pack::lookup_platform(TEMPLATE_PACK)
+        .map_err(Error::MissingPack)?
+        .expect_local();
+    let bike = templating::init(Some(config));
+    let render = |rel: &Path| {
+        bike.render(&rel.to_string_lossy(), |_| ())
+            .map(PathBuf::from)
+            .map_err(|cause| icons::Error::RenderFailed {
+                path: rel.to_owned(),
+                cause,
+            })
+    };
+    icons::sync(&src, &config.project_dir(), is_icon, render, mode).map_err(Error::IconSyncFailed)
+}
+
+/// Merges each platform's `info-plist-patch` into its generated `Info.plist`.
+/// XcodeGen rewrites each `Info.plist` every time it runs, so this has to run
+/// after it every time too.
+pub fn patch_info_plists(config: &Config, metadata: &Metadata) -> Result<(), info_plist::Error> {
+    for (name, platform) in &[("iOS", metadata.ios()), ("macOS", metadata.macos())] {
+        if let Some(patch) = platform.info_plist_patch() {
+            info_plist::apply(
+                &config
+                    .project_dir()
+                    .join(format!("{}_{}", config.app().name(), name))
+                    .join("Info.plist"),
+                &config.app().root_dir().join(patch),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Generates the Xcode project from the `project.yml` in `project_dir`.
+pub fn run_xcodegen(project_dir: &Path) -> Result<(), watchdog::Error> {
+    let mut command = std::process::Command::new("xcodegen");
+    command
+        .args(&["generate", "--spec"])
+        .arg(project_dir.join("project.yml"));
+    watchdog::run(watchdog::Class::Xcodegen, command).map(|_| ())
+}
+
+/// How the project refers to cargo's target dir. `CARGO_TARGET_DIR` can
+/// move it anywhere, and only a dir inside the app can be made relative.
+fn xcode_target_dir(root_dir: &Path, target_dir: &Path, rel_prefix: &Path) -> String {
+    match util::unprefix_path(root_dir, target_dir) {
+        Ok(rel) => format!("$(PROJECT_DIR)/{}", rel_prefix.join(rel).display()),
+        Err(_) => target_dir.display().to_string(),
+    }
+}
+
+/// Everything in `gen` that comes out of the template pack.
+pub(crate) fn process_templates(
     config: &Config,
     metadata: &Metadata,
     submodule_path: Option<&Path>,
     bike: &bicycle::Bicycle,
-    wrapper: &TextWrapper,
-    non_interactive: opts::NonInteractive,
-    skip_dev_tools: opts::SkipDevTools,
-    reinstall_deps: opts::ReinstallDeps,
     filter: &templating::Filter,
+    conflicts: opts::Conflicts,
+    src: &Path,
+    watchos_src: Option<&Path>,
+    dest: &Path,
 ) -> Result<(), Error> {
-    println!("Installing iOS toolchains...");
-    Target::install_all().map_err(Error::RustupFailed)?;
-    rust_version_check(wrapper).map_err(Error::RustVersionCheckFailed)?;
-
-    deps::install_all(wrapper, non_interactive, skip_dev_tools, reinstall_deps)
-        .map_err(Error::DepsInstallFailed)?;
-
-    let dest = config.project_dir();
-    let rel_prefix = util::relativize_path(config.app().root_dir(), &dest);
+    let rel_prefix = util::relativize_path(config.app().root_dir(), dest);
     let source_dirs = std::iter::once("src".as_ref())
         .chain(submodule_path)
         .map(|path| rel_prefix.join(path))
         .collect::<Vec<PathBuf>>();
 
-    let src = Pack::lookup_platform(TEMPLATE_PACK)
-        .map_err(Error::MissingPack)?
-        .expect_local();
+    let target_dir = xcode_target_dir(
+        config.app().root_dir(),
+        &util::cargo_target_dir(config.app().root_dir()),
+        &rel_prefix,
+    );
 
     let asset_catalogs = metadata.ios().asset_catalogs().unwrap_or_default();
-    let ios_pods = metadata.ios().pods().unwrap_or_default();
-    let macos_pods = metadata.macos().pods().unwrap_or_default();
-    let ios_pod_options = metadata.ios().pod_options().unwrap_or_default();
-    let macos_pod_options = metadata.macos().pod_options().unwrap_or_default();
 
     let default_archs = [String::from("arm64"), String::from("x86_64")];
-    bike.filter_and_process(
+    let platforms = [
+        ("ios", metadata.ios(), config.ios_version()),
+        ("macos", metadata.macos(), config.macos_version()),
+    ]
+    .iter()
+    .map(|(prefix, platform, default_target)| {
+        platform
+            .deployment_target()
+            .map(|target| (*prefix, *platform, target.unwrap_or(*default_target)))
+            .map_err(|cause| Error::DeploymentTargetInvalid {
+                platform: *prefix,
+                cause,
+            })
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+    let watchos = metadata
+        .watchos()
+        .map(|watchos| {
+            watchos
+                .deployment_target()
+                .map(|target| (watchos, target))
+                .map_err(|cause| Error::DeploymentTargetInvalid {
+                    platform: "watchos",
+                    cause,
+                })
+        })
+        .transpose()?;
+    templating::process(
+        bike,
         src,
-        &dest,
+        dest,
         |map| {
+            // Everything pointing outside of the project goes through this
+            // (via `root-rel`), so it survives being cloned elsewhere.
+            map.insert("root-dir-rel", &rel_prefix);
+            map.insert("cargo-target-dir", &target_dir);
             map.insert("file-groups", &source_dirs);
-            map.insert("ios-libraries", metadata.ios().libraries());
-            map.insert("ios-frameworks", metadata.ios().frameworks());
-            map.insert(
-                "ios-valid-archs",
-                metadata
-                    .ios()
-                    .valid_archs()
-                    .unwrap_or_else(|| &default_archs),
-            );
-            map.insert("ios-vendor-frameworks", metadata.ios().vendor_frameworks());
-            map.insert("ios-vendor-sdks", metadata.ios().vendor_sdks());
-            map.insert("macos-libraries", metadata.ios().libraries());
-            map.insert("macos-frameworks", metadata.macos().frameworks());
-            map.insert(
-                "macos-vendor-frameworks",
-                metadata.macos().vendor_frameworks(),
-            );
-            map.insert("macos-vendor-sdks", metadata.macos().vendor_frameworks());
+            for (prefix, platform, deployment_target) in &platforms {
+                for (key, value) in
+                    platform_template_vars(prefix, platform, &default_archs, *deployment_target)
+                {
+                    map.insert(key, value);
+                }
+            }
+            map.insert("watchos-enabled", watchos.is_some());
+            if let Some((watchos, deployment_target)) = watchos {
+                map.insert("watchos-deployment-target", deployment_target.to_string());
+                map.insert("watchos-frameworks", watchos.frameworks());
+            }
             map.insert("asset-catalogs", asset_catalogs);
-            map.insert("ios-pods", ios_pods);
-            map.insert("macos-pods", macos_pods);
-            map.insert("ios-pod-options", ios_pod_options);
-            map.insert("macos-pod-options", macos_pod_options);
-            map.insert(
-                "ios-additional-targets",
-                metadata.ios().additional_targets(),
-            );
-            map.insert(
-                "macos-additional-targets",
-                metadata.macos().additional_targets(),
-            );
-            map.insert("ios-pre-build-scripts", metadata.ios().pre_build_scripts());
+            // One folder reference per installed dir, so merged dirs show up
+            // once.
             map.insert(
-                "ios-post-compile-scripts",
-                metadata.ios().post_compile_scripts(),
+                "ios-asset-dirs",
+                assets::groups(config.app().asset_dirs(), AssetPlatform::Ios)
+                    .into_iter()
+                    .map(|group| group.dest)
+                    .collect::<Vec<_>>(),
             );
+            // Quoted here since YAML would otherwise choke on names like
+            // `Yes` or `Tic: Tac: Toe`; JSON strings are valid YAML.
+            let bundle_id = config.bundle_identifier(opts::Profile::Release);
+            let debug_bundle_id = Some(config.bundle_identifier(opts::Profile::Debug))
+                .filter(|debug_bundle_id| *debug_bundle_id != bundle_id);
+            // With a debug suffix, the name comes from a build setting so it
+            // can differ per configuration.
+            let debug_display_name = config
+                .app()
+                .debug_display_name_suffix()
+                .map(|_| config.app().display_name_for(opts::Profile::Debug));
             map.insert(
-                "ios-post-build-scripts",
-                metadata.ios().post_build_scripts(),
+                "display-name-quoted",
+                if debug_display_name.is_some() {
+                    serde_json::to_string("$(CM_DISPLAY_NAME)").ok()
+                } else if config.app().display_name_explicit() {
+                    serde_json::to_string(config.app().display_name()).ok()
+                } else {
+                    None
+                },
             );
             map.insert(
-                "macos-pre-build-scripts",
-                metadata.macos().pre_build_scripts(),
+                "release-display-name-quoted",
+                serde_json::to_string(&config.app().display_name_for(opts::Profile::Release)).ok(),
             );
             map.insert(
-                "macos-post-compile-scripts",
-                metadata.macos().post_compile_scripts(),
+                "debug-display-name-quoted",
+                debug_display_name
+                    .as_ref()
+                    .and_then(|name| serde_json::to_string(name).ok()),
             );
             map.insert(
-                "macos-post-build-scripts",
-                metadata.macos().post_build_scripts(),
+                "has-debug-overrides",
+                debug_bundle_id.is_some() || debug_display_name.is_some(),
             );
+            map.insert("bundle-id", &bundle_id);
+            map.insert("debug-bundle-id", &debug_bundle_id);
             map.insert(
-                "ios-command-line-arguments",
-                metadata.ios().command_line_arguments(),
+                "has-localizations",
+                !config.app().display_name_localized().is_empty(),
             );
+            // Each configuration leaves out the other's localized names.
             map.insert(
-                "macos-command-line-arguments",
-                metadata.macos().command_line_arguments(),
+                "has-debug-localizations",
+                localization::has_debug(config.app()),
             );
         },
-        filter.fun(),
+        filter,
+        conflicts,
     )
     .map_err(Error::TemplateProcessingFailed)?;
+    if let Some(watchos_src) = watchos_src.filter(|_| watchos.is_some()) {
+        templating::process(bike, watchos_src, dest, |_| (), filter, conflicts)
+            .map_err(Error::TemplateProcessingFailed)?;
+    }
+    Ok(())
+}
 
-    ln::force_symlink_relative(config.app().asset_dir(), &dest, ln::TargetStyle::Directory)
-        .map_err(Error::AssetDirSymlinkFailed)?;
+/// Installs the toolchains for every target we'll build, which only includes
+/// the watchOS ones when they're enabled.
+fn install_targets(metadata: &Metadata) -> Result<(), util::toolchain::RustupTargetAddFailed> {
+    Target::install_with_watchos(metadata.watchos().is_some())
+}
+
+/// Everything `gen` needs from the toolchain, for when the Xcode project is
+/// the user's own. None of the deps are needed without a project to generate.
+pub fn adopt(metadata: &Metadata, wrapper: &TextWrapper) -> Result<(), Error> {
+    events::say("Installing iOS toolchains...");
+    events::step(&RUSTUP_STEP, || install_targets(metadata)).map_err(Error::RustupFailed)?;
+    rust_version_check(wrapper).map_err(Error::RustVersionCheckFailed)
+}
+
+// unprefixed app_root seems pretty dangerous!!
+// TODO: figure out what I meant by that
+pub fn gen(
+    config: &Config,
+    metadata: &Metadata,
+    submodule_path: Option<&Path>,
+    bike: &bicycle::Bicycle,
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    skip_dev_tools: opts::SkipDevTools,
+    reinstall_deps: opts::ReinstallDeps,
+    filter: &templating::Filter,
+    conflicts: opts::Conflicts,
+) -> Result<(), Error> {
+    events::say("Installing iOS toolchains...");
+    events::step(&RUSTUP_STEP, || install_targets(metadata)).map_err(Error::RustupFailed)?;
+    rust_version_check(wrapper).map_err(Error::RustVersionCheckFailed)?;
+
+    events::step(&DEPS_INSTALL_STEP, || {
+        deps::install_all(
+            wrapper,
+            config.dependencies(),
+            non_interactive,
+            skip_dev_tools,
+            reinstall_deps,
+        )
+    })
+    .map_err(Error::DepsInstallFailed)?;
+
+    let dest = config.project_dir();
+    let src = Pack::lookup_platform(TEMPLATE_PACK)
+        .map_err(Error::MissingPack)?
+        .expect_local();
+    // Only looked up when it'll be used, so packs without it still work for
+    // apps without a watch app.
+    let watchos_src = metadata
+        .watchos()
+        .map(|_| {
+            Pack::lookup_platform(WATCHOS_TEMPLATE_PACK)
+                .map(Pack::expect_local)
+                .map_err(Error::MissingPack)
+        })
+        .transpose()?;
+    process_templates(
+        config,
+        metadata,
+        submodule_path,
+        bike,
+        filter,
+        conflicts,
+        &src,
+        watchos_src.as_deref(),
+        &dest,
+    )?;
+    let asset_catalogs = metadata.ios().asset_catalogs().unwrap_or_default();
+
+    // These aren't templated, since they're arbitrarily nested plist values.
+    for (name, platform) in &[("iOS", metadata.ios()), ("macOS", metadata.macos())] {
+        if let Some(platform_entitlements) = platform.entitlements() {
+            entitlements::write(
+                &dest.join(entitlements::rel_path(config.app().name(), name)),
+                platform_entitlements,
+            )
+            .map_err(Error::EntitlementsWriteFailed)?;
+        }
+    }
+
+    assets::install(config.app(), AssetPlatform::Ios, &dest)
+        .map_err(Error::AssetDirInstallFailed)?;
+
+    localization::gen(config.app(), dest.clone()).map_err(Error::LocalizationGenFailed)?;
 
     // Create all asset catalog directories if they don't already exist
     for dir in asset_catalogs {
@@ -179,18 +446,294 @@ pub fn gen(
 
     // Note that Xcode doesn't always reload the project nicely; reopening is
     // often necessary.
-    println!("Generating Xcode project...");
-    bossy::Command::impure("xcodegen")
-        .with_args(&["generate", "--spec"])
-        .with_arg(dest.join("project.yml"))
-        .run_and_wait()
-        .map_err(Error::XcodegenFailed)?;
-
-    if !ios_pods.is_empty() || !macos_pods.is_empty() {
-        bossy::Command::impure_parse("pod install")
-            .with_arg(format!("--project-directory={}", dest.display()))
-            .run_and_wait()
-            .map_err(Error::PodInstallFailed)?;
+    events::say("Generating Xcode project...");
+    events::step(&XCODEGEN_STEP, || run_xcodegen(&dest)).map_err(Error::XcodegenFailed)?;
+    patch_info_plists(config, metadata).map_err(Error::InfoPlistPatchFailed)?;
+
+    let has_pods = |platform: &Platform| platform.pods().map_or(false, |pods| !pods.is_empty());
+    if has_pods(metadata.ios()) || has_pods(metadata.macos()) {
+        events::step(&POD_INSTALL_STEP, || {
+            pod::install(&dest, &["install".to_owned()])
+        })
+        .map_err(Error::PodInstallFailed)?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::fixture::{self, Fixture};
+    use serde_json::json;
+    use std::fs;
+
+    /// Generates the project for `fixture` into `dest`.
+    fn gen_into(fixture: &Fixture, dest: &Path) {
+        let packs = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates/platforms");
+        process_templates(
+            fixture.config.apple(),
+            fixture.metadata.apple(),
+            None,
+            &fixture.config.build_a_bike(),
+            &templating::Filter::WildWest,
+            opts::Conflicts::Keep,
+            &packs.join(TEMPLATE_PACK),
+            Some(&packs.join(WATCHOS_TEMPLATE_PACK)),
+            dest,
+        )
+        .unwrap();
+    }
+
+    /// Generates a project for an app with `metadata` in its `Cargo.toml`,
+    /// giving back the app and where the project went.
+    fn gen_project(metadata: &str) -> (Fixture, PathBuf) {
+        let fixture = fixture::app(
+            "portable",
+            "[apple]\ndevelopment-team = \"ABCDE12345\"\n",
+            metadata,
+        );
+        fs::create_dir(fixture.root.join("scripts")).unwrap();
+        fs::write(fixture.root.join("scripts/sign.sh"), "").unwrap();
+        let dest = fixture.root.join("gen/apple");
+        gen_into(&fixture, &dest);
+        (fixture, dest)
+    }
+
+    #[test]
+    fn generation_is_reproducible() {
+        let fixture = fixture::app(
+            "repro",
+            "[apple]\ndevelopment-team = \"ABCDE12345\"\n\n[app.display-name-localized]\nfr = \"Reproductible\"\nde = \"Reproduzierbar\"\n",
+            r#"
+            [package.metadata.cargo-apple.ios]
+            frameworks = ["StoreKit", "GameKit", "AVFoundation"]
+            pods = [{ name = "Firebase", version = "10.0" }, { name = "Ads" }]
+            background-modes = ["audio", "fetch"]
+
+            [package.metadata.cargo-apple.macos]
+            frameworks = ["GameController", "Metal"]
+
+            [package.metadata.cargo-apple.watchos]
+            frameworks = ["HealthKit", "WatchKit"]
+            "#,
+        );
+        // Both at the same depth, so they refer to the app the same way.
+        let gen = |name: &str| {
+            let dest = fixture.root.join(name).join("apple");
+            gen_into(&fixture, &dest);
+            localization::gen(fixture.config.app(), dest.clone()).unwrap();
+            templating::read_tree(&dest)
+        };
+        let first = gen("first");
+        assert!(first.len() > 1);
+        assert_eq!(first, gen("second"));
+    }
+
+    #[test]
+    fn generated_project_is_portable() {
+        let (fixture, dest) = gen_project(
+            r#"
+            [package.metadata.cargo-apple.ios]
+            vendor-frameworks = ["vendor/Ads.framework"]
+            additional-targets = ["ios-extras"]
+
+            [package.metadata.cargo-apple.macos]
+            post-compile-scripts = [{ name = "Sign", path = "scripts/sign.sh", input-file-lists = ["lists/in.xcfilelist"] }]
+            "#,
+        );
+        let project_yml = fs::read_to_string(dest.join("project.yml")).unwrap();
+        assert!(
+            !project_yml.contains(fixture.root.to_str().unwrap()),
+            "`project.yml` has the app root baked in:\n{}",
+            project_yml
+        );
+        assert!(project_yml.contains("../../vendor/Ads.framework"));
+        assert!(project_yml
+            .contains("\"$(PROJECT_DIR)/../../target/aarch64-apple-ios/$(CONFIGURATION)\""));
+        assert!(project_yml.contains("- path: ../../scripts/sign.sh\n        name: Sign"));
+        assert!(project_yml.contains("inputFileLists:\n          - lists/in.xcfilelist"));
+        // Which is also what `relocalize` goes by.
+        assert_eq!(
+            crate::apple::relocalize::stale_root(&project_yml, Path::new("/elsewhere")),
+            None
+        );
+        assert!(!project_yml.contains("watchOS"));
+        assert!(!dest.join("portable_watchOS").exists());
+    }
+
+    #[test]
+    fn target_dir_is_only_relative_inside_the_app() {
+        let (root, rel_prefix) = (Path::new("/app"), Path::new("../.."));
+        assert_eq!(
+            xcode_target_dir(root, Path::new("/app/target"), rel_prefix),
+            "$(PROJECT_DIR)/../../target"
+        );
+        assert_eq!(
+            xcode_target_dir(root, Path::new("/app/build/cargo"), rel_prefix),
+            "$(PROJECT_DIR)/../../build/cargo"
+        );
+        assert_eq!(
+            xcode_target_dir(root, Path::new("/cache/cargo"), rel_prefix),
+            "/cache/cargo"
+        );
+    }
+
+    #[test]
+    fn generates_watchos_targets_when_enabled() {
+        let (_fixture, dest) = gen_project(
+            r#"
+            [package.metadata.cargo-apple.watchos]
+            deployment-target = "10.0"
+            frameworks = ["HealthKit"]
+            "#,
+        );
+        let project_yml = fs::read_to_string(dest.join("project.yml")).unwrap();
+        assert!(project_yml.contains("  portable_watchOS:\n    type: application.watchapp2"));
+        assert!(
+            project_yml.contains("  portable_watchOS_Extension:\n    type: watchkit2-extension")
+        );
+        assert!(project_yml.contains("deploymentTarget: \"10.0\""));
+        assert!(project_yml.contains("- sdk: HealthKit.framework"));
+        assert!(project_yml.contains("- target: portable_watchOS\n"));
+        assert!(project_yml.contains("CM_COMPANION_BUNDLE_ID: com.example.portable"));
+        for triple in &[
+            "arm64_32-apple-watchos",
+            "aarch64-apple-watchos",
+            "aarch64-apple-watchos-sim",
+            "x86_64-apple-watchos-sim",
+        ] {
+            assert!(project_yml.contains(&format!(
+                "\"$(PROJECT_DIR)/../../target/{}/$(CONFIGURATION)\"",
+                triple
+            )));
+        }
+        assert!(dest.join("portable_watchOS/Interface.storyboard").is_file());
+        assert!(dest
+            .join("portable_watchOS_Extension/ExtensionDelegate.m")
+            .is_file());
+    }
+
+    #[test]
+    fn platform_vars_use_their_own_platform() {
+        let metadata: Metadata = toml::from_str(
+            r#"
+            [ios]
+            libraries = ["ios-lib"]
+            frameworks = ["ios-framework"]
+            vendor-frameworks = ["ios-vendor-framework"]
+            excluded-simulator-archs = ["ios-arch"]
+            vendor-sdks = ["ios-vendor-sdk"]
+            command-line-arguments = ["--ios"]
+
+            [macos]
+            libraries = ["macos-lib"]
+            frameworks = ["macos-framework"]
+            vendor-frameworks = ["macos-vendor-framework"]
+            vendor-sdks = ["macos-vendor-sdk"]
+            command-line-arguments = ["--macos"]
+            "#,
+        )
+        .unwrap();
+        let default_archs = [String::from("arm64")];
+        let deployment_target = util::VersionDouble::from_str("12.0").unwrap();
+        for (prefix, platform) in &[("ios", metadata.ios()), ("macos", metadata.macos())] {
+            let vars = platform_template_vars(prefix, platform, &default_archs, deployment_target)
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>();
+            for (key, value) in &[
+                ("libraries", format!("{}-lib", prefix)),
+                ("frameworks", format!("{}-framework", prefix)),
+                ("vendor-frameworks", format!("{}-vendor-framework", prefix)),
+                ("vendor-sdks", format!("{}-vendor-sdk", prefix)),
+                ("command-line-arguments", format!("--{}", prefix)),
+            ] {
+                assert_eq!(vars[&format!("{}-{}", prefix, key)], json!([value]));
+            }
+            assert_eq!(
+                vars[&format!("{}-excluded-simulator-archs", prefix)],
+                if *prefix == "ios" {
+                    json!(["ios-arch"])
+                } else {
+                    json!([])
+                }
+            );
+            assert_eq!(vars[&format!("{}-valid-archs", prefix)], json!(["arm64"]));
+            assert_eq!(
+                vars[&format!("{}-deployment-target", prefix)],
+                json!("12.0")
+            );
+            assert_eq!(vars[&format!("{}-generate-scheme", prefix)], json!(true));
+            assert!(vars
+                .keys()
+                .all(|key| key.starts_with(&format!("{}-", prefix))));
+        }
+    }
+
+    #[test]
+    fn platforms_override_deployment_target_and_scheme() {
+        let metadata: Metadata = toml::from_str(
+            r#"
+            [ios]
+            deployment-target = "12.0"
+
+            [macos]
+            deployment-target = "12.3"
+            generate-scheme = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            metadata
+                .ios()
+                .deployment_target()
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            "12.0"
+        );
+        assert!(metadata.ios().generate_scheme());
+        assert_eq!(
+            metadata
+                .macos()
+                .deployment_target()
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            "12.3"
+        );
+        assert!(!metadata.macos().generate_scheme());
+
+        let invalid: Metadata = toml::from_str("[ios]\ndeployment-target = \"twelve\"").unwrap();
+        assert!(invalid.ios().deployment_target().is_err());
+    }
+
+    #[test]
+    fn entitlements_and_background_modes_are_per_platform() {
+        let metadata: Metadata = toml::from_str(
+            r#"
+            [ios]
+            background-modes = ["audio", "fetch"]
+
+            [ios.entitlements]
+            "aps-environment" = "development"
+
+            [macos.entitlements]
+            "#,
+        )
+        .unwrap();
+        let default_archs = [String::from("arm64")];
+        let deployment_target = util::VersionDouble::from_str("12.0").unwrap();
+        let vars = |prefix, platform| {
+            platform_template_vars(prefix, platform, &default_archs, deployment_target)
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>()
+        };
+        let ios = vars("ios", metadata.ios());
+        assert_eq!(ios["ios-has-entitlements"], json!(true));
+        assert_eq!(ios["ios-background-modes"], json!(["audio", "fetch"]));
+        // An empty table is the same as none at all.
+        let macos = vars("macos", metadata.macos());
+        assert_eq!(macos["macos-has-entitlements"], json!(false));
+        assert_eq!(macos["macos-background-modes"], json!([]));
+    }
+}
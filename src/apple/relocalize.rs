@@ -0,0 +1,132 @@
+//! Projects generated before `root-rel` existed have the app root baked into
+//! `project.yml` (and so the Xcode project) as an absolute path, so a clone
+//! on another machine links against a target dir that isn't there.
+
+use super::{
+    config::{Config, Metadata},
+    info_plist, project,
+};
+use once_cell_regex::regex;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to read {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to write {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: io::Error },
+    #[error(transparent)]
+    XcodegenFailed(crate::util::watchdog::Error),
+    #[error(transparent)]
+    InfoPlistPatchFailed(info_plist::Error),
+}
+
+fn project_yml_path(config: &Config) -> PathBuf {
+    config.project_dir().join("project.yml")
+}
+
+/// The app root `project_yml` was generated for, if it's an absolute path
+/// other than `root_dir`. The lib search paths are always there, so they're
+/// what we go by.
+pub fn stale_root(project_yml: &str, root_dir: &Path) -> Option<PathBuf> {
+    let caps =
+        regex!(r#""(?P<root>/[^"$]*?)/target/[\w-]+-apple-(?:ios|darwin)/\$\(CONFIGURATION\)[/"]"#)
+            .captures(project_yml)?;
+    Some(PathBuf::from(&caps["root"])).filter(|root| root != root_dir)
+}
+
+fn read_project_yml(path: &Path) -> Result<Option<String>, Error> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    fs::read_to_string(path)
+        .map(Some)
+        .map_err(|source| Error::ReadFailed {
+            path: path.to_owned(),
+            source,
+        })
+}
+
+/// Runs before every Apple command, so it only reads one small file.
+pub fn warn_if_stale(config: &Config) {
+    let path = project_yml_path(config);
+    match read_project_yml(&path) {
+        Ok(Some(project_yml)) => {
+            if let Some(old_root) = stale_root(&project_yml, config.app().root_dir()) {
+                log::warn!(
+                    "the Xcode project was generated at {:?}, and still points there instead of {:?}; run `cargo {} relocalize` to fix it",
+                    old_root,
+                    config.app().root_dir(),
+                    super::NAME,
+                );
+            }
+        }
+        Ok(None) => (),
+        Err(err) => log::warn!("{}", err),
+    }
+}
+
+/// Points `project.yml` at the current app root and regenerates the Xcode
+/// project from it, giving back the root it used to point at. Only the
+/// absolute paths change, so this doesn't need template packs or
+/// `cargo mobile init`, which is what makes the project portable for good.
+pub fn relocalize(config: &Config, metadata: &Metadata) -> Result<Option<PathBuf>, Error> {
+    let path = project_yml_path(config);
+    let project_yml = match read_project_yml(&path)? {
+        Some(project_yml) => project_yml,
+        None => return Ok(None),
+    };
+    let root_dir = config.app().root_dir();
+    let old_root = match stale_root(&project_yml, root_dir) {
+        Some(old_root) => old_root,
+        None => return Ok(None),
+    };
+    let relocalized = project_yml.replace(
+        &format!("{}/", old_root.display()),
+        &format!("{}/", root_dir.display()),
+    );
+    fs::write(&path, relocalized).map_err(|source| Error::WriteFailed {
+        path: path.clone(),
+        source,
+    })?;
+    project::run_xcodegen(&config.project_dir()).map_err(Error::XcodegenFailed)?;
+    project::patch_info_plists(config, metadata).map_err(Error::InfoPlistPatchFailed)?;
+    Ok(Some(old_root))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        project_yml,
+        expected,
+        case(
+            r#"LIBRARY_SEARCH_PATHS[sdk=iphoneos*]: $(inherited) "/Users/jane/game/target/aarch64-apple-ios/$(CONFIGURATION)""#,
+            Some("/Users/jane/game")
+        ),
+        case(
+            r#"- "/Users/jane/game/target/x86_64-apple-darwin/$(CONFIGURATION)/libgame.a""#,
+            Some("/Users/jane/game")
+        ),
+        case(
+            r#"LIBRARY_SEARCH_PATHS[sdk=iphoneos*]: $(inherited) "/Users/sam/src/game/target/aarch64-apple-ios/$(CONFIGURATION)""#,
+            None
+        ),
+        case(
+            r#"LIBRARY_SEARCH_PATHS[sdk=iphoneos*]: $(inherited) "$(PROJECT_DIR)/../../target/aarch64-apple-ios/$(CONFIGURATION)""#,
+            None
+        )
+    )]
+    fn detects_stale_roots(project_yml: &str, expected: Option<&str>) {
+        assert_eq!(
+            stale_root(project_yml, Path::new("/Users/sam/src/game")),
+            expected.map(PathBuf::from)
+        );
+    }
+}
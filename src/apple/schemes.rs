@@ -0,0 +1,254 @@
+//! The schemes `xcodebuild` can build, which include any targets users added
+//! to the generated project themselves, like a widget extension.
+
+use super::config::Config;
+use crate::util::cli::{Report, Reportable};
+use serde::Deserialize;
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to run `xcodebuild -list`: {0}")]
+    ListFailed(#[from] bossy::Error),
+    #[error("`xcodebuild -list` output wasn't valid JSON: {0}")]
+    ParseFailed(#[from] serde_json::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        Report::error("Failed to list Xcode schemes", self)
+    }
+}
+
+/// `--scheme` named something that isn't there.
+#[derive(Debug)]
+pub struct Missing {
+    scheme: String,
+    workspace: PathBuf,
+    available: Vec<String>,
+}
+
+impl Reportable for Missing {
+    fn report(&self) -> Report {
+        let msg = format!("Scheme {:?} doesn't exist", self.scheme);
+        if self.available.is_empty() {
+            Report::action_request(
+                msg,
+                format!(
+                    "No schemes were found in {:?}; run `cargo mobile init` to regenerate it.",
+                    self.workspace
+                ),
+            )
+        } else {
+            Report::action_request(
+                msg,
+                format!(
+                    "The available schemes are {}. If you just added it in Xcode, make sure \"Shared\" is checked for it in \"Manage Schemes...\".",
+                    self.available.join(", ")
+                ),
+            )
+        }
+    }
+}
+
+/// The shared schemes in `container`, which is an `.xcodeproj` or an
+/// `.xcworkspace`. That's where `xcodegen` puts the ones it generates.
+pub fn shared(container: &Path) -> io::Result<Vec<String>> {
+    let mut schemes = fs::read_dir(container.join("xcshareddata/xcschemes"))?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? == "xcscheme" {
+                path.file_stem()?.to_str().map(ToOwned::to_owned)
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    schemes.sort();
+    Ok(schemes)
+}
+
+#[derive(Debug, Deserialize)]
+struct Listed {
+    #[serde(default)]
+    schemes: Vec<String>,
+}
+
+/// `xcodebuild -list -json` says `workspace` or `project` depending on what
+/// it was pointed at.
+#[derive(Debug, Deserialize)]
+struct ListOutput {
+    workspace: Option<Listed>,
+    project: Option<Listed>,
+}
+
+pub fn parse_list(output: &str) -> Result<Vec<String>, serde_json::Error> {
+    let output = serde_json::from_str::<ListOutput>(output)?;
+    let mut schemes = output
+        .workspace
+        .into_iter()
+        .chain(output.project)
+        .flat_map(|listed| listed.schemes)
+        .collect::<Vec<_>>();
+    schemes.sort();
+    schemes.dedup();
+    Ok(schemes)
+}
+
+/// The shared schemes in `containers`, which are skipped if they don't exist.
+fn shared_in(containers: &[PathBuf]) -> Vec<String> {
+    let mut schemes = BTreeSet::new();
+    for container in containers {
+        match shared(container) {
+            Ok(found) => schemes.extend(found),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => log::warn!("failed to read schemes from {:?}: {}", container, err),
+        }
+    }
+    schemes.into_iter().collect()
+}
+
+/// Whether the shared schemes are enough to go on, or `xcodebuild` has to be
+/// asked. Schemes Xcode makes on its own aren't shared, so a scheme that
+/// isn't among them might still be there.
+fn shared_suffice(shared: &[String], wanted: Option<&str>) -> bool {
+    match wanted {
+        Some(wanted) => shared.iter().any(|scheme| scheme == wanted),
+        None => !shared.is_empty(),
+    }
+}
+
+/// Every scheme in the project and workspace, or enough of them to tell if
+/// `wanted` is there. The shared ones are read straight from disk, and if
+/// that doesn't settle it, we ask `xcodebuild` instead, which is slower but
+/// sees the rest too.
+pub fn list(config: &Config, wanted: Option<&str>) -> Result<Vec<String>, Error> {
+    let schemes = shared_in(&[config.xcodeproj_path(), config.workspace_path()]);
+    if shared_suffice(&schemes, wanted) {
+        return Ok(schemes);
+    }
+    log::info!(
+        "falling back to `xcodebuild -list`, since the shared schemes are {:?}",
+        schemes
+    );
+    let mut listed = bossy::Command::pure("xcodebuild")
+        .with_args(&["-list", "-json", "-workspace"])
+        .with_arg(config.workspace_path())
+        .run_and_wait_for_str(parse_list)?
+        .map_err(Error::ParseFailed)?;
+    listed.extend(schemes);
+    listed.sort();
+    listed.dedup();
+    Ok(listed)
+}
+
+/// Fails if `scheme` isn't one of `available`.
+pub fn check(scheme: &str, workspace: &Path, available: &[String]) -> Result<(), Missing> {
+    if available.iter().any(|available| available == scheme) {
+        Ok(())
+    } else {
+        Err(Missing {
+            scheme: scheme.to_owned(),
+            workspace: workspace.to_owned(),
+            available: available.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    // Captured from Xcode 14.2, for a generated project with a widget
+    // extension added.
+    static WORKSPACE_LIST: &str = r#"{
+  "workspace" : {
+    "name" : "tic-tac",
+    "schemes" : [
+      "tic-tac_iOS",
+      "TicTacWidget",
+      "tic-tac_macOS"
+    ]
+  }
+}
+"#;
+    static PROJECT_LIST: &str = r#"{
+  "project" : {
+    "configurations" : [
+      "debug",
+      "release"
+    ],
+    "name" : "tic-tac",
+    "schemes" : [
+      "tic-tac_iOS"
+    ],
+    "targets" : [
+      "tic-tac_iOS",
+      "lib_tic-tac_iOS"
+    ]
+  }
+}
+"#;
+
+    #[test]
+    fn parses_list_output() {
+        assert_eq!(
+            parse_list(WORKSPACE_LIST).unwrap(),
+            vec!["TicTacWidget", "tic-tac_iOS", "tic-tac_macOS"]
+        );
+        assert_eq!(parse_list(PROJECT_LIST).unwrap(), vec!["tic-tac_iOS"]);
+        assert!(parse_list("{}").unwrap().is_empty());
+        assert!(parse_list("xcodebuild: error: nope").is_err());
+    }
+
+    #[test]
+    fn finds_shared_schemes() {
+        let dir = TempDirGuard::new().unwrap();
+        let xcodeproj = dir.join("tic-tac.xcodeproj");
+        let workspace = dir.join("tic-tac.xcworkspace");
+        for (container, name) in &[
+            (&xcodeproj, "tic-tac_iOS.xcscheme"),
+            (&xcodeproj, "tic-tac_macOS.xcscheme"),
+            (&xcodeproj, "README.md"),
+            (&workspace, "TicTacWidget.xcscheme"),
+            (&workspace, "tic-tac_iOS.xcscheme"),
+        ] {
+            let schemes_dir = container.join("xcshareddata/xcschemes");
+            fs::create_dir_all(&schemes_dir).unwrap();
+            fs::write(schemes_dir.join(name), "<Scheme/>").unwrap();
+        }
+        assert_eq!(
+            shared(&xcodeproj).unwrap(),
+            vec!["tic-tac_iOS", "tic-tac_macOS"]
+        );
+        assert_eq!(
+            shared_in(&[xcodeproj, workspace, dir.join("missing.xcworkspace")]),
+            vec!["TicTacWidget", "tic-tac_iOS", "tic-tac_macOS"]
+        );
+    }
+
+    #[test]
+    fn asks_xcodebuild_unless_shared_schemes_settle_it() {
+        let shared = vec!["tic-tac_iOS".to_owned(), "tic-tac_macOS".to_owned()];
+        assert!(shared_suffice(&shared, None));
+        assert!(shared_suffice(&shared, Some("tic-tac_iOS")));
+        assert!(!shared_suffice(&shared, Some("TicTacWidget")));
+        assert!(!shared_suffice(&[], None));
+    }
+
+    #[test]
+    fn checks_scheme_exists() {
+        let available = vec!["TicTacWidget".to_owned(), "tic-tac_iOS".to_owned()];
+        let workspace = Path::new("gen/apple/tic-tac.xcworkspace");
+        assert!(check("TicTacWidget", workspace, &available).is_ok());
+        let missing = check("TicTacClip", workspace, &available).unwrap_err();
+        assert_eq!(missing.available, available);
+        assert!(check("tic-tac_iOS", workspace, &[]).is_err());
+    }
+}
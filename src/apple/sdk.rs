@@ -0,0 +1,279 @@
+//! Catches Xcode being too old for the project or the device before we get
+//! far enough for `xcodebuild` to fail with something misleading.
+
+use super::system_profile::DeveloperTools;
+use crate::util::{
+    cli::{Report, Reportable},
+    VersionDouble,
+};
+use once_cell_regex::regex;
+use std::fmt::{self, Display};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sdk {
+    /// i.e. `iphoneos`, `iphonesimulator`, or `driverkit.macosx`.
+    pub name: String,
+    pub version: VersionDouble,
+}
+
+/// Parses the output of `xcodebuild -showsdks`, skipping anything that
+/// doesn't look like an SDK.
+pub fn parse_sdks(output: &str) -> Vec<Sdk> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let caps =
+                regex!(r"-sdk (?P<name>[a-z.]+?)(?P<version>\d+(?:\.\d+)*)\s*$").captures(line)?;
+            VersionDouble::from_str(&caps["version"])
+                .map_err(|err| log::warn!("skipping SDK in line {:?}: {}", line, err))
+                .ok()
+                .map(|version| Sdk {
+                    name: caps["name"].to_owned(),
+                    version,
+                })
+        })
+        .collect()
+}
+
+pub fn list_sdks() -> bossy::Result<Vec<Sdk>> {
+    bossy::Command::impure_parse("xcodebuild -showsdks").run_and_wait_for_str(parse_sdks)
+}
+
+/// The newest installed version of the SDK named `name`.
+pub fn latest(sdks: &[Sdk], name: &str) -> Option<VersionDouble> {
+    sdks.iter()
+        .filter(|sdk| sdk.name == name)
+        .map(|sdk| sdk.version)
+        .max()
+}
+
+// Patch releases never need a newer SDK.
+fn major_minor(version: VersionDouble) -> (u32, u32) {
+    (version.major, version.minor)
+}
+
+#[derive(Debug)]
+pub enum Incompatible {
+    SdkTooOld {
+        xcode: Option<(u32, u32)>,
+        sdk: VersionDouble,
+        deployment_target: VersionDouble,
+    },
+    DeviceTooNew {
+        xcode: Option<(u32, u32)>,
+        sdk: VersionDouble,
+        device: String,
+        device_version: VersionDouble,
+    },
+}
+
+struct XcodeVersion(Option<(u32, u32)>);
+
+impl Display for XcodeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some((major, minor)) => write!(f, "Xcode v{}.{}", major, minor),
+            None => write!(f, "your Xcode"),
+        }
+    }
+}
+
+impl Reportable for Incompatible {
+    fn report(&self) -> Report {
+        match self {
+            Self::SdkTooOld {
+                xcode,
+                sdk,
+                deployment_target,
+            } => Report::action_request(
+                "Xcode is too old for this project's deployment target",
+                format!(
+                    "{} comes with the iOS {} SDK, but the deployment target is iOS {}. Update Xcode, or lower the deployment target.",
+                    XcodeVersion(*xcode), sdk, deployment_target
+                ),
+            ),
+            Self::DeviceTooNew {
+                xcode,
+                sdk,
+                device,
+                device_version,
+            } => Report::action_request(
+                format!("Xcode is too old to deploy to {}", device),
+                format!(
+                    "{} supports up to iOS {}, but {} is running iOS {}. Update Xcode to deploy to this device.",
+                    XcodeVersion(*xcode), sdk, device, device_version
+                ),
+            ),
+        }
+    }
+}
+
+/// Checks the newest installed `iphoneos` SDK against the deployment target,
+/// and against the device being deployed to, if any.
+pub fn check_compat(
+    sdks: &[Sdk],
+    xcode: Option<(u32, u32)>,
+    deployment_target: VersionDouble,
+    device: Option<(&str, VersionDouble)>,
+) -> Result<(), Incompatible> {
+    let sdk = if let Some(sdk) = latest(sdks, "iphoneos") {
+        sdk
+    } else {
+        log::warn!("no `iphoneos` SDK found, so Xcode compatibility can't be checked");
+        return Ok(());
+    };
+    if major_minor(sdk) < major_minor(deployment_target) {
+        return Err(Incompatible::SdkTooOld {
+            xcode,
+            sdk,
+            deployment_target,
+        });
+    }
+    if let Some((device, device_version)) = device {
+        if major_minor(sdk) < major_minor(device_version) {
+            return Err(Incompatible::DeviceTooNew {
+                xcode,
+                sdk,
+                device: device.to_owned(),
+                device_version,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Runs `check_compat` against what's installed. Failing to find out what's
+/// installed isn't fatal, since this is only here to give better errors.
+pub fn preflight(
+    deployment_target: VersionDouble,
+    device: Option<(&str, VersionDouble)>,
+) -> Result<(), Incompatible> {
+    let sdks = match list_sdks() {
+        Ok(sdks) => sdks,
+        Err(err) => {
+            log::warn!(
+                "failed to list SDKs, so Xcode compatibility can't be checked: {}",
+                err
+            );
+            return Ok(());
+        }
+    };
+    let xcode = DeveloperTools::new()
+        .map(|tools| tools.version)
+        .map_err(|err| log::warn!("failed to get Xcode version: {}", err))
+        .ok();
+    check_compat(&sdks, xcode, deployment_target, device)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Captured from Xcode 11.3.
+    static XCODE_11: &str = "iOS SDKs:
+\tiOS 13.2                      \t-sdk iphoneos13.2
+
+iOS Simulator SDKs:
+\tSimulator - iOS 13.2          \t-sdk iphonesimulator13.2
+
+macOS SDKs:
+\tDriverKit 19.0                \t-sdk driverkit.macosx19.0
+\tmacOS 10.15                   \t-sdk macosx10.15
+
+tvOS SDKs:
+\ttvOS 13.2                     \t-sdk appletvos13.2
+
+tvOS Simulator SDKs:
+\tSimulator - tvOS 13.2         \t-sdk appletvsimulator13.2
+
+watchOS SDKs:
+\twatchOS 6.1                   \t-sdk watchos6.1
+
+watchOS Simulator SDKs:
+\tSimulator - watchOS 6.1       \t-sdk watchsimulator6.1
+
+";
+
+    // Captured from Xcode 15.0.
+    static XCODE_15: &str = "DriverKit SDKs:
+\tDriverKit 23.0                \t-sdk driverkit23.0
+
+iOS SDKs:
+\tiOS 17.0                      \t-sdk iphoneos17.0
+
+iOS Simulator SDKs:
+\tSimulator - iOS 17.0          \t-sdk iphonesimulator17.0
+
+macOS SDKs:
+\tmacOS 14.0                    \t-sdk macosx14.0
+
+tvOS SDKs:
+\ttvOS 17.0                     \t-sdk appletvos17.0
+
+tvOS Simulator SDKs:
+\tSimulator - tvOS 17.0         \t-sdk appletvsimulator17.0
+
+visionOS SDKs:
+\tvisionOS 1.0                  \t-sdk xros1.0
+
+visionOS Simulator SDKs:
+\tSimulator - visionOS 1.0      \t-sdk xrsimulator1.0
+
+watchOS SDKs:
+\twatchOS 10.0                  \t-sdk watchos10.0
+
+watchOS Simulator SDKs:
+\tSimulator - watchOS 10.0      \t-sdk watchsimulator10.0
+";
+
+    fn version(s: &str) -> VersionDouble {
+        VersionDouble::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parses_xcode_11() {
+        let sdks = parse_sdks(XCODE_11);
+        assert_eq!(sdks.len(), 7);
+        assert_eq!(latest(&sdks, "iphoneos"), Some(version("13.2")));
+        assert_eq!(latest(&sdks, "macosx"), Some(version("10.15")));
+        assert_eq!(latest(&sdks, "driverkit.macosx"), Some(version("19.0")));
+    }
+
+    #[test]
+    fn parses_xcode_15() {
+        let sdks = parse_sdks(XCODE_15);
+        assert_eq!(sdks.len(), 10);
+        assert_eq!(latest(&sdks, "iphoneos"), Some(version("17.0")));
+        assert_eq!(latest(&sdks, "iphonesimulator"), Some(version("17.0")));
+        assert_eq!(latest(&sdks, "xros"), Some(version("1.0")));
+    }
+
+    #[test]
+    fn checks_deployment_target_and_device() {
+        let sdks = parse_sdks(XCODE_11);
+        assert!(check_compat(&sdks, Some((11, 3)), version("13.0"), None).is_ok());
+        assert!(matches!(
+            check_compat(&sdks, Some((11, 3)), version("14.0"), None),
+            Err(Incompatible::SdkTooOld { .. })
+        ));
+        // Patch releases don't count.
+        assert!(check_compat(
+            &sdks,
+            Some((11, 3)),
+            version("13.0"),
+            Some(("iPhone", version("13.2.3")))
+        )
+        .is_ok());
+        assert!(matches!(
+            check_compat(
+                &sdks,
+                Some((11, 3)),
+                version("13.0"),
+                Some(("iPhone", version("13.3")))
+            ),
+            Err(Incompatible::DeviceTooNew { .. })
+        ));
+        // Nothing to check against.
+        assert!(check_compat(&[], None, version("99.0"), None).is_ok());
+    }
+}
@@ -0,0 +1,189 @@
+//! Recognizes the errors Xcode gives when a vendor framework doesn't have a
+//! slice for the simulator arch being built, which is common on Apple Silicon
+//! with frameworks that only ship x86_64 simulator slices. The raw
+//! `xcodebuild` failure doesn't say what to do about it, so we do.
+
+use crate::util::{lines, ErrorSummary};
+use once_cell_regex::regex;
+use std::{
+    fmt::{self, Display},
+    sync::Mutex,
+};
+
+/// What to do about a mismatch, which depends on which way it goes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fix {
+    /// Something only has x86_64 (or device) slices, so build the simulator
+    /// for x86_64 and run it under Rosetta.
+    ExcludeArm64,
+    /// Something only has arm64 slices, so stop building x86_64, which
+    /// usually means Xcode or the terminal is running under Rosetta.
+    ExcludeX86_64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mismatch {
+    /// The arch being built, if the error said.
+    pub building: Option<String>,
+    /// The archs the object file or module actually has; empty when the
+    /// error only said it was built for devices.
+    pub available: Vec<String>,
+}
+
+impl Mismatch {
+    pub fn fix(&self) -> Fix {
+        let has_arm64 = self.available.iter().any(|arch| arch == "arm64");
+        if self.building.as_deref() == Some("x86_64") && has_arm64 {
+            Fix::ExcludeX86_64
+        } else {
+            Fix::ExcludeArm64
+        }
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fix() {
+            Fix::ExcludeArm64 => write!(
+                f,
+                "A framework or library doesn't have an arm64 slice for the iOS Simulator. Add `excluded-simulator-archs = [\"arm64\"]` to `[package.metadata.cargo-apple.ios]` in your Cargo.toml (which sets `EXCLUDED_ARCHS[sdk=iphonesimulator*] = arm64`), run `cargo mobile init`, and run the simulator under Rosetta."
+            ),
+            Fix::ExcludeX86_64 => write!(
+                f,
+                "A framework or library only has arm64 slices, but x86_64 is being built, which usually means Xcode or your terminal is running under Rosetta. Stop running under Rosetta, or add `excluded-simulator-archs = [\"x86_64\"]` to `[package.metadata.cargo-apple.ios]` in your Cargo.toml and run `cargo mobile init`."
+            ),
+        }
+    }
+}
+
+// `x86_64-apple-ios-simulator` and `x86_64` both name x86_64.
+fn arch_of(target: &str) -> &str {
+    target.split('-').next().unwrap_or(target)
+}
+
+/// Finds a mismatch in a single line of `xcodebuild` output.
+pub fn detect(line: &str) -> Option<Mismatch> {
+    if let Some(caps) = regex!(
+        r"building for '?iOS[ -][Ss]imulator'?, but linking in object file .*built for '?iOS'?(?:, file '[^']*')?(?: for architecture (?P<arch>\w+))?"
+    )
+    .captures(line)
+    {
+        return Some(Mismatch {
+            building: caps.name("arch").map(|arch| arch.as_str().to_owned()),
+            available: Vec::new(),
+        });
+    }
+    let caps = regex!(
+        r"could not find module '[^']+' for target '(?P<target>[\w-]+)'; found: (?P<found>[^\n]*?)(?:, at: .*)?$"
+    )
+    .captures(line)?;
+    let mut available = Vec::<String>::new();
+    for found in caps["found"].split(',') {
+        let arch = arch_of(found.trim());
+        if !arch.is_empty() && !available.iter().any(|known| known == arch) {
+            available.push(arch.to_owned());
+        }
+    }
+    Some(Mismatch {
+        building: Some(arch_of(&caps["target"]).to_owned()),
+        available,
+    })
+}
+
+/// Finds the first mismatch among the errors a build printed.
+pub fn find(summary: &ErrorSummary) -> Option<Mismatch> {
+    summary.lines().iter().find_map(|line| detect(line))
+}
+
+/// Runs `command` with its output passed through, and returns a summary of
+/// the errors it printed along with the result.
+pub fn run_and_wait(command: bossy::Command) -> (bossy::Result<()>, ErrorSummary) {
+    let mut handle = match command.with_stdout_piped().with_stderr_piped().run() {
+        Ok(handle) => handle,
+        Err(err) => return (Err(err), ErrorSummary::default()),
+    };
+    let summary = Mutex::new(ErrorSummary::default());
+    lines::read_both(
+        &mut handle,
+        |line| {
+            summary.lock().unwrap().feed(line);
+            println!("{}", line);
+        },
+        |line| {
+            summary.lock().unwrap().feed(line);
+            eprintln!("{}", line);
+        },
+    );
+    (
+        handle.wait_for_output().map(|_| ()),
+        summary.into_inner().unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        line,
+        building,
+        available,
+        fix,
+        case(
+            "ld: building for iOS Simulator, but linking in object file built for iOS, file '/Users/jane/game/gen/apple/Vendor/Ads.framework/Ads' for architecture arm64",
+            Some("arm64"),
+            &[],
+            Fix::ExcludeArm64
+        ),
+        case(
+            "ld: building for 'iOS-simulator', but linking in object file (/Users/jane/game/gen/apple/Vendor/libads.a[2](ads.o)) built for 'iOS'",
+            None,
+            &[],
+            Fix::ExcludeArm64
+        ),
+        case(
+            "/Users/jane/game/gen/apple/Sources/game/main.swift:2:8: error: could not find module 'Ads' for target 'arm64-apple-ios-simulator'; found: x86_64-apple-ios-simulator, x86_64, at: /Users/jane/game/gen/apple/Vendor/Ads.framework/Modules/Ads.swiftmodule",
+            Some("arm64"),
+            &["x86_64"],
+            Fix::ExcludeArm64
+        ),
+        case(
+            "/Users/jane/game/gen/apple/Sources/game/main.swift:2:8: error: could not find module 'Ads' for target 'x86_64-apple-ios-simulator'; found: arm64, arm64-apple-ios-simulator, at: /Users/jane/game/gen/apple/Vendor/Ads.framework/Modules/Ads.swiftmodule",
+            Some("x86_64"),
+            &["arm64"],
+            Fix::ExcludeX86_64
+        )
+    )]
+    fn detects_mismatches(line: &str, building: Option<&str>, available: &[&str], fix: Fix) {
+        let mismatch = detect(line).unwrap();
+        assert_eq!(mismatch.building.as_deref(), building);
+        assert_eq!(mismatch.available, available);
+        assert_eq!(mismatch.fix(), fix);
+    }
+
+    #[rstest(
+        line,
+        case("ld: library not found for -lgame"),
+        case("ld: warning: object file was built for newer iOS version (16.0) than being linked (13.0)"),
+        case("error: could not find module 'Ads'")
+    )]
+    fn ignores_other_errors(line: &str) {
+        assert_eq!(detect(line), None);
+    }
+
+    #[test]
+    fn finds_mismatches_in_summaries() {
+        let mut summary = ErrorSummary::default();
+        for line in &[
+            "Ld /Users/jane/game/build/game normal arm64",
+            "ld: library not found for -lgame",
+            "ld: building for iOS Simulator, but linking in object file built for iOS, file '/Users/jane/game/gen/apple/Vendor/Ads.framework/Ads' for architecture arm64",
+            "** BUILD FAILED **",
+        ] {
+            summary.feed(line);
+        }
+        assert_eq!(find(&summary).unwrap().fix(), Fix::ExcludeArm64);
+        assert_eq!(find(&ErrorSummary::default()), None);
+    }
+}
@@ -1,5 +1,7 @@
 use super::{
     config::{Config, Metadata},
+    dsym::{self, DsymError},
+    sim_archs,
     system_profile::{self, DeveloperTools},
     version_number::VersionNumber,
 };
@@ -10,14 +12,14 @@ use crate::{
     util::{
         self,
         cli::{Report, Reportable},
-        CargoCommand, WithWorkingDirError,
+        diagnostics::{self, Hint, Inspector, Outcome},
+        timings,
+        toolchain::{self, RustupTargetAddFailed},
+        CargoCommand, ErrorSummary, WithWorkingDirError,
     },
 };
 use once_cell_regex::exports::once_cell::sync::OnceCell;
-use std::{
-    collections::{BTreeMap, HashMap},
-    ffi::OsStr,
-};
+use std::{collections::BTreeMap, ffi::OsString};
 
 fn verbosity(noise_level: opts::NoiseLevel) -> Option<&'static str> {
     if noise_level.pedantic() {
@@ -74,24 +76,46 @@ impl Reportable for CheckError {
 #[derive(Debug)]
 pub enum CompileLibError {
     VersionCheckFailed(VersionCheckError),
-    CargoBuildFailed(bossy::Error),
+    CargoBuildFailed {
+        cause: bossy::Error,
+        hints: Vec<Hint>,
+    },
 }
 
 impl Reportable for CompileLibError {
     fn report(&self) -> Report {
         match self {
             Self::VersionCheckFailed(err) => err.report(),
-            Self::CargoBuildFailed(err) => Report::error("Failed to run `cargo build`", err),
+            Self::CargoBuildFailed { cause, hints } => Report::error(
+                "Failed to run `cargo build`",
+                diagnostics::with_hints(cause, hints),
+            ),
         }
     }
 }
 
 #[derive(Debug)]
-pub struct BuildError(bossy::Error);
+pub struct BuildError {
+    cause: bossy::Error,
+    summary: ErrorSummary,
+}
 
 impl Reportable for BuildError {
     fn report(&self) -> Report {
-        Report::error("Failed to build via `xcodebuild`", &self.0)
+        // The log's long gone by the time this is printed, so the errors are
+        // repeated here.
+        let details = if self.summary.is_empty() {
+            self.cause.to_string()
+        } else {
+            format!("{}\nErrors:\n{}", self.cause, self.summary)
+        };
+        match sim_archs::find(&self.summary) {
+            Some(mismatch) => Report::action_request(
+                "Failed to build via `xcodebuild`, since the simulator archs don't match",
+                format!("{}\n\n{}", mismatch, details),
+            ),
+            None => Report::error("Failed to build via `xcodebuild`", details),
+        }
     }
 }
 
@@ -99,6 +123,7 @@ impl Reportable for BuildError {
 pub enum ArchiveError {
     SetVersionFailed(WithWorkingDirError<bossy::Error>),
     ArchiveFailed(bossy::Error),
+    DsymFailed(DsymError),
 }
 
 impl Reportable for ArchiveError {
@@ -106,6 +131,7 @@ impl Reportable for ArchiveError {
         match self {
             Self::SetVersionFailed(err) => Report::error("Failed to set app version number", err),
             Self::ArchiveFailed(err) => Report::error("Failed to archive via `xcodebuild`", err),
+            Self::DsymFailed(err) => err.report(),
         }
     }
 }
@@ -119,10 +145,60 @@ impl Reportable for ExportError {
     }
 }
 
+/// Which SDK a target builds against, going by the `PLATFORM_DISPLAY_NAME`
+/// Xcode gives build phases. Simulators have their own triples for arm64,
+/// but the iOS Simulator still uses the iOS one for x86_64.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Os {
+    Ios,
+    IosSimulator,
+    Macos,
+    Watchos,
+    WatchosSimulator,
+}
+
+impl Os {
+    pub const DISPLAY_NAMES: &'static [&'static str] = &[
+        "iOS",
+        "iOS Simulator",
+        "macOS",
+        "watchOS",
+        "watchOS Simulator",
+    ];
+
+    /// Anything we don't know about is treated as iOS, which is what
+    /// `xcode-script` always did.
+    pub fn from_display_name(name: &str) -> Self {
+        match name {
+            "iOS Simulator" => Self::IosSimulator,
+            "macOS" => Self::Macos,
+            "watchOS" => Self::Watchos,
+            "watchOS Simulator" => Self::WatchosSimulator,
+            _ => Self::Ios,
+        }
+    }
+
+    pub fn is_watchos(self) -> bool {
+        matches!(self, Self::Watchos | Self::WatchosSimulator)
+    }
+
+    /// What to pass to `xcrun --sdk` to find the SDK to build against. macOS
+    /// builds find the macOS SDK relative to the iOS one.
+    pub fn sdk(self) -> &'static str {
+        match self {
+            Self::Ios | Self::Macos => "iphoneos",
+            Self::IosSimulator => "iphonesimulator",
+            Self::Watchos => "watchos",
+            Self::WatchosSimulator => "watchsimulator",
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Target<'a> {
     pub triple: &'a str,
     pub arch: &'a str,
+    pub os: Os,
     alias: Option<&'a str>,
     min_xcode_version: Option<((u32, u32), &'static str)>,
 }
@@ -139,15 +215,27 @@ impl<'a> TargetTrait<'a> for Target<'a> {
                 Target {
                     triple: "aarch64-apple-ios",
                     arch: "arm64",
+                    os: Os::Ios,
                     alias: Some("arm64e"),
                     min_xcode_version: None,
                 },
             );
+            targets.insert(
+                "aarch64-sim",
+                Target {
+                    triple: "aarch64-apple-ios-sim",
+                    arch: "arm64",
+                    os: Os::IosSimulator,
+                    alias: None,
+                    min_xcode_version: None,
+                },
+            );
             targets.insert(
                 "x86_64",
                 Target {
                     triple: "x86_64-apple-ios",
                     arch: "x86_64",
+                    os: Os::Ios,
                     alias: None,
                     // Simulator only supports Metal as of Xcode 11.0:
                     // https://developer.apple.com/documentation/metal/developing_metal_apps_that_run_in_simulator?language=objc
@@ -168,27 +256,152 @@ impl<'a> TargetTrait<'a> for Target<'a> {
     fn arch(&'a self) -> &'a str {
         self.arch
     }
+
+    // The watchOS targets aren't in `all`, but they can still be asked for
+    // by name.
+    fn for_name(name: &str) -> Option<&'a Self> {
+        Self::all()
+            .get(name)
+            .or_else(|| Self::watchos_all().get(name))
+    }
 }
 
 impl<'a> Target<'a> {
+    /// The watchOS targets, which are kept out of `all` since they're tier 3
+    /// and only get built when the watchOS metadata is there.
+    pub fn watchos_all() -> &'a BTreeMap<&'a str, Self> {
+        static TARGETS: OnceCell<BTreeMap<&'static str, Target<'static>>> = OnceCell::new();
+        TARGETS.get_or_init(|| {
+            let mut targets = BTreeMap::new();
+            for &(name, triple, arch, os) in &[
+                (
+                    "watchos-arm64_32",
+                    "arm64_32-apple-watchos",
+                    "arm64_32",
+                    Os::Watchos,
+                ),
+                (
+                    "watchos-aarch64",
+                    "aarch64-apple-watchos",
+                    "arm64",
+                    Os::Watchos,
+                ),
+                (
+                    "watchos-sim-aarch64",
+                    "aarch64-apple-watchos-sim",
+                    "arm64",
+                    Os::WatchosSimulator,
+                ),
+                (
+                    "watchos-sim-x86_64",
+                    "x86_64-apple-watchos-sim",
+                    "x86_64",
+                    Os::WatchosSimulator,
+                ),
+            ] {
+                targets.insert(
+                    name,
+                    Target {
+                        triple,
+                        arch,
+                        os,
+                        alias: None,
+                        min_xcode_version: None,
+                    },
+                );
+            }
+            targets
+        })
+    }
+
+    /// Every name `check` and `build` take, which includes the watchOS
+    /// targets.
+    pub fn name_list_with_watchos() -> &'static [&'a str]
+    where
+        Self: 'static,
+    {
+        static INSTANCE: OnceCell<Vec<&str>> = OnceCell::new();
+        INSTANCE.get_or_init(|| {
+            Self::all()
+                .keys()
+                .chain(Self::watchos_all().keys())
+                .copied()
+                .collect()
+        })
+    }
+
     // TODO: Make this cleaner
     pub fn macos() -> Self {
         Self {
             triple: "x86_64-apple-darwin",
             arch: "x86_64",
+            os: Os::Macos,
             alias: None,
             min_xcode_version: None,
         }
     }
 
+    /// What to pass to `xcrun --sdk` to find the SDK to build against.
+    /// `x86_64-apple-ios` is only ever a simulator target, though it
+    /// predates the `-sim` triples and so is listed under iOS.
+    pub fn sdk(&self) -> &'static str {
+        if self.os == Os::Ios && self.arch == "x86_64" {
+            Os::IosSimulator.sdk()
+        } else {
+            self.os.sdk()
+        }
+    }
+
     pub fn is_macos(&self) -> bool {
         *self == Self::macos()
     }
 
+    pub fn is_watchos(&self) -> bool {
+        self.os.is_watchos()
+    }
+
+    /// Installs every target, plus what the watchOS ones need if `watchos`
+    /// is set. Tier 3 targets can't be added with `rustup target add`, so
+    /// for those we get `rust-src` for nightly, which `-Zbuild-std` builds
+    /// the standard library from.
+    pub fn install_with_watchos(watchos: bool) -> Result<(), RustupTargetAddFailed> {
+        Self::install_all()?;
+        if watchos {
+            toolchain::install_rust_src(&toolchain::build_std_toolchain())?;
+        }
+        Ok(())
+    }
+
+    /// The iOS target for `arch`.
     pub fn for_arch(arch: &str) -> Option<&'a Self> {
-        Self::all()
-            .values()
-            .find(|target| target.arch == arch || target.alias == Some(arch))
+        Self::for_os_arch(Os::Ios, arch)
+    }
+
+    /// The target for `arch` on `os`, which is how Xcode's `ARCHS` get
+    /// mapped to triples. The macOS target isn't in `all`, so it's never
+    /// found here. The iOS Simulator falls back on the iOS targets, since
+    /// `x86_64-apple-ios` is what it uses on Intel.
+    pub fn for_os_arch(os: Os, arch: &str) -> Option<&'a Self> {
+        let find = |os: Os| {
+            Self::all()
+                .values()
+                .chain(Self::watchos_all().values())
+                .find(|target| {
+                    target.os == os && (target.arch == arch || target.alias == Some(arch))
+                })
+        };
+        find(os).or_else(|| {
+            if os == Os::IosSimulator {
+                find(Os::Ios)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The target to build for the simulator on a Mac with `arch`.
+    pub fn for_simulator_arch(arch: &str) -> Option<&'a Self> {
+        Self::for_os_arch(Os::IosSimulator, arch)
     }
 
     fn min_xcode_version_satisfied(&self) -> Result<(), VersionCheckError> {
@@ -221,7 +434,14 @@ impl<'a> Target<'a> {
             metadata.ios()
         };
         self.min_xcode_version_satisfied().map(|()| {
+            let toolchain = if self.is_watchos() {
+                Some(toolchain::build_std_toolchain())
+            } else {
+                None
+            };
             CargoCommand::new(subcommand)
+                .with_toolchain(toolchain)
+                .with_build_std(self.is_watchos())
                 .with_package(Some(config.app().name()))
                 .with_manifest_path(Some(config.app().manifest_path()))
                 .with_target(Some(&self.triple))
@@ -257,20 +477,36 @@ impl<'a> Target<'a> {
         force_color: ForceColor,
         profile: Profile,
         env: &Env,
-        cc_env: HashMap<&str, &OsStr>,
-    ) -> Result<(), CompileLibError> {
+        cc_env: &BTreeMap<String, OsString>,
+    ) -> Result<Outcome, CompileLibError> {
         // Force color when running from CLI
         let color = if force_color.yes() { "always" } else { "auto" };
-        self.cargo(config, metadata, "build")
+        let command = self
+            .cargo(config, metadata, "build")
             .map_err(CompileLibError::VersionCheckFailed)?
             .with_verbose(noise_level.pedantic())
             .with_release(profile.release())
+            .with_message_format(Some(diagnostics::message_format(force_color.yes())))
             .into_command_pure(env)
             .with_env_vars(cc_env)
-            .with_args(&["--color", color])
-            .run_and_wait()
-            .map_err(CompileLibError::CargoBuildFailed)?;
-        Ok(())
+            .with_args(&["--color", color]);
+        timings::step(format!("cargo build {}", self.triple), || {
+            let (result, outcome) = diagnostics::run_and_wait(
+                command,
+                Inspector::new(config.app().name(), "staticlib"),
+                None,
+            );
+            match result {
+                Ok(()) => {
+                    outcome.warn();
+                    Ok(outcome)
+                }
+                Err(cause) => Err(CompileLibError::CargoBuildFailed {
+                    cause,
+                    hints: outcome.hints,
+                }),
+            }
+        })
     }
 
     pub fn build(
@@ -285,7 +521,7 @@ impl<'a> Target<'a> {
         let features_val = features
             .map(|f| format!("--features {f}"))
             .unwrap_or_default();
-        bossy::Command::pure("xcodebuild")
+        let mut command = bossy::Command::pure("xcodebuild")
             .with_env_vars(env.explicit_env())
             .with_env_var("FORCE_COLOR", "--force-color")
             .with_env_var("FEATURES", features_val)
@@ -294,12 +530,19 @@ impl<'a> Target<'a> {
             .with_arg("-workspace")
             .with_arg(&config.workspace_path())
             .with_args(&["-configuration", configuration])
-            .with_args(&["-arch", self.arch])
-            .with_arg("-allowProvisioningUpdates")
-            .with_arg("build")
-            .run_and_wait()
-            .map_err(BuildError)?;
-        Ok(())
+            .with_args(&["-arch", self.arch]);
+        // Otherwise arm64 means a device build.
+        if self.os == Os::IosSimulator {
+            command.add_args(&["-sdk", self.os.sdk()]);
+        }
+        timings::step(format!("xcodebuild build {}", self.arch), || {
+            let (result, summary) = sim_archs::run_and_wait(
+                command
+                    .with_arg("-allowProvisioningUpdates")
+                    .with_arg("build"),
+            );
+            result.map_err(|cause| BuildError { cause, summary })
+        })
     }
 
     pub fn archive(
@@ -321,13 +564,12 @@ impl<'a> Target<'a> {
             .map_err(ArchiveError::SetVersionFailed)?;
         }
         let configuration = profile.as_str();
-        let archive_path = config
-            .archive_dir(&configuration_suffix.unwrap_or_default())
-            .join(&config.scheme());
+        let suffix = configuration_suffix.unwrap_or_default();
+        let archive_path = config.archive_dir(&suffix).join(&config.scheme());
         let features_val = features
             .map(|f| format!("--features {f}"))
             .unwrap_or_default();
-        bossy::Command::pure("xcodebuild")
+        let command = bossy::Command::pure("xcodebuild")
             .with_env_vars(env.explicit_env())
             .with_env_var("FEATURES", features_val)
             .with_args(verbosity(noise_level))
@@ -340,9 +582,10 @@ impl<'a> Target<'a> {
             .with_arg("-allowProvisioningUpdates")
             .with_arg("archive")
             .with_arg("-archivePath")
-            .with_arg(&archive_path)
-            .run_and_wait()
+            .with_arg(&archive_path);
+        timings::run_and_wait(format!("xcodebuild archive {}", self.arch), command)
             .map_err(ArchiveError::ArchiveFailed)?;
+        dsym::process(config, &config.xcarchive_path(&suffix)).map_err(ArchiveError::DsymFailed)?;
         Ok(())
     }
 
@@ -354,9 +597,7 @@ impl<'a> Target<'a> {
         configuration_suffix: Option<String>,
     ) -> Result<(), ExportError> {
         // Super fun discrepancy in expectation of `-archivePath` value
-        let archive_path = config
-            .archive_dir(&configuration_suffix.unwrap_or_default())
-            .join(&format!("{}.xcarchive", config.scheme()));
+        let archive_path = config.xcarchive_path(&configuration_suffix.unwrap_or_default());
         bossy::Command::pure("xcodebuild")
             .with_env_vars(env.explicit_env())
             .with_args(verbosity(noise_level))
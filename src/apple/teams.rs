@@ -1,12 +1,23 @@
+use crate::util;
 use once_cell_regex::regex;
 use openssl::{
     error::ErrorStack as OpenSslError,
     nid::Nid,
     x509::{X509NameRef, X509},
 };
-use std::collections::BTreeSet;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use thiserror::Error;
 
+// Long enough to cover one `init`, short enough that a newly installed cert
+// shows up without having to think about it.
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
 pub fn get_pem_list(name_substr: &str) -> bossy::Result<bossy::Output> {
     bossy::Command::impure("security")
         .with_args(&["find-certificate", "-p", "-a", "-c", name_substr])
@@ -66,7 +77,7 @@ pub enum FromX509Error {
     },
 }
 
-#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct Team {
     pub name: String,
     pub id: String,
@@ -108,7 +119,7 @@ impl Team {
     }
 }
 
-pub fn find_development_teams() -> Result<Vec<Team>, Error> {
+fn find_teams_from_certs() -> Result<BTreeSet<Team>, Error> {
     let certs = {
         let new = get_pem_list_new_name_scheme().map_err(Error::SecurityCommandFailed)?;
         let mut certs = X509::stack_from_pem(new.stdout()).map_err(Error::X509ParseFailed)?;
@@ -124,8 +135,142 @@ pub fn find_development_teams() -> Result<Vec<Team>, Error> {
                 err
             })
         })
-        // Silly way to sort this and ensure no dupes
-        .collect::<BTreeSet<_>>()
-        .into_iter()
         .collect())
 }
+
+/// Parses the output of `security find-identity -v -p codesigning`, which
+/// only has the certificate subject to go on, i.e.
+/// `  1) 0123...CDEF "Apple Development: Jane Doe (ABCDE12345)"`.
+fn parse_identities(output: &str) -> BTreeSet<Team> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let subject = regex!(r#"^\s*\d+\)\s+[0-9A-F]{40}\s+"(.+)"\s*$"#).captures(line)?;
+            let caps = regex!(r"^(?:Apple|iPhone|Mac) Develop\w+: (.+) \(([A-Z0-9]+)\)$")
+                .captures(&subject[1])?;
+            Some(Team {
+                name: caps[1].to_owned(),
+                id: caps[2].to_owned(),
+            })
+        })
+        .collect()
+}
+
+fn find_teams_from_identities() -> Result<BTreeSet<Team>, Error> {
+    bossy::Command::impure("security")
+        .with_args(&["find-identity", "-v", "-p", "codesigning"])
+        .run_and_wait_for_str(parse_identities)
+        .map_err(Error::SecurityCommandFailed)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Cache {
+    // Seconds since the Unix epoch.
+    created: u64,
+    teams: Vec<Team>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+fn cache_path() -> Option<PathBuf> {
+    util::install_dir()
+        .map(|dir| dir.join("cache/teams.json"))
+        .ok()
+}
+
+fn read_cache() -> Option<Vec<Team>> {
+    let path = cache_path()?;
+    let cache = fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Cache>(&bytes).ok())?;
+    let age = now().saturating_sub(cache.created);
+    if age <= CACHE_TTL.as_secs() && !cache.teams.is_empty() {
+        log::info!(
+            "using cached development teams from {:?} ({}s old)",
+            path,
+            age
+        );
+        Some(cache.teams)
+    } else {
+        None
+    }
+}
+
+fn write_cache(teams: &[Team]) {
+    if let Some(path) = cache_path() {
+        let cache = Cache {
+            created: now(),
+            teams: teams.to_vec(),
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                fs::write(
+                    &path,
+                    serde_json::to_vec(&cache).expect("developer error: cache didn't serialize"),
+                )
+            });
+        if let Err(err) = result {
+            log::warn!("failed to cache development teams to {:?}: {}", path, err);
+        }
+    }
+}
+
+/// Finds teams both from installed certs and from valid signing identities,
+/// since the former comes up empty on some setups (i.e. with only the
+/// Command Line Tools installed). Results are cached for a few minutes, since
+/// this is slow.
+pub fn find_development_teams() -> Result<Vec<Team>, Error> {
+    if let Some(teams) = read_cache() {
+        return Ok(teams);
+    }
+    let teams = match (find_teams_from_certs(), find_teams_from_identities()) {
+        (Ok(certs), Ok(identities)) => certs.into_iter().chain(identities).collect(),
+        (Ok(teams), Err(err)) | (Err(err), Ok(teams)) => {
+            log::warn!("only found some development teams: {}", err);
+            teams.into_iter().collect::<Vec<_>>()
+        }
+        (Err(err), Err(_)) => return Err(err),
+    };
+    // Names can differ between the two sources, so dedup on ID, preferring
+    // the first (cert-derived names come from the organization field, which
+    // is nicer).
+    let mut seen = BTreeSet::new();
+    let teams = teams
+        .into_iter()
+        .filter(|team| seen.insert(team.id.clone()))
+        .collect::<Vec<_>>();
+    // Coming up empty usually means the user's about to go sign in, so
+    // caching that would hide the teams they sign in with.
+    if !teams.is_empty() {
+        write_cache(&teams);
+    }
+    Ok(teams)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_identities() {
+        let output = r#"  1) 0123456789ABCDEF0123456789ABCDEF01234567 "Apple Development: Jane Doe (ABCDE12345)"
+  2) 76543210FEDCBA9876543210FEDCBA9876543210 "Developer ID Application: Brainium Studios LLC (ZYXWV98765)"
+  3) 1111111111111111111111111111111111111111 "iPhone Developer: Jane Doe (ABCDE12345)"
+     3 valid identities found
+"#;
+        assert_eq!(
+            parse_identities(output).into_iter().collect::<Vec<_>>(),
+            vec![Team {
+                name: "Jane Doe".to_owned(),
+                id: "ABCDE12345".to_owned(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! `cargo apple validate`, which catches what would make an archive fail
+//! without building anything first.
+
+use super::{
+    archive_versions::ArchiveVersions,
+    config::{Config, Metadata},
+    entitlements, schemes, sdk,
+    version_number::VersionNumber,
+};
+use crate::{doctor::Section, opts::Profile, util::VersionDouble};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+// Xcode 14 and later only build down to this, and warn about anything older.
+const MIN_DEPLOYMENT_TARGET: VersionDouble = VersionDouble::new(11, 0);
+
+/// How a single check went. Only failures block archiving.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Check {
+    Passed(String),
+    Warned(String),
+    Failed(String),
+}
+
+impl Check {
+    fn add_to(self, section: Section) -> Section {
+        match self {
+            Self::Passed(msg) => section.with_victory(msg),
+            Self::Warned(msg) => section.with_warning(msg),
+            Self::Failed(msg) => section.with_failure(msg),
+        }
+    }
+}
+
+pub fn development_team(team: Option<&str>) -> Check {
+    match team.map(str::trim) {
+        Some(team) if !team.is_empty() => Check::Passed(format!("Development team: {}", team)),
+        _ => Check::Failed(format!(
+            "No development team is set; add `development-team` to the `[apple]` section of {}",
+            crate::config::file_name()
+        )),
+    }
+}
+
+pub fn scheme(scheme: &str, xcodeproj: &Path, available: &[String]) -> Check {
+    if available.iter().any(|available| available == scheme) {
+        Check::Passed(format!("Scheme {:?} exists", scheme))
+    } else if available.is_empty() {
+        Check::Failed(format!(
+            "{:?} doesn't have any shared schemes; run `cargo mobile init` to regenerate it",
+            xcodeproj
+        ))
+    } else {
+        Check::Failed(format!(
+            "Scheme {:?} isn't in {:?}, which only has {}; run `cargo mobile init` to regenerate it",
+            scheme,
+            xcodeproj,
+            available.join(", ")
+        ))
+    }
+}
+
+/// Checks `target` against the newest installed `iphoneos` SDK, if we
+/// found one.
+pub fn deployment_target(target: VersionDouble, sdk: Option<VersionDouble>) -> Check {
+    match sdk {
+        Some(sdk) if (sdk.major, sdk.minor) < (target.major, target.minor) => {
+            Check::Failed(format!(
+                "Deployment target iOS {} is newer than the iOS {} SDK; update Xcode or lower the deployment target",
+                target, sdk
+            ))
+        }
+        _ if target < MIN_DEPLOYMENT_TARGET => Check::Warned(format!(
+            "Deployment target iOS {} is older than current Xcode supports, so it'll build for iOS {} instead",
+            target, MIN_DEPLOYMENT_TARGET
+        )),
+        Some(sdk) => Check::Passed(format!(
+            "Deployment target iOS {} (iOS {} SDK)",
+            target, sdk
+        )),
+        None => Check::Warned(format!(
+            "Deployment target is iOS {}, but no `iphoneos` SDK was found to check it against",
+            target
+        )),
+    }
+}
+
+/// Since App Store Connect rejects bundle versions that don't increase.
+pub fn bundle_version(
+    version: &VersionNumber,
+    scheme: &str,
+    previous: Option<&VersionNumber>,
+) -> Check {
+    match previous {
+        Some(previous) if !version.follows(previous) => Check::Failed(format!(
+            "Bundle version {} isn't greater than {}, which was last archived for scheme {:?}; bump it, or archive with `--auto-increment`",
+            version, previous, scheme
+        )),
+        Some(previous) => Check::Passed(format!(
+            "Bundle version {} follows {}",
+            version, previous
+        )),
+        None => Check::Passed(format!(
+            "Bundle version {} (nothing archived for scheme {:?} yet)",
+            version, scheme
+        )),
+    }
+}
+
+/// Not having a profile isn't blocking, since Xcode can make one while
+/// archiving.
+pub fn provisioning_profile(app_id: &str, profiles: &[(PathBuf, String)]) -> Check {
+    match entitlements::best_profile(profiles, app_id) {
+        Some((path, true)) => Check::Passed(format!(
+            "Provisioning profile {:?} is for {}",
+            path, app_id
+        )),
+        Some((path, false)) => Check::Passed(format!(
+            "Wildcard provisioning profile {:?} covers {}",
+            path, app_id
+        )),
+        None => Check::Warned(format!(
+            "No installed provisioning profile covers {}; Xcode will have to make one while archiving",
+            app_id
+        )),
+    }
+}
+
+pub fn tool(name: &str, present: bossy::Result<bool>) -> Check {
+    match present {
+        Ok(true) => Check::Passed(format!("`{}` is installed", name)),
+        Ok(false) => Check::Failed(format!(
+            "`{}` isn't installed; run `cargo mobile doctor` for details",
+            name
+        )),
+        Err(err) => Check::Failed(format!("Failed to check for `{}`: {}", name, err)),
+    }
+}
+
+/// Runs every check, in the order they'd fail during an archive.
+pub fn run(
+    config: &Config,
+    metadata: &Metadata,
+    profile: Profile,
+    build_number: Option<u32>,
+) -> Section {
+    let mut checks = vec![development_team(config.development_team())];
+
+    let scheme_name = config.scheme();
+    checks.push(match config.generated_scheme(metadata) {
+        Ok(scheme_name) => {
+            let xcodeproj = config.xcodeproj_path();
+            match schemes::shared(&xcodeproj) {
+                Ok(available) => scheme(&scheme_name, &xcodeproj, &available),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    scheme(&scheme_name, &xcodeproj, &[])
+                }
+                Err(err) => Check::Failed(format!(
+                    "Failed to read schemes from {:?}: {}",
+                    xcodeproj, err
+                )),
+            }
+        }
+        Err(_) => Check::Failed(format!(
+            "The {:?} scheme isn't generated, since `generate-scheme` is set to `false` in `[package.metadata.cargo-apple.ios]`",
+            scheme_name
+        )),
+    });
+
+    checks.push(match metadata.ios().deployment_target() {
+        Ok(target) => {
+            let sdk = sdk::list_sdks()
+                .map_err(|err| log::warn!("failed to list SDKs: {}", err))
+                .ok()
+                .and_then(|sdks| sdk::latest(&sdks, "iphoneos"));
+            deployment_target(target.unwrap_or_else(|| config.ios_version()), sdk)
+        }
+        Err(err) => Check::Failed(format!(
+            "`package.metadata.cargo-apple.ios.deployment-target` is invalid: {}",
+            err
+        )),
+    });
+
+    let mut version = config.bundle_version().clone();
+    if let Some(build_number) = build_number {
+        version.push_extra(build_number);
+    }
+    checks.push(
+        match ArchiveVersions::load(&config.export_dir())
+            .and_then(|versions| versions.last(&scheme_name))
+        {
+            Ok(previous) => bundle_version(&version, &scheme_name, previous.as_ref()),
+            Err(err) => Check::Failed(err.to_string()),
+        },
+    );
+
+    if let Some(team) = config.development_team() {
+        let app_id = format!("{}.{}", team, config.bundle_identifier(profile));
+        checks.push(match entitlements::installed_app_ids() {
+            Ok(profiles) => provisioning_profile(&app_id, &profiles),
+            Err(err) => Check::Warned(format!("Failed to check provisioning profiles: {}", err)),
+        });
+    }
+
+    let mut tools = vec!["xcodebuild", "xcodegen"];
+    if metadata.ios().pods().map_or(false, |pods| !pods.is_empty()) {
+        tools.push("pod");
+    }
+    checks.extend(
+        tools
+            .into_iter()
+            .map(|name| tool(name, crate::util::command_present(name))),
+    );
+
+    checks
+        .into_iter()
+        .fold(Section::new("Archive validation"), |section, check| {
+            check.add_to(section)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+    use std::fs;
+
+    fn is_failure(check: &Check) -> bool {
+        matches!(check, Check::Failed(_))
+    }
+
+    #[rstest(
+        team,
+        passes,
+        case(Some("ABCDE12345"), true),
+        case(Some("  "), false),
+        case(Some(""), false),
+        case(None, false)
+    )]
+    fn checks_development_team(team: Option<&str>, passes: bool) {
+        assert_eq!(is_failure(&development_team(team)), !passes);
+    }
+
+    #[test]
+    fn finds_shared_schemes() {
+        let dir = TempDirGuard::new().unwrap();
+        let xcodeproj = dir.join("game.xcodeproj");
+        let schemes_dir = xcodeproj.join("xcshareddata/xcschemes");
+        fs::create_dir_all(&schemes_dir).unwrap();
+        for file in &["game_macOS.xcscheme", "game_iOS.xcscheme", "notes.txt"] {
+            fs::write(schemes_dir.join(file), "").unwrap();
+        }
+        let available = schemes::shared(&xcodeproj).unwrap();
+        assert_eq!(available, vec!["game_iOS", "game_macOS"]);
+        assert!(!is_failure(&scheme("game_iOS", &xcodeproj, &available)));
+        assert!(is_failure(&scheme("other_iOS", &xcodeproj, &available)));
+        assert!(is_failure(&scheme("game_iOS", &xcodeproj, &[])));
+    }
+
+    #[rstest(
+        target,
+        sdk,
+        expected,
+        case((13, 0), Some((17, 0)), "passed"),
+        case((17, 0), Some((17, 0)), "passed"),
+        case((17, 2), Some((17, 0)), "failed"),
+        case((9, 0), Some((17, 0)), "warned"),
+        case((9, 0), None, "warned"),
+        case((13, 0), None, "warned")
+    )]
+    fn checks_deployment_target(target: (u32, u32), sdk: Option<(u32, u32)>, expected: &str) {
+        let version = |(major, minor)| VersionDouble::new(major, minor);
+        let check = deployment_target(version(target), sdk.map(version));
+        let actual = match check {
+            Check::Passed(_) => "passed",
+            Check::Warned(_) => "warned",
+            Check::Failed(_) => "failed",
+        };
+        assert_eq!(actual, expected, "{:?}", check);
+    }
+
+    #[rstest(
+        version,
+        previous,
+        passes,
+        case("1.0.1", Some("1.0.0"), true),
+        case("1.0.0", Some("1.0.0"), false),
+        case("1.0.0", Some("1.1.0"), false),
+        case("1.0.0", None, true)
+    )]
+    fn checks_bundle_version(version: &str, previous: Option<&str>, passes: bool) {
+        let version = VersionNumber::from_str(version).unwrap();
+        let previous = previous.map(|previous| VersionNumber::from_str(previous).unwrap());
+        assert_eq!(
+            is_failure(&bundle_version(&version, "game_iOS", previous.as_ref())),
+            !passes
+        );
+    }
+
+    #[test]
+    fn missing_profile_only_warns() {
+        let profiles = vec![(
+            PathBuf::from("other.mobileprovision"),
+            "FGHIJ67890.*".to_owned(),
+        )];
+        assert!(matches!(
+            provisioning_profile("ABCDE12345.com.example.app", &profiles),
+            Check::Warned(_)
+        ));
+    }
+}
@@ -86,4 +86,99 @@ impl VersionNumber {
     pub fn push_extra(&mut self, number: u32) {
         self.extra.get_or_insert_with(Default::default).push(number);
     }
+
+    /// Whether this can be archived after `previous`. This uses our own
+    /// ordering, in which any extra components make a version greater than
+    /// the same triple without them, even if they're all zero.
+    pub fn follows(&self, previous: &Self) -> bool {
+        self > previous
+    }
+
+    /// Increments the last extra component, adding one if there are none.
+    /// At `u32::MAX` this saturates, which `follows` will then catch.
+    pub fn bumped(&self) -> Self {
+        let mut bumped = self.clone();
+        match bumped.extra.as_mut().and_then(|extra| extra.last_mut()) {
+            Some(last) => *last = last.saturating_add(1),
+            None => bumped.push_extra(1),
+        }
+        bumped
+    }
+
+    /// The version to archive with `--auto-increment`: this one if it already
+    /// follows `previous`, since it was bumped by hand, or otherwise
+    /// `previous` bumped.
+    pub fn next_after(&self, previous: &Self) -> Self {
+        if self.follows(previous) {
+            self.clone()
+        } else {
+            previous.bumped()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn v(s: &str) -> VersionNumber {
+        VersionNumber::from_str(s).unwrap()
+    }
+
+    #[rstest(
+        version,
+        previous,
+        follows,
+        case("1.0.1", "1.0.0", true),
+        case("1.0.0", "1.0.0", false),
+        case("1.0.0", "1.0.1", false),
+        case("2.0.0", "1.9.9.9", true),
+        case("1.0.0.1", "1.0.0", true),
+        // Extra components always win over none, even zeroes.
+        case("1.0.0.0", "1.0.0", true),
+        case("1.0.0", "1.0.0.0", false),
+        case("1.0.0.2", "1.0.0.10", false),
+        case("1.0.0.10", "1.0.0.9", true),
+        // Longer wins when one is a prefix of the other.
+        case("1.0.0.1.0", "1.0.0.1", true),
+        case("1.0.0.1", "1.0.0.1.0", false),
+        case("1.0.0.2", "1.0.0.1.9", true)
+    )]
+    fn follows_uses_our_ordering(version: &str, previous: &str, follows: bool) {
+        assert_eq!(v(version).follows(&v(previous)), follows);
+    }
+
+    #[rstest(
+        version,
+        bumped,
+        case("1.0.0", "1.0.0.1"),
+        case("1.0.0.0", "1.0.0.1"),
+        case("1.0.0.9", "1.0.0.10"),
+        case("1.2.3.4.5", "1.2.3.4.6")
+    )]
+    fn bumps_last_extra(version: &str, bumped: &str) {
+        let bumped_version = v(version).bumped();
+        assert_eq!(bumped_version, v(bumped));
+        assert!(bumped_version.follows(&v(version)));
+    }
+
+    #[test]
+    fn bump_saturates() {
+        let max = v(&format!("1.0.0.{}", u32::MAX));
+        assert!(!max.bumped().follows(&max));
+    }
+
+    #[rstest(
+        version,
+        previous,
+        next,
+        case("1.0.0", "1.0.0.4", "1.0.0.5"),
+        case("1.0.0", "1.0.0", "1.0.0.1"),
+        case("1.1.0", "1.0.0.4", "1.1.0"),
+        case("1.0.0.7", "1.0.0.4", "1.0.0.7")
+    )]
+    fn next_after_respects_manual_bumps(version: &str, previous: &str, next: &str) {
+        assert_eq!(v(version).next_after(&v(previous)), v(next));
+    }
 }
@@ -0,0 +1,318 @@
+use crate::{
+    config::app::{App, AssetDir, AssetPlatform, AssetStrategy},
+    util::{self, ln},
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to symlink asset dir: {0}")]
+    SymlinkFailed(#[from] ln::Error),
+    #[error("Failed to copy asset dir: {0}")]
+    CopyFailed(#[from] util::CopyDirError),
+    #[error("Failed to walk asset dir {src:?}: {source}")]
+    WalkFailed {
+        src: PathBuf,
+        source: walkdir::Error,
+    },
+    #[error("Asset dirs {first:?} and {second:?} both have {path:?}, so they can't be merged; rename one, or give the dirs different `dest`s")]
+    Conflict {
+        path: PathBuf,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    #[error("Asset dir {asset_dir:?} doesn't exist; create it, or fix its path in `{}.asset-dir` or `{}.asset-dirs`", crate::config::app::KEY, crate::config::app::KEY)]
+    CustomDirMissing { asset_dir: PathBuf },
+    #[error(
+        "Asset dir {asset_dir:?} doesn't exist; re-running `cargo mobile init` will create it"
+    )]
+    DefaultDirMissing { asset_dir: PathBuf },
+}
+
+// Symlinking a missing dir would leave the link dangling, and on Windows, we
+// wouldn't even know to make it a directory link.
+fn ensure_exists(asset_dir: &Path, explicit: bool) -> Result<(), Error> {
+    if asset_dir.is_dir() {
+        Ok(())
+    } else if explicit {
+        Err(Error::CustomDirMissing {
+            asset_dir: asset_dir.to_owned(),
+        })
+    } else {
+        Err(Error::DefaultDirMissing {
+            asset_dir: asset_dir.to_owned(),
+        })
+    }
+}
+
+/// Asset dirs that get installed under the same name, and so get merged.
+#[derive(Debug)]
+pub struct Group<'a> {
+    pub dest: &'a str,
+    pub sources: Vec<&'a AssetDir>,
+}
+
+/// The asset dirs to include for `platform`, grouped by where they get
+/// installed. Groups and their sources keep the order they were listed in.
+pub fn groups(dirs: &[AssetDir], platform: AssetPlatform) -> Vec<Group<'_>> {
+    let mut groups: Vec<Group<'_>> = Vec::new();
+    for dir in dirs.iter().filter(|dir| dir.includes(platform)) {
+        if let Some(group) = groups.iter_mut().find(|group| group.dest == dir.dest()) {
+            group.sources.push(dir);
+        } else {
+            groups.push(Group {
+                dest: dir.dest(),
+                sources: vec![dir],
+            });
+        }
+    }
+    groups
+}
+
+/// Since merging two files into the same place would silently drop one of
+/// them, every file has to come from exactly one source.
+fn check_conflicts(sources: &[PathBuf]) -> Result<(), Error> {
+    let mut seen = BTreeMap::<PathBuf, &Path>::new();
+    for src in sources {
+        for entry in walkdir::WalkDir::new(src).follow_links(true) {
+            let entry = entry.map_err(|source| Error::WalkFailed {
+                src: src.clone(),
+                source,
+            })?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let rel = entry
+                .path()
+                .strip_prefix(src)
+                .expect("developer error: walked path wasn't prefixed by its root")
+                .to_owned();
+            if let Some(first) = seen.get(&rel) {
+                return Err(Error::Conflict {
+                    path: rel,
+                    first: first.to_path_buf(),
+                    second: src.clone(),
+                });
+            }
+            seen.insert(rel, src);
+        }
+    }
+    Ok(())
+}
+
+fn copy(sources: &[PathBuf], dest: &Path) -> Result<(), Error> {
+    if sources.len() > 1 {
+        check_conflicts(sources)?;
+    }
+    let stats = util::mirror_dirs_if_changed(sources, dest)?;
+    log::info!(
+        "copied {} asset(s) from {:?} into {:?} ({} unchanged, {} removed)",
+        stats.copied,
+        sources,
+        dest,
+        stats.unchanged,
+        stats.removed
+    );
+    Ok(())
+}
+
+fn symlink(src: &Path, dest_parent: &Path, dest: &str) -> Result<(), ln::Error> {
+    if src.file_name().and_then(|name| name.to_str()) == Some(dest) {
+        ln::force_symlink_relative(src, dest_parent, ln::TargetStyle::Directory)
+    } else {
+        ln::force_symlink(
+            util::relativize_path(src, dest_parent),
+            dest_parent.join(dest),
+            ln::TargetStyle::Directory,
+        )
+    }
+}
+
+/// Puts the asset dirs for `platform` into `dest_parent` according to the
+/// asset strategy. Dirs that share a `dest` are always copied, since they
+/// can't share a symlink.
+pub fn install(app: &App, platform: AssetPlatform, dest_parent: &Path) -> Result<(), Error> {
+    if matches!(app.asset_strategy(), AssetStrategy::None) {
+        log::info!("`asset-strategy` is `none`; not installing asset dirs");
+        return Ok(());
+    }
+    for group in groups(app.asset_dirs(), platform) {
+        let sources = group
+            .sources
+            .iter()
+            .map(|dir| app.root_dir().join(dir.path()))
+            .collect::<Vec<_>>();
+        for src in &sources {
+            ensure_exists(src, app.asset_dir_explicit())?;
+        }
+        let dest = dest_parent.join(group.dest);
+        match (app.asset_strategy(), sources.as_slice()) {
+            (AssetStrategy::Symlink, [src]) => match symlink(src, dest_parent, group.dest) {
+                // Symlinking needs developer mode (or admin) on Windows, which
+                // plenty of people don't have.
+                Err(err) if cfg!(windows) => {
+                    log::warn!(
+                        "symlinking asset dir failed, so it'll be copied instead (set `{}.asset-strategy = \"copy\"` to silence this): {}",
+                        crate::config::app::KEY,
+                        err
+                    );
+                    copy(&sources, &dest)?
+                }
+                result => result?,
+            },
+            _ => copy(&sources, &dest)?,
+        }
+    }
+    Ok(())
+}
+
+/// Brings copied asset dirs up to date before a build. This includes merged
+/// asset dirs, and ones that were copied as a fallback for a failed symlink.
+pub fn refresh(app: &App, platform: AssetPlatform, dest_parent: &Path) -> Result<(), Error> {
+    for group in groups(app.asset_dirs(), platform) {
+        let dest = dest_parent.join(group.dest);
+        let copied = match app.asset_strategy() {
+            AssetStrategy::Copy => true,
+            AssetStrategy::Symlink if group.sources.len() > 1 => true,
+            AssetStrategy::Symlink if cfg!(windows) => std::fs::symlink_metadata(&dest)
+                .map(|meta| meta.is_dir())
+                .unwrap_or(false),
+            _ => false,
+        };
+        if copied {
+            let sources = group
+                .sources
+                .iter()
+                .map(|dir| app.root_dir().join(dir.path()))
+                .collect::<Vec<_>>();
+            copy(&sources, &dest)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn groups_by_dest_per_platform() {
+        let dirs = vec![
+            AssetDir::new("assets", AssetPlatform::ALL, "assets"),
+            AssetDir::new(
+                "assets-android",
+                &[AssetPlatform::Android],
+                "assets-android",
+            ),
+            AssetDir::new("shared/assets", &[AssetPlatform::Android], "assets"),
+        ];
+        let summary = |platform| {
+            groups(&dirs, platform)
+                .into_iter()
+                .map(|group| {
+                    let sources = group
+                        .sources
+                        .iter()
+                        .map(|dir| dir.path().to_str().unwrap())
+                        .collect::<Vec<_>>();
+                    (group.dest, sources)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(AssetPlatform::Android),
+            vec![
+                ("assets", vec!["assets", "shared/assets"]),
+                ("assets-android", vec!["assets-android"]),
+            ]
+        );
+        assert_eq!(
+            summary(AssetPlatform::Ios),
+            vec![("assets", vec!["assets"])]
+        );
+    }
+
+    #[test]
+    fn merges_disjoint_dirs() {
+        let root = crate::util::TempDirGuard::new().unwrap();
+        let (common, extra) = (root.join("common"), root.join("extra"));
+        write(&common.join("fonts/a.ttf"), "a");
+        write(&extra.join("fonts/b.ttf"), "b");
+        let dest = root.join("project/assets");
+        copy(&[common, extra], &dest).unwrap();
+        assert!(dest.join("fonts/a.ttf").is_file());
+        assert!(dest.join("fonts/b.ttf").is_file());
+    }
+
+    #[test]
+    fn merges_prune_deleted_files() {
+        let root = crate::util::TempDirGuard::new().unwrap();
+        let (common, extra) = (root.join("common"), root.join("extra"));
+        write(&common.join("fonts/a.ttf"), "a");
+        write(&extra.join("fonts/b.ttf"), "b");
+        let dest = root.join("project/assets");
+        let sources = [common, extra.clone()];
+        copy(&sources, &dest).unwrap();
+        fs::remove_file(extra.join("fonts/b.ttf")).unwrap();
+        copy(&sources, &dest).unwrap();
+        assert!(dest.join("fonts/a.ttf").is_file());
+        assert!(!dest.join("fonts/b.ttf").exists());
+    }
+
+    #[test]
+    fn conflicting_files_are_reported() {
+        let root = crate::util::TempDirGuard::new().unwrap();
+        let (common, extra) = (root.join("common"), root.join("extra"));
+        write(&common.join("config.json"), "{}");
+        write(&extra.join("config.json"), "[]");
+        let dest = root.join("project/assets");
+        let err = copy(&[common.clone(), extra.clone()], &dest).unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::Conflict { path, first, second }
+                if path == Path::new("config.json") && first == &common && second == &extra
+        ));
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn missing_custom_dir_is_named() {
+        let root = crate::util::TempDirGuard::new().unwrap();
+        let asset_dir = root.join("nope");
+        let err = ensure_exists(&asset_dir, true).unwrap_err();
+        assert!(matches!(&err, Error::CustomDirMissing { asset_dir: path } if path == &asset_dir));
+        assert!(err.to_string().contains("nope"));
+        assert!(matches!(
+            ensure_exists(&asset_dir, false),
+            Err(Error::DefaultDirMissing { .. })
+        ));
+        assert!(ensure_exists(&root, true).is_ok());
+    }
+
+    #[test]
+    fn missing_custom_dir_fails_install() {
+        let fixture = crate::config::fixture::app("art", "asset-dir = \"art\"\n", "");
+        let app = fixture.config.app();
+        assert!(app.asset_dir_explicit());
+        let dest = fixture.root.join("project");
+        fs::create_dir(&dest).unwrap();
+        let err = install(app, AssetPlatform::Android, &dest).unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::CustomDirMissing { asset_dir } if asset_dir == &fixture.root.join("art")
+        ));
+        // Neither a dangling link nor an empty dir standing in for it.
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 0);
+        assert!(!fixture.root.join("art").exists());
+    }
+}
@@ -1,14 +1,15 @@
 #![forbid(unsafe_code)]
 
 use cargo_mobile::{
-    doctor, init, opts, update,
+    ci, config, doctor, info, init, install_templates, licenses, opts, uninstall, update,
     util::{
         self,
         cli::{
             self, Exec, GlobalFlags, Report, Reportable, TextWrapper, VERSION_LONG, VERSION_SHORT,
         },
+        events,
     },
-    NAME,
+    verify_templates, NAME,
 };
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -45,8 +46,24 @@ pub enum Command {
             parse(from_flag = opts::OpenInEditor::from_bool),
         )]
         open_in_editor: opts::OpenInEditor,
+        #[structopt(flatten)]
+        conflicts: cli::Conflicts,
         #[structopt(long = "submodule-commit", help = "Template pack commit to checkout")]
         submodule_commit: Option<String>,
+        #[structopt(
+            long = "event-stream",
+            value_name = "path",
+            help = "Report progress as JSON lines written to a file or pipe, and read prompt responses as JSON from stdin"
+        )]
+        event_stream: Option<PathBuf>,
+        #[structopt(
+            long = "adopt",
+            help = "Set up the Rust side for the existing projects in `existing-project-dir` instead of generating any",
+            parse(from_flag = opts::Adopt::from_bool),
+        )]
+        adopt: opts::Adopt,
+        #[structopt(flatten)]
+        guided: cli::Guided,
     },
     #[structopt(name = "new", about = "Creates a new project in a new directory")]
     New {
@@ -60,8 +77,18 @@ pub enum Command {
             parse(from_flag = opts::OpenInEditor::from_bool),
         )]
         open_in_editor: opts::OpenInEditor,
+        #[structopt(flatten)]
+        conflicts: cli::Conflicts,
         #[structopt(long = "submodule-commit", help = "Template pack commit to checkout")]
         submodule_commit: Option<String>,
+        #[structopt(
+            long = "event-stream",
+            value_name = "path",
+            help = "Report progress as JSON lines written to a file or pipe, and read prompt responses as JSON from stdin"
+        )]
+        event_stream: Option<PathBuf>,
+        #[structopt(flatten)]
+        guided: cli::Guided,
         #[structopt(
             name = "DIRECTORY",
             help = "New directory to create project in",
@@ -70,6 +97,30 @@ pub enum Command {
         )]
         directory: PathBuf,
     },
+    #[structopt(
+        name = "upgrade-project",
+        about = "Regenerates your project, merging template changes into files you edited"
+    )]
+    UpgradeProject {
+        #[structopt(flatten)]
+        skip_dev_tools: cli::SkipDevTools,
+        #[structopt(flatten)]
+        reinstall_deps: cli::ReinstallDeps,
+    },
+    #[structopt(
+        name = "uninstall",
+        alias = "deinit",
+        about = "Removes the generated projects and what `init` added to your cargo config, after confirming"
+    )]
+    Uninstall {
+        #[structopt(long = "dry-run", help = "Only list what would be removed")]
+        dry_run: bool,
+        #[structopt(
+            long = "remove-config",
+            help = "Delete `mobile.toml` too, without asking"
+        )]
+        remove_config: bool,
+    },
     #[structopt(name = "open", about = "Open project in default code editor")]
     Open,
     #[structopt(name = "update", about = "Update `cargo-mobile`")]
@@ -95,7 +146,84 @@ pub enum Command {
         name = "doctor",
         about = "Perform a check-up on your installation and environment"
     )]
-    Doctor,
+    Doctor {
+        #[structopt(
+            long = "section",
+            value_name = "slug",
+            help = "Only check this section (repeatable); see `--list-sections`",
+            number_of_values = 1
+        )]
+        sections: Vec<String>,
+        #[structopt(
+            long = "quiet",
+            help = "Only print warnings and failures, and exit with 1 for warnings or 2 for failures"
+        )]
+        quiet: bool,
+        #[structopt(long = "list-sections", help = "List section slugs for `--section`")]
+        list_sections: bool,
+    },
+    #[structopt(
+        name = "info",
+        about = "Prints machine-readable project info as JSON, without generating anything"
+    )]
+    Info,
+    #[structopt(
+        name = "migrate-config",
+        about = "Rewrites your config file in the newest format, after showing what will change"
+    )]
+    MigrateConfig,
+    #[structopt(
+        name = "version",
+        about = "Prints or bumps `app.version`, the version both platforms default to"
+    )]
+    Version {
+        #[structopt(
+            long = "bump",
+            help = "Part of the version to bump, resetting the parts after it",
+            possible_values = &opts::VersionPart::variants(),
+            case_insensitive = true,
+        )]
+        bump: Option<opts::VersionPart>,
+    },
+    #[structopt(
+        name = "ci-template",
+        about = "Generates a CI pipeline that builds your project"
+    )]
+    CiTemplate {
+        #[structopt(
+            long = "provider",
+            help = "CI provider to generate a pipeline for",
+            possible_values = &opts::CiProvider::variants(),
+            case_insensitive = true,
+        )]
+        provider: opts::CiProvider,
+        #[structopt(long = "force", help = "Overwrite an existing pipeline")]
+        force: bool,
+    },
+    #[structopt(
+        name = "licenses",
+        about = "Writes an attribution report of your dependencies' licenses into your asset dir"
+    )]
+    Licenses,
+    #[structopt(
+        name = "verify-templates",
+        about = "Renders your installed template packs with a few sample configs and checks the output"
+    )]
+    VerifyTemplates,
+    #[structopt(name = "self", about = "Manages this install of cargo-mobile")]
+    Installation {
+        #[structopt(subcommand)]
+        command: InstallationCommand,
+    },
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum InstallationCommand {
+    #[structopt(
+        name = "install-templates",
+        about = "Reinstalls the template packs this binary was built with, replacing any installed ones"
+    )]
+    InstallTemplates,
 }
 
 #[derive(Debug)]
@@ -105,16 +233,29 @@ pub enum Error {
         path: PathBuf,
         source: std::io::Error,
     },
+    EventStreamFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
     DirChangeFailed {
         path: PathBuf,
         source: std::io::Error,
     },
     OpenFailed(util::OpenInEditorError),
+    UninstallFailed(uninstall::Error),
     UpdateFailed(update::Error),
     #[cfg(target_os = "macos")]
     AppleFailed(cargo_mobile::apple::cli::Error),
     AndroidFailed(cargo_mobile::android::cli::Error),
-    DoctorFailed(doctor::Unrecoverable),
+    DoctorFailed(doctor::Error),
+    DoctorFoundProblems(doctor::Status),
+    InfoFailed(info::Error),
+    MigrateConfigFailed(config::MigrateFileError),
+    VersionFailed(config::version::Error),
+    CiTemplateFailed(ci::Error),
+    LicensesFailed(licenses::Error),
+    VerifyTemplatesFailed(verify_templates::Error),
+    InstallTemplatesFailed(cargo_mobile::InstallTemplatesError),
 }
 
 impl Reportable for Error {
@@ -124,6 +265,9 @@ impl Reportable for Error {
             Self::DirCreationFailed { path, source } => {
                 Report::error(format!("Failed to create directory {:?}", path), source)
             }
+            Self::EventStreamFailed { path, source } => {
+                Report::error(format!("Failed to open event stream at {:?}", path), source)
+            }
             Self::DirChangeFailed { path, source } => Report::error(
                 format!("Failed to change current directory {:?}", path),
                 source,
@@ -131,20 +275,45 @@ impl Reportable for Error {
             Self::OpenFailed(err) => {
                 Report::error("Failed to open project in default code editor", err)
             }
+            Self::UninstallFailed(err) => err.report(),
             Self::UpdateFailed(err) => Report::error("Failed to update `cargo-mobile`", err),
             #[cfg(target_os = "macos")]
             Self::AppleFailed(err) => err.report(),
             Self::AndroidFailed(err) => err.report(),
-            Self::DoctorFailed(err) => Report::error("Failed to run doctor", err),
+            Self::DoctorFailed(err) => err.report(),
+            Self::DoctorFoundProblems(_) => Report::action_request(
+                "Doctor found problems",
+                "Run `cargo mobile doctor` without `--quiet` to see them",
+            ),
+            Self::InfoFailed(err) => err.report(),
+            Self::MigrateConfigFailed(err) => err.report(),
+            Self::VersionFailed(err) => err.report(),
+            Self::CiTemplateFailed(err) => err.report(),
+            Self::LicensesFailed(err) => err.report(),
+            Self::VerifyTemplatesFailed(err) => err.report(),
+            Self::InstallTemplatesFailed(err) => err.report(),
+        }
+    }
+
+    // `doctor --quiet` already printed the problems, and its exit code says
+    // how bad they were.
+    fn silent_exit_code(&self) -> Option<i32> {
+        match self {
+            Self::DoctorFoundProblems(status) => Some(status.exit_code()),
+            _ => None,
         }
     }
 }
 
+fn enable_events(path: PathBuf) -> Result<(), Error> {
+    events::enable(&path).map_err(|source| Error::EventStreamFailed { path, source })
+}
+
 impl Exec for Input {
     type Report = Error;
 
     fn global_flags(&self) -> GlobalFlags {
-        self.flags
+        self.flags.clone()
     }
 
     fn exec(self, wrapper: &TextWrapper) -> Result<(), Self::Report> {
@@ -157,25 +326,43 @@ impl Exec for Input {
                 skip_dev_tools: cli::SkipDevTools { skip_dev_tools },
                 reinstall_deps: cli::ReinstallDeps { reinstall_deps },
                 open_in_editor,
+                conflicts,
                 submodule_commit,
-            } => init::exec(
-                wrapper,
-                non_interactive,
-                skip_dev_tools,
-                reinstall_deps,
-                open_in_editor,
-                submodule_commit,
-                ".",
-            )
-            .map(|_| ())
-            .map_err(Error::InitFailed),
+                event_stream,
+                adopt,
+                guided: cli::Guided { guided },
+            } => {
+                if let Some(path) = event_stream {
+                    enable_events(path)?;
+                }
+                init::exec(
+                    wrapper,
+                    non_interactive,
+                    skip_dev_tools,
+                    reinstall_deps,
+                    open_in_editor,
+                    conflicts.conflicts(),
+                    adopt,
+                    guided,
+                    submodule_commit,
+                    ".",
+                )
+                .map(|_| ())
+                .map_err(Error::InitFailed)
+            }
             Command::New {
                 skip_dev_tools: cli::SkipDevTools { skip_dev_tools },
                 reinstall_deps: cli::ReinstallDeps { reinstall_deps },
                 open_in_editor,
+                conflicts,
                 submodule_commit,
+                event_stream,
+                guided: cli::Guided { guided },
                 directory,
             } => {
+                if let Some(path) = event_stream {
+                    enable_events(path)?;
+                }
                 std::fs::create_dir_all(&directory).map_err(|source| Error::DirCreationFailed {
                     path: directory.clone(),
                     source,
@@ -190,12 +377,32 @@ impl Exec for Input {
                     skip_dev_tools,
                     reinstall_deps,
                     open_in_editor,
+                    conflicts.conflicts(),
+                    opts::Adopt::No,
+                    guided,
                     submodule_commit,
                     ".",
                 )
                 .map(|_| ())
                 .map_err(Error::InitFailed)
             }
+            Command::UpgradeProject {
+                skip_dev_tools: cli::SkipDevTools { skip_dev_tools },
+                reinstall_deps: cli::ReinstallDeps { reinstall_deps },
+            } => init::upgrade_project(
+                wrapper,
+                non_interactive,
+                skip_dev_tools,
+                reinstall_deps,
+                ".",
+            )
+            .map(|_| ())
+            .map_err(Error::InitFailed),
+            Command::Uninstall {
+                dry_run,
+                remove_config,
+            } => uninstall::exec(".", non_interactive, dry_run, remove_config)
+                .map_err(Error::UninstallFailed),
             Command::Open => util::open_in_editor(".").map_err(Error::OpenFailed),
             Command::Update { init } => {
                 update::update(wrapper).map_err(Error::UpdateFailed)?;
@@ -207,6 +414,8 @@ impl Exec for Input {
                         Default::default(),
                         Default::default(),
                         Default::default(),
+                        Default::default(),
+                        Default::default(),
                         ".",
                     )
                     .map_err(Error::InitFailed)?;
@@ -220,7 +429,49 @@ impl Exec for Input {
             Command::Android(command) => cargo_mobile::android::cli::Input::new(flags, command)
                 .exec(wrapper)
                 .map_err(Error::AndroidFailed),
-            Command::Doctor => doctor::exec(wrapper).map_err(Error::DoctorFailed),
+            Command::Doctor {
+                sections,
+                quiet,
+                list_sections,
+            } => {
+                if list_sections {
+                    doctor::list_sections();
+                    return Ok(());
+                }
+                let status = doctor::exec(wrapper, &sections, quiet, non_interactive)
+                    .map_err(Error::DoctorFailed)?;
+                if quiet && status != doctor::Status::Clean {
+                    Err(Error::DoctorFoundProblems(status))
+                } else {
+                    Ok(())
+                }
+            }
+            Command::Info => info::exec(".").map_err(Error::InfoFailed),
+            Command::MigrateConfig => {
+                config::migrate_file(".", non_interactive).map_err(Error::MigrateConfigFailed)
+            }
+            Command::Version { bump } => {
+                config::version::exec(".", bump).map_err(Error::VersionFailed)
+            }
+            Command::CiTemplate { provider, force } => {
+                ci::exec(".", provider, force).map_err(Error::CiTemplateFailed)
+            }
+            Command::Licenses => licenses::exec(".").map_err(Error::LicensesFailed),
+            Command::VerifyTemplates => {
+                verify_templates::exec(wrapper).map_err(Error::VerifyTemplatesFailed)
+            }
+            Command::Installation {
+                command: InstallationCommand::InstallTemplates,
+            } => {
+                let (install_dir, count) =
+                    install_templates().map_err(Error::InstallTemplatesFailed)?;
+                Report::victory(
+                    "Installed template packs",
+                    format!("Wrote {} template files into {:?}", count, install_dir),
+                )
+                .print(wrapper);
+                Ok(())
+            }
         }
     }
 }
@@ -0,0 +1,114 @@
+use super::{yaml::Writer, Cache, Job, Runner, Step};
+
+fn runs_on(runner: Runner) -> &'static str {
+    match runner {
+        Runner::Linux => "ubuntu-latest",
+        Runner::Macos => "macos-latest",
+    }
+}
+
+fn cache(w: &mut Writer, cache: Cache) {
+    let (name, paths, key) = match cache {
+        Cache::Cargo => (
+            "Cache cargo",
+            "~/.cargo/registry\n~/.cargo/git\ntarget",
+            "cargo-${{ runner.os }}-${{ hashFiles('**/Cargo.lock') }}",
+        ),
+        Cache::Gradle => (
+            "Cache gradle",
+            "~/.gradle/caches\n~/.gradle/wrapper",
+            "gradle-${{ runner.os }}-${{ hashFiles('**/*.gradle*', '**/gradle-wrapper.properties') }}",
+        ),
+    };
+    w.item_pair(2, "name", name);
+    w.pair(3, "uses", "actions/cache@v4");
+    w.key(3, "with");
+    w.block(4, "path", paths);
+    w.pair(4, "key", key);
+}
+
+fn step(w: &mut Writer, step: &Step) {
+    match step {
+        Step::Checkout => w.item_pair(2, "uses", "actions/checkout@v4"),
+        Step::SetupJava => {
+            w.item_pair(2, "uses", "actions/setup-java@v4");
+            w.key(3, "with");
+            w.pair(4, "distribution", "temurin");
+            w.pair(4, "java-version", "17");
+        }
+        Step::Cache(kind) => cache(w, *kind),
+        Step::Run {
+            name,
+            script,
+            secrets,
+        } => {
+            w.item_pair(2, "name", name);
+            if !secrets.is_empty() {
+                w.key(3, "env");
+                for secret in secrets {
+                    w.pair(4, secret, &format!("${{{{ secrets.{} }}}}", secret));
+                }
+            }
+            if script.contains('\n') {
+                w.block(3, "run", script);
+            } else {
+                w.pair(3, "run", script);
+            }
+        }
+        Step::Upload { name, paths } => {
+            w.item_pair(2, "uses", "actions/upload-artifact@v4");
+            w.key(3, "with");
+            w.pair(4, "name", name);
+            w.block(4, "path", &paths.join("\n"));
+        }
+    }
+}
+
+pub fn render(jobs: &[Job]) -> String {
+    let mut w = Writer::default();
+    w.comment(0, "Generated by `cargo mobile ci-template`.");
+    w.pair(0, "name", "cargo-mobile");
+    w.blank();
+    // `on` has to be quoted, or YAML 1.1 parsers read it as `true`.
+    w.key(0, "\"on\"");
+    w.item(1, "push");
+    w.item(1, "pull_request");
+    w.item(1, "workflow_dispatch");
+    w.blank();
+    w.key(0, "jobs");
+    for (i, job) in jobs.iter().enumerate() {
+        if i > 0 {
+            w.blank();
+        }
+        for note in &job.notes {
+            w.comment(1, note);
+        }
+        w.key(1, job.id);
+        w.pair(2, "name", job.name);
+        w.pair(2, "runs-on", runs_on(job.runner));
+        w.key(2, "steps");
+        for s in &job.steps {
+            step(&mut w, s);
+        }
+    }
+    w.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{jobs, test::params};
+
+    #[test]
+    fn references_secrets() {
+        let rendered = super::render(&jobs(&params(true)));
+        assert!(rendered.contains("    runs-on: ubuntu-latest\n"));
+        assert!(rendered.contains("    runs-on: macos-latest\n"));
+        assert!(rendered.contains(
+            "          ANDROID_KEYSTORE_BASE64: \"${{ secrets.ANDROID_KEYSTORE_BASE64 }}\"\n"
+        ));
+        assert!(rendered.contains(
+            "          GAME_KEYSTORE_PASSWORD: \"${{ secrets.GAME_KEYSTORE_PASSWORD }}\"\n"
+        ));
+        assert!(!super::render(&jobs(&params(false))).contains("secrets."));
+    }
+}
@@ -0,0 +1,134 @@
+use super::{yaml::Writer, Cache, Job, Runner, Step};
+
+fn header(w: &mut Writer, runner: Runner) {
+    match runner {
+        Runner::Linux => {
+            w.comment(
+                1,
+                "This image doesn't include the Android SDK; swap in one that does, or\ninstall it in `before_script`, and set `ANDROID_SDK_ROOT`.",
+            );
+            w.pair(1, "image", "rust:latest");
+        }
+        Runner::Macos => {
+            w.comment(1, "Needs a macOS runner with Xcode and Rust installed.");
+            w.key(1, "tags");
+            w.item(2, "macos");
+        }
+    }
+}
+
+// GitLab can only cache paths inside the project dir, so anything that'd
+// normally live in `$HOME` gets moved there.
+fn variables(w: &mut Writer, caches: &[Cache]) {
+    if caches.is_empty() {
+        return;
+    }
+    w.key(1, "variables");
+    for cache in caches {
+        match cache {
+            Cache::Cargo => w.pair(2, "CARGO_HOME", "$CI_PROJECT_DIR/.cargo-home"),
+            Cache::Gradle => w.pair(2, "GRADLE_USER_HOME", "$CI_PROJECT_DIR/.gradle-home"),
+        }
+    }
+    w.key(1, "cache");
+    w.key(2, "key");
+    w.key(3, "files");
+    w.item(4, "Cargo.lock");
+    w.key(2, "paths");
+    for cache in caches {
+        match cache {
+            Cache::Cargo => {
+                w.item(3, ".cargo-home/registry");
+                w.item(3, ".cargo-home/git");
+                w.item(3, "target");
+            }
+            Cache::Gradle => {
+                w.item(3, ".gradle-home/caches");
+                w.item(3, ".gradle-home/wrapper");
+            }
+        }
+    }
+}
+
+fn job(w: &mut Writer, job: &Job) {
+    for note in &job.notes {
+        w.comment(0, note);
+    }
+    let secrets = job.secrets().collect::<Vec<_>>();
+    if !secrets.is_empty() {
+        w.comment(
+            0,
+            &format!(
+                "Add these as masked CI/CD variables: {}",
+                secrets.join(", ")
+            ),
+        );
+    }
+    w.key(0, job.id);
+    header(w, job.runner);
+    let caches = job
+        .steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Cache(cache) => Some(*cache),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    variables(w, &caches);
+    w.key(1, "script");
+    for step in &job.steps {
+        // GitLab checks out the repo by itself, and Java comes with the image.
+        if let Step::Run { name, script, .. } = step {
+            w.comment(2, name);
+            // CARGO_HOME moved, so its bin dir isn't on the `PATH` yet.
+            if script.starts_with("cargo install") && caches.contains(&Cache::Cargo) {
+                w.item(2, "export PATH=\"$CARGO_HOME/bin:$PATH\"");
+            }
+            for line in script.lines() {
+                w.item(2, line);
+            }
+        }
+    }
+    let paths = job
+        .steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Upload { paths, .. } => Some(paths),
+            _ => None,
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    if !paths.is_empty() {
+        w.key(1, "artifacts");
+        w.key(2, "paths");
+        for path in paths {
+            w.item(3, path);
+        }
+    }
+}
+
+pub fn render(jobs: &[Job]) -> String {
+    let mut w = Writer::default();
+    w.comment(0, "Generated by `cargo mobile ci-template`.");
+    for j in jobs {
+        w.blank();
+        job(&mut w, j);
+    }
+    w.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{jobs, test::params};
+
+    #[test]
+    fn lists_secrets_and_artifacts() {
+        let rendered = super::render(&jobs(&params(true)));
+        assert!(rendered.contains(
+            "# Add these as masked CI/CD variables: ANDROID_KEYSTORE_BASE64, GAME_KEYSTORE_PASSWORD\n"
+        ));
+        assert!(rendered.contains("    - \"(cd gen/android/game && ./gradlew bundleRelease)\"\n"));
+        assert!(rendered.contains("  artifacts:\n    paths:\n"));
+        assert!(rendered.contains("  tags:\n    - macos\n"));
+    }
+}
@@ -0,0 +1,513 @@
+//! Generates a CI pipeline for building the project's mobile targets. The
+//! pipeline is described provider-agnostically here, and then rendered by the
+//! module for each provider.
+
+mod github;
+mod gitlab;
+mod yaml;
+
+use crate::{
+    config::{
+        metadata::{self, Metadata},
+        Config, LoadExistingError,
+    },
+    opts::{CiProvider, Profile},
+    util::{
+        self,
+        cli::{Report, Reportable},
+    },
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// We pin this rather than asking the runner, so builds don't change out from
+// under people when a new NDK ships.
+static NDK_VERSION: &str = "26.3.11579264";
+static INSTALL_CARGO_MOBILE: &str =
+    "cargo install --git https://github.com/BrainiumLLC/cargo-mobile";
+static KEYSTORE_SECRET: &str = "ANDROID_KEYSTORE_BASE64";
+// What Apple builds fall back to when there's no Apple config to ask, which
+// is the case everywhere but macOS.
+#[cfg(not(target_os = "macos"))]
+static DEFAULT_APPLE_PROJECT_DIR: &str = "gen/apple";
+#[cfg(not(target_os = "macos"))]
+static DEFAULT_APPLE_TRIPLE: &str = "aarch64-apple-ios";
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadExistingError),
+    MetadataFailed(metadata::Error),
+    NothingToBuild,
+    AlreadyExists {
+        path: PathBuf,
+    },
+    WriteFailed {
+        path: PathBuf,
+        cause: std::io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::MetadataFailed(err) => err.report(),
+            Self::NothingToBuild => Report::error(
+                "Failed to generate CI template",
+                "Every platform is marked as unsupported in your Cargo.toml metadata, so there's nothing for CI to build.",
+            ),
+            Self::AlreadyExists { path } => Report::action_request(
+                format!("{:?} already exists", path),
+                "Pass `--force` to overwrite it.",
+            ),
+            Self::WriteFailed { path, cause } => {
+                Report::error(format!("Failed to write CI template to {:?}", path), cause)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Runner {
+    Linux,
+    Macos,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Cache {
+    Cargo,
+    Gradle,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Step {
+    /// Providers that don't check out the repo automatically need this first.
+    Checkout,
+    SetupJava,
+    Cache(Cache),
+    Run {
+        name: String,
+        script: String,
+        /// Secrets the script reads from the environment.
+        secrets: Vec<String>,
+    },
+    Upload {
+        name: String,
+        paths: Vec<String>,
+    },
+}
+
+impl Step {
+    fn run(name: impl Into<String>, script: impl Into<String>) -> Self {
+        Self::Run {
+            name: name.into(),
+            script: script.into(),
+            secrets: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Job {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub runner: Runner,
+    /// Things the user will need to know or fill in, rendered as comments.
+    pub notes: Vec<String>,
+    pub steps: Vec<Step>,
+}
+
+impl Job {
+    pub fn secrets(&self) -> impl Iterator<Item = &str> {
+        self.steps
+            .iter()
+            .flat_map(|step| match step {
+                Step::Run { secrets, .. } => secrets.as_slice(),
+                _ => &[],
+            })
+            .map(String::as_str)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AndroidSigning {
+    pub keystore: PathBuf,
+    pub keystore_password_env: String,
+}
+
+/// Everything the pipeline depends on, with paths relative to the project
+/// root.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Params {
+    pub android: Option<AndroidParams>,
+    pub apple: Option<AppleParams>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AndroidParams {
+    pub triples: Vec<String>,
+    pub min_sdk_version: u32,
+    pub project_dir: PathBuf,
+    pub aabs: Vec<PathBuf>,
+    pub signing: Option<AndroidSigning>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AppleParams {
+    /// What `cargo apple archive` builds when it's given no targets and
+    /// there's no device, which is always the case on CI.
+    pub triples: Vec<String>,
+    pub xcarchive: PathBuf,
+    pub export_options: PathBuf,
+    pub export_dir: PathBuf,
+    /// Whether these came from the defaults rather than an Apple config.
+    pub guessed: bool,
+}
+
+impl AppleParams {
+    #[cfg(target_os = "macos")]
+    fn new(config: &Config, rel: impl Fn(PathBuf) -> PathBuf) -> Self {
+        use crate::{apple::target::Target, target::TargetTrait as _};
+        let apple_config = config.apple();
+        let target = apple_config
+            .default_target()
+            .unwrap_or_else(|| Target::default_ref());
+        Self {
+            triples: vec![target.triple.to_owned()],
+            xcarchive: rel(apple_config.xcarchive_path("")),
+            export_options: rel(apple_config.export_plist_path()),
+            export_dir: rel(apple_config.export_dir()),
+            guessed: false,
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn new(config: &Config, _rel: impl Fn(PathBuf) -> PathBuf) -> Self {
+        let project_dir = Path::new(DEFAULT_APPLE_PROJECT_DIR);
+        Self {
+            triples: vec![DEFAULT_APPLE_TRIPLE.to_owned()],
+            xcarchive: project_dir
+                .join("build")
+                .join(format!("{}_iOS.xcarchive", config.app().name())),
+            export_options: project_dir.join("ExportOptions.plist"),
+            export_dir: project_dir.join("build"),
+            guessed: true,
+        }
+    }
+}
+
+impl Params {
+    pub fn new(config: &Config, metadata: &Metadata) -> Self {
+        let root_dir = config.app().root_dir();
+        let rel = |path: PathBuf| util::relativize_path(path, root_dir);
+        let android_config = config.android();
+        let android = if metadata.android().supported() {
+            let targets = android_config.targets();
+            Some(AndroidParams {
+                triples: targets
+                    .iter()
+                    .map(|target| target.triple.to_owned())
+                    .collect(),
+                min_sdk_version: android_config.min_sdk_version(),
+                project_dir: rel(android_config.project_dir()),
+                aabs: targets
+                    .iter()
+                    .map(|target| rel(android_config.aab_path(Profile::Release, target.arch)))
+                    .collect(),
+                signing: android_config.signing().map(|signing| AndroidSigning {
+                    keystore: rel(signing.keystore.clone()),
+                    keystore_password_env: signing.keystore_password_env.clone(),
+                }),
+            })
+        } else {
+            None
+        };
+        #[cfg(target_os = "macos")]
+        let apple_supported = metadata.apple().supported();
+        // Apple metadata is only parsed on macOS, so we can't check.
+        #[cfg(not(target_os = "macos"))]
+        let apple_supported = true;
+        let apple = if apple_supported {
+            Some(AppleParams::new(config, rel))
+        } else {
+            None
+        };
+        Self { android, apple }
+    }
+}
+
+fn path_str(path: &Path) -> String {
+    // Always forward slashes, since CI scripts run in a POSIX shell.
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn android_job(params: &AndroidParams) -> Job {
+    let ndk_home = format!("export NDK_HOME=\"$ANDROID_SDK_ROOT/ndk/{}\"", NDK_VERSION);
+    let sdkmanager = "\"$ANDROID_SDK_ROOT/cmdline-tools/latest/bin/sdkmanager\"";
+    let mut notes = vec![format!(
+        "Builds {} with a min SDK of {}.",
+        params.triples.join(", "),
+        params.min_sdk_version
+    )];
+    let mut steps = vec![
+        Step::Checkout,
+        Step::SetupJava,
+        Step::Cache(Cache::Cargo),
+        Step::Cache(Cache::Gradle),
+        Step::run(
+            "Install Rust targets",
+            format!("rustup target add {}", params.triples.join(" ")),
+        ),
+        Step::run(
+            "Install Android NDK",
+            format!(
+                "yes | {} --licenses > /dev/null\n{} --install \"ndk;{}\"",
+                sdkmanager, sdkmanager, NDK_VERSION
+            ),
+        ),
+        Step::run("Install cargo-mobile", INSTALL_CARGO_MOBILE),
+        Step::run(
+            "Generate project",
+            format!(
+                "{}\ncargo mobile init --non-interactive --skip-dev-tools",
+                ndk_home
+            ),
+        ),
+        Step::run(
+            "Build AAB",
+            format!(
+                "{}\n(cd {} && ./gradlew bundleRelease)",
+                ndk_home,
+                path_str(&params.project_dir)
+            ),
+        ),
+    ];
+    if let Some(signing) = &params.signing {
+        notes.push(format!(
+            "Signing needs the `{}` secret (your keystore, base64-encoded) and the `{}` secret (its password).",
+            KEYSTORE_SECRET, signing.keystore_password_env
+        ));
+        let mut script = format!(
+            "echo \"${}\" | base64 --decode > {}",
+            KEYSTORE_SECRET,
+            path_str(&signing.keystore)
+        );
+        for aab in &params.aabs {
+            script.push_str(&format!("\ncargo android sign {}", path_str(aab)));
+        }
+        steps.push(Step::Run {
+            name: "Sign AAB".to_owned(),
+            script,
+            secrets: vec![
+                KEYSTORE_SECRET.to_owned(),
+                signing.keystore_password_env.clone(),
+            ],
+        });
+    } else {
+        notes.push("`android.signing` isn't configured, so the AAB is left unsigned.".to_owned());
+    }
+    steps.push(Step::Upload {
+        name: "android-aab".to_owned(),
+        paths: params.aabs.iter().map(|aab| path_str(aab)).collect(),
+    });
+    Job {
+        id: "android",
+        name: "Android",
+        runner: Runner::Linux,
+        notes,
+        steps,
+    }
+}
+
+fn apple_job(params: &AppleParams) -> Job {
+    let mut notes = vec![
+        "Archiving needs your signing certificate and provisioning profile installed on the runner; add steps for that before `Archive`.".to_owned(),
+    ];
+    if params.guessed {
+        notes.push(
+            "This was generated without an Apple config, so it assumes the default project dir and target; check them against `mobile.toml`.".to_owned(),
+        );
+    }
+    Job {
+        id: "ios",
+        name: "iOS",
+        runner: Runner::Macos,
+        notes,
+        steps: vec![
+            Step::Checkout,
+            Step::Cache(Cache::Cargo),
+            Step::run(
+                "Install Rust targets",
+                format!("rustup target add {}", params.triples.join(" ")),
+            ),
+            Step::run("Install cargo-mobile", INSTALL_CARGO_MOBILE),
+            Step::run("Generate project", "cargo mobile init --non-interactive"),
+            Step::run("Archive", "cargo apple archive --release"),
+            // Archiving stops at the `.xcarchive`; the IPA comes from
+            // exporting it.
+            Step::run(
+                "Export IPA",
+                format!(
+                    "xcodebuild -exportArchive -archivePath {} -exportOptionsPlist {} -exportPath {}",
+                    path_str(&params.xcarchive),
+                    path_str(&params.export_options),
+                    path_str(&params.export_dir)
+                ),
+            ),
+            Step::Upload {
+                name: "ios-ipa".to_owned(),
+                paths: vec![format!("{}/*.ipa", path_str(&params.export_dir))],
+            },
+        ],
+    }
+}
+
+pub fn jobs(params: &Params) -> Vec<Job> {
+    let mut jobs = Vec::new();
+    if let Some(android) = &params.android {
+        jobs.push(android_job(android));
+    }
+    if let Some(apple) = &params.apple {
+        jobs.push(apple_job(apple));
+    }
+    jobs
+}
+
+/// Where each provider expects its pipeline, relative to the project root.
+pub fn output_path(provider: CiProvider) -> &'static Path {
+    Path::new(match provider {
+        CiProvider::Github => ".github/workflows/cargo-mobile.yml",
+        CiProvider::Gitlab => ".gitlab-ci.yml",
+    })
+}
+
+pub fn render(provider: CiProvider, jobs: &[Job]) -> String {
+    match provider {
+        CiProvider::Github => github::render(jobs),
+        CiProvider::Gitlab => gitlab::render(jobs),
+    }
+}
+
+pub fn exec(cwd: impl AsRef<Path>, provider: CiProvider, force: bool) -> Result<(), Error> {
+    let config = Config::load(cwd).map_err(Error::ConfigFailed)?;
+    let metadata = Metadata::load(&config.app().root_dir()).map_err(Error::MetadataFailed)?;
+    let jobs = jobs(&Params::new(&config, &metadata));
+    if jobs.is_empty() {
+        return Err(Error::NothingToBuild);
+    }
+    let path = config.app().root_dir().join(output_path(provider));
+    if path.exists() && !force {
+        return Err(Error::AlreadyExists { path });
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|cause| Error::WriteFailed {
+            path: path.clone(),
+            cause,
+        })?;
+    }
+    fs::write(&path, render(provider, &jobs)).map_err(|cause| Error::WriteFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    println!("Wrote CI pipeline to {:?}", path);
+    let mut secrets = jobs.iter().flat_map(Job::secrets).collect::<Vec<_>>();
+    secrets.dedup();
+    if !secrets.is_empty() {
+        println!(
+            "Before it'll pass, add these secrets in your CI provider's settings: {}",
+            secrets.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    pub fn params(signing: bool) -> Params {
+        Params {
+            android: Some(AndroidParams {
+                triples: vec![
+                    "aarch64-linux-android".to_owned(),
+                    "x86_64-linux-android".to_owned(),
+                ],
+                min_sdk_version: 24,
+                project_dir: PathBuf::from("gen/android/game"),
+                aabs: vec![
+                    PathBuf::from("gen/android/game/app/build/outputs/bundle/arm64Release/app-arm64-release.aab"),
+                    PathBuf::from("gen/android/game/app/build/outputs/bundle/x86_64Release/app-x86_64-release.aab"),
+                ],
+                signing: if signing {
+                    Some(AndroidSigning {
+                        keystore: PathBuf::from("release.keystore"),
+                        keystore_password_env: "GAME_KEYSTORE_PASSWORD".to_owned(),
+                    })
+                } else {
+                    None
+                },
+            }),
+            apple: Some(AppleParams {
+                triples: vec!["aarch64-apple-ios".to_owned()],
+                xcarchive: PathBuf::from("gen/apple/build/game_iOS.xcarchive"),
+                export_options: PathBuf::from("gen/apple/ExportOptions.plist"),
+                export_dir: PathBuf::from("gen/apple/build"),
+                guessed: false,
+            }),
+        }
+    }
+
+    #[test]
+    fn one_job_per_platform() {
+        let ids = |params: &Params| jobs(params).iter().map(|job| job.id).collect::<Vec<_>>();
+        assert_eq!(ids(&params(false)), vec!["android", "ios"]);
+        let android_only = Params {
+            apple: None,
+            ..params(false)
+        };
+        assert_eq!(ids(&android_only), vec!["android"]);
+    }
+
+    #[test]
+    fn android_uses_configured_targets() {
+        let job = android_job(params(false).android.as_ref().unwrap());
+        assert!(job.steps.contains(&Step::run(
+            "Install Rust targets",
+            "rustup target add aarch64-linux-android x86_64-linux-android"
+        )));
+        assert!(job.notes[0].contains("min SDK of 24"));
+    }
+
+    #[test]
+    fn apple_exports_what_it_archives() {
+        let job = apple_job(params(false).apple.as_ref().unwrap());
+        assert!(job.steps.contains(&Step::run(
+            "Install Rust targets",
+            "rustup target add aarch64-apple-ios"
+        )));
+        assert!(job.steps.contains(&Step::run(
+            "Export IPA",
+            "xcodebuild -exportArchive -archivePath gen/apple/build/game_iOS.xcarchive -exportOptionsPlist gen/apple/ExportOptions.plist -exportPath gen/apple/build"
+        )));
+        assert!(job.steps.contains(&Step::Upload {
+            name: "ios-ipa".to_owned(),
+            paths: vec!["gen/apple/build/*.ipa".to_owned()],
+        }));
+    }
+
+    #[test]
+    fn signing_adds_secrets() {
+        let unsigned = android_job(params(false).android.as_ref().unwrap());
+        assert_eq!(unsigned.secrets().count(), 0);
+        let signed = android_job(params(true).android.as_ref().unwrap());
+        assert_eq!(
+            signed.secrets().collect::<Vec<_>>(),
+            vec![KEYSTORE_SECRET, "GAME_KEYSTORE_PASSWORD"]
+        );
+    }
+}
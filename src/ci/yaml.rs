@@ -0,0 +1,101 @@
+//! Just enough YAML emitting for pipeline files. We write these by hand
+//! rather than serializing, since people are going to edit the result and
+//! deserve something that reads like a normal workflow file.
+
+use std::borrow::Cow;
+
+/// Quotes `s` unless it's unambiguously a plain string. JSON strings are
+/// valid double-quoted YAML scalars, so we lean on `serde_json` for escaping.
+pub fn scalar(s: &str) -> Cow<'_, str> {
+    let plain = !s.is_empty()
+        && s.chars()
+            .next()
+            .map_or(false, |c| c.is_ascii_alphabetic() || c == '/' || c == '.')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./@+".contains(c))
+        && !matches!(
+            s.to_ascii_lowercase().as_str(),
+            "true" | "false" | "yes" | "no" | "on" | "off" | "null" | "y" | "n"
+        );
+    if plain {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(serde_json::to_string(s).expect("developer error: string didn't serialize"))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Writer {
+    out: String,
+}
+
+impl Writer {
+    fn line(&mut self, indent: usize, line: &str) {
+        for _ in 0..indent {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    pub fn blank(&mut self) {
+        self.out.push('\n');
+    }
+
+    pub fn comment(&mut self, indent: usize, comment: &str) {
+        for line in comment.lines() {
+            self.line(indent, &format!("# {}", line));
+        }
+    }
+
+    /// Starts a mapping or sequence under `key`.
+    pub fn key(&mut self, indent: usize, key: &str) {
+        self.line(indent, &format!("{}:", key));
+    }
+
+    pub fn pair(&mut self, indent: usize, key: &str, value: &str) {
+        self.line(indent, &format!("{}: {}", key, scalar(value)));
+    }
+
+    pub fn item(&mut self, indent: usize, value: &str) {
+        self.line(indent, &format!("- {}", scalar(value)));
+    }
+
+    /// A sequence item that starts a mapping, i.e. `- name: foo`.
+    pub fn item_pair(&mut self, indent: usize, key: &str, value: &str) {
+        self.line(indent, &format!("- {}: {}", key, scalar(value)));
+    }
+
+    /// A literal block scalar, which keeps shell scripts readable.
+    pub fn block(&mut self, indent: usize, key: &str, text: &str) {
+        self.line(indent, &format!("{}: |", key));
+        for line in text.lines() {
+            self.line(indent + 1, line);
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        s,
+        expected,
+        case("ubuntu-latest", "ubuntu-latest"),
+        case("actions/checkout@v4", "actions/checkout@v4"),
+        case("on", "\"on\""),
+        case("17", "\"17\""),
+        case("${{ secrets.KEY }}", "\"${{ secrets.KEY }}\""),
+        case("a: b", "\"a: b\""),
+        case("", "\"\"")
+    )]
+    fn quotes_when_needed(s: &str, expected: &str) {
+        assert_eq!(scalar(s), expected);
+    }
+}
@@ -6,11 +6,17 @@ mod raw;
 pub use self::raw::*;
 
 use crate::{
+    hooks::Hooks,
+    opts::Profile,
     templating::{self, Pack},
-    util::{self, cli::Report},
+    util::{self, cli::Report, VersionTriple, VersionTripleError},
+};
+use once_cell_regex::regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
 };
-use serde::Serialize;
-use std::path::{Path, PathBuf};
 
 pub static KEY: &str = "app";
 
@@ -22,6 +28,185 @@ pub static DEFAULT_TEMPLATE_PACK: &str = if cfg!(feature = "brainium") {
     "bevy"
 };
 
+// This only checks the shape (language, then optional script, region, and
+// variants), which is enough to catch typos like `en_US`.
+fn looks_like_bcp47(tag: &str) -> bool {
+    regex!(r"^[A-Za-z]{2,3}(-[A-Za-z]{4})?(-[A-Za-z]{2}|-[0-9]{3})?(-[A-Za-z0-9]{5,8}|-[0-9][A-Za-z0-9]{3})*$")
+        .is_match(tag)
+}
+
+/// Appends `debug_suffix` to `base` for debug builds, so they can be
+/// installed alongside release builds. Every per-profile id and display name
+/// goes through here, so generated projects and the commands that launch
+/// them can't disagree.
+pub fn for_profile(base: &str, debug_suffix: Option<&str>, profile: Profile) -> String {
+    match (profile, debug_suffix) {
+        (Profile::Debug, Some(suffix)) => format!("{}{}", base, suffix),
+        _ => base.to_owned(),
+    }
+}
+
+/// Whether `suffix` can go on the end of an app id, i.e. `.debug`. Android
+/// allows `_` in ids and Apple allows `-`, so the caller says which.
+pub fn id_suffix_valid(suffix: &str, separator: char) -> bool {
+    suffix.strip_prefix('.').map_or(false, |suffix| {
+        suffix.split('.').all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == separator)
+        })
+    })
+}
+
+/// How the asset dir gets into the generated projects.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssetStrategy {
+    /// Symlink the asset dir into each project.
+    Symlink,
+    /// Copy the asset dir into each project, refreshing it before builds.
+    /// This is for filesystems and tools that can't follow symlinks.
+    Copy,
+    /// Leave assets alone entirely, for projects that manage them themselves.
+    None,
+}
+
+impl Default for AssetStrategy {
+    fn default() -> Self {
+        Self::Symlink
+    }
+}
+
+/// The platforms an asset dir can be included in.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssetPlatform {
+    Android,
+    Ios,
+}
+
+impl AssetPlatform {
+    pub const ALL: &'static [Self] = &[Self::Android, Self::Ios];
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AssetDir {
+    path: PathBuf,
+    platforms: Vec<AssetPlatform>,
+    dest: String,
+}
+
+impl AssetDir {
+    #[cfg(test)]
+    pub fn new(path: &str, platforms: &[AssetPlatform], dest: &str) -> Self {
+        Self {
+            path: path.into(),
+            platforms: platforms.to_vec(),
+            dest: dest.to_owned(),
+        }
+    }
+
+    /// Relative to the app root.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn platforms(&self) -> &[AssetPlatform] {
+        &self.platforms
+    }
+
+    pub fn includes(&self, platform: AssetPlatform) -> bool {
+        self.platforms.contains(&platform)
+    }
+
+    /// The name this is installed under in the generated projects, which
+    /// defaults to the dir's own name. Dirs with the same `dest` get merged.
+    pub fn dest(&self) -> &str {
+        &self.dest
+    }
+}
+
+fn dest_valid(dest: &str) -> bool {
+    !dest.is_empty() && dest != "." && dest != ".." && !dest.contains(&['/', '\\'][..])
+}
+
+fn asset_dirs(
+    asset_dir: Option<String>,
+    asset_dirs: Option<Vec<RawAssetDir>>,
+    root_dir: &Path,
+) -> Result<Vec<AssetDir>, Error> {
+    let raw_dirs = match (asset_dir, asset_dirs) {
+        (Some(_), Some(_)) => return Err(Error::AssetDirAndAssetDirs),
+        (None, Some(dirs)) if dirs.is_empty() => return Err(Error::AssetDirsEmpty),
+        (None, Some(dirs)) => dirs,
+        (asset_dir, None) => {
+            if asset_dir.as_deref() == Some(DEFAULT_ASSET_DIR) {
+                log::warn!(
+                    "`{}.asset-dir` is set to the default value; you can remove it from your config",
+                    KEY
+                );
+            }
+            let path = asset_dir.unwrap_or_else(|| {
+                log::info!(
+                    "`{}.asset-dir` not set; defaulting to {}",
+                    KEY,
+                    DEFAULT_ASSET_DIR
+                );
+                DEFAULT_ASSET_DIR.to_owned()
+            });
+            vec![RawAssetDir {
+                path,
+                platforms: None,
+                dest: None,
+            }]
+        }
+    };
+    raw_dirs
+        .into_iter()
+        .map(|raw| {
+            let path = PathBuf::from(raw.path);
+            if !util::under_root(&path, root_dir).map_err(|cause| {
+                Error::AssetDirNormalizationFailed {
+                    asset_dir: path.clone(),
+                    cause,
+                }
+            })? {
+                return Err(Error::AssetDirOutsideOfAppRoot {
+                    asset_dir: path,
+                    root_dir: root_dir.to_owned(),
+                });
+            }
+            let mut platforms = raw.platforms.unwrap_or_else(|| AssetPlatform::ALL.to_vec());
+            if platforms.is_empty() {
+                return Err(Error::AssetDirPlatformsEmpty { asset_dir: path });
+            }
+            platforms.sort();
+            platforms.dedup();
+            let dest = raw
+                .dest
+                .or_else(|| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .map(ToOwned::to_owned)
+                })
+                .unwrap_or_default();
+            if !dest_valid(&dest) {
+                return Err(Error::AssetDirDestInvalid {
+                    asset_dir: path,
+                    dest,
+                });
+            }
+            Ok(AssetDir {
+                path,
+                platforms,
+                dest,
+            })
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 pub enum Error {
     NameInvalid(name::Invalid),
@@ -37,7 +222,21 @@ pub enum Error {
         asset_dir: PathBuf,
         root_dir: PathBuf,
     },
+    AssetDirAndAssetDirs,
+    AssetDirsEmpty,
+    AssetDirPlatformsEmpty {
+        asset_dir: PathBuf,
+    },
+    AssetDirDestInvalid {
+        asset_dir: PathBuf,
+        dest: String,
+    },
     TemplatePackNotFound(templating::LookupError),
+    DisplayNameEmpty,
+    DisplayNameLocalizedEmpty {
+        tag: String,
+    },
+    VersionInvalid(VersionTripleError),
 }
 
 impl Error {
@@ -52,10 +251,7 @@ impl Error {
             ),
             Self::AssetDirNormalizationFailed { asset_dir, cause } => Report::error(
                 msg,
-                format!(
-                    "`{}.asset-dir` {:?} couldn't be normalized: {}",
-                    KEY, asset_dir, cause
-                ),
+                format!("Asset dir {:?} couldn't be normalized: {}", asset_dir, cause),
             ),
             Self::AssetDirOutsideOfAppRoot {
                 asset_dir,
@@ -63,11 +259,52 @@ impl Error {
             } => Report::error(
                 msg,
                 format!(
-                    "`{}.asset-dir` {:?} is outside of the app root {:?}",
-                    KEY, asset_dir, root_dir,
+                    "Asset dir {:?} is outside of the app root {:?}",
+                    asset_dir, root_dir,
+                ),
+            ),
+            Self::AssetDirAndAssetDirs => Report::error(
+                msg,
+                format!(
+                    "`{0}.asset-dir` and `{0}.asset-dirs` can't both be set; `asset-dir = \"dir\"` is shorthand for `asset-dirs = [{{ path = \"dir\" }}]`",
+                    KEY
+                ),
+            ),
+            Self::AssetDirsEmpty => Report::error(
+                msg,
+                format!(
+                    "`{}.asset-dirs` is empty; remove it to use the default, or set `asset-strategy = \"none\"` to skip assets",
+                    KEY
+                ),
+            ),
+            Self::AssetDirPlatformsEmpty { asset_dir } => Report::error(
+                msg,
+                format!(
+                    "Asset dir {:?} has an empty `platforms` list; remove it to include the dir everywhere",
+                    asset_dir
+                ),
+            ),
+            Self::AssetDirDestInvalid { asset_dir, dest } => Report::error(
+                msg,
+                format!(
+                    "Asset dir {:?} would be installed as {:?}, which isn't a valid dir name; set `dest` to a name without slashes",
+                    asset_dir, dest
                 ),
             ),
             Self::TemplatePackNotFound(err) => Report::error(msg, err),
+            Self::DisplayNameEmpty => {
+                Report::error(msg, format!("`{}.display-name` can't be empty", KEY))
+            }
+            Self::DisplayNameLocalizedEmpty { tag } => Report::error(
+                msg,
+                format!(
+                    "`{}.display-name-localized` has an empty name for {:?}",
+                    KEY, tag
+                ),
+            ),
+            Self::VersionInvalid(err) => {
+                Report::error(msg, format!("`{}.version` invalid: {}", KEY, err))
+            }
         }
     }
 }
@@ -78,20 +315,52 @@ pub struct App {
     root_dir: PathBuf,
     name: String,
     stylized_name: String,
+    display_name: Option<String>,
+    #[serde(skip)]
+    debug_display_name_suffix: Option<String>,
+    display_name_localized: BTreeMap<String, String>,
     domain: String,
+    // Serialized so templates can use it directly as `app.version`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<VersionTriple>,
+    // Only the first of `asset_dirs`, for templates that predate them.
     asset_dir: PathBuf,
+    asset_dirs: Vec<AssetDir>,
+    #[serde(skip)]
+    asset_dir_explicit: bool,
+    asset_strategy: AssetStrategy,
     #[serde(skip)]
     template_pack: Pack,
+    hooks: Hooks,
 }
 
 impl App {
-    pub fn from_raw(root_dir: PathBuf, raw: Raw) -> Result<Self, Error> {
+    pub fn from_raw(root_dir: PathBuf, raw: Raw, hooks: Hooks) -> Result<Self, Error> {
         assert!(root_dir.is_absolute(), "root must be absolute");
 
         let name = name::validate(raw.name).map_err(Error::NameInvalid)?;
 
         let stylized_name = raw.stylized_name.unwrap_or_else(|| name.clone());
 
+        // Unlike `name`, these can be anything at all, as long as there's
+        // something there.
+        if raw.display_name.as_deref().map_or(false, str::is_empty) {
+            return Err(Error::DisplayNameEmpty);
+        }
+        let display_name_localized = raw.display_name_localized.unwrap_or_default();
+        for (tag, localized) in &display_name_localized {
+            if localized.is_empty() {
+                return Err(Error::DisplayNameLocalizedEmpty { tag: tag.clone() });
+            }
+            if !looks_like_bcp47(tag) {
+                log::warn!(
+                    "`{}.display-name-localized` has {:?}, which doesn't look like a BCP-47 language tag (i.e. `fr` or `pt-BR`)",
+                    KEY,
+                    tag
+                );
+            }
+        }
+
         let domain = {
             let domain = raw.domain;
             domain::check_domain_syntax(&domain)
@@ -102,31 +371,25 @@ impl App {
                 .map(|()| domain)
         }?;
 
-        if raw.asset_dir.as_deref() == Some(DEFAULT_ASSET_DIR) {
-            log::warn!(
-                "`{}.asset-dir` is set to the default value; you can remove it from your config",
-                KEY
-            );
-        }
-        let asset_dir = raw.asset_dir.map(PathBuf::from).unwrap_or_else(|| {
+        let version = raw
+            .version
+            .as_deref()
+            .map(VersionTriple::from_str)
+            .transpose()
+            .map_err(Error::VersionInvalid)?;
+
+        let asset_dir_explicit = raw.asset_dir.is_some() || raw.asset_dirs.is_some();
+        let asset_dirs = asset_dirs(raw.asset_dir, raw.asset_dirs, &root_dir)?;
+        let asset_dir = asset_dirs[0].path().to_owned();
+
+        let asset_strategy = raw.asset_strategy.unwrap_or_else(|| {
             log::info!(
-                "`{}.asset-dir` not set; defaulting to {}",
+                "`{}.asset-strategy` not set; defaulting to {:?}",
                 KEY,
-                DEFAULT_ASSET_DIR
+                AssetStrategy::default()
             );
-            DEFAULT_ASSET_DIR.into()
+            AssetStrategy::default()
         });
-        if !util::under_root(&asset_dir, &root_dir).map_err(|cause| {
-            Error::AssetDirNormalizationFailed {
-                asset_dir: asset_dir.clone(),
-                cause,
-            }
-        })? {
-            return Err(Error::AssetDirOutsideOfAppRoot {
-                asset_dir,
-                root_dir,
-            });
-        }
 
         let template_pack = {
             if raw.template_pack.as_deref() == Some(IMPLIED_TEMPLATE_PACK) {
@@ -150,9 +413,17 @@ impl App {
             root_dir,
             name,
             stylized_name,
+            display_name: raw.display_name,
+            debug_display_name_suffix: raw.debug_display_name_suffix,
+            display_name_localized,
             domain,
+            version,
             asset_dir,
+            asset_dirs,
+            asset_dir_explicit,
+            asset_strategy,
             template_pack,
+            hooks,
         })
     }
 
@@ -181,6 +452,46 @@ impl App {
         &self.stylized_name
     }
 
+    /// The name shown under the app icon, which defaults to the stylized
+    /// name.
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.stylized_name)
+    }
+
+    /// The display name for `profile`, which has `debug-display-name-suffix`
+    /// on it for debug builds so the icons can be told apart.
+    pub fn display_name_for(&self, profile: Profile) -> String {
+        for_profile(
+            self.display_name(),
+            self.debug_display_name_suffix.as_deref(),
+            profile,
+        )
+    }
+
+    pub fn debug_display_name_suffix(&self) -> Option<&str> {
+        self.debug_display_name_suffix.as_deref()
+    }
+
+    /// Whether `display-name` was set; platforms that didn't historically
+    /// use the stylized name only override their default when it is.
+    pub fn display_name_explicit(&self) -> bool {
+        self.display_name.is_some()
+    }
+
+    /// Display names keyed by BCP-47 language tag.
+    pub fn display_name_localized(&self) -> &BTreeMap<String, String> {
+        &self.display_name_localized
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// The marketing version both platforms default to, i.e. `1.4.0`.
+    pub fn version(&self) -> Option<VersionTriple> {
+        self.version
+    }
+
     pub fn reverse_domain(&self) -> String {
         self.domain
             .clone()
@@ -194,11 +505,140 @@ impl App {
         self.root_dir().join("Cargo.toml")
     }
 
+    /// The first asset dir, which is the only one unless `asset-dirs` is set.
     pub fn asset_dir(&self) -> PathBuf {
         self.root_dir().join(&self.asset_dir)
     }
 
+    pub fn asset_dirs(&self) -> &[AssetDir] {
+        &self.asset_dirs
+    }
+
+    /// Whether `asset-dir` or `asset-dirs` was set. We only create the default one
+    /// ourselves, since a missing custom one is more likely a typo.
+    pub fn asset_dir_explicit(&self) -> bool {
+        self.asset_dir_explicit
+    }
+
+    pub fn asset_strategy(&self) -> AssetStrategy {
+        self.asset_strategy
+    }
+
+    /// These live in the top-level `[hooks]` section, but they're kept here
+    /// so every platform's config can get at them.
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
     pub fn template_pack(&self) -> &Pack {
         &self.template_pack
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        tag,
+        valid,
+        case("fr", true),
+        case("pt-BR", true),
+        case("zh-Hans", true),
+        case("sr-Latn-RS", true),
+        case("es-419", true),
+        case("en_US", false),
+        case("english", false),
+        case("", false)
+    )]
+    fn bcp47_shape(tag: &str, valid: bool) {
+        assert_eq!(looks_like_bcp47(tag), valid);
+    }
+
+    #[test]
+    fn only_debug_builds_get_suffixes() {
+        let base = "com.example.app";
+        assert_eq!(
+            for_profile(base, Some(".debug"), Profile::Debug),
+            "com.example.app.debug"
+        );
+        assert_eq!(
+            for_profile(base, Some(".debug"), Profile::Release),
+            "com.example.app"
+        );
+        assert_eq!(for_profile(base, None, Profile::Debug), "com.example.app");
+    }
+
+    #[rstest(
+        suffix,
+        separator,
+        valid,
+        case(".debug", '_', true),
+        case(".debug.qa_1", '_', true),
+        case(".debug-qa", '-', true),
+        case(".debug-qa", '_', false),
+        case("debug", '_', false),
+        case(".", '_', false),
+        case(".debug..qa", '_', false),
+        case(".де", '-', false)
+    )]
+    fn id_suffixes(suffix: &str, separator: char, valid: bool) {
+        assert_eq!(id_suffix_valid(suffix, separator), valid);
+    }
+
+    fn parse_asset_dirs(toml: &str) -> Result<Vec<AssetDir>, Error> {
+        let raw = toml::from_str::<Raw>(&format!(
+            "name = \"game\"\ndomain = \"example.com\"\n{}",
+            toml
+        ))
+        .unwrap();
+        asset_dirs(raw.asset_dir, raw.asset_dirs, Path::new("/project"))
+    }
+
+    #[test]
+    fn asset_dir_is_shorthand() {
+        use AssetPlatform::*;
+        assert_eq!(
+            parse_asset_dirs("").unwrap(),
+            vec![AssetDir::new("assets", &[Android, Ios], "assets")]
+        );
+        assert_eq!(
+            parse_asset_dirs("asset-dir = \"res/assets\"").unwrap(),
+            vec![AssetDir::new("res/assets", &[Android, Ios], "assets")]
+        );
+    }
+
+    #[test]
+    fn parses_asset_dirs() {
+        use AssetPlatform::*;
+        let dirs = parse_asset_dirs(
+            r#"asset-dirs = [
+                { path = "assets", platforms = ["ios", "android"] },
+                { path = "assets-android", platforms = ["android"] },
+                { path = "shared/sounds", dest = "assets" },
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            dirs,
+            vec![
+                AssetDir::new("assets", &[Android, Ios], "assets"),
+                AssetDir::new("assets-android", &[Android], "assets-android"),
+                AssetDir::new("shared/sounds", &[Android, Ios], "assets"),
+            ]
+        );
+    }
+
+    #[rstest(
+        toml,
+        case("asset-dir = \"assets\"\nasset-dirs = [{ path = \"more\" }]"),
+        case("asset-dirs = []"),
+        case("asset-dirs = [{ path = \"assets\", platforms = [] }]"),
+        case("asset-dirs = [{ path = \"assets\", dest = \"a/b\" }]"),
+        case("asset-dirs = [{ path = \"../elsewhere\" }]")
+    )]
+    fn rejects_bad_asset_dirs(toml: &str) {
+        assert!(parse_asset_dirs(toml).is_err());
+    }
+}
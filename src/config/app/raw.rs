@@ -1,5 +1,7 @@
 use super::{common_email_providers::COMMON_EMAIL_PROVIDERS, domain, name};
 use crate::{
+    config::guide,
+    opts::Guided,
     templating,
     util::{cli::TextWrapper, prompt, Git},
 };
@@ -7,6 +9,7 @@ use colored::{Color, Colorize as _};
 use heck::{ToKebabCase as _, ToTitleCase as _};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     env,
     fmt::{self, Display},
     io,
@@ -115,11 +118,11 @@ impl Display for DetectError {
 #[derive(Debug)]
 pub enum PromptError {
     DefaultsFailed(DefaultsError),
-    NamePromptFailed(io::Error),
-    StylizedNamePromptFailed(io::Error),
-    DomainPromptFailed(io::Error),
+    NamePromptFailed(prompt::Error),
+    StylizedNamePromptFailed(prompt::Error),
+    DomainPromptFailed(prompt::Error),
     ListTemplatePacksFailed(templating::ListError),
-    TemplatePackPromptFailed(io::Error),
+    TemplatePackPromptFailed(prompt::Error),
 }
 
 impl Display for PromptError {
@@ -139,13 +142,28 @@ impl Display for PromptError {
     }
 }
 
+/// An entry in `asset-dirs`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RawAssetDir {
+    pub path: String,
+    pub platforms: Option<Vec<super::AssetPlatform>>,
+    pub dest: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Raw {
     pub name: String,
     pub stylized_name: Option<String>,
+    pub display_name: Option<String>,
+    pub debug_display_name_suffix: Option<String>,
+    pub display_name_localized: Option<BTreeMap<String, String>>,
     pub domain: String,
+    pub version: Option<String>,
     pub asset_dir: Option<String>,
+    pub asset_dirs: Option<Vec<RawAssetDir>>,
+    pub asset_strategy: Option<super::AssetStrategy>,
     pub template_pack: Option<String>,
 }
 
@@ -155,31 +173,74 @@ impl Raw {
         Ok(Self {
             name: defaults.name.ok_or_else(|| DetectError::NameNotDetected)?,
             stylized_name: Some(defaults.stylized_name),
+            display_name: None,
+            debug_display_name_suffix: None,
+            display_name_localized: None,
             domain: defaults.domain,
+            version: None,
             asset_dir: None,
+            asset_dirs: None,
+            asset_strategy: None,
             template_pack: Some(super::DEFAULT_TEMPLATE_PACK.to_owned())
                 .filter(|pack| pack != super::IMPLIED_TEMPLATE_PACK),
         })
     }
 
-    pub fn prompt(wrapper: &TextWrapper) -> Result<Self, PromptError> {
+    pub fn prompt(wrapper: &TextWrapper, guided: Guided) -> Result<Self, PromptError> {
         let defaults = Defaults::new(wrapper).map_err(PromptError::DefaultsFailed)?;
+        guide::NAME.print(guided, wrapper);
         let (name, default_stylized) = Self::prompt_name(wrapper, &defaults)?;
+        guide::STYLIZED_NAME.print(guided, wrapper);
         let stylized_name = Self::prompt_stylized_name(&name, default_stylized)?;
+        guide::DOMAIN.print(guided, wrapper);
         let domain = Self::prompt_domain(wrapper, &defaults)?;
+        guide::TEMPLATE_PACK.print(guided, wrapper);
         let template_pack = Some(Self::prompt_template_pack(wrapper)?)
             .filter(|pack| pack != super::IMPLIED_TEMPLATE_PACK);
         Ok(Self {
             name,
             stylized_name: Some(stylized_name),
+            display_name: None,
+            debug_display_name_suffix: None,
+            display_name_localized: None,
             domain,
+            version: None,
             asset_dir: None,
+            asset_dirs: None,
+            asset_strategy: None,
             template_pack,
         })
     }
 }
 
 impl Raw {
+    pub fn reprompt_name(&mut self, wrapper: &TextWrapper) -> Result<(), PromptError> {
+        let defaults = Defaults::new(wrapper).map_err(PromptError::DefaultsFailed)?;
+        let (name, _) = Self::prompt_name(wrapper, &defaults)?;
+        self.name = name;
+        Ok(())
+    }
+
+    pub fn reprompt_domain(&mut self, wrapper: &TextWrapper) -> Result<(), PromptError> {
+        let defaults = Defaults::new(wrapper).map_err(PromptError::DefaultsFailed)?;
+        self.domain = Self::prompt_domain(wrapper, &defaults)?;
+        Ok(())
+    }
+
+    pub fn reprompt_stylized_name(&mut self) -> Result<(), PromptError> {
+        self.stylized_name = Some(Self::prompt_stylized_name(
+            &self.name,
+            self.stylized_name.clone(),
+        )?);
+        Ok(())
+    }
+
+    pub fn reprompt_template_pack(&mut self, wrapper: &TextWrapper) -> Result<(), PromptError> {
+        self.template_pack = Some(Self::prompt_template_pack(wrapper)?)
+            .filter(|pack| pack != super::IMPLIED_TEMPLATE_PACK);
+        Ok(())
+    }
+
     fn prompt_name(
         wrapper: &TextWrapper,
         defaults: &Defaults,
@@ -189,6 +250,8 @@ impl Raw {
         let mut default_stylized = None;
         let name = loop {
             let response = prompt::default(
+                "config.app.name",
+                "app.name",
                 "Project name",
                 default_name.as_ref().map(|s| s.as_str()),
                 None,
@@ -228,14 +291,26 @@ impl Raw {
     ) -> Result<String, PromptError> {
         let stylized = default_stylized
             .unwrap_or_else(|| name.replace("-", " ").replace("_", " ").to_title_case());
-        prompt::default("Stylized name", Some(&stylized), None)
-            .map_err(PromptError::StylizedNamePromptFailed)
+        prompt::default(
+            "config.app.stylized-name",
+            "app.stylized-name",
+            "Stylized name",
+            Some(&stylized),
+            None,
+        )
+        .map_err(PromptError::StylizedNamePromptFailed)
     }
 
     fn prompt_domain(wrapper: &TextWrapper, defaults: &Defaults) -> Result<String, PromptError> {
         Ok(loop {
-            let response = prompt::default("Domain", Some(&defaults.domain), None)
-                .map_err(PromptError::DomainPromptFailed)?;
+            let response = prompt::default(
+                "config.app.domain",
+                "app.domain",
+                "Domain",
+                Some(&defaults.domain),
+                None,
+            )
+            .map_err(PromptError::DomainPromptFailed)?;
             match domain::check_domain_syntax(response.as_str()) {
                 Ok(_) => break response,
                 Err(err) => {
@@ -272,6 +347,8 @@ impl Raw {
         loop {
             println!("  Enter an {} for a template pack above.", "index".green(),);
             let pack_input = prompt::default(
+                "config.app.template-pack",
+                "app.template-pack",
                 "Template pack",
                 default_pack.as_deref(),
                 Some(Color::BrightGreen),
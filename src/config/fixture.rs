@@ -0,0 +1,50 @@
+//! A throwaway app for tests that need a loaded config, so they don't each
+//! write their own `mobile.toml` and `Cargo.toml`.
+
+use super::{metadata::Metadata, Config};
+use crate::{
+    opts,
+    util::{cli::TextWrapper, TempDirGuard},
+};
+use std::{fs, path::PathBuf};
+
+pub struct Fixture {
+    // Holds onto the dir until the test's done with it.
+    _dir: TempDirGuard,
+    pub root: PathBuf,
+    pub config: Config,
+    pub metadata: Metadata,
+}
+
+/// An app named `name`, with `config` appended to its `mobile.toml` and
+/// `metadata` appended to its `Cargo.toml`.
+pub fn app(name: &str, config: &str, metadata: &str) -> Fixture {
+    let dir = TempDirGuard::new().unwrap();
+    // Paths in the config are resolved, so this has to match them.
+    let root = dir.canonicalize().unwrap();
+    fs::write(
+        root.join(super::file_name()),
+        format!(
+            "[app]\nname = \"{}\"\ndomain = \"example.com\"\n{}",
+            name, config
+        ),
+    )
+    .unwrap();
+    fs::write(
+        root.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n{}",
+            name, metadata
+        ),
+    )
+    .unwrap();
+    let wrapper = TextWrapper::with_splitter(80, textwrap::NoHyphenation);
+    let (config, _) = Config::load_or_gen(&root, opts::NonInteractive::Yes, &wrapper).unwrap();
+    let metadata = Metadata::load(&root).unwrap();
+    Fixture {
+        _dir: dir,
+        root,
+        config,
+        metadata,
+    }
+}
@@ -0,0 +1,241 @@
+//! `cargo mobile init --guided`, which says what each value is for before
+//! prompting for it, and then shows everything that was picked so any of it
+//! can be changed before the config is written.
+
+use crate::{opts::Guided, util::cli::TextWrapper};
+use colored::Colorize as _;
+use std::io::{self, Write};
+
+/// What `--guided` says before prompting for a value.
+#[derive(Clone, Copy, Debug)]
+pub struct Explanation {
+    pub what: &'static str,
+    /// What happens if it's changed once the project's been generated.
+    pub later: &'static str,
+    pub example: &'static str,
+}
+
+impl Explanation {
+    pub fn print(&self, guided: Guided, wrapper: &TextWrapper) {
+        if guided.yes() {
+            println!();
+            println!("{}", wrapper.fill(self.what).bright_white().bold());
+            println!(
+                "{}",
+                wrapper.fill(&format!("Changing it later: {}", self.later))
+            );
+            println!(
+                "{}",
+                wrapper
+                    .fill(&format!("For example: {}", self.example))
+                    .bright_black()
+            );
+        }
+    }
+}
+
+pub static NAME: Explanation = Explanation {
+    what: "The project name is the Rust package name, and what the Android package, Xcode project, and lib are called.",
+    later: "you'd have to regenerate both projects, and the app stores see an app with a new name as a different app.",
+    example: "space-invaders",
+};
+
+pub static STYLIZED_NAME: Explanation = Explanation {
+    what: "The stylized name is what people see under the app's icon and in its title bar.",
+    later: "it's only display text, so change it whenever you like and re-run `cargo mobile init`.",
+    example: "Space Invaders",
+};
+
+pub static DOMAIN: Explanation = Explanation {
+    what: "The domain is reversed and combined with the project name to make the bundle ID and application ID, i.e. `com.example.space-invaders`. It doesn't have to be a domain you own, but it should be unique to you.",
+    later: "once you've shipped, the App Store and Play Store see a new ID as a different app.",
+    example: "example.com",
+};
+
+pub static TEMPLATE_PACK: Explanation = Explanation {
+    what: "The template pack is the starting code for your app, i.e. which engine or UI library it's set up to use.",
+    later: "it's only used when generating files, so changing it won't touch code you've written.",
+    example: "bevy",
+};
+
+pub static DEVELOPMENT_TEAM: Explanation = Explanation {
+    what: "The development team is the Apple team builds are signed as, which is required to run on a device.",
+    later: "set `development-team` in `mobile.toml` and re-run `cargo mobile init`.",
+    example: "ABCDE12345",
+};
+
+/// A value on the review screen.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Answer {
+    pub label: &'static str,
+    pub value: String,
+}
+
+impl Answer {
+    pub fn new(label: &'static str, value: impl Into<String>) -> Self {
+        Self {
+            label,
+            value: value.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Choice {
+    Confirm,
+    /// The index of the answer to change.
+    Edit(usize),
+}
+
+/// What to make of `input` on a review screen with `count` answers. They're
+/// numbered from 1, since that's how they're shown.
+pub fn parse_choice(input: &str, count: usize) -> Option<Choice> {
+    let input = input.trim();
+    if input.is_empty() || input.eq_ignore_ascii_case("y") || input.eq_ignore_ascii_case("yes") {
+        Some(Choice::Confirm)
+    } else {
+        input
+            .parse::<usize>()
+            .ok()
+            .filter(|number| (1..=count).contains(number))
+            .map(|number| Choice::Edit(number - 1))
+    }
+}
+
+pub fn render(answers: &[Answer]) -> String {
+    answers
+        .iter()
+        .enumerate()
+        .map(|(index, answer)| format!("  [{}] {}: {}\n", index + 1, answer.label, answer.value))
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum ReviewError<E> {
+    PromptFailed(io::Error),
+    EditFailed(E),
+}
+
+/// Shows the `answers` in `state` until they're confirmed, calling `edit`
+/// with the index of each one picked for a change. `ask` prompts for a line
+/// and gives back what was typed.
+pub fn review<S, E>(
+    state: &mut S,
+    output: &mut impl Write,
+    mut ask: impl FnMut(&str) -> io::Result<String>,
+    answers: impl Fn(&S) -> Vec<Answer>,
+    mut edit: impl FnMut(&mut S, usize) -> Result<(), E>,
+) -> Result<(), ReviewError<E>> {
+    loop {
+        let current = answers(state);
+        write!(
+            output,
+            "\nHere's everything you picked. Nothing's been written yet.\n{}",
+            render(&current)
+        )
+        .and_then(|()| output.flush())
+        .map_err(ReviewError::PromptFailed)?;
+        let input = ask("Enter a number to change that value, or press enter to go ahead")
+            .map_err(ReviewError::PromptFailed)?;
+        match parse_choice(&input, current.len()) {
+            Some(Choice::Confirm) => return Ok(()),
+            Some(Choice::Edit(index)) => edit(state, index).map_err(ReviewError::EditFailed)?,
+            None => writeln!(output, "{:?} isn't one of the numbers above.", input.trim())
+                .map_err(ReviewError::PromptFailed)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+    use std::collections::VecDeque;
+
+    #[rstest(
+        input,
+        expected,
+        case("", Some(Choice::Confirm)),
+        case(" Y\n", Some(Choice::Confirm)),
+        case("yes", Some(Choice::Confirm)),
+        case("1", Some(Choice::Edit(0))),
+        case(" 3 ", Some(Choice::Edit(2))),
+        case("0", None),
+        case("4", None),
+        case("n", None)
+    )]
+    fn parses_choices(input: &str, expected: Option<Choice>) {
+        assert_eq!(parse_choice(input, 3), expected);
+    }
+
+    /// Runs the review with `inputs` typed in turn, where editing sets a value
+    /// to `edited`. Gives back the values and everything that was printed.
+    fn run(inputs: &[&str]) -> (Result<Vec<String>, String>, String) {
+        let mut inputs = inputs
+            .iter()
+            .map(|s| (*s).to_owned())
+            .collect::<VecDeque<_>>();
+        let mut values = vec!["tic-tac".to_owned(), "example.com".to_owned()];
+        let mut output = Vec::new();
+        let result = review(
+            &mut values,
+            &mut output,
+            |_| {
+                inputs
+                    .pop_front()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "out of input"))
+            },
+            |values: &Vec<String>| {
+                vec![
+                    Answer::new("Project name", values[0].clone()),
+                    Answer::new("Domain", values[1].clone()),
+                ]
+            },
+            |values, index| {
+                values[index] = "edited".to_owned();
+                Ok::<_, String>(())
+            },
+        );
+        let result = match result {
+            Ok(()) => Ok(values),
+            Err(ReviewError::EditFailed(err)) => Err(err),
+            Err(ReviewError::PromptFailed(err)) => Err(err.to_string()),
+        };
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn confirming_keeps_everything() {
+        let (result, output) = run(&[""]);
+        assert_eq!(
+            result,
+            Ok(vec!["tic-tac".to_owned(), "example.com".to_owned()])
+        );
+        assert!(output.contains("  [1] Project name: tic-tac\n  [2] Domain: example.com\n"));
+    }
+
+    #[test]
+    fn edits_until_confirmed() {
+        let (result, output) = run(&["2", "y"]);
+        assert_eq!(result, Ok(vec!["tic-tac".to_owned(), "edited".to_owned()]));
+        // The screen is shown again with the new value.
+        assert!(output.contains("  [2] Domain: edited\n"));
+    }
+
+    #[test]
+    fn asks_again_after_bad_input() {
+        let (result, output) = run(&["7", "domain", "1", ""]);
+        assert_eq!(
+            result,
+            Ok(vec!["edited".to_owned(), "example.com".to_owned()])
+        );
+        assert!(output.contains("\"7\" isn't one of the numbers above."));
+        assert!(output.contains("\"domain\" isn't one of the numbers above."));
+    }
+
+    #[test]
+    fn stops_when_input_runs_out() {
+        let (result, _) = run(&["1"]);
+        assert_eq!(result, Err("out of input".to_owned()));
+    }
+}
@@ -15,6 +15,8 @@ pub enum Error {
         path: PathBuf,
         cause: toml::de::Error,
     },
+    #[cfg(target_os = "macos")]
+    AppleBuildScriptInvalid(crate::apple::config::BuildScriptInvalid),
 }
 
 impl Reportable for Error {
@@ -28,6 +30,8 @@ impl Reportable for Error {
                 msg,
                 format!("Failed to parse contents of {:?}: {}", path, cause),
             ),
+            #[cfg(target_os = "macos")]
+            Self::AppleBuildScriptInvalid(err) => Report::error(msg, err),
         }
     }
 }
@@ -61,7 +65,13 @@ impl Metadata {
         })?;
         let cargo_toml = toml::from_slice::<CargoToml>(&bytes)
             .map_err(|cause| Error::ParseFailed { path, cause })?;
-        Ok(cargo_toml.package.metadata.unwrap_or_default())
+        let metadata = cargo_toml.package.metadata.unwrap_or_default();
+        #[cfg(target_os = "macos")]
+        metadata
+            .apple
+            .validate_build_scripts(project_root)
+            .map_err(Error::AppleBuildScriptInvalid)?;
+        Ok(metadata)
     }
 
     #[cfg(target_os = "macos")]
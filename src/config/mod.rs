@@ -1,20 +1,29 @@
 pub mod app;
+#[cfg(test)]
+pub mod fixture;
+pub mod guide;
 pub mod metadata;
+pub mod overrides;
 mod raw;
+pub mod version;
 
 use self::{app::App, raw::*};
 #[cfg(target_os = "macos")]
 use crate::apple;
 use crate::{
-    android,
-    opts::NonInteractive,
+    android, hooks,
+    opts::{Guided, NonInteractive},
     templating,
-    util::cli::{Report, Reportable, TextWrapper},
+    util::{
+        self,
+        cli::{Report, Reportable, TextWrapper},
+        prompt, tools, watchdog,
+    },
 };
 use serde::Serialize;
 use std::{
     fmt::Debug,
-    io,
+    fs, io,
     path::{Path, PathBuf},
 };
 
@@ -28,6 +37,7 @@ pub enum FromRawError {
     #[cfg(target_os = "macos")]
     AppleConfigInvalid(apple::config::Error),
     AndroidConfigInvalid(android::config::Error),
+    HooksInvalid(hooks::Error),
 }
 
 impl FromRawError {
@@ -37,6 +47,28 @@ impl FromRawError {
             #[cfg(target_os = "macos")]
             Self::AppleConfigInvalid(err) => err.report(msg),
             Self::AndroidConfigInvalid(err) => err.report(msg),
+            Self::HooksInvalid(err) => err.report(msg),
+        }
+    }
+
+    /// The field responsible for this error, if it can be re-prompted for on
+    /// its own.
+    pub fn field(&self) -> Option<Field> {
+        match self {
+            Self::AppConfigInvalid(app::Error::NameInvalid(_)) => Some(Field::AppName),
+            Self::AppConfigInvalid(app::Error::DomainInvalid { .. }) => Some(Field::AppDomain),
+            #[cfg(target_os = "macos")]
+            Self::AppleConfigInvalid(err) => match err {
+                apple::config::Error::DevelopmentTeamEmpty => Some(Field::AppleDevelopmentTeam),
+                apple::config::Error::ProjectDirInvalid(_) => Some(Field::AppleProjectDir),
+                apple::config::Error::IosVersionInvalid(_) => Some(Field::AppleIosVersion),
+                apple::config::Error::MacOsVersionInvalid(_) => Some(Field::AppleMacOsVersion),
+                _ => None,
+            },
+            Self::AndroidConfigInvalid(android::config::Error::ProjectDirInvalid(_)) => {
+                Some(Field::AndroidProjectDir)
+            }
+            _ => None,
         }
     }
 }
@@ -65,10 +97,243 @@ impl Reportable for GenError {
     }
 }
 
+fn from_raw_failed_report(path: &Path, causes: &[FromRawError]) -> Report {
+    let msg = format!("Config file at {:?} invalid", path);
+    if let [cause] = causes {
+        cause.report(&msg)
+    } else {
+        Report::error(
+            &msg,
+            causes
+                .iter()
+                .map(|cause| cause.report(&msg).details().to_owned())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadExistingError {
+    LoadFailed(LoadError),
+    NotFound {
+        cwd: PathBuf,
+    },
+    FromRawFailed {
+        path: PathBuf,
+        causes: Vec<FromRawError>,
+    },
+}
+
+impl Reportable for LoadExistingError {
+    fn report(&self) -> Report {
+        match self {
+            Self::LoadFailed(err) => Report::error("Failed to load config", err),
+            Self::NotFound { cwd } => Report::action_request(
+                "Please run `cargo mobile init` and try again!",
+                format!(
+                    "No {} was found in {:?} or any of its parents.",
+                    file_name(),
+                    cwd
+                ),
+            ),
+            Self::FromRawFailed { path, causes } => from_raw_failed_report(path, causes),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MigrateFileError {
+    DiscoverFailed(io::Error),
+    NotFound { cwd: PathBuf },
+    ReadFailed { path: PathBuf, cause: io::Error },
+    LoadFailed(LoadError),
+    SerializeFailed(toml::ser::Error),
+    PromptFailed(prompt::Error),
+    WriteFailed(WriteError),
+}
+
+impl Reportable for MigrateFileError {
+    fn report(&self) -> Report {
+        match self {
+            Self::DiscoverFailed(err) => Report::error("Failed to search for config file", err),
+            Self::NotFound { cwd } => Report::action_request(
+                "Please run `cargo mobile init` and try again!",
+                format!(
+                    "No {} was found in {:?} or any of its parents.",
+                    file_name(),
+                    cwd
+                ),
+            ),
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to read config file at {:?}", path), cause)
+            }
+            Self::LoadFailed(err) => Report::error("Failed to load config", err),
+            Self::SerializeFailed(err) => Report::error("Failed to serialize migrated config", err),
+            Self::PromptFailed(err) => err.report(),
+            Self::WriteFailed(err) => err.report(),
+        }
+    }
+}
+
+/// Rewrites the config file at the current version, after showing what'll
+/// change. Loading migrates in memory on its own, so this is never required.
+pub fn migrate_file(
+    cwd: impl AsRef<Path>,
+    non_interactive: NonInteractive,
+) -> Result<(), MigrateFileError> {
+    let cwd = cwd.as_ref();
+    let root_dir = Raw::discover_root(cwd)
+        .map_err(MigrateFileError::DiscoverFailed)?
+        .ok_or_else(|| MigrateFileError::NotFound {
+            cwd: cwd.to_owned(),
+        })?;
+    let path = root_dir.join(file_name());
+    let old = fs::read_to_string(&path).map_err(|cause| MigrateFileError::ReadFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    let (raw, version) = Raw::parse(&path, old.as_bytes()).map_err(MigrateFileError::LoadFailed)?;
+    if version == migrate::CURRENT_VERSION {
+        println!(
+            "{} is already at config version {}; nothing to do!",
+            file_name(),
+            version
+        );
+        return Ok(());
+    }
+    // Exactly what'll be written, comments and all.
+    let new = raw
+        .render(Some(&old))
+        .map_err(MigrateFileError::SerializeFailed)?;
+    println!(
+        "Migrating {} from config version {} to {}:\n{}",
+        file_name(),
+        version,
+        migrate::CURRENT_VERSION,
+        util::diff_lines(&old, &new)
+    );
+    if non_interactive.no()
+        && prompt::yes_no(
+            "config.migrate.write",
+            "--non-interactive",
+            "Write these changes?",
+            Some(prompt::YesOrNo::Yes),
+        )
+        .map_err(MigrateFileError::PromptFailed)?
+        .unwrap_or(prompt::YesOrNo::No)
+        .no()
+    {
+        println!("Left {:?} unchanged.", path);
+        return Ok(());
+    }
+    raw.write(&root_dir)
+        .map_err(MigrateFileError::WriteFailed)?;
+    println!("Wrote migrated config to {:?}", path);
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum SetSigningError {
+    DiscoverFailed(io::Error),
+    NotFound {
+        cwd: PathBuf,
+    },
+    ReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ParseFailed {
+        path: PathBuf,
+        cause: toml_edit::TomlError,
+    },
+    WriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Reportable for SetSigningError {
+    fn report(&self) -> Report {
+        match self {
+            Self::DiscoverFailed(err) => Report::error("Failed to search for config file", err),
+            Self::NotFound { cwd } => Report::action_request(
+                "Please run `cargo mobile init` and try again!",
+                format!(
+                    "No {} was found in {:?} or any of its parents.",
+                    file_name(),
+                    cwd
+                ),
+            ),
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to read config file at {:?}", path), cause)
+            }
+            Self::ParseFailed { path, cause } => {
+                Report::error(format!("Failed to parse config file at {:?}", path), cause)
+            }
+            Self::WriteFailed { path, cause } => {
+                Report::error(format!("Failed to write config file at {:?}", path), cause)
+            }
+        }
+    }
+}
+
+/// `contents` with `signing` as its `android.signing` section. This goes
+/// through `toml_edit` so everything else, comments included, stays as it
+/// was.
+fn with_android_signing(
+    contents: &str,
+    signing: &android::config::Signing,
+) -> Result<String, toml_edit::TomlError> {
+    let mut doc = contents.parse::<toml_edit::Document>()?;
+    let mut table = toml_edit::Table::new();
+    table["keystore"] = toml_edit::value(signing.keystore.display().to_string());
+    table["key-alias"] = toml_edit::value(signing.key_alias.as_str());
+    table["keystore-password-env"] = toml_edit::value(signing.keystore_password_env.as_str());
+    doc["android"].or_insert(toml_edit::table())["signing"] = toml_edit::Item::Table(table);
+    Ok(doc.to_string())
+}
+
+/// Writes `signing` into the `android.signing` section of the config file,
+/// returning the path of the file.
+pub fn set_android_signing(
+    cwd: impl AsRef<Path>,
+    signing: android::config::Signing,
+) -> Result<PathBuf, SetSigningError> {
+    let cwd = cwd.as_ref();
+    let root_dir = Raw::discover_root(cwd)
+        .map_err(SetSigningError::DiscoverFailed)?
+        .ok_or_else(|| SetSigningError::NotFound {
+            cwd: cwd.to_owned(),
+        })?;
+    let path = root_dir.join(file_name());
+    let contents = fs::read_to_string(&path).map_err(|cause| SetSigningError::ReadFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    let contents = with_android_signing(&contents, &signing).map_err(|cause| {
+        SetSigningError::ParseFailed {
+            path: path.clone(),
+            cause,
+        }
+    })?;
+    log::info!("writing config to {:?}", path);
+    fs::write(&path, contents).map_err(|cause| SetSigningError::WriteFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    Ok(path)
+}
+
 #[derive(Debug)]
 pub enum LoadOrGenError {
     LoadFailed(LoadError),
-    FromRawFailed { path: PathBuf, cause: FromRawError },
+    FromRawFailed {
+        path: PathBuf,
+        causes: Vec<FromRawError>,
+    },
+    RepairPromptFailed(PromptError),
+    RepairWriteFailed(WriteError),
     GenFailed(GenError),
 }
 
@@ -76,10 +341,9 @@ impl Reportable for LoadOrGenError {
     fn report(&self) -> Report {
         match self {
             Self::LoadFailed(err) => Report::error("Failed to load config", err),
-            Self::FromRawFailed { path, cause } => {
-                let msg = format!("Config file at {:?} invalid", path);
-                cause.report(&msg)
-            }
+            Self::FromRawFailed { path, causes } => from_raw_failed_report(path, causes),
+            Self::RepairPromptFailed(err) => err.report(),
+            Self::RepairWriteFailed(err) => err.report(),
             Self::GenFailed(err) => err.report(),
         }
     }
@@ -105,32 +369,49 @@ pub struct Config {
     apple: apple::config::Config,
     android: android::config::Config,
     env: Option<toml::value::Table>,
+    #[serde(skip)]
+    tools: tools::Tools,
 }
 
 impl Config {
     fn from_raw(root_dir: PathBuf, raw: Raw) -> Result<Self, FromRawError> {
-        let app = App::from_raw(root_dir, raw.app).map_err(FromRawError::AppConfigInvalid)?;
+        let tools = tools::Tools::from_raw(&root_dir, raw.tools);
+        let hooks = hooks::Hooks::from_raw(raw.hooks).map_err(FromRawError::HooksInvalid)?;
+        let app =
+            App::from_raw(root_dir, raw.app, hooks).map_err(FromRawError::AppConfigInvalid)?;
         #[cfg(target_os = "macos")]
         let apple = apple::config::Config::from_raw(app.clone(), raw.apple)
             .map_err(FromRawError::AppleConfigInvalid)?;
         let android = android::config::Config::from_raw(app.clone(), raw.android)
             .map_err(FromRawError::AndroidConfigInvalid)?;
-        Ok(Self {
+        watchdog::configure(watchdog::Timeouts::from_raw(raw.timeouts));
+        tools::configure(tools.clone());
+        let config = Self {
             app,
             #[cfg(target_os = "macos")]
             apple,
             android,
             env: raw.env,
-        })
+            tools,
+        };
+        // This is loaded on every run, so don't serialize it for nothing.
+        if log::log_enabled!(log::Level::Debug) {
+            match serde_json::to_string(&config) {
+                Ok(summary) => log::debug!("resolved config: {}", summary),
+                Err(err) => log::debug!("failed to summarize resolved config: {}", err),
+            }
+        }
+        Ok(config)
     }
 
     fn gen(
         cwd: impl AsRef<Path>,
         non_interactive: NonInteractive,
+        guided: Guided,
         wrapper: &TextWrapper,
     ) -> Result<Self, GenError> {
         let raw = if non_interactive.no() {
-            Raw::prompt(wrapper).map_err(GenError::PromptFailed)
+            Raw::prompt(wrapper, guided).map_err(GenError::PromptFailed)
         } else {
             Raw::detect(wrapper).map_err(GenError::DetectFailed)
         }?;
@@ -145,26 +426,130 @@ impl Config {
         Ok(config)
     }
 
+    // Finds as many invalid fields as possible by stubbing out each
+    // repairable one we hit and trying again.
+    fn collect_from_raw_errors(root_dir: &Path, mut raw: Raw) -> Vec<FromRawError> {
+        let mut causes: Vec<FromRawError> = Vec::new();
+        while let Err(cause) = Self::from_raw(root_dir.to_owned(), raw.clone()) {
+            let field = cause
+                .field()
+                .filter(|field| causes.iter().all(|other| other.field() != Some(*field)));
+            causes.push(cause);
+            if let Some(field) = field {
+                raw.stub(field);
+            } else {
+                break;
+            }
+        }
+        causes
+    }
+
+    fn from_raw_or_repair(
+        root_dir: PathBuf,
+        mut raw: Raw,
+        non_interactive: NonInteractive,
+        wrapper: &TextWrapper,
+    ) -> Result<Self, LoadOrGenError> {
+        let mut repaired = false;
+        loop {
+            match Self::from_raw(root_dir.clone(), raw.clone()) {
+                Ok(config) => {
+                    if repaired {
+                        raw.write(&root_dir)
+                            .map_err(LoadOrGenError::RepairWriteFailed)?;
+                    }
+                    break Ok(config);
+                }
+                Err(cause) => match cause.field() {
+                    // Repairs get written back, which overrides never should.
+                    Some(field) if non_interactive.no() && overrides::get().is_empty() => {
+                        let msg = format!("{} needs a new value", field);
+                        Report::action_request(&msg, cause.report(&msg).details()).print(wrapper);
+                        raw.repair(field, wrapper)
+                            .map_err(LoadOrGenError::RepairPromptFailed)?;
+                        repaired = true;
+                    }
+                    _ => {
+                        break Err(LoadOrGenError::FromRawFailed {
+                            path: root_dir.clone(),
+                            causes: if non_interactive.yes() {
+                                Self::collect_from_raw_errors(&root_dir, raw)
+                            } else {
+                                vec![cause]
+                            },
+                        })
+                    }
+                },
+            }
+        }
+    }
+
+    // `Raw::load` with `--config` overrides applied.
+    fn load_raw(cwd: &Path) -> Result<Option<(PathBuf, Raw)>, LoadError> {
+        Raw::load(cwd)?
+            .map(|(root_dir, raw)| {
+                raw.with_overrides(overrides::get())
+                    .map(|raw| (root_dir, raw))
+                    .map_err(LoadError::OverrideFailed)
+            })
+            .transpose()
+    }
+
+    /// The resolved config as TOML, with `--config` overrides applied, or
+    /// `None` if anything at all goes wrong. If it doesn't resolve, which
+    /// might be what crashed, this falls back to the raw config. This is for
+    /// crash reports, which take what they can get.
+    pub fn load_toml(cwd: impl AsRef<Path>) -> Option<toml::Value> {
+        let (root_dir, raw) = Self::load_raw(cwd.as_ref()).ok()??;
+        Self::from_raw(root_dir, raw.clone())
+            .ok()
+            .and_then(|config| toml::Value::try_from(&config).ok())
+            .or_else(|| toml::Value::try_from(&raw).ok())
+    }
+
     pub fn load_or_gen(
         cwd: impl AsRef<Path>,
         non_interactive: NonInteractive,
         wrapper: &TextWrapper,
+    ) -> Result<(Self, Origin), LoadOrGenError> {
+        Self::load_or_gen_guided(cwd, non_interactive, Guided::No, wrapper)
+    }
+
+    /// `load_or_gen`, but explaining each prompt and reviewing the answers if
+    /// it comes to generating.
+    pub fn load_or_gen_guided(
+        cwd: impl AsRef<Path>,
+        non_interactive: NonInteractive,
+        guided: Guided,
+        wrapper: &TextWrapper,
     ) -> Result<(Self, Origin), LoadOrGenError> {
         let cwd = cwd.as_ref();
-        if let Some((root_dir, raw)) = Raw::load(cwd).map_err(LoadOrGenError::LoadFailed)? {
-            Self::from_raw(root_dir.clone(), raw)
+        if let Some((root_dir, raw)) = Self::load_raw(cwd).map_err(LoadOrGenError::LoadFailed)? {
+            Self::from_raw_or_repair(root_dir, raw, non_interactive, wrapper)
                 .map(|config| (config, Origin::Loaded))
-                .map_err(|cause| LoadOrGenError::FromRawFailed {
-                    path: root_dir,
-                    cause,
-                })
         } else {
-            Self::gen(cwd, non_interactive, wrapper)
+            Self::gen(cwd, non_interactive, guided, wrapper)
                 .map(|config| (config, Origin::FreshlyMinted))
                 .map_err(LoadOrGenError::GenFailed)
         }
     }
 
+    /// Loads the config without ever generating, prompting, or repairing.
+    pub fn load(cwd: impl AsRef<Path>) -> Result<Self, LoadExistingError> {
+        let cwd = cwd.as_ref();
+        let (root_dir, raw) = Self::load_raw(cwd)
+            .map_err(LoadExistingError::LoadFailed)?
+            .ok_or_else(|| LoadExistingError::NotFound {
+                cwd: cwd.to_owned(),
+            })?;
+        Self::from_raw(root_dir.clone(), raw.clone()).map_err(|_| {
+            LoadExistingError::FromRawFailed {
+                causes: Self::collect_from_raw_errors(&root_dir, raw),
+                path: root_dir,
+            }
+        })
+    }
+
     pub fn path(&self) -> PathBuf {
         self.app().root_dir().join(file_name())
     }
@@ -186,7 +571,38 @@ impl Config {
         &self.env
     }
 
+    pub fn tools(&self) -> &tools::Tools {
+        &self.tools
+    }
+
     pub fn build_a_bike(&self) -> bicycle::Bicycle {
         templating::init(Some(self))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn setting_signing_keeps_comments() {
+        let contents = r#"# The app's config.
+[app]
+name = "example" # don't change this
+
+[android]
+# Keep this low.
+min-sdk-version = 24
+"#;
+        let signing = android::config::Signing {
+            keystore: PathBuf::from("example.keystore"),
+            key_alias: "upload".to_owned(),
+            keystore_password_env: "EXAMPLE_KEYSTORE_PASSWORD".to_owned(),
+        };
+        let written = with_android_signing(contents, &signing).unwrap();
+        assert!(written.starts_with(contents));
+        assert!(written.contains(
+            "[android.signing]\nkeystore = \"example.keystore\"\nkey-alias = \"upload\"\nkeystore-password-env = \"EXAMPLE_KEYSTORE_PASSWORD\"\n"
+        ));
+    }
+}
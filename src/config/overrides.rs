@@ -0,0 +1,291 @@
+//! `--config <key>=<value>`, for overriding a value from `mobile.toml` for a
+//! single run without editing the file. Overrides are patched into the file's
+//! TOML before it's deserialized, so they're validated like anything else,
+//! and keys the config doesn't have are rejected instead of ignored.
+
+use once_cell_regex::exports::once_cell::sync::OnceCell;
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum ParseError {
+    #[error("{arg:?} needs to look like `<key>=<value>`, i.e. `android.min-sdk-version=26`")]
+    EqualsMissing { arg: String },
+    #[error("{key:?} has an empty part; keys are dotted paths, i.e. `apple.development-team`")]
+    KeyInvalid { key: String },
+}
+
+#[derive(Debug, Error)]
+pub enum ApplyError {
+    #[error("`{key}` can't be overridden, since `{at}` is an array")]
+    ArrayInPath { key: String, at: String },
+    #[error("`{key}` can't be overridden, since `{at}` is a {kind}, not a table")]
+    NotATable {
+        key: String,
+        at: String,
+        kind: &'static str,
+    },
+    #[error("`{key}` isn't a config key, so overriding it wouldn't do anything")]
+    KeyUnknown { key: String },
+    #[error("Failed to read the config back after applying `--config` overrides: {0}")]
+    SerializeFailed(#[from] toml::ser::Error),
+    #[error("Config is invalid after applying `--config` overrides: {0}")]
+    DeserializeFailed(#[from] toml::de::Error),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Override {
+    path: Vec<String>,
+    value: toml::Value,
+}
+
+impl Override {
+    pub fn key(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+impl Display for Override {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key(), self.value)
+    }
+}
+
+// Anything that isn't valid TOML is taken as a bare string, so
+// `apple.development-team=ABCDE12345` doesn't need quotes.
+fn parse_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::value::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_owned()))
+}
+
+impl FromStr for Override {
+    type Err = ParseError;
+
+    fn from_str(arg: &str) -> Result<Self, Self::Err> {
+        let (key, value) = arg
+            .split_once('=')
+            .ok_or_else(|| ParseError::EqualsMissing {
+                arg: arg.to_owned(),
+            })?;
+        let path = key
+            .split('.')
+            .map(|part| part.trim().to_owned())
+            .collect::<Vec<_>>();
+        if path.iter().any(String::is_empty) {
+            return Err(ParseError::KeyInvalid {
+                key: key.to_owned(),
+            });
+        }
+        Ok(Self {
+            path,
+            value: parse_value(value.trim()),
+        })
+    }
+}
+
+/// Sets each override in `table`, in order, creating intermediate tables as
+/// needed. Going through an array or a value that isn't a table is an error.
+pub fn apply(table: &mut toml::value::Table, overrides: &[Override]) -> Result<(), ApplyError> {
+    for over in overrides {
+        let (last, parents) = over
+            .path
+            .split_last()
+            .expect("developer error: override key was empty");
+        let mut current = &mut *table;
+        for (index, part) in parents.iter().enumerate() {
+            let value = current
+                .entry(part.clone())
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+            let at = || over.path[..=index].join(".");
+            current = match value {
+                toml::Value::Table(table) => table,
+                toml::Value::Array(_) => {
+                    return Err(ApplyError::ArrayInPath {
+                        key: over.key(),
+                        at: at(),
+                    })
+                }
+                other => {
+                    return Err(ApplyError::NotATable {
+                        key: over.key(),
+                        at: at(),
+                        kind: other.type_str(),
+                    })
+                }
+            };
+        }
+        log::info!(
+            "overriding `{}` with {} from `--config`",
+            over.key(),
+            over.value
+        );
+        current.insert(last.clone(), over.value.clone());
+    }
+    Ok(())
+}
+
+fn contains(table: &toml::value::Table, path: &[String]) -> bool {
+    match path.split_first() {
+        Some((first, [])) => table.contains_key(first),
+        Some((first, rest)) => match table.get(first) {
+            Some(toml::Value::Table(table)) => contains(table, rest),
+            _ => false,
+        },
+        None => true,
+    }
+}
+
+/// Checks that every override made it into `parsed`, which is the config
+/// serialized again after it was deserialized with the overrides applied.
+/// Deserializing drops keys the config doesn't have, so anything missing was
+/// a typo, or is just not a thing.
+pub fn check_known(parsed: &toml::value::Table, overrides: &[Override]) -> Result<(), ApplyError> {
+    for over in overrides {
+        // There's no Apple config to keep these off macOS, but they're still
+        // fine to pass, i.e. from a script shared with Mac users.
+        if cfg!(not(target_os = "macos")) && over.path[0] == "apple" {
+            continue;
+        }
+        if !contains(parsed, &over.path) {
+            return Err(ApplyError::KeyUnknown { key: over.key() });
+        }
+    }
+    Ok(())
+}
+
+static OVERRIDES: OnceCell<Vec<Override>> = OnceCell::new();
+
+/// Only the first call counts, which is the one made while handling the
+/// global flags.
+pub fn set(overrides: Vec<Override>) {
+    let _ = OVERRIDES.set(overrides);
+}
+
+pub fn get() -> &'static [Override] {
+    OVERRIDES.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn table(toml: &str) -> toml::value::Table {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn overrides(args: &[&str]) -> Vec<Override> {
+        args.iter().map(|arg| arg.parse().unwrap()).collect()
+    }
+
+    #[rstest(
+        arg,
+        key,
+        value,
+        case("android.min-sdk-version=26", "android.min-sdk-version", toml::Value::Integer(26)),
+        case("apple.development-team=ABCDE12345", "apple.development-team", toml::Value::String("ABCDE12345".to_owned())),
+        case("apple.project-dir=\"gen/experiment\"", "apple.project-dir", toml::Value::String("gen/experiment".to_owned())),
+        case("apple.enable-bitcode=true", "apple.enable-bitcode", toml::Value::Boolean(true)),
+        case(" app.name = game ", "app.name", toml::Value::String("game".to_owned())),
+        case("app.domain=a=b", "app.domain", toml::Value::String("a=b".to_owned())),
+        case("env.EMPTY=", "env.EMPTY", toml::Value::String(String::new()))
+    )]
+    fn parses_overrides(arg: &str, key: &str, value: toml::Value) {
+        let over = arg.parse::<Override>().unwrap();
+        assert_eq!((over.key(), over.value), (key.to_owned(), value));
+    }
+
+    #[rstest(
+        arg,
+        expected,
+        case("android.min-sdk-version", ParseError::EqualsMissing { arg: "android.min-sdk-version".to_owned() }),
+        case("android..min-sdk-version=26", ParseError::KeyInvalid { key: "android..min-sdk-version".to_owned() }),
+        case("=26", ParseError::KeyInvalid { key: "".to_owned() })
+    )]
+    fn rejects_malformed_overrides(arg: &str, expected: ParseError) {
+        assert_eq!(arg.parse::<Override>(), Err(expected));
+    }
+
+    #[test]
+    fn replaces_and_creates_values() {
+        let mut config = table(
+            r#"
+            [app]
+            name = "game"
+
+            [android]
+            min-sdk-version = 24
+            "#,
+        );
+        apply(
+            &mut config,
+            &overrides(&[
+                "android.min-sdk-version=26",
+                "apple.development-team=ABCDE12345",
+                "android.min-sdk-version=28",
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            table(
+                r#"
+                [app]
+                name = "game"
+
+                [android]
+                min-sdk-version = 28
+
+                [apple]
+                development-team = "ABCDE12345"
+                "#,
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_arrays_in_the_path() {
+        let mut config = table(r#"android = { targets = ["aarch64"] }"#);
+        assert!(matches!(
+            apply(&mut config, &overrides(&["android.targets.first=armv7"])),
+            Err(ApplyError::ArrayInPath { at, .. }) if at == "android.targets"
+        ));
+    }
+
+    #[rstest(
+        arg,
+        known,
+        case("android.min-sdk-version=26", true),
+        case("env.FOO=bar", true),
+        case("android.min-sdk-versoin=26", false),
+        case("androd.min-sdk-version=26", false),
+        case("android.min-sdk-version.first=26", false)
+    )]
+    fn checks_keys_are_known(arg: &str, known: bool) {
+        let parsed = table(
+            r#"
+            [android]
+            min-sdk-version = 26
+
+            [env]
+            FOO = "bar"
+            "#,
+        );
+        let result = check_known(&parsed, &overrides(&[arg]));
+        assert_eq!(result.is_ok(), known, "{:?}", result);
+    }
+
+    #[test]
+    fn rejects_values_in_the_path() {
+        let mut config = table(r#"app = { name = "game" }"#);
+        assert!(matches!(
+            apply(&mut config, &overrides(&["app.name.first=x"])),
+            Err(ApplyError::NotATable { at, kind: "string", .. }) if at == "app.name"
+        ));
+    }
+}
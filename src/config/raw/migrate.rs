@@ -0,0 +1,142 @@
+//! Upgrades older config files to the current structure. Each migration takes
+//! the parsed TOML from version `n` to `n + 1`, so the structs in `raw` only
+//! ever need to understand the newest format.
+
+use std::fmt::{self, Display};
+use toml::value::{Table, Value};
+
+pub static KEY: &str = "config-version";
+
+type Migration = fn(&mut Table);
+
+// Append only! A migration's index is the version it upgrades from.
+const MIGRATIONS: &[Migration] = &[pin_legacy_build_system];
+
+pub const CURRENT_VERSION: u32 = MIGRATIONS.len() as u32;
+
+#[derive(Debug)]
+pub enum Error {
+    VersionInvalid(Value),
+    VersionTooNew(i64),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VersionInvalid(value) => {
+                write!(f, "`{}` must be a non-negative integer, but was `{}`", KEY, value)
+            }
+            Self::VersionTooNew(version) => write!(
+                f,
+                "`{} = {}` is newer than the newest version this cargo-mobile understands ({}), so this project needs a newer cargo-mobile. Run `cargo mobile update` and try again.",
+                KEY, version, CURRENT_VERSION
+            ),
+        }
+    }
+}
+
+fn subtable<'a>(table: &'a mut Table, key: &str) -> Option<&'a mut Table> {
+    table.get_mut(key).and_then(Value::as_table_mut)
+}
+
+// `apple.use-legacy-build-system` used to be implied when absent. Writing it
+// out lets the default change without changing how existing projects build.
+fn pin_legacy_build_system(table: &mut Table) {
+    if let Some(apple) = subtable(table, "apple") {
+        apple
+            .entry("use-legacy-build-system")
+            .or_insert(Value::Boolean(true));
+    }
+}
+
+/// Migrates `table` to the current version in place, returning the version it
+/// started at. Files without a version are version 0.
+pub fn migrate(table: &mut Table) -> Result<u32, Error> {
+    let version = match table.get(KEY) {
+        None => 0,
+        Some(Value::Integer(version)) if *version > i64::from(CURRENT_VERSION) => {
+            return Err(Error::VersionTooNew(*version))
+        }
+        Some(Value::Integer(version)) if *version >= 0 => *version as u32,
+        Some(other) => return Err(Error::VersionInvalid(other.clone())),
+    };
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        log::info!("migrating config from version {} to {}", from, from + 1);
+        migration(table);
+    }
+    table.insert(KEY.to_owned(), Value::Integer(CURRENT_VERSION.into()));
+    Ok(version)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(s: &str) -> Table {
+        toml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn unversioned_is_version_0() {
+        let mut table = parse("[app]\nname = \"foo\"\n");
+        assert_eq!(migrate(&mut table).unwrap(), 0);
+        assert_eq!(
+            table.get(KEY),
+            Some(&Value::Integer(CURRENT_VERSION.into()))
+        );
+    }
+
+    #[test]
+    fn current_is_untouched() {
+        let mut table = parse(&format!("{} = {}\n[apple]\n", KEY, CURRENT_VERSION));
+        let before = table.clone();
+        assert_eq!(migrate(&mut table).unwrap(), CURRENT_VERSION);
+        assert_eq!(table, before);
+    }
+
+    #[test]
+    fn too_new_is_rejected() {
+        let mut table = parse(&format!("{} = {}\n", KEY, CURRENT_VERSION + 1));
+        assert!(matches!(
+            migrate(&mut table),
+            Err(Error::VersionTooNew(version)) if version == i64::from(CURRENT_VERSION) + 1
+        ));
+    }
+
+    #[test]
+    fn non_integer_is_rejected() {
+        let mut table = parse(&format!("{} = \"1\"\n", KEY));
+        assert!(matches!(migrate(&mut table), Err(Error::VersionInvalid(_))));
+    }
+
+    #[test]
+    fn pins_legacy_build_system() {
+        let mut table = parse("[apple]\ndevelopment-team = \"ABC\"\n");
+        pin_legacy_build_system(&mut table);
+        assert_eq!(
+            subtable(&mut table, "apple")
+                .unwrap()
+                .get("use-legacy-build-system"),
+            Some(&Value::Boolean(true))
+        );
+    }
+
+    #[test]
+    fn keeps_explicit_build_system() {
+        let mut table = parse("[apple]\nuse-legacy-build-system = false\n");
+        pin_legacy_build_system(&mut table);
+        assert_eq!(
+            subtable(&mut table, "apple")
+                .unwrap()
+                .get("use-legacy-build-system"),
+            Some(&Value::Boolean(false))
+        );
+    }
+
+    #[test]
+    fn skips_missing_apple_table() {
+        let mut table = parse("[android]\n");
+        pin_legacy_build_system(&mut table);
+        assert!(!table.contains_key("apple"));
+    }
+}
@@ -0,0 +1,816 @@
+pub mod migrate;
+
+use super::{app, guide, overrides};
+#[cfg(target_os = "macos")]
+use crate::apple;
+use crate::{
+    android, hooks,
+    opts::Guided,
+    util::{
+        cli::{Report, Reportable, TextWrapper},
+        prompt, tools, watchdog,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum PromptError {
+    AppFailed(app::PromptError),
+    #[cfg(target_os = "macos")]
+    AppleFailed(apple::config::PromptError),
+    AndroidFailed(prompt::Error),
+    ReviewFailed(io::Error),
+}
+
+impl Reportable for PromptError {
+    fn report(&self) -> Report {
+        match self {
+            Self::AppFailed(err) => {
+                Report::error(format!("Failed to prompt for `{}` config", app::KEY), err)
+            }
+            #[cfg(target_os = "macos")]
+            Self::AppleFailed(err) => Report::error(
+                format!("Failed to prompt for `{}` config", apple::NAME),
+                err,
+            ),
+            Self::AndroidFailed(err) => Report::error(
+                format!("Failed to prompt for `{}` config", android::NAME),
+                err,
+            ),
+            Self::ReviewFailed(err) => Report::error("Failed to review config", err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DetectError {
+    AppFailed(app::DetectError),
+    #[cfg(target_os = "macos")]
+    AppleFailed(apple::config::DetectError),
+}
+
+impl Reportable for DetectError {
+    fn report(&self) -> Report {
+        match self {
+            Self::AppFailed(err) => {
+                Report::error(format!("Failed to detect `{}` config", app::KEY), err)
+            }
+            #[cfg(target_os = "macos")]
+            Self::AppleFailed(err) => {
+                Report::error(format!("Failed to detect `{}` config", apple::NAME), err)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    DiscoverFailed(io::Error),
+    ReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ParseFailed {
+        path: PathBuf,
+        cause: toml::de::Error,
+    },
+    MigrateFailed {
+        path: PathBuf,
+        cause: migrate::Error,
+    },
+    OverrideFailed(overrides::ApplyError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DiscoverFailed(err) => write!(
+                f,
+                "Failed to canonicalize path while searching for config file: {}",
+                err
+            ),
+            Self::ReadFailed { path, cause } => {
+                write!(f, "Failed to read config file at {:?}: {}", path, cause)
+            }
+            Self::ParseFailed { path, cause } => {
+                write!(f, "Failed to parse config file at {:?}: {}", path, cause)
+            }
+            Self::MigrateFailed { path, cause } => {
+                write!(f, "Failed to migrate config file at {:?}: {}", path, cause)
+            }
+            Self::OverrideFailed(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WriteError {
+    SerializeFailed(toml::ser::Error),
+    WriteFailed(io::Error),
+}
+
+impl Reportable for WriteError {
+    fn report(&self) -> Report {
+        match self {
+            Self::SerializeFailed(err) => Report::error("Failed to serialize config", err),
+            Self::WriteFailed(err) => Report::error("Failed to write config", err),
+        }
+    }
+}
+
+/// A field that can be fixed in place, without regenerating the whole config.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Field {
+    AppName,
+    AppDomain,
+    #[cfg(target_os = "macos")]
+    AppleDevelopmentTeam,
+    #[cfg(target_os = "macos")]
+    AppleProjectDir,
+    #[cfg(target_os = "macos")]
+    AppleIosVersion,
+    #[cfg(target_os = "macos")]
+    AppleMacOsVersion,
+    AndroidProjectDir,
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AppName => write!(f, "`{}.name`", app::KEY),
+            Self::AppDomain => write!(f, "`{}.domain`", app::KEY),
+            #[cfg(target_os = "macos")]
+            Self::AppleDevelopmentTeam => write!(f, "`{}.development-team`", apple::NAME),
+            #[cfg(target_os = "macos")]
+            Self::AppleProjectDir => write!(f, "`{}.project-dir`", apple::NAME),
+            #[cfg(target_os = "macos")]
+            Self::AppleIosVersion => write!(f, "`{}.ios-version`", apple::NAME),
+            #[cfg(target_os = "macos")]
+            Self::AppleMacOsVersion => write!(f, "`{}.macos-version`", apple::NAME),
+            Self::AndroidProjectDir => write!(f, "`{}.project-dir`", android::NAME),
+        }
+    }
+}
+
+/// A value `--guided` can send back to be prompted for again.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Answered {
+    AppName,
+    AppStylizedName,
+    AppDomain,
+    AppTemplatePack,
+    #[cfg(target_os = "macos")]
+    AppleDevelopmentTeam,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raw {
+    pub config_version: Option<u32>,
+    pub app: app::Raw,
+    #[cfg(target_os = "macos")]
+    pub apple: Option<apple::config::Raw>,
+    pub android: Option<android::config::Raw>,
+    pub env: Option<toml::value::Table>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "hooks::serialize_raw"
+    )]
+    pub hooks: Option<hooks::Raw>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeouts: Option<watchdog::Raw>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<tools::Raw>,
+}
+
+impl Raw {
+    /// With `guided`, each value is explained before it's prompted for, and
+    /// then they can all be reviewed and changed.
+    pub fn prompt(wrapper: &TextWrapper, guided: Guided) -> Result<Self, PromptError> {
+        let app = app::Raw::prompt(wrapper, guided).map_err(PromptError::AppFailed)?;
+        #[cfg(target_os = "macos")]
+        let apple =
+            apple::config::Raw::prompt(wrapper, guided).map_err(PromptError::AppleFailed)?;
+        let mut raw = Self {
+            config_version: Some(migrate::CURRENT_VERSION),
+            app,
+            #[cfg(target_os = "macos")]
+            apple,
+            android: None,
+            env: None,
+            hooks: None,
+            timeouts: None,
+            tools: None,
+        };
+        if guided.yes() {
+            raw.review(wrapper)?;
+        }
+        Ok(raw)
+    }
+
+    /// Everything `prompt` asked for, in the order it asked.
+    fn answers(&self) -> Vec<(Answered, guide::Answer)> {
+        let mut answers = vec![
+            (
+                Answered::AppName,
+                guide::Answer::new("Project name", &self.app.name),
+            ),
+            (
+                Answered::AppStylizedName,
+                guide::Answer::new(
+                    "Stylized name",
+                    self.app.stylized_name.as_deref().unwrap_or_default(),
+                ),
+            ),
+            (
+                Answered::AppDomain,
+                guide::Answer::new("Domain", &self.app.domain),
+            ),
+        ];
+        answers.push((
+            Answered::AppTemplatePack,
+            guide::Answer::new(
+                "Template pack",
+                self.app
+                    .template_pack
+                    .as_deref()
+                    .unwrap_or(app::IMPLIED_TEMPLATE_PACK),
+            ),
+        ));
+        #[cfg(target_os = "macos")]
+        answers.push((
+            Answered::AppleDevelopmentTeam,
+            guide::Answer::new(
+                "Apple development team",
+                self.apple
+                    .as_ref()
+                    .map(|apple| apple.development_team.as_str())
+                    .unwrap_or("(skipped)"),
+            ),
+        ));
+        answers
+    }
+
+    fn reprompt(&mut self, answered: Answered, wrapper: &TextWrapper) -> Result<(), PromptError> {
+        match answered {
+            Answered::AppName => {
+                guide::NAME.print(Guided::Yes, wrapper);
+                self.repair(Field::AppName, wrapper)
+            }
+            Answered::AppStylizedName => {
+                guide::STYLIZED_NAME.print(Guided::Yes, wrapper);
+                self.app
+                    .reprompt_stylized_name()
+                    .map_err(PromptError::AppFailed)
+            }
+            Answered::AppDomain => {
+                guide::DOMAIN.print(Guided::Yes, wrapper);
+                self.repair(Field::AppDomain, wrapper)
+            }
+            Answered::AppTemplatePack => {
+                guide::TEMPLATE_PACK.print(Guided::Yes, wrapper);
+                self.app
+                    .reprompt_template_pack(wrapper)
+                    .map_err(PromptError::AppFailed)
+            }
+            #[cfg(target_os = "macos")]
+            Answered::AppleDevelopmentTeam => {
+                guide::DEVELOPMENT_TEAM.print(Guided::Yes, wrapper);
+                self.repair(Field::AppleDevelopmentTeam, wrapper)
+            }
+        }
+    }
+
+    /// The `--guided` review screen, which has to be confirmed before
+    /// anything gets written.
+    fn review(&mut self, wrapper: &TextWrapper) -> Result<(), PromptError> {
+        guide::review(
+            self,
+            &mut io::stdout(),
+            |msg| prompt::minimal("config.review", "--non-interactive", msg).map_err(Into::into),
+            |raw| {
+                raw.answers()
+                    .into_iter()
+                    .map(|(_, answer)| answer)
+                    .collect()
+            },
+            |raw, index| {
+                let answered = raw.answers()[index].0;
+                raw.reprompt(answered, wrapper)
+            },
+        )
+        .map_err(|err| match err {
+            guide::ReviewError::PromptFailed(err) => PromptError::ReviewFailed(err),
+            guide::ReviewError::EditFailed(err) => err,
+        })
+    }
+
+    pub fn detect(wrapper: &TextWrapper) -> Result<Self, DetectError> {
+        let app = app::Raw::detect(wrapper).map_err(DetectError::AppFailed)?;
+        #[cfg(target_os = "macos")]
+        let apple = apple::config::Raw::detect().map_err(DetectError::AppleFailed)?;
+        Ok(Self {
+            config_version: Some(migrate::CURRENT_VERSION),
+            app,
+            #[cfg(target_os = "macos")]
+            apple: Some(apple),
+            android: None,
+            env: None,
+            hooks: None,
+            timeouts: None,
+            tools: None,
+        })
+    }
+
+    pub fn discover_root(cwd: impl AsRef<Path>) -> io::Result<Option<PathBuf>> {
+        let file_name = super::file_name();
+        let mut path = cwd.as_ref().canonicalize()?.join(&file_name);
+        log::info!("looking for config file at {:?}", path);
+        while !path.exists() {
+            if let Some(parent) = path.parent().and_then(Path::parent) {
+                path = parent.join(&file_name);
+                log::info!("looking for config file at {:?}", path);
+            } else {
+                log::info!("no config file was ever found");
+                return Ok(None);
+            }
+        }
+        log::info!("found config file at {:?}", path);
+        path.pop();
+        Ok(Some(path))
+    }
+
+    /// Parses a config file, migrating it to the current version in memory.
+    /// Returns the version the file was at, too.
+    pub fn parse(path: &Path, bytes: &[u8]) -> Result<(Self, u32), LoadError> {
+        let mut table = toml::from_slice::<toml::value::Table>(bytes).map_err(|cause| {
+            LoadError::ParseFailed {
+                path: path.to_owned(),
+                cause,
+            }
+        })?;
+        let version = migrate::migrate(&mut table).map_err(|cause| LoadError::MigrateFailed {
+            path: path.to_owned(),
+            cause,
+        })?;
+        if version < migrate::CURRENT_VERSION {
+            log::info!(
+                "config file at {:?} is at version {}; migrated to version {} in memory (run `cargo mobile migrate-config` to update the file)",
+                path,
+                version,
+                migrate::CURRENT_VERSION
+            );
+        }
+        toml::Value::Table(table)
+            .try_into::<Self>()
+            .map(|raw| (raw, version))
+            .map_err(|cause| LoadError::ParseFailed {
+                path: path.to_owned(),
+                cause,
+            })
+    }
+
+    pub fn load(cwd: impl AsRef<Path>) -> Result<Option<(PathBuf, Self)>, LoadError> {
+        Self::discover_root(cwd)
+            .map_err(LoadError::DiscoverFailed)?
+            .map(|root_dir| {
+                let path = root_dir.join(super::file_name());
+                let bytes = fs::read(&path).map_err(|cause| LoadError::ReadFailed {
+                    path: path.clone(),
+                    cause,
+                })?;
+                Self::parse(&path, &bytes).map(|(raw, _version)| (root_dir, raw))
+            })
+            .transpose()
+    }
+
+    /// Applies `--config` overrides. The result should never be written,
+    /// since they're only meant to last for one run.
+    pub fn with_overrides(
+        self,
+        overrides: &[overrides::Override],
+    ) -> Result<Self, overrides::ApplyError> {
+        if overrides.is_empty() {
+            return Ok(self);
+        }
+        let mut table = toml::Value::try_from(&self)?.try_into::<toml::value::Table>()?;
+        overrides::apply(&mut table, overrides)?;
+        let raw = toml::Value::Table(table).try_into::<Self>()?;
+        let parsed = toml::Value::try_from(&raw)?.try_into::<toml::value::Table>()?;
+        overrides::check_known(&parsed, overrides)?;
+        Ok(raw)
+    }
+
+    /// What `write` would write over `existing`. Only the fields that changed
+    /// are edited into it, so comments and formatting everywhere else
+    /// survive.
+    pub fn render(&self, existing: Option<&str>) -> Result<String, toml::ser::Error> {
+        let contents = toml::to_string(self)?;
+        Ok(existing
+            .and_then(|existing| edit_into(existing, &contents))
+            .unwrap_or(contents))
+    }
+
+    /// Writes the config to `root_dir`, keeping whatever's already there
+    /// that didn't change.
+    pub fn write(&self, root_dir: &Path) -> Result<(), WriteError> {
+        let path = root_dir.join(super::file_name());
+        let existing = fs::read_to_string(&path).ok();
+        let contents = self
+            .render(existing.as_deref())
+            .map_err(WriteError::SerializeFailed)?;
+        log::info!("writing config to {:?}", path);
+        fs::write(path, contents).map_err(WriteError::WriteFailed)
+    }
+
+    /// Replaces `field` with a value that's known to be valid, so that the
+    /// remaining fields can still be checked. The result is never written.
+    pub fn stub(&mut self, field: Field) {
+        match field {
+            Field::AppName => self.app.name = "example".to_owned(),
+            Field::AppDomain => self.app.domain = "example.com".to_owned(),
+            #[cfg(target_os = "macos")]
+            Field::AppleDevelopmentTeam => {
+                self.apple
+                    .get_or_insert_with(Default::default)
+                    .development_team = "stub".to_owned()
+            }
+            #[cfg(target_os = "macos")]
+            Field::AppleProjectDir => {
+                self.apple.get_or_insert_with(Default::default).project_dir = None
+            }
+            #[cfg(target_os = "macos")]
+            Field::AppleIosVersion => {
+                self.apple.get_or_insert_with(Default::default).ios_version = None
+            }
+            #[cfg(target_os = "macos")]
+            Field::AppleMacOsVersion => {
+                self.apple
+                    .get_or_insert_with(Default::default)
+                    .macos_version = None
+            }
+            Field::AndroidProjectDir => self
+                .android
+                .get_or_insert_with(Default::default)
+                .clear_project_dir(),
+        }
+    }
+
+    /// Prompts for a new value for `field`, leaving everything else alone.
+    pub fn repair(&mut self, field: Field, wrapper: &TextWrapper) -> Result<(), PromptError> {
+        match field {
+            Field::AppName => self
+                .app
+                .reprompt_name(wrapper)
+                .map_err(PromptError::AppFailed),
+            Field::AppDomain => self
+                .app
+                .reprompt_domain(wrapper)
+                .map_err(PromptError::AppFailed),
+            #[cfg(target_os = "macos")]
+            Field::AppleDevelopmentTeam => {
+                self.apple
+                    .get_or_insert_with(Default::default)
+                    .development_team = apple::config::Raw::prompt_development_team(wrapper)
+                    .map_err(PromptError::AppleFailed)?;
+                Ok(())
+            }
+            #[cfg(target_os = "macos")]
+            Field::AppleProjectDir => {
+                self.apple.get_or_insert_with(Default::default).project_dir = Some(
+                    apple::config::Raw::prompt_project_dir().map_err(PromptError::AppleFailed)?,
+                );
+                Ok(())
+            }
+            #[cfg(target_os = "macos")]
+            Field::AppleIosVersion => {
+                self.apple.get_or_insert_with(Default::default).ios_version = Some(
+                    apple::config::Raw::prompt_ios_version().map_err(PromptError::AppleFailed)?,
+                );
+                Ok(())
+            }
+            #[cfg(target_os = "macos")]
+            Field::AppleMacOsVersion => {
+                self.apple
+                    .get_or_insert_with(Default::default)
+                    .macos_version = Some(
+                    apple::config::Raw::prompt_macos_version().map_err(PromptError::AppleFailed)?,
+                );
+                Ok(())
+            }
+            Field::AndroidProjectDir => self
+                .android
+                .get_or_insert_with(Default::default)
+                .prompt_project_dir()
+                .map_err(PromptError::AndroidFailed),
+        }
+    }
+}
+
+/// Edits `new` into `existing`, or gives `None` if either doesn't parse.
+fn edit_into(existing: &str, new: &str) -> Option<String> {
+    let mut doc = existing.parse::<toml_edit::Document>().ok()?;
+    let new_doc = new.parse::<toml_edit::Document>().ok()?;
+    merge(
+        doc.as_table_mut(),
+        &toml::from_str(existing).ok()?,
+        new_doc.as_table(),
+        &toml::from_str(new).ok()?,
+    );
+    Some(doc.to_string())
+}
+
+// The parsed tables are only there to tell which items actually changed,
+// since items that are formatted differently can still hold the same value.
+fn merge(
+    old: &mut dyn toml_edit::TableLike,
+    old_values: &toml::value::Table,
+    new: &dyn toml_edit::TableLike,
+    new_values: &toml::value::Table,
+) {
+    let removed = old_values
+        .keys()
+        .filter(|key| !new_values.contains_key(*key))
+        .cloned()
+        .collect::<Vec<_>>();
+    for key in removed {
+        old.remove(&key);
+    }
+    for (key, new_value) in new_values {
+        let new_item = match new.get(key) {
+            Some(item) => item,
+            None => continue,
+        };
+        let old_item = match old.get_mut(key) {
+            Some(item) => item,
+            None => {
+                old.insert(key, new_item.clone());
+                continue;
+            }
+        };
+        match (old_values.get(key), new_value) {
+            (Some(old_value), _) if old_value == new_value => (),
+            (Some(toml::Value::Table(old_values)), toml::Value::Table(new_values)) => {
+                match (old_item.as_table_like_mut(), new_item.as_table_like()) {
+                    (Some(old), Some(new)) => merge(old, old_values, new, new_values),
+                    _ => *old_item = new_item.clone(),
+                }
+            }
+            _ => replace(old_item, new_item),
+        }
+    }
+}
+
+// Keeps any comment that was next to the old value.
+fn replace(old: &mut toml_edit::Item, new: &toml_edit::Item) {
+    if let (Some(old), Some(new)) = (old.as_value_mut(), new.as_value()) {
+        let decor = old.decor().clone();
+        *old = new.clone();
+        *old.decor_mut() = decor;
+        return;
+    }
+    *old = new.clone();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::{OneOrMany, TempDirGuard};
+    use std::fmt::Debug;
+
+    static FIXTURE: &str = r#"
+[app]
+name = "fixture"
+domain = "example.com"
+asset-dir = "assets"
+
+[android]
+min-sdk-version = 24
+features = ["metal", "vulkan"]
+targets = ["aarch64", "armv7"]
+auto-connect = ["192.168.1.50:5555"]
+
+[env]
+FOO = "bar"
+
+[hooks]
+android-pre-build = ["./cook-assets", "--android"]
+apple-pre-archive = "./bump-build-metadata"
+apple-post-archive = { command = "./upload \"$CM_ARTIFACT_PATH\"", shell = true }
+"#;
+
+    fn load(dir: &Path) -> (Raw, toml::Value) {
+        let (root_dir, raw) = Raw::load(dir).unwrap().unwrap();
+        let written = fs::read_to_string(root_dir.join(super::super::file_name())).unwrap();
+        (raw, toml::from_str(&written).unwrap())
+    }
+
+    #[test]
+    fn load_write_load_round_trips() {
+        let dir = TempDirGuard::new().unwrap();
+        let mut fixture = toml::from_str::<toml::value::Table>(FIXTURE).unwrap();
+        fixture.insert(
+            "config-version".to_owned(),
+            toml::Value::Integer(i64::from(migrate::CURRENT_VERSION)),
+        );
+        fs::write(
+            dir.join(super::super::file_name()),
+            toml::to_string(&fixture).unwrap(),
+        )
+        .unwrap();
+
+        let (raw, _) = load(&dir);
+        raw.write(&dir).unwrap();
+        let (reloaded, file) = load(&dir);
+        assert_eq!(
+            toml::Value::try_from(&reloaded).unwrap(),
+            toml::Value::try_from(&raw).unwrap()
+        );
+        // Arrays have to stay arrays, instead of turning into strings that
+        // happen to look like them.
+        assert_eq!(
+            file["android"]["targets"],
+            toml::Value::try_from(vec!["aarch64", "armv7"]).unwrap()
+        );
+        assert!(file["hooks"]["android-pre-build"].is_array());
+        assert!(file["hooks"]["apple-post-archive"].is_table());
+        assert_eq!(file["env"]["FOO"].as_str(), Some("bar"));
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Wrapper<T: Debug> {
+        value: OneOrMany<T>,
+    }
+
+    #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct Payload {
+        name: String,
+        is_cool: bool,
+    }
+
+    // Checks the shape of what was serialized, too, since a string that
+    // happens to deserialize again is exactly the bug this guards against.
+    fn round_trip<T>(value: OneOrMany<T>, shape: impl Fn(&toml::Value) -> bool)
+    where
+        T: Debug + PartialEq + Serialize + serde::de::DeserializeOwned,
+    {
+        let wrapper = Wrapper { value };
+        let toml = toml::to_string(&wrapper).unwrap();
+        let parsed = toml::from_str::<toml::Value>(&toml).unwrap();
+        assert!(shape(&parsed["value"]), "unexpected shape: {}", toml);
+        assert_eq!(toml::from_str::<Wrapper<T>>(&toml).unwrap(), wrapper);
+        let json = serde_json::to_string(&wrapper).unwrap();
+        assert_eq!(serde_json::from_str::<Wrapper<T>>(&json).unwrap(), wrapper);
+    }
+
+    #[test]
+    fn one_or_many_round_trips_strings() {
+        round_trip(OneOrMany::One("foo".to_owned()), |value| {
+            value.as_str() == Some("foo")
+        });
+        round_trip(
+            OneOrMany::Many(vec!["foo".to_owned(), "bar".to_owned()]),
+            |value| value.as_array().map(Vec::len) == Some(2),
+        );
+    }
+
+    #[test]
+    fn one_or_many_round_trips_structs() {
+        let payload = |name: &str| Payload {
+            name: name.to_owned(),
+            is_cool: true,
+        };
+        round_trip(OneOrMany::One(payload("foo")), |value| {
+            value.get("is-cool").and_then(toml::Value::as_bool) == Some(true)
+        });
+        round_trip(
+            OneOrMany::Many(vec![payload("foo"), payload("bar")]),
+            |value| {
+                value.as_array().map_or(false, |values| {
+                    values.len() == 2 && values.iter().all(toml::Value::is_table)
+                })
+            },
+        );
+    }
+
+    #[test]
+    fn writing_keeps_comments() {
+        let dir = TempDirGuard::new().unwrap();
+        fs::write(
+            dir.join(super::super::file_name()),
+            format!(
+                r#"# Managed by hand.
+config-version = {}
+
+[app]
+# Has to match the store listing.
+name = "Fixture!" # not valid
+domain = "example.com"
+asset-dir = "assets"
+
+[env]
+# Kept for CI.
+FOO = "bar"
+"#,
+                migrate::CURRENT_VERSION
+            ),
+        )
+        .unwrap();
+
+        let (mut raw, _) = load(&dir);
+        raw.app.name = "fixture".to_owned();
+        raw.app.asset_dir = None;
+        raw.env
+            .as_mut()
+            .unwrap()
+            .insert("BAZ".to_owned(), toml::Value::String("qux".to_owned()));
+        raw.write(&dir).unwrap();
+        let written = fs::read_to_string(dir.join(super::super::file_name())).unwrap();
+        for comment in &[
+            "# Managed by hand.",
+            "# Has to match the store listing.",
+            "# not valid",
+            "# Kept for CI.",
+        ] {
+            assert!(
+                written.contains(comment),
+                "lost {:?}:\n{}",
+                comment,
+                written
+            );
+        }
+        let (reloaded, file) = load(&dir);
+        assert_eq!(
+            toml::Value::try_from(&reloaded).unwrap(),
+            toml::Value::try_from(&raw).unwrap()
+        );
+        assert_eq!(file["app"]["name"].as_str(), Some("fixture"));
+        assert!(file["app"].get("asset-dir").is_none());
+        assert_eq!(file["env"]["BAZ"].as_str(), Some("qux"));
+    }
+
+    #[test]
+    fn rendering_matches_writing() {
+        let dir = TempDirGuard::new().unwrap();
+        let path = dir.join(super::super::file_name());
+        // No `config-version`, so this needs migrating.
+        let old = r#"# Our app.
+[app]
+name = "fixture" # the crate name
+domain = "example.com"
+
+# Only ARM.
+[android]
+targets = ["aarch64"]
+"#;
+        fs::write(&path, old).unwrap();
+        let (raw, _) = Raw::parse(&path, old.as_bytes()).unwrap();
+        let preview = raw.render(Some(old)).unwrap();
+        raw.write(&dir).unwrap();
+        assert_eq!(preview, fs::read_to_string(&path).unwrap());
+        for comment in &["# Our app.", "# the crate name", "# Only ARM."] {
+            assert!(
+                preview.contains(comment),
+                "lost {:?}:\n{}",
+                comment,
+                preview
+            );
+        }
+    }
+
+    #[test]
+    fn overrides_are_applied_before_deserializing() {
+        let (raw, _) = Raw::parse(Path::new("mobile.toml"), FIXTURE.as_bytes()).unwrap();
+        let overridden = raw
+            .clone()
+            .with_overrides(&[
+                "android.min-sdk-version=26".parse().unwrap(),
+                "app.name=override".parse().unwrap(),
+            ])
+            .unwrap();
+        assert_eq!(overridden.app.name, "override");
+        assert_eq!(
+            toml::Value::try_from(&overridden).unwrap()["android"]["min-sdk-version"],
+            toml::Value::Integer(26)
+        );
+        // Which also means the types are checked.
+        assert!(matches!(
+            raw.clone()
+                .with_overrides(&["android.min-sdk-version=soon".parse().unwrap()]),
+            Err(overrides::ApplyError::DeserializeFailed(_))
+        ));
+        // And keys that don't exist aren't quietly dropped.
+        assert!(matches!(
+            raw.with_overrides(&["android.min-sdk-versoin=26".parse().unwrap()]),
+            Err(overrides::ApplyError::KeyUnknown { key }) if key == "android.min-sdk-versoin"
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+//! `cargo mobile version`, which bumps `app.version` in place. The file's
+//! edited with `toml_edit` instead of going through `Raw`, since otherwise
+//! every comment and blank line in it would be lost.
+
+use super::{file_name, Raw};
+use crate::{
+    opts::VersionPart,
+    util::{
+        cli::{Report, Reportable},
+        VersionTriple, VersionTripleError,
+    },
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use toml_edit::{Document, Item, TomlError, Value};
+
+// Keys that win over `app.version` for their platform, if they're set.
+static OVERRIDES: &[(&str, &str)] = &[
+    ("apple", "bundle-version"),
+    ("apple", "bundle-version-short"),
+    ("android", "version-name"),
+];
+
+pub fn bump(version: VersionTriple, part: VersionPart) -> VersionTriple {
+    match part {
+        VersionPart::Major => VersionTriple::new(version.major + 1, 0, 0),
+        VersionPart::Minor => VersionTriple::new(version.major, version.minor + 1, 0),
+        VersionPart::Patch => VersionTriple::new(version.major, version.minor, version.patch + 1),
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    DiscoverFailed(io::Error),
+    NotFound {
+        cwd: PathBuf,
+    },
+    ReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ParseFailed {
+        path: PathBuf,
+        cause: TomlError,
+    },
+    VersionMissing {
+        path: PathBuf,
+    },
+    VersionInvalid {
+        path: PathBuf,
+        cause: VersionTripleError,
+    },
+    WriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::DiscoverFailed(err) => Report::error("Failed to search for config file", err),
+            Self::NotFound { cwd } => Report::action_request(
+                "Please run `cargo mobile init` and try again!",
+                format!(
+                    "No {} was found in {:?} or any of its parents.",
+                    file_name(),
+                    cwd
+                ),
+            ),
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to read config file at {:?}", path), cause)
+            }
+            Self::ParseFailed { path, cause } => {
+                Report::error(format!("Failed to parse config file at {:?}", path), cause)
+            }
+            Self::VersionMissing { path } => Report::action_request(
+                "Add a `version` to the `[app]` section and try again!",
+                format!("{:?} doesn't set `app.version`.", path),
+            ),
+            Self::VersionInvalid { path, cause } => {
+                Report::error(format!("`app.version` in {:?} is invalid", path), cause)
+            }
+            Self::WriteFailed { path, cause } => {
+                Report::error(format!("Failed to write config file at {:?}", path), cause)
+            }
+        }
+    }
+}
+
+fn parse(path: &Path, contents: &str) -> Result<Document, Error> {
+    contents
+        .parse::<Document>()
+        .map_err(|cause| Error::ParseFailed {
+            path: path.to_owned(),
+            cause,
+        })
+}
+
+fn current(path: &Path, doc: &Document) -> Result<VersionTriple, Error> {
+    let version = doc
+        .get("app")
+        .and_then(|app| app.get("version"))
+        .and_then(Item::as_str)
+        .ok_or_else(|| Error::VersionMissing {
+            path: path.to_owned(),
+        })?;
+    VersionTriple::from_str(version).map_err(|cause| Error::VersionInvalid {
+        path: path.to_owned(),
+        cause,
+    })
+}
+
+/// The explicit version keys in `doc`, which `app.version` won't affect.
+fn overrides(doc: &Document) -> Vec<String> {
+    OVERRIDES
+        .iter()
+        .filter(|(section, key)| {
+            doc.get(section)
+                .and_then(|section| section.get(key))
+                .is_some()
+        })
+        .map(|(section, key)| format!("{}.{}", section, key))
+        .collect()
+}
+
+/// Bumps `part` of `app.version` in `contents`, leaving everything else as it
+/// was. Gives back the new contents along with the old and new versions.
+pub fn rewrite(
+    path: &Path,
+    contents: &str,
+    part: VersionPart,
+) -> Result<(String, VersionTriple, VersionTriple), Error> {
+    let mut doc = parse(path, contents)?;
+    let old = current(path, &doc)?;
+    let new = bump(old, part);
+    let value = doc
+        .get_mut("app")
+        .and_then(|app| app.get_mut("version"))
+        .and_then(Item::as_value_mut)
+        .expect("`app.version` was found but then disappeared");
+    let decor = value.decor().clone();
+    *value = Value::from(new.to_string());
+    *value.decor_mut() = decor;
+    Ok((doc.to_string(), old, new))
+}
+
+/// Prints `app.version`, or bumps `part` of it and writes it back.
+pub fn exec(cwd: impl AsRef<Path>, part: Option<VersionPart>) -> Result<(), Error> {
+    let cwd = cwd.as_ref();
+    let root_dir = Raw::discover_root(cwd)
+        .map_err(Error::DiscoverFailed)?
+        .ok_or_else(|| Error::NotFound {
+            cwd: cwd.to_owned(),
+        })?;
+    let path = root_dir.join(file_name());
+    let contents = fs::read_to_string(&path).map_err(|cause| Error::ReadFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    let part = if let Some(part) = part {
+        part
+    } else {
+        println!("{}", current(&path, &parse(&path, &contents)?)?);
+        return Ok(());
+    };
+    let (new_contents, old, new) = rewrite(&path, &contents, part)?;
+    fs::write(&path, new_contents).map_err(|cause| Error::WriteFailed {
+        path: path.clone(),
+        cause,
+    })?;
+    println!("Bumped `app.version` from {} to {}", old, new);
+    let overrides = overrides(&parse(&path, &contents)?);
+    if !overrides.is_empty() {
+        println!(
+            "Note: these are set explicitly, so they stay as they are: {}",
+            overrides
+                .iter()
+                .map(|key| format!("`{}`", key))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        part,
+        expected,
+        case(VersionPart::Major, VersionTriple::new(2, 0, 0)),
+        case(VersionPart::Minor, VersionTriple::new(1, 3, 0)),
+        case(VersionPart::Patch, VersionTriple::new(1, 2, 4))
+    )]
+    fn bumps_versions(part: VersionPart, expected: VersionTriple) {
+        assert_eq!(bump(VersionTriple::new(1, 2, 3), part), expected);
+    }
+
+    #[test]
+    fn rewrite_keeps_everything_else() {
+        let contents = r#"# The app's config.
+[app]
+name = "example"
+version = "1.2.3" # what the stores show
+
+[android]
+# Still explicit, for now.
+version-name = "1.2"
+"#;
+        let (new_contents, old, new) =
+            rewrite(Path::new("mobile.toml"), contents, VersionPart::Minor).unwrap();
+        assert_eq!(old, VersionTriple::new(1, 2, 3));
+        assert_eq!(new, VersionTriple::new(1, 3, 0));
+        assert_eq!(
+            new_contents,
+            contents.replace(r#"version = "1.2.3""#, r#"version = "1.3.0""#)
+        );
+        assert_eq!(
+            overrides(&contents.parse().unwrap()),
+            vec!["android.version-name".to_owned()]
+        );
+    }
+
+    #[test]
+    fn rewrite_needs_a_version() {
+        let contents = "[app]\nname = \"example\"\n";
+        assert!(matches!(
+            rewrite(Path::new("mobile.toml"), contents, VersionPart::Patch),
+            Err(Error::VersionMissing { .. })
+        ));
+    }
+}
@@ -1,10 +1,13 @@
-use crate::util::cli::{Report, Reportable};
-use std::{fmt::Debug, io};
+use crate::util::{
+    cli::{Report, Reportable},
+    prompt,
+};
+use std::fmt::Debug;
 
 #[derive(Debug)]
 pub enum PromptErrorCause<T: Reportable> {
     DetectionFailed(T),
-    PromptFailed(io::Error),
+    PromptFailed(prompt::Error),
     NoneDetected,
 }
 
@@ -18,9 +21,7 @@ impl<T: Reportable> Reportable for PromptError<T> {
     fn report(&self) -> Report {
         match &self.cause {
             PromptErrorCause::DetectionFailed(err) => err.report(),
-            PromptErrorCause::PromptFailed(err) => {
-                Report::error(format!("Failed to prompt for {} device", self.name), err)
-            }
+            PromptErrorCause::PromptFailed(err) => err.report(),
             PromptErrorCause::NoneDetected => Report::error(
                 format!("Failed to prompt for {} device", self.name),
                 format!("No connected {} devices detected", self.name),
@@ -38,7 +39,7 @@ impl<T: Reportable> PromptError<T> {
         Self::new(name, PromptErrorCause::DetectionFailed(err))
     }
 
-    pub fn prompt_failed(name: &'static str, err: io::Error) -> Self {
+    pub fn prompt_failed(name: &'static str, err: prompt::Error) -> Self {
         Self::new(name, PromptErrorCause::PromptFailed(err))
     }
 
@@ -49,19 +50,33 @@ impl<T: Reportable> PromptError<T> {
 
 #[macro_export]
 macro_rules! define_device_prompt {
-    ($func:path, $e:ty, $name:ident) => {
-        fn device_prompt<'a>(env: &'_ Env) -> Result<Device<'a>, $crate::device::PromptError<$e>> {
-            let device_list = $func(env).map_err(|cause| {
+    ($func:path, $e:ty, $name:ident $(, $arg:ident: $arg_ty:ty)*) => {
+        /// When several devices are connected, the first one for
+        /// `preferred_triple` is picked if you just press enter. Any other
+        /// args are passed on to the device list.
+        fn device_prompt<'a>(
+            env: &'_ Env,
+            preferred_triple: Option<&str>,
+            $($arg: $arg_ty,)*
+        ) -> Result<Device<'a>, $crate::device::PromptError<$e>> {
+            let device_list = $func(env $(, $arg)*).map_err(|cause| {
                 $crate::device::PromptError::detection_failed(stringify!($name), cause)
             })?;
             if device_list.len() > 0 {
                 let index = if device_list.len() > 1 {
-                    prompt::list(
+                    let preferred = preferred_triple.and_then(|triple| {
+                        device_list
+                            .iter()
+                            .position(|device| device.target().triple == triple)
+                    });
+                    prompt::list_with_default(
+                        concat!("device.", stringify!($name)),
                         concat!("Detected ", stringify!($name), " devices"),
                         device_list.iter(),
                         "device",
                         None,
                         "Device",
+                        preferred,
                     )
                     .map_err(|cause| {
                         $crate::device::PromptError::prompt_failed(stringify!($name), cause)
@@ -1,8 +1,14 @@
 mod section;
 
+pub use self::section::{Fix, Section};
 use crate::{
     env::{self, Env},
-    util::{self, cli::TextWrapper},
+    opts,
+    util::{
+        self,
+        cli::{Report, Reportable, TextWrapper},
+        prompt,
+    },
 };
 use thiserror::Error;
 
@@ -20,12 +26,240 @@ pub enum Unrecoverable {
     ContractHomeFailed(#[from] util::ContractHomeError),
 }
 
-pub fn exec(wrapper: &TextWrapper) -> Result<(), Unrecoverable> {
-    let env = Env::new()?;
-    section::cargo_mobile::check()?.print(wrapper);
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Unrecoverable(#[from] Unrecoverable),
+    #[error("There's no section called {slug:?}; run `cargo mobile doctor --list-sections` to see what there is.")]
+    SectionUnknown { slug: String },
+    #[error("Failed to prompt to fix what was found: {0}")]
+    FixPromptFailed(#[from] prompt::Error),
+    #[error("Failed to run `{program}`: {source}")]
+    FixFailed {
+        program: &'static str,
+        source: bossy::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::Unrecoverable(err) => Report::error("Failed to run doctor", err),
+            Self::SectionUnknown { .. } => Report::action_request("Unknown doctor section", self),
+            Self::FixPromptFailed(_) | Self::FixFailed { .. } => {
+                Report::error("Failed to fix what doctor found", self)
+            }
+        }
+    }
+}
+
+/// How things went overall, from best to worst.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Status {
+    Clean,
+    Warnings,
+    Failures,
+}
+
+impl Status {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Clean => 0,
+            Self::Warnings => 1,
+            Self::Failures => 2,
+        }
+    }
+}
+
+/// A section that hasn't been checked yet, since some of them take a while.
+struct Check<C> {
+    slug: &'static str,
+    title: &'static str,
+    run: fn(&C) -> Result<Section, Unrecoverable>,
+}
+
+fn checks() -> Vec<Check<Env>> {
+    let mut checks = vec![Check {
+        slug: "cargo-mobile",
+        title: "cargo-mobile installation",
+        run: |_| section::cargo_mobile::check(),
+    }];
     #[cfg(target_os = "macos")]
-    section::apple::check().print(wrapper);
-    section::android::check(&env)?.print(wrapper);
-    section::device_list::check(&env).print(wrapper);
+    checks.push(Check {
+        slug: "apple",
+        title: "Apple developer tools",
+        run: |_| Ok(section::apple::check()),
+    });
+    checks.push(Check {
+        slug: "android",
+        title: "Android developer tools",
+        run: section::android::check,
+    });
+    checks.push(Check {
+        slug: "devices",
+        title: "Connected devices",
+        run: |env| Ok(section::device_list::check(env)),
+    });
+    checks
+}
+
+/// The checks named by `slugs`, in their usual order, or all of them if
+/// `slugs` is empty.
+fn select<'a, C>(checks: &'a [Check<C>], slugs: &[String]) -> Result<Vec<&'a Check<C>>, Error> {
+    if let Some(slug) = slugs
+        .iter()
+        .find(|slug| !checks.iter().any(|check| check.slug == slug.as_str()))
+    {
+        return Err(Error::SectionUnknown { slug: slug.clone() });
+    }
+    Ok(checks
+        .iter()
+        .filter(|check| slugs.is_empty() || slugs.iter().any(|slug| slug == check.slug))
+        .collect())
+}
+
+/// Runs and prints each section in turn, so slow probes don't hold up
+/// everything else.
+fn run<C>(
+    checks: &[&Check<C>],
+    context: &C,
+    mut print: impl FnMut(&Section),
+) -> Result<Status, Unrecoverable> {
+    let mut status = Status::Clean;
+    for check in checks {
+        log::info!("running doctor section {:?}", check.slug);
+        let section = (check.run)(context)?;
+        status = status.max(section.status());
+        print(&section);
+    }
+    Ok(status)
+}
+
+pub fn list_sections() {
+    for check in checks() {
+        println!("{:<14}{}", check.slug, check.title);
+    }
+}
+
+/// Offers each fix in turn, once everything has been checked and printed.
+fn offer_fixes(fixes: &[Fix]) -> Result<(), Error> {
+    for fix in fixes {
+        let answer = loop {
+            if let Some(answer) = prompt::yes_no(
+                "doctor.fix",
+                "--non-interactive",
+                &fix.prompt,
+                Some(prompt::YesOrNo::Yes),
+            )? {
+                break answer;
+            }
+        };
+        if answer.yes() {
+            fix.command()
+                .run_and_wait()
+                .map_err(|source| Error::FixFailed {
+                    program: fix.program,
+                    source,
+                })?;
+        }
+    }
     Ok(())
 }
+
+/// With `quiet`, only warnings and failures are printed. Fixes are only
+/// offered when we're interactive and not `quiet`.
+pub fn exec(
+    wrapper: &TextWrapper,
+    sections: &[String],
+    quiet: bool,
+    non_interactive: opts::NonInteractive,
+) -> Result<Status, Error> {
+    let checks = checks();
+    let selected = select(&checks, sections)?;
+    let env = Env::new().map_err(Unrecoverable::from)?;
+    let mut fixes = Vec::new();
+    let status = run(&selected, &env, |section| {
+        section.print(wrapper, quiet);
+        fixes.extend_from_slice(section.fixes());
+    })?;
+    if !quiet && non_interactive.no() {
+        offer_fixes(&fixes)?;
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    type Ran = RefCell<Vec<&'static str>>;
+
+    fn fake_checks() -> Vec<Check<Ran>> {
+        vec![
+            Check {
+                slug: "clean",
+                title: "Clean",
+                run: |ran| {
+                    ran.borrow_mut().push("clean");
+                    Ok(Section::new("Clean").with_victory("fine"))
+                },
+            },
+            Check {
+                slug: "warned",
+                title: "Warned",
+                run: |ran| {
+                    ran.borrow_mut().push("warned");
+                    Ok(Section::new("Warned")
+                        .with_victory("fine")
+                        .with_warning("meh"))
+                },
+            },
+            Check {
+                slug: "failed",
+                title: "Failed",
+                run: |ran| {
+                    ran.borrow_mut().push("failed");
+                    Ok(Section::new("Failed")
+                        .with_warning("meh")
+                        .with_failure("bad"))
+                },
+            },
+        ]
+    }
+
+    fn run_fakes(slugs: &[&str]) -> Result<(Status, Vec<&'static str>), Error> {
+        let checks = fake_checks();
+        let slugs = slugs.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let selected = select(&checks, &slugs)?;
+        let ran = Ran::default();
+        let status = run(&selected, &ran, |_| ())?;
+        Ok((status, ran.into_inner()))
+    }
+
+    #[test]
+    fn only_selected_sections_run() {
+        let (_, ran) = run_fakes(&["failed", "clean"]).unwrap();
+        // Still in the usual order.
+        assert_eq!(ran, vec!["clean", "failed"]);
+        let (_, ran) = run_fakes(&[]).unwrap();
+        assert_eq!(ran, vec!["clean", "warned", "failed"]);
+    }
+
+    #[test]
+    fn unknown_sections_are_rejected() {
+        assert!(matches!(
+            run_fakes(&["clean", "nope"]),
+            Err(Error::SectionUnknown { slug }) if slug == "nope"
+        ));
+    }
+
+    #[test]
+    fn exit_codes_reflect_the_worst_section() {
+        let code = |slugs: &[&str]| run_fakes(slugs).unwrap().0.exit_code();
+        assert_eq!(code(&["clean"]), 0);
+        assert_eq!(code(&["clean", "warned"]), 1);
+        assert_eq!(code(&["warned", "failed"]), 2);
+        assert_eq!(code(&[]), 2);
+    }
+}
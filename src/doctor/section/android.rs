@@ -1,8 +1,154 @@
-use super::Section;
-use crate::{android, doctor::Unrecoverable, env::Env, util};
+use super::{Item, Section};
+use crate::{
+    android::{self, cargo_config, gradle_daemons, gradlew},
+    config::Config,
+    doctor::Unrecoverable,
+    env::Env,
+    target::TargetTrait as _,
+    util,
+};
+
+fn check_rust(android_env: &android::env::Env) -> Result<String, String> {
+    let version = util::RustVersion::check().map_err(|err| err.to_string())?;
+    let unmet = android::rust_version_requirements(&android_env.ndk)
+        .into_iter()
+        .filter(|requirement| !requirement.satisfied_by(&version))
+        .map(|requirement| requirement.reason)
+        .collect::<Vec<_>>();
+    if unmet.is_empty() {
+        Ok(format!(
+            "rustc v{} is compatible with the installed NDK",
+            version
+        ))
+    } else {
+        Err(format!(
+            "rustc v{} is too old for the installed NDK: {}",
+            version,
+            unmet.join(" ")
+        ))
+    }
+}
+
+// Only for the project we're in, since that's whose gradle version counts.
+fn check_gradle_daemons(config: Option<&Config>, android_env: &android::env::Env) -> Option<Item> {
+    let config = config?;
+    if !config.android().project_dir_exists() {
+        return None;
+    }
+    // Otherwise, the wrapper would download all of gradle just to say there
+    // aren't any daemons.
+    let cached = gradlew::gradle_user_home().map_or(false, |home| {
+        gradlew::distribution_cached(&config.android().project_dir(), &home)
+    });
+    if !cached {
+        return Some(Item::victory(
+            "No gradle daemons, since the gradle wrapper hasn't downloaded gradle yet",
+        ));
+    }
+    Some(
+        match gradle_daemons::status(config.android(), android_env) {
+            Ok(daemons) => {
+                let summary = gradle_daemons::Summary::new(&daemons);
+                if summary.too_many() {
+                    Item::warning(format!(
+                        "{}, which is a lot of memory to leave tied up; run `{}` to stop them",
+                        summary,
+                        gradle_daemons::STOP_COMMAND
+                    ))
+                } else {
+                    Item::victory(summary)
+                }
+            }
+            Err(err) => Item::warning(format!("Failed to check gradle daemons: {}", err)),
+        },
+    )
+}
+
+#[cfg(not(target_os = "macos"))]
+fn check_bundletool(config: Option<&Config>) -> Item {
+    use crate::util::tools::{Resolution, Tools};
+    // Outside of a project, there's only user scope.
+    let user = Tools::default();
+    let tools = config.map_or(&user, Config::tools);
+    match android::bundletool::BUNDLE_TOOL_JAR_INFO.resolve_with(tools) {
+        Resolution::Found {
+            path,
+            fallback: false,
+            scope,
+        } => Item::victory(format!("bundletool found at {:?} ({} scope)", path, scope)),
+        Resolution::Found { path, .. } => Item::warning(format!(
+            "`[tools] scope` is \"project\", but bundletool was only found at {:?} (user scope); `cargo mobile init --reinstall-deps` downloads it into {:?}",
+            path,
+            tools.project_dir()
+        )),
+        Resolution::Missing { install_path } => Item::victory(format!(
+            "bundletool isn't downloaded yet; it'll go in {:?} ({} scope) when it's needed",
+            install_path,
+            tools.scope()
+        )),
+    }
+}
+
+// Homebrew installs are machine-wide, so `[tools] scope` doesn't matter.
+#[cfg(target_os = "macos")]
+fn check_bundletool(_config: Option<&Config>) -> Item {
+    match crate::os::command_path("bundletool") {
+        Ok(output) => Item::victory(format!(
+            "bundletool found at {:?} (from Homebrew, which is always machine-wide)",
+            String::from_utf8_lossy(output.stdout()).trim()
+        )),
+        Err(_) => Item::victory(
+            "bundletool isn't installed yet; it'll be installed with Homebrew when it's needed",
+        ),
+    }
+}
+
+fn check_cargo_config(config: Option<&Config>, android_env: &android::env::Env) -> Vec<Item> {
+    let targets = android::target::Target::all();
+    let triples = targets
+        .values()
+        .map(|target| target.triple)
+        .collect::<Vec<_>>();
+    // Outside of a project, all we can check is the configs from here up.
+    let (root_dir, injected) = match config {
+        Some(config) => (
+            config.app().root_dir().to_owned(),
+            targets
+                .values()
+                .filter_map(|target| {
+                    target
+                        .injected_cargo_config(config.android(), android_env)
+                        .ok()
+                })
+                .flatten()
+                .collect(),
+        ),
+        None => (std::env::current_dir().unwrap_or_default(), Vec::new()),
+    };
+    let conflicts = cargo_config::check(&root_dir, &triples, injected);
+    if conflicts.is_empty() {
+        vec![Item::victory(
+            "Cargo configs agree on linkers, `ar`, and `rustflags` for Android targets",
+        )]
+    } else {
+        conflicts.iter().map(Item::warning).collect()
+    }
+}
 
 pub fn check(env: &Env) -> Result<Section, Unrecoverable> {
     let section = Section::new("Android developer tools");
+    // Some checks only make sense inside a project.
+    let config = match Config::load(".") {
+        Ok(config) => Some(config),
+        Err(err) => {
+            log::info!(
+                "skipping project-specific Android checks, since no config was loaded: {:?}",
+                err
+            );
+            None
+        }
+    };
+    let config = config.as_ref();
     Ok(match android::env::Env::from_env(env.clone()) {
         Ok(android_env) => section
             // It'd be a bit too inconvenient to use `map` here, since we need
@@ -17,12 +163,17 @@ pub fn check(env: &Env) -> Result<Section, Unrecoverable> {
             })
             .with_item(match android_env.ndk.version() {
                 Ok(ndk_version) => Ok(format!(
-                    "NDK v{} installed at {:?}",
+                    "NDK v{} installed at {:?} (host tag `{}`)",
                     ndk_version,
                     util::contract_home(android_env.ndk.home())?,
+                    android_env.ndk.host_tag(),
                 )),
                 Err(err) => Err(format!("Failed to get NDK version: {}", err)),
-            }),
+            })
+            .with_item(check_rust(&android_env))
+            .with_items(check_gradle_daemons(config, &android_env))
+            .with_item(check_bundletool(config))
+            .with_items(check_cargo_config(config, &android_env)),
         Err(err) => section.with_failure(err),
     })
 }
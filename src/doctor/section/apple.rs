@@ -1,50 +1,50 @@
-use super::{Item, Section};
-use crate::{
-    apple::{deps::xcode_plugin, system_profile::DeveloperTools, teams},
-    util::prompt,
+use super::{Fix, Item, Section};
+use crate::apple::{
+    deps::{self, brew, xcode_plugin},
+    system_profile::DeveloperTools,
+    teams,
 };
 use std::path::Path;
 
-fn validate_developer_dir() -> Result<String, String> {
-    static FORBIDDEN: &str = "/Library/Developer/CommandLineTools";
-    static SUGGESTED: &str = "/Applications/Xcode.app/Contents/Developer";
-    let xcode_developer_dir = xcode_plugin::xcode_developer_dir()
-        .map_err(|err| format!("Failed to get active Xcode developer dir: {}", err))?;
-    let xcode_developer_dir = {
-        if xcode_developer_dir == Path::new(FORBIDDEN) {
-            println!(
-                "Your active toolchain appears to be the Apple command-line tools: {:?}",
-                xcode_developer_dir
-            );
-            println!("Changing your active toolchain to Xcode may be necessary for everything to work correctly.");
-            let answer = loop {
-                if let Some(answer) = prompt::yes_no(
-                    format!("Would you like us to change it to {:?} for you?", SUGGESTED),
-                    Some(prompt::YesOrNo::Yes),
-                )
-                .map_err(|err| {
-                    format!(
-                        "Failed to prompt for changing the Xcode developer dir: {}",
-                        err
-                    )
-                })? {
-                    break answer;
-                }
-            };
-            if answer.yes() {
-                bossy::Command::impure_parse("xcode-select -s")
-                    .with_arg(SUGGESTED)
-                    .run_and_wait()
-                    .map_err(|err| format!("Failed to update Xcode developer dir: {}", err))?;
-                Path::new(SUGGESTED)
-            } else {
-                &xcode_developer_dir
-            }
-        } else {
-            &xcode_developer_dir
+static COMMAND_LINE_TOOLS_DIR: &str = "/Library/Developer/CommandLineTools";
+static XCODE_DEVELOPER_DIR: &str = "/Applications/Xcode.app/Contents/Developer";
+
+// Xcode builds don't work with the command-line tools selected, which we can
+// fix, but only after asking.
+fn developer_dir_item(xcode_developer_dir: &Path) -> (Item, Option<Fix>) {
+    if xcode_developer_dir == Path::new(COMMAND_LINE_TOOLS_DIR) {
+        (
+            Item::warning(format!(
+                "Active developer dir is the Apple command-line tools ({:?}); changing it to Xcode with `xcode-select -s {}` may be necessary for everything to work correctly",
+                xcode_developer_dir, XCODE_DEVELOPER_DIR
+            )),
+            Some(Fix {
+                prompt: format!(
+                    "Would you like us to change the active developer dir to {:?} for you?",
+                    XCODE_DEVELOPER_DIR
+                ),
+                program: "xcode-select",
+                args: vec!["-s".to_owned(), XCODE_DEVELOPER_DIR.to_owned()],
+            }),
+        )
+    } else {
+        (
+            Item::victory(format!("Active developer dir: {:?}", xcode_developer_dir)),
+            None,
+        )
+    }
+}
+
+fn validate_developer_dir(section: Section) -> Section {
+    match xcode_plugin::xcode_developer_dir() {
+        Ok(xcode_developer_dir) => {
+            let (item, fix) = developer_dir_item(&xcode_developer_dir);
+            section.with_item(item).with_fixes(fix)
         }
-    };
-    Ok(format!("Active developer dir: {:?}", xcode_developer_dir))
+        Err(err) => {
+            section.with_failure(format!("Failed to get active Xcode developer dir: {}", err))
+        }
+    }
 }
 
 fn validate_xcode_plugin(xcode_version: (u32, u32), section: Section) -> Section {
@@ -101,26 +101,41 @@ fn validate_xcode_plugin(xcode_version: (u32, u32), section: Section) -> Section
     }
 }
 
+fn brew_prefixes() -> impl Iterator<Item = Item> {
+    let host = brew::brews().host;
+    deps::PACKAGES.iter().filter_map(move |spec| {
+        spec.brew().map(|brew| {
+            let msg = format!("`{}` is from the {}", spec.pkg_name, brew);
+            if brew.matches(host) {
+                Item::victory(msg)
+            } else {
+                Item::warning(format!("{}, which doesn't match this {} Mac", msg, host))
+            }
+        })
+    })
+}
+
 pub fn check() -> Section {
     let xcode_version = DeveloperTools::new().map(|dev_tools| dev_tools.version);
-    let section = Section::new("Apple developer tools")
-        .with_item(
+    let section = validate_developer_dir(
+        Section::new("Apple developer tools").with_item(
             xcode_version
                 .as_ref()
                 .map(|(major, minor)| format!("Xcode v{}.{}", major, minor))
                 .map_err(|err| format!("Failed to check Xcode version: {}", err)),
-        )
-        .with_item(validate_developer_dir())
-        .with_item(
-            bossy::Command::impure_parse("ios-deploy --version")
-                .run_and_wait_for_str(|version| format!("ios-deploy v{}", version.trim()))
-                .map_err(|err| format!("Failed to check ios-deploy version: {}", err)),
-        )
-        .with_item(
-            bossy::Command::impure_parse("xcodegen --version")
-                .run_and_wait_for_str(|version| version.trim().replace("Version: ", "XcodeGen v"))
-                .map_err(|err| format!("Failed to check ios-deploy version: {}", err)),
-        );
+        ),
+    )
+    .with_item(
+        bossy::Command::impure_parse("ios-deploy --version")
+            .run_and_wait_for_str(|version| format!("ios-deploy v{}", version.trim()))
+            .map_err(|err| format!("Failed to check ios-deploy version: {}", err)),
+    )
+    .with_item(
+        bossy::Command::impure_parse("xcodegen --version")
+            .run_and_wait_for_str(|version| version.trim().replace("Version: ", "XcodeGen v"))
+            .map_err(|err| format!("Failed to check XcodeGen version: {}", err)),
+    )
+    .with_items(brew_prefixes());
     let section = if let Ok(version) = xcode_version {
         validate_xcode_plugin(version, section)
     } else {
@@ -137,3 +152,24 @@ pub fn check() -> Section {
         Err(err) => section.with_failure(format!("Failed to find development teams: {}", err)),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command_line_tools_dir_gets_a_fix() {
+        let (item, fix) = developer_dir_item(Path::new(COMMAND_LINE_TOOLS_DIR));
+        assert!(item.is_warning());
+        let fix = fix.unwrap();
+        assert_eq!(fix.program, "xcode-select");
+        assert_eq!(fix.args, vec!["-s", XCODE_DEVELOPER_DIR]);
+    }
+
+    #[test]
+    fn xcode_dir_is_fine() {
+        let (item, fix) = developer_dir_item(Path::new(XCODE_DEVELOPER_DIR));
+        assert!(item.is_victory());
+        assert_eq!(fix, None);
+    }
+}
@@ -1,8 +1,13 @@
 use super::Section;
 use crate::{
+    config::Config,
     doctor::Unrecoverable,
     os,
-    util::{self, cli::VERSION_SHORT},
+    util::{self, cli::VERSION_SHORT, root},
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
 };
 
 fn check_os() -> Result<String, String> {
@@ -11,22 +16,138 @@ fn check_os() -> Result<String, String> {
         .map_err(|err| format!("Failed to get OS info: {}", err))
 }
 
+// The NDK-specific requirements need an NDK, which is the Android section's
+// business, so only the ones we can check everywhere are here.
+fn platform_rust_requirements() -> Vec<util::RustVersionRequirement> {
+    #[cfg(target_os = "macos")]
+    {
+        crate::apple::rust_version_requirements()
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Vec::new()
+    }
+}
+
 fn check_rust() -> Result<String, String> {
     util::RustVersion::check()
         .map_err(|err| err.to_string())
         .and_then(|version| {
-            version
-                .valid()
-                .then(|| format!("rustc v{}", version.to_string()))
-                .ok_or_else(|| {
+            let unmet = platform_rust_requirements()
+                .into_iter()
+                .filter(|requirement| !requirement.satisfied_by(&version))
+                .map(|requirement| {
                     format!(
-                        "iOS linking is broken on rustc v{}; please update to 1.49.0 or later",
-                        version
+                        "{} needs {} or later",
+                        requirement.platform, requirement.min
                     )
                 })
+                .collect::<Vec<_>>();
+            if unmet.is_empty() {
+                Ok(format!("rustc v{}", version))
+            } else {
+                Err(format!(
+                    "rustc v{} is too old: {}",
+                    version,
+                    unmet.join(", ")
+                ))
+            }
         })
 }
 
+fn check_ownership() -> Option<Result<String, String>> {
+    let ids = os::effective_ids()?;
+    if ids.0 == 0 {
+        return Some(Ok(
+            "Running as root, so root-owned files weren't checked for".to_owned(),
+        ));
+    }
+    let dirs = output_dirs();
+    let found = dirs
+        .iter()
+        .flat_map(|dir| root::root_owned(dir))
+        .collect::<Vec<_>>();
+    Some(if found.is_empty() {
+        Ok(format!(
+            "No root-owned files in {} checked director{}",
+            dirs.len(),
+            if dirs.len() == 1 { "y" } else { "ies" }
+        ))
+    } else {
+        Err(format!(
+            "Found root-owned files, probably from running with `sudo`: {:?}. To fix this, run `{}`",
+            found,
+            root::chown_command(&found, ids)
+        ))
+    })
+}
+
+// Everything we'd write to, whether or not it exists yet. Everything outside
+// of the project is under the install dir.
+fn write_dirs() -> Vec<PathBuf> {
+    let mut dirs = util::install_dir().ok().into_iter().collect::<Vec<_>>();
+    if let Ok(config) = Config::load(".") {
+        dirs.push(util::cargo_target_dir(config.app().root_dir()));
+        dirs.push(config.android().project_dir());
+        #[cfg(target_os = "macos")]
+        dirs.push(config.apple().project_dir());
+    }
+    dirs
+}
+
+// Permissions bits don't say whether *we* can write somewhere, so we just try
+// it in the closest dir that exists.
+fn writable(dir: &Path) -> bool {
+    let existing = match dir.ancestors().find(|dir| dir.is_dir()) {
+        Some(existing) => existing,
+        None => return false,
+    };
+    let probe = existing.join(format!(".cargo-mobile-write-check-{}", std::process::id()));
+    let writable = fs::write(&probe, "").is_ok();
+    let _ = fs::remove_file(&probe);
+    writable
+}
+
+// Where an earlier run as root would've written things.
+fn output_dirs() -> Vec<PathBuf> {
+    let mut dirs = write_dirs();
+    dirs.retain(|dir| dir.exists());
+    dirs
+}
+
+fn check_write_dirs() -> Result<String, String> {
+    let dirs = write_dirs();
+    let contracted =
+        |dir: &PathBuf| util::contract_home(dir).unwrap_or_else(|_| dir.display().to_string());
+    let unwritable = dirs
+        .iter()
+        .filter(|dir| !writable(dir))
+        .map(contracted)
+        .collect::<Vec<_>>();
+    let source = if util::install_dir_override().is_some() {
+        format!(
+            " (the install dir is from `{}`)",
+            util::INSTALL_DIR_OVERRIDE_VAR
+        )
+    } else {
+        String::new()
+    };
+    if unwritable.is_empty() {
+        Ok(format!(
+            "Writes to {}{}",
+            dirs.iter().map(contracted).collect::<Vec<_>>().join(", "),
+            source
+        ))
+    } else {
+        Err(format!(
+            "Can't write to {}{}; if the install dir isn't writable, set `{}` to somewhere that is",
+            unwritable.join(", "),
+            source,
+            util::INSTALL_DIR_OVERRIDE_VAR
+        ))
+    }
+}
+
 pub fn check() -> Result<Section, Unrecoverable> {
     let section = Section::new(format!("cargo-mobile {}", VERSION_SHORT));
     Ok(match util::install_dir() {
@@ -49,5 +170,7 @@ pub fn check() -> Result<Section, Unrecoverable> {
         Err(err) => section.with_failure(err),
     }
     .with_item(check_os())
-    .with_item(check_rust()))
+    .with_item(check_rust())
+    .with_item(check_write_dirs())
+    .with_items(check_ownership()))
 }
@@ -10,7 +10,7 @@ pub fn check(env: &Env) -> Section {
     #[cfg(target_os = "macos")]
     let section = {
         use crate::apple::ios_deploy;
-        match ios_deploy::device_list(env) {
+        match ios_deploy::device_list(env, Default::default()) {
             Ok(list) => section.with_victories(list),
             Err(err) => section.with_failure(format!("Failed to get iOS device list: {}", err)),
         }
@@ -4,6 +4,7 @@ pub mod apple;
 pub mod cargo_mobile;
 pub mod device_list;
 
+use super::Status;
 use crate::util::{
     self,
     cli::{colors, TextWrapper},
@@ -41,21 +42,39 @@ impl Label {
         }
     }
 
-    fn format_title(self, title: &str) -> colored::ColoredString {
-        format!("[{}] {}", self.title_symbol(), title)
-            .color(self.color())
-            .bold()
+    fn format_title(self, title: &str, colorize: bool) -> String {
+        let title = format!("[{}] {}", self.title_symbol(), title);
+        if colorize {
+            title.color(self.color()).bold().to_string()
+        } else {
+            title
+        }
     }
 
-    fn format_item(self, msg: &str) -> colored::ColoredString {
+    fn format_item(self, msg: &str, colorize: bool) -> String {
         let item = format!("{} {}", self.item_symbol(), msg);
         match self {
-            Self::Victory => item.normal(),
-            _ => item.color(self.color()).bold(),
+            Self::Warning | Self::Error if colorize => item.color(self.color()).bold().to_string(),
+            _ => item,
         }
     }
 }
 
+/// Something a check found that we know how to fix. Checks never prompt, so
+/// `doctor` offers these once everything's been checked.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fix {
+    pub prompt: String,
+    pub program: &'static str,
+    pub args: Vec<String>,
+}
+
+impl Fix {
+    pub fn command(&self) -> bossy::Command {
+        bossy::Command::impure(self.program).with_args(&self.args)
+    }
+}
+
 #[derive(Debug)]
 struct Item {
     label: Label,
@@ -92,6 +111,10 @@ impl Item {
         util::unwrap_either(result.map(Self::victory).map_err(Self::failure))
     }
 
+    fn is_victory(&self) -> bool {
+        matches!(self.label, Label::Victory)
+    }
+
     fn is_warning(&self) -> bool {
         matches!(self.label, Label::Warning)
     }
@@ -100,8 +123,8 @@ impl Item {
         matches!(self.label, Label::Error)
     }
 
-    fn format(&self) -> colored::ColoredString {
-        self.label.format_item(&self.msg)
+    fn format(&self, colorize: bool) -> String {
+        self.label.format_item(&self.msg, colorize)
     }
 }
 
@@ -109,13 +132,15 @@ impl Item {
 pub struct Section {
     title: String,
     items: Vec<Item>,
+    fixes: Vec<Fix>,
 }
 
 impl Section {
-    fn new(title: impl ToString) -> Self {
+    pub fn new(title: impl ToString) -> Self {
         Self {
             title: title.to_string(),
             items: Default::default(),
+            fixes: Default::default(),
         }
     }
 
@@ -124,11 +149,15 @@ impl Section {
         self
     }
 
-    fn with_victory(self, victory: impl ToString) -> Self {
+    pub fn with_victory(self, victory: impl ToString) -> Self {
         self.with_item(Item::victory(victory))
     }
 
-    fn with_failure(self, failure: impl ToString) -> Self {
+    pub fn with_warning(self, warning: impl ToString) -> Self {
+        self.with_item(Item::warning(warning))
+    }
+
+    pub fn with_failure(self, failure: impl ToString) -> Self {
         self.with_item(Item::failure(failure))
     }
 
@@ -141,6 +170,15 @@ impl Section {
         self.with_items(victories.into_iter().map(Item::victory))
     }
 
+    fn with_fixes(mut self, fixes: impl IntoIterator<Item = Fix>) -> Self {
+        self.fixes.extend(fixes);
+        self
+    }
+
+    pub fn fixes(&self) -> &[Fix] {
+        &self.fixes
+    }
+
     pub fn is_empty(&self) -> bool {
         self.items.is_empty()
     }
@@ -163,25 +201,128 @@ impl Section {
         }
     }
 
-    pub fn print(&self, wrapper: &TextWrapper) {
+    pub fn status(&self) -> Status {
+        match self.label() {
+            Label::Victory => Status::Clean,
+            Label::Warning => Status::Warnings,
+            Label::Error => Status::Failures,
+        }
+    }
+
+    /// With `quiet`, victories are left out, along with sections that don't
+    /// have anything else.
+    fn format(&self, wrapper: &TextWrapper, quiet: bool, colorize: bool) -> Option<String> {
+        if quiet && self.status() == Status::Clean {
+            return None;
+        }
         static BULLET_INDENT: &str = "    ";
         static HANGING_INDENT: &str = "      ";
         let bullet_wrapper = wrapper
             .clone()
             .initial_indent(BULLET_INDENT)
             .subsequent_indent(HANGING_INDENT);
-        println!(
-            "\n{}",
-            // The `.to_string()` at the end is necessary for the color/bold to
-            // actually show - otherwise, the colored string just `AsRef`s to
-            // satisfy `TextWrapper::fill` and the formatting is left behind.
-            wrapper.fill(&self.label().format_title(&self.title).to_string())
+        let mut s = format!(
+            "\n{}\n",
+            wrapper.fill(&self.label().format_title(&self.title, colorize))
         );
-        for report_bullet in &self.items {
-            println!(
-                "{}",
-                bullet_wrapper.fill(&report_bullet.format().to_string())
-            );
+        for item in self
+            .items
+            .iter()
+            .filter(|item| !quiet || !item.is_victory())
+        {
+            s.push_str(&bullet_wrapper.fill(&item.format(colorize)));
+            s.push('\n');
         }
+        Some(s)
+    }
+
+    pub fn print(&self, wrapper: &TextWrapper, quiet: bool) {
+        if let Some(s) = self.format(
+            wrapper,
+            quiet,
+            colored::control::SHOULD_COLORIZE.should_colorize(),
+        ) {
+            print!("{}", s);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn sample() -> Section {
+        Section::new("Apple developer tools")
+            .with_victory("Xcode v14.2")
+            .with_warning(
+                "`xcodegen` is from the Intel Homebrew, which doesn't match this Apple Silicon Mac",
+            )
+            .with_victory("ios-deploy v1.12.1")
+    }
+
+    fn format(section: &Section, quiet: bool) -> Option<String> {
+        let wrapper = TextWrapper::with_splitter(46, textwrap::NoHyphenation);
+        section.format(&wrapper, quiet, false)
+    }
+
+    #[rstest(
+        label,
+        title_symbol,
+        item_symbol,
+        color,
+        case(Label::Victory, "✔", "•", colors::VICTORY),
+        case(Label::Warning, "✔", "✗", colors::WARNING),
+        case(Label::Error, "!", "✗", colors::ERROR)
+    )]
+    fn labels_have_their_own_look(
+        label: Label,
+        title_symbol: &str,
+        item_symbol: &str,
+        color: colored::Color,
+    ) {
+        assert_eq!(label.title_symbol(), title_symbol);
+        assert_eq!(label.item_symbol(), item_symbol);
+        assert_eq!(label.color(), color);
+    }
+
+    #[test]
+    fn formats_sections() {
+        assert_eq!(
+            format(&sample(), false).unwrap(),
+            "
+[✔] Apple developer tools
+    • Xcode v14.2
+    ✗ `xcodegen` is from the Intel Homebrew,
+      which doesn't match this Apple Silicon
+      Mac
+    • ios-deploy v1.12.1
+"
+        );
+    }
+
+    #[test]
+    fn quiet_leaves_out_victories() {
+        assert_eq!(
+            format(
+                &sample().with_failure("Failed to find development teams"),
+                true
+            )
+            .unwrap(),
+            "
+[!] Apple developer tools
+    ✗ `xcodegen` is from the Intel Homebrew,
+      which doesn't match this Apple Silicon
+      Mac
+    ✗ Failed to find development teams
+"
+        );
+        assert_eq!(
+            format(
+                &Section::new("Connected devices").with_victory("iPhone"),
+                true
+            ),
+            None
+        );
     }
 }
@@ -159,6 +159,14 @@ impl DotCargo {
         }
     }
 
+    pub fn target(&self, name: &str) -> Option<&DotCargoTarget> {
+        self.target.get(name)
+    }
+
+    pub fn remove_target(&mut self, name: &str) {
+        self.target.remove(name);
+    }
+
     pub fn write(self, app: &App) -> Result<(), WriteError> {
         let path = Self::create_dir_and_get_path(app)
             .map_err(|(path, cause)| WriteError::DirCreationFailed { path, cause })?;
@@ -8,7 +8,7 @@ pub trait ExplicitEnv: Debug {
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("The `HOME` environment variable isn't set, which is pretty weird: {0}")]
+    #[error("The `HOME` environment variable isn't set, which is pretty weird; if that's on purpose, set `CARGO_MOBILE_HOME` to where cargo-mobile should keep its files: {0}")]
     HomeNotSet(#[source] std::env::VarError),
     #[error("The `PATH` environment variable isn't set, which is super weird: {0}")]
     PathNotSet(#[source] std::env::VarError),
@@ -22,7 +22,9 @@ impl Reportable for Error {
 
 #[derive(Clone, Debug)]
 pub struct Env {
-    home: String,
+    // Only `None` when `CARGO_MOBILE_HOME` is set, since then nothing of ours
+    // needs it.
+    home: Option<String>,
     path: String,
     term: Option<String>,
     ssh_auth_sock: Option<String>,
@@ -30,7 +32,18 @@ pub struct Env {
 
 impl Env {
     pub fn new() -> Result<Self, Error> {
-        let home = std::env::var("HOME").map_err(Error::HomeNotSet)?;
+        let home = match std::env::var("HOME") {
+            Ok(home) => Some(home),
+            Err(err) if crate::util::install_dir_override().is_some() => {
+                log::info!(
+                    "`HOME` isn't set ({}), but `{}` is, so carrying on without it",
+                    err,
+                    crate::util::INSTALL_DIR_OVERRIDE_VAR
+                );
+                None
+            }
+            Err(err) => return Err(Error::HomeNotSet(err)),
+        };
         let path = std::env::var("PATH").map_err(Error::PathNotSet)?;
         let term = std::env::var("TERM").ok();
         let ssh_auth_sock = std::env::var("SSH_AUTH_SOCK").ok();
@@ -54,7 +67,10 @@ impl Env {
 
 impl ExplicitEnv for Env {
     fn explicit_env(&self) -> Vec<(&str, &std::ffi::OsStr)> {
-        let mut env = vec![("HOME", self.home.as_ref()), ("PATH", self.path.as_ref())];
+        let mut env = vec![("PATH", self.path.as_ref())];
+        if let Some(home) = self.home.as_ref() {
+            env.push(("HOME", home.as_ref()));
+        }
         if let Some(term) = self.term.as_ref() {
             env.push(("TERM", term.as_ref()));
         }
@@ -0,0 +1,399 @@
+//! Commands from the `[hooks]` section of the config, run at fixed points in
+//! the build, so things like asset cookers don't need wrapper scripts.
+
+use crate::{
+    env::ExplicitEnv,
+    opts::Profile,
+    util::cli::{Report, Reportable},
+};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::{self, Display},
+    path::Path,
+};
+
+pub static KEY: &str = "hooks";
+
+/// Where a hook can run. Pre hooks run before anything else happens for a
+/// target, and post hooks only run if everything succeeded.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Event {
+    AndroidPreBuild,
+    AndroidPostBuild,
+    AndroidPreRun,
+    ApplePreBuild,
+    ApplePostBuild,
+    ApplePreRun,
+    ApplePreArchive,
+    ApplePostArchive,
+}
+
+impl Event {
+    pub const ALL: &'static [Self] = &[
+        Self::AndroidPreBuild,
+        Self::AndroidPostBuild,
+        Self::AndroidPreRun,
+        Self::ApplePreBuild,
+        Self::ApplePostBuild,
+        Self::ApplePreRun,
+        Self::ApplePreArchive,
+        Self::ApplePostArchive,
+    ];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::AndroidPreBuild => "android-pre-build",
+            Self::AndroidPostBuild => "android-post-build",
+            Self::AndroidPreRun => "android-pre-run",
+            Self::ApplePreBuild => "apple-pre-build",
+            Self::ApplePostBuild => "apple-post-build",
+            Self::ApplePreRun => "apple-pre-run",
+            Self::ApplePreArchive => "apple-pre-archive",
+            Self::ApplePostArchive => "apple-post-archive",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|event| event.key() == key)
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}.{}`", KEY, self.key())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum RawCommand {
+    Line(String),
+    Args(Vec<String>),
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum RawHook {
+    Command(RawCommand),
+    Detailed {
+        command: RawCommand,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        shell: bool,
+    },
+}
+
+pub type Raw = BTreeMap<String, RawHook>;
+
+/// TOML needs tables to come after plain values, which sorting by name doesn't
+/// guarantee once some hooks use the detailed form.
+pub fn serialize_raw<S: Serializer>(raw: &Option<Raw>, serializer: S) -> Result<S::Ok, S::Error> {
+    match raw {
+        Some(raw) => toml::ser::tables_last(raw, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    EventUnknown { key: String },
+    CommandEmpty { event: Event },
+    QuoteUnclosed { event: Event },
+}
+
+impl Error {
+    pub fn report(&self, msg: &str) -> Report {
+        match self {
+            Self::EventUnknown { key } => Report::error(
+                msg,
+                format!(
+                    "`{}.{}` isn't a hook; the hooks are {}.",
+                    KEY,
+                    key,
+                    Event::ALL
+                        .iter()
+                        .map(|event| format!("`{}`", event.key()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            ),
+            Self::CommandEmpty { event } => {
+                Report::error(msg, format!("{} has an empty command.", event))
+            }
+            Self::QuoteUnclosed { event } => {
+                Report::error(msg, format!("{} has a quote that's never closed.", event))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HookFailed {
+    event: Event,
+    command: String,
+    cause: bossy::Error,
+}
+
+impl Reportable for HookFailed {
+    fn report(&self) -> Report {
+        Report::error(
+            format!("Hook {} failed", self.event),
+            format!("`{}` failed: {}", self.command, self.cause),
+        )
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub enum Hook {
+    /// Run directly; the first arg is the program.
+    Args(Vec<String>),
+    /// Run through `sh -c`, which is only done when asked for since quoting
+    /// rules then depend on the user's shell.
+    Shell(String),
+}
+
+impl Hook {
+    fn from_raw(event: Event, raw: RawHook) -> Result<Self, Error> {
+        let (command, shell) = match raw {
+            RawHook::Command(command) => (command, false),
+            RawHook::Detailed { command, shell } => (command, shell),
+        };
+        let hook = match (command, shell) {
+            (RawCommand::Line(line), true) => Self::Shell(line),
+            (RawCommand::Args(args), true) => Self::Shell(args.join(" ")),
+            // Split like a shell would, so quoted args can have spaces.
+            (RawCommand::Line(line), false) => {
+                Self::Args(shell_words::split(&line).map_err(|_| Error::QuoteUnclosed { event })?)
+            }
+            (RawCommand::Args(args), false) => Self::Args(args),
+        };
+        let empty = match &hook {
+            Self::Args(args) => args.first().map_or(true, |program| program.is_empty()),
+            Self::Shell(line) => line.trim().is_empty(),
+        };
+        if empty {
+            Err(Error::CommandEmpty { event })
+        } else {
+            Ok(hook)
+        }
+    }
+
+    fn command(&self) -> bossy::Command {
+        match self {
+            Self::Args(args) => bossy::Command::pure(&args[0]).with_args(&args[1..]),
+            Self::Shell(line) => bossy::Command::pure("sh").with_args(&["-c", line.as_str()]),
+        }
+    }
+}
+
+impl Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Args(args) => write!(f, "{}", shell_words::join(args)),
+            Self::Shell(line) => write!(f, "sh -c {:?}", line),
+        }
+    }
+}
+
+/// What the surrounding operation is doing, exported as `CM_*` variables.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Context<'a> {
+    pub profile: Option<Profile>,
+    pub target: Option<&'a str>,
+    pub artifact_path: Option<&'a Path>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(profile: Profile) -> Self {
+        Self {
+            profile: Some(profile),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_target(self, target: &'a str) -> Self {
+        Self {
+            target: Some(target),
+            ..self
+        }
+    }
+
+    pub fn with_artifact_path(self, artifact_path: &'a Path) -> Self {
+        Self {
+            artifact_path: Some(artifact_path),
+            ..self
+        }
+    }
+
+    fn vars(&self) -> Vec<(&'static str, OsString)> {
+        let mut vars = Vec::new();
+        if let Some(profile) = self.profile {
+            vars.push(("CM_PROFILE", profile.as_str().into()));
+        }
+        if let Some(target) = self.target {
+            vars.push(("CM_TARGET", target.into()));
+        }
+        if let Some(artifact_path) = self.artifact_path {
+            vars.push(("CM_ARTIFACT_PATH", artifact_path.into()));
+        }
+        vars
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Hooks {
+    hooks: BTreeMap<&'static str, Hook>,
+}
+
+impl Hooks {
+    pub fn from_raw(raw: Option<Raw>) -> Result<Self, Error> {
+        let mut hooks = BTreeMap::new();
+        for (key, raw) in raw.unwrap_or_default() {
+            let event = Event::from_key(&key).ok_or(Error::EventUnknown { key })?;
+            hooks.insert(event.key(), Hook::from_raw(event, raw)?);
+        }
+        Ok(Self { hooks })
+    }
+
+    pub fn get(&self, event: Event) -> Option<&Hook> {
+        self.hooks.get(event.key())
+    }
+
+    /// Runs the hook for `event` if there is one, from `root_dir` and with
+    /// `env` plus whatever `context` has.
+    pub fn run(
+        &self,
+        event: Event,
+        root_dir: &Path,
+        env: &impl ExplicitEnv,
+        context: Context<'_>,
+    ) -> Result<(), HookFailed> {
+        let hook = match self.get(event) {
+            Some(hook) => hook,
+            None => return Ok(()),
+        };
+        println!("Running {} hook: {}", event, hook);
+        hook.command()
+            .with_current_dir(root_dir)
+            .with_env_vars(env.explicit_env())
+            .with_env_vars(context.vars())
+            .run_and_wait()
+            .map(|_| ())
+            .map_err(|cause| HookFailed {
+                event,
+                command: hook.to_string(),
+                cause,
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+    use std::fs;
+
+    fn hooks(toml: &str) -> Result<Hooks, Error> {
+        Hooks::from_raw(Some(toml::from_str(toml).unwrap()))
+    }
+
+    #[rstest(
+        toml,
+        expected,
+        case(
+            r#"android-pre-build = "./cook --fast""#,
+            Hook::Args(vec!["./cook".to_owned(), "--fast".to_owned()])
+        ),
+        case(
+            r#"android-pre-build = "./cook 'two words' --out=\"a b\"""#,
+            Hook::Args(vec![
+                "./cook".to_owned(),
+                "two words".to_owned(),
+                "--out=a b".to_owned()
+            ])
+        ),
+        case(
+            r#"android-pre-build = ["./cook", "two words"]"#,
+            Hook::Args(vec!["./cook".to_owned(), "two words".to_owned()])
+        ),
+        case(
+            r#"android-pre-build = { command = "./cook && ./bake", shell = true }"#,
+            Hook::Shell("./cook && ./bake".to_owned())
+        ),
+        case(
+            r#"android-pre-build = { command = ["./cook"] }"#,
+            Hook::Args(vec!["./cook".to_owned()])
+        )
+    )]
+    fn parses_hooks(toml: &str, expected: Hook) {
+        assert_eq!(
+            hooks(toml).unwrap().get(Event::AndroidPreBuild),
+            Some(&expected)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_hooks() {
+        assert!(matches!(
+            hooks(r#"android-pre-bulid = "./cook""#),
+            Err(Error::EventUnknown { key }) if key == "android-pre-bulid"
+        ));
+        assert!(matches!(
+            hooks(r#"apple-pre-run = "./cook 'unclosed""#),
+            Err(Error::QuoteUnclosed {
+                event: Event::ApplePreRun
+            })
+        ));
+        assert!(matches!(
+            hooks(r#"apple-pre-archive = []"#),
+            Err(Error::CommandEmpty {
+                event: Event::ApplePreArchive
+            })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hooks_get_context_and_fail_loudly() {
+        let root = TempDirGuard::new().unwrap();
+        let env = crate::env::Env::new().unwrap();
+        // The "fake command" just records what it was given.
+        let hooks = hooks(
+            r#"
+            apple-post-archive = { command = "echo \"$CM_PROFILE $CM_TARGET $CM_ARTIFACT_PATH\" >> record.txt", shell = true }
+            android-pre-build = ["sh", "-c", "echo \"$CM_PROFILE ${CM_ARTIFACT_PATH:-none}\" >> record.txt"]
+            android-post-build = ["false"]
+            "#,
+        )
+        .unwrap();
+        let context = Context::new(Profile::Release);
+        hooks
+            .run(
+                Event::ApplePostArchive,
+                &root,
+                &env,
+                context
+                    .with_target("aarch64-apple-ios")
+                    .with_artifact_path(Path::new("out/App.xcarchive")),
+            )
+            .unwrap();
+        hooks
+            .run(Event::AndroidPreBuild, &root, &env, context)
+            .unwrap();
+        // Events without a hook are no-ops.
+        hooks
+            .run(Event::AndroidPreRun, &root, &env, context)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("record.txt")).unwrap(),
+            "release aarch64-apple-ios out/App.xcarchive\nrelease none\n"
+        );
+        let err = hooks
+            .run(Event::AndroidPostBuild, &root, &env, context)
+            .unwrap_err();
+        assert_eq!(err.event, Event::AndroidPostBuild);
+        assert!(err.report().details().contains("`false`"));
+    }
+}
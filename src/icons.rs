@@ -0,0 +1,225 @@
+//! Keeps a generated project's icons in sync with the template pack they came
+//! from, without touching anything else in the project.
+
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to walk icon source dir {path:?}: {source}")]
+    WalkFailed {
+        path: PathBuf,
+        source: walkdir::Error,
+    },
+    #[error("Failed to read icon {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to write icon {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to render icon path {path:?}: {cause}")]
+    RenderFailed {
+        path: PathBuf,
+        cause: bicycle::RenderingError,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// Overwrite any icon that differs from its source.
+    Overwrite,
+    /// Don't write anything; just report what's stale.
+    Check,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Change {
+    Added,
+    Updated,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added => write!(f, "added"),
+            Self::Updated => write!(f, "updated"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Changes {
+    changes: Vec<(PathBuf, Change)>,
+    unchanged: usize,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Prints each changed icon (relative to the project dir, which shows
+    /// which size it is), then a count.
+    pub fn print(&self, mode: Mode) {
+        for (rel, change) in &self.changes {
+            let change = if mode == Mode::Check {
+                match change {
+                    Change::Added => "missing".to_owned(),
+                    Change::Updated => "stale".to_owned(),
+                }
+            } else {
+                change.to_string()
+            };
+            println!("  {} {}", change, rel.display());
+        }
+        println!(
+            "{} icon{} {}, {} up to date",
+            self.changes.len(),
+            if self.changes.len() == 1 { "" } else { "s" },
+            if mode == Mode::Check {
+                "out of date"
+            } else {
+                "regenerated"
+            },
+            self.unchanged,
+        );
+    }
+}
+
+/// Syncs every file under `src_dir` that `is_icon` accepts (given its path
+/// relative to `src_dir`) into the place under `dest_dir` that `render` maps
+/// that path to, since template packs can have variables in their paths.
+pub fn sync(
+    src_dir: &Path,
+    dest_dir: &Path,
+    is_icon: impl Fn(&Path) -> bool,
+    render: impl Fn(&Path) -> Result<PathBuf, Error>,
+    mode: Mode,
+) -> Result<Changes, Error> {
+    let mut changes = Changes::default();
+    for entry in walkdir::WalkDir::new(src_dir).sort_by_file_name() {
+        let entry = entry.map_err(|source| Error::WalkFailed {
+            path: src_dir.to_owned(),
+            source,
+        })?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry
+            .path()
+            .strip_prefix(src_dir)
+            .expect("developer error: walked path wasn't prefixed by its root");
+        if !is_icon(rel) {
+            continue;
+        }
+        let rel = render(rel)?;
+        let dest = dest_dir.join(&rel);
+        let change = if !dest.exists() {
+            Change::Added
+        } else {
+            let read = |path: &Path| {
+                fs::read(path).map_err(|source| Error::ReadFailed {
+                    path: path.to_owned(),
+                    source,
+                })
+            };
+            if read(entry.path())? == read(&dest)? {
+                changes.unchanged += 1;
+                continue;
+            }
+            Change::Updated
+        };
+        if mode != Mode::Check {
+            let map_err = |source| Error::WriteFailed {
+                path: dest.clone(),
+                source,
+            };
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).map_err(map_err)?;
+            }
+            fs::copy(entry.path(), &dest).map_err(map_err)?;
+        }
+        changes.changes.push((rel, change));
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    fn unrendered(rel: &Path) -> Result<PathBuf, Error> {
+        Ok(rel.to_owned())
+    }
+
+    #[test]
+    fn respects_mode() {
+        let root = TempDirGuard::new().unwrap();
+        let (src, dest) = (root.join("src"), root.join("dest"));
+        fs::create_dir_all(src.join("mipmap-hdpi")).unwrap();
+        fs::create_dir_all(dest.join("mipmap-hdpi")).unwrap();
+        fs::write(src.join("mipmap-hdpi/ic_launcher.png"), "new").unwrap();
+        fs::write(src.join("mipmap-hdpi/ic_launcher_round.png"), "round").unwrap();
+        fs::write(src.join("strings.xml"), "not an icon").unwrap();
+        fs::write(dest.join("mipmap-hdpi/ic_launcher.png"), "old").unwrap();
+        let is_icon = |rel: &Path| rel.starts_with("mipmap-hdpi");
+
+        let changes = sync(&src, &dest, is_icon, unrendered, Mode::Check).unwrap();
+        assert_eq!(
+            changes.changes,
+            vec![
+                (
+                    PathBuf::from("mipmap-hdpi/ic_launcher.png"),
+                    Change::Updated
+                ),
+                (
+                    PathBuf::from("mipmap-hdpi/ic_launcher_round.png"),
+                    Change::Added
+                ),
+            ]
+        );
+        assert!(!dest.join("mipmap-hdpi/ic_launcher_round.png").exists());
+        assert_eq!(
+            fs::read_to_string(dest.join("mipmap-hdpi/ic_launcher.png")).unwrap(),
+            "old"
+        );
+
+        let changes = sync(&src, &dest, is_icon, unrendered, Mode::Overwrite).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            fs::read_to_string(dest.join("mipmap-hdpi/ic_launcher.png")).unwrap(),
+            "new"
+        );
+        assert!(sync(&src, &dest, is_icon, unrendered, Mode::Check)
+            .unwrap()
+            .is_empty());
+        assert!(!dest.join("strings.xml").exists());
+    }
+
+    #[test]
+    fn writes_to_rendered_paths() {
+        let root = TempDirGuard::new().unwrap();
+        let (src, dest) = (root.join("src"), root.join("dest"));
+        let icon = "{{app.name}}_iOS/Assets.xcassets/AppIcon.appiconset/icon.png";
+        fs::create_dir_all(src.join(icon).parent().unwrap()).unwrap();
+        fs::write(src.join(icon), "icon").unwrap();
+        let render = |rel: &Path| {
+            Ok(PathBuf::from(
+                rel.to_str().unwrap().replace("{{app.name}}", "game"),
+            ))
+        };
+
+        let changes = sync(&src, &dest, |_| true, render, Mode::Overwrite).unwrap();
+        let rendered = PathBuf::from("game_iOS/Assets.xcassets/AppIcon.appiconset/icon.png");
+        assert_eq!(changes.changes, vec![(rendered.clone(), Change::Added)]);
+        assert!(dest.join(rendered).exists());
+        assert!(!dest.join("{{app.name}}_iOS").exists());
+    }
+}
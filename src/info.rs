@@ -0,0 +1,247 @@
+use crate::{
+    android,
+    config::{
+        metadata::{self, Metadata},
+        Config, LoadExistingError,
+    },
+    opts::Profile,
+    target::TargetTrait as _,
+    util::cli::{Report, Reportable},
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Bump this whenever a field is removed or changes meaning. Adding fields
+/// doesn't need a bump.
+pub const SCHEMA: u32 = 1;
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadExistingError),
+    MetadataFailed(metadata::Error),
+    SerializeFailed(serde_json::Error),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::MetadataFailed(err) => err.report(),
+            Self::SerializeFailed(err) => Report::error("Failed to serialize project info", err),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathInfo {
+    pub path: PathBuf,
+    pub exists: bool,
+}
+
+impl From<PathBuf> for PathInfo {
+    fn from(path: PathBuf) -> Self {
+        let exists = path.exists();
+        Self { path, exists }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppInfo {
+    pub name: String,
+    pub stylized_name: String,
+    pub domain: String,
+    pub root_dir: PathInfo,
+    pub asset_dir: PathInfo,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AndroidTargetInfo {
+    pub triple: String,
+    pub abi: String,
+    pub arch: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AndroidArtifacts {
+    pub apk: PathInfo,
+    pub aab: PathInfo,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AndroidInfo {
+    pub supported: bool,
+    pub application_id: String,
+    /// Only differs from `application-id` with `debug-application-id-suffix`.
+    pub debug_application_id: String,
+    pub min_sdk_version: u32,
+    pub project_dir: PathInfo,
+    pub targets: BTreeMap<String, AndroidTargetInfo>,
+    /// Release artifacts, keyed by target name.
+    pub release_artifacts: BTreeMap<String, AndroidArtifacts>,
+}
+
+impl AndroidInfo {
+    fn new(config: &android::config::Config, metadata: &android::config::Metadata) -> Self {
+        // Only the configured targets get built, or have flavors to build.
+        let targets = config
+            .target_names()
+            .iter()
+            .zip(config.targets())
+            .collect::<Vec<_>>();
+        Self {
+            supported: metadata.supported(),
+            application_id: config.app_id(Profile::Release),
+            debug_application_id: config.app_id(Profile::Debug),
+            min_sdk_version: config.min_sdk_version(),
+            project_dir: config.project_dir().into(),
+            targets: targets
+                .iter()
+                .map(|(name, target)| {
+                    (
+                        name.to_string(),
+                        AndroidTargetInfo {
+                            triple: target.triple.to_owned(),
+                            abi: target.abi.to_owned(),
+                            arch: target.arch.to_owned(),
+                        },
+                    )
+                })
+                .collect(),
+            release_artifacts: targets
+                .iter()
+                .map(|(name, target)| {
+                    (
+                        name.to_string(),
+                        AndroidArtifacts {
+                            apk: config.apk_path(Profile::Release, target.arch).into(),
+                            aab: config.aab_path(Profile::Release, target.arch).into(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppleTargetInfo {
+    pub triple: String,
+    pub arch: String,
+}
+
+#[cfg(target_os = "macos")]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppleInfo {
+    pub supported: bool,
+    pub bundle_identifier: String,
+    /// Only differs from `bundle-identifier` with `debug-bundle-id-suffix`.
+    pub debug_bundle_identifier: String,
+    pub scheme: String,
+    pub project_dir: PathInfo,
+    pub targets: BTreeMap<String, AppleTargetInfo>,
+    pub macos_target: AppleTargetInfo,
+    pub release_ipa: PathInfo,
+}
+
+#[cfg(target_os = "macos")]
+impl AppleInfo {
+    fn new(
+        config: &crate::apple::config::Config,
+        metadata: &crate::apple::config::Metadata,
+    ) -> Self {
+        use crate::apple::target::Target;
+        let target_info = |target: &Target<'_>| AppleTargetInfo {
+            triple: target.triple.to_owned(),
+            arch: target.arch.to_owned(),
+        };
+        Self {
+            supported: metadata.supported(),
+            bundle_identifier: config.bundle_identifier(Profile::Release),
+            debug_bundle_identifier: config.bundle_identifier(Profile::Debug),
+            scheme: config.scheme(),
+            project_dir: config.project_dir().into(),
+            targets: Target::all()
+                .iter()
+                .map(|(name, target)| (name.to_string(), target_info(target)))
+                .collect(),
+            macos_target: target_info(&Target::macos()),
+            // Only one of the candidates will ever exist, so we report
+            // whichever one does, or the current naming scheme otherwise.
+            release_ipa: config.ipa_path().unwrap_or_else(|(_old, new)| new).into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Info {
+    pub schema: u32,
+    pub config_path: PathInfo,
+    pub app: AppInfo,
+    pub android: AndroidInfo,
+    #[cfg(target_os = "macos")]
+    pub apple: AppleInfo,
+}
+
+impl Info {
+    pub fn new(config: &Config, metadata: &Metadata) -> Self {
+        let app = config.app();
+        Self {
+            schema: SCHEMA,
+            config_path: config.path().into(),
+            app: AppInfo {
+                name: app.name().to_owned(),
+                stylized_name: app.stylized_name().to_owned(),
+                domain: app.domain().to_owned(),
+                root_dir: app.root_dir().to_owned().into(),
+                asset_dir: app.asset_dir().into(),
+            },
+            android: AndroidInfo::new(config.android(), metadata.android()),
+            #[cfg(target_os = "macos")]
+            apple: AppleInfo::new(config.apple(), metadata.apple()),
+        }
+    }
+}
+
+/// Prints project info as JSON. This never generates or prompts for anything,
+/// so it's safe to call from build tooling.
+pub fn exec(cwd: impl AsRef<std::path::Path>) -> Result<(), Error> {
+    let config = Config::load(cwd).map_err(Error::ConfigFailed)?;
+    let metadata = Metadata::load(&config.app().root_dir()).map_err(Error::MetadataFailed)?;
+    let json = serde_json::to_string_pretty(&Info::new(&config, &metadata))
+        .map_err(Error::SerializeFailed)?;
+    println!("{}", json);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::fixture;
+
+    #[test]
+    fn only_lists_configured_android_targets() {
+        let fixture = fixture::app(
+            "info",
+            "[android]\ntargets = [\"aarch64\", \"x86_64\"]\n",
+            "",
+        );
+        let info = AndroidInfo::new(fixture.config.android(), fixture.metadata.android());
+        assert_eq!(
+            info.targets.keys().collect::<Vec<_>>(),
+            vec!["aarch64", "x86_64"]
+        );
+        assert_eq!(
+            info.release_artifacts.keys().collect::<Vec<_>>(),
+            vec!["aarch64", "x86_64"]
+        );
+        assert_eq!(info.targets["aarch64"].abi, "arm64-v8a");
+    }
+}
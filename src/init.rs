@@ -2,6 +2,7 @@ use crate::android;
 #[cfg(target_os = "macos")]
 use crate::apple;
 use crate::{
+    adopt,
     config::{
         self,
         metadata::{self, Metadata},
@@ -11,6 +12,7 @@ use crate::{
     util::{
         self,
         cli::{Report, Reportable, TextWrapper},
+        events,
     },
 };
 use std::{
@@ -18,6 +20,24 @@ use std::{
     path::{Path, PathBuf},
 };
 
+pub const LOAD_CONFIG_STEP: events::Step = events::Step::new("init.config.load", "Loading config");
+pub const GEN_BASE_STEP: events::Step =
+    events::Step::new("init.base.gen", "Generating base project");
+#[cfg(target_os = "macos")]
+pub const GEN_APPLE_STEP: events::Step =
+    events::Step::new("init.apple.gen", "Generating Xcode project");
+pub const GEN_ANDROID_STEP: events::Step =
+    events::Step::new("init.android.gen", "Generating Android Studio project");
+#[cfg(target_os = "macos")]
+pub const ADOPT_APPLE_STEP: events::Step =
+    events::Step::new("init.apple.adopt", "Setting up for your Xcode project");
+pub const ADOPT_ANDROID_STEP: events::Step = events::Step::new(
+    "init.android.adopt",
+    "Setting up for your Android Studio project",
+);
+pub const ADOPT_SNIPPETS_STEP: events::Step =
+    events::Step::new("init.adopt.snippets", "Writing integration snippets");
+
 pub static DOT_FIRST_INIT_FILE_NAME: &str = ".first-init";
 static DOT_FIRST_INIT_CONTENTS: &str = // newline
     r#"The presence of this file indicates `cargo mobile init` has been called for
@@ -44,20 +64,21 @@ pub enum Error {
     },
     FilterConfigureFailed(templating::FilterError),
     ProjectInitFailed(project::Error),
-    AssetDirCreationFailed {
-        asset_dir: PathBuf,
-        cause: io::Error,
-    },
     CodeCommandPresentFailed(bossy::Error),
     LldbExtensionInstallFailed(bossy::Error),
     DotCargoLoadFailed(dot_cargo::LoadError),
     HostTargetTripleDetectionFailed(util::HostTargetTripleError),
     MetadataFailed(metadata::Error),
+    AdoptFailed(adopt::Error),
     #[cfg(target_os = "macos")]
     AppleInitFailed(apple::project::Error),
     AndroidEnvFailed(android::env::Error),
     AndroidInitFailed(android::project::Error),
     DotCargoWriteFailed(dot_cargo::WriteError),
+    GitignoreUpdateFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
     DotFirstInitDeleteFailed {
         path: PathBuf,
         cause: io::Error,
@@ -72,17 +93,18 @@ impl Reportable for Error {
             Self::DotFirstInitWriteFailed { path, cause } => Report::error(format!("Failed to write first init dot file {:?}", path), cause),
             Self::FilterConfigureFailed(err) => Report::error("Failed to configure template filter", err),
             Self::ProjectInitFailed(err) => err.report(),
-            Self::AssetDirCreationFailed { asset_dir, cause } => Report::error(format!("Failed to create asset dir {:?}", asset_dir), cause),
             Self::CodeCommandPresentFailed(err) => Report::error("Failed to check for presence of `code` command", err),
             Self::LldbExtensionInstallFailed(err) => Report::error("Failed to install CodeLLDB extension", err),
             Self::DotCargoLoadFailed(err) => err.report(),
             Self::HostTargetTripleDetectionFailed(err) => err.report(),
             Self::MetadataFailed(err) => err.report(),
+            Self::AdoptFailed(err) => err.report(),
             Self::AndroidEnvFailed(err) => err.report(),
             Self::AndroidInitFailed(err) => err.report(),
             #[cfg(target_os = "macos")]
             Self::AppleInitFailed(err) => err.report(),
             Self::DotCargoWriteFailed(err) => err.report(),
+            Self::GitignoreUpdateFailed { path, cause } => Report::error(format!("Failed to add project tools dir to {:?}", path), cause),
             Self::DotFirstInitDeleteFailed { path, cause } => Report::action_request(format!("Failed to delete first init dot file {:?}; the project generated successfully, but `cargo mobile init` will have unexpected results unless you manually delete this file!", path), cause),
             Self::OpenInEditorFailed(err) => Report::error("Failed to open project in editor (your project generated successfully though, so no worries!)", err),
         }
@@ -95,12 +117,39 @@ pub fn exec(
     skip_dev_tools: opts::SkipDevTools,
     reinstall_deps: opts::ReinstallDeps,
     open_in_editor: opts::OpenInEditor,
+    conflicts: opts::Conflicts,
+    adopt: opts::Adopt,
+    guided: opts::Guided,
     submodule_commit: Option<String>,
     cwd: impl AsRef<Path>,
 ) -> Result<Config, Error> {
     let cwd = cwd.as_ref();
-    let (config, config_origin) =
-        Config::load_or_gen(cwd, non_interactive, wrapper).map_err(Error::ConfigLoadOrGenFailed)?;
+    if guided.yes() && non_interactive.yes() {
+        log::warn!(
+            "`--guided` does nothing with `--non-interactive`, since nothing gets prompted for"
+        );
+    }
+    // There's nobody to ask, so edits are kept unless we were told otherwise.
+    let conflicts = match conflicts {
+        opts::Conflicts::Prompt if non_interactive.yes() => opts::Conflicts::Keep,
+        opts::Conflicts::Merge { .. } if non_interactive.yes() => opts::Conflicts::Merge {
+            keep_unmergeable: true,
+        },
+        conflicts => conflicts,
+    };
+    let (config, config_origin) = events::step(&LOAD_CONFIG_STEP, || {
+        Config::load_or_gen_guided(cwd, non_interactive, guided, wrapper)
+    })
+    .map_err(Error::ConfigLoadOrGenFailed)?;
+    if guided.yes() && !config_origin.freshly_minted() {
+        println!(
+            "{}",
+            wrapper.fill(&format!(
+                "`--guided` only applies to new projects, so the existing {:?} was used as-is.",
+                config.path()
+            ))
+        );
+    }
     let dot_first_init_path = config.app().root_dir().join(DOT_FIRST_INIT_FILE_NAME);
     let dot_first_init_exists = {
         let dot_first_init_exists = dot_first_init_path.exists();
@@ -124,20 +173,18 @@ pub fn exec(
         .map_err(Error::FilterConfigureFailed)?;
 
     // Generate the base project
-    project::gen(
-        &config,
-        &bike,
-        &filter,
-        submodule_commit,
-        dot_first_init_exists,
-    )
+    events::step(&GEN_BASE_STEP, || {
+        project::gen(
+            &config,
+            &bike,
+            &filter,
+            conflicts,
+            submodule_commit,
+            dot_first_init_exists,
+        )
+    })
     .map_err(Error::ProjectInitFailed)?;
 
-    let asset_dir = config.app().asset_dir();
-    if !asset_dir.is_dir() {
-        fs::create_dir_all(&asset_dir)
-            .map_err(|cause| Error::AssetDirCreationFailed { asset_dir, cause })?;
-    }
     if skip_dev_tools.no()
         && util::command_present("code").map_err(Error::CodeCommandPresentFailed)?
     {
@@ -169,38 +216,58 @@ pub fn exec(
     dot_cargo.set_env(config.env().clone());
 
     let metadata = Metadata::load(&config.app().root_dir()).map_err(Error::MetadataFailed)?;
+    let adopted = adopt::Adopted::detect(&config, &metadata, adopt).map_err(Error::AdoptFailed)?;
 
     // Generate Xcode project
     #[cfg(target_os = "macos")]
-    if metadata.apple().supported() {
-        apple::project::gen(
-            config.apple(),
-            metadata.apple(),
-            config.app().template_pack().submodule_path(),
-            &bike,
-            wrapper,
-            non_interactive,
-            skip_dev_tools,
-            reinstall_deps,
-            &filter,
-        )
+    if adopted.apple.is_some() {
+        events::step(&ADOPT_APPLE_STEP, || {
+            apple::project::adopt(metadata.apple(), wrapper)
+        })
+        .map_err(Error::AppleInitFailed)?;
+    } else if metadata.apple().supported() && config.apple().is_configured() {
+        events::step(&GEN_APPLE_STEP, || {
+            apple::project::gen(
+                config.apple(),
+                metadata.apple(),
+                config.app().template_pack().submodule_path(),
+                &bike,
+                wrapper,
+                non_interactive,
+                skip_dev_tools,
+                reinstall_deps,
+                &filter,
+                conflicts,
+            )
+        })
         .map_err(Error::AppleInitFailed)?;
+    } else if !metadata.apple().supported() {
+        events::say(
+            "Skipping iOS init, since it's marked as unsupported in your Cargo.toml metadata",
+        );
     } else {
-        println!("Skipping iOS init, since it's marked as unsupported in your Cargo.toml metadata");
+        events::say("Skipping iOS init, since Apple setup was skipped");
     }
 
     // Generate Android Studio project
     if metadata.android().supported() {
         match android::env::Env::new() {
-            Ok(env) => android::project::gen(
-                config.android(),
-                metadata.android(),
-                &env,
-                &bike,
-                wrapper,
-                &filter,
-                &mut dot_cargo,
-            )
+            Ok(env) if adopted.android.is_some() => events::step(&ADOPT_ANDROID_STEP, || {
+                android::project::adopt(config.android(), &env, &mut dot_cargo)
+            })
+            .map_err(Error::AndroidInitFailed)?,
+            Ok(env) => events::step(&GEN_ANDROID_STEP, || {
+                android::project::gen(
+                    config.android(),
+                    metadata.android(),
+                    &env,
+                    &bike,
+                    wrapper,
+                    &filter,
+                    conflicts,
+                    &mut dot_cargo,
+                )
+            })
             .map_err(Error::AndroidInitFailed)?,
             Err(err) => {
                 if err.sdk_or_ndk_issue() {
@@ -215,14 +282,29 @@ pub fn exec(
             }
         }
     } else {
-        println!(
-            "Skipping Android init, since it's marked as unsupported in your Cargo.toml metadata"
+        events::say(
+            "Skipping Android init, since it's marked as unsupported in your Cargo.toml metadata",
         );
     }
 
     dot_cargo
         .write(config.app())
         .map_err(Error::DotCargoWriteFailed)?;
+    if util::tools::get().scope() == util::tools::Scope::Project {
+        let root_dir = config.app().root_dir();
+        let added = util::tools::ensure_ignored(root_dir).map_err(|cause| {
+            Error::GitignoreUpdateFailed {
+                path: root_dir.join(".gitignore"),
+                cause,
+            }
+        })?;
+        if added {
+            log::info!(
+                "added {:?} to `.gitignore`, since tools are project-scoped",
+                util::tools::PROJECT_TOOLS_DIR
+            );
+        }
+    }
     if dot_first_init_exists {
         log::info!("deleting first init dot file at {:?}", dot_first_init_path);
         fs::remove_file(&dot_first_init_path).map_err(|cause| Error::DotFirstInitDeleteFailed {
@@ -230,13 +312,55 @@ pub fn exec(
             cause,
         })?;
     }
-    Report::victory(
-        "Project generated successfully!",
-        "Make cool apps! 🌻 🐕 🎉",
-    )
-    .print(wrapper);
+    if adopted.is_empty() {
+        Report::victory(
+            "Project generated successfully!",
+            "Make cool apps! 🌻 🐕 🎉",
+        )
+        .print(wrapper);
+    } else {
+        let dir = events::step(&ADOPT_SNIPPETS_STEP, || adopt::write(&config, &adopted))
+            .map_err(Error::AdoptFailed)?;
+        Report::victory(
+            "Rust project set up successfully!",
+            format!(
+                "Your own projects were left alone; see {:?} for how to hook them up.",
+                dir.join("README.md")
+            ),
+        )
+        .print(wrapper);
+    }
     if open_in_editor.yes() {
         util::open_in_editor(cwd).map_err(Error::OpenInEditorFailed)?;
     }
     Ok(config)
 }
+
+/// Regenerates the project like `exec`, except that template changes are
+/// merged into files the user edited, followed by a summary of what happened
+/// to each file.
+pub fn upgrade_project(
+    wrapper: &TextWrapper,
+    non_interactive: opts::NonInteractive,
+    skip_dev_tools: opts::SkipDevTools,
+    reinstall_deps: opts::ReinstallDeps,
+    cwd: impl AsRef<Path>,
+) -> Result<Config, Error> {
+    // Anything left over from an earlier `process` isn't part of this.
+    templating::take_summary();
+    let config = exec(
+        wrapper,
+        non_interactive,
+        skip_dev_tools,
+        reinstall_deps,
+        opts::OpenInEditor::No,
+        opts::Conflicts::Merge {
+            keep_unmergeable: false,
+        },
+        opts::Adopt::No,
+        None,
+        cwd,
+    )?;
+    templating::take_summary().print();
+    Ok(config)
+}
@@ -1,20 +1,31 @@
 #![deny(unsafe_code)]
 
+mod adopt;
 pub mod android;
 #[cfg(target_os = "macos")]
 pub mod apple;
+mod assets;
+pub mod ci;
 pub mod config;
 pub mod device;
 pub mod doctor;
 mod dot_cargo;
 pub mod env;
+pub mod hooks;
+mod icons;
+pub mod info;
 pub mod init;
+pub mod licenses;
 pub mod opts;
 pub mod os;
 mod project;
 pub mod target;
 mod templating;
+pub mod uninstall;
 pub mod update;
 pub mod util;
+pub mod verify_templates;
+
+pub use self::templating::{install_templates, InstallTemplatesError};
 
 pub static NAME: &str = "mobile";
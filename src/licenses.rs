@@ -0,0 +1,932 @@
+//! Collects the licenses of everything that ends up in the app, for the
+//! attribution screens app stores want. Rust crates come from
+//! `cargo metadata`, and Android and iOS dependencies from gradle and
+//! `Podfile.lock`. Anything whose license we can't vouch for is kept and
+//! flagged, since a report that quietly leaves things out is worse than none.
+
+use crate::{
+    android, assets,
+    config::{
+        app::{AssetDir, AssetPlatform},
+        Config, LoadExistingError,
+    },
+    util::{
+        cargo::CargoCommand,
+        cli::{Report, Reportable},
+        timings,
+    },
+};
+use once_cell_regex::regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The version of `licenses.json`'s layout, which apps that read it at
+/// runtime can check before trusting the rest.
+pub const SCHEMA: u32 = 1;
+
+static JSON_FILE_NAME: &str = "licenses.json";
+static HTML_FILE_NAME: &str = "licenses.html";
+static TEXT_FILE_NAME: &str = "licenses.txt";
+// Only what ships counts, and simulator builds don't. Off macOS, there's no
+// Apple config to ask about anything else.
+static APPLE_TRIPLES: &[&str] = &["aarch64-apple-ios"];
+#[cfg(not(target_os = "macos"))]
+static DEFAULT_APPLE_PROJECT_DIR: &str = "gen/apple";
+
+#[derive(Debug)]
+pub enum Error {
+    ConfigFailed(LoadExistingError),
+    CargoMetadataFailed(bossy::Error),
+    CargoMetadataInvalid(serde_json::Error),
+    AndroidEnvFailed(android::env::Error),
+    GradleDependenciesFailed(bossy::Error),
+    PodfileLockReadFailed { path: PathBuf, source: io::Error },
+    SerializeFailed(serde_json::Error),
+    DirCreationFailed { path: PathBuf, source: io::Error },
+    WriteFailed { path: PathBuf, source: io::Error },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ConfigFailed(err) => err.report(),
+            Self::CargoMetadataFailed(err) => Report::error("Failed to run `cargo metadata`", err),
+            Self::CargoMetadataInvalid(err) => {
+                Report::error("Failed to parse `cargo metadata` output", err)
+            }
+            Self::AndroidEnvFailed(err) => err.report(),
+            Self::GradleDependenciesFailed(err) => {
+                Report::error("Failed to list Android dependencies with gradle", err)
+            }
+            Self::PodfileLockReadFailed { path, source } => {
+                Report::error(format!("Failed to read {:?}", path), source)
+            }
+            Self::SerializeFailed(err) => Report::error("Failed to serialize license report", err),
+            Self::DirCreationFailed { path, source } => {
+                Report::error(format!("Failed to create directory {:?}", path), source)
+            }
+            Self::WriteFailed { path, source } => Report::error(
+                format!("Failed to write license report to {:?}", path),
+                source,
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Ecosystem {
+    Cargo,
+    Maven,
+    Cocoapods,
+}
+
+impl Ecosystem {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Cargo => "Rust crate",
+            Self::Maven => "Android library",
+            Self::Cocoapods => "CocoaPod",
+        }
+    }
+}
+
+/// A dependency as one of the tools reported it, before any merging.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Dependency {
+    pub ecosystem: Ecosystem,
+    pub name: String,
+    pub version: String,
+    /// Whatever the tool said, verbatim. Gradle and CocoaPods don't say.
+    pub license: Option<String>,
+    pub authors: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LicenseStatus {
+    /// A valid SPDX expression made of identifiers we know.
+    Spdx,
+    /// There's a license string, but it isn't one we can vouch for.
+    NonSpdx,
+    /// There's no license string at all.
+    Unknown,
+}
+
+// Not the whole SPDX list, just what turns up in practice. Anything else is
+// flagged for someone to look at, which is the point anyway.
+static KNOWN_LICENSES: &[&str] = &[
+    "0BSD",
+    "AFL-3.0",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-1.1",
+    "Apache-2.0",
+    "Artistic-2.0",
+    "BlueOak-1.0.0",
+    "BSD-1-Clause",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "BSD-3-Clause-Clear",
+    "BSL-1.0",
+    "bzip2-1.0.6",
+    "CC-BY-3.0",
+    "CC-BY-4.0",
+    "CC0-1.0",
+    "CDDL-1.0",
+    "CDDL-1.1",
+    "curl",
+    "EPL-1.0",
+    "EPL-2.0",
+    "GPL-2.0",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "ICU",
+    "ISC",
+    "JSON",
+    "LGPL-2.0-only",
+    "LGPL-2.0-or-later",
+    "LGPL-2.1",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "libpng",
+    "MIT",
+    "MIT-0",
+    "MPL-1.1",
+    "MPL-2.0",
+    "MS-PL",
+    "NCSA",
+    "OFL-1.1",
+    "OpenSSL",
+    "PostgreSQL",
+    "Python-2.0",
+    "Unicode-3.0",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "UPL-1.0",
+    "WTFPL",
+    "X11",
+    "Zlib",
+];
+
+static KNOWN_EXCEPTIONS: &[&str] = &[
+    "Autoconf-exception-3.0",
+    "Bison-exception-2.2",
+    "Classpath-exception-2.0",
+    "GCC-exception-3.1",
+    "LLVM-exception",
+    "OpenJDK-assembly-exception-1.0",
+    "Swift-exception",
+];
+
+// SPDX identifiers are case-insensitive, so this also gives the canonical
+// spelling.
+fn known(list: &[&'static str], id: &str) -> Option<&'static str> {
+    list.iter()
+        .copied()
+        .find(|known| known.eq_ignore_ascii_case(id))
+}
+
+fn normalize_id(id: &str) -> Option<String> {
+    let prefix = "LicenseRef-";
+    if let Some(custom) = id
+        .get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .and_then(|_| id.get(prefix.len()..))
+        .filter(|custom| !custom.is_empty())
+    {
+        return Some(format!("{}{}", prefix, custom));
+    }
+    let (base, plus) = match id.strip_suffix('+') {
+        Some(base) => (base, "+"),
+        None => (id, ""),
+    };
+    known(KNOWN_LICENSES, base).map(|base| format!("{}{}", base, plus))
+}
+
+/// Checks `raw` against the SPDX expression grammar, giving it back in
+/// canonical form if it passes. Cargo's deprecated `MIT/Apache-2.0` style is
+/// read as `OR`, since that's what it always meant.
+pub fn normalize_license(raw: &str) -> (Option<String>, LicenseStatus) {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return (None, LicenseStatus::Unknown);
+    }
+    let spaced = raw
+        .replace('/', " OR ")
+        .replace('(', " ( ")
+        .replace(')', " ) ");
+    let mut normalized = Vec::new();
+    let mut depth = 0usize;
+    // Whether the next token has to be a license (or an opening paren), as
+    // opposed to an operator (or a closing paren).
+    let mut expect_operand = true;
+    let mut after_with = false;
+    let mut valid = true;
+    for token in spaced.split_whitespace() {
+        let upper = token.to_ascii_uppercase();
+        let accepted = match upper.as_str() {
+            "(" if expect_operand && !after_with => {
+                depth += 1;
+                Some("(".to_owned())
+            }
+            ")" if !expect_operand && depth > 0 => {
+                depth -= 1;
+                Some(")".to_owned())
+            }
+            "AND" | "OR" | "WITH" if !expect_operand => {
+                expect_operand = true;
+                after_with = upper == "WITH";
+                Some(upper)
+            }
+            _ if expect_operand => {
+                let id = if after_with {
+                    known(KNOWN_EXCEPTIONS, token).map(ToOwned::to_owned)
+                } else {
+                    normalize_id(token)
+                };
+                expect_operand = false;
+                after_with = false;
+                id
+            }
+            _ => None,
+        };
+        match accepted {
+            Some(token) => normalized.push(token),
+            None => {
+                valid = false;
+                break;
+            }
+        }
+    }
+    if valid && !expect_operand && depth == 0 {
+        let expr = normalized.join(" ").replace("( ", "(").replace(" )", ")");
+        (Some(expr), LicenseStatus::Spdx)
+    } else {
+        (Some(raw.to_owned()), LicenseStatus::NonSpdx)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Entry {
+    pub ecosystem: Ecosystem,
+    pub name: String,
+    pub version: String,
+    /// Canonical if `license-status` is `spdx`, and verbatim otherwise.
+    pub license: Option<String>,
+    pub license_status: LicenseStatus,
+    pub authors: Vec<String>,
+}
+
+impl Entry {
+    pub fn flagged(&self) -> bool {
+        self.license_status != LicenseStatus::Spdx
+    }
+}
+
+/// Dedupes `deps` by ecosystem, name, and version, keeping the first license
+/// any of them gave and everyone's authors, and sorts them for stable output.
+pub fn merge(deps: impl IntoIterator<Item = Dependency>) -> Vec<Entry> {
+    let mut merged = BTreeMap::<(Ecosystem, String, String), (Option<String>, Vec<String>)>::new();
+    for dep in deps {
+        let (license, authors) = merged
+            .entry((dep.ecosystem, dep.name, dep.version))
+            .or_default();
+        if license.is_none() {
+            *license = dep.license.filter(|license| !license.trim().is_empty());
+        }
+        for author in dep.authors {
+            if !authors.contains(&author) {
+                authors.push(author);
+            }
+        }
+    }
+    merged
+        .into_iter()
+        .map(|((ecosystem, name, version), (license, authors))| {
+            let (license, license_status) = license
+                .as_deref()
+                .map(normalize_license)
+                .unwrap_or((None, LicenseStatus::Unknown));
+            Entry {
+                ecosystem,
+                name,
+                version,
+                license,
+                license_status,
+                authors,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct CargoMetadata {
+    packages: Vec<CargoPackage>,
+    workspace_members: Vec<String>,
+    resolve: CargoResolve,
+}
+
+#[derive(Debug, Deserialize)]
+struct CargoPackage {
+    id: String,
+    name: String,
+    version: String,
+    license: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CargoResolve {
+    nodes: Vec<CargoNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CargoNode {
+    id: String,
+    #[serde(default)]
+    deps: Vec<CargoNodeDep>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CargoNodeDep {
+    pkg: String,
+    #[serde(default)]
+    dep_kinds: Vec<CargoDepKind>,
+}
+
+// There's a `target` too, but we don't have to evaluate it: with
+// `--filter-platform`, cargo already dropped the kinds whose `target` doesn't
+// match any of the triples we gave.
+#[derive(Debug, Deserialize)]
+struct CargoDepKind {
+    /// `None` for normal deps, and `"dev"` or `"build"` otherwise.
+    kind: Option<String>,
+}
+
+impl CargoNodeDep {
+    fn bundled(&self) -> bool {
+        self.dep_kinds
+            .iter()
+            .any(|dep_kind| dep_kind.kind.is_none())
+    }
+}
+
+/// Every package the app's own crates depend on in `cargo metadata
+/// --format-version 1` output, directly or not. Dev and build deps aren't
+/// bundled, so they're left out, along with anything only they depend on.
+pub fn parse_cargo_metadata(json: &str) -> Result<Vec<Dependency>, serde_json::Error> {
+    let metadata = serde_json::from_str::<CargoMetadata>(json)?;
+    let members = metadata.workspace_members.iter().collect::<HashSet<_>>();
+    let nodes = metadata
+        .resolve
+        .nodes
+        .iter()
+        .map(|node| (&node.id, &node.deps))
+        .collect::<HashMap<_, _>>();
+    let mut bundled = HashSet::new();
+    let mut pending = metadata.workspace_members.iter().collect::<Vec<_>>();
+    while let Some(id) = pending.pop() {
+        for dep in nodes.get(id).into_iter().flat_map(|deps| deps.iter()) {
+            if dep.bundled() && bundled.insert(&dep.pkg) {
+                pending.push(&dep.pkg);
+            }
+        }
+    }
+    Ok(metadata
+        .packages
+        .into_iter()
+        .filter(|package| bundled.contains(&package.id) && !members.contains(&package.id))
+        .map(|package| Dependency {
+            ecosystem: Ecosystem::Cargo,
+            name: package.name,
+            version: package.version,
+            license: package.license,
+            authors: package.authors,
+        })
+        .collect())
+}
+
+/// Every library in the tree `gradle dependencies` prints. Where gradle
+/// resolved a conflict, the version it picked is the one that's bundled.
+pub fn parse_gradle_dependencies(output: &str) -> Vec<Dependency> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let caps = regex!(
+                r"^[| ]*[+\\]--- (?P<group>[^:\s]+):(?P<artifact>[^:\s]+)(?::(?P<version>[^\s]+))?(?: -> (?P<resolved>[^\s]+))?(?P<marker> \([*cn]\))?$"
+            )
+            .captures(line.trim_end())?;
+            // Constraints and unresolved declarations aren't bundled.
+            if matches!(
+                caps.name("marker").map(|marker| marker.as_str()),
+                Some(" (c)") | Some(" (n)")
+            ) {
+                return None;
+            }
+            let version = caps.name("resolved").or_else(|| caps.name("version"))?;
+            Some(Dependency {
+                ecosystem: Ecosystem::Maven,
+                name: format!("{}:{}", &caps["group"], &caps["artifact"]),
+                version: version.as_str().to_owned(),
+                license: None,
+                authors: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Every pod in the `PODS` section of a `Podfile.lock`. Subspecs are reported
+/// as the pod they belong to.
+pub fn parse_podfile_lock(lock: &str) -> Vec<Dependency> {
+    lock.lines()
+        .skip_while(|line| line.trim_end() != "PODS:")
+        .skip(1)
+        .take_while(|line| line.is_empty() || line.starts_with(' '))
+        .filter_map(|line| {
+            let caps = regex!(r#"^  - "?(?P<name>[^\s("]+) \((?P<version>[^)]+)\)"?:?$"#)
+                .captures(line.trim_end())?;
+            let name = caps["name"].split('/').next().unwrap_or(&caps["name"]);
+            Some(Dependency {
+                ecosystem: Ecosystem::Cocoapods,
+                name: name.to_owned(),
+                version: caps["version"].to_owned(),
+                license: None,
+                authors: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+fn license_label(entry: &Entry) -> String {
+    match (&entry.license, entry.license_status) {
+        (Some(license), LicenseStatus::Spdx) => license.clone(),
+        (Some(license), _) => format!("{} (not a recognized SPDX expression)", license),
+        (None, _) => "unknown".to_owned(),
+    }
+}
+
+pub fn render_text(app_name: &str, entries: &[Entry]) -> String {
+    let mut text = format!("{} uses the following open source software.\n", app_name);
+    for entry in entries {
+        writeln!(
+            text,
+            "\n{} {} ({})\nLicense: {}",
+            entry.name,
+            entry.version,
+            entry.ecosystem.as_str(),
+            license_label(entry)
+        )
+        .unwrap();
+        if !entry.authors.is_empty() {
+            writeln!(text, "Authors: {}", entry.authors.join(", ")).unwrap();
+        }
+    }
+    text
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn render_html(app_name: &str, entries: &[Entry]) -> String {
+    let title = format!("{} open source licenses", escape_html(app_name));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n<ul>\n",
+        title, title
+    );
+    for entry in entries {
+        write!(
+            html,
+            "<li>\n<strong>{}</strong> {} ({})<br>\nLicense: {}",
+            escape_html(&entry.name),
+            escape_html(&entry.version),
+            entry.ecosystem.as_str(),
+            escape_html(&license_label(entry))
+        )
+        .unwrap();
+        if !entry.authors.is_empty() {
+            write!(
+                html,
+                "<br>\nAuthors: {}",
+                escape_html(&entry.authors.join(", "))
+            )
+            .unwrap();
+        }
+        html.push_str("\n</li>\n");
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    html
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LicenseReport {
+    pub schema: u32,
+    pub app: String,
+    pub entries: Vec<Entry>,
+}
+
+fn cargo_deps(config: &Config) -> Result<Vec<Dependency>, Error> {
+    let triples = config
+        .android()
+        .targets()
+        .into_iter()
+        .map(|target| target.triple)
+        .chain(APPLE_TRIPLES.iter().copied());
+    let mut command = CargoCommand::new("metadata")
+        .with_manifest_path(Some(config.app().manifest_path()))
+        .into_command_impure()
+        .with_args(&["--format-version", "1"])
+        .with_env_var("CARGO_TARGET_DIR", config.android().cargo_target_dir());
+    for triple in triples {
+        command.add_args(&["--filter-platform", triple]);
+    }
+    let json = timings::step("cargo metadata", || {
+        command.run_and_wait_for_str(|s| s.to_owned())
+    })
+    .map_err(Error::CargoMetadataFailed)?;
+    parse_cargo_metadata(&json).map_err(Error::CargoMetadataInvalid)
+}
+
+fn gradle_deps(config: &android::config::Config) -> Result<Vec<Dependency>, Error> {
+    if !config.project_dir_exists() {
+        log::info!("no Android project at {:?}", config.project_dir());
+        return Ok(Vec::new());
+    }
+    let env = android::env::Env::new().map_err(Error::AndroidEnvFailed)?;
+    let invocation = android::gradle::GradleInvocation::new()
+        .with_task(":app:dependencies")
+        .with_task_option("configuration", "releaseRuntimeClasspath");
+    log::info!("running {}", invocation);
+    let output = timings::step(invocation.to_string(), || {
+        invocation
+            .command(config, &env)
+            .run_and_wait_for_str(|s| s.to_owned())
+    })
+    .map_err(Error::GradleDependenciesFailed)?;
+    Ok(parse_gradle_dependencies(&output))
+}
+
+#[cfg(target_os = "macos")]
+fn apple_project_dir(config: &Config) -> PathBuf {
+    config.apple().project_dir()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn apple_project_dir(config: &Config) -> PathBuf {
+    config.app().prefix_path(DEFAULT_APPLE_PROJECT_DIR)
+}
+
+// `Podfile.lock` is checked in, so there's no reason to need a Mac for this.
+fn pod_deps(project_dir: &Path) -> Result<Vec<Dependency>, Error> {
+    let path = project_dir.join("Podfile.lock");
+    if !path.is_file() {
+        log::info!("no `Podfile.lock` at {:?}", path);
+        return Ok(Vec::new());
+    }
+    fs::read_to_string(&path)
+        .map(|lock| parse_podfile_lock(&lock))
+        .map_err(|source| Error::PodfileLockReadFailed { path, source })
+}
+
+/// One dir from each group of asset dirs, so every app gets the report
+/// without it turning up twice in anything that gets merged.
+fn report_dirs(root_dir: &Path, asset_dirs: &[AssetDir]) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for platform in AssetPlatform::ALL {
+        for group in assets::groups(asset_dirs, *platform) {
+            let dir = root_dir.join(group.sources[0].path());
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs
+}
+
+fn write(path: PathBuf, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+    fs::write(&path, contents).map_err(|source| Error::WriteFailed { path, source })
+}
+
+/// Writes the report into the asset dirs, so every app bundles it.
+pub fn exec(cwd: impl AsRef<Path>) -> Result<(), Error> {
+    let config = Config::load(cwd).map_err(Error::ConfigFailed)?;
+    let mut deps = cargo_deps(&config)?;
+    deps.extend(gradle_deps(config.android())?);
+    deps.extend(pod_deps(&apple_project_dir(&config))?);
+    let report = LicenseReport {
+        schema: SCHEMA,
+        app: config.app().stylized_name().to_owned(),
+        entries: merge(deps),
+    };
+
+    let json = serde_json::to_string_pretty(&report).map_err(Error::SerializeFailed)?;
+    let html = render_html(&report.app, &report.entries);
+    let text = render_text(&report.app, &report.entries);
+    let dirs = report_dirs(config.app().root_dir(), config.app().asset_dirs());
+    for dir in &dirs {
+        fs::create_dir_all(dir).map_err(|source| Error::DirCreationFailed {
+            path: dir.clone(),
+            source,
+        })?;
+        write(dir.join(JSON_FILE_NAME), &json)?;
+        write(dir.join(HTML_FILE_NAME), &html)?;
+        write(dir.join(TEXT_FILE_NAME), &text)?;
+    }
+
+    let flagged = report
+        .entries
+        .iter()
+        .filter(|entry| entry.flagged())
+        .collect::<Vec<_>>();
+    for entry in &flagged {
+        log::warn!(
+            "license of {} {} {} needs checking by hand: {}",
+            entry.ecosystem.as_str(),
+            entry.name,
+            entry.version,
+            license_label(entry)
+        );
+    }
+    println!(
+        "Wrote licenses for {} dependencies to {:?} ({} need checking by hand)",
+        report.entries.len(),
+        dirs,
+        flagged.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn dep(ecosystem: Ecosystem, name: &str, version: &str, license: Option<&str>) -> Dependency {
+        Dependency {
+            ecosystem,
+            name: name.to_owned(),
+            version: version.to_owned(),
+            license: license.map(ToOwned::to_owned),
+            authors: Vec::new(),
+        }
+    }
+
+    #[rstest(
+        raw,
+        expected,
+        status,
+        case("MIT", Some("MIT"), LicenseStatus::Spdx),
+        case("MIT OR Apache-2.0", Some("MIT OR Apache-2.0"), LicenseStatus::Spdx),
+        case("MIT/Apache-2.0", Some("MIT OR Apache-2.0"), LicenseStatus::Spdx),
+        case("mit or apache-2.0", Some("MIT OR Apache-2.0"), LicenseStatus::Spdx),
+        case(
+            "(MIT OR Apache-2.0) AND Unicode-DFS-2016",
+            Some("(MIT OR Apache-2.0) AND Unicode-DFS-2016"),
+            LicenseStatus::Spdx
+        ),
+        case(
+            "Apache-2.0 WITH LLVM-exception",
+            Some("Apache-2.0 WITH LLVM-exception"),
+            LicenseStatus::Spdx
+        ),
+        case("GPL-2.0+", Some("GPL-2.0+"), LicenseStatus::Spdx),
+        case(
+            "LicenseRef-Proprietary",
+            Some("LicenseRef-Proprietary"),
+            LicenseStatus::Spdx
+        ),
+        case(
+            "Apache License 2.0",
+            Some("Apache License 2.0"),
+            LicenseStatus::NonSpdx
+        ),
+        case("MIT OR", Some("MIT OR"), LicenseStatus::NonSpdx),
+        case("(MIT", Some("(MIT"), LicenseStatus::NonSpdx),
+        case(
+            "MIT WITH Apache-2.0",
+            Some("MIT WITH Apache-2.0"),
+            LicenseStatus::NonSpdx
+        ),
+        case("  ", None, LicenseStatus::Unknown)
+    )]
+    fn normalizes_licenses(raw: &str, expected: Option<&str>, status: LicenseStatus) {
+        let (license, actual_status) = normalize_license(raw);
+        assert_eq!(license.as_deref(), expected);
+        assert_eq!(actual_status, status);
+    }
+
+    #[test]
+    fn merges_duplicates() {
+        let mut with_authors = dep(Ecosystem::Cargo, "log", "0.4.17", Some("MIT/Apache-2.0"));
+        with_authors.authors = vec!["The Rust Project Developers".to_owned()];
+        let entries = merge(vec![
+            dep(Ecosystem::Maven, "androidx.core:core", "1.9.0", None),
+            dep(Ecosystem::Cargo, "log", "0.4.17", None),
+            with_authors.clone(),
+            with_authors,
+            dep(Ecosystem::Cargo, "log", "0.4.14", Some("Apache License")),
+        ]);
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    ecosystem: Ecosystem::Cargo,
+                    name: "log".to_owned(),
+                    version: "0.4.14".to_owned(),
+                    license: Some("Apache License".to_owned()),
+                    license_status: LicenseStatus::NonSpdx,
+                    authors: Vec::new(),
+                },
+                Entry {
+                    ecosystem: Ecosystem::Cargo,
+                    name: "log".to_owned(),
+                    version: "0.4.17".to_owned(),
+                    license: Some("MIT OR Apache-2.0".to_owned()),
+                    license_status: LicenseStatus::Spdx,
+                    authors: vec!["The Rust Project Developers".to_owned()],
+                },
+                Entry {
+                    ecosystem: Ecosystem::Maven,
+                    name: "androidx.core:core".to_owned(),
+                    version: "1.9.0".to_owned(),
+                    license: None,
+                    license_status: LicenseStatus::Unknown,
+                    authors: Vec::new(),
+                },
+            ]
+        );
+        assert!(!entries[1].flagged());
+        assert!(entries[0].flagged() && entries[2].flagged());
+    }
+
+    #[test]
+    fn parses_cargo_metadata() {
+        let json = r#"{
+            "packages": [
+                {"id": "game 0.1.0 (path+file:///game)", "name": "game", "version": "0.1.0", "license": null, "authors": []},
+                {"id": "log 0.4.17 (registry+https://github.com/rust-lang/crates.io-index)", "name": "log", "version": "0.4.17", "license": "MIT OR Apache-2.0", "authors": ["The Rust Project Developers"]},
+                {"id": "ring 0.16.20 (registry+https://github.com/rust-lang/crates.io-index)", "name": "ring", "version": "0.16.20", "license": null, "license_file": "LICENSE", "authors": []},
+                {"id": "cc 1.0.73 (registry+https://github.com/rust-lang/crates.io-index)", "name": "cc", "version": "1.0.73", "license": "MIT OR Apache-2.0", "authors": []},
+                {"id": "jobserver 0.1.24 (registry+https://github.com/rust-lang/crates.io-index)", "name": "jobserver", "version": "0.1.24", "license": "MIT OR Apache-2.0", "authors": []},
+                {"id": "rstest 0.12.0 (registry+https://github.com/rust-lang/crates.io-index)", "name": "rstest", "version": "0.12.0", "license": "MIT OR Apache-2.0", "authors": []}
+            ],
+            "workspace_members": ["game 0.1.0 (path+file:///game)"],
+            "resolve": {
+                "nodes": [
+                    {"id": "game 0.1.0 (path+file:///game)", "deps": [
+                        {"name": "log", "pkg": "log 0.4.17 (registry+https://github.com/rust-lang/crates.io-index)", "dep_kinds": [{"kind": null, "target": null}]},
+                        {"name": "ring", "pkg": "ring 0.16.20 (registry+https://github.com/rust-lang/crates.io-index)", "dep_kinds": [{"kind": null, "target": "cfg(target_os = \"android\")"}]},
+                        {"name": "cc", "pkg": "cc 1.0.73 (registry+https://github.com/rust-lang/crates.io-index)", "dep_kinds": [{"kind": "build", "target": null}]},
+                        {"name": "rstest", "pkg": "rstest 0.12.0 (registry+https://github.com/rust-lang/crates.io-index)", "dep_kinds": [{"kind": "dev", "target": null}]}
+                    ]},
+                    {"id": "log 0.4.17 (registry+https://github.com/rust-lang/crates.io-index)", "deps": []},
+                    {"id": "ring 0.16.20 (registry+https://github.com/rust-lang/crates.io-index)", "deps": [
+                        {"name": "cc", "pkg": "cc 1.0.73 (registry+https://github.com/rust-lang/crates.io-index)", "dep_kinds": [{"kind": "build", "target": null}]}
+                    ]},
+                    {"id": "cc 1.0.73 (registry+https://github.com/rust-lang/crates.io-index)", "deps": [
+                        {"name": "jobserver", "pkg": "jobserver 0.1.24 (registry+https://github.com/rust-lang/crates.io-index)", "dep_kinds": [{"kind": null, "target": null}]}
+                    ]},
+                    {"id": "jobserver 0.1.24 (registry+https://github.com/rust-lang/crates.io-index)", "deps": []},
+                    {"id": "rstest 0.12.0 (registry+https://github.com/rust-lang/crates.io-index)", "deps": []}
+                ],
+                "root": "game 0.1.0 (path+file:///game)"
+            },
+            "version": 1
+        }"#;
+        let deps = parse_cargo_metadata(json).unwrap();
+        assert_eq!(
+            deps.iter()
+                .map(|dep| (dep.name.as_str(), dep.license.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![("log", Some("MIT OR Apache-2.0")), ("ring", None)]
+        );
+        assert_eq!(deps[0].authors, vec!["The Rust Project Developers"]);
+    }
+
+    #[test]
+    fn reports_once_per_asset_group() {
+        let asset_dirs = vec![
+            AssetDir::new("assets", AssetPlatform::ALL, "assets"),
+            AssetDir::new("levels", AssetPlatform::ALL, "assets"),
+            AssetDir::new("ios-assets", &[AssetPlatform::Ios], "assets"),
+            AssetDir::new("shaders", &[AssetPlatform::Android], "shaders"),
+        ];
+        assert_eq!(
+            report_dirs(Path::new("/game"), &asset_dirs),
+            vec![
+                PathBuf::from("/game/assets"),
+                PathBuf::from("/game/shaders"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_gradle_dependencies() {
+        let output = r#"
+> Task :app:dependencies
+
+------------------------------------------------------------
+Project ':app'
+------------------------------------------------------------
+
+releaseRuntimeClasspath - Resolved configuration for runtime for variant: release
++--- androidx.appcompat:appcompat:1.4.1
+|    +--- androidx.annotation:annotation:1.3.0 -> 1.5.0
+|    \--- androidx.core:core:1.7.0 -> 1.9.0 (*)
++--- androidx.core:core:1.9.0
+|    \--- androidx.lifecycle:lifecycle-runtime:2.3.1 (c)
++--- project :lib
+\--- com.google.android.material:material -> 1.6.1
+
+(c) - dependency constraint
+(*) - dependencies omitted (listed previously)
+"#;
+        let deps = parse_gradle_dependencies(output)
+            .into_iter()
+            .map(|dep| (dep.name, dep.version))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            ("androidx.appcompat:appcompat", "1.4.1"),
+            ("androidx.annotation:annotation", "1.5.0"),
+            ("androidx.core:core", "1.9.0"),
+            ("androidx.core:core", "1.9.0"),
+            ("com.google.android.material:material", "1.6.1"),
+        ]
+        .into_iter()
+        .map(|(name, version)| (name.to_owned(), version.to_owned()))
+        .collect::<Vec<_>>();
+        assert_eq!(deps, expected);
+    }
+
+    #[test]
+    fn parses_podfile_lock() {
+        let lock = r#"PODS:
+  - Alamofire (5.6.2)
+  - "GoogleUtilities/Environment (7.7.0)":
+    - PromisesObjC (< 3.0, >= 1.2)
+  - GoogleUtilities/Logger (7.7.0):
+    - GoogleUtilities/Environment
+  - PromisesObjC (2.1.0)
+
+DEPENDENCIES:
+  - Alamofire (~> 5.6)
+
+COCOAPODS: 1.11.3
+"#;
+        let deps = parse_podfile_lock(lock)
+            .into_iter()
+            .map(|dep| (dep.name, dep.version))
+            .collect::<Vec<_>>();
+        let expected = vec![
+            ("Alamofire", "5.6.2"),
+            ("GoogleUtilities", "7.7.0"),
+            ("GoogleUtilities", "7.7.0"),
+            ("PromisesObjC", "2.1.0"),
+        ]
+        .into_iter()
+        .map(|(name, version)| (name.to_owned(), version.to_owned()))
+        .collect::<Vec<_>>();
+        assert_eq!(deps, expected);
+    }
+
+    #[test]
+    fn renders_flagged_entries() {
+        let entries = merge(vec![
+            dep(Ecosystem::Cargo, "log", "0.4.17", Some("MIT")),
+            dep(Ecosystem::Cocoapods, "<Weird>", "1.0", Some("Custom & Co")),
+        ]);
+        let text = render_text("Game", &entries);
+        assert!(text.contains("log 0.4.17 (Rust crate)\nLicense: MIT\n"));
+        assert!(text.contains("License: Custom & Co (not a recognized SPDX expression)"));
+        let html = render_html("Game", &entries);
+        assert!(html.contains("<strong>&lt;Weird&gt;</strong>"));
+        assert!(html.contains("Custom &amp; Co"));
+        assert!(!html.contains("<Weird>"));
+    }
+}
@@ -47,6 +47,46 @@ yes_or_no!(pub ReinstallDeps);
 
 yes_or_no!(pub OpenInEditor);
 
+yes_or_no!(pub Force);
+
+yes_or_no!(pub Adopt);
+
+yes_or_no!(pub Guided);
+
+/// What to do with a generated file that the user edited, when the template
+/// it came from changed too.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Conflicts {
+    Prompt,
+    Overwrite,
+    Keep,
+    /// Three-way merge the template's changes into the user's version, using
+    /// the templates the file was generated from as the base. Files that
+    /// can't be merged, like binaries, are kept if `keep_unmergeable` and
+    /// prompted about otherwise.
+    Merge {
+        keep_unmergeable: bool,
+    },
+}
+
+impl Default for Conflicts {
+    fn default() -> Self {
+        Self::Prompt
+    }
+}
+
+impl Conflicts {
+    pub fn from_flags(force: bool, keep_all: bool) -> Self {
+        if force {
+            Self::Overwrite
+        } else if keep_all {
+            Self::Keep
+        } else {
+            Self::Prompt
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum Profile {
     Debug,
@@ -90,6 +130,16 @@ arg_enum! {
     }
 }
 
+arg_enum! {
+    /// When to color output, used as a global argument
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum ColorChoice {
+        Auto,
+        Always,
+        Never,
+    }
+}
+
 impl FilterLevel {
     /// Filter level for logcat
     pub fn logcat(&self) -> &'static str {
@@ -102,3 +152,23 @@ impl FilterLevel {
         }
     }
 }
+
+arg_enum! {
+    /// CI provider to generate a pipeline for, used as an argument for
+    /// `ci-template`
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum CiProvider {
+        Github,
+        Gitlab,
+    }
+}
+
+arg_enum! {
+    /// Which part of `app.version` to bump, used as an argument for `version`
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum VersionPart {
+        Major,
+        Minor,
+        Patch,
+    }
+}
@@ -1,6 +1,6 @@
 use crate::os::Info;
 use once_cell_regex::regex;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -12,34 +12,82 @@ pub enum Error {
     },
     #[error("Failed to find `NAME` in {path:?}: {text:?}")]
     NameMissing { path: PathBuf, text: String },
-    #[error("Failed to find `VERSION` in {path:?}: {text:?}")]
+    #[error("Failed to find `VERSION`, `VERSION_ID`, or `BUILD_ID` in {path:?}: {text:?}")]
     VersionMissing { path: PathBuf, text: String },
 }
 
-pub fn check() -> Result<Info, Error> {
-    // Doing this robustly seems like a huge nightmare, since not all distros
-    // use this path or even the same format... we'll punt on that for now.
-    // https://grep.app/search?q=os-release&case=true
-    let path = "/etc/os-release";
-    let release = std::fs::read_to_string(&path).map_err(|source| Error::ReadFailed {
-        path: path.into(),
-        source,
+// `/etc/os-release` is usually a symlink to the other one, but the spec only
+// promises that at least one of them exists.
+static OS_RELEASE_PATHS: &[&str] = &["/etc/os-release", "/usr/lib/os-release"];
+
+fn field<'a>(release: &'a str, key: &str) -> Option<&'a str> {
+    release.lines().find_map(|line| {
+        let value = line.trim().strip_prefix(key)?.strip_prefix('=')?;
+        let value = regex!(r#"^["']?(.*?)["']?$"#)
+            .captures(value)
+            .and_then(|caps| caps.get(1))
+            .map_or(value, |value| value.as_str());
+        Some(value).filter(|value| !value.is_empty())
+    })
+}
+
+fn parse(path: &Path, release: &str) -> Result<Info, Error> {
+    let name = field(release, "NAME").ok_or_else(|| Error::NameMissing {
+        path: path.to_owned(),
+        text: release.to_owned(),
     })?;
-    // I'll remain optimistic that these regexes won't choke on some unknown
-    // edge-cases... (as always)
-    let name = regex!(r#"\bNAME="?(.*)\b"#)
-        .captures(&release)
-        .map(|caps| caps[1].to_owned())
-        .ok_or_else(|| Error::NameMissing {
-            path: path.into(),
-            text: release.to_owned(),
-        })?;
-    let version = regex!(r#"\bVERSION="?(.*)\b"#)
-        .captures(&release)
-        .map(|caps| caps[1].to_owned())
+    // Rolling releases (i.e. Arch) don't have a `VERSION`, so we take what we
+    // can get.
+    let version = field(release, "VERSION")
+        .or_else(|| field(release, "VERSION_ID"))
+        .or_else(|| field(release, "BUILD_ID"))
         .ok_or_else(|| Error::VersionMissing {
-            path: path.into(),
+            path: path.to_owned(),
             text: release.to_owned(),
         })?;
-    Ok(Info { name, version })
+    Ok(Info {
+        name: name.to_owned(),
+        version: version.to_owned(),
+    })
+}
+
+pub fn check() -> Result<Info, Error> {
+    let mut last_err = None;
+    for path in OS_RELEASE_PATHS {
+        let path = Path::new(path);
+        match std::fs::read_to_string(path) {
+            Ok(release) => return parse(path, &release),
+            Err(source) => {
+                last_err = Some(Error::ReadFailed {
+                    path: path.to_owned(),
+                    source,
+                })
+            }
+        }
+    }
+    Err(last_err.expect("developer error: no `os-release` paths were checked"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_os_release() {
+        let path = Path::new("/etc/os-release");
+        let ubuntu = "PRETTY_NAME=\"Ubuntu 22.04.3 LTS\"\nNAME=\"Ubuntu\"\nVERSION_ID=\"22.04\"\nVERSION=\"22.04.3 LTS (Jammy Jellyfish)\"\n";
+        let info = parse(path, ubuntu).unwrap();
+        assert_eq!(info.name, "Ubuntu");
+        assert_eq!(info.version, "22.04.3 LTS (Jammy Jellyfish)");
+
+        let arch = "NAME=\"Arch Linux\"\nPRETTY_NAME=\"Arch Linux\"\nID=arch\nBUILD_ID=rolling\n";
+        let info = parse(path, arch).unwrap();
+        assert_eq!(info.name, "Arch Linux");
+        assert_eq!(info.version, "rolling");
+
+        assert!(matches!(
+            parse(path, "ID=mystery\n"),
+            Err(Error::NameMissing { .. })
+        ));
+    }
 }
@@ -4,11 +4,18 @@ use once_cell_regex::regex;
 pub fn check() -> Result<Info, util::RunAndSearchError> {
     util::run_and_search(
         &mut bossy::Command::impure_parse("system_profiler SPSoftwareDataType"),
-        regex!(r"macOS (?P<version>.*)"),
+        regex!(r"macOS (?P<version>\S+)"),
         |_output, caps| caps.name("version").unwrap().as_str().to_owned(),
     )
     .map(|version| Info {
         name: "macOS".to_owned(),
-        version,
+        // This normalizes the version for display, and lets us notice if
+        // `system_profiler` starts giving us something we don't expect.
+        version: util::VersionDouble::from_str(&version)
+            .map(|parsed| parsed.to_string())
+            .unwrap_or_else(|err| {
+                log::warn!("failed to parse macOS version: {}", err);
+                version
+            }),
     })
 }
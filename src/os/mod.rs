@@ -15,6 +15,36 @@ pub use self::linux::*;
 #[cfg(not(any(target_os = "macos", target_os = "linux")))]
 compile_error!("Host platform not yet supported by cargo-mobile! We'd love if you made a PR to add support for this platform ❤️");
 
+/// The effective user and group IDs, or `None` where there's no such thing.
+#[cfg(unix)]
+pub fn effective_ids() -> Option<(u32, u32)> {
+    extern "C" {
+        fn geteuid() -> u32;
+        fn getegid() -> u32;
+    }
+    // These can't fail.
+    Some(unsafe { (geteuid(), getegid()) })
+}
+
+#[cfg(not(unix))]
+pub fn effective_ids() -> Option<(u32, u32)> {
+    None
+}
+
+/// The user ID owning `path`, without following symlinks.
+#[cfg(unix)]
+pub fn owner_uid(path: &std::path::Path) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt as _;
+    std::fs::symlink_metadata(path)
+        .ok()
+        .map(|metadata| metadata.uid())
+}
+
+#[cfg(not(unix))]
+pub fn owner_uid(_path: &std::path::Path) -> Option<u32> {
+    None
+}
+
 // TODO: we should probably expose common functionality throughout `os` in a
 // less ad-hoc way... since it's really easy to accidentally break things.
 #[derive(Debug)]
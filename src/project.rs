@@ -1,12 +1,16 @@
 use crate::{
     config::Config,
+    opts,
     templating::{self, FancyPackResolveError},
     util::{
         cli::{Report, Reportable},
-        prompt, Git,
+        events, prompt, Git,
     },
 };
-use std::path::PathBuf;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug)]
 pub enum Error {
@@ -15,10 +19,14 @@ pub enum Error {
     ProcessingFailed {
         src: PathBuf,
         dest: PathBuf,
-        cause: bicycle::ProcessingError,
+        cause: templating::ProcessError,
     },
-    PromptFailed(std::io::Error),
+    PromptFailed(prompt::Error),
     OverwriteFilePermissionDenied,
+    AssetDirCreationFailed {
+        asset_dir: PathBuf,
+        cause: io::Error,
+    },
 }
 
 impl Reportable for Error {
@@ -35,13 +43,13 @@ impl Reportable for Error {
                 ),
                 cause,
             ),
-            Self::PromptFailed(err) => Report::error(
-                "Failed to prompt to for permission to overwrite project files",
-                err,
-            ),
+            Self::PromptFailed(err) => err.report(),
             Self::OverwriteFilePermissionDenied => {
                 Report::error("Failed to get persmission to overwrite project files", "")
             }
+            Self::AssetDirCreationFailed { asset_dir, cause } => {
+                Report::error(format!("Failed to create asset dir {:?}", asset_dir), cause)
+            }
         }
     }
 }
@@ -50,10 +58,11 @@ pub fn gen(
     config: &Config,
     bike: &bicycle::Bicycle,
     filter: &templating::Filter,
+    conflicts: opts::Conflicts,
     submodule_commit: Option<String>,
     dot_first_init_exists: bool,
 ) -> Result<(), Error> {
-    println!("Generating base project...");
+    events::say("Generating base project...");
     let root = config.app().root_dir();
     let git = Git::new(&root);
     git.init().map_err(Error::GitInitFailed)?;
@@ -85,6 +94,8 @@ pub fn gen(
             if !to_overwrite.is_empty() {
                 log::warn!("first `cargo mobile init` expects a fresh project setup");
                 if prompt::yes_no(
+                    "init.overwrite",
+                    prompt::ASSUME_YES_ENV_VAR,
                     format!(
                         "the following files will be overwritten:\n{:#?}\nOverwrite files?",
                         to_overwrite
@@ -99,12 +110,50 @@ pub fn gen(
                 }
             }
         }
-        bike.filter_and_process(&pack, &root, |_| (), filter.fun())
-            .map_err(|cause| Error::ProcessingFailed {
+        templating::process(bike, &pack, &root, |_| (), filter, conflicts).map_err(|cause| {
+            Error::ProcessingFailed {
                 src: pack.to_owned(),
                 dest: root.to_owned(),
                 cause,
-            })?;
+            }
+        })?;
+    }
+    // The platform projects link to this, so it has to exist before they're
+    // generated. A missing custom one is left for them to report.
+    if !config.app().asset_dir_explicit() {
+        create_asset_dir(&config.app().asset_dir())?;
     }
     Ok(())
 }
+
+/// Creates `asset_dir` with a `.gitkeep`, so that it survives being cloned
+/// while still empty.
+fn create_asset_dir(asset_dir: &Path) -> Result<(), Error> {
+    if asset_dir.is_dir() {
+        return Ok(());
+    }
+    log::info!("creating asset dir at {:?}", asset_dir);
+    fs::create_dir_all(asset_dir)
+        .and_then(|()| fs::write(asset_dir.join(".gitkeep"), ""))
+        .map_err(|cause| Error::AssetDirCreationFailed {
+            asset_dir: asset_dir.to_owned(),
+            cause,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fresh_crates_get_an_asset_dir() {
+        let root = crate::util::TempDirGuard::new().unwrap();
+        let asset_dir = root.join("assets");
+        create_asset_dir(&asset_dir).unwrap();
+        assert!(asset_dir.join(".gitkeep").is_file());
+        // Existing asset dirs are left alone.
+        fs::remove_file(asset_dir.join(".gitkeep")).unwrap();
+        create_asset_dir(&asset_dir).unwrap();
+        assert!(!asset_dir.join(".gitkeep").exists());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::util;
+use crate::util::toolchain::{self, RustupTargetAddFailed};
 use once_cell_regex::exports::once_cell::sync::OnceCell;
 use std::{
     collections::BTreeMap,
@@ -36,18 +36,15 @@ pub trait TargetTrait<'a>: Debug + Sized {
 
     fn arch(&'a self) -> &'a str;
 
-    fn install(&'a self) -> bossy::Result<bossy::ExitStatus> {
-        util::rustup_add(self.triple())
+    fn install(&'a self) -> Result<(), RustupTargetAddFailed> {
+        toolchain::install_targets(std::iter::once(self.triple()))
     }
 
-    fn install_all() -> bossy::Result<()>
+    fn install_all() -> Result<(), RustupTargetAddFailed>
     where
         Self: 'a,
     {
-        for target in Self::all().values() {
-            target.install()?;
-        }
-        Ok(())
+        toolchain::install_targets(Self::all().values().map(|target| target.triple()))
     }
 }
 
@@ -67,10 +64,14 @@ impl Display for TargetInvalid {
     }
 }
 
+/// Resolves which targets to act on: the ones named on the command line, or
+/// else the target of the detected device, or else `configured_default`
+/// (the `default-target` from the config), or else `DEFAULT_KEY`.
 pub fn get_targets<'a, Iter, I, T, U>(
     targets: Iter,
     // we use `dyn` so the type doesn't need to be known when this is `None`
     fallback: Option<(&'a dyn Fn(U) -> Option<&'a T>, U)>,
+    configured_default: Option<&'a T>,
 ) -> Result<Vec<&'a T>, TargetInvalid>
 where
     Iter: ExactSizeIterator<Item = &'a I>,
@@ -90,6 +91,12 @@ where
     } else {
         let target = fallback
             .and_then(|(get_target, arg)| get_target(arg))
+            .or_else(|| {
+                configured_default.map(|target| {
+                    log::info!("falling back on configured default target ({:?})", target);
+                    target
+                })
+            })
             .unwrap_or_else(|| {
                 log::info!("falling back on default target ({})", T::DEFAULT_KEY);
                 T::default_ref()
@@ -102,6 +109,7 @@ pub fn call_for_targets_with_fallback<'a, Iter, I, T, U, E, F>(
     targets: Iter,
     fallback: &'a dyn Fn(U) -> Option<&'a T>,
     arg: U,
+    configured_default: Option<&'a T>,
     f: F,
 ) -> Result<Result<(), E>, TargetInvalid>
 where
@@ -110,7 +118,7 @@ where
     T: TargetTrait<'a>,
     F: Fn(&T) -> Result<(), E>,
 {
-    get_targets(targets, Some((fallback, arg))).map(|targets| {
+    get_targets(targets, Some((fallback, arg)), configured_default).map(|targets| {
         for target in targets {
             f(target)?;
         }
@@ -128,10 +136,78 @@ where
     T: TargetTrait<'a> + 'a,
     F: Fn(&T) -> Result<(), E>,
 {
-    get_targets::<_, _, _, ()>(targets, None).map(|targets| {
+    get_targets::<_, _, _, ()>(targets, None, None).map(|targets| {
         for target in targets {
             f(target)?;
         }
         Ok(())
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct FakeTarget(&'static str);
+
+    impl TargetTrait<'static> for FakeTarget {
+        const DEFAULT_KEY: &'static str = "builtin";
+
+        fn all() -> &'static BTreeMap<&'static str, Self> {
+            static TARGETS: OnceCell<BTreeMap<&'static str, FakeTarget>> = OnceCell::new();
+            TARGETS.get_or_init(|| {
+                ["builtin", "cli", "detected", "configured"]
+                    .iter()
+                    .map(|name| (*name, FakeTarget(*name)))
+                    .collect()
+            })
+        }
+
+        fn triple(&'static self) -> &'static str {
+            self.0
+        }
+
+        fn arch(&'static self) -> &'static str {
+            self.0
+        }
+    }
+
+    fn detect(detected: bool) -> Option<&'static FakeTarget> {
+        if detected {
+            FakeTarget::for_name("detected")
+        } else {
+            None
+        }
+    }
+
+    #[rstest(
+        cli,
+        detected,
+        configured,
+        expected,
+        case(&["cli"], true, true, "cli"),
+        case(&[], true, true, "detected"),
+        case(&[], true, false, "detected"),
+        case(&[], false, true, "configured"),
+        case(&[], false, false, "builtin")
+    )]
+    fn resolves_targets_in_order(cli: &[&str], detected: bool, configured: bool, expected: &str) {
+        let configured = if configured {
+            FakeTarget::for_name("configured")
+        } else {
+            None
+        };
+        let targets = get_targets(cli.iter(), Some((&detect, detected)), configured).unwrap();
+        assert_eq!(
+            targets.iter().map(|target| target.0).collect::<Vec<_>>(),
+            vec![expected]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_cli_targets() {
+        assert!(get_targets::<_, _, FakeTarget, ()>(["nope"].iter(), None, None).is_err());
+    }
+}
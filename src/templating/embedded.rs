@@ -0,0 +1,307 @@
+//! The template packs, compiled into the binary. `build.rs` installs them
+//! into the install dir when building from source, but a prebuilt binary
+//! (i.e. from `cargo binstall`) never runs it on the machine it's used on, so
+//! we write them out ourselves the first time they're missing, or whenever
+//! `cargo mobile self install-templates` asks.
+
+use crate::util::{
+    self,
+    cli::{Report, Reportable},
+};
+use sha2::{Digest as _, Sha256};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedFile {
+    /// Relative to the install dir, with `/` as the separator.
+    pub path: &'static str,
+    pub contents: &'static [u8],
+    pub sha256: &'static str,
+}
+
+include!(concat!(env!("OUT_DIR"), "/embedded_templates.rs"));
+
+/// What gets replaced wholesale, relative to the install dir. This matches
+/// what `build.rs` replaces.
+static PACK_DIRS: &[&str] = &["templates/platforms", "templates/apps"];
+
+#[derive(Debug, Error)]
+pub enum InstallTemplatesError {
+    #[error(transparent)]
+    NoHomeDir(#[from] util::NoHomeDir),
+    #[error(
+        "Embedded template {path:?} is corrupt; its hash is {actual}, but should be {expected}"
+    )]
+    EmbeddedCorrupt {
+        path: &'static str,
+        expected: &'static str,
+        actual: String,
+    },
+    #[error("Failed to delete old templates at {path:?}: {source}")]
+    CleanFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to create directory {path:?}: {source}")]
+    DirCreationFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to write {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to read back {path:?}: {source}")]
+    ReadBackFailed { path: PathBuf, source: io::Error },
+    #[error(
+        "{path:?} doesn't match what was written; its hash is {actual}, but should be {expected}"
+    )]
+    VerifyFailed {
+        path: PathBuf,
+        expected: &'static str,
+        actual: String,
+    },
+}
+
+impl Reportable for InstallTemplatesError {
+    fn report(&self) -> Report {
+        Report::error("Failed to install template packs", self)
+    }
+}
+
+pub fn sha256(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+fn install_path(install_dir: &Path, rel: &str) -> PathBuf {
+    rel.split('/')
+        .fold(install_dir.to_owned(), |path, component| {
+            path.join(component)
+        })
+}
+
+/// Whether the templates still need installing, which is the case on a
+/// prebuilt binary's first run.
+pub fn templates_missing(install_dir: &Path) -> bool {
+    !PACK_DIRS
+        .iter()
+        .all(|rel| install_path(install_dir, rel).is_dir())
+}
+
+/// Fails if any of `files` doesn't match its hash. This runs before anything
+/// is written, so a corrupt binary doesn't leave half the templates behind.
+pub fn check_embedded(files: &[EmbeddedFile]) -> Result<(), InstallTemplatesError> {
+    for file in files {
+        let actual = sha256(file.contents);
+        if actual != file.sha256 {
+            return Err(InstallTemplatesError::EmbeddedCorrupt {
+                path: file.path,
+                expected: file.sha256,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the packs in `install_dir` with `dirs` and `files`, reading each
+/// file back to make sure it landed intact. Gives back how many files were
+/// written.
+pub fn extract_templates(
+    install_dir: &Path,
+    dirs: &[&str],
+    files: &[EmbeddedFile],
+) -> Result<usize, InstallTemplatesError> {
+    check_embedded(files)?;
+    for rel in PACK_DIRS {
+        let path = install_path(install_dir, rel);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)
+                .map_err(|source| InstallTemplatesError::CleanFailed { path, source })?;
+        }
+    }
+    // The packs can have empty directories, which wouldn't otherwise exist.
+    for rel in dirs {
+        let path = install_path(install_dir, rel);
+        fs::create_dir_all(&path)
+            .map_err(|source| InstallTemplatesError::DirCreationFailed { path, source })?;
+    }
+    for file in files {
+        let path = install_path(install_dir, file.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|source| {
+                InstallTemplatesError::DirCreationFailed {
+                    path: parent.to_owned(),
+                    source,
+                }
+            })?;
+        }
+        fs::write(&path, file.contents).map_err(|source| InstallTemplatesError::WriteFailed {
+            path: path.clone(),
+            source,
+        })?;
+        let written = fs::read(&path).map_err(|source| InstallTemplatesError::ReadBackFailed {
+            path: path.clone(),
+            source,
+        })?;
+        let actual = sha256(&written);
+        if actual != file.sha256 {
+            return Err(InstallTemplatesError::VerifyFailed {
+                path,
+                expected: file.sha256,
+                actual,
+            });
+        }
+    }
+    Ok(files.len())
+}
+
+/// The same `commit` and `commit-hash` files `build.rs` writes, so projects
+/// get stamped the same way no matter how cargo-mobile was installed.
+pub fn write_version_stamps(
+    install_dir: &Path,
+    commit_msg: Option<&str>,
+    commit_hash: Option<&str>,
+) -> Result<(), InstallTemplatesError> {
+    for (name, contents) in &[("commit", commit_msg), ("commit-hash", commit_hash)] {
+        if let Some(contents) = contents {
+            let path = install_dir.join(name);
+            fs::write(&path, contents)
+                .map_err(|source| InstallTemplatesError::WriteFailed { path, source })?;
+        }
+    }
+    Ok(())
+}
+
+/// Installs the compiled-in packs, giving back where they went and how many
+/// files there were.
+pub fn install_templates() -> Result<(PathBuf, usize), InstallTemplatesError> {
+    let install_dir = util::install_dir()?;
+    let count = extract_templates(&install_dir, EMBEDDED_DIRS, EMBEDDED_FILES)?;
+    write_version_stamps(
+        &install_dir,
+        util::COMPILED_COMMIT_MSG,
+        util::COMPILED_COMMIT_HASH,
+    )?;
+    Ok((install_dir, count))
+}
+
+/// Runs before every command. Failing isn't fatal here, since plenty of
+/// commands don't need templates, and the ones that do say so themselves.
+pub fn install_templates_if_missing() {
+    let install_dir = match util::install_dir() {
+        Ok(install_dir) => install_dir,
+        Err(_) => return,
+    };
+    if !templates_missing(&install_dir) {
+        return;
+    }
+    match install_templates() {
+        Ok((install_dir, count)) => log::info!(
+            "installed {} template files into {:?}, since they weren't there yet",
+            count,
+            install_dir
+        ),
+        Err(err) => log::error!("{}", err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    static DIRS: &[&str] = &[
+        "templates/platforms",
+        "templates/platforms/xcode",
+        "templates/platforms/xcode/Sources",
+        "templates/apps",
+    ];
+
+    fn file(path: &'static str, contents: &'static [u8]) -> EmbeddedFile {
+        EmbeddedFile {
+            path,
+            contents,
+            sha256: Box::leak(sha256(contents).into_boxed_str()),
+        }
+    }
+
+    fn files() -> Vec<EmbeddedFile> {
+        vec![
+            file(
+                "templates/platforms/xcode/project.yml.hbs",
+                b"name: {{app.name}}\n",
+            ),
+            file("templates/apps/bevy.toml", b"base = \"wgpu\"\n"),
+        ]
+    }
+
+    #[test]
+    fn hashes_like_sha256sum() {
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn detects_first_run() {
+        let dir = TempDirGuard::new().unwrap();
+        assert!(templates_missing(&dir));
+        fs::create_dir_all(dir.join("templates/platforms")).unwrap();
+        assert!(templates_missing(&dir));
+        fs::create_dir_all(dir.join("templates/apps")).unwrap();
+        assert!(!templates_missing(&dir));
+    }
+
+    #[test]
+    fn extracts_and_replaces_old_packs() {
+        let dir = TempDirGuard::new().unwrap();
+        let stale = dir.join("templates/platforms/gradle/stale.txt");
+        fs::create_dir_all(stale.parent().unwrap()).unwrap();
+        fs::write(&stale, "old").unwrap();
+        // Everything else in the install dir is left alone.
+        fs::create_dir_all(dir.join("tools")).unwrap();
+        assert_eq!(extract_templates(&dir, DIRS, &files()).unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(dir.join("templates/platforms/xcode/project.yml.hbs")).unwrap(),
+            "name: {{app.name}}\n"
+        );
+        assert!(dir.join("templates/platforms/xcode/Sources").is_dir());
+        assert!(!stale.exists());
+        assert!(dir.join("tools").is_dir());
+        assert!(!templates_missing(&dir));
+    }
+
+    #[test]
+    fn refuses_corrupt_embedded_files() {
+        let dir = TempDirGuard::new().unwrap();
+        let mut files = files();
+        files[1].contents = b"base = \"tampered\"\n";
+        assert!(matches!(
+            extract_templates(&dir, DIRS, &files),
+            Err(InstallTemplatesError::EmbeddedCorrupt {
+                path: "templates/apps/bevy.toml",
+                ..
+            })
+        ));
+        // Nothing was written.
+        assert!(templates_missing(&dir));
+        assert!(!dir.join("templates/platforms/xcode").exists());
+    }
+
+    #[test]
+    fn writes_version_stamps() {
+        let dir = TempDirGuard::new().unwrap();
+        write_version_stamps(&dir, Some("Add a thing"), None).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("commit")).unwrap(),
+            "Add a thing"
+        );
+        assert!(!dir.join("commit-hash").exists());
+    }
+
+    #[test]
+    fn embedded_packs_are_intact() {
+        check_embedded(EMBEDDED_FILES).unwrap();
+        assert!(EMBEDDED_FILES
+            .iter()
+            .any(|file| file.path.starts_with("templates/platforms/xcode/")));
+    }
+}
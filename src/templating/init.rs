@@ -41,6 +41,46 @@ fn html_escape(
         .map_err(Into::into)
 }
 
+// Escapes for inside a Kotlin string literal, where `$` starts a template
+// expression.
+fn kotlin_string_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '$' => escaped.push_str("\\$"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn kotlin_escape(
+    helper: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    out.write(&kotlin_string_escape(get_str(helper)))
+        .map_err(Into::into)
+}
+
+fn android_string_escape(
+    helper: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    out.write(&crate::android::localization::escape(get_str(helper)))
+        .map_err(Into::into)
+}
+
 fn join(
     helper: &Helper,
     _: &Handlebars,
@@ -164,6 +204,34 @@ fn unprefix_path(
     .map_err(Into::into)
 }
 
+// Like `prefix-path`, but relative to the project being generated, so the
+// project still works after being cloned somewhere else. Absolute paths are
+// left alone, since there's nothing to relativize them against.
+fn root_rel(
+    helper: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let path = get_str(helper);
+    if std::path::Path::new(path).is_absolute() {
+        return out.write(path).map_err(Into::into);
+    }
+    let root_dir_rel = ctx
+        .data()
+        .get("root-dir-rel")
+        .and_then(|root_dir_rel| root_dir_rel.as_str())
+        .ok_or_else(|| RenderError::new("`root-dir-rel` missing from template data."))?
+        .trim_end_matches('/');
+    if root_dir_rel.is_empty() {
+        out.write(path)
+    } else {
+        out.write(&format!("{}/{}", root_dir_rel, path))
+    }
+    .map_err(Into::into)
+}
+
 fn detect_author() -> String {
     let git = Git::new(".".as_ref());
     let name_output = git.user_name().ok();
@@ -181,6 +249,8 @@ pub fn init(config: Option<&Config>) -> Bicycle {
         {
             let mut helpers = HashMap::<_, Box<dyn HelperDef + Send + Sync>>::new();
             helpers.insert("html-escape", Box::new(html_escape));
+            helpers.insert("kotlin-escape", Box::new(kotlin_escape));
+            helpers.insert("android-string-escape", Box::new(android_string_escape));
             helpers.insert("join", Box::new(join));
             helpers.insert("quote-and-join", Box::new(quote_and_join));
             helpers.insert(
@@ -189,6 +259,7 @@ pub fn init(config: Option<&Config>) -> Bicycle {
             );
             helpers.insert("snake-case", Box::new(snake_case));
             helpers.insert("reverse-domain", Box::new(reverse_domain));
+            helpers.insert("root-rel", Box::new(root_rel));
             if config.is_some() {
                 // don't mix these up or very bad things will happen to all of us
                 helpers.insert("prefix-path", Box::new(prefix_path));
@@ -209,3 +280,41 @@ pub fn init(config: Option<&Config>) -> Bicycle {
         },
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        s,
+        escaped,
+        case("1.2.0", "1.2.0"),
+        case(r#"1.0 "beta""#, r#"1.0 \"beta\""#),
+        case("${version}", r"\${version}"),
+        case(r"C:\build", r"C:\\build"),
+        case("line\nbreak", r"line\nbreak")
+    )]
+    fn escapes_kotlin_strings(s: &str, escaped: &str) {
+        assert_eq!(kotlin_string_escape(s), escaped);
+    }
+
+    #[test]
+    fn escapes_android_app_names() {
+        let fixture =
+            crate::config::fixture::app("chess", r#"display-name = "@Bob's \"Chess\"""#, "");
+        let rendered = init(Some(&fixture.config))
+            .render(
+                include_str!(
+                    "../../templates/platforms/android-studio/app/src/main/res/values/strings.xml.hbs"
+                ),
+                |_| (),
+            )
+            .unwrap();
+        assert!(
+            rendered.contains(r#"<string name="app_name">\@Bob\'s \"Chess\"</string>"#),
+            "{}",
+            rendered
+        );
+    }
+}
@@ -0,0 +1,556 @@
+//! Remembers what we wrote the last time a template pack was processed, so
+//! regenerating can tell files that are just out of date apart from files the
+//! user edited.
+
+use super::{filter_and_process, Filter, OldTemplates, Outcome, Summary, TemplateConfigError};
+use crate::{
+    opts,
+    util::{self, prompt, TempDirError, TempDirGuard},
+};
+use bicycle::{Bicycle, JsonMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Lives at the root of each destination directory.
+pub static MANIFEST_FILE_NAME: &str = ".cargo-mobile-manifest.json";
+
+#[derive(Debug, Error)]
+pub enum ProcessError {
+    #[error(transparent)]
+    StageCreationFailed(TempDirError),
+    #[error("{0}")]
+    ProcessingFailed(bicycle::ProcessingError),
+    #[error(transparent)]
+    TemplateConfigInvalid(TemplateConfigError),
+    #[error("Failed to read manifest {path:?}: {source}")]
+    ManifestReadFailed { path: PathBuf, source: io::Error },
+    #[error("Manifest {path:?} is invalid: {source}")]
+    ManifestParseFailed {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Failed to write manifest {path:?}: {source}")]
+    ManifestWriteFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to traverse rendered templates: {0}")]
+    StageTraversalFailed(walkdir::Error),
+    #[error("Failed to read {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to create directory {path:?}: {source}")]
+    DirCreationFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to write {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to prompt about {path:?}: {source}")]
+    PromptFailed {
+        path: PathBuf,
+        source: prompt::Error,
+    },
+}
+
+/// FNV-1a, since we need something that's stable across Rust versions and
+/// this isn't guarding against anything adversarial.
+fn hash(contents: &[u8]) -> String {
+    let hash = contents
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Manifest {
+    // Keyed by path relative to the destination, with `/` separators so the
+    // manifest means the same thing on every platform.
+    files: BTreeMap<String, String>,
+    // The `cargo-mobile` commit the templates came from, so upgrading can
+    // reconstruct what we wrote.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template_commit: Option<String>,
+}
+
+impl Manifest {
+    pub fn path(dest: &Path) -> PathBuf {
+        dest.join(MANIFEST_FILE_NAME)
+    }
+
+    pub fn load(dest: &Path) -> Result<Self, ProcessError> {
+        let path = Self::path(dest);
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|source| ProcessError::ManifestParseFailed { path, source }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(source) => Err(ProcessError::ManifestReadFailed { path, source }),
+        }
+    }
+
+    pub fn save(&self, dest: &Path) -> Result<(), ProcessError> {
+        let path = Self::path(dest);
+        let contents =
+            serde_json::to_string_pretty(self).expect("developer error: manifest wasn't JSON");
+        fs::write(&path, contents + "\n")
+            .map_err(|source| ProcessError::ManifestWriteFailed { path, source })
+    }
+
+    fn key(rel: &Path) -> String {
+        rel.to_string_lossy().replace('\\', "/")
+    }
+
+    pub fn recorded(&self, rel: &Path) -> Option<&str> {
+        self.files.get(&Self::key(rel)).map(String::as_str)
+    }
+
+    pub fn record(&mut self, rel: &Path, hash: String) {
+        self.files.insert(Self::key(rel), hash);
+    }
+
+    /// Records `contents` as what we wrote to `rel`.
+    pub fn record_contents(&mut self, rel: &Path, contents: &[u8]) {
+        self.record(rel, hash(contents));
+    }
+
+    /// Whether `contents` differs from what we wrote to `rel`, or `None` if we
+    /// never wrote it.
+    pub fn edited(&self, rel: &Path, contents: &[u8]) -> Option<bool> {
+        self.recorded(rel)
+            .map(|recorded| recorded != hash(contents))
+    }
+
+    pub fn template_commit(&self) -> Option<&str> {
+        self.template_commit.as_deref()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Plan {
+    Write,
+    Keep,
+    Conflict,
+}
+
+/// Decides what to do with a file given the hash we recorded for it, the hash
+/// of what's on disk, and the hash of what we just rendered.
+fn plan(recorded: Option<&str>, current: Option<&str>, rendered: &str) -> Plan {
+    match (recorded, current) {
+        (_, None) => Plan::Write,
+        (_, Some(current)) if current == rendered => Plan::Keep,
+        // We don't know if this was edited, so we do what we always did
+        // before there were manifests.
+        (None, Some(_)) => Plan::Write,
+        (Some(recorded), Some(current)) if current == recorded => Plan::Write,
+        // The user edited it, but we don't have anything new to say.
+        (Some(recorded), Some(_)) if rendered == recorded => Plan::Keep,
+        (Some(_), Some(_)) => Plan::Conflict,
+    }
+}
+
+fn ask(path: &Path, current: &[u8], rendered: &[u8]) -> Result<bool, ProcessError> {
+    let prompt_failed = |source| ProcessError::PromptFailed {
+        path: path.to_owned(),
+        source,
+    };
+    loop {
+        let response = prompt::default(
+            "templating.conflict",
+            "--keep-all",
+            format!(
+                "{:?} was edited since it was generated, but the template changed too. Keep your version, overwrite it, or show the diff? [k/o/d]",
+                path
+            ),
+            Some("k"),
+            None,
+        )
+        .map_err(prompt_failed)?;
+        match response.to_ascii_lowercase().as_str() {
+            "k" | "keep" => break Ok(false),
+            "o" | "overwrite" => break Ok(true),
+            "d" | "diff" => print!(
+                "{}",
+                util::diff_lines(
+                    &String::from_utf8_lossy(current),
+                    &String::from_utf8_lossy(rendered)
+                )
+            ),
+            _ => println!("Please enter `k`, `o`, or `d`."),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Option<Vec<u8>>, ProcessError> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(ProcessError::ReadFailed {
+            path: path.to_owned(),
+            source,
+        }),
+    }
+}
+
+// Renders the templates the project was last generated from, if we can.
+fn render_old(
+    bike: &Bicycle,
+    commit: Option<&str>,
+    old_src: impl FnOnce(&str) -> Option<OldTemplates>,
+    insert_data: impl Fn(&mut JsonMap),
+) -> Option<TempDirGuard> {
+    let commit = commit.or_else(|| {
+        log::warn!("no template commit was recorded, so edited files won't be merged");
+        None
+    })?;
+    let old = old_src(commit)?;
+    let stage = TempDirGuard::new()
+        .map_err(|err| log::warn!("failed to create stage for old templates: {}", err))
+        .ok()?;
+    filter_and_process(bike, old.src(), &stage, insert_data, |_, _| true)
+        .map_err(|err| log::warn!("failed to render old templates: {}", err))
+        .ok()?;
+    Some(stage)
+}
+
+enum Write {
+    Rendered,
+    Merged(String),
+}
+
+/// Merges the template's changes into the user's version, as long as what we
+/// reconstructed is really what we wrote last time, and everything's text.
+fn merge(
+    recorded: Option<&str>,
+    old: Option<&[u8]>,
+    current: &[u8],
+    rendered: &[u8],
+) -> Option<util::Merged> {
+    let old = old.filter(|old| Some(hash(old).as_str()) == recorded)?;
+    Some(util::merge3(
+        std::str::from_utf8(old).ok()?,
+        std::str::from_utf8(current).ok()?,
+        std::str::from_utf8(rendered).ok()?,
+    ))
+}
+
+/// Processes `src` into `dest` like `filter_and_process`, except that
+/// generated files the user edited are only replaced if `conflicts` says so.
+/// Everything is rendered into a temp dir first, and then copied over.
+pub fn process(
+    bike: &Bicycle,
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    insert_data: impl Fn(&mut JsonMap),
+    filter: &Filter,
+    conflicts: opts::Conflicts,
+) -> Result<(), ProcessError> {
+    let src = src.as_ref();
+    let commit = util::installed_commit_hash().unwrap_or_else(|err| {
+        log::warn!("failed to get installed commit hash: {}", err);
+        None
+    });
+    let summary = process_with_old(
+        bike,
+        src,
+        dest,
+        insert_data,
+        filter,
+        conflicts,
+        commit,
+        |commit| OldTemplates::checkout_or_warn(commit, src),
+    )?;
+    super::upgrade::record(summary);
+    Ok(())
+}
+
+/// `process`, with the templates' commit and where to find the old templates
+/// given explicitly.
+#[allow(clippy::too_many_arguments)]
+fn process_with_old(
+    bike: &Bicycle,
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    insert_data: impl Fn(&mut JsonMap),
+    filter: &Filter,
+    conflicts: opts::Conflicts,
+    commit: Option<String>,
+    old_src: impl FnOnce(&str) -> Option<OldTemplates>,
+) -> Result<Summary, ProcessError> {
+    let dest = dest.as_ref();
+    let stage = TempDirGuard::new().map_err(ProcessError::StageCreationFailed)?;
+    filter_and_process(bike, src.as_ref(), &stage, &insert_data, |path, is_dir| {
+        let rel = path
+            .strip_prefix(&stage)
+            .expect("developer error: bicycle wrote outside of the destination");
+        filter.allows(&dest.join(rel), is_dir)
+    })?;
+
+    let mut manifest = Manifest::load(dest)?;
+    let original = manifest.clone();
+    let mut summary = Summary::default();
+    // Only rendered once something actually needs merging.
+    let mut old_src = Some(old_src);
+    let mut old_stage = None;
+    for entry in walkdir::WalkDir::new(&stage)
+        .min_depth(1)
+        .sort_by_file_name()
+    {
+        let entry = entry.map_err(ProcessError::StageTraversalFailed)?;
+        let rel = entry.path().strip_prefix(&stage).unwrap();
+        let target = dest.join(rel);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target).map_err(|source| ProcessError::DirCreationFailed {
+                path: target,
+                source,
+            })?;
+            continue;
+        }
+        let rendered = read(entry.path())?.unwrap_or_default();
+        let rendered_hash = hash(&rendered);
+        let current = read(&target)?;
+        let (write, outcome) = match plan(
+            manifest.recorded(rel),
+            current.as_deref().map(hash).as_deref(),
+            &rendered_hash,
+        ) {
+            Plan::Write => (Some(Write::Rendered), Outcome::Updated),
+            Plan::Keep => (None, Outcome::Unchanged),
+            Plan::Conflict => {
+                let current = current.as_deref().unwrap_or_default();
+                match conflicts {
+                    opts::Conflicts::Overwrite => (Some(Write::Rendered), Outcome::Updated),
+                    opts::Conflicts::Keep => {
+                        log::warn!(
+                            "keeping edited {:?} even though the template changed",
+                            target
+                        );
+                        (None, Outcome::Kept)
+                    }
+                    opts::Conflicts::Prompt => {
+                        if ask(&target, current, &rendered)? {
+                            (Some(Write::Rendered), Outcome::Updated)
+                        } else {
+                            (None, Outcome::Kept)
+                        }
+                    }
+                    opts::Conflicts::Merge { keep_unmergeable } => {
+                        let old_stage = old_stage.get_or_insert_with(|| {
+                            render_old(
+                                bike,
+                                original.template_commit(),
+                                old_src
+                                    .take()
+                                    .expect("developer error: old templates taken twice"),
+                                &insert_data,
+                            )
+                        });
+                        let old = match old_stage {
+                            Some(old_stage) => read(&old_stage.join(rel))?,
+                            None => None,
+                        };
+                        match merge(manifest.recorded(rel), old.as_deref(), current, &rendered) {
+                            Some(merged) if merged.conflicts > 0 => {
+                                (Some(Write::Merged(merged.text)), Outcome::Conflicted)
+                            }
+                            Some(merged) => (Some(Write::Merged(merged.text)), Outcome::Merged),
+                            None if keep_unmergeable => {
+                                log::warn!(
+                                    "keeping edited {:?} even though the template changed, since it can't be merged",
+                                    target
+                                );
+                                (None, Outcome::Kept)
+                            }
+                            None => {
+                                if ask(&target, current, &rendered)? {
+                                    (Some(Write::Rendered), Outcome::Updated)
+                                } else {
+                                    (None, Outcome::Kept)
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        };
+        match write {
+            Some(Write::Rendered) => {
+                log::info!("writing generated file {:?}", target);
+                // `fs::copy` keeps permissions, which matters for things like
+                // `gradlew`.
+                fs::copy(entry.path(), &target).map_err(|source| ProcessError::WriteFailed {
+                    path: target.clone(),
+                    source,
+                })?;
+            }
+            Some(Write::Merged(text)) => {
+                log::info!("writing merged file {:?}", target);
+                fs::write(&target, text).map_err(|source| ProcessError::WriteFailed {
+                    path: target.clone(),
+                    source,
+                })?;
+            }
+            None => (),
+        }
+        summary.add(target, outcome);
+        // Recording what we rendered even when keeping the user's version
+        // means they only get asked again once the template changes again.
+        manifest.record(rel, rendered_hash);
+    }
+    manifest.template_commit = commit;
+    if manifest != original {
+        manifest.save(dest)?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        recorded,
+        current,
+        rendered,
+        expected,
+        case(None, None, "a", Plan::Write),
+        case(Some("a"), None, "b", Plan::Write),
+        case(None, Some("a"), "a", Plan::Keep),
+        case(None, Some("a"), "b", Plan::Write),
+        case(Some("a"), Some("a"), "b", Plan::Write),
+        case(Some("a"), Some("b"), "a", Plan::Keep),
+        case(Some("a"), Some("b"), "b", Plan::Keep),
+        case(Some("a"), Some("b"), "c", Plan::Conflict)
+    )]
+    fn plans(recorded: Option<&str>, current: Option<&str>, rendered: &str, expected: Plan) {
+        assert_eq!(plan(recorded, current, rendered), expected);
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(hash(b""), "cbf29ce484222325");
+        assert_eq!(hash(b"a"), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn edits_survive_regeneration() {
+        let bike = super::super::init(None);
+        let src = TempDirGuard::new().unwrap();
+        let dest = TempDirGuard::new().unwrap();
+        let gen =
+            |conflicts| process(&bike, &src, &dest, |_| (), &Filter::WildWest, conflicts).unwrap();
+        let generated = dest.join("generated.txt");
+
+        fs::write(src.join("generated.txt"), "one").unwrap();
+        gen(opts::Conflicts::Keep);
+        assert_eq!(fs::read_to_string(&generated).unwrap(), "one");
+        assert!(Manifest::path(&dest).is_file());
+
+        // Untouched files just get updated.
+        fs::write(src.join("generated.txt"), "two").unwrap();
+        gen(opts::Conflicts::Keep);
+        assert_eq!(fs::read_to_string(&generated).unwrap(), "two");
+
+        // Edits are kept when the template didn't change, even when forcing.
+        fs::write(&generated, "mine").unwrap();
+        gen(opts::Conflicts::Overwrite);
+        assert_eq!(fs::read_to_string(&generated).unwrap(), "mine");
+
+        // When both changed, it's up to `conflicts`.
+        fs::write(src.join("generated.txt"), "three").unwrap();
+        gen(opts::Conflicts::Keep);
+        assert_eq!(fs::read_to_string(&generated).unwrap(), "mine");
+        fs::write(src.join("generated.txt"), "four").unwrap();
+        gen(opts::Conflicts::Overwrite);
+        assert_eq!(fs::read_to_string(&generated).unwrap(), "four");
+    }
+
+    #[test]
+    fn upgrades_merge_template_changes_into_edits() {
+        let bike = super::super::init(None);
+        let v1 = TempDirGuard::new().unwrap();
+        let v2 = TempDirGuard::new().unwrap();
+        let dest = TempDirGuard::new().unwrap();
+        let gen = |src: &Path, conflicts, commit: &str| {
+            process_with_old(
+                &bike,
+                src,
+                &dest,
+                |_| (),
+                &Filter::WildWest,
+                conflicts,
+                Some(commit.to_owned()),
+                |commit| {
+                    assert_eq!(commit, "v1");
+                    Some(OldTemplates::from_src(v1.path()))
+                },
+            )
+            .unwrap()
+        };
+        let read = |name: &str| fs::read(dest.join(name)).unwrap();
+
+        fs::write(v1.join("same.txt"), "same\n").unwrap();
+        fs::write(
+            v1.join("config.txt"),
+            "name = app\nversion = 1\ntheme = dark\nlog = quiet\n",
+        )
+        .unwrap();
+        fs::write(v1.join("clash.txt"), "color = blue\n").unwrap();
+        fs::write(v1.join("icon.png"), b"\x89PNG\xff\x01").unwrap();
+        gen(&v1, opts::Conflicts::Keep, "v1");
+        assert_eq!(Manifest::load(&dest).unwrap().template_commit(), Some("v1"));
+
+        fs::write(
+            dest.join("config.txt"),
+            "name = app\nversion = 1\ntheme = dark\nlog = loud\n",
+        )
+        .unwrap();
+        fs::write(dest.join("clash.txt"), "color = red\n").unwrap();
+        fs::write(dest.join("icon.png"), b"\x89PNG\xff\x02").unwrap();
+
+        fs::write(v2.join("same.txt"), "same\n").unwrap();
+        fs::write(
+            v2.join("config.txt"),
+            "name = app\nversion = 2\ntheme = dark\nlog = quiet\n",
+        )
+        .unwrap();
+        fs::write(v2.join("clash.txt"), "color = green\n").unwrap();
+        fs::write(v2.join("icon.png"), b"\x89PNG\xff\x03").unwrap();
+        fs::write(v2.join("new.txt"), "new\n").unwrap();
+        let summary = gen(
+            &v2,
+            opts::Conflicts::Merge {
+                keep_unmergeable: true,
+            },
+            "v2",
+        );
+
+        // The local edit and the upstream change were to different lines.
+        assert_eq!(
+            read("config.txt"),
+            b"name = app\nversion = 2\ntheme = dark\nlog = loud\n"
+        );
+        assert_eq!(
+            String::from_utf8(read("clash.txt")).unwrap(),
+            format!(
+                "{}\ncolor = red\n{}\ncolor = green\n{}\n",
+                util::OURS_MARKER,
+                util::SEPARATOR_MARKER,
+                util::THEIRS_MARKER
+            )
+        );
+        assert_eq!(read("icon.png"), b"\x89PNG\xff\x02");
+        assert_eq!(read("new.txt"), b"new\n");
+        assert_eq!(
+            summary,
+            Summary {
+                unchanged: 1,
+                updated: vec![dest.join("new.txt")],
+                merged: vec![dest.join("config.txt")],
+                conflicted: vec![dest.join("clash.txt")],
+                kept: vec![dest.join("icon.png")],
+            }
+        );
+        assert_eq!(Manifest::load(&dest).unwrap().template_commit(), Some("v2"));
+    }
+}
@@ -1,8 +1,14 @@
+mod embedded;
 mod fancy;
 mod filter;
 mod init;
+mod manifest;
+mod template_config;
+mod upgrade;
 
-pub use self::{fancy::*, filter::*, init::*};
+pub use self::{
+    embedded::*, fancy::*, filter::*, init::*, manifest::*, template_config::*, upgrade::*,
+};
 
 use crate::util::{self, Git};
 use std::{
@@ -193,3 +199,23 @@ pub fn list_app_packs() -> Result<Vec<String>, ListError> {
         packs
     })
 }
+
+/// Every path under `dir`, along with the contents of the files, so tests can
+/// compare generated projects.
+#[cfg(test)]
+pub fn read_tree(dir: &Path) -> std::collections::BTreeMap<PathBuf, Option<Vec<u8>>> {
+    walkdir::WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let rel = entry.path().strip_prefix(dir).unwrap().to_owned();
+            let contents = if entry.file_type().is_file() {
+                Some(fs::read(entry.path()).unwrap())
+            } else {
+                None
+            };
+            (rel, contents)
+        })
+        .collect()
+}
@@ -0,0 +1,299 @@
+//! `.template-config.toml`, which lets a template pack say which of its files
+//! bicycle shouldn't treat the usual way, i.e. a `.hbs` file that should be
+//! copied as-is, or a `.plist` that needs rendering anyway.
+//!
+//! Each list holds gitignore-style patterns relative to the pack's root. When
+//! a file matches more than one, the first of these wins:
+//!
+//! 1. `skip`: it isn't generated at all.
+//! 2. `raw`: it's copied byte-for-byte, keeping its name, `.hbs` and all.
+//! 3. `render`: it's rendered, even without a `.hbs` suffix.
+//! 4. Otherwise, `.hbs` files are rendered (and lose the suffix), and
+//!    everything else is copied.
+
+use super::ProcessError;
+use bicycle::{Action, Bicycle, JsonMap};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Deserialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Lives at the root of a template pack, and is never generated itself.
+pub static TEMPLATE_CONFIG_FILE_NAME: &str = ".template-config.toml";
+
+#[derive(Debug, Error)]
+pub enum TemplateConfigError {
+    #[error("Failed to read template config {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("Template config {path:?} is invalid: {source}")]
+    ParseFailed {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Template config {path:?} has an invalid pattern {pattern:?}: {source}")]
+    PatternInvalid {
+        path: PathBuf,
+        pattern: String,
+        source: ignore::Error,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Raw {
+    #[serde(default)]
+    raw: Vec<String>,
+    #[serde(default)]
+    render: Vec<String>,
+    #[serde(default)]
+    skip: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Handling {
+    Skip,
+    Raw,
+    Render,
+    Default,
+}
+
+#[derive(Debug)]
+pub struct TemplateConfig {
+    skip: Gitignore,
+    raw: Gitignore,
+    render: Gitignore,
+}
+
+impl Default for TemplateConfig {
+    fn default() -> Self {
+        Self {
+            skip: Gitignore::empty(),
+            raw: Gitignore::empty(),
+            render: Gitignore::empty(),
+        }
+    }
+}
+
+impl TemplateConfig {
+    /// `path` is only used for error messages.
+    pub fn parse(root: &Path, path: &Path, contents: &str) -> Result<Self, TemplateConfigError> {
+        let raw =
+            toml::from_str::<Raw>(contents).map_err(|source| TemplateConfigError::ParseFailed {
+                path: path.to_owned(),
+                source,
+            })?;
+        let matcher = |patterns: &[String]| {
+            let mut builder = GitignoreBuilder::new(root);
+            for pattern in patterns {
+                builder.add_line(None, pattern).map_err(|source| {
+                    TemplateConfigError::PatternInvalid {
+                        path: path.to_owned(),
+                        pattern: pattern.clone(),
+                        source,
+                    }
+                })?;
+            }
+            builder
+                .build()
+                .map_err(|source| TemplateConfigError::PatternInvalid {
+                    path: path.to_owned(),
+                    pattern: patterns.join(", "),
+                    source,
+                })
+        };
+        Ok(Self {
+            skip: matcher(&raw.skip)?,
+            raw: matcher(&raw.raw)?,
+            render: matcher(&raw.render)?,
+        })
+    }
+
+    /// Packs without a config get the default handling for everything.
+    pub fn load(root: &Path) -> Result<Self, TemplateConfigError> {
+        let path = root.join(TEMPLATE_CONFIG_FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(root, &path, &contents),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(source) => Err(TemplateConfigError::ReadFailed { path, source }),
+        }
+    }
+
+    /// How to handle the file at `rel`, relative to the pack's root. Patterns
+    /// that match a directory match everything in it.
+    pub fn handling(&self, rel: &Path) -> Handling {
+        let matches =
+            |matcher: &Gitignore| matcher.matched_path_or_any_parents(rel, false).is_ignore();
+        if matches(&self.skip) {
+            Handling::Skip
+        } else if matches(&self.raw) {
+            Handling::Raw
+        } else if matches(&self.render) {
+            Handling::Render
+        } else {
+            Handling::Default
+        }
+    }
+
+    /// What to do with `action`. Directories are left as they are, so a
+    /// skipped directory is still created, though nothing's put in it.
+    fn adjust(&self, root: &Path, action: &Action) -> Adjusted {
+        let (src, dest, template) = match action {
+            Action::CreateDirectory { .. } => return Adjusted::Keep,
+            Action::CopyFile { src, dest } => (src, dest, false),
+            Action::WriteTemplate { src, dest } => (src, dest, true),
+        };
+        let rel = src
+            .strip_prefix(root)
+            .expect("developer error: bicycle read outside of the template pack");
+        if rel == Path::new(TEMPLATE_CONFIG_FILE_NAME) {
+            return Adjusted::Drop;
+        }
+        match (self.handling(rel), template) {
+            (Handling::Skip, _) => Adjusted::Drop,
+            (Handling::Raw, true) => Adjusted::Replace(Action::CopyFile {
+                src: src.clone(),
+                // bicycle already took the suffix off.
+                dest: dest.with_file_name(rel.file_name().unwrap_or_default()),
+            }),
+            (Handling::Render, false) => Adjusted::Replace(Action::WriteTemplate {
+                src: src.clone(),
+                dest: dest.clone(),
+            }),
+            _ => Adjusted::Keep,
+        }
+    }
+}
+
+enum Adjusted {
+    Keep,
+    Drop,
+    Replace(Action),
+}
+
+/// `Bicycle::filter_and_process`, but following the pack's
+/// `.template-config.toml`. `filter` is given where each file or directory
+/// would be written, and whether it's a directory.
+pub fn filter_and_process(
+    bike: &Bicycle,
+    src: &Path,
+    dest: &Path,
+    insert_data: impl Fn(&mut JsonMap),
+    mut filter: impl FnMut(&Path, bool) -> bool,
+) -> Result<(), ProcessError> {
+    let config = TemplateConfig::load(src).map_err(ProcessError::TemplateConfigInvalid)?;
+    // Replacements are processed afterwards, since bicycle's filter can only
+    // say yes or no.
+    let mut replacements = Vec::new();
+    bike.filter_and_process(src, dest, &insert_data, |action| {
+        match config.adjust(src, action) {
+            Adjusted::Keep => filter(action.dest(), action.is_create_directory()),
+            Adjusted::Drop => false,
+            Adjusted::Replace(replacement) => {
+                if filter(replacement.dest(), false) {
+                    replacements.push(replacement);
+                }
+                false
+            }
+        }
+    })
+    .map_err(ProcessError::ProcessingFailed)?;
+    for action in &replacements {
+        bike.process_action(action, &insert_data)
+            .map_err(ProcessError::ProcessingFailed)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+
+    static CONFIG: &str = r#"
+raw = ["*.hbs", "fonts/"]
+render = ["*.plist", "fonts/*.txt", "notes.hbs"]
+skip = ["README.md", "fonts/draft.ttf"]
+"#;
+
+    fn config() -> TemplateConfig {
+        TemplateConfig::parse(
+            Path::new("/pack"),
+            Path::new("/pack/.template-config.toml"),
+            CONFIG,
+        )
+        .unwrap()
+    }
+
+    #[rstest(
+        rel,
+        expected,
+        case("README.md", Handling::Skip),
+        case("docs/README.md", Handling::Skip),
+        case("fonts/draft.ttf", Handling::Skip),
+        case("fonts/Inter.ttf", Handling::Raw),
+        // `raw` beats `render`, however specific the `render` pattern is.
+        case("fonts/LICENSE.txt", Handling::Raw),
+        case("notes.hbs", Handling::Raw),
+        case("view.html.hbs", Handling::Raw),
+        case("Info.plist", Handling::Render),
+        case("src/lib.rs", Handling::Default),
+        case("icon.png", Handling::Default)
+    )]
+    fn follows_precedence(rel: &str, expected: Handling) {
+        assert_eq!(config().handling(Path::new(rel)), expected);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(matches!(
+            TemplateConfig::parse(Path::new("/pack"), Path::new("/pack/x.toml"), "copy = []"),
+            Err(TemplateConfigError::ParseFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn processes_following_config() {
+        let bike = super::super::init(None);
+        let src = TempDirGuard::new().unwrap();
+        let dest = TempDirGuard::new().unwrap();
+        fs::write(
+            src.join(TEMPLATE_CONFIG_FILE_NAME),
+            "raw = [\"*.html.hbs\", \"*.ttf\"]\nrender = [\"*.plist\"]\nskip = [\"NOTES.md\"]\n",
+        )
+        .unwrap();
+        fs::write(src.join("Info.plist"), "<string>{{name}}</string>").unwrap();
+        fs::write(src.join("page.html.hbs"), "<h1>{{title}}</h1>").unwrap();
+        fs::write(src.join("main.rs.hbs"), "// {{name}}").unwrap();
+        fs::write(src.join("NOTES.md"), "notes").unwrap();
+        // Not valid UTF-8, and it looks a bit like a template.
+        fs::write(src.join("font.ttf"), b"\x00\xff{{name}}\xfe").unwrap();
+        let mut filtered = Vec::new();
+        filter_and_process(
+            &bike,
+            &src,
+            &dest,
+            |map| {
+                map.insert("name".to_owned(), "tic-tac".into());
+            },
+            |path, _| {
+                filtered.push(path.to_owned());
+                true
+            },
+        )
+        .unwrap();
+        let read = |name: &str| fs::read(dest.join(name)).unwrap();
+        assert_eq!(read("Info.plist"), b"<string>tic-tac</string>");
+        assert_eq!(read("page.html.hbs"), b"<h1>{{title}}</h1>");
+        assert_eq!(read("main.rs"), b"// tic-tac");
+        assert_eq!(read("font.ttf"), b"\x00\xff{{name}}\xfe");
+        assert!(!dest.join("NOTES.md").exists());
+        assert!(!dest.join("page.html").exists());
+        assert!(!dest.join(TEMPLATE_CONFIG_FILE_NAME).exists());
+        // The filter sees where things end up.
+        assert!(filtered.contains(&dest.join("page.html.hbs")));
+    }
+}
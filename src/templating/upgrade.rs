@@ -0,0 +1,217 @@
+//! Reconstructs the templates a project was last generated from, so
+//! `cargo mobile upgrade-project` can merge template changes into files the
+//! user edited instead of making them pick a side.
+
+use crate::{
+    update,
+    util::{
+        self,
+        repo::{self, Repo},
+        TempDirError, TempDirGuard,
+    },
+};
+use once_cell_regex::exports::once_cell::sync::Lazy;
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum OldTemplatesError {
+    #[error(transparent)]
+    NoHomeDir(util::NoHomeDir),
+    #[error("Failed to clone `cargo-mobile` repo: {0}")]
+    CloneFailed(repo::Error),
+    #[error("Failed to fetch commit {commit:?}: {source}")]
+    FetchFailed {
+        commit: String,
+        source: bossy::Error,
+    },
+    #[error(transparent)]
+    TempDirFailed(TempDirError),
+    #[error("Failed to check out commit {commit:?}: {source}")]
+    CheckoutFailed {
+        commit: String,
+        source: bossy::Error,
+    },
+}
+
+// A detached worktree of the `cargo-mobile` checkout, which is removed again
+// once we're done with it.
+#[derive(Debug)]
+struct Worktree {
+    repo: Repo,
+    path: PathBuf,
+    _dir: TempDirGuard,
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        if let Err(err) = self
+            .repo
+            .git()
+            .command_parse("worktree remove --force")
+            .with_arg(&self.path)
+            .run_and_wait_for_output()
+        {
+            log::warn!("failed to remove worktree {:?}: {}", self.path, err);
+        }
+    }
+}
+
+/// The template pack a project was last generated from.
+#[derive(Debug)]
+pub struct OldTemplates {
+    src: PathBuf,
+    _worktree: Option<Worktree>,
+}
+
+impl OldTemplates {
+    pub fn from_src(src: impl Into<PathBuf>) -> Self {
+        Self {
+            src: src.into(),
+            _worktree: None,
+        }
+    }
+
+    /// Checks out `commit` of `cargo-mobile` and finds the pack there that
+    /// corresponds to the installed pack at `src`. Packs that didn't come
+    /// with `cargo-mobile` don't have a history we know about, so they're
+    /// `None`.
+    pub fn checkout(commit: &str, src: &Path) -> Result<Option<Self>, OldTemplatesError> {
+        let install_dir = util::install_dir().map_err(OldTemplatesError::NoHomeDir)?;
+        let rel = match src.strip_prefix(&install_dir) {
+            Ok(rel) => rel,
+            Err(_) => return Ok(None),
+        };
+        let repo = update::cargo_mobile_repo().map_err(OldTemplatesError::NoHomeDir)?;
+        if !repo.path().is_dir() {
+            repo.update(update::REPO_URL)
+                .map_err(OldTemplatesError::CloneFailed)?;
+        }
+        let git = repo.git();
+        if !git.has_commit(commit) {
+            git.fetch_commit(commit)
+                .map_err(|source| OldTemplatesError::FetchFailed {
+                    commit: commit.to_owned(),
+                    source,
+                })?;
+        }
+        let dir = TempDirGuard::new().map_err(OldTemplatesError::TempDirFailed)?;
+        let path = dir.join("cargo-mobile");
+        git.command_parse("worktree add --detach")
+            .with_arg(&path)
+            .with_arg(commit)
+            .run_and_wait_for_output()
+            .map_err(|source| OldTemplatesError::CheckoutFailed {
+                commit: commit.to_owned(),
+                source,
+            })?;
+        Ok(Some(Self {
+            src: path.join(rel),
+            _worktree: Some(Worktree {
+                repo,
+                path,
+                _dir: dir,
+            }),
+        }))
+    }
+
+    /// `checkout`, except that failing just means we can't merge.
+    pub fn checkout_or_warn(commit: &str, src: &Path) -> Option<Self> {
+        Self::checkout(commit, src).unwrap_or_else(|err| {
+            log::warn!(
+                "can't reconstruct templates from commit {:?}, so edited files won't be merged: {}",
+                commit,
+                err
+            );
+            None
+        })
+    }
+
+    pub fn src(&self) -> &Path {
+        &self.src
+    }
+}
+
+/// What happened to a generated file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Unchanged,
+    Updated,
+    Merged,
+    Conflicted,
+    Kept,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Summary {
+    pub unchanged: usize,
+    pub updated: Vec<PathBuf>,
+    pub merged: Vec<PathBuf>,
+    pub conflicted: Vec<PathBuf>,
+    pub kept: Vec<PathBuf>,
+}
+
+impl Summary {
+    pub fn add(&mut self, path: PathBuf, outcome: Outcome) {
+        match outcome {
+            Outcome::Unchanged => self.unchanged += 1,
+            Outcome::Updated => self.updated.push(path),
+            Outcome::Merged => self.merged.push(path),
+            Outcome::Conflicted => self.conflicted.push(path),
+            Outcome::Kept => self.kept.push(path),
+        }
+    }
+
+    pub fn extend(&mut self, other: Self) {
+        self.unchanged += other.unchanged;
+        self.updated.extend(other.updated);
+        self.merged.extend(other.merged);
+        self.conflicted.extend(other.conflicted);
+        self.kept.extend(other.kept);
+    }
+
+    pub fn print(&self) {
+        println!("{} generated files were unchanged.", self.unchanged);
+        for (label, paths) in [
+            ("Updated", &self.updated),
+            ("Merged with your edits", &self.merged),
+            ("Kept your version of", &self.kept),
+            (
+                "Conflicted (search for `<<<<<<<` to resolve them)",
+                &self.conflicted,
+            ),
+        ]
+        .iter()
+        {
+            if !paths.is_empty() {
+                println!("{}:", label);
+                for path in paths.iter() {
+                    println!("  {}", path.display());
+                }
+            }
+        }
+    }
+}
+
+// Every `process` call adds to this, since a project is generated from
+// several packs in several places.
+static SUMMARY: Lazy<Mutex<Summary>> = Lazy::new(Default::default);
+
+pub(super) fn record(summary: Summary) {
+    SUMMARY
+        .lock()
+        .expect("developer error: summary lock was poisoned")
+        .extend(summary);
+}
+
+/// Everything processed since the last call.
+pub fn take_summary() -> Summary {
+    std::mem::take(
+        &mut *SUMMARY
+            .lock()
+            .expect("developer error: summary lock was poisoned"),
+    )
+}
@@ -0,0 +1,932 @@
+//! `cargo mobile uninstall`, which takes back out what `cargo mobile init` put
+//! into a project: the generated projects, our entries in
+//! `.cargo/config.toml`, and (only if you say so separately) `mobile.toml`.
+//! Generated files that were edited since are left where they are, and so
+//! are projects that were adopted instead of generated.
+
+use crate::{
+    adopt,
+    android::target::Target as AndroidTarget,
+    config::{self, Config, LoadExistingError},
+    opts::NonInteractive,
+    target::TargetTrait as _,
+    templating::{Manifest, ProcessError, MANIFEST_FILE_NAME},
+    util::{
+        self,
+        cli::{Report, Reportable},
+        prompt, tools,
+    },
+};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use toml_edit::{Document, Item, TomlError};
+
+// What `DotCargoTarget` sets, which is all we ever put in a target table.
+static TARGET_FIELDS: &[&str] = &["ar", "linker", "rustflags"];
+
+// Build output and IDE state, which go with the project no matter what's in
+// them. These are relative to the project dir, since a dir called `build`
+// anywhere else could just as well be a source package.
+static BUILD_DIRS: &[&str] = &[
+    "build",
+    ".gradle",
+    ".idea",
+    "app/build",
+    "app/.cxx",
+    "Pods",
+    "DerivedData",
+];
+
+// Apple's per-user state, which lives in `xcuserdata` dirs inside of each
+// `.xcodeproj` and `.xcworkspace`.
+static XCUSERDATA: &str = "xcuserdata";
+
+// Projects generated on a Mac can be checked in and uninstalled elsewhere,
+// where there's no Apple config to ask, so this mirrors its default.
+#[cfg(not(target_os = "macos"))]
+static DEFAULT_APPLE_PROJECT_DIR: &str = "gen/apple";
+
+#[derive(Debug)]
+pub enum Error {
+    LoadFailed(LoadExistingError),
+    TraversalFailed {
+        path: PathBuf,
+        cause: walkdir::Error,
+    },
+    ReadFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    ManifestLoadFailed(ProcessError),
+    DotCargoParseFailed {
+        path: PathBuf,
+        cause: TomlError,
+    },
+    PromptFailed(prompt::Error),
+    RemoveFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+    WriteFailed {
+        path: PathBuf,
+        cause: io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::LoadFailed(err) => err.report(),
+            Self::TraversalFailed { path, cause } => {
+                Report::error(format!("Failed to traverse {:?}", path), cause)
+            }
+            Self::ReadFailed { path, cause } => {
+                Report::error(format!("Failed to read {:?}", path), cause)
+            }
+            Self::ManifestLoadFailed(err) => {
+                Report::error("Failed to load generation manifest", err)
+            }
+            Self::DotCargoParseFailed { path, cause } => {
+                Report::error(format!("Failed to parse cargo config at {:?}", path), cause)
+            }
+            Self::PromptFailed(err) => err.report(),
+            Self::RemoveFailed { path, cause } => {
+                Report::error(format!("Failed to remove {:?}", path), cause)
+            }
+            Self::WriteFailed { path, cause } => {
+                Report::error(format!("Failed to write {:?}", path), cause)
+            }
+        }
+    }
+}
+
+/// What's to become of a generated project dir.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct DirPlan {
+    /// There's no manifest, so we can't tell generated files from edited
+    /// ones, and the whole dir goes.
+    pub whole: bool,
+    /// Files we generated that are just as we left them.
+    pub generated: Vec<PathBuf>,
+    /// Files and dirs we didn't generate that go anyway, i.e. build output,
+    /// our manifests, and symlinks to the Rust lib and assets.
+    pub other: Vec<PathBuf>,
+    /// Files we generated that were edited since, which are left alone.
+    pub edited: Vec<PathBuf>,
+    /// Files we didn't generate, like sources you added, which are left
+    /// alone too.
+    pub kept: Vec<PathBuf>,
+}
+
+impl DirPlan {
+    fn removals(&self) -> impl Iterator<Item = &PathBuf> {
+        self.generated.iter().chain(&self.other)
+    }
+}
+
+fn is_xcode_bundle(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext == "xcodeproj" || ext == "xcworkspace")
+}
+
+fn is_build_dir(dir: &Path, entry: &walkdir::DirEntry) -> bool {
+    if !entry.file_type().is_dir() {
+        return false;
+    }
+    let rel = if let Ok(rel) = entry.path().strip_prefix(dir) {
+        rel
+    } else {
+        return false;
+    };
+    if BUILD_DIRS
+        .iter()
+        .any(|build_dir| rel == Path::new(build_dir))
+    {
+        return true;
+    }
+    // i.e. `app.xcodeproj/xcuserdata` or
+    // `app.xcodeproj/project.xcworkspace/xcuserdata`
+    entry.file_name() == XCUSERDATA
+        && rel.parent().map_or(false, |parent| {
+            parent.components().count() > 0
+                && parent
+                    .components()
+                    .all(|component| is_xcode_bundle(Path::new(component.as_os_str())))
+        })
+}
+
+/// Sorts everything in `dir` using the manifests our template processing left
+/// there (one at the root, and one for each asset pack).
+pub fn scan_dir(dir: &Path) -> Result<DirPlan, Error> {
+    if !Manifest::path(dir).is_file() {
+        return Ok(DirPlan {
+            whole: true,
+            generated: vec![dir.to_owned()],
+            ..Default::default()
+        });
+    }
+    let mut plan = DirPlan::default();
+    let mut build_dirs = Vec::new();
+    let mut manifests = BTreeMap::new();
+    let entries = walkdir::WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            if is_build_dir(dir, entry) {
+                build_dirs.push(entry.path().to_owned());
+                false
+            } else {
+                true
+            }
+        });
+    for entry in entries {
+        let entry = entry.map_err(|cause| Error::TraversalFailed {
+            path: dir.to_owned(),
+            cause,
+        })?;
+        let path = entry.path();
+        if entry.file_type().is_dir() {
+            if Manifest::path(path).is_file() {
+                manifests.insert(
+                    path.to_owned(),
+                    Manifest::load(path).map_err(Error::ManifestLoadFailed)?,
+                );
+            }
+            continue;
+        }
+        if entry.file_type().is_symlink() || entry.file_name() == MANIFEST_FILE_NAME {
+            plan.other.push(path.to_owned());
+            continue;
+        }
+        let (manifest_dir, manifest) = path
+            .ancestors()
+            .find_map(|ancestor| manifests.get_key_value(ancestor))
+            .expect("developer error: the root manifest wasn't loaded");
+        let contents = fs::read(path).map_err(|cause| Error::ReadFailed {
+            path: path.to_owned(),
+            cause,
+        })?;
+        let rel = path
+            .strip_prefix(manifest_dir)
+            .expect("developer error: manifest dir wasn't an ancestor");
+        match manifest.edited(rel, &contents) {
+            Some(false) => plan.generated.push(path.to_owned()),
+            Some(true) => plan.edited.push(path.to_owned()),
+            None => plan.kept.push(path.to_owned()),
+        }
+    }
+    plan.other.extend(build_dirs);
+    plan.other.sort();
+    plan.kept.sort();
+    Ok(plan)
+}
+
+/// What stripping our entries out of a cargo config left.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Stripped {
+    pub contents: String,
+    /// Dotted keys, i.e. `target.aarch64-linux-android.linker`.
+    pub removed: Vec<String>,
+}
+
+impl Stripped {
+    /// Whether there's nothing left worth keeping the file for.
+    pub fn is_empty(&self) -> bool {
+        self.contents.trim().is_empty()
+    }
+}
+
+// Removes `keys` from the table at `name` in `parent`, and then the table
+// itself if that left it empty.
+fn remove_keys(
+    parent: &mut dyn toml_edit::TableLike,
+    name: &str,
+    keys: &[&str],
+    prefix: &str,
+    removed: &mut Vec<String>,
+) {
+    let table = if let Some(table) = parent.get_mut(name).and_then(Item::as_table_like_mut) {
+        table
+    } else {
+        return;
+    };
+    let before = removed.len();
+    for key in keys {
+        if table.remove(key).is_some() {
+            removed.push(format!("{}{}.{}", prefix, name, key));
+        }
+    }
+    if removed.len() > before && table.is_empty() {
+        parent.remove(name);
+    }
+}
+
+/// Takes the entries `cargo mobile init` writes out of a cargo config: the
+/// `ar`, `linker`, and `rustflags` of each of `triples`, the `env_keys` from
+/// `mobile.toml`, and `build.target` if it's still `build_target`. Everything
+/// else, comments included, stays as it was.
+pub fn strip_dot_cargo(
+    contents: &str,
+    triples: &[&str],
+    env_keys: &[&str],
+    build_target: Option<&str>,
+) -> Result<Stripped, TomlError> {
+    let mut doc = contents.parse::<Document>()?;
+    let mut removed = Vec::new();
+    let root = doc.as_table_mut();
+    if let Some(targets) = root.get_mut("target").and_then(Item::as_table_like_mut) {
+        let before = removed.len();
+        for triple in triples {
+            remove_keys(targets, triple, TARGET_FIELDS, "target.", &mut removed);
+        }
+        if removed.len() > before && targets.is_empty() {
+            root.remove("target");
+        }
+    }
+    remove_keys(root, "env", env_keys, "", &mut removed);
+    let ours = |item: &Item| build_target.is_some() && item.as_str() == build_target;
+    if root
+        .get("build")
+        .and_then(|build| build.get("target"))
+        .map_or(false, ours)
+    {
+        remove_keys(root, "build", &["target"], "", &mut removed);
+    }
+    Ok(Stripped {
+        contents: doc.to_string(),
+        removed,
+    })
+}
+
+#[derive(Debug, Default)]
+struct Plan {
+    dirs: Vec<(PathBuf, DirPlan)>,
+    /// Dirs that are ours through and through.
+    whole_dirs: Vec<PathBuf>,
+    adopted: Vec<PathBuf>,
+    dot_cargo: Option<(PathBuf, Stripped)>,
+    gitignore: Option<(PathBuf, String)>,
+}
+
+impl Plan {
+    fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+            && self.whole_dirs.is_empty()
+            && self.dot_cargo.is_none()
+            && self.gitignore.is_none()
+    }
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, Error> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(cause) => Err(Error::ReadFailed {
+            path: path.to_owned(),
+            cause,
+        }),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn apple_project_dir(config: &Config) -> Option<(PathBuf, bool)> {
+    Some((config.apple().project_dir(), config.apple().adopted()))
+}
+
+// Without an Apple config to go by, this reads the `apple` section of
+// `mobile.toml` itself.
+#[cfg(not(target_os = "macos"))]
+fn apple_project_dir(config: &Config) -> Option<(PathBuf, bool)> {
+    let raw = fs::read_to_string(config.path())
+        .ok()?
+        .parse::<toml::Value>()
+        .ok()?;
+    let field = |key: &str| {
+        raw.get("apple")
+            .and_then(|apple| apple.get(key))
+            .and_then(toml::Value::as_str)
+            .map(ToOwned::to_owned)
+    };
+    Some(match field("existing-project-dir") {
+        Some(dir) => (config.app().prefix_path(dir), true),
+        None => (
+            config.app().prefix_path(
+                field("project-dir").unwrap_or_else(|| DEFAULT_APPLE_PROJECT_DIR.to_owned()),
+            ),
+            false,
+        ),
+    })
+}
+
+/// Each project dir, and whether it's adopted.
+fn project_dirs(config: &Config) -> impl Iterator<Item = (PathBuf, bool)> {
+    std::iter::once((config.android().project_dir(), config.android().adopted()))
+        .chain(apple_project_dir(config))
+}
+
+fn plan(config: &Config) -> Result<Plan, Error> {
+    let root_dir = config.app().root_dir();
+    let mut plan = Plan::default();
+    for (dir, adopted) in project_dirs(config) {
+        if adopted {
+            plan.adopted.push(dir);
+        } else if dir.is_dir() {
+            let dir_plan = scan_dir(&dir)?;
+            plan.dirs.push((dir, dir_plan));
+        }
+    }
+    for dir in &[
+        root_dir.join(adopt::DIR_NAME),
+        root_dir.join(tools::PROJECT_TOOLS_DIR),
+    ] {
+        if dir.is_dir() {
+            plan.whole_dirs.push(dir.clone());
+        }
+    }
+
+    let dot_cargo_path = root_dir.join(".cargo/config.toml");
+    if let Some(contents) = read_optional(&dot_cargo_path)? {
+        let triples = AndroidTarget::all_with_opt_in()
+            .map(|target| target.triple)
+            .collect::<Vec<_>>();
+        let env_keys = config
+            .env()
+            .iter()
+            .flat_map(|env| env.keys())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let host = util::host_target_triple()
+            .map_err(|err| log::warn!("leaving `build.target` alone, since {:?}", err))
+            .ok();
+        let stripped =
+            strip_dot_cargo(&contents, &triples, &env_keys, host.as_deref()).map_err(|cause| {
+                Error::DotCargoParseFailed {
+                    path: dot_cargo_path.clone(),
+                    cause,
+                }
+            })?;
+        if !stripped.removed.is_empty() {
+            plan.dot_cargo = Some((dot_cargo_path, stripped));
+        }
+    }
+
+    let gitignore_path = root_dir.join(".gitignore");
+    if let Some(stripped) = read_optional(&gitignore_path)?
+        .as_deref()
+        .and_then(tools::strip_ignore_entry)
+    {
+        plan.gitignore = Some((gitignore_path, stripped));
+    }
+    Ok(plan)
+}
+
+fn print_list(heading: &str, root_dir: &Path, paths: &[&PathBuf]) {
+    if paths.is_empty() {
+        return;
+    }
+    println!("    {}:", heading);
+    // Everything gets listed, however long that makes this, since saying yes
+    // to deleting something you didn't see isn't saying yes.
+    for path in paths {
+        println!(
+            "      {}",
+            util::unprefix_path(root_dir, path)
+                .unwrap_or_else(|_| path.to_path_buf())
+                .display()
+        );
+    }
+}
+
+fn print_plan(root_dir: &Path, plan: &Plan) {
+    let rel = |path: &Path| util::unprefix_path(root_dir, path).unwrap_or_else(|_| path.to_owned());
+    println!("This will remove:");
+    for (dir, dir_plan) in &plan.dirs {
+        if dir_plan.whole {
+            println!(
+                "  {} (all of it, since it has no generation manifest to tell edited files apart)",
+                rel(dir).display()
+            );
+        } else {
+            println!("  from {}:", rel(dir).display());
+            print_list(
+                "generated files",
+                root_dir,
+                &dir_plan.generated.iter().collect::<Vec<_>>(),
+            );
+            print_list(
+                "along with these, which we didn't generate",
+                root_dir,
+                &dir_plan.other.iter().collect::<Vec<_>>(),
+            );
+        }
+    }
+    for dir in &plan.whole_dirs {
+        println!("  {}", rel(dir).display());
+    }
+    if let Some((path, stripped)) = &plan.dot_cargo {
+        println!("  from {}:", rel(path).display());
+        for key in &stripped.removed {
+            println!("    {}", key);
+        }
+    }
+    if let Some((path, _)) = &plan.gitignore {
+        println!(
+            "  the `{}` entry from {}",
+            tools::PROJECT_TOOLS_DIR,
+            rel(path).display()
+        );
+    }
+    print_left_alone(root_dir, plan);
+}
+
+fn print_left_alone(root_dir: &Path, plan: &Plan) {
+    let edited = plan
+        .dirs
+        .iter()
+        .flat_map(|(_, dir_plan)| &dir_plan.edited)
+        .collect::<Vec<_>>();
+    let kept = plan
+        .dirs
+        .iter()
+        .flat_map(|(_, dir_plan)| &dir_plan.kept)
+        .collect::<Vec<_>>();
+    if edited.is_empty() && kept.is_empty() && plan.adopted.is_empty() {
+        return;
+    }
+    println!("Left alone:");
+    print_list("edited since they were generated", root_dir, &edited);
+    print_list("not generated by us", root_dir, &kept);
+    print_list(
+        "your own projects, from `existing-project-dir`",
+        root_dir,
+        &plan.adopted.iter().collect::<Vec<_>>(),
+    );
+}
+
+fn remove(path: &Path) -> Result<(), Error> {
+    let result = if path.is_dir()
+        && !path
+            .symlink_metadata()
+            .map_or(false, |meta| meta.file_type().is_symlink())
+    {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    match result {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Error::RemoveFailed {
+            path: path.to_owned(),
+            cause: err,
+        }),
+        _ => Ok(()),
+    }
+}
+
+// Removes whatever dirs in `dir` (and `dir` itself) are left empty, and then
+// its ancestors up to `root_dir` if that empties them too.
+fn prune(dir: &Path, root_dir: &Path) {
+    let is_empty =
+        |dir: &Path| fs::read_dir(dir).map_or(false, |mut entries| entries.next().is_none());
+    for entry in walkdir::WalkDir::new(dir)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_dir())
+    {
+        if is_empty(entry.path()) {
+            let _ = fs::remove_dir(entry.path());
+        }
+    }
+    for ancestor in dir.ancestors().skip(1) {
+        if ancestor == root_dir || !ancestor.starts_with(root_dir) || !is_empty(ancestor) {
+            break;
+        }
+        let _ = fs::remove_dir(ancestor);
+    }
+}
+
+fn apply(root_dir: &Path, plan: &Plan) -> Result<(), Error> {
+    for (dir, dir_plan) in &plan.dirs {
+        for path in dir_plan.removals() {
+            remove(path)?;
+        }
+        prune(dir, root_dir);
+    }
+    for dir in &plan.whole_dirs {
+        remove(dir)?;
+        prune(dir, root_dir);
+    }
+    if let Some((path, stripped)) = &plan.dot_cargo {
+        if stripped.is_empty() {
+            remove(path)?;
+            if let Some(dir) = path.parent() {
+                prune(dir, root_dir);
+            }
+        } else {
+            fs::write(path, &stripped.contents).map_err(|cause| Error::WriteFailed {
+                path: path.clone(),
+                cause,
+            })?;
+        }
+    }
+    if let Some((path, contents)) = &plan.gitignore {
+        fs::write(path, contents).map_err(|cause| Error::WriteFailed {
+            path: path.clone(),
+            cause,
+        })?;
+    }
+    Ok(())
+}
+
+fn confirm(id: &str, key: &str, msg: &str, non_interactive: NonInteractive) -> Result<bool, Error> {
+    // `--non-interactive` goes with the default, which is always no here.
+    if non_interactive.yes() {
+        return Ok(false);
+    }
+    Ok(prompt::yes_no(id, key, msg, Some(prompt::YesOrNo::No))
+        .map_err(Error::PromptFailed)?
+        .unwrap_or(prompt::YesOrNo::No)
+        .yes())
+}
+
+/// `remove_config` deletes `mobile.toml` without asking. Otherwise, it's only
+/// deleted if you answer the prompt yourself; neither `--non-interactive` nor
+/// `CARGO_MOBILE_ASSUME_YES` will do.
+pub fn exec(
+    cwd: impl AsRef<Path>,
+    non_interactive: NonInteractive,
+    dry_run: bool,
+    remove_config: bool,
+) -> Result<(), Error> {
+    let config = Config::load(cwd).map_err(Error::LoadFailed)?;
+    let root_dir = config.app().root_dir();
+    let config_path = root_dir.join(config::file_name());
+    let plan = plan(&config)?;
+    if plan.is_empty() {
+        println!("There's nothing generated to remove.");
+        print_left_alone(root_dir, &plan);
+    } else {
+        print_plan(root_dir, &plan);
+    }
+    if dry_run {
+        if remove_config {
+            println!(
+                "Nothing was changed, since this was a dry run. {} would be removed too.",
+                config::file_name()
+            );
+        } else {
+            println!(
+                "Nothing was changed, since this was a dry run. {} would be offered up separately.",
+                config::file_name()
+            );
+        }
+        return Ok(());
+    }
+    if !plan.is_empty() {
+        if confirm(
+            "uninstall.confirm",
+            prompt::ASSUME_YES_ENV_VAR,
+            "Remove everything listed above?",
+            non_interactive,
+        )? {
+            apply(root_dir, &plan)?;
+            println!("Removed everything listed.");
+        } else {
+            println!("Nothing was removed.");
+            if non_interactive.yes() {
+                println!(
+                    "`--non-interactive` says no to this; run without it and set `{}=1` to go ahead without a prompt.",
+                    prompt::ASSUME_YES_ENV_VAR
+                );
+            }
+            return Ok(());
+        }
+    }
+    let remove_config = if remove_config {
+        true
+    } else if prompt::assume_yes() {
+        // Your config is the one thing here that can't be regenerated, so a
+        // blanket yes isn't enough.
+        println!(
+            "Not deleting {} on account of `{}`; pass `--remove-config` for that.",
+            config::file_name(),
+            prompt::ASSUME_YES_ENV_VAR
+        );
+        false
+    } else {
+        confirm(
+            "uninstall.remove-config",
+            "--remove-config",
+            &format!(
+                "Delete {} too? It's not needed by anything but cargo-mobile",
+                config::file_name()
+            ),
+            non_interactive,
+        )?
+    };
+    if remove_config {
+        remove(&config_path)?;
+        println!("Removed {:?}.", config_path);
+    } else {
+        println!("Kept {:?}.", config_path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    static TRIPLES: &[&str] = &["aarch64-linux-android", "armv7-linux-androideabi"];
+
+    #[test]
+    fn strips_only_our_entries() {
+        let contents = r#"# Kept by hand.
+[build]
+target = "x86_64-unknown-linux-gnu"
+jobs = 4
+
+[target.aarch64-linux-android]
+ar = "/ndk/llvm-ar"
+linker = "/ndk/aarch64-linux-android21-clang"
+rustflags = ["-Clink-arg=-landroid"]
+runner = "my-runner"
+
+[target.armv7-linux-androideabi]
+ar = "/ndk/llvm-ar"
+linker = "/ndk/armv7a-linux-androideabi21-clang"
+rustflags = []
+
+[target.x86_64-unknown-linux-gnu]
+linker = "clang"
+
+[env]
+FROM_MOBILE_TOML = "1"
+# Mine.
+HAND_WRITTEN = "2"
+
+[alias]
+b = "build"
+"#;
+        let stripped = strip_dot_cargo(
+            contents,
+            TRIPLES,
+            &["FROM_MOBILE_TOML"],
+            Some("x86_64-unknown-linux-gnu"),
+        )
+        .unwrap();
+        assert_eq!(
+            stripped.contents,
+            r#"# Kept by hand.
+[build]
+jobs = 4
+
+[target.aarch64-linux-android]
+runner = "my-runner"
+
+[target.x86_64-unknown-linux-gnu]
+linker = "clang"
+
+[env]
+# Mine.
+HAND_WRITTEN = "2"
+
+[alias]
+b = "build"
+"#
+        );
+        assert_eq!(
+            stripped.removed,
+            vec![
+                "target.aarch64-linux-android.ar",
+                "target.aarch64-linux-android.linker",
+                "target.aarch64-linux-android.rustflags",
+                "target.armv7-linux-androideabi.ar",
+                "target.armv7-linux-androideabi.linker",
+                "target.armv7-linux-androideabi.rustflags",
+                "env.FROM_MOBILE_TOML",
+                "build.target",
+            ]
+        );
+    }
+
+    #[test]
+    fn strips_what_init_wrote_down_to_nothing() {
+        let contents = r#"[build]
+target = "x86_64-apple-darwin"
+
+[target.aarch64-linux-android]
+ar = "/ndk/llvm-ar"
+linker = "/ndk/aarch64-linux-android21-clang"
+rustflags = []
+"#;
+        let stripped =
+            strip_dot_cargo(contents, TRIPLES, &[], Some("x86_64-apple-darwin")).unwrap();
+        assert!(stripped.is_empty(), "{:?}", stripped.contents);
+    }
+
+    #[test]
+    fn leaves_a_build_target_that_isnt_ours() {
+        let contents = "[build]\ntarget = \"wasm32-unknown-unknown\"\n";
+        let stripped =
+            strip_dot_cargo(contents, TRIPLES, &[], Some("x86_64-apple-darwin")).unwrap();
+        assert_eq!(stripped.contents, contents);
+        assert!(stripped.removed.is_empty());
+        // Without knowing the host, we can't say it's ours.
+        let contents = "[build]\ntarget = \"x86_64-apple-darwin\"\n";
+        assert_eq!(
+            strip_dot_cargo(contents, TRIPLES, &[], None)
+                .unwrap()
+                .contents,
+            contents
+        );
+    }
+
+    #[test]
+    fn leaves_unrelated_configs_alone() {
+        let contents = "# Nothing of ours.\n[alias]\nb = \"build\"\n\n[env]\nFOO = \"1\"\n";
+        let stripped = strip_dot_cargo(contents, TRIPLES, &["BAR"], None).unwrap();
+        assert_eq!(stripped.contents, contents);
+        assert!(stripped.removed.is_empty());
+    }
+
+    #[test]
+    fn handles_inline_and_dotted_tables() {
+        let contents = r#"target.aarch64-linux-android.linker = "/ndk/clang"
+env = { FROM_MOBILE_TOML = "1", OTHER = "2" }
+"#;
+        let stripped = strip_dot_cargo(contents, TRIPLES, &["FROM_MOBILE_TOML"], None).unwrap();
+        assert_eq!(
+            stripped.removed,
+            vec![
+                "target.aarch64-linux-android.linker",
+                "env.FROM_MOBILE_TOML"
+            ]
+        );
+        assert!(!stripped.contents.contains("FROM_MOBILE_TOML"));
+        assert!(!stripped.contents.contains("/ndk/clang"));
+        assert!(stripped.contents.contains(r#"OTHER = "2""#));
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        assert!(strip_dot_cargo("[target\n", TRIPLES, &[], None).is_err());
+    }
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn scans_dirs_with_manifests() {
+        let dir = TempDirGuard::new().unwrap();
+        let mut manifest = Manifest::default();
+        for (rel, contents) in &[
+            ("build.gradle", "generated"),
+            ("app/src/main/AndroidManifest.xml", "generated"),
+            ("app/src/main/MainActivity.kt", "generated"),
+        ] {
+            write(&dir.join(rel), contents);
+            manifest.record_contents(Path::new(rel), contents.as_bytes());
+        }
+        manifest.save(&dir).unwrap();
+        write(&dir.join("app/src/main/MainActivity.kt"), "edited");
+        write(&dir.join("app/src/main/assets/logo.png"), "copied");
+        write(&dir.join("app/src/main/Added.kt"), "added by hand");
+        write(&dir.join("app/build/outputs/app.apk"), "built");
+        // A source package that just happens to be called `build`.
+        write(&dir.join("app/src/main/com/example/build/Tool.kt"), "mine");
+        write(
+            &dir.join("app.xcodeproj/xcuserdata/me.xcuserdatad/x.plist"),
+            "ide",
+        );
+        // An asset pack, which has a manifest of its own.
+        let pack = dir.join("levels");
+        let mut pack_manifest = Manifest::default();
+        write(&pack.join("build.gradle"), "pack");
+        pack_manifest.record_contents(Path::new("build.gradle"), b"pack");
+        pack_manifest.save(&pack).unwrap();
+
+        let plan = scan_dir(&dir).unwrap();
+        assert!(!plan.whole);
+        assert_eq!(
+            plan.generated,
+            vec![
+                dir.join("app/src/main/AndroidManifest.xml"),
+                dir.join("build.gradle"),
+                pack.join("build.gradle"),
+            ]
+        );
+        assert_eq!(plan.edited, vec![dir.join("app/src/main/MainActivity.kt")]);
+        assert_eq!(
+            plan.other,
+            vec![
+                Manifest::path(&dir),
+                dir.join("app/build"),
+                dir.join("app.xcodeproj/xcuserdata"),
+                Manifest::path(&pack),
+            ]
+        );
+        assert_eq!(
+            plan.kept,
+            vec![
+                dir.join("app/src/main/Added.kt"),
+                dir.join("app/src/main/assets/logo.png"),
+                dir.join("app/src/main/com/example/build/Tool.kt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_dirs_without_manifests_whole() {
+        let dir = TempDirGuard::new().unwrap();
+        write(&dir.join("build.gradle"), "generated long ago");
+        assert_eq!(
+            scan_dir(&dir).unwrap(),
+            DirPlan {
+                whole: true,
+                generated: vec![dir.to_path_buf()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn applying_keeps_edited_files_and_prunes_empty_dirs() {
+        let root = TempDirGuard::new().unwrap();
+        let dir = root.join("gen/android/game");
+        let mut manifest = Manifest::default();
+        for rel in &[
+            "settings.gradle",
+            "app/build.gradle",
+            "app/src/main/Main.kt",
+        ] {
+            write(&dir.join(rel), "generated");
+            manifest.record_contents(Path::new(rel), b"generated");
+        }
+        manifest.save(&dir).unwrap();
+        write(&dir.join("app/src/main/Main.kt"), "edited");
+        write(&dir.join("app/src/main/Added.kt"), "added by hand");
+        let plan = Plan {
+            dirs: vec![(dir.clone(), scan_dir(&dir).unwrap())],
+            ..Default::default()
+        };
+        apply(&root, &plan).unwrap();
+        assert!(dir.join("app/src/main/Main.kt").is_file());
+        assert!(dir.join("app/src/main/Added.kt").is_file());
+        assert!(!dir.join("settings.gradle").exists());
+        assert!(!dir.join("app/build.gradle").exists());
+
+        // Once nothing's left, the dirs above it go too, but never the root.
+        fs::remove_file(dir.join("app/src/main/Main.kt")).unwrap();
+        fs::remove_file(dir.join("app/src/main/Added.kt")).unwrap();
+        apply(&root, &plan).unwrap();
+        assert!(!root.join("gen").exists());
+        assert!(root.is_dir());
+    }
+}
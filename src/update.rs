@@ -48,6 +48,8 @@ impl Display for Error {
     }
 }
 
+pub(crate) static REPO_URL: &str = "https://github.com/BrainiumLLC/cargo-mobile";
+
 pub(crate) fn cargo_mobile_repo() -> Result<Repo, util::NoHomeDir> {
     Repo::checkouts_dir("cargo-mobile")
 }
@@ -75,10 +77,10 @@ pub fn update(wrapper: &TextWrapper) -> Result<(), Error> {
             path: marker.to_owned(),
             cause,
         })?;
-        repo.update("https://github.com/BrainiumLLC/cargo-mobile")
-            .map_err(Error::UpdateFailed)?;
+        repo.update(REPO_URL).map_err(Error::UpdateFailed)?;
         println!("Installing updated `cargo-mobile`...");
-        bossy::Command::impure_parse("cargo install --force --path")
+        util::toolchain::cargo_impure()
+            .with_parsed_args("install --force --path")
             .with_arg(repo.path())
             .with_parsed_args("--no-default-features --features")
             // Using `with_arg` instead of `with_args`/`with_parsed_args` here
@@ -1,9 +1,20 @@
+use super::toolchain;
 use crate::env::ExplicitEnv;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Where cargo puts build output for the crate at `root_dir`, which
+/// `CARGO_TARGET_DIR` can move.
+pub fn cargo_target_dir(root_dir: &Path) -> PathBuf {
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(|dir| root_dir.join(dir))
+        .unwrap_or_else(|| root_dir.join("target"))
+}
 
 #[derive(Debug)]
 pub struct CargoCommand<'a> {
     subcommand: &'a str,
+    toolchain: Option<String>,
+    build_std: bool,
     verbose: bool,
     package: Option<&'a str>,
     manifest_path: Option<PathBuf>,
@@ -11,12 +22,15 @@ pub struct CargoCommand<'a> {
     no_default_features: bool,
     features: Option<&'a [String]>,
     release: bool,
+    message_format: Option<&'a str>,
 }
 
 impl<'a> CargoCommand<'a> {
     pub fn new(subcommand: &'a str) -> Self {
         Self {
             subcommand,
+            toolchain: Default::default(),
+            build_std: Default::default(),
             verbose: Default::default(),
             package: Default::default(),
             manifest_path: Default::default(),
@@ -24,9 +38,23 @@ impl<'a> CargoCommand<'a> {
             no_default_features: Default::default(),
             features: Default::default(),
             release: Default::default(),
+            message_format: Default::default(),
         }
     }
 
+    /// Runs cargo from `toolchain` via `rustup run`, rather than whichever
+    /// one is running us.
+    pub fn with_toolchain(mut self, toolchain: Option<String>) -> Self {
+        self.toolchain = toolchain;
+        self
+    }
+
+    /// Builds the standard library too, which tier 3 targets need.
+    pub fn with_build_std(mut self, build_std: bool) -> Self {
+        self.build_std = build_std;
+        self
+    }
+
     pub fn with_verbose(mut self, verbose: bool) -> Self {
         self.verbose = verbose;
         self
@@ -62,6 +90,11 @@ impl<'a> CargoCommand<'a> {
         self
     }
 
+    pub fn with_message_format(mut self, message_format: Option<&'a str>) -> Self {
+        self.message_format = message_format;
+        self
+    }
+
     fn into_command_inner(self, mut command: bossy::Command) -> bossy::Command {
         command.add_arg(self.subcommand);
         if self.verbose {
@@ -96,14 +129,28 @@ impl<'a> CargoCommand<'a> {
         if self.release {
             command.add_arg("--release");
         }
+        if self.build_std {
+            command.add_arg("-Zbuild-std");
+        }
+        if let Some(message_format) = self.message_format {
+            command.add_arg(format!("--message-format={}", message_format));
+        }
         command
     }
 
     pub fn into_command_impure(self) -> bossy::Command {
-        self.into_command_inner(bossy::Command::impure("cargo"))
+        let command = match &self.toolchain {
+            Some(name) => toolchain::cargo_from(bossy::Command::impure("rustup"), name),
+            None => toolchain::cargo_impure(),
+        };
+        self.into_command_inner(command)
     }
 
     pub fn into_command_pure(self, env: &impl ExplicitEnv) -> bossy::Command {
-        self.into_command_inner(bossy::Command::pure("cargo").with_env_vars(env.explicit_env()))
+        let command = match &self.toolchain {
+            Some(name) => toolchain::cargo_from(bossy::Command::pure("rustup"), name),
+            None => toolchain::cargo_pure(),
+        };
+        self.into_command_inner(command.with_env_vars(env.explicit_env()))
     }
 }
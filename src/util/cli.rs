@@ -1,7 +1,14 @@
-use crate::{opts, util};
+use crate::{
+    config, opts,
+    util::{self, crash, events, logging, root, timings, watchdog},
+};
 use colored::Colorize as _;
 use once_cell_regex::exports::once_cell::sync::Lazy;
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    io::{self, IsTerminal as _},
+    path::PathBuf,
+};
 use structopt::{
     clap::{self, AppSettings},
     StructOpt,
@@ -30,7 +37,7 @@ pub static VERSION_LONG: Lazy<String> = Lazy::new(|| match util::installed_commi
     }
 });
 
-#[derive(Clone, Copy, Debug, StructOpt)]
+#[derive(Clone, Debug, StructOpt)]
 pub struct GlobalFlags {
     #[structopt(
         short = "v",
@@ -49,6 +56,50 @@ pub struct GlobalFlags {
         parse(from_flag = opts::NonInteractive::from_bool),
     )]
     pub non_interactive: opts::NonInteractive,
+    #[structopt(
+        long = "timings",
+        help = "Print how long each step took once finished",
+        global = true
+    )]
+    pub timings: bool,
+    #[structopt(
+        long = "color",
+        value_name = "when",
+        help = "Whether to color output; `auto` colors only when printing to a terminal and `NO_COLOR` isn't set",
+        global = true,
+        default_value = "auto",
+        possible_values = &opts::ColorChoice::variants(),
+        case_insensitive = true,
+    )]
+    pub color: opts::ColorChoice,
+    #[structopt(
+        long = "log-file",
+        value_name = "path",
+        help = "Also write debug-level logs to this file, whatever the verbosity (or set `CARGO_MOBILE_LOG_FILE`)",
+        global = true,
+        parse(from_os_str)
+    )]
+    pub log_file: Option<PathBuf>,
+    #[structopt(
+        long = "allow-root",
+        help = "Run even as root, despite the files it'd leave behind being owned by root",
+        global = true
+    )]
+    pub allow_root: bool,
+    #[structopt(
+        long = "config",
+        value_name = "key=value",
+        help = "Override a value from `mobile.toml` for this run (repeatable), i.e. `--config android.min-sdk-version=26`",
+        global = true,
+        number_of_values = 1
+    )]
+    pub config_overrides: Vec<config::overrides::Override>,
+    #[structopt(
+        long = "no-timeouts",
+        help = "Never stop external tools for taking too long, whatever `[timeouts]` says",
+        global = true
+    )]
+    pub no_timeouts: bool,
 }
 
 #[derive(Clone, Copy, Debug, StructOpt)]
@@ -71,6 +122,37 @@ pub struct ReinstallDeps {
     pub reinstall_deps: opts::ReinstallDeps,
 }
 
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct Guided {
+    #[structopt(
+        long = "guided",
+        help = "Explain each prompt, and review every answer before anything is written",
+        parse(from_flag = opts::Guided::from_bool),
+    )]
+    pub guided: opts::Guided,
+}
+
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct Conflicts {
+    #[structopt(
+        long = "force",
+        help = "Overwrite generated files you've edited when their templates changed",
+        conflicts_with = "keep-all"
+    )]
+    pub force: bool,
+    #[structopt(
+        long = "keep-all",
+        help = "Keep generated files you've edited, even when their templates changed"
+    )]
+    pub keep_all: bool,
+}
+
+impl Conflicts {
+    pub fn conflicts(self) -> opts::Conflicts {
+        opts::Conflicts::from_flags(self.force, self.keep_all)
+    }
+}
+
 #[derive(Clone, Copy, Debug, StructOpt)]
 pub struct Profile {
     #[structopt(
@@ -93,6 +175,17 @@ pub struct Filter {
     pub filter: Option<opts::FilterLevel>,
 }
 
+#[derive(Clone, Debug, StructOpt)]
+pub struct LaunchArgs {
+    #[structopt(
+        long = "launch-arg",
+        help = "Pass an argument to the app when launching it (can be repeated)",
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    pub launch_args: Vec<String>,
+}
+
 pub type TextWrapper = textwrap::Wrapper<'static, textwrap::NoHyphenation>;
 
 pub mod colors {
@@ -168,29 +261,77 @@ impl Report {
         self.label.exit_code()
     }
 
-    fn format(&self, wrapper: &TextWrapper) -> String {
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
+
+    pub fn details(&self) -> &str {
+        &self.details
+    }
+
+    /// Formats the report, wrapping it with `wrapper` if there is one. Without
+    /// one, every item is left on a single line, which is what you want when
+    /// output is going to a log file instead of a terminal.
+    fn format(&self, wrapper: Option<&TextWrapper>, colorize: bool) -> String {
         static INDENT: &str = "    ";
-        let head = if colored::control::SHOULD_COLORIZE.should_colorize() {
-            wrapper.fill(&format!(
+        let head = if colorize {
+            format!(
                 "{} {}",
                 format!("{}:", self.label.as_str())
                     .color(self.label.color())
                     .bold(),
                 self.msg.color(self.label.color())
-            ))
+            )
         } else {
-            wrapper.fill(&format!("{}: {}", self.label.as_str(), &self.msg))
+            format!("{}: {}", self.label.as_str(), &self.msg)
         };
-        let wrapper = wrapper
-            .clone()
-            .initial_indent(INDENT)
-            .subsequent_indent(INDENT);
-        format!("{}\n{}\n", head, wrapper.fill(&self.details))
+        if let Some(wrapper) = wrapper {
+            let details_wrapper = wrapper
+                .clone()
+                .initial_indent(INDENT)
+                .subsequent_indent(INDENT);
+            format!(
+                "{}\n{}\n",
+                wrapper.fill(&head),
+                details_wrapper.fill(&self.details)
+            )
+        } else {
+            let details = self
+                .details
+                .lines()
+                .map(|line| format!("{}{}\n", INDENT, line))
+                .collect::<String>();
+            format!("{}\n{}", head, details)
+        }
     }
 
     pub fn print(&self, wrapper: &TextWrapper) {
-        let s = self.format(wrapper);
-        if matches!(self.label, Label::Error) {
+        if events::enabled() {
+            events::emit(&events::Event::Report {
+                label: self.label.as_str(),
+                msg: &self.msg,
+                details: &self.details,
+            });
+            return;
+        }
+        let to_stderr = matches!(self.label, Label::Error);
+        let tty = if to_stderr {
+            io::stderr().is_terminal()
+        } else {
+            io::stdout().is_terminal()
+        };
+        // The terminal could've been resized since the wrapper was made, so
+        // we use the current width instead.
+        let wrapper = tty.then(|| {
+            let mut wrapper = wrapper.clone();
+            wrapper.width = textwrap::termwidth();
+            wrapper
+        });
+        let s = self.format(
+            wrapper.as_ref(),
+            colored::control::SHOULD_COLORIZE.should_colorize(),
+        );
+        if to_stderr {
             eprint!("{}", s)
         } else {
             print!("{}", s)
@@ -200,6 +341,12 @@ impl Report {
 
 pub trait Reportable: Debug {
     fn report(&self) -> Report;
+
+    /// For failures the exit code already says everything about, so nothing
+    /// gets printed.
+    fn silent_exit_code(&self) -> Option<i32> {
+        None
+    }
 }
 
 pub trait Exec: Debug + StructOpt {
@@ -220,31 +367,32 @@ fn get_args(name: &str) -> Vec<String> {
     args
 }
 
-fn init_logging(noise_level: opts::NoiseLevel) {
-    use env_logger::{Builder, Env};
-    let default_level = match noise_level {
-        opts::NoiseLevel::Polite => "warn",
-        opts::NoiseLevel::LoudAndProud => {
-            "cargo_mobile=info,cargo_android=info,cargo_apple=info,bossy=info,hit=info"
-        }
-        opts::NoiseLevel::FranklyQuitePedantic => {
-            "info,cargo_mobile=debug,cargo_android=debug,cargo_apple=debug,bossy=debug,hit=debug"
+fn init_color(choice: opts::ColorChoice) {
+    match choice {
+        opts::ColorChoice::Always => colored::control::set_override(true),
+        opts::ColorChoice::Never => colored::control::set_override(false),
+        opts::ColorChoice::Auto => {
+            if std::env::var_os("NO_COLOR").is_some() || !io::stdout().is_terminal() {
+                colored::control::set_override(false)
+            }
         }
-    };
-    let env = Env::default().default_filter_or(default_level);
-    Builder::from_env(env).init();
+    }
 }
 
 #[derive(Debug)]
 pub enum Exit {
     Report(Report),
+    Silent(i32),
     Clap(clap::Error),
 }
 
 impl Exit {
     fn report(reportable: impl Reportable) -> Self {
         log::info!("exiting with {:#?}", reportable);
-        Self::Report(reportable.report())
+        match reportable.silent_exit_code() {
+            Some(code) => Self::Silent(code),
+            None => Self::Report(reportable.report()),
+        }
     }
 
     fn do_the_thing(self, wrapper: TextWrapper) -> ! {
@@ -253,24 +401,95 @@ impl Exit {
                 report.print(&wrapper);
                 std::process::exit(report.label.exit_code().into())
             }
+            Self::Silent(code) => std::process::exit(code),
             Self::Clap(err) => err.exit(),
         }
     }
 
     pub fn main(inner: impl FnOnce(&TextWrapper) -> Result<(), Self>) {
-        let wrapper = TextWrapper::with_splitter(textwrap::termwidth(), textwrap::NoHyphenation);
+        // When we're not printing to a terminal, hard line breaks would only
+        // get in the way of copying paths and commands out of logs.
+        let width = if io::stdout().is_terminal() {
+            textwrap::termwidth()
+        } else {
+            usize::MAX
+        };
+        let wrapper = TextWrapper::with_splitter(width, textwrap::NoHyphenation);
         if let Err(exit) = inner(&wrapper) {
             exit.do_the_thing(wrapper)
         }
     }
 }
 
+fn finish_timings(print: bool) {
+    let timings = timings::take();
+    if timings.is_empty() {
+        return;
+    }
+    if print && !events::enabled() {
+        println!("Timings:\n{}", timings);
+    }
+    match util::install_dir() {
+        Ok(install_dir) => {
+            let path = install_dir.join("last-timings.json");
+            if let Err(err) = timings.write_json(&path) {
+                log::warn!("failed to write timings to {:?}: {}", path, err);
+            }
+        }
+        Err(err) => log::warn!("failed to write timings: {}", err),
+    }
+}
+
 pub fn exec<E: Exec>(name: &str) {
     Exit::main(|wrapper| {
         let args = get_args(name);
+        crash::install_hook(args.clone());
         let input = E::from_iter_safe(&args).map_err(Exit::Clap)?;
-        init_logging(input.global_flags().noise_level);
+        let flags = input.global_flags();
+        init_color(flags.color);
+        let log_file = logging::log_file(flags.log_file.as_deref());
+        logging::init(flags.noise_level, log_file.as_deref(), &args);
         log::debug!("raw args: {:#?}", args);
-        input.exec(wrapper).map_err(Exit::report)
+        config::overrides::set(flags.config_overrides.clone());
+        if flags.no_timeouts {
+            watchdog::disable();
+        }
+        if !flags.allow_root {
+            root::check_not_root(util::install_dir().ok().as_deref()).map_err(Exit::report)?;
+        }
+        crate::templating::install_templates_if_missing();
+        util::sweep_stale_temp_dirs();
+        let result = input.exec(wrapper).map_err(Exit::report);
+        finish_timings(flags.timings);
+        result
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> Report {
+        Report::error(
+            "Failed to build the thing",
+            "Make sure the path exists and try again.",
+        )
+    }
+
+    #[test]
+    fn report_wraps_in_terminal() {
+        let wrapper = TextWrapper::with_splitter(30, textwrap::NoHyphenation);
+        assert_eq!(
+            sample().format(Some(&wrapper), false),
+            "error: Failed to build the\nthing\n    Make sure the path exists\n    and try again.\n"
+        );
+    }
+
+    #[test]
+    fn report_is_unwrapped_otherwise() {
+        assert_eq!(
+            sample().format(None, false),
+            "error: Failed to build the thing\n    Make sure the path exists and try again.\n"
+        );
+    }
+}
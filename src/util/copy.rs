@@ -0,0 +1,241 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CopyDirError {
+    #[error("Failed to walk directory {src:?}: {source}")]
+    WalkFailed {
+        src: PathBuf,
+        source: walkdir::Error,
+    },
+    #[error("Failed to remove {path:?} to make room for a copy: {source}")]
+    RemoveFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to remove {path:?}, which is gone from the source dir: {source}")]
+    PruneFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to create directory {path:?}: {source}")]
+    CreateDirFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to copy {src:?} to {dest:?}: {source}")]
+    CopyFailed {
+        src: PathBuf,
+        dest: PathBuf,
+        source: io::Error,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CopyStats {
+    pub copied: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+// We consider a file unchanged if the copy is the same size and at least as
+// new as the original, which saves us from reading every file in large asset
+// trees. Since copying bumps the copy's mtime, this holds after any copy.
+fn unchanged(src: &fs::Metadata, dest: &Path) -> bool {
+    fs::metadata(dest)
+        .ok()
+        .filter(|dest| dest.is_file() && dest.len() == src.len())
+        .and_then(|dest| dest.modified().ok().zip(src.modified().ok()))
+        .map(|(dest, src)| dest >= src)
+        .unwrap_or(false)
+}
+
+/// Recursively copies `src` to `dest`, skipping files that haven't changed
+/// since they were last copied. If `dest` is a symlink (i.e. from a previous
+/// symlink-based setup), it's replaced with a real directory.
+pub fn copy_dir_if_changed(
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+) -> Result<CopyStats, CopyDirError> {
+    let src = src.as_ref();
+    let dest = dest.as_ref();
+    if fs::symlink_metadata(dest)
+        .map(|meta| meta.file_type().is_symlink())
+        .unwrap_or(false)
+    {
+        fs::remove_file(dest).map_err(|source| CopyDirError::RemoveFailed {
+            path: dest.to_owned(),
+            source,
+        })?;
+    }
+    let mut stats = CopyStats::default();
+    for entry in walkdir::WalkDir::new(src).follow_links(true) {
+        let entry = entry.map_err(|source| CopyDirError::WalkFailed {
+            src: src.to_owned(),
+            source,
+        })?;
+        let rel = entry
+            .path()
+            .strip_prefix(src)
+            .expect("developer error: walked path wasn't prefixed by its root");
+        let target = dest.join(rel);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target).map_err(|source| CopyDirError::CreateDirFailed {
+                path: target.clone(),
+                source,
+            })?;
+        } else {
+            let meta = entry
+                .metadata()
+                .map_err(|source| CopyDirError::WalkFailed {
+                    src: src.to_owned(),
+                    source,
+                })?;
+            if unchanged(&meta, &target) {
+                stats.unchanged += 1;
+            } else {
+                fs::copy(entry.path(), &target).map_err(|source| CopyDirError::CopyFailed {
+                    src: entry.path().to_owned(),
+                    dest: target.clone(),
+                    source,
+                })?;
+                stats.copied += 1;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// Copies each of `sources` into `dest` like `copy_dir_if_changed`, and then
+/// removes whatever's in `dest` that none of them have, so files deleted from
+/// a source don't live on in the copy.
+pub fn mirror_dirs_if_changed(
+    sources: &[impl AsRef<Path>],
+    dest: impl AsRef<Path>,
+) -> Result<CopyStats, CopyDirError> {
+    let dest = dest.as_ref();
+    let mut stats = CopyStats::default();
+    for src in sources {
+        let src_stats = copy_dir_if_changed(src, dest)?;
+        stats.copied += src_stats.copied;
+        stats.unchanged += src_stats.unchanged;
+    }
+    let in_sources = |rel: &Path| sources.iter().any(|src| src.as_ref().join(rel).exists());
+    let mut stale = Vec::new();
+    let mut entries = walkdir::WalkDir::new(dest).min_depth(1).into_iter();
+    while let Some(entry) = entries.next() {
+        let entry = entry.map_err(|source| CopyDirError::WalkFailed {
+            src: dest.to_owned(),
+            source,
+        })?;
+        let rel = entry
+            .path()
+            .strip_prefix(dest)
+            .expect("developer error: walked path wasn't prefixed by its root");
+        if !in_sources(rel) {
+            let is_dir = entry.file_type().is_dir();
+            if is_dir {
+                entries.skip_current_dir();
+            }
+            stale.push((entry.into_path(), is_dir));
+        }
+    }
+    for (path, is_dir) in stale {
+        let result = if is_dir {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        result.map_err(|source| CopyDirError::PruneFailed { path, source })?;
+        stats.removed += 1;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+
+    #[test]
+    fn copies_then_skips_unchanged() {
+        let root = TempDirGuard::new().unwrap();
+        let src = root.join("assets");
+        let dest = root.join("project/assets");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("a.txt"), "a").unwrap();
+        fs::write(src.join("nested/b.txt"), "bb").unwrap();
+
+        let first = copy_dir_if_changed(&src, &dest).unwrap();
+        assert_eq!(
+            first,
+            CopyStats {
+                copied: 2,
+                unchanged: 0,
+                removed: 0,
+            }
+        );
+        assert_eq!(fs::read_to_string(dest.join("nested/b.txt")).unwrap(), "bb");
+
+        let second = copy_dir_if_changed(&src, &dest).unwrap();
+        assert_eq!(
+            second,
+            CopyStats {
+                copied: 0,
+                unchanged: 2,
+                removed: 0,
+            }
+        );
+
+        fs::write(src.join("a.txt"), "changed").unwrap();
+        let third = copy_dir_if_changed(&src, &dest).unwrap();
+        assert_eq!(
+            third,
+            CopyStats {
+                copied: 1,
+                unchanged: 1,
+                removed: 0,
+            }
+        );
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "changed");
+    }
+
+    #[test]
+    fn mirroring_prunes_deleted_files() {
+        let root = TempDirGuard::new().unwrap();
+        let src = root.join("assets");
+        let dest = root.join("project/assets");
+        fs::create_dir_all(src.join("old")).unwrap();
+        fs::write(src.join("a.txt"), "a").unwrap();
+        fs::write(src.join("b.txt"), "b").unwrap();
+        fs::write(src.join("old/c.txt"), "c").unwrap();
+        mirror_dirs_if_changed(&[&src], &dest).unwrap();
+
+        fs::remove_file(src.join("b.txt")).unwrap();
+        fs::remove_dir_all(src.join("old")).unwrap();
+        let stats = mirror_dirs_if_changed(&[&src], &dest).unwrap();
+        assert_eq!(
+            stats,
+            CopyStats {
+                copied: 0,
+                unchanged: 1,
+                removed: 2,
+            }
+        );
+        assert!(dest.join("a.txt").is_file());
+        assert!(!dest.join("b.txt").exists());
+        assert!(!dest.join("old").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaces_symlink() {
+        let root = TempDirGuard::new().unwrap();
+        let src = root.join("assets");
+        let dest = root.join("link");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink(&src, &dest).unwrap();
+
+        copy_dir_if_changed(&src, &dest).unwrap();
+        assert!(!fs::symlink_metadata(&dest)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(dest.join("a.txt")).unwrap(), "a");
+    }
+}
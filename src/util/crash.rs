@@ -0,0 +1,528 @@
+//! A panic hook that leaves a bundle of breadcrumbs in the install dir, so
+//! issues about our own panics come with enough context to act on. Nothing is
+//! ever sent anywhere; whether it gets attached to an issue is up to the user.
+//!
+//! Everything the hook does is best-effort. Past formatting the panic itself,
+//! each step runs on a thread of its own, since a panic in a panic hook
+//! aborts, while a panic on another thread just means that part of the bundle
+//! goes missing.
+
+use super::{cli::VERSION_LONG, logging};
+use crate::{config::Config, os, update::REPO_URL};
+use std::{
+    backtrace::Backtrace,
+    fmt::Write as _,
+    fs,
+    io::{self, Write as _},
+    panic,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub static REDACTED: &str = "<redacted>";
+
+/// How long each tool gets to print its version.
+const TOOL_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Any key with one of these in its name has its value redacted.
+static SECRET_WORDS: &[&str] = &[
+    "password",
+    "passwd",
+    "passphrase",
+    "secret",
+    "token",
+    "credential",
+    "private",
+    "api-key",
+    "api_key",
+    "apikey",
+];
+
+/// What we ask for the version of, and how.
+static TOOLS: &[(&str, &[&str])] = &[
+    ("rustc", &["--version"]),
+    ("cargo", &["--version"]),
+    ("rustup", &["--version"]),
+    ("adb", &["version"]),
+    ("java", &["-version"]),
+];
+
+static APPLE_TOOLS: &[(&str, &[&str])] = &[
+    ("xcodebuild", &["-version"]),
+    ("xcodegen", &["--version"]),
+    ("ios-deploy", &["--version"]),
+    ("pod", &["--version"]),
+];
+
+/// Set once we've started reporting, so a panic while we're at it doesn't
+/// start another report.
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+pub fn looks_secret(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SECRET_WORDS.iter().any(|word| key.contains(word))
+}
+
+fn redact_all(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => table.values_mut().for_each(redact_all),
+        toml::Value::Array(values) => values.iter_mut().for_each(redact_all),
+        _ => *value = toml::Value::String(REDACTED.to_owned()),
+    }
+}
+
+/// Redacts every value in an `env` table, since that's where people put
+/// things like signing passwords, along with anything else under a key that
+/// looks secret. Keys are always kept, since they're what we need to see.
+pub fn redact_config(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                if key == "env" || looks_secret(key) {
+                    redact_all(value);
+                } else {
+                    redact_config(value);
+                }
+            }
+        }
+        toml::Value::Array(values) => values.iter_mut().for_each(redact_config),
+        _ => (),
+    }
+}
+
+/// Redacts `key=value` args with a secret-looking or `env` key, which covers
+/// `--config` overrides, and the args after secret-looking flags.
+pub fn redact_args(args: &[String]) -> Vec<String> {
+    let mut redacted = Vec::with_capacity(args.len());
+    let mut redact_next = false;
+    for arg in args {
+        if redact_next {
+            redacted.push(REDACTED.to_owned());
+            redact_next = false;
+        } else if let Some((key, _)) = arg.split_once('=') {
+            let is_env = key.split('.').any(|part| part == "env");
+            if is_env || looks_secret(key) {
+                redacted.push(format!("{}={}", key, REDACTED));
+            } else {
+                redacted.push(arg.clone());
+            }
+        } else {
+            redact_next = arg.starts_with('-') && looks_secret(arg);
+            redacted.push(arg.clone());
+        }
+    }
+    redacted
+}
+
+/// `2022-03-04T05:06:07Z`, minus the punctuation that isn't welcome in
+/// directory names everywhere.
+fn dir_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (year, month, day) = logging::civil_from_days(secs.div_euclid(86_400));
+    let secs_of_day = secs.rem_euclid(86_400);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+    )
+}
+
+/// A directory in `install_dir` that isn't taken yet, named for `time`.
+pub fn bundle_dir(install_dir: &Path, time: SystemTime) -> PathBuf {
+    let name = format!("crash-{}", dir_timestamp(time));
+    let mut dir = install_dir.join(&name);
+    let mut n = 2;
+    while dir.exists() {
+        dir = install_dir.join(format!("{}-{}", name, n));
+        n += 1;
+    }
+    dir
+}
+
+/// The first line `tool` prints, or why there isn't one. This gives up on
+/// tools that take longer than `timeout`, since a wedged `adb` server
+/// shouldn't keep the crash report from being written.
+pub fn tool_version(tool: &str, args: &[&str], timeout: Duration) -> String {
+    let mut child = match Command::new(tool)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return "not found".to_owned(),
+        Err(err) => return format!("failed to run: {}", err),
+    };
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if start.elapsed() < timeout => thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return format!("timed out after {:?}", timeout);
+            }
+            Err(err) => return format!("failed to wait: {}", err),
+        }
+    }
+    match child.wait_with_output() {
+        // `java -version` prints to stderr, so we take whichever has something.
+        Ok(output) => [&output.stdout, &output.stderr]
+            .iter()
+            .find_map(|bytes| {
+                String::from_utf8_lossy(bytes)
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .map(ToOwned::to_owned)
+            })
+            .unwrap_or_else(|| "no output".to_owned()),
+        Err(err) => format!("failed to read output: {}", err),
+    }
+}
+
+fn tools() -> impl Iterator<Item = &'static (&'static str, &'static [&'static str])> {
+    let apple: &[_] = if cfg!(target_os = "macos") {
+        APPLE_TOOLS
+    } else {
+        &[]
+    };
+    TOOLS.iter().chain(apple)
+}
+
+/// Runs `f` on a thread of its own, so that if it panics, we just don't get
+/// anything.
+fn best_effort<T: Send + 'static>(f: impl FnOnce() -> Option<T> + Send + 'static) -> Option<T> {
+    thread::Builder::new()
+        .name("crash-report".to_owned())
+        .spawn(f)
+        .ok()?
+        .join()
+        .ok()
+        .flatten()
+}
+
+#[derive(Debug)]
+pub struct Bundle {
+    version: String,
+    panic: String,
+    backtrace: String,
+    args: Vec<String>,
+    config: Option<String>,
+    os: Option<String>,
+    tools: Vec<(String, String)>,
+}
+
+impl Bundle {
+    /// Redacts `args` and `config`, which is the raw config with `--config`
+    /// overrides applied.
+    pub fn new(
+        version: String,
+        panic: String,
+        backtrace: String,
+        args: &[String],
+        config: Option<toml::Value>,
+        os: Option<String>,
+        tools: Vec<(String, String)>,
+    ) -> Self {
+        let config = config.and_then(|mut config| {
+            redact_config(&mut config);
+            toml::to_string_pretty(&config).ok()
+        });
+        Self {
+            version,
+            panic,
+            backtrace,
+            args: redact_args(args),
+            config,
+            os,
+            tools,
+        }
+    }
+
+    /// The file names and contents that make up the bundle.
+    pub fn files(&self) -> Vec<(&'static str, String)> {
+        let mut tools = String::new();
+        for (tool, version) in &self.tools {
+            let _ = writeln!(tools, "{}: {}", tool, version);
+        }
+        vec![
+            (
+                "panic.txt",
+                format!(
+                    "cargo-mobile {}\n\n{}\n\n{}\n",
+                    self.version, self.panic, self.backtrace
+                ),
+            ),
+            ("command.txt", format!("{}\n", self.args.join(" "))),
+            (
+                "config.toml",
+                self.config
+                    .clone()
+                    .unwrap_or_else(|| "# No config was found.\n".to_owned()),
+            ),
+            (
+                "os.txt",
+                format!(
+                    "{}\n",
+                    self.os.as_deref().unwrap_or("Failed to get OS info")
+                ),
+            ),
+            ("tools.txt", tools),
+        ]
+    }
+
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        for (name, contents) in self.files() {
+            fs::write(dir.join(name), contents)?;
+        }
+        Ok(())
+    }
+}
+
+fn gather(panic: String, backtrace: String, args: &[String]) -> Bundle {
+    let version = best_effort(|| Some(VERSION_LONG.clone()))
+        .unwrap_or_else(|| super::cli::VERSION_SHORT.to_owned());
+    let config = best_effort(|| Config::load_toml("."));
+    let os = best_effort(|| {
+        os::Info::check()
+            .ok()
+            .map(|info| format!("{} {}", info.name, info.version))
+    });
+    // These all run at once, so the slow ones don't add up.
+    let checks = tools()
+        .map(|&(tool, args)| {
+            let handle = thread::Builder::new()
+                .name("crash-report".to_owned())
+                .spawn(move || tool_version(tool, args, TOOL_TIMEOUT))
+                .ok();
+            (tool, handle)
+        })
+        .collect::<Vec<_>>();
+    let tools = checks
+        .into_iter()
+        .map(|(tool, handle)| {
+            let version = handle
+                .and_then(|handle| handle.join().ok())
+                .unwrap_or_else(|| "failed to check".to_owned());
+            (tool.to_owned(), version)
+        })
+        .collect();
+    Bundle::new(version, panic, backtrace, args, config, os, tools)
+}
+
+fn report(panic: String, backtrace: String, args: Vec<String>) -> Option<PathBuf> {
+    best_effort(move || {
+        let install_dir = super::install_dir().ok()?;
+        let bundle = gather(panic, backtrace, &args);
+        let dir = bundle_dir(&install_dir, SystemTime::now());
+        bundle.write(&dir).ok()?;
+        Some(dir)
+    })
+}
+
+/// Installs the hook, which runs after the default one. `args` is what we
+/// were run with, which is redacted before it's written.
+pub fn install_hook(args: Vec<String>) {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default(info);
+        if REPORTING.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let panic = info.to_string();
+        let backtrace = Backtrace::force_capture().to_string();
+        let mut stderr = io::stderr();
+        // Writing to stderr can fail, but `eprintln` would panic if it did.
+        let _ = match report(panic, backtrace, args.clone()) {
+            Some(dir) => writeln!(
+                stderr,
+                "\ncargo-mobile crashed, which is always a bug. We saved a crash report to {:?}; if you open an issue at {}/issues, please attach it. It never leaves your machine unless you share it, and we've redacted your `env` values and anything that looked secret, but please look it over first.",
+                dir, REPO_URL
+            ),
+            None => writeln!(
+                stderr,
+                "\ncargo-mobile crashed, which is always a bug, and we also failed to save a crash report. If you open an issue at {}/issues, please include the output above.",
+                REPO_URL
+            ),
+        };
+    }));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+
+    static CONFIG: &str = r#"
+[app]
+name = "tic-tac"
+domain = "example.com"
+
+[android]
+min-sdk-version = 24
+upload-token = "abc123"
+
+[android.signing]
+keystore-path = "release.keystore"
+keystore-password = "hunter2"
+
+[env]
+SIGNING_PASSWORD = "hunter2"
+FEATURES = ["a", "b"]
+"#;
+
+    fn bundle() -> Bundle {
+        Bundle::new(
+            "v0.1.0".to_owned(),
+            "panicked at src/doctor/section/android.rs:12:34:\nno NDK".to_owned(),
+            "   0: std::backtrace::Backtrace::force_capture".to_owned(),
+            &[
+                "cargo-mobile".to_owned(),
+                "android".to_owned(),
+                "build".to_owned(),
+                "--config".to_owned(),
+                "env.SIGNING_PASSWORD=hunter2".to_owned(),
+            ],
+            Some(toml::from_str(CONFIG).unwrap()),
+            Some("Ubuntu 22.04".to_owned()),
+            vec![
+                ("rustc".to_owned(), "rustc 1.66.0".to_owned()),
+                ("adb".to_owned(), "not found".to_owned()),
+            ],
+        )
+    }
+
+    #[rstest(
+        key,
+        expected,
+        case("keystore-password", true),
+        case("KEYSTORE_PASSWORD", true),
+        case("upload-token", true),
+        case("app-store-api-key", true),
+        case("keystore-path", false),
+        case("domain", false)
+    )]
+    fn detects_secret_looking_keys(key: &str, expected: bool) {
+        assert_eq!(looks_secret(key), expected);
+    }
+
+    #[test]
+    fn redacts_config() {
+        let mut config = toml::from_str::<toml::Value>(CONFIG).unwrap();
+        redact_config(&mut config);
+        let redacted = toml::Value::String(REDACTED.to_owned());
+        assert_eq!(config["app"]["name"].as_str(), Some("tic-tac"));
+        assert_eq!(config["android"]["min-sdk-version"].as_integer(), Some(24));
+        assert_eq!(config["android"]["upload-token"], redacted);
+        assert_eq!(
+            config["android"]["signing"]["keystore-path"].as_str(),
+            Some("release.keystore")
+        );
+        assert_eq!(config["android"]["signing"]["keystore-password"], redacted);
+        assert_eq!(config["env"]["SIGNING_PASSWORD"], redacted);
+        assert_eq!(
+            config["env"]["FEATURES"],
+            toml::Value::Array(vec![redacted.clone(), redacted])
+        );
+    }
+
+    #[rstest(
+        args,
+        expected,
+        case(&["android", "run", "--release"], &["android", "run", "--release"]),
+        case(
+            &["--config", "android.signing.keystore-password=hunter2"],
+            &["--config", "android.signing.keystore-password=<redacted>"]
+        ),
+        case(
+            &["--config", "env.FOO=bar", "--config", "app.name=tic-tac"],
+            &["--config", "env.FOO=<redacted>", "--config", "app.name=tic-tac"]
+        ),
+        case(&["--api-token", "abc123", "run"], &["--api-token", "<redacted>", "run"]),
+        case(&["--password=abc123"], &["--password=<redacted>"])
+    )]
+    fn redacts_args(args: &[&str], expected: &[&str]) {
+        let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(redact_args(&args), expected);
+    }
+
+    #[test]
+    fn assembles_bundle() {
+        let files = bundle().files();
+        let names = files.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "panic.txt",
+                "command.txt",
+                "config.toml",
+                "os.txt",
+                "tools.txt"
+            ]
+        );
+        let file = |name| {
+            files
+                .iter()
+                .find(|(file, _)| *file == name)
+                .map(|(_, contents)| contents.as_str())
+                .unwrap()
+        };
+        assert!(file("panic.txt").starts_with("cargo-mobile v0.1.0\n\npanicked at"));
+        assert!(file("panic.txt").contains("force_capture"));
+        assert_eq!(
+            file("command.txt"),
+            "cargo-mobile android build --config env.SIGNING_PASSWORD=<redacted>\n"
+        );
+        assert!(file("config.toml").contains("keystore-path = \"release.keystore\""));
+        assert!(!files
+            .iter()
+            .any(|(_, contents)| contents.contains("hunter2")));
+        assert!(!file("config.toml").contains("abc123"));
+        assert_eq!(file("os.txt"), "Ubuntu 22.04\n");
+        assert_eq!(file("tools.txt"), "rustc: rustc 1.66.0\nadb: not found\n");
+    }
+
+    #[test]
+    fn writes_bundle_without_clobbering() {
+        let dir = TempDirGuard::new().unwrap();
+        let time = UNIX_EPOCH + Duration::from_secs(1_646_370_367);
+        let first = bundle_dir(&dir, time);
+        assert_eq!(first, dir.join("crash-20220304T050607Z"));
+        bundle().write(&first).unwrap();
+        assert!(first.join("config.toml").is_file());
+        assert_eq!(bundle_dir(&dir, time), dir.join("crash-20220304T050607Z-2"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn checks_tool_versions() {
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            tool_version("cargo-mobile-not-a-real-tool", &[], timeout),
+            "not found"
+        );
+        assert_eq!(
+            tool_version("sh", &["-c", "echo; echo 'tool 1.2.3' >&2"], timeout),
+            "tool 1.2.3"
+        );
+        assert!(
+            tool_version("sh", &["-c", "sleep 10"], Duration::from_millis(100))
+                .starts_with("timed out")
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! Runs cargo with `--message-format=json-diagnostic-rendered-ansi`, so we can
+//! look over what it says instead of just passing it through. Each message's
+//! `rendered` field is printed exactly as cargo would've printed it, so the
+//! output looks the same; the difference is that when a build fails in a way
+//! we've seen before, like the NDK's `liblog` not being linked, the report
+//! ends with a hint about what to do.
+
+use super::{lines, prefixed::Prefix};
+use once_cell_regex::regex;
+use serde::Deserialize;
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+    io::{self, IsTerminal as _, Write as _},
+    path::{Path, PathBuf},
+};
+
+/// `rendered` has color codes in it.
+static MESSAGE_FORMAT_ANSI: &str = "json-diagnostic-rendered-ansi";
+/// `rendered` has no color codes in it.
+static MESSAGE_FORMAT_PLAIN: &str = "json";
+
+/// Which NDK lib has which symbols, by prefix.
+static NDK_LIBS: &[(&str, &str)] = &[
+    ("__android_log_", "log"),
+    ("AAssetManager_", "android"),
+    ("AAsset_", "android"),
+    ("AConfiguration_", "android"),
+    ("AInputEvent_", "android"),
+    ("AInputQueue_", "android"),
+    ("AKeyEvent_", "android"),
+    ("ALooper_", "android"),
+    ("AMotionEvent_", "android"),
+    ("ANativeActivity_", "android"),
+    ("ANativeWindow_", "android"),
+    ("AAudio", "aaudio"),
+    ("AMediaCodec_", "mediandk"),
+    ("AMediaExtractor_", "mediandk"),
+    ("AMediaFormat_", "mediandk"),
+    ("egl", "EGL"),
+    ("slCreateEngine", "OpenSLES"),
+    ("vk", "vulkan"),
+];
+
+/// `--force-color` wins, and otherwise we only ask for color when stderr is a
+/// terminal, which is where cargo would've checked.
+pub fn message_format(color: bool) -> &'static str {
+    if color || io::stderr().is_terminal() {
+        MESSAGE_FORMAT_ANSI
+    } else {
+        MESSAGE_FORMAT_PLAIN
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Diagnostic {
+    message: String,
+    #[serde(default)]
+    rendered: Option<String>,
+    #[serde(default)]
+    children: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    /// Linker errors are one diagnostic saying the linker failed, with what
+    /// the linker said in a `note` child.
+    fn messages(&self) -> Vec<&str> {
+        let mut messages = vec![self.message.as_str()];
+        for child in &self.children {
+            messages.extend(child.messages());
+        }
+        messages
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct ArtifactTarget {
+    pub name: String,
+    #[serde(default)]
+    pub crate_types: Vec<String>,
+}
+
+/// A `compiler-artifact` message, which cargo sends for each unit it built
+/// (or found already built).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Artifact {
+    pub target: ArtifactTarget,
+    #[serde(default)]
+    pub filenames: Vec<PathBuf>,
+}
+
+impl Artifact {
+    fn is_lib(&self, lib_name: &str) -> bool {
+        self.target.name.replace('-', "_") == lib_name.replace('-', "_")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum Message {
+    CompilerMessage {
+        message: Diagnostic,
+    },
+    CompilerArtifact(Artifact),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Hint {
+    NdkLibUnlinked { symbol: String, lib: &'static str },
+    FrameworkMissing { framework: String },
+    CrateTypeMissing { lib: String, crate_type: String },
+}
+
+impl Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NdkLibUnlinked { symbol, lib } => write!(
+                f,
+                "`{}` is in the NDK's `lib{}`, which isn't being linked. If you're using `android_logger` or `ndk-glue`, make sure it's a dependency of your lib (and not only of a binary); otherwise, add `println!(\"cargo:rustc-link-lib={}\");` to your build script.",
+                symbol, lib, lib,
+            ),
+            Self::FrameworkMissing { framework } => write!(
+                f,
+                "The linker couldn't find the `{}` framework. System frameworks go in `frameworks` in the `[package.metadata.cargo-apple.ios]` (or `.macos`) section of your `Cargo.toml`, so check the spelling there; ones you ship yourself go in `vendor-frameworks` instead.",
+                framework,
+            ),
+            Self::CrateTypeMissing { lib, crate_type } => write!(
+                f,
+                "`{}` was built without the \"{}\" crate type, so there's nothing to package. Add it to `crate-type` in the `[lib]` section of your `Cargo.toml`.",
+                lib, crate_type,
+            ),
+        }
+    }
+}
+
+/// `details`, followed by a line for each hint.
+pub fn with_hints(details: impl Display, hints: &[Hint]) -> String {
+    let mut details = details.to_string();
+    for hint in hints {
+        details.push_str(&format!("\n\nhint: {}", hint));
+    }
+    details
+}
+
+fn ndk_lib(symbol: &str) -> Option<&'static str> {
+    NDK_LIBS
+        .iter()
+        .find(|(prefix, _)| symbol.starts_with(prefix))
+        .map(|(_, lib)| *lib)
+}
+
+/// The hints `message` calls for, which is none for the vast majority.
+fn match_signatures(message: &str, hints: &mut BTreeSet<Hint>) {
+    // `ld.lld` says "undefined symbol: ", and GNU ld says "undefined
+    // reference to".
+    for caps in regex!(r"undefined (?:symbol: |reference to [`'])(\w+)").captures_iter(message) {
+        if let Some(lib) = ndk_lib(&caps[1]) {
+            hints.insert(Hint::NdkLibUnlinked {
+                symbol: caps[1].to_owned(),
+                lib,
+            });
+        }
+    }
+    // Xcode 15's linker quotes the name, and puts "not found" after it.
+    for caps in regex!(r"framework (?:not found (\S+)|'([^']+)' not found)").captures_iter(message)
+    {
+        if let Some(framework) = caps.get(1).or_else(|| caps.get(2)) {
+            hints.insert(Hint::FrameworkMissing {
+                framework: framework.as_str().to_owned(),
+            });
+        }
+    }
+}
+
+/// What we learned from a build.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub artifacts: Vec<Artifact>,
+    pub hints: Vec<Hint>,
+}
+
+impl Outcome {
+    /// Where the lib ended up, for the output with `extension`.
+    pub fn lib_path(&self, lib_name: &str, extension: &str) -> Option<&Path> {
+        self.artifacts
+            .iter()
+            .filter(|artifact| artifact.is_lib(lib_name))
+            .flat_map(|artifact| &artifact.filenames)
+            .find(|path| path.extension().map_or(false, |ext| ext == extension))
+            .map(PathBuf::as_path)
+    }
+
+    /// Logs each hint as a warning, for builds that succeeded anyway.
+    pub fn warn(&self) {
+        for hint in &self.hints {
+            log::warn!("{}", hint);
+        }
+    }
+}
+
+/// Looks over cargo's messages one line at a time.
+#[derive(Debug)]
+pub struct Inspector<'a> {
+    lib_name: &'a str,
+    crate_type: &'a str,
+    artifacts: Vec<Artifact>,
+    hints: BTreeSet<Hint>,
+}
+
+impl<'a> Inspector<'a> {
+    /// `lib_name` is the app's lib, which has to be built as `crate_type`.
+    pub fn new(lib_name: &'a str, crate_type: &'a str) -> Self {
+        Self {
+            lib_name,
+            crate_type,
+            artifacts: Default::default(),
+            hints: Default::default(),
+        }
+    }
+
+    /// Gives back what to print to stderr for `line`, if anything. Lines that
+    /// aren't JSON are given back as they are.
+    pub fn inspect(&mut self, line: &str) -> Option<String> {
+        match serde_json::from_str::<Message>(line) {
+            Ok(Message::CompilerMessage { message }) => {
+                for text in message.messages() {
+                    match_signatures(text, &mut self.hints);
+                }
+                message.rendered
+            }
+            Ok(Message::CompilerArtifact(artifact)) => {
+                if artifact.is_lib(self.lib_name)
+                    && !artifact
+                        .target
+                        .crate_types
+                        .iter()
+                        .any(|t| t == self.crate_type)
+                {
+                    self.hints.insert(Hint::CrateTypeMissing {
+                        lib: artifact.target.name.clone(),
+                        crate_type: self.crate_type.to_owned(),
+                    });
+                }
+                self.artifacts.push(artifact);
+                None
+            }
+            Ok(Message::Other) => None,
+            Err(_) => Some(format!("{}\n", line)),
+        }
+    }
+
+    pub fn finish(self) -> Outcome {
+        Outcome {
+            artifacts: self.artifacts,
+            hints: self.hints.into_iter().collect(),
+        }
+    }
+}
+
+/// Runs `command`, which has to have been given `--message-format`. Without a
+/// `prefix`, cargo's own status lines still go straight to stderr.
+pub fn run_and_wait(
+    command: bossy::Command,
+    mut inspector: Inspector<'_>,
+    prefix: Option<&Prefix>,
+) -> (bossy::Result<()>, Outcome) {
+    let command = command.with_stdout_piped();
+    let command = if prefix.is_some() {
+        command.with_stderr_piped()
+    } else {
+        command
+    };
+    let mut handle = match command.run() {
+        Ok(handle) => handle,
+        Err(err) => return (Err(err), inspector.finish()),
+    };
+    lines::read_both(
+        &mut handle,
+        |line| {
+            if let Some(rendered) = inspector.inspect(line) {
+                match prefix {
+                    Some(prefix) => {
+                        for line in rendered.lines() {
+                            prefix.emit(line, true);
+                        }
+                    }
+                    None => {
+                        let _ = io::stderr().write_all(rendered.as_bytes());
+                    }
+                }
+            }
+        },
+        // Only piped with a prefix.
+        |line| {
+            if let Some(prefix) = prefix {
+                prefix.emit(line, true);
+            }
+        },
+    );
+    (handle.wait_for_output().map(|_| ()), inspector.finish())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    // Recorded from `cargo build --message-format=json` for
+    // `aarch64-linux-android` and `x86_64-apple-darwin`, trimmed down to the
+    // fields we read (plus a few we don't, to make sure they're ignored).
+    static NDK_LINK_FAILED: &str = r#"{"reason":"compiler-message","package_id":"tic-tac 0.1.0 (path+file:///Users/jane/tic-tac)","manifest_path":"/Users/jane/tic-tac/Cargo.toml","target":{"kind":["cdylib"],"crate_types":["cdylib"],"name":"tic_tac","src_path":"/Users/jane/tic-tac/src/lib.rs","edition":"2018"},"message":{"rendered":"error: linking with `/opt/ndk/toolchains/llvm/prebuilt/darwin-x86_64/bin/aarch64-linux-android24-clang` failed: exit status: 1\n  |\n  = note: ld: error: undefined symbol: __android_log_write\n          >>> referenced by lib.rs:12\n\n","children":[{"children":[],"code":null,"level":"note","message":"ld: error: undefined symbol: __android_log_write\n>>> referenced by lib.rs:12\n>>>               tic_tac.o:(tic_tac::log)\n\nld: error: undefined symbol: AAssetManager_open\n>>> referenced by assets.rs:40\nclang: error: linker command failed with exit code 1","rendered":null,"spans":[]}],"code":null,"level":"error","message":"linking with `/opt/ndk/toolchains/llvm/prebuilt/darwin-x86_64/bin/aarch64-linux-android24-clang` failed: exit status: 1","spans":[]}}"#;
+    static FRAMEWORK_MISSING: &str = r#"{"reason":"compiler-message","package_id":"tic-tac 0.1.0 (path+file:///Users/jane/tic-tac)","message":{"rendered":"error: linking with `cc` failed: exit status: 1\n  |\n  = note: ld: framework not found Metall\n\n","children":[{"children":[],"code":null,"level":"note","message":"ld: framework not found Metall\nclang: error: linker command failed with exit code 1 (use -v to see invocation)","rendered":null,"spans":[]}],"code":null,"level":"error","message":"linking with `cc` failed: exit status: 1","spans":[]}}"#;
+    static WARNING: &str = r#"{"reason":"compiler-message","package_id":"tic-tac 0.1.0 (path+file:///Users/jane/tic-tac)","message":{"rendered":"\u001b[0m\u001b[1m\u001b[33mwarning\u001b[0m: unused variable: `board`\n","children":[],"code":{"code":"unused_variables","explanation":null},"level":"warning","message":"unused variable: `board`","spans":[]}}"#;
+    static RLIB_ARTIFACT: &str = r#"{"reason":"compiler-artifact","package_id":"tic-tac 0.1.0 (path+file:///Users/jane/tic-tac)","target":{"kind":["lib"],"crate_types":["lib"],"name":"tic-tac","src_path":"/Users/jane/tic-tac/src/lib.rs","edition":"2018","doctest":true,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/Users/jane/tic-tac/target/aarch64-linux-android/debug/libtic_tac.rlib"],"executable":null,"fresh":false}"#;
+    static STATICLIB_ARTIFACT: &str = r#"{"reason":"compiler-artifact","package_id":"tic-tac 0.1.0 (path+file:///Users/jane/tic-tac)","target":{"kind":["staticlib","cdylib","rlib"],"crate_types":["staticlib","cdylib","rlib"],"name":"tic_tac","src_path":"/Users/jane/tic-tac/src/lib.rs","edition":"2018","doctest":true,"test":true},"features":[],"filenames":["/Users/jane/tic-tac/target/aarch64-apple-ios/debug/libtic_tac.a","/Users/jane/tic-tac/target/aarch64-apple-ios/debug/libtic_tac.dylib","/Users/jane/tic-tac/target/aarch64-apple-ios/debug/libtic_tac.rlib"],"executable":null,"fresh":true}"#;
+    static DEP_ARTIFACT: &str = r#"{"reason":"compiler-artifact","package_id":"log 0.4.14 (registry+https://github.com/rust-lang/crates.io-index)","target":{"kind":["lib"],"crate_types":["lib"],"name":"log","src_path":"/Users/jane/.cargo/registry/src/github.com-1ecc6299db9ec823/log-0.4.14/src/lib.rs","edition":"2015"},"filenames":["/Users/jane/tic-tac/target/aarch64-linux-android/debug/deps/liblog-0bc8cb1e1ad7f5a8.rlib"],"fresh":true}"#;
+    static BUILD_FINISHED: &str = r#"{"reason":"build-finished","success":false}"#;
+
+    fn inspect(lines: &[&str], crate_type: &str) -> (Vec<Option<String>>, Outcome) {
+        let mut inspector = Inspector::new("tic-tac", crate_type);
+        let printed = lines.iter().map(|line| inspector.inspect(line)).collect();
+        (printed, inspector.finish())
+    }
+
+    #[rstest(
+        symbol,
+        expected,
+        case("__android_log_write", Some("log")),
+        case("ANativeWindow_getWidth", Some("android")),
+        case("AAudioStreamBuilder_openStream", Some("aaudio")),
+        case("eglGetDisplay", Some("EGL")),
+        case("vkCreateInstance", Some("vulkan")),
+        case("tic_tac_start", None)
+    )]
+    fn knows_ndk_libs(symbol: &str, expected: Option<&str>) {
+        assert_eq!(ndk_lib(symbol), expected);
+    }
+
+    #[test]
+    fn hints_unlinked_ndk_libs() {
+        let (printed, outcome) = inspect(&[NDK_LINK_FAILED, BUILD_FINISHED], "cdylib");
+        assert!(printed[0]
+            .as_deref()
+            .unwrap()
+            .starts_with("error: linking with"));
+        assert_eq!(printed[1], None);
+        assert_eq!(
+            outcome.hints,
+            vec![
+                Hint::NdkLibUnlinked {
+                    symbol: "AAssetManager_open".to_owned(),
+                    lib: "android",
+                },
+                Hint::NdkLibUnlinked {
+                    symbol: "__android_log_write".to_owned(),
+                    lib: "log",
+                },
+            ]
+        );
+    }
+
+    #[rstest(
+        message,
+        expected,
+        case("ld: framework not found Metall", Some("Metall")),
+        case("ld: framework 'GameKit' not found", Some("GameKit")),
+        case("ld: library not found for -lz", None)
+    )]
+    fn finds_missing_frameworks(message: &str, expected: Option<&str>) {
+        let mut hints = BTreeSet::new();
+        match_signatures(message, &mut hints);
+        assert_eq!(
+            hints.into_iter().collect::<Vec<_>>(),
+            expected
+                .map(|framework| Hint::FrameworkMissing {
+                    framework: framework.to_owned(),
+                })
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn hints_missing_framework() {
+        let (_, outcome) = inspect(&[FRAMEWORK_MISSING], "staticlib");
+        assert_eq!(
+            outcome.hints,
+            vec![Hint::FrameworkMissing {
+                framework: "Metall".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn passes_rendered_through_unchanged() {
+        let (printed, outcome) = inspect(&[WARNING, "not json at all"], "cdylib");
+        assert_eq!(
+            printed,
+            vec![
+                Some(
+                    "\u{1b}[0m\u{1b}[1m\u{1b}[33mwarning\u{1b}[0m: unused variable: `board`\n"
+                        .to_owned()
+                ),
+                Some("not json at all\n".to_owned()),
+            ]
+        );
+        assert!(outcome.hints.is_empty());
+    }
+
+    #[test]
+    fn hints_missing_crate_type() {
+        let (_, outcome) = inspect(&[DEP_ARTIFACT, RLIB_ARTIFACT], "cdylib");
+        assert_eq!(
+            outcome.hints,
+            vec![Hint::CrateTypeMissing {
+                lib: "tic-tac".to_owned(),
+                crate_type: "cdylib".to_owned(),
+            }]
+        );
+        assert_eq!(outcome.artifacts.len(), 2);
+        let (_, outcome) = inspect(&[DEP_ARTIFACT, STATICLIB_ARTIFACT], "cdylib");
+        assert!(outcome.hints.is_empty());
+    }
+
+    #[test]
+    fn finds_lib_path() {
+        let (_, outcome) = inspect(&[DEP_ARTIFACT, STATICLIB_ARTIFACT], "staticlib");
+        assert_eq!(
+            outcome.lib_path("tic-tac", "a"),
+            Some(Path::new(
+                "/Users/jane/tic-tac/target/aarch64-apple-ios/debug/libtic_tac.a"
+            ))
+        );
+        assert_eq!(outcome.lib_path("tic-tac", "so"), None);
+    }
+
+    #[test]
+    fn appends_hints() {
+        let hints = vec![Hint::FrameworkMissing {
+            framework: "Metall".to_owned(),
+        }];
+        let details = with_hints("Command failed", &hints);
+        assert!(details.starts_with(
+            "Command failed\n\nhint: The linker couldn't find the `Metall` framework."
+        ));
+        assert_eq!(with_hints("Command failed", &[]), "Command failed");
+    }
+}
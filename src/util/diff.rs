@@ -0,0 +1,51 @@
+/// Renders a line-by-line diff of `old` and `new`, with removed lines prefixed
+/// by `-`, added lines by `+`, and unchanged lines by a space. This is only
+/// meant for showing the user small files (like configs) before we overwrite
+/// them, so it doesn't bother being clever.
+pub fn diff_lines(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    // `lcs[i][j]` is the length of the longest common subsequence of
+    // `old[i..]` and `new[j..]`.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push_str(&format!("  {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push_str(&format!("+ {}\n", new[j]));
+            j += 1;
+        } else {
+            out.push_str(&format!("- {}\n", old[i]));
+            i += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn marks_changes() {
+        assert_eq!(diff_lines("a\nb\nc\n", "a\nc\nd\n"), "  a\n- b\n  c\n+ d\n");
+    }
+
+    #[test]
+    fn identical_is_all_context() {
+        assert_eq!(diff_lines("a\nb\n", "a\nb\n"), "  a\n  b\n");
+    }
+}
@@ -0,0 +1,105 @@
+//! Picks the lines that say what went wrong out of a build log, which is
+//! mostly progress. Failure reports show these instead of the whole log (or
+//! nothing), and anything that recognizes specific errors only has to look
+//! at them.
+
+use once_cell_regex::regex;
+use std::fmt::{self, Display};
+
+/// More than this and the summary's as hard to read as the log.
+const MAX_LINES: usize = 20;
+
+#[derive(Debug, Default)]
+pub struct ErrorSummary {
+    lines: Vec<String>,
+}
+
+impl ErrorSummary {
+    /// Whether `line` reports an error, going by how compilers, linkers, and
+    /// `xcodebuild` do.
+    pub fn is_error(line: &str) -> bool {
+        let line = regex!(r"\x1b\[[0-9;]*m").replace_all(line, "");
+        regex!(r"(?:^|\s|:)(?:fatal )?error(?:\[\w+\])?: |^ld: |^\*\* [A-Z ]+ FAILED \*\*")
+            .is_match(&line)
+            && !line.starts_with("ld: warning: ")
+    }
+
+    /// Keeps `line` if it's an error we haven't seen yet.
+    pub fn feed(&mut self, line: &str) {
+        let line = line.trim();
+        if Self::is_error(line) && !self.lines.iter().any(|seen| seen == line) {
+            self.lines.push(line.to_owned());
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+impl Display for ErrorSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.lines.iter().take(MAX_LINES) {
+            writeln!(f, "    {}", line)?;
+        }
+        if self.lines.len() > MAX_LINES {
+            writeln!(f, "    ...and {} more", self.lines.len() - MAX_LINES)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        line,
+        error,
+        case("/Users/jane/game/gen/apple/Sources/game/main.swift:2:8: error: could not find module 'Ads' for target 'arm64-apple-ios-simulator'; found: x86_64", true),
+        case("ld: building for iOS Simulator, but linking in object file built for iOS, file 'Ads' for architecture arm64", true),
+        case("clang: error: linker command failed with exit code 1 (use -v to see invocation)", true),
+        case("\x1b[1m\x1b[31merror[E0425]\x1b[0m: cannot find value `board`", true),
+        case("** BUILD FAILED **", true),
+        case("CompileSwift normal arm64 /Users/jane/game/gen/apple/Sources/game/main.swift", false),
+        case("warning: unused variable `board`", false),
+        case("ld: warning: object file was built for newer iOS version (16.0) than being linked (13.0)", false),
+        case("** BUILD SUCCEEDED **", false)
+    )]
+    fn recognizes_errors(line: &str, error: bool) {
+        assert_eq!(ErrorSummary::is_error(line), error);
+    }
+
+    #[test]
+    fn keeps_each_error_once() {
+        let mut summary = ErrorSummary::default();
+        for line in &[
+            "note: Using new build system",
+            "ld: framework not found Ads",
+            "  ld: framework not found Ads",
+            "** BUILD FAILED **",
+        ] {
+            summary.feed(line);
+        }
+        assert_eq!(
+            summary.to_string(),
+            "    ld: framework not found Ads\n    ** BUILD FAILED **\n"
+        );
+    }
+
+    #[test]
+    fn caps_long_summaries() {
+        let mut summary = ErrorSummary::default();
+        for n in 0..MAX_LINES + 3 {
+            summary.feed(&format!("error: number {}", n));
+        }
+        let text = summary.to_string();
+        assert_eq!(text.lines().count(), MAX_LINES + 1);
+        assert!(text.ends_with("    ...and 3 more\n"));
+    }
+}
@@ -0,0 +1,245 @@
+//! Newline-delimited JSON events, for tools (i.e. IDE extensions) that drive
+//! us and want something sturdier than scraping our output. Events are
+//! written to a path of their own (a file, a FIFO, or i.e. `/dev/fd/3`),
+//! since stderr is shared with logging and with every tool we run. While the
+//! stream is on, human output is suppressed and prompts are answered with
+//! JSON on stdin.
+
+use super::timings;
+use once_cell_regex::exports::once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::{self, BufRead as _, Write as _},
+    path::Path,
+    sync::Mutex,
+    time::Instant,
+};
+
+static SINK: OnceCell<Mutex<File>> = OnceCell::new();
+
+/// A step that's reported in the event stream. IDs are part of our interface,
+/// so never change one once it's shipped; titles can change freely.
+#[derive(Clone, Copy, Debug)]
+pub struct Step {
+    pub id: &'static str,
+    pub title: &'static str,
+}
+
+impl Step {
+    pub const fn new(id: &'static str, title: &'static str) -> Self {
+        Self { id, title }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Ok,
+    Failed,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    StepStart {
+        id: &'a str,
+        title: &'a str,
+    },
+    #[serde(rename_all = "kebab-case")]
+    StepEnd {
+        id: &'a str,
+        status: Status,
+        duration_secs: f64,
+    },
+    PromptRequest {
+        id: &'a str,
+        msg: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        default: Option<&'a str>,
+        /// For list prompts, which are answered with an index into this.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        choices: Option<&'a [String]>,
+    },
+    Report {
+        label: &'a str,
+        msg: &'a str,
+        details: &'a str,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct PromptResponse {
+    response: String,
+}
+
+/// Turns the stream on, writing events to `path`. Anything already at `path`
+/// is appended to, since it's as likely to be a pipe as a file.
+pub fn enable(path: &Path) -> io::Result<()> {
+    let sink = OpenOptions::new().create(true).append(true).open(path)?;
+    if SINK.set(Mutex::new(sink)).is_err() {
+        log::warn!(
+            "the event stream was already on, so {:?} won't be used",
+            path
+        );
+    }
+    // Escape codes have no business in JSON.
+    colored::control::set_override(false);
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    SINK.get().is_some()
+}
+
+fn write_line(sink: &mut impl io::Write, event: &Event<'_>) -> io::Result<()> {
+    let line = serde_json::to_string(event).expect("developer error: event didn't serialize");
+    writeln!(sink, "{}", line).and_then(|()| sink.flush())
+}
+
+pub fn emit(event: &Event<'_>) {
+    if let Some(sink) = SINK.get() {
+        let mut sink = sink.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(err) = write_line(&mut *sink, event) {
+            log::error!("failed to emit event: {}", err);
+        }
+    }
+}
+
+/// Prints `msg` on its own line, unless the event stream is on.
+pub fn say(msg: impl Display) {
+    if !enabled() {
+        println!("{}", msg);
+    }
+}
+
+/// Runs `f` as `step`, which is both timed and reported in the event stream.
+pub fn step<T, E>(step: &Step, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    if !enabled() {
+        return timings::step(step.title, f);
+    }
+    emit(&Event::StepStart {
+        id: step.id,
+        title: step.title,
+    });
+    let started = Instant::now();
+    let result = timings::step(step.title, f);
+    emit(&Event::StepEnd {
+        id: step.id,
+        status: if result.is_ok() {
+            Status::Ok
+        } else {
+            Status::Failed
+        },
+        duration_secs: started.elapsed().as_secs_f64(),
+    });
+    result
+}
+
+fn parse_response(line: &str) -> io::Result<String> {
+    serde_json::from_str::<PromptResponse>(line)
+        .map(|response| response.response)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Runs `command` to completion. While the event stream is on, its output is
+/// captured rather than passed through, so it doesn't end up in between the
+/// prompts of whatever's driving us.
+pub fn run_and_wait(mut command: bossy::Command) -> bossy::Result<()> {
+    if enabled() {
+        command.run_and_wait_for_output().map(|_| ())
+    } else {
+        command.run_and_wait().map(|_| ())
+    }
+}
+
+/// Asks for a response to `msg` via the event stream, then reads it from
+/// stdin as a single line like `{"response":"..."}`. Like step IDs, `id` is
+/// part of our interface, so it never changes once it's shipped, even if
+/// `msg` does.
+pub fn prompt(
+    id: &str,
+    msg: &str,
+    default: Option<&str>,
+    choices: Option<&[String]>,
+) -> io::Result<String> {
+    emit(&Event::PromptRequest {
+        id,
+        msg,
+        default,
+        choices,
+    });
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    if line.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "stdin closed before a prompt response was sent",
+        ));
+    }
+    parse_response(line.trim()).map(|response| response.trim().to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events_serialize_as_documented() {
+        let json = |event| serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json(Event::StepStart {
+                id: "apple.deps.install",
+                title: "Installing iOS dependencies",
+            }),
+            r#"{"event":"step-start","id":"apple.deps.install","title":"Installing iOS dependencies"}"#
+        );
+        assert_eq!(
+            json(Event::StepEnd {
+                id: "apple.deps.install",
+                status: Status::Failed,
+                duration_secs: 1.5,
+            }),
+            r#"{"event":"step-end","id":"apple.deps.install","status":"failed","duration-secs":1.5}"#
+        );
+        assert_eq!(
+            json(Event::PromptRequest {
+                id: "config.app.name",
+                msg: "Project name",
+                default: None,
+                choices: None,
+            }),
+            r#"{"event":"prompt-request","id":"config.app.name","msg":"Project name"}"#
+        );
+        assert_eq!(
+            json(Event::PromptRequest {
+                id: "device.select",
+                msg: "Device",
+                default: Some("0"),
+                choices: Some(&["Pixel 6".to_owned(), "Pixel 7".to_owned()]),
+            }),
+            r#"{"event":"prompt-request","id":"device.select","msg":"Device","default":"0","choices":["Pixel 6","Pixel 7"]}"#
+        );
+    }
+
+    #[test]
+    fn events_are_one_per_line() {
+        let mut sink = Vec::new();
+        for id in &["init.config.load", "init.base.gen"] {
+            write_line(&mut sink, &Event::StepStart { id, title: "Step" }).unwrap();
+        }
+        let sink = String::from_utf8(sink).unwrap();
+        let lines = sink.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines
+            .iter()
+            .all(|line| line.starts_with(r#"{"event":"step-start""#)));
+    }
+
+    #[test]
+    fn responses_are_json() {
+        assert_eq!(parse_response(r#"{"response":"0"}"#).unwrap(), "0");
+        assert!(parse_response("0").is_err());
+    }
+}
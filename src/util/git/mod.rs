@@ -2,7 +2,10 @@ pub mod lfs;
 pub mod repo;
 pub mod submodule;
 
-use std::{fs, io, path::Path};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Git<'a> {
@@ -28,15 +31,80 @@ impl<'a> Git<'a> {
         self.command().with_parsed_args(arg_str)
     }
 
+    /// The git dir of the repo containing `root`, which isn't necessarily
+    /// `root/.git` if `root` is a subdirectory of a work tree.
+    pub fn git_dir(&self) -> Option<PathBuf> {
+        self.command()
+            .with_args(&["rev-parse", "--git-dir"])
+            .run_and_wait_for_str(|s| self.root.join(s.trim()))
+            .ok()
+    }
+
+    /// The root of the work tree containing `root`.
+    pub fn top_level(&self) -> Option<PathBuf> {
+        self.command()
+            .with_args(&["rev-parse", "--show-toplevel"])
+            .run_and_wait_for_str(|s| PathBuf::from(s.trim()))
+            .ok()
+    }
+
     pub fn init(&self) -> bossy::Result<()> {
-        if !self.root.join(".git").exists() {
+        if let Some(git_dir) = self.git_dir() {
+            log::info!(
+                "{:?} is already in a git repo at {:?}; skipping `git init`",
+                self.root,
+                git_dir
+            );
+        } else {
             self.command().with_arg("init").run_and_wait()?;
         }
         Ok(())
     }
 
+    pub fn is_shallow(&self) -> bool {
+        self.command()
+            .with_args(&["rev-parse", "--is-shallow-repository"])
+            .run_and_wait_for_str(|s| s.trim() == "true")
+            .unwrap_or(false)
+    }
+
+    pub fn has_commit(&self, commit: &str) -> bool {
+        self.command()
+            .with_args(&["cat-file", "-e"])
+            .with_arg(format!("{}^{{commit}}", commit))
+            .run_and_wait_for_output()
+            .is_ok()
+    }
+
+    /// Fetches only `commit` from `origin`, falling back to fetching the
+    /// full history if the shallow fetch can't find it (i.e. because the
+    /// remote doesn't allow fetching unadvertised objects, or because `commit`
+    /// is abbreviated).
+    pub fn fetch_commit(&self, commit: &str) -> bossy::Result<()> {
+        if let Err(err) = self
+            .command()
+            .with_args(&["fetch", "--depth", "1", "origin", commit])
+            .run_and_wait()
+        {
+            log::info!(
+                "shallow fetch of commit {:?} failed; fetching full history instead: {}",
+                commit,
+                err
+            );
+            let mut fetch = self.command().with_arg("fetch");
+            if self.is_shallow() {
+                fetch.add_arg("--unshallow");
+            }
+            fetch.with_arg("origin").run_and_wait()?;
+        }
+        Ok(())
+    }
+
     pub fn config(&self) -> io::Result<Option<String>> {
-        let path = self.root.join(".git/config");
+        let path = self
+            .git_dir()
+            .unwrap_or_else(|| self.root.join(".git"))
+            .join("config");
         if path.exists() {
             fs::read_to_string(&path).map(Some)
         } else {
@@ -45,7 +113,10 @@ impl<'a> Git<'a> {
     }
 
     pub fn modules(&self) -> io::Result<Option<String>> {
-        let path = self.root.join(".gitmodules");
+        let path = self
+            .top_level()
+            .unwrap_or_else(|| self.root.to_owned())
+            .join(".gitmodules");
         if path.exists() {
             fs::read_to_string(&path).map(Some)
         } else {
@@ -65,3 +136,90 @@ impl<'a> Git<'a> {
             .run_and_wait_for_string()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{submodule::Submodule, *};
+    use crate::util::TempDirGuard;
+
+    fn commit(git: Git<'_>, msg: &str) -> String {
+        git.command()
+            .with_args(&["commit", "--allow-empty", "-m", msg])
+            .run_and_wait_for_output()
+            .unwrap();
+        git.command_parse("rev-parse HEAD")
+            .run_and_wait_for_str(|s| s.trim().to_owned())
+            .unwrap()
+    }
+
+    fn subject(git: Git<'_>) -> String {
+        git.command_parse("log -1 --pretty=%s")
+            .run_and_wait_for_str(|s| s.trim().to_owned())
+            .unwrap()
+    }
+
+    #[test]
+    fn init_skips_existing_work_tree() {
+        let root = TempDirGuard::new().unwrap();
+        Git::new(&root).init().unwrap();
+        let sub = root.join("app");
+        fs::create_dir_all(&sub).unwrap();
+        let git = Git::new(&sub);
+        git.init().unwrap();
+        assert!(!sub.join(".git").exists());
+        assert_eq!(
+            git.git_dir().map(|dir| dir.canonicalize().unwrap()),
+            Some(root.join(".git").canonicalize().unwrap())
+        );
+    }
+
+    // Commits need an identity, and newer versions of git don't let
+    // submodules use local remotes by default. Setting these in the
+    // environment means every `git` picks them up, including the ones `git`
+    // runs itself.
+    fn configure_git() {
+        let config = [
+            ("protocol.file.allow", "always"),
+            ("user.name", "cargo-mobile"),
+            ("user.email", "cargo-mobile@example.com"),
+        ];
+        std::env::set_var("GIT_CONFIG_COUNT", config.len().to_string());
+        for (index, (key, value)) in config.iter().enumerate() {
+            std::env::set_var(format!("GIT_CONFIG_KEY_{}", index), key);
+            std::env::set_var(format!("GIT_CONFIG_VALUE_{}", index), value);
+        }
+    }
+
+    fn pinned_submodule_checkout(abbreviate: bool) {
+        configure_git();
+        let root = TempDirGuard::new().unwrap();
+        let remote = root.join("pack.git");
+        fs::create_dir_all(&remote).unwrap();
+        let remote_git = Git::new(&remote);
+        remote_git.init().unwrap();
+        let first = commit(remote_git, "first");
+        commit(remote_git, "second");
+
+        let app = root.join("app");
+        fs::create_dir_all(&app).unwrap();
+        let git = Git::new(&app);
+        git.init().unwrap();
+        let pinned = if abbreviate { &first[..8] } else { &first[..] };
+        Submodule::with_remote_and_path(format!("file://{}", remote.display()), "pack")
+            .init(git, Some(pinned))
+            .unwrap();
+        let pack = app.join("pack");
+        assert!(Git::new(&pack).is_shallow() != abbreviate);
+        assert_eq!(subject(Git::new(&pack)), "first");
+    }
+
+    #[test]
+    fn submodule_fetches_pinned_commit_shallowly() {
+        pinned_submodule_checkout(false);
+    }
+
+    #[test]
+    fn submodule_falls_back_to_full_fetch() {
+        pinned_submodule_checkout(true);
+    }
+}
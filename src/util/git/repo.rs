@@ -84,7 +84,7 @@ impl Repo {
             Status::Stale
         } else {
             let git = self.git();
-            git.command_parse("fetch origin")
+            git.command_parse("fetch --depth 1 origin")
                 .run_and_wait()
                 .map_err(Error::FetchFailed)?;
             let local = git
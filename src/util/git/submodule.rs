@@ -16,6 +16,7 @@ pub enum Cause {
     PathInvalidUtf8,
     AddFailed(bossy::Error),
     InitFailed(bossy::Error),
+    FetchFailed { commit: String, cause: bossy::Error },
     CheckoutFailed { commit: String, cause: bossy::Error },
 }
 
@@ -63,6 +64,11 @@ impl Display for Error {
                 "Failed to init submodule {:?} with remote {:?} and path {:?}: {}",
                 self.submodule.name().unwrap(), self.submodule.remote, self.submodule.path, err
             ),
+            Cause::FetchFailed { commit, cause } => write!(
+                f,
+                "Failed to fetch commit {:?} for submodule {:?} with remote {:?} and path {:?}: {}",
+                commit, self.submodule.name().unwrap(), self.submodule.remote, self.submodule.path, cause
+            ),
             Cause::CheckoutFailed { commit, cause } => write!(
                 f,
                 "Failed to checkout commit {:?} from submodule {:?} with remote {:?} and path {:?}: {}",
@@ -145,7 +151,9 @@ impl Submodule {
             })?;
             log::info!("adding submodule: {:#?}", self);
             git.command()
-                .with_args(&["submodule", "add", "--name", &name, &self.remote, path_str])
+                .with_args(&["submodule", "add", "--depth", "1", "--name", &name])
+                .with_arg(&self.remote)
+                .with_arg(path_str)
                 .run_and_wait()
                 .map_err(|cause| Error {
                     submodule: self.clone(),
@@ -162,7 +170,7 @@ impl Submodule {
         if !initialized {
             log::info!("initializing submodule: {:#?}", self);
             git.command()
-                .with_parsed_args("submodule update --init --recursive")
+                .with_parsed_args("submodule update --init --recursive --depth 1")
                 .run_and_wait()
                 .map_err(|cause| Error {
                     submodule: self.clone(),
@@ -178,7 +186,17 @@ impl Submodule {
                 commit,
                 path
             );
-            Git::new(&path)
+            let submodule_git = Git::new(&path);
+            if !submodule_git.has_commit(commit) {
+                submodule_git.fetch_commit(commit).map_err(|cause| Error {
+                    submodule: self.clone(),
+                    cause: Cause::FetchFailed {
+                        commit: commit.to_owned(),
+                        cause,
+                    },
+                })?;
+            }
+            submodule_git
                 .command()
                 .with_args(&["checkout", commit])
                 .run_and_wait()
@@ -0,0 +1,102 @@
+//! Reads a child's output a line at a time from both pipes at once. Reading
+//! one pipe to the end before starting on the other stalls any child that
+//! fills the second pipe in the meantime, which is a deadlock as soon as the
+//! child's waiting on us.
+
+use std::{
+    fs::File,
+    io::{self, BufRead as _, BufReader, Read},
+    process::ChildStderr,
+    thread,
+};
+
+/// Calls `f` with each line `reader` gives, without the line ending.
+pub fn for_each_line(reader: impl Read, mut f: impl FnMut(&str)) -> io::Result<()> {
+    for line in BufReader::new(reader).split(b'\n') {
+        let line = line?;
+        f(String::from_utf8_lossy(&line).trim_end());
+    }
+    Ok(())
+}
+
+// The handle only lends out its pipes, and both can't be borrowed at once, so
+// the stderr thread gets its own handle to the same pipe.
+#[cfg(unix)]
+fn own_pipe(pipe: &ChildStderr) -> io::Result<File> {
+    use std::os::unix::io::AsFd as _;
+    pipe.as_fd().try_clone_to_owned().map(File::from)
+}
+
+#[cfg(windows)]
+fn own_pipe(pipe: &ChildStderr) -> io::Result<File> {
+    use std::os::windows::io::AsHandle as _;
+    pipe.as_handle().try_clone_to_owned().map(File::from)
+}
+
+fn log_failure(stream: &str, result: io::Result<()>) {
+    if let Err(err) = result {
+        log::error!("failed to read child's {}: {}", stream, err);
+    }
+}
+
+/// Feeds each line `handle` prints to `on_stdout` or `on_stderr` as it comes,
+/// for whichever of its streams were piped. This returns once both pipes are
+/// closed, which is usually when the child exits.
+pub fn read_both(
+    handle: &mut bossy::Handle,
+    mut on_stdout: impl FnMut(&str),
+    mut on_stderr: impl FnMut(&str) + Send,
+) {
+    let stderr = match handle.stderr().map(|pipe| own_pipe(pipe)) {
+        Some(Ok(stderr)) => Some(stderr),
+        Some(Err(err)) => {
+            log::warn!("failed to read child's stderr on its own thread: {}", err);
+            None
+        }
+        None => None,
+    };
+    let threaded = stderr.is_some();
+    thread::scope(|scope| {
+        if let Some(stderr) = stderr {
+            let on_stderr = &mut on_stderr;
+            scope.spawn(move || log_failure("stderr", for_each_line(stderr, on_stderr)));
+        }
+        if let Some(stdout) = handle.stdout() {
+            log_failure("stdout", for_each_line(stdout, &mut on_stdout));
+        }
+    });
+    // We'd rather risk the stall than lose the output.
+    if !threaded {
+        if let Some(stderr) = handle.stderr() {
+            log_failure("stderr", for_each_line(stderr, on_stderr));
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_both_pipes_at_once() {
+        // Far more than a pipe holds, so reading stdout first would hang.
+        let mut handle = bossy::Command::impure("sh")
+            .with_args(&[
+                "-c",
+                "i=0; while [ $i -lt 20000 ]; do echo \"err $i\" >&2; i=$((i+1)); done; echo done",
+            ])
+            .with_stdout_piped()
+            .with_stderr_piped()
+            .run()
+            .unwrap();
+        let (mut stdout, mut stderr) = (Vec::new(), 0);
+        read_both(
+            &mut handle,
+            |line| stdout.push(line.to_owned()),
+            |_| stderr += 1,
+        );
+        handle.wait_for_output().unwrap();
+        assert_eq!(stdout, vec!["done".to_owned()]);
+        assert_eq!(stderr, 20000);
+    }
+}
@@ -0,0 +1,202 @@
+//! Sets up logging for all of our binaries: the terminal gets whatever the
+//! noise level asks for, while `--log-file` gets everything at debug level,
+//! so bug reports can come with the full trail no matter how the command was
+//! run.
+
+use super::cli::VERSION_LONG;
+use crate::opts::NoiseLevel;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    fs::{self, File},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub static LOG_FILE_ENV_VAR: &str = "CARGO_MOBILE_LOG_FILE";
+
+// `bossy` logs each command it runs at debug level, so pedantic mode doubles
+// as a command echo.
+fn terminal_filter(noise_level: NoiseLevel) -> &'static str {
+    match noise_level {
+        NoiseLevel::Polite => "warn",
+        NoiseLevel::LoudAndProud => {
+            "warn,cargo_mobile=info,cargo_android=info,cargo_apple=info,bossy=info,hit=info"
+        }
+        NoiseLevel::FranklyQuitePedantic => {
+            "info,cargo_mobile=debug,cargo_android=debug,cargo_apple=debug,bossy=debug,hit=debug"
+        }
+    }
+}
+
+// Days since the epoch to a civil date, from Howard Hinnant's
+// `civil_from_days`.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// An RFC 3339 UTC timestamp with millisecond precision.
+fn timestamp(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs() as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let secs_of_day = secs.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since.subsec_millis(),
+    )
+}
+
+fn format_line(time: SystemTime, record: &Record<'_>) -> String {
+    format!(
+        "{} {:<5} {}: {}\n",
+        timestamp(time),
+        record.level(),
+        record.module_path().unwrap_or_else(|| record.target()),
+        record.args()
+    )
+}
+
+struct Logger {
+    terminal: env_logger::Logger,
+    file: Option<Mutex<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // The file gets everything up to debug.
+        self.terminal.enabled(metadata) || (self.file.is_some() && metadata.level() <= Level::Debug)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.terminal.matches(record) {
+            self.terminal.log(record);
+        }
+        if let Some(file) = &self.file {
+            if record.level() <= Level::Debug {
+                if let Ok(mut file) = file.lock() {
+                    // There's nowhere left to report this to.
+                    let _ = file.write_all(format_line(SystemTime::now(), record).as_bytes());
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.terminal.flush();
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// `--log-file` wins over the env var.
+pub fn log_file(flag: Option<&Path>) -> Option<PathBuf> {
+    flag.map(Path::to_owned).or_else(|| {
+        std::env::var_os(LOG_FILE_ENV_VAR)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Installs the global logger. `RUST_LOG` only applies when no `-v` was
+/// given, since asking for more noise should always get it.
+pub fn init(noise_level: NoiseLevel, log_file: Option<&Path>, args: &[String]) {
+    let mut builder = if noise_level.polite() {
+        env_logger::Builder::from_env(
+            env_logger::Env::default().default_filter_or(terminal_filter(noise_level)),
+        )
+    } else {
+        let mut builder = env_logger::Builder::new();
+        builder.parse_filters(terminal_filter(noise_level));
+        builder
+    };
+    let terminal = builder.build();
+    let mut max_level = terminal.filter();
+    let file = log_file.and_then(|path| match open(path) {
+        Ok(file) => Some((path, file)),
+        Err(err) => {
+            eprintln!("Failed to open log file {:?}: {}", path, err);
+            None
+        }
+    });
+    if file.is_some() {
+        max_level = max_level.max(LevelFilter::Debug);
+    }
+    let file_path = file.as_ref().map(|(path, _)| path.to_owned());
+    let logger = Logger {
+        terminal,
+        file: file.map(|(_, file)| Mutex::new(file)),
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+    if let Some(path) = file_path {
+        log::info!("logging to {:?}", path);
+        log::debug!("command line: {:?}", args);
+        log::debug!(
+            "{} {} on {} ({})",
+            crate::NAME,
+            VERSION_LONG.as_str(),
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        // 2024 was a leap year, so this checks the date math too.
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_millis(1_709_210_096_789)),
+            "2024-02-29T12:34:56.789Z"
+        );
+    }
+
+    #[test]
+    fn tags_lines_with_module() {
+        let line = format_line(
+            UNIX_EPOCH,
+            &Record::builder()
+                .args(format_args!("running `gradlew`"))
+                .level(Level::Debug)
+                .module_path(Some("cargo_mobile::android::device"))
+                .build(),
+        );
+        assert_eq!(
+            line,
+            "1970-01-01T00:00:00.000Z DEBUG cargo_mobile::android::device: running `gradlew`\n"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Line-based three-way merging, for bringing template changes into files the
+//! user edited. Like `diff_lines`, this is quadratic and only meant for the
+//! small text files that templates produce.
+
+/// The result of `merge3`, with conflict markers in `text` if `conflicts`
+/// isn't zero.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Merged {
+    pub text: String,
+    pub conflicts: usize,
+}
+
+pub const OURS_MARKER: &str = "<<<<<<< yours";
+pub const SEPARATOR_MARKER: &str = "=======";
+pub const THEIRS_MARKER: &str = ">>>>>>> template";
+
+// For each line of `base`, the index of the line it's matched with in
+// `other`, along the longest common subsequence.
+fn matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut lcs = vec![vec![0usize; other.len() + 1]; base.len() + 1];
+    for i in (0..base.len()).rev() {
+        for j in (0..other.len()).rev() {
+            lcs[i][j] = if base[i] == other[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut matched = vec![None; base.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < base.len() && j < other.len() {
+        if base[i] == other[j] {
+            matched[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matched
+}
+
+/// Merges the changes from `base` to `ours` with the changes from `base` to
+/// `theirs`. Where both changed the same lines differently, both versions
+/// are kept between conflict markers, with ours first.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Merged {
+    let base_lines = base.lines().collect::<Vec<_>>();
+    let our_lines = ours.lines().collect::<Vec<_>>();
+    let their_lines = theirs.lines().collect::<Vec<_>>();
+    let ours_matched = matches(&base_lines, &our_lines);
+    let theirs_matched = matches(&base_lines, &their_lines);
+
+    let mut out = Vec::new();
+    let mut conflicts = 0;
+    let (mut i, mut a, mut b) = (0, 0, 0);
+    loop {
+        if i < base_lines.len() && ours_matched[i] == Some(a) && theirs_matched[i] == Some(b) {
+            out.push(base_lines[i]);
+            i += 1;
+            a += 1;
+            b += 1;
+            continue;
+        }
+        // Everything up to the next line all three agree on is a chunk that
+        // at least one side changed.
+        let next = (i..base_lines.len())
+            .find_map(|k| Some((k, ours_matched[k]?, theirs_matched[k]?)))
+            .unwrap_or((base_lines.len(), our_lines.len(), their_lines.len()));
+        let (k, ka, kb) = next;
+        let (base_chunk, our_chunk, their_chunk) =
+            (&base_lines[i..k], &our_lines[a..ka], &their_lines[b..kb]);
+        if our_chunk == base_chunk || our_chunk == their_chunk {
+            out.extend_from_slice(their_chunk);
+        } else if their_chunk == base_chunk {
+            out.extend_from_slice(our_chunk);
+        } else {
+            conflicts += 1;
+            out.push(OURS_MARKER);
+            out.extend_from_slice(our_chunk);
+            out.push(SEPARATOR_MARKER);
+            out.extend_from_slice(their_chunk);
+            out.push(THEIRS_MARKER);
+        }
+        if k == base_lines.len() && ka == our_lines.len() && kb == their_lines.len() {
+            break;
+        }
+        i = k;
+        a = ka;
+        b = kb;
+    }
+
+    let mut text = out.join("\n");
+    // The template decides whether the file ends with a newline.
+    if !text.is_empty() && (theirs.ends_with('\n') || (theirs.is_empty() && ours.ends_with('\n'))) {
+        text.push('\n');
+    }
+    Merged { text, conflicts }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        base,
+        ours,
+        theirs,
+        merged,
+        case("a\nb\nc\n", "a\nb\nc\n", "a\nB\nc\n", "a\nB\nc\n"),
+        case("a\nb\nc\n", "a\nB\nc\n", "a\nb\nc\n", "a\nB\nc\n"),
+        case("a\nb\nc\nd\n", "A\nb\nc\nd\n", "a\nb\nc\nD\n", "A\nb\nc\nD\n"),
+        case("a\nb\n", "a\nmine\nb\n", "a\nb\ntheirs\n", "a\nmine\nb\ntheirs\n"),
+        case("a\nb\nc\n", "a\nc\n", "a\nb\nc\nd\n", "a\nc\nd\n"),
+        case("a\nb\n", "a\nB\n", "a\nB\n", "a\nB\n"),
+        case("", "", "new\n", "new\n")
+    )]
+    fn merges_cleanly(base: &str, ours: &str, theirs: &str, merged: &str) {
+        assert_eq!(
+            merge3(base, ours, theirs),
+            Merged {
+                text: merged.to_owned(),
+                conflicts: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn marks_conflicts() {
+        let merged = merge3("a\nb\nc\n", "a\nmine\nc\n", "a\ntheirs\nc\n");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            merged.text,
+            format!(
+                "a\n{}\nmine\n{}\ntheirs\n{}\nc\n",
+                OURS_MARKER, SEPARATOR_MARKER, THEIRS_MARKER
+            )
+        );
+    }
+
+    #[test]
+    fn conflicts_are_counted_separately() {
+        let merged = merge3("a\nb\nc\nd\ne\n", "a\nB1\nc\nD1\ne\n", "a\nB2\nc\nD2\ne\n");
+        assert_eq!(merged.conflicts, 2);
+        assert!(merged.text.starts_with("a\n"));
+        assert!(merged.text.ends_with("e\n"));
+    }
+}
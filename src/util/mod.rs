@@ -1,11 +1,27 @@
 mod cargo;
 pub mod cli;
+mod copy;
+pub mod crash;
+pub mod diagnostics;
+mod diff;
+mod error_summary;
+pub mod events;
 mod git;
+pub mod lines;
 pub mod ln;
+pub mod logging;
+mod merge;
 mod path;
+pub mod prefixed;
 pub mod prompt;
+pub mod root;
+mod temp;
+pub mod timings;
+pub mod toolchain;
+pub mod tools;
+pub mod watchdog;
 
-pub use self::{cargo::*, git::*, path::*};
+pub use self::{cargo::*, copy::*, diff::*, error_summary::*, git::*, merge::*, path::*, temp::*};
 
 use self::cli::{Report, Reportable};
 use crate::os::{self, command_path};
@@ -43,12 +59,6 @@ pub fn reverse_domain(domain: &str) -> String {
     domain.split('.').rev().collect::<Vec<_>>().join(".")
 }
 
-pub fn rustup_add(triple: &str) -> bossy::Result<bossy::ExitStatus> {
-    bossy::Command::impure("rustup")
-        .with_args(&["target", "add", triple])
-        .run_and_wait()
-}
-
 #[derive(Debug)]
 pub enum HostTargetTripleError {
     CommandFailed(RunAndSearchError),
@@ -65,7 +75,7 @@ impl Reportable for HostTargetTripleError {
 pub fn host_target_triple() -> Result<String, HostTargetTripleError> {
     // TODO: add fast paths
     run_and_search(
-        &mut bossy::Command::impure_parse("rustc --verbose --version"),
+        &mut toolchain::rustc().with_parsed_args("--verbose --version"),
         regex!(r"host: ([\w-]+)"),
         |_text, caps| {
             let triple = caps[1].to_owned();
@@ -242,22 +252,67 @@ pub enum VersionDoubleError {
         version: String,
         source: std::num::ParseIntError,
     },
+    #[error("Failed to parse patch version from {version:?}: {source}")]
+    PatchInvalid {
+        version: String,
+        source: std::num::ParseIntError,
+    },
     #[error(
-        "Failed to parse version string {version:?}: string must be in format <major>[.minor]"
+        "Failed to parse version string {version:?}: string must be in format <major>[.minor[.patch]]"
     )]
     VersionStringInvalid { version: String },
 }
 
-// Generic version double
-#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+// Generic version double, with an optional patch component for the likes of
+// macOS versions. Comparisons are numeric, so "10.04" == "10.4", but the
+// original spelling of the minor version is kept for display.
+#[derive(Clone, Copy, Debug)]
 pub struct VersionDouble {
     pub major: u32,
     pub minor: u32,
+    pub patch: Option<u32>,
+    minor_width: usize,
+}
+
+impl VersionDouble {
+    fn key(&self) -> (u32, u32, u32) {
+        (self.major, self.minor, self.patch.unwrap_or(0))
+    }
+}
+
+impl PartialEq for VersionDouble {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for VersionDouble {}
+
+impl PartialOrd for VersionDouble {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for VersionDouble {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
 }
 
 impl Display for VersionDouble {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}.{}", self.major, self.minor)
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.major,
+            self.minor,
+            width = self.minor_width
+        )?;
+        if let Some(patch) = self.patch {
+            write!(f, ".{}", patch)?;
+        }
+        Ok(())
     }
 }
 
@@ -272,41 +327,59 @@ impl Serialize for VersionDouble {
 
 impl VersionDouble {
     pub const fn new(major: u32, minor: u32) -> Self {
-        Self { major, minor }
+        Self {
+            major,
+            minor,
+            patch: None,
+            minor_width: 0,
+        }
     }
 
     pub fn from_str(v: &str) -> Result<Self, VersionDoubleError> {
-        match v.split(".").count() {
-            1 => Ok(VersionDouble {
-                major: v
+        let mut s = v.split('.');
+        let major =
+            s.next()
+                .unwrap()
+                .parse()
+                .map_err(|source| VersionDoubleError::MajorInvalid {
+                    version: v.to_owned(),
+                    source,
+                })?;
+        let (minor, minor_width) = s
+            .next()
+            .map(|minor| {
+                minor
                     .parse()
-                    .map_err(|source| VersionDoubleError::MajorInvalid {
+                    .map(|parsed| (parsed, minor.len()))
+                    .map_err(|source| VersionDoubleError::MinorInvalid {
                         version: v.to_owned(),
                         source,
-                    })?,
-                minor: 0,
-            }),
-            2 => {
-                let mut s = v.split(".");
-                Ok(VersionDouble {
-                    major: s.next().unwrap().parse().map_err(|source| {
-                        VersionDoubleError::MajorInvalid {
-                            version: v.to_owned(),
-                            source,
-                        }
-                    })?,
-                    minor: s.next().unwrap().parse().map_err(|source| {
-                        VersionDoubleError::MinorInvalid {
-                            version: v.to_owned(),
-                            source,
-                        }
-                    })?,
-                })
-            }
-            _ => Err(VersionDoubleError::VersionStringInvalid {
+                    })
+            })
+            .transpose()?
+            .unwrap_or((0, 0));
+        let patch = s
+            .next()
+            .map(|patch| {
+                patch
+                    .parse()
+                    .map_err(|source| VersionDoubleError::PatchInvalid {
+                        version: v.to_owned(),
+                        source,
+                    })
+            })
+            .transpose()?;
+        if s.next().is_some() {
+            return Err(VersionDoubleError::VersionStringInvalid {
                 version: v.to_owned(),
-            }),
+            });
         }
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            minor_width,
+        })
     }
 }
 
@@ -390,7 +463,7 @@ impl Display for RustVersion {
 impl RustVersion {
     pub fn check() -> Result<Self, RustVersionError> {
         run_and_search(
-            &mut bossy::Command::impure_parse("rustc --version"),
+            &mut toolchain::rustc().with_arg("--version"),
             regex!(
                 r"rustc (?P<version>(?P<major>\d+)\.(?P<minor>\d+)\.(?P<patch>\d+)(-(?P<flavor>\w+)(.(?P<candidate>\d+))?)?)(?P<details> \((?P<hash>\w{9}) (?P<date>(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2}))\))?"
             ),
@@ -431,30 +504,130 @@ impl RustVersion {
         )?
     }
 
-    pub fn valid(&self) -> bool {
-        if cfg!(target_os = "macos") {
-            const LAST_GOOD_STABLE: VersionTriple = VersionTriple::new(1, 45, 2);
-            const NEXT_GOOD_STABLE: VersionTriple = VersionTriple::new(1, 49, 0);
-            const FIRST_GOOD_NIGHTLY: (u32, u32, u32) = (2020, 10, 24);
-
-            let old_good = self.triple <= LAST_GOOD_STABLE;
-            let new_good = self.triple >= NEXT_GOOD_STABLE
-                && self
-                    .details
-                    .as_ref()
-                    .map(|details| details.date >= FIRST_GOOD_NIGHTLY)
-                    .unwrap_or_else(|| {
-                        log::warn!("output of `rustc --version` didn't contain date info; continuing with the assumption that the release date is at least 2020-10-24");
-                        true
-                    });
-
-            old_good || new_good
-        } else {
-            true
+    /// When this version was released. Without date info, it's estimated
+    /// from the 6-week release train, which is close enough for telling
+    /// people how far behind they are.
+    pub fn release_date(&self) -> (u32, u32, u32) {
+        self.details
+            .as_ref()
+            .map(|details| details.date)
+            .unwrap_or_else(|| {
+                // 1.0.0 was released on 2015-05-15.
+                const FIRST_RELEASE_DAYS: i64 = 16_570;
+                let (year, month, day) = logging::civil_from_days(
+                    FIRST_RELEASE_DAYS + 42 * i64::from(self.triple.minor),
+                );
+                (year as u32, month, day)
+            })
+    }
+
+    /// How many whole years older than `today` this version is.
+    pub fn years_old(&self, today: (u32, u32, u32)) -> u32 {
+        let (year, month, day) = self.release_date();
+        let mut months = (today.0 * 12 + today.1).saturating_sub(year * 12 + month);
+        if today.2 < day {
+            // The last month isn't over yet.
+            months = months.saturating_sub(1);
         }
+        months / 12
     }
 }
 
+/// A minimum rustc version for building for a particular platform.
+#[derive(Debug)]
+pub struct RustVersionRequirement {
+    /// i.e. `Android` or `iOS`.
+    pub platform: &'static str,
+    pub min: VersionTriple,
+    pub reason: String,
+}
+
+impl RustVersionRequirement {
+    pub fn satisfied_by(&self, version: &RustVersion) -> bool {
+        version.triple >= self.min
+    }
+
+    fn report(&self, version: &RustVersion) -> Report {
+        Report::action_request(
+            format!(
+                "{} builds need Rust {} or later, but you're on {}!",
+                self.platform, self.min, version
+            ),
+            format!(
+                "{}\nTo update: `rustup update stable && rustup default stable`",
+                self.reason
+            ),
+        )
+    }
+}
+
+/// Anything this old is likely missing fixes we rely on, even if no
+/// requirement says so yet.
+const OLD_RUST_YEARS: u32 = 1;
+
+fn parse_source_date_epoch(raw: &str) -> Option<u64> {
+    let epoch = raw.trim().parse::<u64>();
+    if epoch.is_err() {
+        log::warn!(
+            "ignoring `SOURCE_DATE_EPOCH`, since {:?} isn't a number of seconds",
+            raw
+        );
+    }
+    epoch.ok()
+}
+
+/// `SOURCE_DATE_EPOCH`, which reproducible builds set to the time artifacts
+/// should claim they were built at, in seconds since the Unix epoch. See
+/// https://reproducible-builds.org/specs/source-date-epoch/
+pub fn source_date_epoch() -> Option<u64> {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|raw| parse_source_date_epoch(&raw))
+}
+
+fn today() -> (u32, u32, u32) {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (year, month, day) = logging::civil_from_days(secs.div_euclid(86_400));
+    (year as u32, month, day)
+}
+
+/// Checks the installed rustc against `requirements`, and warns about each
+/// one that isn't met. These aren't fatal, since `rustc` versions can be
+/// patched in ways we can't see.
+pub fn rust_version_check(
+    wrapper: &cli::TextWrapper,
+    requirements: &[RustVersionRequirement],
+) -> Result<RustVersion, RustVersionError> {
+    let version = RustVersion::check()?;
+    let mut unmet = false;
+    for requirement in requirements {
+        if !requirement.satisfied_by(&version) {
+            unmet = true;
+            requirement.report(&version).print(wrapper);
+        }
+    }
+    let years_old = version.years_old(today());
+    // One report is enough when we're already telling them to update.
+    if !unmet && years_old >= OLD_RUST_YEARS {
+        Report::action_request(
+            format!(
+                "Your rustc is {} year{} old",
+                years_old,
+                if years_old == 1 { "" } else { "s" }
+            ),
+            format!(
+                "You're on {}. Newer platform SDKs often need newer Rust, so if you run into build problems, try updating first:\n`rustup update stable && rustup default stable`",
+                version
+            ),
+        )
+        .print(wrapper);
+    }
+    Ok(version)
+}
+
 pub fn prepend_to_path(path: impl Display, base_path: impl Display) -> String {
     format!("{}:{}", path, base_path)
 }
@@ -586,6 +759,62 @@ pub fn open_in_editor(path: impl AsRef<Path>) -> Result<(), OpenInEditorError> {
         .map_err(OpenInEditorError::OpenFailed)
 }
 
+#[cfg(target_os = "macos")]
+#[derive(Debug, Error)]
+pub enum OpenInXcodeError {
+    #[error("Failed to resolve path {path:?}: {source}")]
+    PathInvalid { path: PathBuf, source: io::Error },
+    #[error("File {file:?} isn't inside the app root {root:?}")]
+    FileOutsideRoot { file: PathBuf, root: PathBuf },
+    #[error("Failed to check for presence of `xed`: {0}")]
+    XedCheckFailed(bossy::Error),
+    #[error("`xed` wasn't found, so the project was opened without jumping to {file:?}; installing the Xcode command line tools with `xcode-select --install` should fix this")]
+    XedMissing { file: PathBuf },
+    #[error("Failed to launch Xcode: {0}")]
+    LaunchFailed(bossy::Error),
+}
+
+/// Opens `project` in Xcode, jumping to `line` of `file` if given. `file`
+/// has to be inside `root`. Without `xed` we can't jump anywhere, so we open
+/// the project instead, but still fail with `XedMissing` so the caller knows
+/// why it didn't jump.
+#[cfg(target_os = "macos")]
+pub fn open_in_xcode_at(
+    project: &Path,
+    root: &Path,
+    file: Option<&Path>,
+    line: Option<u32>,
+) -> Result<(), OpenInXcodeError> {
+    let canonicalize = |path: &Path| {
+        path.canonicalize()
+            .map_err(|source| OpenInXcodeError::PathInvalid {
+                path: path.to_owned(),
+                source,
+            })
+    };
+    if let Some(file) = file {
+        let file = canonicalize(file)?;
+        let root = canonicalize(root)?;
+        if !file.starts_with(&root) {
+            return Err(OpenInXcodeError::FileOutsideRoot { file, root });
+        }
+        if command_present("xed").map_err(OpenInXcodeError::XedCheckFailed)? {
+            let mut command = bossy::Command::impure("xed");
+            if let Some(line) = line {
+                command.add_arg("--line").add_arg(line.to_string());
+            }
+            return command
+                .with_arg(&file)
+                .run_and_wait()
+                .map(|_| ())
+                .map_err(OpenInXcodeError::LaunchFailed);
+        }
+        os::open_file_with("Xcode", project).map_err(OpenInXcodeError::LaunchFailed)?;
+        return Err(OpenInXcodeError::XedMissing { file });
+    }
+    os::open_file_with("Xcode", project).map_err(OpenInXcodeError::LaunchFailed)
+}
+
 #[derive(Debug, Error)]
 pub enum InstalledCommitMsgError {
     #[error(transparent)]
@@ -594,17 +823,39 @@ pub enum InstalledCommitMsgError {
     ReadFailed { path: PathBuf, source: io::Error },
 }
 
-pub fn installed_commit_msg() -> Result<Option<String>, InstalledCommitMsgError> {
-    let path = install_dir()?.join("commit");
+/// The commit this binary was built from, if `build.rs` could tell.
+pub static COMPILED_COMMIT_MSG: Option<&str> = option_env!("CARGO_MOBILE_COMMIT_MSG");
+pub static COMPILED_COMMIT_HASH: Option<&str> = option_env!("CARGO_MOBILE_COMMIT_HASH");
+
+/// Reads the stamp at `path`, or gives back `fallback` if there isn't one,
+/// which is the case for prebuilt binaries until their templates are
+/// installed.
+fn read_stamp(
+    path: PathBuf,
+    fallback: Option<&str>,
+) -> Result<Option<String>, InstalledCommitMsgError> {
     if path.is_file() {
         std::fs::read_to_string(&path)
             .map(Some)
             .map_err(|source| InstalledCommitMsgError::ReadFailed { path, source })
     } else {
-        Ok(None)
+        Ok(fallback.map(ToOwned::to_owned))
     }
 }
 
+pub fn installed_commit_msg() -> Result<Option<String>, InstalledCommitMsgError> {
+    read_stamp(install_dir()?.join("commit"), COMPILED_COMMIT_MSG)
+}
+
+/// The commit the installed templates were built from, which is what
+/// generated projects get stamped with.
+pub fn installed_commit_hash() -> Result<Option<String>, InstalledCommitMsgError> {
+    read_stamp(install_dir()?.join("commit-hash"), COMPILED_COMMIT_HASH).map(|hash| {
+        hash.map(|hash| hash.trim().to_owned())
+            .filter(|hash| !hash.is_empty())
+    })
+}
+
 pub fn format_commit_msg(msg: String) -> String {
     format!("Contains commits up to {:?}", msg)
 }
@@ -657,7 +908,9 @@ where
     Ok(result)
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+/// Serializes the same way it deserializes: a bare value for one, and a
+/// sequence for many.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(untagged)]
 pub enum OneOrMany<T: Debug> {
     One(T),
@@ -673,15 +926,111 @@ impl<T: Debug> From<OneOrMany<T>> for Vec<T> {
     }
 }
 
-impl<T: Debug> Serialize for OneOrMany<T> {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let serialized_str = match self {
-            Self::One(one) => format!("{:?}", one),
-            Self::Many(vec) => format!("{:?}", vec),
-        };
-        serializer.serialize_str(&serialized_str)
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn stamps_fall_back_to_compiled_in() {
+        let dir = TempDirGuard::new().unwrap();
+        let path = dir.join("commit");
+        assert_eq!(
+            read_stamp(path.clone(), Some("Add a thing"))
+                .unwrap()
+                .as_deref(),
+            Some("Add a thing")
+        );
+        assert_eq!(read_stamp(path.clone(), None).unwrap(), None);
+        std::fs::write(&path, "Add another thing").unwrap();
+        assert_eq!(
+            read_stamp(path, Some("Add a thing")).unwrap().as_deref(),
+            Some("Add another thing")
+        );
+    }
+
+    #[rstest(
+        input,
+        major,
+        minor,
+        patch,
+        display,
+        case("13", 13, 0, None, "13.0"),
+        case("12.6", 12, 6, None, "12.6"),
+        case("12.6.1", 12, 6, Some(1), "12.6.1"),
+        case("10.04", 10, 4, None, "10.04")
+    )]
+    fn test_version_double_parse(
+        input: &str,
+        major: u32,
+        minor: u32,
+        patch: Option<u32>,
+        display: &str,
+    ) {
+        let version = VersionDouble::from_str(input).unwrap();
+        assert_eq!(version.major, major);
+        assert_eq!(version.minor, minor);
+        assert_eq!(version.patch, patch);
+        assert_eq!(version.to_string(), display);
+    }
+
+    #[rstest(input, case("12.6.1.2"), case("12.x"), case(""))]
+    fn test_version_double_reject(input: &str) {
+        assert!(VersionDouble::from_str(input).is_err());
+    }
+
+    #[rstest(
+        raw,
+        epoch,
+        case("1700000000", Some(1_700_000_000)),
+        case(" 0\n", Some(0)),
+        case("", None),
+        case("-1", None),
+        case("2023-11-14", None)
+    )]
+    fn parses_source_date_epoch(raw: &str, epoch: Option<u64>) {
+        assert_eq!(parse_source_date_epoch(raw), epoch);
+    }
+
+    fn rust_version(minor: u32, date: Option<(u32, u32, u32)>) -> RustVersion {
+        RustVersion {
+            triple: VersionTriple::new(1, minor, 0),
+            flavor: None,
+            details: date.map(|date| RustVersionDetails {
+                hash: "0123456789".to_owned(),
+                date,
+            }),
+        }
+    }
+
+    #[rstest(
+        minor,
+        date,
+        today,
+        years_old,
+        case(70, Some((2023, 5, 31)), (2023, 6, 1), 0),
+        case(70, Some((2023, 5, 31)), (2024, 5, 30), 0),
+        case(70, Some((2023, 5, 31)), (2024, 5, 31), 1),
+        case(70, Some((2023, 5, 31)), (2026, 10, 14), 3),
+        // 1.70.0 was actually released on 2023-06-01.
+        case(70, None, (2025, 6, 2), 2),
+        case(70, None, (2022, 1, 1), 0)
+    )]
+    fn test_rust_version_years_old(
+        minor: u32,
+        date: Option<(u32, u32, u32)>,
+        today: (u32, u32, u32),
+        years_old: u32,
+    ) {
+        assert_eq!(rust_version(minor, date).years_old(today), years_old);
+    }
+
+    #[test]
+    fn test_version_double_ordering() {
+        let parse = |v| VersionDouble::from_str(v).unwrap();
+        assert_eq!(parse("10.04"), parse("10.4"));
+        assert!(parse("12.6") < parse("12.6.1"));
+        assert_eq!(parse("12.6"), parse("12.6.0"));
+        assert!(parse("9.3") < parse("10.0"));
     }
 }
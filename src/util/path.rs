@@ -1,5 +1,6 @@
 use path_abs::PathAbs;
 use std::{
+    ffi::OsString,
     fmt::{self, Display},
     io,
     path::{Path, PathBuf},
@@ -15,10 +16,9 @@ pub fn home_dir() -> Result<PathBuf, NoHomeDir> {
 }
 
 pub fn expand_home(path: impl AsRef<Path>) -> Result<PathBuf, NoHomeDir> {
-    let home = home_dir()?;
     let path = path.as_ref();
     if let Ok(path) = path.strip_prefix("~") {
-        Ok(home.join(path))
+        Ok(home_dir()?.join(path))
     } else {
         Ok(path.to_owned())
     }
@@ -39,13 +39,45 @@ pub fn contract_home(path: impl AsRef<Path>) -> Result<String, ContractHomeError
         .as_ref()
         .to_str()
         .ok_or(ContractHomeError::PathInvalidUtf8)?;
-    let home = home_dir()?;
+    // This is only for display, so there's nothing to contract without a home.
+    let home = match home_dir() {
+        Ok(home) => home,
+        Err(_) => return Ok(path.to_owned()),
+    };
     let home = home.to_str().ok_or(ContractHomeError::HomeInvalidUtf8)?;
     Ok(path.replace(home, "~").to_owned())
 }
 
+/// Overrides the install dir, for when there's no usable home dir (i.e. in
+/// containers, where `HOME` can be unset or read-only).
+pub static INSTALL_DIR_OVERRIDE_VAR: &str = "CARGO_MOBILE_HOME";
+
+fn install_dir_override_from(var: Option<OsString>) -> Option<PathBuf> {
+    var.filter(|dir| !dir.is_empty()).map(PathBuf::from)
+}
+
+/// `CARGO_MOBILE_HOME`, if it's set to anything.
+pub fn install_dir_override() -> Option<PathBuf> {
+    install_dir_override_from(std::env::var_os(INSTALL_DIR_OVERRIDE_VAR))
+}
+
+/// `install_dir`, given what `CARGO_MOBILE_HOME` is set to and where the
+/// home dir is.
+pub fn install_dir_from(
+    var: Option<OsString>,
+    home: Result<PathBuf, NoHomeDir>,
+) -> Result<PathBuf, NoHomeDir> {
+    match install_dir_override_from(var) {
+        Some(dir) => Ok(dir),
+        None => home.map(|home| home.join(concat!(".", env!("CARGO_PKG_NAME")))),
+    }
+}
+
+/// Where everything cargo-mobile writes outside of projects goes, which is
+/// `~/.cargo-mobile` unless `CARGO_MOBILE_HOME` is set. Everything else
+/// we write outside of projects is under here, so they all move with it.
 pub fn install_dir() -> Result<PathBuf, NoHomeDir> {
-    home_dir().map(|home| home.join(concat!(".", env!("CARGO_PKG_NAME"))))
+    install_dir_from(std::env::var_os(INSTALL_DIR_OVERRIDE_VAR), home_dir())
 }
 
 pub fn checkouts_dir() -> Result<PathBuf, NoHomeDir> {
@@ -56,8 +88,18 @@ pub fn tools_dir() -> Result<PathBuf, NoHomeDir> {
     install_dir().map(|install_dir| install_dir.join("tools"))
 }
 
-pub fn temp_dir() -> PathBuf {
-    std::env::temp_dir().join("com.brainiumstudios.cargo-mobile")
+/// Where cargo keeps its global config and installs, which follows
+/// `CARGO_HOME` like cargo does.
+pub fn cargo_home() -> Result<PathBuf, NoHomeDir> {
+    match std::env::var_os("CARGO_HOME").filter(|dir| !dir.is_empty()) {
+        Some(cargo_home) => Ok(PathBuf::from(cargo_home)),
+        None => home_dir().map(|home| home.join(".cargo")),
+    }
+}
+
+/// Where cargo installs binaries.
+pub fn cargo_bin_dir() -> Result<PathBuf, NoHomeDir> {
+    cargo_home().map(|cargo_home| cargo_home.join("bin"))
 }
 
 #[derive(Debug)]
@@ -185,3 +227,37 @@ pub fn under_root(
 ) -> Result<bool, NormalizationError> {
     normalize_path(root.as_ref().join(path)).map(|norm| norm.starts_with(root))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn install_dir_override_wins() {
+        let home = || Ok(PathBuf::from("/home/jane"));
+        assert_eq!(
+            install_dir_from(Some("/opt/cargo-mobile".into()), home()).unwrap(),
+            Path::new("/opt/cargo-mobile")
+        );
+        // Set but empty is the same as not set.
+        assert_eq!(
+            install_dir_from(Some("".into()), home()).unwrap(),
+            Path::new("/home/jane/.cargo-mobile")
+        );
+        assert_eq!(
+            install_dir_from(None, home()).unwrap(),
+            Path::new("/home/jane/.cargo-mobile")
+        );
+        // The home dir isn't needed when there's an override.
+        assert!(install_dir_from(Some("/opt/cargo-mobile".into()), Err(NoHomeDir)).is_ok());
+        assert!(install_dir_from(None, Err(NoHomeDir)).is_err());
+    }
+
+    #[test]
+    fn expanding_needs_no_home_without_tilde() {
+        assert_eq!(
+            expand_home("/opt/android-sdk").unwrap(),
+            Path::new("/opt/android-sdk")
+        );
+    }
+}
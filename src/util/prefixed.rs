@@ -0,0 +1,126 @@
+//! Tags each line of a child's output with a colored `[label]`, so output
+//! from several consecutive invocations (i.e. one per target) can be told
+//! apart.
+
+use super::lines;
+use colored::{Color, Colorize as _};
+use once_cell_regex::regex;
+use std::sync::Mutex;
+
+static COLORS: &[Color] = &[
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Green,
+    Color::Blue,
+];
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Counts {
+    pub warnings: usize,
+    pub errors: usize,
+}
+
+impl Counts {
+    fn record(&mut self, line: &str) {
+        // Cargo forces color on, so we have to look past the escape codes.
+        let line = regex!(r"\x1b\[[0-9;]*m").replace_all(line, "");
+        // Cargo's own recaps would otherwise count everything twice.
+        if regex!(r"generated \d+ warnings?|^error: could not compile").is_match(&line) {
+            return;
+        }
+        if let Some(caps) = regex!(r"^(warning|error)(?:\[\w+\])?: ").captures(&line) {
+            if &caps[1] == "warning" {
+                self.warnings += 1;
+            } else {
+                self.errors += 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Prefix {
+    label: String,
+    color: Color,
+    // Both of a child's streams can be emitted at once.
+    counts: Mutex<Counts>,
+}
+
+impl Prefix {
+    /// `index` picks the color, so give each label its own.
+    pub fn new(label: impl Into<String>, index: usize) -> Self {
+        Self {
+            label: label.into(),
+            color: COLORS[index % COLORS.len()],
+            counts: Default::default(),
+        }
+    }
+
+    pub fn counts(&self) -> Counts {
+        *self.counts.lock().unwrap()
+    }
+
+    pub fn emit(&self, line: &str, to_stderr: bool) {
+        self.counts.lock().unwrap().record(line);
+        let prefix = format!("[{}]", self.label).color(self.color).bold();
+        if to_stderr {
+            eprintln!("{} {}", prefix, line);
+        } else {
+            println!("{} {}", prefix, line);
+        }
+    }
+
+    /// Runs `command` with its output piped through us.
+    pub fn run_and_wait(&self, command: bossy::Command) -> bossy::Result<()> {
+        let mut handle = command.with_stdout_piped().with_stderr_piped().run()?;
+        lines::read_both(
+            &mut handle,
+            |line| self.emit(line, false),
+            |line| self.emit(line, true),
+        );
+        handle.wait_for_output().map(|_| ())
+    }
+}
+
+/// Prints the warning and error counts for each prefix.
+pub fn print_summary(prefixes: &[&Prefix]) {
+    for prefix in prefixes {
+        let Counts { warnings, errors } = prefix.counts();
+        println!(
+            "{} {} warning{}, {} error{}",
+            format!("[{}]", prefix.label).color(prefix.color).bold(),
+            warnings,
+            if warnings == 1 { "" } else { "s" },
+            errors,
+            if errors == 1 { "" } else { "s" },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_diagnostics_but_not_recaps() {
+        let mut counts = Counts::default();
+        for line in &[
+            "   Compiling game v0.1.0",
+            "\x1b[0m\x1b[1m\x1b[33mwarning\x1b[0m\x1b[0m\x1b[1m: unused variable: `x`\x1b[0m",
+            "warning: field is never read",
+            "error[E0308]: mismatched types",
+            "warning: `game` (lib) generated 2 warnings",
+            "error: could not compile `game` due to previous error; 2 warnings emitted",
+        ] {
+            counts.record(line);
+        }
+        assert_eq!(
+            counts,
+            Counts {
+                warnings: 2,
+                errors: 1
+            }
+        );
+    }
+}
@@ -1,33 +1,214 @@
+use super::{
+    cli::{Report, Reportable},
+    events,
+};
 use colored::{Color, Colorize as _};
 use std::{
-    fmt::Display,
-    io::{self, Write},
+    fmt::{self, Display},
+    io::{self, IsTerminal as _, Write},
 };
 use yes_or_no::yes_or_no;
 
-pub fn minimal(msg: impl Display) -> io::Result<String> {
+/// Set this to answer every yes/no prompt with yes, for scripts that can't
+/// pass flags.
+pub static ASSUME_YES_ENV_VAR: &str = "CARGO_MOBILE_ASSUME_YES";
+
+/// Why nobody's around to answer a prompt.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NotInteractive {
+    NotATty,
+    Ci,
+    DumbTerm,
+}
+
+impl Display for NotInteractive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotATty => write!(f, "stdin isn't a terminal"),
+            Self::Ci => write!(f, "`CI` is set"),
+            Self::DumbTerm => write!(f, "`TERM` is `dumb`"),
+        }
+    }
+}
+
+fn env_flag(value: Option<&str>) -> bool {
+    value
+        .map(|value| !matches!(value.trim(), "" | "0" | "false" | "no"))
+        .unwrap_or_default()
+}
+
+/// The reason prompting would hang, if there is one. `var` looks up
+/// environment variables, so the logic can be tested without touching ours.
+fn detect(stdin_is_tty: bool, var: impl Fn(&str) -> Option<String>) -> Option<NotInteractive> {
+    if !stdin_is_tty {
+        Some(NotInteractive::NotATty)
+    } else if env_flag(var("CI").as_deref()) {
+        Some(NotInteractive::Ci)
+    } else if var("TERM").as_deref() == Some("dumb") {
+        Some(NotInteractive::DumbTerm)
+    } else {
+        None
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Whether `CARGO_MOBILE_ASSUME_YES` is set, which `yes_no` answers for.
+pub fn assume_yes() -> bool {
+    env_flag(env_var(ASSUME_YES_ENV_VAR).as_deref())
+}
+
+/// How to answer a prompt without being asked, given the config `key` (like
+/// `app.name`), flag (like `--team`), or environment variable that can.
+fn how_to_answer(key: Option<&str>) -> String {
+    let answer = match key {
+        Some("--non-interactive") => return "Pass `--non-interactive` to go with defaults.".into(),
+        Some(flag) if flag.starts_with("--") => format!("Pass `{}`", flag),
+        Some(var) if var.chars().all(|c| c.is_ascii_uppercase() || c == '_') => {
+            format!("Set `{}=1`", var)
+        }
+        Some(key) => format!(
+            "Set `{}` in `{}` (or pass `--config {}=<value>`)",
+            key,
+            crate::config::file_name(),
+            key
+        ),
+        None => "Run this from a terminal".to_owned(),
+    };
+    format!(
+        "{}, or pass `--non-interactive` to go with defaults.",
+        answer
+    )
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NotInteractive {
+        msg: String,
+        key: Option<String>,
+        reason: NotInteractive,
+    },
+    PromptFailed {
+        msg: String,
+        cause: io::Error,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotInteractive { msg, key, reason } => write!(
+                f,
+                "Needed an answer to {:?}, but can't prompt since {}. {}",
+                msg,
+                reason,
+                how_to_answer(key.as_deref())
+            ),
+            Self::PromptFailed { cause, .. } => write!(f, "{}", cause),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::NotInteractive { msg, key, reason } => Report::error(
+                format!("Can't prompt for {:?}, since {}", msg, reason),
+                how_to_answer(key.as_deref()),
+            ),
+            Self::PromptFailed { msg, cause } => {
+                Report::error(format!("Failed to prompt for {:?}", msg), cause)
+            }
+        }
+    }
+}
+
+// For the places that only deal in `io::Error`s; the message keeps the key.
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::PromptFailed { cause, .. } => cause,
+            err => io::Error::new(io::ErrorKind::Other, err),
+        }
+    }
+}
+
+impl Error {
+    fn prompt_failed(msg: impl Display) -> impl FnOnce(io::Error) -> Self {
+        let msg = msg.to_string();
+        move |cause| Self::PromptFailed { msg, cause }
+    }
+}
+
+fn not_interactive(msg: &str, key: Option<&str>, reason: NotInteractive) -> Error {
+    Error::NotInteractive {
+        msg: msg.to_owned(),
+        key: key.map(ToOwned::to_owned),
+        reason,
+    }
+}
+
+/// Fails right away if nobody can answer `msg`, since otherwise we'd wait on
+/// stdin until the CI job times out.
+fn ensure_interactive(msg: &str, key: Option<&str>) -> Result<(), Error> {
+    match detect(io::stdin().is_terminal(), env_var) {
+        Some(reason) => Err(not_interactive(msg, key, reason)),
+        None => Ok(()),
+    }
+}
+
+// Every prompt takes an `id`, which is how the event stream tells prompts
+// apart. Those are part of our interface, so never change one once it's
+// shipped; messages can change freely. The `key` is whatever can answer the
+// prompt up front, which we point to when there's nobody around to ask.
+
+fn ask(id: &str, key: Option<&str>, msg: impl Display) -> Result<String, Error> {
+    let msg = msg.to_string();
+    if events::enabled() {
+        return events::prompt(id, &msg, None, None).map_err(Error::prompt_failed(&msg));
+    }
+    ensure_interactive(&msg, key)?;
     let mut input = String::new();
     print!("{}: ", msg);
-    io::stdout().flush()?;
-    io::stdin().read_line(&mut input)?;
+    io::stdout()
+        .flush()
+        .and_then(|()| io::stdin().read_line(&mut input))
+        .map_err(Error::prompt_failed(&msg))?;
     input = input.trim().to_owned();
     Ok(input)
 }
 
-pub fn default(
+fn ask_with_default(
+    id: &str,
+    key: Option<&str>,
     msg: impl Display,
     default: Option<&str>,
     default_color: Option<Color>,
-) -> io::Result<String> {
+) -> Result<String, Error> {
+    if events::enabled() {
+        return events::prompt(id, &msg.to_string(), default, None)
+            .map(|response| {
+                if response.is_empty() {
+                    default.map(ToOwned::to_owned).unwrap_or(response)
+                } else {
+                    response
+                }
+            })
+            .map_err(Error::prompt_failed(&msg));
+    }
     if let Some(default) = default {
         let msg = if let Some(default_color) = default_color {
             format!("{} ({})", msg, default.color(default_color).bold())
         } else {
             format!("{} ({})", msg, default)
         };
-        minimal(msg)
+        ask(id, key, msg)
     } else {
-        minimal(msg)
+        ask(id, key, msg)
     }
     .map(|response| {
         if response.is_empty() && default.is_some() {
@@ -38,15 +219,42 @@ pub fn default(
     })
 }
 
+pub fn minimal(id: &str, key: &str, msg: impl Display) -> Result<String, Error> {
+    ask(id, Some(key), msg)
+}
+
+pub fn default(
+    id: &str,
+    key: &str,
+    msg: impl Display,
+    default: Option<&str>,
+    default_color: Option<Color>,
+) -> Result<String, Error> {
+    ask_with_default(id, Some(key), msg, default, default_color)
+}
+
 yes_or_no!(pub YesOrNo);
 
-pub fn yes_no(msg: impl Display, default: Option<YesOrNo>) -> io::Result<Option<YesOrNo>> {
+pub fn yes_no(
+    id: &str,
+    key: &str,
+    msg: impl Display,
+    default: Option<YesOrNo>,
+) -> Result<Option<YesOrNo>, Error> {
     let y_n = match default {
         Some(YesOrNo::Yes) => "[Y/n]",
         Some(YesOrNo::No) => "[y/N]",
         None => "[y/n]",
     };
-    minimal(&format!("{} {}", msg, y_n)).map(|response| {
+    if assume_yes() {
+        log::info!(
+            "answering yes to {:?}, since `{}` is set",
+            msg.to_string(),
+            ASSUME_YES_ENV_VAR
+        );
+        return Ok(Some(YesOrNo::Yes));
+    }
+    minimal(id, key, &format!("{} {}", msg, y_n)).map(|response| {
         if response.eq_ignore_ascii_case("y") {
             Some(YesOrNo::Yes)
         } else if response.eq_ignore_ascii_case("n") {
@@ -54,7 +262,7 @@ pub fn yes_no(msg: impl Display, default: Option<YesOrNo>) -> io::Result<Option<
         } else if response.is_empty() {
             default
         } else {
-            println!("That was neither a Y nor an N! You're pretty silly.");
+            events::say("That was neither a Y nor an N! You're pretty silly.");
             None
         }
     })
@@ -70,16 +278,202 @@ pub fn list_display_only(choices: impl Iterator<Item = impl Display>, choice_cou
     }
 }
 
+/// Lists longer than this are shown a page at a time, and can be filtered.
+pub const PAGE_SIZE: usize = 15;
+
+#[derive(Debug, Eq, PartialEq)]
+enum Input {
+    Index(usize),
+    NextPage,
+    PrevPage,
+    Filter(String),
+    Empty,
+}
+
+fn parse_input(response: &str) -> Input {
+    let response = response.trim();
+    if response.is_empty() {
+        Input::Empty
+    } else if let Ok(index) = response.parse::<usize>() {
+        Input::Index(index)
+    } else if response.eq_ignore_ascii_case("n") {
+        Input::NextPage
+    } else if response.eq_ignore_ascii_case("p") {
+        Input::PrevPage
+    } else {
+        Input::Filter(response.to_owned())
+    }
+}
+
+/// Indices into `labels` of the ones containing `filter`, ignoring case.
+fn filter_indices(labels: &[String], filter: &str) -> Vec<usize> {
+    let filter = filter.to_lowercase();
+    labels
+        .iter()
+        .enumerate()
+        .filter(|(_, label)| label.to_lowercase().contains(&filter))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Which choices `list` is showing. Everything is tracked by index into the
+/// full list, so filtering never changes what an index means.
+#[derive(Debug)]
+struct Pager {
+    visible: Vec<usize>,
+    page: usize,
+    filter: Option<String>,
+}
+
+impl Pager {
+    fn new(choice_count: usize) -> Self {
+        Self {
+            visible: (0..choice_count).collect(),
+            page: 0,
+            filter: None,
+        }
+    }
+
+    fn page_count(&self) -> usize {
+        ((self.visible.len() + PAGE_SIZE - 1) / PAGE_SIZE).max(1)
+    }
+
+    fn current_page(&self) -> &[usize] {
+        let start = self.page * PAGE_SIZE;
+        let end = (start + PAGE_SIZE).min(self.visible.len());
+        &self.visible[start..end]
+    }
+
+    fn next_page(&mut self) -> bool {
+        let turned = self.page + 1 < self.page_count();
+        if turned {
+            self.page += 1;
+        }
+        turned
+    }
+
+    fn prev_page(&mut self) -> bool {
+        let turned = self.page > 0;
+        if turned {
+            self.page -= 1;
+        }
+        turned
+    }
+
+    /// Leaves things as they were if nothing matches, so a typo doesn't
+    /// leave you looking at an empty list.
+    fn filter(&mut self, labels: &[String], filter: &str) -> bool {
+        let visible = filter_indices(labels, filter);
+        let matched = !visible.is_empty();
+        if matched {
+            self.visible = visible;
+            self.page = 0;
+            self.filter = Some(filter.to_owned());
+        }
+        matched
+    }
+
+    fn clear_filter(&mut self, choice_count: usize) {
+        *self = Self::new(choice_count);
+    }
+
+    /// The one choice left after filtering, which becomes the default.
+    fn only_match(&self) -> Option<usize> {
+        match self.visible.as_slice() {
+            [index] if self.filter.is_some() => Some(*index),
+            _ => None,
+        }
+    }
+
+    fn print(&self, labels: &[String]) {
+        for index in self.current_page() {
+            println!("  [{}] {}", index.to_string().green(), labels[*index]);
+        }
+        if self.page_count() > 1 {
+            println!(
+                "  Page {} of {}; enter {} or {} to see more.",
+                self.page + 1,
+                self.page_count(),
+                "n".cyan(),
+                "p".cyan(),
+            );
+        }
+        if let Some(filter) = &self.filter {
+            println!(
+                "  Showing matches for {:?}; enter nothing to show everything.",
+                filter
+            );
+        }
+    }
+}
+
 pub fn list(
+    id: &str,
+    header: impl Display,
+    choices: impl ExactSizeIterator<Item = impl Display>,
+    noun: impl Display,
+    alternative: Option<&str>,
+    msg: impl Display,
+) -> Result<usize, Error> {
+    list_with_default(id, header, choices, noun, alternative, msg, None)
+}
+
+// The event stream gets the whole list at once, and an index back.
+fn list_via_events(
+    id: &str,
+    labels: &[String],
+    msg: impl Display,
+    default: Option<usize>,
+) -> Result<usize, Error> {
+    let default = default
+        .filter(|index| *index < labels.len())
+        .map(|index| index.to_string());
+    let response = events::prompt(id, &msg.to_string(), default.as_deref(), Some(labels))
+        .map_err(Error::prompt_failed(&msg))?;
+    let response = if response.is_empty() {
+        default.unwrap_or(response)
+    } else {
+        response
+    };
+    match parse_input(&response) {
+        Input::Index(index) if index < labels.len() => Ok(index),
+        _ => Err(Error::PromptFailed {
+            msg: msg.to_string(),
+            cause: io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{:?} isn't an index into the {} choices",
+                    response,
+                    labels.len()
+                ),
+            ),
+        }),
+    }
+}
+
+/// Like `list`, but just pressing enter picks the choice at `default`.
+pub fn list_with_default(
+    id: &str,
     header: impl Display,
     choices: impl ExactSizeIterator<Item = impl Display>,
     noun: impl Display,
     alternative: Option<&str>,
     msg: impl Display,
-) -> io::Result<usize> {
+    default: Option<usize>,
+) -> Result<usize, Error> {
+    let labels = choices.map(|choice| choice.to_string()).collect::<Vec<_>>();
+    if events::enabled() {
+        return list_via_events(id, &labels, msg, default);
+    }
     println!("{}:", header);
-    let choice_count = choices.len();
-    list_display_only(choices, choice_count);
+    let choice_count = labels.len();
+    let paged = choice_count > PAGE_SIZE;
+    let mut pager = Pager::new(choice_count);
+    if paged {
+        pager.print(&labels);
+    } else {
+        list_display_only(labels.iter(), choice_count);
+    }
     if let Some(alternative) = alternative {
         println!(
             "  Enter an {} for a {} above, or enter a {} manually.",
@@ -90,24 +484,202 @@ pub fn list(
     } else {
         println!("  Enter an {} for a {} above.", "index".green(), noun);
     }
+    if paged {
+        println!("  You can also enter part of a name to only show matches.");
+    }
+    let default_index = default
+        .filter(|index| *index < choice_count)
+        .map(|index| index.to_string());
     loop {
-        let response = default(
+        let only_match = pager.only_match().map(|index| index.to_string());
+        // There's nothing to set for picking from what was just detected.
+        let response = ask_with_default(
+            id,
+            None,
             &msg,
-            if choice_count == 1 { Some("0") } else { None },
+            if choice_count == 1 {
+                Some("0")
+            } else {
+                only_match
+                    .as_deref()
+                    .or_else(|| default_index.as_deref().filter(|_| pager.filter.is_none()))
+            },
             Some(Color::Green),
         )?;
-        if !response.is_empty() {
-            if let Some(index) = response.parse::<usize>().ok() {
-                if index < choice_count {
-                    return Ok(index);
+        match parse_input(&response) {
+            Input::Index(index) if index < choice_count => return Ok(index),
+            Input::Index(_) => println!("There's no {} with an index that high.", noun),
+            Input::NextPage if paged => {
+                if pager.next_page() {
+                    pager.print(&labels);
                 } else {
-                    println!("There's no device with an index that high.");
+                    println!("That's the last page.");
                 }
-            } else {
-                println!("Hey, that wasn't a number! You're silly.");
             }
-        } else {
-            println!("Not to be pushy, but you need to pick a device.");
+            Input::PrevPage if paged => {
+                if pager.prev_page() {
+                    pager.print(&labels);
+                } else {
+                    println!("That's the first page.");
+                }
+            }
+            Input::Filter(filter) if paged => {
+                if pager.filter(&labels, &filter) {
+                    pager.print(&labels);
+                } else {
+                    println!("Nothing matches {:?}.", filter);
+                }
+            }
+            Input::Empty if pager.filter.is_some() => {
+                pager.clear_filter(choice_count);
+                pager.print(&labels);
+            }
+            Input::Empty => println!("Not to be pushy, but you need to pick a {}.", noun),
+            Input::NextPage | Input::PrevPage | Input::Filter(_) => {
+                println!("Hey, that wasn't a number! You're silly.")
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        tty,
+        ci,
+        term,
+        expected,
+        case(true, None, Some("xterm-256color"), None),
+        case(false, None, Some("xterm-256color"), Some(NotInteractive::NotATty)),
+        case(false, Some("true"), Some("dumb"), Some(NotInteractive::NotATty)),
+        case(true, Some("true"), None, Some(NotInteractive::Ci)),
+        case(true, Some("1"), Some("dumb"), Some(NotInteractive::Ci)),
+        case(true, Some("false"), None, None),
+        case(true, Some(""), Some("xterm"), None),
+        case(true, None, Some("dumb"), Some(NotInteractive::DumbTerm))
+    )]
+    fn detects_non_interactive_environments(
+        tty: bool,
+        ci: Option<&str>,
+        term: Option<&str>,
+        expected: Option<NotInteractive>,
+    ) {
+        let var = |name: &str| match name {
+            "CI" => ci.map(ToOwned::to_owned),
+            "TERM" => term.map(ToOwned::to_owned),
+            _ => None,
+        };
+        assert_eq!(detect(tty, var), expected);
+    }
+
+    #[rstest(
+        key,
+        expected,
+        case(
+            Some("app.name"),
+            "Set `app.name` in `mobile.toml` (or pass `--config app.name=<value>`)"
+        ),
+        case(Some("--team"), "Pass `--team`, or"),
+        case(
+            Some("--non-interactive"),
+            "Pass `--non-interactive` to go with defaults."
+        ),
+        case(Some(ASSUME_YES_ENV_VAR), "Set `CARGO_MOBILE_ASSUME_YES=1`"),
+        case(None, "Run this from a terminal")
+    )]
+    fn not_interactive_errors_say_how_to_answer(key: Option<&str>, expected: &str) {
+        let err = not_interactive("Project name", key, NotInteractive::Ci);
+        let report = err.report();
+        assert_eq!(
+            report.msg(),
+            "Can't prompt for \"Project name\", since `CI` is set"
+        );
+        assert!(
+            report.details().starts_with(expected),
+            "{:?} doesn't start with {:?}",
+            report.details(),
+            expected
+        );
+        assert!(err.to_string().contains(expected));
+    }
+
+    #[rstest(
+        value,
+        expected,
+        case(None, false),
+        case(Some(""), false),
+        case(Some("0"), false),
+        case(Some("false"), false),
+        case(Some("1"), true),
+        case(Some("yes"), true)
+    )]
+    fn reads_env_flags(value: Option<&str>, expected: bool) {
+        assert_eq!(env_flag(value), expected);
+    }
+
+    fn labels(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|index| {
+                if index % 10 == 3 {
+                    format!("iPad Pro {}", index)
+                } else {
+                    format!("iPhone {}", index)
+                }
+            })
+            .collect()
+    }
+
+    #[rstest(
+        response,
+        input,
+        case("", Input::Empty),
+        case(" 12 ", Input::Index(12)),
+        case("N", Input::NextPage),
+        case("p", Input::PrevPage),
+        case("ipad", Input::Filter("ipad".to_owned()))
+    )]
+    fn parses_input(response: &str, input: Input) {
+        assert_eq!(parse_input(response), input);
+    }
+
+    #[test]
+    fn filters_case_insensitively_by_original_index() {
+        assert_eq!(filter_indices(&labels(40), "IPAD"), vec![3, 13, 23, 33]);
+        assert_eq!(filter_indices(&labels(40), "pro 2"), vec![23]);
+        assert!(filter_indices(&labels(40), "pixel").is_empty());
+    }
+
+    #[test]
+    fn pages_through_everything() {
+        let mut pager = Pager::new(40);
+        assert_eq!(pager.page_count(), 3);
+        assert_eq!(pager.current_page(), &(0..15).collect::<Vec<_>>()[..]);
+        assert!(!pager.prev_page());
+        assert!(pager.next_page());
+        assert!(pager.next_page());
+        assert_eq!(pager.current_page(), &(30..40).collect::<Vec<_>>()[..]);
+        assert!(!pager.next_page());
+    }
+
+    #[test]
+    fn filtering_keeps_indices_and_resets_pages() {
+        let labels = labels(40);
+        let mut pager = Pager::new(labels.len());
+        pager.next_page();
+        assert!(pager.filter(&labels, "ipad"));
+        assert_eq!(pager.page, 0);
+        assert_eq!(pager.current_page(), &[3, 13, 23, 33]);
+        assert_eq!(pager.only_match(), None);
+        // No matches leaves the last view alone.
+        assert!(!pager.filter(&labels, "pixel"));
+        assert_eq!(pager.current_page(), &[3, 13, 23, 33]);
+        assert!(pager.filter(&labels, "pro 33"));
+        assert_eq!(pager.only_match(), Some(33));
+        pager.clear_filter(labels.len());
+        assert_eq!(pager.filter, None);
+        assert_eq!(pager.page_count(), 3);
+    }
+}
@@ -0,0 +1,117 @@
+//! Running as root (i.e. because `gem install` suggested `sudo`) leaves
+//! root-owned files behind in directories the user owns, which breaks every
+//! unprivileged run afterwards in ways that don't look related at all.
+
+use super::cli::{Report, Reportable};
+use crate::os;
+use std::path::{Path, PathBuf};
+
+const ROOT_UID: u32 = 0;
+
+#[derive(Debug)]
+pub struct RunningAsRoot {
+    path: PathBuf,
+    owner: u32,
+}
+
+impl Reportable for RunningAsRoot {
+    fn report(&self) -> Report {
+        Report::action_request(
+            "Refusing to run as root",
+            format!(
+                "{:?} is owned by user {}, so anything written there as root would break later runs without `sudo`. Run this again without `sudo`, or pass `--allow-root` if you really mean it. If an earlier run as root already left files behind, `cargo mobile doctor` will tell you how to fix them.",
+                self.path, self.owner
+            ),
+        )
+    }
+}
+
+/// Fails if we're root, but the user's stuff isn't owned by root. Running
+/// as root in a container where everything belongs to root is fine.
+pub fn check_not_root(install_dir: Option<&Path>) -> Result<(), RunningAsRoot> {
+    if os::effective_ids().map_or(true, |(uid, _)| uid != ROOT_UID) {
+        return Ok(());
+    }
+    let cwd = std::env::current_dir().ok();
+    for path in cwd.as_deref().into_iter().chain(install_dir) {
+        if let Some(owner) = os::owner_uid(path).filter(|owner| *owner != ROOT_UID) {
+            return Err(RunningAsRoot {
+                path: path.to_owned(),
+                owner,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The root-owned entries among `dir` and what's directly in it. Walking all
+/// of something like `target` takes ages, so this only catches the dirs a run
+/// as root created at the top. `chown -R` on those covers everything inside.
+pub fn root_owned(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut entries = walkdir::WalkDir::new(dir).max_depth(1).into_iter();
+    while let Some(entry) = entries.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                log::warn!("failed to check ownership under {:?}: {}", dir, err);
+                continue;
+            }
+        };
+        if os::owner_uid(entry.path()) == Some(ROOT_UID) {
+            found.push(entry.path().to_owned());
+            if entry.file_type().is_dir() {
+                entries.skip_current_dir();
+            }
+        }
+    }
+    found
+}
+
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', r"'\''"))
+}
+
+/// The command that gives `paths` back to `(uid, gid)`.
+pub fn chown_command(paths: &[PathBuf], (uid, gid): (u32, u32)) -> String {
+    format!(
+        "sudo chown -R {}:{} {}",
+        uid,
+        gid,
+        paths
+            .iter()
+            .map(|path| shell_quote(path))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chown_command_is_quoted() {
+        assert_eq!(
+            chown_command(
+                &[
+                    PathBuf::from("/Users/me/.cargo-mobile"),
+                    PathBuf::from("/Users/me/Bob's Game/target"),
+                ],
+                (501, 20)
+            ),
+            r"sudo chown -R 501:20 '/Users/me/.cargo-mobile' '/Users/me/Bob'\''s Game/target'"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn own_files_arent_root_owned() {
+        if os::effective_ids().map_or(true, |(uid, _)| uid == ROOT_UID) {
+            return;
+        }
+        let dir = super::super::TempDirGuard::new().unwrap();
+        std::fs::write(dir.join("file"), "").unwrap();
+        assert!(root_owned(&dir).is_empty());
+    }
+}
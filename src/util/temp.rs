@@ -0,0 +1,179 @@
+//! Scratch space that belongs to a single invocation, so concurrent runs (i.e.
+//! CI matrix jobs on the same runner) can't stomp on each other's files.
+
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::{BuildHasher as _, Hasher as _},
+    io,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+/// Directories left behind for longer than this were abandoned by crashed
+/// or killed invocations.
+pub const STALE_TEMP_DIR_AGE: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+/// Everything we put in the system temp dir lives under here.
+pub fn temp_base() -> PathBuf {
+    std::env::temp_dir().join("com.brainiumstudios.cargo-mobile")
+}
+
+#[derive(Debug, Error)]
+#[error("Failed to create temp dir {path:?}: {source}")]
+pub struct TempDirError {
+    path: PathBuf,
+    source: io::Error,
+}
+
+// Good enough to not collide with anything else running under the same PID
+// namespace; `RandomState` is seeded from the OS on creation.
+fn random() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+/// A unique directory that's removed along with its contents when dropped,
+/// including while unwinding from a panic. Removal is best-effort; anything
+/// left behind gets picked up by `sweep_stale_temp_dirs` eventually.
+#[derive(Debug)]
+pub struct TempDirGuard {
+    path: PathBuf,
+}
+
+impl TempDirGuard {
+    /// A new directory under `temp_base`.
+    pub fn new() -> Result<Self, TempDirError> {
+        Self::new_in(&temp_base())
+    }
+
+    pub fn new_in(base: &Path) -> Result<Self, TempDirError> {
+        fs::create_dir_all(base).map_err(|source| TempDirError {
+            path: base.to_owned(),
+            source,
+        })?;
+        loop {
+            let path = base.join(format!("{}-{:016x}", std::process::id(), random()));
+            match fs::create_dir(&path) {
+                Ok(()) => {
+                    log::info!("created temp dir {:?}", path);
+                    break Ok(Self { path });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(source) => break Err(TempDirError { path, source }),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Deref for TempDirGuard {
+    type Target = Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDirGuard {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDirGuard {
+    fn drop(&mut self) {
+        match fs::remove_dir_all(&self.path) {
+            Ok(()) => log::info!("removed temp dir {:?}", self.path),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => log::warn!("failed to remove temp dir {:?}: {}", self.path, err),
+        }
+    }
+}
+
+fn is_stale(entry: &fs::DirEntry, now: SystemTime, max_age: Duration) -> bool {
+    entry
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| now.duration_since(modified).ok())
+        .map_or(false, |age| age > max_age)
+}
+
+/// Removes anything under `base` that hasn't been touched in `max_age`,
+/// returning how many entries were removed. Failures are only logged, since
+/// this is just housekeeping.
+pub fn sweep_stale_temp_dirs_in(base: &Path, max_age: Duration) -> usize {
+    let entries = match fs::read_dir(base) {
+        Ok(entries) => entries,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                log::warn!("failed to read temp dir {:?}: {}", base, err);
+            }
+            return 0;
+        }
+    };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries.filter_map(Result::ok) {
+        if !is_stale(&entry, now, max_age) {
+            continue;
+        }
+        let path = entry.path();
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match result {
+            Ok(()) => {
+                log::info!("removed stale temp entry {:?}", path);
+                removed += 1;
+            }
+            Err(err) => log::warn!("failed to remove stale temp entry {:?}: {}", path, err),
+        }
+    }
+    removed
+}
+
+/// Sweeps `temp_base` for anything older than `STALE_TEMP_DIR_AGE`.
+pub fn sweep_stale_temp_dirs() -> usize {
+    sweep_stale_temp_dirs_in(&temp_base(), STALE_TEMP_DIR_AGE)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn guards_are_unique_and_clean_up() {
+        let base = TempDirGuard::new().unwrap();
+        let a = TempDirGuard::new_in(&base).unwrap();
+        let b = TempDirGuard::new_in(&base).unwrap();
+        assert_ne!(a.path(), b.path());
+        fs::write(a.join("file"), "contents").unwrap();
+        let a_path = a.path().to_owned();
+        drop(a);
+        assert!(!a_path.exists());
+        assert!(b.is_dir());
+    }
+
+    #[test]
+    fn sweep_only_removes_stale_entries() {
+        let base = TempDirGuard::new().unwrap();
+        let fresh = TempDirGuard::new_in(&base).unwrap();
+        assert_eq!(sweep_stale_temp_dirs_in(&base, STALE_TEMP_DIR_AGE), 0);
+        assert!(fresh.is_dir());
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(sweep_stale_temp_dirs_in(&base, Duration::from_millis(1)), 1);
+        assert!(!fresh.is_dir());
+    }
+}
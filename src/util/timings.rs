@@ -0,0 +1,208 @@
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    fs, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+struct Step {
+    name: String,
+    depth: usize,
+    started: Instant,
+    duration: Option<Duration>,
+    success: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub name: String,
+    pub depth: usize,
+    pub duration_secs: f64,
+    pub success: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Timings {
+    steps: Vec<Step>,
+    depth: usize,
+}
+
+impl Timings {
+    pub fn start(&mut self, name: impl Into<String>) -> usize {
+        self.steps.push(Step {
+            name: name.into(),
+            depth: self.depth,
+            started: Instant::now(),
+            duration: None,
+            success: None,
+        });
+        self.depth += 1;
+        self.steps.len() - 1
+    }
+
+    pub fn finish(&mut self, index: usize, success: bool) {
+        let step = &mut self.steps[index];
+        step.duration = Some(step.started.elapsed());
+        step.success = Some(success);
+        self.depth = step.depth;
+    }
+
+    /// Steps that never finished (i.e. because we panicked mid-step) are
+    /// reported as failures lasting until now.
+    pub fn records(&self) -> Vec<Record> {
+        self.steps
+            .iter()
+            .map(|step| Record {
+                name: step.name.clone(),
+                depth: step.depth,
+                duration_secs: step
+                    .duration
+                    .unwrap_or_else(|| step.started.elapsed())
+                    .as_secs_f64(),
+                success: step.success.unwrap_or(false),
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn write_json(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.records())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, json)
+    }
+}
+
+/// A table of steps, slowest first.
+impl Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut records = self.records();
+        records.sort_by(|a, b| {
+            b.duration_secs
+                .partial_cmp(&a.duration_secs)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let width = records
+            .iter()
+            .map(|record| record.name.len())
+            .max()
+            .unwrap_or_default();
+        for record in records {
+            writeln!(
+                f,
+                "  {:<width$}  {:>8.2}s  {}",
+                record.name,
+                record.duration_secs,
+                if record.success { "ok" } else { "failed" },
+                width = width,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    static TIMINGS: RefCell<Timings> = RefCell::new(Timings::default());
+}
+
+/// Times `f`, recording it as a step named `name`. Steps started while `f`
+/// runs are recorded as nested within it.
+pub fn step<T, E>(name: impl Into<String>, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let index = TIMINGS.with(|timings| timings.borrow_mut().start(name));
+    let result = f();
+    TIMINGS.with(|timings| timings.borrow_mut().finish(index, result.is_ok()));
+    result
+}
+
+/// Runs `command` to completion, recording it as a step.
+pub fn run_and_wait(name: impl Into<String>, command: bossy::Command) -> bossy::Result<()> {
+    step(name, || super::events::run_and_wait(command))
+}
+
+pub fn take() -> Timings {
+    TIMINGS.with(|timings| timings.take())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_steps_record_depth() {
+        let mut timings = Timings::default();
+        let outer = timings.start("outer");
+        let inner = timings.start("inner");
+        timings.finish(inner, true);
+        let sibling = timings.start("sibling");
+        timings.finish(sibling, false);
+        timings.finish(outer, true);
+        let after = timings.start("after");
+        timings.finish(after, true);
+        let records = timings.records();
+        let summary = records
+            .iter()
+            .map(|record| (record.name.as_str(), record.depth, record.success))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("outer", 0, true),
+                ("inner", 1, true),
+                ("sibling", 1, false),
+                ("after", 0, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn unfinished_steps_are_failures() {
+        let mut timings = Timings::default();
+        timings.start("never finished");
+        assert!(!timings.records()[0].success);
+    }
+
+    #[test]
+    fn scoped_steps_nest() {
+        let result: Result<(), ()> = step("outer", || {
+            step("inner", || Ok::<_, ()>(()))?;
+            Err(())
+        });
+        assert!(result.is_err());
+        let summary = take()
+            .records()
+            .into_iter()
+            .map(|record| (record.name, record.depth, record.success))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("outer".to_owned(), 0, false),
+                ("inner".to_owned(), 1, true)
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wrapped_commands_are_recorded() {
+        run_and_wait("true", bossy::Command::impure("true")).unwrap();
+        assert!(run_and_wait("false", bossy::Command::impure("false")).is_err());
+        let summary = take()
+            .records()
+            .into_iter()
+            .map(|record| (record.name, record.success))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![("true".to_owned(), true), ("false".to_owned(), false)]
+        );
+    }
+}
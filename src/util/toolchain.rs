@@ -0,0 +1,396 @@
+//! Running `cargo`/`rustc`/`rustup` straight from `PATH` can land on a
+//! different toolchain than the one the user picked, i.e. with
+//! `cargo +nightly mobile` or a pinned `rust-toolchain.toml`. Cargo tells
+//! subcommands which cargo and toolchain they're running under via `CARGO`
+//! and `RUSTUP_TOOLCHAIN`, so we pass those along.
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    /// `RUSTUP_TOOLCHAIN`, which is also how `+toolchain` reaches us.
+    Env,
+    File(PathBuf),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Env => write!(f, "`RUSTUP_TOOLCHAIN`"),
+            Self::File(path) => write!(f, "{:?}", path),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Toolchain {
+    pub name: String,
+    pub source: Source,
+}
+
+/// The channel named by a `rust-toolchain` or `rust-toolchain.toml`. Legacy
+/// files are just the channel on a line by itself.
+pub fn parse_file(contents: &str) -> Option<String> {
+    let trimmed = contents.trim();
+    if !trimmed.is_empty() && !trimmed.contains(|c: char| c.is_whitespace() || c == '=' || c == '[')
+    {
+        return Some(trimmed.to_owned());
+    }
+    let value = toml::from_str::<toml::Value>(contents).ok()?;
+    value
+        .get("toolchain")?
+        .get("channel")?
+        .as_str()
+        .map(ToOwned::to_owned)
+}
+
+// rustup uses the nearest directory with either file, and prefers the legacy
+// name when both are present.
+fn find_file(dir: &Path) -> Option<(PathBuf, String)> {
+    dir.ancestors().find_map(|dir| {
+        ["rust-toolchain", "rust-toolchain.toml"]
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+            .and_then(|path| {
+                let contents = fs::read_to_string(&path)
+                    .map_err(|err| log::warn!("failed to read {:?}: {}", path, err))
+                    .ok()?;
+                let name = parse_file(&contents);
+                if name.is_none() {
+                    log::warn!("{:?} doesn't specify a toolchain channel", path);
+                }
+                name.map(|name| (path, name))
+            })
+    })
+}
+
+/// `RUSTUP_TOOLCHAIN` wins over a toolchain file in `dir` or its ancestors,
+/// same as with rustup itself.
+pub fn resolve_with(env_toolchain: Option<String>, dir: &Path) -> Option<Toolchain> {
+    env_toolchain
+        .filter(|name| !name.is_empty())
+        .map(|name| Toolchain {
+            name,
+            source: Source::Env,
+        })
+        .or_else(|| {
+            find_file(dir).map(|(path, name)| Toolchain {
+                name,
+                source: Source::File(path),
+            })
+        })
+}
+
+pub fn resolve() -> Option<Toolchain> {
+    let dir = std::env::current_dir().unwrap_or_default();
+    resolve_with(std::env::var("RUSTUP_TOOLCHAIN").ok(), &dir)
+}
+
+/// The cargo that's running us, if we're running as a cargo subcommand.
+pub fn cargo_path() -> OsString {
+    std::env::var_os("CARGO")
+        .filter(|cargo| !cargo.is_empty())
+        .unwrap_or_else(|| "cargo".into())
+}
+
+fn pin(mut command: bossy::Command, tool: &str) -> bossy::Command {
+    match resolve() {
+        Some(toolchain) => {
+            log::info!(
+                "`{}` will use toolchain {:?} (from {})",
+                tool,
+                toolchain.name,
+                toolchain.source
+            );
+            command.add_env_var("RUSTUP_TOOLCHAIN", toolchain.name);
+        }
+        None => log::info!("`{}` will use the default toolchain", tool),
+    }
+    command
+}
+
+pub fn cargo_impure() -> bossy::Command {
+    let cargo = cargo_path();
+    log::info!("using cargo at {:?}", cargo);
+    pin(bossy::Command::impure(cargo), "cargo")
+}
+
+pub fn cargo_pure() -> bossy::Command {
+    let cargo = cargo_path();
+    log::info!("using cargo at {:?}", cargo);
+    pin(bossy::Command::pure(cargo), "cargo")
+}
+
+/// `rustup run <name> cargo`, for when we need a specific toolchain no matter
+/// which one is running us. `rustup` should be the bare command.
+pub fn cargo_from(rustup: bossy::Command, name: &str) -> bossy::Command {
+    log::info!("using cargo from toolchain {:?}", name);
+    rustup.with_args(&["run", name, "cargo"])
+}
+
+/// The toolchain to build tier 3 targets with. `-Zbuild-std` needs nightly,
+/// so a nightly the user picked is used as-is and anything else gets swapped
+/// for plain `nightly`.
+pub fn build_std_toolchain() -> String {
+    build_std_toolchain_for(resolve())
+}
+
+fn build_std_toolchain_for(toolchain: Option<Toolchain>) -> String {
+    toolchain
+        .map(|toolchain| toolchain.name)
+        .filter(|name| name.starts_with("nightly"))
+        .unwrap_or_else(|| "nightly".to_owned())
+}
+
+pub fn rustc() -> bossy::Command {
+    pin(bossy::Command::impure("rustc"), "rustc")
+}
+
+/// The args for `rustup target <subcommand>`, pinned to `toolchain` if
+/// there is one.
+pub fn rustup_target_args<'a>(
+    subcommand: &'a str,
+    toolchain: Option<&'a str>,
+    triples: &[&'a str],
+) -> Vec<&'a str> {
+    let mut args = vec!["target", subcommand];
+    if let Some(toolchain) = toolchain {
+        args.extend(&["--toolchain", toolchain]);
+    }
+    args.extend(triples);
+    args
+}
+
+/// The `wanted` triples that aren't listed in `installed`, which is what
+/// `rustup target list --installed` printed. Order is kept and repeats are
+/// dropped.
+pub fn missing_targets<'a>(
+    installed: &str,
+    wanted: impl IntoIterator<Item = &'a str>,
+) -> Vec<&'a str> {
+    let installed = installed.lines().map(str::trim).collect::<BTreeSet<_>>();
+    let mut seen = BTreeSet::new();
+    wanted
+        .into_iter()
+        .filter(|triple| !installed.contains(triple) && seen.insert(*triple))
+        .collect()
+}
+
+pub fn rustup_target_add(triples: &[&str]) -> bossy::Command {
+    let toolchain = resolve();
+    if let Some(toolchain) = &toolchain {
+        log::info!(
+            "adding targets {:?} to toolchain {:?} (from {})",
+            triples,
+            toolchain.name,
+            toolchain.source
+        );
+    }
+    bossy::Command::impure("rustup").with_args(rustup_target_args(
+        "add",
+        toolchain.as_ref().map(|toolchain| toolchain.name.as_str()),
+        triples,
+    ))
+}
+
+fn rustup_target_list_installed() -> bossy::Command {
+    let toolchain = resolve();
+    bossy::Command::impure("rustup").with_args(rustup_target_args(
+        "list",
+        toolchain.as_ref().map(|toolchain| toolchain.name.as_str()),
+        &["--installed"],
+    ))
+}
+
+#[derive(Debug, Error)]
+#[error("Failed to install {}: {source}", .missing.join(", "))]
+pub struct RustupTargetAddFailed {
+    pub missing: Vec<String>,
+    source: bossy::Error,
+}
+
+/// Adds whichever of `triples` aren't installed yet, with a single `rustup`
+/// call. When they're all there, `rustup target add` isn't run at all, so
+/// this works offline on a machine that's already set up.
+pub fn install_targets<'a>(
+    triples: impl IntoIterator<Item = &'a str>,
+) -> Result<(), RustupTargetAddFailed> {
+    let triples = triples.into_iter().collect::<Vec<_>>();
+    let installed = rustup_target_list_installed()
+        .run_and_wait_for_str(ToOwned::to_owned)
+        .unwrap_or_else(|err| {
+            log::warn!(
+                "failed to list installed targets, so adding them all: {}",
+                err
+            );
+            String::new()
+        });
+    let missing = missing_targets(&installed, triples.iter().copied());
+    if missing.is_empty() {
+        log::info!("targets {:?} are already installed", triples);
+        return Ok(());
+    }
+    rustup_target_add(&missing)
+        .run_and_wait()
+        .map(|_| ())
+        .map_err(|source| RustupTargetAddFailed {
+            missing: missing.iter().map(|triple| (*triple).to_owned()).collect(),
+            source,
+        })
+}
+
+/// Adds `rust-src` to `toolchain`, which is what `-Zbuild-std` builds from.
+/// Tier 3 targets have nothing for `rustup target add` to download.
+pub fn install_rust_src(toolchain: &str) -> Result<(), RustupTargetAddFailed> {
+    log::info!("adding `rust-src` to toolchain {:?}", toolchain);
+    bossy::Command::impure("rustup")
+        .with_args(&["component", "add", "rust-src", "--toolchain", toolchain])
+        .run_and_wait()
+        .map(|_| ())
+        .map_err(|source| RustupTargetAddFailed {
+            missing: vec![format!("rust-src for {}", toolchain)],
+            source,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+
+    #[rstest(
+        contents,
+        expected,
+        case("nightly-2021-06-01\n", Some("nightly-2021-06-01")),
+        case("[toolchain]\nchannel = \"1.56.0\"\n", Some("1.56.0")),
+        case(
+            "[toolchain]\ncomponents = [\"rustfmt\"]\nchannel = \"stable\"\n",
+            Some("stable")
+        ),
+        case("[toolchain]\ncomponents = [\"rustfmt\"]\n", None),
+        case("", None)
+    )]
+    fn parses_toolchain_files(contents: &str, expected: Option<&str>) {
+        assert_eq!(parse_file(contents).as_deref(), expected);
+    }
+
+    #[rstest(
+        toolchain,
+        expected,
+        case(None, "nightly"),
+        case(Some("stable"), "nightly"),
+        case(Some("1.56.0"), "nightly"),
+        case(Some("nightly-2021-06-01"), "nightly-2021-06-01")
+    )]
+    fn build_std_needs_nightly(toolchain: Option<&str>, expected: &str) {
+        let toolchain = toolchain.map(|name| Toolchain {
+            name: name.to_owned(),
+            source: Source::Env,
+        });
+        assert_eq!(build_std_toolchain_for(toolchain), expected);
+    }
+
+    #[rstest(
+        installed,
+        expected,
+        case("", vec!["aarch64-linux-android", "armv7-linux-androideabi"]),
+        case(
+            "x86_64-unknown-linux-gnu\naarch64-linux-android\n",
+            vec!["armv7-linux-androideabi"]
+        ),
+        case(
+            "aarch64-linux-android\narmv7-linux-androideabi\nx86_64-unknown-linux-gnu\n",
+            vec![]
+        )
+    )]
+    fn finds_missing_targets(installed: &str, expected: Vec<&str>) {
+        assert_eq!(
+            missing_targets(
+                installed,
+                vec![
+                    "aarch64-linux-android",
+                    "armv7-linux-androideabi",
+                    "aarch64-linux-android",
+                ]
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn batches_target_args() {
+        assert_eq!(
+            rustup_target_args("add", None, &["aarch64-apple-ios", "x86_64-apple-ios"]),
+            vec!["target", "add", "aarch64-apple-ios", "x86_64-apple-ios"]
+        );
+        assert_eq!(
+            rustup_target_args("list", Some("1.56.0"), &["--installed"]),
+            vec!["target", "list", "--toolchain", "1.56.0", "--installed"]
+        );
+    }
+
+    #[test]
+    fn env_beats_files() {
+        let root = TempDirGuard::new().unwrap();
+        fs::write(root.join("rust-toolchain"), "1.50.0\n").unwrap();
+        assert_eq!(
+            resolve_with(Some("nightly".to_owned()), &root),
+            Some(Toolchain {
+                name: "nightly".to_owned(),
+                source: Source::Env,
+            })
+        );
+        // An empty variable is as good as none.
+        assert_eq!(
+            resolve_with(Some(String::new()), &root).map(|toolchain| toolchain.name),
+            Some("1.50.0".to_owned())
+        );
+    }
+
+    #[test]
+    fn nearest_file_wins() {
+        let root = TempDirGuard::new().unwrap();
+        let nested = root.join("crates/app");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(resolve_with(None, &nested), None);
+
+        fs::write(
+            root.join("rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"1.50.0\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            resolve_with(None, &nested),
+            Some(Toolchain {
+                name: "1.50.0".to_owned(),
+                source: Source::File(root.join("rust-toolchain.toml")),
+            })
+        );
+
+        // The legacy name takes precedence within a directory.
+        fs::write(root.join("rust-toolchain"), "1.49.0\n").unwrap();
+        assert_eq!(
+            resolve_with(None, &nested).map(|toolchain| toolchain.name),
+            Some("1.49.0".to_owned())
+        );
+
+        fs::write(
+            root.join("crates/rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"beta\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            resolve_with(None, &nested).map(|toolchain| toolchain.name),
+            Some("beta".to_owned())
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Where tools we download ourselves, like the bundletool jar, are kept. By
+//! default that's `~/.cargo-mobile/tools`, shared by every project, but with
+//! `[tools] scope = "project"` they go in the project's `gen/tools` instead,
+//! so each checkout has what it needs and CI can cache it with the repo.
+//! Homebrew packages are always installed machine-wide.
+
+use super::{tools_dir, NoHomeDir};
+use ignore::gitignore::GitignoreBuilder;
+use once_cell_regex::exports::once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Relative to the project root.
+pub static PROJECT_TOOLS_DIR: &str = "gen/tools";
+
+static IGNORE_ENTRY: &str =
+    "# Tools cargo-mobile downloaded, since `[tools] scope` is \"project\"\n/gen/tools/\n";
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    User,
+    Project,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self::User
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::User => write!(f, "user"),
+            Self::Project => write!(f, "project"),
+        }
+    }
+}
+
+/// The `[tools]` section.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Raw {
+    pub scope: Option<Scope>,
+}
+
+/// Where a tool turned up, if it did.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Resolution {
+    Found {
+        path: PathBuf,
+        scope: Scope,
+        /// It's a user-scope install we're making do with, since there's
+        /// nothing in the project yet.
+        fallback: bool,
+    },
+    Missing {
+        install_path: PathBuf,
+    },
+}
+
+impl Resolution {
+    /// What to run, whether or not it's there yet.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Found { path, .. } => path,
+            Self::Missing { install_path } => install_path,
+        }
+    }
+}
+
+/// Project installs win over user ones in project scope, and user scope never
+/// looks in the project.
+pub fn resolve(
+    scope: Scope,
+    project_dir: &Path,
+    user_dir: &Path,
+    file_name: &str,
+    exists: impl Fn(&Path) -> bool,
+) -> Resolution {
+    let project = project_dir.join(file_name);
+    let user = user_dir.join(file_name);
+    match scope {
+        Scope::Project if exists(&project) => Resolution::Found {
+            path: project,
+            scope: Scope::Project,
+            fallback: false,
+        },
+        Scope::Project if exists(&user) => Resolution::Found {
+            path: user,
+            scope: Scope::User,
+            fallback: true,
+        },
+        Scope::Project => Resolution::Missing {
+            install_path: project,
+        },
+        Scope::User if exists(&user) => Resolution::Found {
+            path: user,
+            scope: Scope::User,
+            fallback: false,
+        },
+        Scope::User => Resolution::Missing { install_path: user },
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Tools {
+    scope: Scope,
+    root_dir: PathBuf,
+}
+
+impl Tools {
+    pub fn from_raw(root_dir: &Path, raw: Option<Raw>) -> Self {
+        Self {
+            scope: raw.and_then(|raw| raw.scope).unwrap_or_default(),
+            root_dir: root_dir.to_owned(),
+        }
+    }
+
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+
+    pub fn project_dir(&self) -> PathBuf {
+        self.root_dir.join(PROJECT_TOOLS_DIR)
+    }
+
+    pub fn resolve(&self, file_name: &str) -> Result<Resolution, NoHomeDir> {
+        Ok(resolve(
+            self.scope,
+            &self.project_dir(),
+            &tools_dir()?,
+            file_name,
+            Path::is_file,
+        ))
+    }
+
+    /// Where a fresh install of `file_name` goes.
+    pub fn install_path(&self, file_name: &str) -> Result<PathBuf, NoHomeDir> {
+        Ok(match self.scope {
+            Scope::User => tools_dir()?.join(file_name),
+            Scope::Project => self.project_dir().join(file_name),
+        })
+    }
+}
+
+static TOOLS: OnceCell<Tools> = OnceCell::new();
+
+/// Only the first call counts, which is the one made when the config is
+/// loaded.
+pub fn configure(tools: Tools) {
+    let _ = TOOLS.set(tools);
+}
+
+/// User scope if no config was loaded.
+pub fn get() -> &'static Tools {
+    TOOLS.get_or_init(Tools::default)
+}
+
+/// Whether `gitignore` (at `root_dir`) leaves the project tools dir out of
+/// version control already, i.e. with the `/gen` our templates ignore.
+pub fn ignored_by(root_dir: &Path, gitignore: &str) -> bool {
+    let mut builder = GitignoreBuilder::new(root_dir);
+    for line in gitignore.lines() {
+        if let Err(err) = builder.add_line(None, line) {
+            log::warn!("failed to parse `.gitignore` line {:?}: {}", line, err);
+        }
+    }
+    match builder.build() {
+        Ok(gitignore) => gitignore
+            .matched_path_or_any_parents(root_dir.join(PROJECT_TOOLS_DIR), true)
+            .is_ignore(),
+        Err(err) => {
+            log::warn!("failed to build `.gitignore` matcher: {}", err);
+            false
+        }
+    }
+}
+
+/// Adds the project tools dir to `.gitignore` unless it's already covered,
+/// giving back whether anything was added.
+pub fn ensure_ignored(root_dir: &Path) -> io::Result<bool> {
+    let path = root_dir.join(".gitignore");
+    let existing = match fs::read_to_string(&path) {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    if ignored_by(root_dir, &existing) {
+        return Ok(false);
+    }
+    let separator = if existing.is_empty() || existing.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    fs::write(&path, format!("{}{}{}", existing, separator, IGNORE_ENTRY))?;
+    Ok(true)
+}
+
+/// `contents` without the entry `ensure_ignored` added, if it's there.
+pub fn strip_ignore_entry(contents: &str) -> Option<String> {
+    contents.find(IGNORE_ENTRY).map(|index| {
+        let mut stripped = contents[..index].to_owned();
+        stripped.push_str(&contents[index + IGNORE_ENTRY.len()..]);
+        stripped
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::TempDirGuard;
+    use rstest::rstest;
+
+    static JAR: &str = "bundletool-all-1.8.0.jar";
+
+    fn found(path: &str, scope: Scope, fallback: bool) -> Resolution {
+        Resolution::Found {
+            path: path.into(),
+            scope,
+            fallback,
+        }
+    }
+
+    fn missing(path: &str) -> Resolution {
+        Resolution::Missing {
+            install_path: path.into(),
+        }
+    }
+
+    #[rstest(
+        scope,
+        in_project,
+        in_user,
+        expected,
+        case(
+            Scope::Project,
+            true,
+            true,
+            found("/app/gen/tools/bundletool-all-1.8.0.jar", Scope::Project, false)
+        ),
+        case(
+            Scope::Project,
+            false,
+            true,
+            found(
+                "/home/.cargo-mobile/tools/bundletool-all-1.8.0.jar",
+                Scope::User,
+                true
+            )
+        ),
+        case(
+            Scope::Project,
+            false,
+            false,
+            missing("/app/gen/tools/bundletool-all-1.8.0.jar")
+        ),
+        case(
+            Scope::User,
+            true,
+            true,
+            found(
+                "/home/.cargo-mobile/tools/bundletool-all-1.8.0.jar",
+                Scope::User,
+                false
+            )
+        ),
+        case(
+            Scope::User,
+            true,
+            false,
+            missing("/home/.cargo-mobile/tools/bundletool-all-1.8.0.jar")
+        )
+    )]
+    fn resolves_in_scope_order(
+        scope: Scope,
+        in_project: bool,
+        in_user: bool,
+        expected: Resolution,
+    ) {
+        let resolution = resolve(
+            scope,
+            Path::new("/app/gen/tools"),
+            Path::new("/home/.cargo-mobile/tools"),
+            JAR,
+            |path| {
+                if path.starts_with("/app") {
+                    in_project
+                } else {
+                    in_user
+                }
+            },
+        );
+        assert_eq!(resolution, expected);
+    }
+
+    #[test]
+    fn defaults_to_user_scope() {
+        let tools = Tools::from_raw(Path::new("/app"), None);
+        assert_eq!(tools.scope(), Scope::User);
+        let tools = Tools::from_raw(
+            Path::new("/app"),
+            Some(Raw {
+                scope: Some(Scope::Project),
+            }),
+        );
+        assert_eq!(
+            tools.install_path(JAR).unwrap(),
+            Path::new("/app/gen/tools").join(JAR)
+        );
+    }
+
+    #[rstest(
+        gitignore,
+        ignored,
+        case("target/\n/gen\n", true),
+        case("gen/\n", true),
+        case("/gen/tools/\n", true),
+        case("target/\n", false),
+        case("/gen/apple\n", false),
+        case("/gen\n!/gen/tools\n", false)
+    )]
+    fn checks_gitignore(gitignore: &str, ignored: bool) {
+        assert_eq!(ignored_by(Path::new("/app"), gitignore), ignored);
+    }
+
+    #[test]
+    fn adds_ignore_entry_once() {
+        let dir = TempDirGuard::new().unwrap();
+        fs::write(dir.join(".gitignore"), "target/").unwrap();
+        assert!(ensure_ignored(&dir).unwrap());
+        assert!(!ensure_ignored(&dir).unwrap());
+        assert_eq!(
+            fs::read_to_string(dir.join(".gitignore")).unwrap(),
+            format!("target/\n{}", IGNORE_ENTRY)
+        );
+    }
+
+    #[test]
+    fn strips_only_our_entry() {
+        let contents = format!("target/\n{}/gen\n", IGNORE_ENTRY);
+        assert_eq!(
+            strip_ignore_entry(&contents).as_deref(),
+            Some("target/\n/gen\n")
+        );
+        assert_eq!(strip_ignore_entry("target/\n/gen/tools/\n"), None);
+    }
+}
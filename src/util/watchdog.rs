@@ -0,0 +1,631 @@
+//! Liveness checks for external tools that occasionally hang forever without
+//! printing anything, like `xcodegen`, `pod install`, or a wedged `adb`. A
+//! watched command's output is piped through us so we can tell when it's gone
+//! quiet, and it's killed once it's been quiet (or running) for too long.
+//!
+//! Nothing is watched unless its call site goes through `run` with a `Class`,
+//! so anything interactive, like logcat or the debugger, is never at risk of
+//! being killed for sitting idle.
+
+use super::{
+    cli::{Report, Reportable},
+    events,
+};
+use once_cell_regex::{exports::once_cell::sync::OnceCell, regex};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    io::{self, Read, Write as _},
+    process::{self, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often we check on a command that hasn't printed anything.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The kinds of commands that get watched, which is also what `[timeouts]`
+/// is keyed by.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Class {
+    Xcodegen,
+    Pod,
+    Adb,
+}
+
+impl Class {
+    /// Which is also its key in `[timeouts]`.
+    pub fn tool(self) -> &'static str {
+        match self {
+            Self::Xcodegen => "xcodegen",
+            Self::Pod => "pod",
+            Self::Adb => "adb",
+        }
+    }
+
+    // These are deliberately generous, since killing something that was just
+    // slow is worse than waiting on something that's stuck.
+    fn default_limits(self) -> Limits {
+        let (inactivity_mins, total_mins) = match self {
+            Self::Xcodegen => (5, 20),
+            // Cloning the specs repo can go quiet for a long while.
+            Self::Pod => (10, 60),
+            // Installing a big APK over a slow cable doesn't print anything.
+            Self::Adb => (5, 30),
+        };
+        Limits {
+            inactivity: Some(Duration::from_secs(inactivity_mins * 60)),
+            total: Some(Duration::from_secs(total_mins * 60)),
+        }
+    }
+}
+
+/// `None` means there's no limit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    pub inactivity: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Exceeded {
+    Inactivity(Duration),
+    Total(Duration),
+}
+
+impl Limits {
+    pub const NONE: Self = Self {
+        inactivity: None,
+        total: None,
+    };
+
+    /// Which limit a command that started `elapsed` ago and last printed
+    /// something `quiet` ago has blown through, if any.
+    pub fn exceeded(&self, elapsed: Duration, quiet: Duration) -> Option<Exceeded> {
+        match (self.inactivity, self.total) {
+            (_, Some(total)) if elapsed >= total => Some(Exceeded::Total(total)),
+            (Some(inactivity), _) if quiet >= inactivity => Some(Exceeded::Inactivity(inactivity)),
+            _ => None,
+        }
+    }
+}
+
+/// One entry in `[timeouts]`; either limit can be left out to keep its
+/// default, and `0` turns it off.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RawLimits {
+    pub inactivity_secs: Option<u64>,
+    pub total_secs: Option<u64>,
+}
+
+pub type Raw = BTreeMap<Class, RawLimits>;
+
+#[derive(Clone, Debug, Default)]
+pub struct Timeouts {
+    raw: Raw,
+}
+
+impl Timeouts {
+    pub fn from_raw(raw: Option<Raw>) -> Self {
+        Self {
+            raw: raw.unwrap_or_default(),
+        }
+    }
+
+    pub fn limits(&self, class: Class) -> Limits {
+        let defaults = class.default_limits();
+        let secs = |secs: Option<u64>, default: Option<Duration>| match secs {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => default,
+        };
+        match self.raw.get(&class) {
+            Some(raw) => Limits {
+                inactivity: secs(raw.inactivity_secs, defaults.inactivity),
+                total: secs(raw.total_secs, defaults.total),
+            },
+            None => defaults,
+        }
+    }
+}
+
+static TIMEOUTS: OnceCell<Timeouts> = OnceCell::new();
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Only the first call counts, which is the one made when the config is
+/// loaded.
+pub fn configure(timeouts: Timeouts) {
+    let _ = TIMEOUTS.set(timeouts);
+}
+
+/// For `--no-timeouts`, which beats anything in `[timeouts]`.
+pub fn disable() {
+    DISABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn limits(class: Class) -> Limits {
+    if DISABLED.load(Ordering::SeqCst) {
+        Limits::NONE
+    } else {
+        TIMEOUTS.get_or_init(Timeouts::default).limits(class)
+    }
+}
+
+/// Keeps track of the last complete line seen, falling back to whatever's
+/// been printed since if the tool hung partway through a line (which is
+/// usually the most telling bit, i.e. a prompt nobody can see).
+#[derive(Debug, Default)]
+pub struct LastLine {
+    complete: Option<String>,
+    partial: Vec<u8>,
+}
+
+impl LastLine {
+    fn clean(bytes: &[u8]) -> Option<String> {
+        let line = String::from_utf8_lossy(bytes);
+        let line = regex!(r"\x1b\[[0-9;]*[A-Za-z]").replace_all(&line, "");
+        let line = line.trim();
+        if line.is_empty() {
+            None
+        } else {
+            Some(line.to_owned())
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            if *byte == b'\n' || *byte == b'\r' {
+                if let Some(line) = Self::clean(&self.partial) {
+                    self.complete = Some(line);
+                }
+                self.partial.clear();
+            } else {
+                self.partial.push(*byte);
+            }
+        }
+    }
+
+    pub fn get(&self) -> Option<String> {
+        Self::clean(&self.partial).or_else(|| self.complete.clone())
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl Output {
+    /// Everything it printed, since tools aren't consistent about which
+    /// stream their errors go to.
+    pub fn text(&self) -> String {
+        format!(
+            "{}{}",
+            String::from_utf8_lossy(&self.stdout),
+            String::from_utf8_lossy(&self.stderr)
+        )
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 60, secs % 60) {
+        (0, secs) => format!("{}s", secs),
+        (mins, 0) => format!("{}m", mins),
+        (mins, secs) => format!("{}m {}s", mins, secs),
+    }
+}
+
+#[derive(Debug)]
+pub struct Hung {
+    pub class: Class,
+    pub exceeded: Exceeded,
+    pub elapsed: Duration,
+    pub last_line: Option<String>,
+    pub output: Output,
+}
+
+impl Display for Hung {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.exceeded {
+            Exceeded::Inactivity(limit) => write!(
+                f,
+                "`{}` didn't print anything for {} (after running for {}), so it was stopped.",
+                self.class.tool(),
+                format_duration(limit),
+                format_duration(self.elapsed),
+            )?,
+            Exceeded::Total(limit) => write!(
+                f,
+                "`{}` was still running after {}, so it was stopped.",
+                self.class.tool(),
+                format_duration(limit),
+            )?,
+        }
+        match &self.last_line {
+            Some(line) => write!(f, " The last thing it printed was:\n    {}", line)?,
+            None => write!(f, " It never printed anything.")?,
+        }
+        let key = match self.exceeded {
+            Exceeded::Inactivity(_) => "inactivity-secs",
+            Exceeded::Total(_) => "total-secs",
+        };
+        write!(
+            f,
+            "\nIf it was just slow, raise `timeouts.{}.{}` in {}, or pass `--no-timeouts`.",
+            self.class.tool(),
+            key,
+            crate::config::file_name(),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    SpawnFailed { class: Class, cause: io::Error },
+    WaitFailed { class: Class, cause: io::Error },
+    Failed { class: Class, output: Output },
+    Hung(Hung),
+}
+
+impl Error {
+    pub fn output(&self) -> Option<&Output> {
+        match self {
+            Self::Failed { output, .. } => Some(output),
+            Self::Hung(hung) => Some(&hung.output),
+            Self::SpawnFailed { .. } | Self::WaitFailed { .. } => None,
+        }
+    }
+
+    pub fn is_hung(&self) -> bool {
+        matches!(self, Self::Hung(_))
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SpawnFailed { class, cause } => {
+                write!(f, "Failed to start `{}`: {}", class.tool(), cause)
+            }
+            Self::WaitFailed { class, cause } => {
+                write!(f, "Failed to wait for `{}`: {}", class.tool(), cause)
+            }
+            Self::Failed { class, output } => {
+                write!(f, "`{}` failed with {}", class.tool(), output.status)?;
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let stderr = stderr.trim();
+                if !stderr.is_empty() {
+                    write!(f, ":\n{}", stderr)?;
+                }
+                Ok(())
+            }
+            Self::Hung(hung) => write!(f, "{}", hung),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::Hung(hung) => Report::error(format!("`{}` hung", hung.class.tool()), hung),
+            _ => Report::error(format!("Failed to run `{}`", self.class().tool()), self),
+        }
+    }
+}
+
+impl Error {
+    fn class(&self) -> Class {
+        match self {
+            Self::SpawnFailed { class, .. }
+            | Self::WaitFailed { class, .. }
+            | Self::Failed { class, .. } => *class,
+            Self::Hung(hung) => hung.class,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+fn pump(stream: Stream, mut reader: impl Read, tx: mpsc::Sender<(Stream, Vec<u8>)>) {
+    let mut buf = [0; 8192];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return,
+            Ok(len) => {
+                if tx.send((stream, buf[..len].to_vec())).is_err() {
+                    return;
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => {
+                log::warn!("failed to read {:?}: {}", stream, err);
+                return;
+            }
+        }
+    }
+}
+
+fn echo(stream: Stream, bytes: &[u8]) {
+    // Like `events::run_and_wait`, output can't go anywhere near the stream.
+    if events::enabled() {
+        return;
+    }
+    let _ = match stream {
+        Stream::Stdout => io::stdout().write_all(bytes),
+        Stream::Stderr => io::stderr().write_all(bytes),
+    };
+}
+
+// The tools we watch start their own children (`pod` runs `git`, `adb`
+// starts a server), which would otherwise outlive it holding our pipes.
+#[cfg(unix)]
+fn own_group(command: &mut process::Command) {
+    use std::os::unix::process::CommandExt as _;
+    command.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn own_group(_command: &mut process::Command) {}
+
+#[cfg(unix)]
+fn kill_group(child: &mut process::Child) -> io::Result<()> {
+    let status = process::Command::new("kill")
+        .args(&["-KILL", "--", &format!("-{}", child.id())])
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        child.kill()
+    }
+}
+
+#[cfg(windows)]
+fn kill_group(child: &mut process::Child) -> io::Result<()> {
+    let status = process::Command::new("taskkill")
+        .args(&["/T", "/F", "/PID", &child.id().to_string()])
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        child.kill()
+    }
+}
+
+/// Runs `command` to completion under the limits for `class`, passing its
+/// output through as it comes. A std command is needed rather than a bossy
+/// one, since stopping a hung child means owning it.
+pub fn run(class: Class, command: process::Command) -> Result<Output, Error> {
+    run_with_limits(limits(class), class, command)
+}
+
+/// `run`, but with `limits` instead of whatever's configured for `class`.
+pub fn run_with_limits(
+    limits: Limits,
+    class: Class,
+    mut command: process::Command,
+) -> Result<Output, Error> {
+    log::info!("running {:?} with limits {:?}", command, limits);
+    own_group(&mut command);
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|cause| Error::SpawnFailed { class, cause })?;
+    let (tx, rx) = mpsc::channel();
+    let stdout = child
+        .stdout
+        .take()
+        .expect("developer error: stdout not piped");
+    let stderr = child
+        .stderr
+        .take()
+        .expect("developer error: stderr not piped");
+    let stdout_tx = tx.clone();
+    thread::spawn(move || pump(Stream::Stdout, stdout, stdout_tx));
+    thread::spawn(move || pump(Stream::Stderr, stderr, tx));
+
+    let started = Instant::now();
+    let mut last_activity = started;
+    let mut last_line = LastLine::default();
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    let mut open = true;
+    let status = loop {
+        if open {
+            match rx.recv_timeout(POLL_INTERVAL) {
+                Ok((stream, bytes)) => {
+                    last_activity = Instant::now();
+                    last_line.feed(&bytes);
+                    echo(stream, &bytes);
+                    match stream {
+                        Stream::Stdout => stdout.extend_from_slice(&bytes),
+                        Stream::Stderr => stderr.extend_from_slice(&bytes),
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => open = false,
+            }
+        } else {
+            // Both pipes closed, which almost always means it exited, but a
+            // child that closes them and carries on still has to be watched.
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(cause) => return Err(Error::WaitFailed { class, cause }),
+            }
+        }
+        if let Some(exceeded) = limits.exceeded(started.elapsed(), last_activity.elapsed()) {
+            if let Err(err) = kill_group(&mut child) {
+                log::warn!("failed to stop `{}`: {}", class.tool(), err);
+            }
+            let status = child
+                .wait()
+                .map_err(|cause| Error::WaitFailed { class, cause })?;
+            return Err(Error::Hung(Hung {
+                class,
+                exceeded,
+                elapsed: started.elapsed(),
+                last_line: last_line.get(),
+                output: Output {
+                    status,
+                    stdout,
+                    stderr,
+                },
+            }));
+        }
+    };
+    let output = Output {
+        status,
+        stdout,
+        stderr,
+    };
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(Error::Failed { class, output })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[rstest(
+        elapsed,
+        quiet,
+        expected,
+        case(10, 10, None),
+        case(299, 299, None),
+        case(300, 300, Some(Exceeded::Inactivity(secs(300)))),
+        case(1000, 10, None),
+        case(1200, 10, Some(Exceeded::Total(secs(1200)))),
+        // Both at once is reported as the total, since that's the one that
+        // raising the inactivity limit wouldn't have helped with.
+        case(1200, 1200, Some(Exceeded::Total(secs(1200))))
+    )]
+    fn checks_both_limits(elapsed: u64, quiet: u64, expected: Option<Exceeded>) {
+        assert_eq!(
+            Class::Xcodegen
+                .default_limits()
+                .exceeded(secs(elapsed), secs(quiet)),
+            expected
+        );
+    }
+
+    #[test]
+    fn unlimited_never_exceeds() {
+        assert_eq!(Limits::NONE.exceeded(secs(u64::MAX), secs(u64::MAX)), None);
+    }
+
+    #[test]
+    fn config_overrides_defaults_per_limit() {
+        let raw = toml::from_str::<Raw>(
+            r#"
+            pod = { inactivity-secs = 1800 }
+            adb = { inactivity-secs = 0, total-secs = 120 }
+            "#,
+        )
+        .unwrap();
+        let timeouts = Timeouts::from_raw(Some(raw));
+        assert_eq!(
+            timeouts.limits(Class::Pod),
+            Limits {
+                inactivity: Some(secs(1800)),
+                total: Class::Pod.default_limits().total,
+            }
+        );
+        assert_eq!(
+            timeouts.limits(Class::Adb),
+            Limits {
+                inactivity: None,
+                total: Some(secs(120)),
+            }
+        );
+        assert_eq!(
+            timeouts.limits(Class::Xcodegen),
+            Class::Xcodegen.default_limits()
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_tools() {
+        assert!(toml::from_str::<Raw>("gradle = { total-secs = 60 }").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_limits() {
+        assert!(toml::from_str::<Raw>("pod = { inactivity = 60 }").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stops_quiet_commands() {
+        let mut command = process::Command::new("sh");
+        command.args(&["-c", "echo 'Analyzing dependencies'; sleep 30; echo done"]);
+        let started = Instant::now();
+        let err = run_with_limits(
+            Limits {
+                inactivity: Some(Duration::from_millis(500)),
+                total: None,
+            },
+            Class::Pod,
+            command,
+        )
+        .unwrap_err();
+        assert!(started.elapsed() < secs(10));
+        match err {
+            Error::Hung(hung) => {
+                assert_eq!(
+                    hung.exceeded,
+                    Exceeded::Inactivity(Duration::from_millis(500))
+                );
+                assert_eq!(hung.last_line.as_deref(), Some("Analyzing dependencies"));
+                assert_eq!(hung.output.stdout, b"Analyzing dependencies\n");
+            }
+            err => panic!("expected it to hang, but got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn remembers_the_last_line() {
+        let mut last_line = LastLine::default();
+        assert_eq!(last_line.get(), None);
+        last_line.feed(b"Analyzing dependencies\nDownloading depen");
+        assert_eq!(last_line.get().as_deref(), Some("Downloading depen"));
+        last_line.feed(b"dencies\n\n  \n");
+        assert_eq!(last_line.get().as_deref(), Some("Downloading dependencies"));
+        last_line.feed(b"\x1b[32mInstalling Foo\x1b[0m\r\n");
+        assert_eq!(last_line.get().as_deref(), Some("Installing Foo"));
+    }
+
+    #[rstest(
+        duration,
+        formatted,
+        case(secs(45), "45s"),
+        case(secs(300), "5m"),
+        case(secs(432), "7m 12s")
+    )]
+    fn formats_durations(duration: Duration, formatted: &str) {
+        assert_eq!(format_duration(duration), formatted);
+    }
+}
@@ -0,0 +1,560 @@
+//! Renders the installed template packs with a handful of representative
+//! configs and checks that what comes out is at least well-formed, so that
+//! template regressions (including in packs you've customized) show up
+//! before someone's build does.
+
+use crate::{
+    android,
+    config::{metadata::Metadata, Config},
+    opts,
+    templating::{self, Pack},
+    util::{
+        self,
+        cli::{Report, Reportable, TextWrapper},
+        Git, TempDirError, TempDirGuard,
+    },
+};
+use once_cell_regex::regex;
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    NoHomeDir(util::NoHomeDir),
+    TempDirFailed(TempDirError),
+    WriteFailed { path: PathBuf, cause: io::Error },
+    ToolCheckFailed(bossy::Error),
+    ProblemsFound { count: usize },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        let msg = "Failed to verify templates";
+        match self {
+            Self::NoHomeDir(err) => Report::error(msg, err),
+            Self::TempDirFailed(err) => Report::error(msg, err),
+            Self::WriteFailed { path, cause } => {
+                Report::error(msg, format!("Failed to write {:?}: {}", path, cause))
+            }
+            Self::ToolCheckFailed(err) => Report::error(
+                msg,
+                format!("Failed to check which tools are installed: {}", err),
+            ),
+            Self::ProblemsFound { count } => Report::error(
+                "Templates have problems",
+                format!(
+                    "Found {} problem{}; see above for which config and file each one is in.",
+                    count,
+                    if *count == 1 { "" } else { "s" }
+                ),
+            ),
+        }
+    }
+}
+
+/// A config to render with, covering the cases templates tend to get wrong.
+#[derive(Debug)]
+struct Variant {
+    name: &'static str,
+    mobile_toml: &'static str,
+    cargo_toml: &'static str,
+}
+
+static VARIANTS: &[Variant] = &[
+    Variant {
+        name: "minimal",
+        mobile_toml: r#"
+            [app]
+            name = "verify"
+            domain = "example.com"
+
+            [apple]
+            development-team = "ABCDE12345"
+            "#,
+        cargo_toml: r#"
+            [package]
+            name = "verify"
+            version = "0.1.0"
+            "#,
+    },
+    Variant {
+        name: "many-options",
+        mobile_toml: r#"
+            [app]
+            name = "verify"
+            stylized-name = "Verify"
+            domain = "example.com"
+
+            [apple]
+            development-team = "ABCDE12345"
+
+            [android]
+            allow-cleartext = true
+            jnilibs-strategy = "direct"
+            "#,
+        cargo_toml: r#"
+            [package]
+            name = "verify"
+            version = "0.1.0"
+
+            [package.metadata.cargo-android]
+            app-plugins = ["kotlin-android"]
+            project-dependencies = ["com.google.gms:google-services:4.3.10"]
+            app-dependencies = ["androidx.core:core-ktx:1.7.0"]
+            app-dependencies-platform = ["com.google.firebase:firebase-bom:29.0.0"]
+            asset-packs = [
+                { name = "levels", delivery-type = "fast-follow" },
+                { name = "music", delivery-type = "on-demand" },
+            ]
+
+            [package.metadata.cargo-apple.ios]
+            frameworks = ["GameKit"]
+            command-line-arguments = ["--verbose"]
+            post-build-scripts = [{ name = "Done", script = "echo done" }]
+
+            [package.metadata.cargo-apple.macos]
+            frameworks = ["GameController"]
+
+            [package.metadata.cargo-apple.watchos]
+            frameworks = ["HealthKit"]
+            "#,
+    },
+    Variant {
+        name: "hyphenated-names",
+        mobile_toml: r#"
+            [app]
+            name = "my-verify-app"
+            stylized-name = "My Verify App"
+            domain = "example-domain.com"
+
+            [apple]
+            development-team = "ABCDE12345"
+            "#,
+        cargo_toml: r#"
+            [package]
+            name = "my-verify-app"
+            version = "0.1.0"
+            "#,
+    },
+    Variant {
+        name: "custom-targets",
+        mobile_toml: r#"
+            [app]
+            name = "verify"
+            domain = "example.com"
+
+            [apple]
+            development-team = "ABCDE12345"
+
+            [android]
+            targets = ["armv7", "x86_64"]
+            "#,
+        cargo_toml: r#"
+            [package]
+            name = "verify"
+            version = "0.1.0"
+            "#,
+    },
+];
+
+/// Something wrong with one rendered file, or with rendering at all.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Problem {
+    variant: String,
+    file: Option<PathBuf>,
+    msg: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "[{}] {}: {}", self.variant, file.display(), self.msg),
+            None => write!(f, "[{}] {}", self.variant, self.msg),
+        }
+    }
+}
+
+fn describe(report: Report) -> String {
+    format!("{}: {}", report.msg(), report.details())
+}
+
+/// Unrendered `{{...}}`s, with 1-based line numbers.
+fn placeholders(contents: &str) -> Vec<(usize, &str)> {
+    contents
+        .lines()
+        .enumerate()
+        .flat_map(|(index, line)| {
+            regex!(r"\{\{[^\n]*?\}\}")
+                .find_iter(line)
+                .map(move |found| (index + 1, found.as_str()))
+        })
+        .collect()
+}
+
+fn is_xml(path: &Path, contents: &str) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("xml") => true,
+        // Plists can be binary or XML.
+        Some("plist") | Some("entitlements") => contents.trim_start().starts_with("<?xml"),
+        _ => false,
+    }
+}
+
+/// The checks that don't need any platform tooling.
+fn check_contents(path: &Path, contents: &str) -> Vec<String> {
+    let mut problems = placeholders(contents)
+        .into_iter()
+        .map(|(line, placeholder)| {
+            format!(
+                "line {} still has the template placeholder `{}`",
+                line, placeholder
+            )
+        })
+        .collect::<Vec<_>>();
+    if is_xml(path, contents) {
+        if let Err(err) = roxmltree::Document::parse(contents) {
+            problems.push(format!("isn't valid XML: {}", err));
+        }
+    }
+    if path.file_name() == Some("project.yml".as_ref()) {
+        if let Err(err) = serde_yaml::from_str::<serde_yaml::Value>(contents) {
+            problems.push(format!("isn't valid YAML: {}", err));
+        }
+    }
+    problems
+}
+
+/// What's installed for the checks that need more than us.
+#[derive(Clone, Copy, Debug, Default)]
+struct Tools {
+    xcodegen: bool,
+    java: bool,
+}
+
+impl Tools {
+    fn detect() -> Result<Self, Error> {
+        let present = |name| util::command_present(name).map_err(Error::ToolCheckFailed);
+        let tools = Self {
+            xcodegen: present("xcodegen")?,
+            java: present("java")?,
+        };
+        if !tools.xcodegen {
+            log::warn!("`xcodegen` isn't installed, so `project.yml` won't be checked with `xcodegen dump`");
+        }
+        if !tools.java {
+            log::warn!(
+                "no JDK was found, so gradle files won't be checked with `gradlew help --dry-run`"
+            );
+        }
+        Ok(tools)
+    }
+}
+
+fn check_with_tools(tools: Tools, config: &Config, root: &Path) -> Vec<(PathBuf, String)> {
+    let mut problems = Vec::new();
+    let project_yml = root.join("gen/apple/project.yml");
+    if tools.xcodegen && project_yml.is_file() {
+        if let Err(err) = bossy::Command::impure("xcodegen")
+            .with_args(&["dump", "--type", "json", "--spec"])
+            .with_arg(&project_yml)
+            .run_and_wait_for_output()
+        {
+            problems.push((project_yml, format!("`xcodegen dump` failed: {}", err)));
+        }
+    }
+    let android_dir = config.android().project_dir();
+    if tools.java && android_dir.join("build.gradle.kts").is_file() {
+        if let Err(err) = bossy::Command::pure(android::gradlew::prepare(&android_dir))
+            .with_arg("--project-dir")
+            .with_arg(&android_dir)
+            .with_args(&["help", "--dry-run"])
+            .run_and_wait_for_output()
+        {
+            problems.push((
+                android_dir.join("build.gradle.kts"),
+                format!("`gradlew help --dry-run` failed: {}", err),
+            ));
+        }
+    }
+    problems
+}
+
+fn write(path: PathBuf, contents: &str) -> Result<(), Error> {
+    fs::write(&path, contents).map_err(|cause| Error::WriteFailed { path, cause })
+}
+
+#[cfg(target_os = "macos")]
+fn render_xcode(
+    config: &Config,
+    metadata: &Metadata,
+    bike: &bicycle::Bicycle,
+    filter: &templating::Filter,
+    platforms: &Path,
+) -> Result<(), String> {
+    crate::apple::project::process_templates(
+        config.apple(),
+        metadata.apple(),
+        None,
+        bike,
+        filter,
+        opts::Conflicts::Keep,
+        &platforms.join("xcode"),
+        Some(&platforms.join("xcode-watchos")),
+        &config.apple().project_dir(),
+    )
+    .map_err(|err| describe(err.report()))
+}
+
+// The Xcode pack is rendered from the Apple config and metadata, which (like
+// everything else Apple) are only built on macOS.
+#[cfg(not(target_os = "macos"))]
+fn render_xcode(
+    _config: &Config,
+    _metadata: &Metadata,
+    _bike: &bicycle::Bicycle,
+    _filter: &templating::Filter,
+    _platforms: &Path,
+) -> Result<(), String> {
+    log::warn!("the Xcode template pack can only be rendered on macOS, so it won't be checked");
+    Ok(())
+}
+
+/// Renders the platform packs in `templates` into `root` with `variant`'s
+/// config, which is also written there. Failures are given as a message.
+fn render(
+    templates: &Path,
+    variant: &Variant,
+    root: &Path,
+    wrapper: &TextWrapper,
+) -> Result<Result<Config, String>, Error> {
+    write(root.join("mobile.toml"), variant.mobile_toml)?;
+    write(root.join("Cargo.toml"), variant.cargo_toml)?;
+    let config = match Config::load_or_gen(root, opts::NonInteractive::Yes, wrapper) {
+        Ok((config, _)) => config,
+        Err(err) => return Ok(Err(describe(err.report()))),
+    };
+    let metadata = match Metadata::load(root) {
+        Ok(metadata) => metadata,
+        Err(err) => return Ok(Err(describe(err.report()))),
+    };
+    let bike = config.build_a_bike();
+    let platforms = templates.join("platforms");
+    let filter = templating::Filter::WildWest;
+    if let Err(msg) = render_xcode(&config, &metadata, &bike, &filter, &platforms) {
+        return Ok(Err(msg));
+    }
+    if let Err(err) = android::project::process_templates(
+        config.android(),
+        metadata.android(),
+        &bike,
+        &filter,
+        opts::Conflicts::Keep,
+        &platforms.join("android-studio"),
+        &platforms.join("android-studio-asset-pack"),
+        &config.android().project_dir(),
+    ) {
+        return Ok(Err(describe(err.report())));
+    }
+    Ok(Ok(config))
+}
+
+/// Renders each app pack in `templates` that doesn't need a submodule, using
+/// `config`'s data. Failures are given as `(pack, message)`.
+fn render_app_packs(templates: &Path, config: &Config) -> Vec<(String, String)> {
+    let names = match templating::list_app_packs() {
+        Ok(names) => names,
+        Err(err) => return vec![("*".to_owned(), err.to_string())],
+    };
+    let root = config.app().root_dir();
+    let bike = config.build_a_bike();
+    let mut failures = Vec::new();
+    for name in names {
+        let pack = match Pack::lookup(templates.join("apps"), &name) {
+            Ok(pack) => pack,
+            Err(err) => {
+                failures.push((name, err.to_string()));
+                continue;
+            }
+        };
+        if pack.submodule_path().is_some() {
+            log::warn!(
+                "skipping app template pack {:?}, since it needs its submodule",
+                name
+            );
+            continue;
+        }
+        let result = pack
+            .resolve(Git::new(root), None)
+            .map_err(|err| err.to_string())
+            .and_then(|paths| {
+                paths.into_iter().try_for_each(|path| {
+                    templating::process(
+                        &bike,
+                        path,
+                        root,
+                        |_| (),
+                        &templating::Filter::WildWest,
+                        opts::Conflicts::Overwrite,
+                    )
+                    .map_err(|err| err.to_string())
+                })
+            });
+        if let Err(err) = result {
+            failures.push((name, err));
+        }
+    }
+    failures
+}
+
+/// Runs the content checks on everything under `root`.
+fn scan(variant: &str, root: &Path) -> Vec<Problem> {
+    walkdir::WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| entry.file_name() != templating::MANIFEST_FILE_NAME)
+        .flat_map(|entry| {
+            let rel = entry
+                .path()
+                .strip_prefix(root)
+                .unwrap_or_else(|_| entry.path())
+                .to_owned();
+            // Binaries, like the launcher icons, have nothing to check.
+            fs::read_to_string(entry.path())
+                .map(|contents| check_contents(&rel, &contents))
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |msg| Problem {
+                    variant: variant.to_owned(),
+                    file: Some(rel.clone()),
+                    msg,
+                })
+        })
+        .collect()
+}
+
+fn verify_variant(
+    templates: &Path,
+    variant: &Variant,
+    app_packs: bool,
+    tools: Tools,
+    wrapper: &TextWrapper,
+) -> Result<Vec<Problem>, Error> {
+    let dir = TempDirGuard::new().map_err(Error::TempDirFailed)?;
+    let root = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let problem = |file: Option<PathBuf>, msg: String| Problem {
+        variant: variant.name.to_owned(),
+        file,
+        msg,
+    };
+    let config = match render(templates, variant, &root, wrapper)? {
+        Ok(config) => config,
+        Err(msg) => return Ok(vec![problem(None, format!("failed to render: {}", msg))]),
+    };
+    let mut problems = Vec::new();
+    if app_packs {
+        problems.extend(
+            render_app_packs(templates, &config)
+                .into_iter()
+                .map(|(pack, msg)| {
+                    problem(
+                        None,
+                        format!("failed to render app pack {:?}: {}", pack, msg),
+                    )
+                }),
+        );
+    }
+    problems.extend(scan(variant.name, &root));
+    problems.extend(
+        check_with_tools(tools, &config, &root)
+            .into_iter()
+            .map(|(path, msg)| {
+                let rel = path.strip_prefix(&root).map(Path::to_owned).unwrap_or(path);
+                problem(Some(rel), msg)
+            }),
+    );
+    Ok(problems)
+}
+
+pub fn exec(wrapper: &TextWrapper) -> Result<(), Error> {
+    let templates = util::install_dir()
+        .map_err(Error::NoHomeDir)?
+        .join("templates");
+    let tools = Tools::detect()?;
+    let mut count = 0;
+    for variant in VARIANTS {
+        println!("Rendering templates with the {:?} config...", variant.name);
+        for problem in verify_variant(&templates, variant, true, tools, wrapper)? {
+            println!("  {}", problem);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        println!("Templates look fine with every config");
+        Ok(())
+    } else {
+        Err(Error::ProblemsFound { count })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn finds_leftover_placeholders() {
+        assert_eq!(
+            placeholders("name: game\nbundleId: {{reverse-domain app.domain}}.{{app.name}}\n"),
+            vec![(2, "{{reverse-domain app.domain}}"), (2, "{{app.name}}")]
+        );
+        assert_eq!(placeholders("package = \"${applicationId}\" { }"), vec![]);
+    }
+
+    #[rstest(
+        path,
+        contents,
+        expected,
+        case(
+            "app/src/main/AndroidManifest.xml",
+            "<manifest><application></manifest>",
+            1
+        ),
+        case(
+            "app/src/main/AndroidManifest.xml",
+            "<manifest><application/></manifest>",
+            0
+        ),
+        case("gen/apple/project.yml", "name: game\n  targets: [", 1),
+        case("gen/apple/project.yml", "name: game\ntargets: {}\n", 0),
+        case(
+            "gen/apple/game_iOS/Info.plist",
+            "<?xml version=\"1.0\"?><plist><dict></plist>",
+            1
+        ),
+        case("build.gradle.kts", "<not xml", 0)
+    )]
+    fn checks_formats(path: &str, contents: &str, expected: usize) {
+        assert_eq!(check_contents(Path::new(path), contents).len(), expected);
+    }
+
+    // This part can't need any tooling, so it runs anywhere.
+    #[test]
+    fn bundled_platform_packs_render_cleanly() {
+        let templates = Path::new(env!("CARGO_MANIFEST_DIR")).join("templates");
+        let wrapper = TextWrapper::with_splitter(80, textwrap::NoHyphenation);
+        for variant in VARIANTS {
+            let problems =
+                verify_variant(&templates, variant, false, Tools::default(), &wrapper).unwrap();
+            assert!(
+                problems.is_empty(),
+                "{:#?}",
+                problems.iter().map(ToString::to_string).collect::<Vec<_>>()
+            );
+        }
+    }
+}
@@ -0,0 +1,10 @@
+#import "ExtensionDelegate.h"
+
+@implementation ExtensionDelegate
+
+- (void)applicationDidFinishLaunching {
+	// The Rust lib is linked into this extension, so anything it exports
+	// with `extern "C"` can be called from here.
+}
+
+@end
@@ -0,0 +1,5 @@
+#import <WatchKit/WatchKit.h>
+
+@interface InterfaceController : WKInterfaceController
+
+@end
@@ -0,0 +1,5 @@
+#import "InterfaceController.h"
+
+@implementation InterfaceController
+
+@end